@@ -0,0 +1,47 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use weather_api_rust::{
+    bench::{demo_rows, plot_json},
+    precision::JsonPrecision,
+};
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+fn bench_plot_json(c: &mut Criterion) {
+    let precision = JsonPrecision::default();
+    let mut group = c.benchmark_group("plot_json");
+    for size in SIZES {
+        let rows = demo_rows(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &rows, |b, rows| {
+            b.iter(|| plot_json(black_box(rows), &precision));
+        });
+    }
+    group.finish();
+}
+
+#[cfg(feature = "analysis")]
+fn bench_parquet(c: &mut Criterion) {
+    use weather_api_rust::polars_analysis::insert_rows_into_parquet;
+
+    let mut group = c.benchmark_group("parquet_write");
+    group.sample_size(10);
+    for size in SIZES {
+        let rows = demo_rows(size);
+        let directory = std::env::temp_dir().join(format!("weather_api_criterion_{size}"));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &rows, |b, rows| {
+            b.iter(|| {
+                std::fs::remove_dir_all(&directory).ok();
+                std::fs::create_dir_all(&directory).unwrap();
+                insert_rows_into_parquet(rows.iter().cloned(), &directory).unwrap()
+            });
+        });
+        std::fs::remove_dir_all(&directory).ok();
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "analysis"))]
+fn bench_parquet(_: &mut Criterion) {}
+
+criterion_group!(benches, bench_plot_json, bench_parquet);
+criterion_main!(benches);
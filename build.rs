@@ -0,0 +1,8 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/weather.proto");
+        tonic_build::compile_protos("proto/weather.proto")?;
+    }
+    Ok(())
+}
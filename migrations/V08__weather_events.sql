@@ -0,0 +1,15 @@
+CREATE TABLE weather_events (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    location_name TEXT NOT NULL,
+    server TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    start_time TIMESTAMP WITH TIME ZONE NOT NULL,
+    end_time TIMESTAMP WITH TIME ZONE NOT NULL,
+    peak_wind_speed DOUBLE PRECISION NOT NULL,
+    total_precipitation DOUBLE PRECISION NOT NULL,
+    min_pressure DOUBLE PRECISION NOT NULL,
+    max_pressure_drop_rate DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+
+    UNIQUE (location_name, server, start_time)
+);
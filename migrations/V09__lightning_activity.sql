@@ -0,0 +1,11 @@
+CREATE TABLE lightning_activity (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    location_name TEXT NOT NULL,
+    server TEXT NOT NULL,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    radius_km DOUBLE PRECISION NOT NULL,
+    strike_count INTEGER NOT NULL,
+    nearest_distance_km DOUBLE PRECISION,
+
+    UNIQUE (location_name, server, recorded_at)
+);
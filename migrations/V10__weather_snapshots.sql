@@ -0,0 +1,11 @@
+CREATE TABLE weather_snapshots (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    location_name TEXT NOT NULL,
+    taken_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    image_url TEXT,
+    s3_key TEXT,
+    content_type TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX weather_snapshots_location_taken_at ON weather_snapshots (location_name, taken_at);
@@ -0,0 +1,13 @@
+CREATE TABLE air_quality_data (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    location_name TEXT NOT NULL,
+    server TEXT NOT NULL,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    aqi INTEGER NOT NULL,
+    pm2_5 DOUBLE PRECISION NOT NULL,
+    pm10 DOUBLE PRECISION NOT NULL,
+    o3 DOUBLE PRECISION NOT NULL,
+    no2 DOUBLE PRECISION NOT NULL,
+
+    UNIQUE (location_name, server, recorded_at)
+);
@@ -0,0 +1,13 @@
+CREATE TABLE alert_rules (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    email TEXT NOT NULL,
+    location_name TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    comparison TEXT NOT NULL,
+    threshold DOUBLE PRECISION NOT NULL,
+    forecast_hours INTEGER,
+    webhook_url TEXT NOT NULL,
+    last_fired_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+CREATE INDEX alert_rules_email_idx ON alert_rules (email);
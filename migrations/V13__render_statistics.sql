@@ -0,0 +1,12 @@
+CREATE TABLE render_statistics (
+    route TEXT NOT NULL,
+    version TEXT NOT NULL,
+    server TEXT NOT NULL,
+    count BIGINT NOT NULL,
+    p50_length BIGINT NOT NULL,
+    p95_length BIGINT NOT NULL,
+    max_length BIGINT NOT NULL,
+    last_render_at TIMESTAMP WITH TIME ZONE,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (route, version, server)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE api_keys (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    email TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    description TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    revoked_at TIMESTAMP WITH TIME ZONE
+);
+CREATE INDEX api_keys_email_idx ON api_keys (email);
@@ -0,0 +1,89 @@
+ALTER TABLE weather_data ADD COLUMN condition_code INTEGER;
+
+-- first condition of the stored text, e.g. 'Rain light rain , Clouds broken clouds '
+UPDATE weather_data w
+SET condition_code = c.code
+FROM (VALUES
+    (200, 'Thunderstorm', 'thunderstorm with light rain'),
+    (201, 'Thunderstorm', 'thunderstorm with rain'),
+    (202, 'Thunderstorm', 'thunderstorm with heavy rain'),
+    (210, 'Thunderstorm', 'light thunderstorm'),
+    (211, 'Thunderstorm', 'thunderstorm'),
+    (212, 'Thunderstorm', 'heavy thunderstorm'),
+    (221, 'Thunderstorm', 'ragged thunderstorm'),
+    (230, 'Thunderstorm', 'thunderstorm with light drizzle'),
+    (231, 'Thunderstorm', 'thunderstorm with drizzle'),
+    (232, 'Thunderstorm', 'thunderstorm with heavy drizzle'),
+    (300, 'Drizzle', 'light intensity drizzle'),
+    (301, 'Drizzle', 'drizzle'),
+    (302, 'Drizzle', 'heavy intensity drizzle'),
+    (310, 'Drizzle', 'light intensity drizzle rain'),
+    (311, 'Drizzle', 'drizzle rain'),
+    (312, 'Drizzle', 'heavy intensity drizzle rain'),
+    (313, 'Drizzle', 'shower rain and drizzle'),
+    (314, 'Drizzle', 'heavy shower rain and drizzle'),
+    (321, 'Drizzle', 'shower drizzle'),
+    (500, 'Rain', 'light rain'),
+    (501, 'Rain', 'moderate rain'),
+    (502, 'Rain', 'heavy intensity rain'),
+    (503, 'Rain', 'very heavy rain'),
+    (504, 'Rain', 'extreme rain'),
+    (511, 'Rain', 'freezing rain'),
+    (520, 'Rain', 'light intensity shower rain'),
+    (521, 'Rain', 'shower rain'),
+    (522, 'Rain', 'heavy intensity shower rain'),
+    (531, 'Rain', 'ragged shower rain'),
+    (600, 'Snow', 'light snow'),
+    (601, 'Snow', 'snow'),
+    (602, 'Snow', 'heavy snow'),
+    (611, 'Snow', 'sleet'),
+    (612, 'Snow', 'light shower sleet'),
+    (613, 'Snow', 'shower sleet'),
+    (615, 'Snow', 'light rain and snow'),
+    (616, 'Snow', 'rain and snow'),
+    (620, 'Snow', 'light shower snow'),
+    (621, 'Snow', 'shower snow'),
+    (622, 'Snow', 'heavy shower snow'),
+    (701, 'Mist', 'mist'),
+    (711, 'Smoke', 'smoke'),
+    (721, 'Haze', 'haze'),
+    (731, 'Dust', 'sand/dust whirls'),
+    (741, 'Fog', 'fog'),
+    (751, 'Sand', 'sand'),
+    (761, 'Dust', 'dust'),
+    (762, 'Ash', 'volcanic ash'),
+    (771, 'Squall', 'squalls'),
+    (781, 'Tornado', 'tornado'),
+    (800, 'Clear', 'clear sky'),
+    (801, 'Clouds', 'few clouds'),
+    (802, 'Clouds', 'scattered clouds'),
+    (803, 'Clouds', 'broken clouds'),
+    (804, 'Clouds', 'overcast clouds')
+) AS c(code, main, description)
+WHERE lower(split_part(trim(split_part(w.condition, ',', 1)), ' ', 1)) = lower(c.main)
+  AND lower(trim(substring(trim(split_part(w.condition, ',', 1)) FROM position(' ' IN trim(split_part(w.condition, ',', 1)))))) = c.description;
+
+-- conditions without a known description (other providers) by main group
+UPDATE weather_data w
+SET condition_code = c.code
+FROM (VALUES
+    (211, 'thunderstorm'),
+    (301, 'drizzle'),
+    (501, 'rain'),
+    (601, 'snow'),
+    (701, 'mist'),
+    (711, 'smoke'),
+    (721, 'haze'),
+    (741, 'fog'),
+    (751, 'sand'),
+    (761, 'dust'),
+    (762, 'ash'),
+    (771, 'squall'),
+    (781, 'tornado'),
+    (800, 'clear'),
+    (803, 'clouds')
+) AS c(code, main)
+WHERE w.condition_code IS NULL
+  AND lower(split_part(trim(split_part(w.condition, ',', 1)), ' ', 1)) = c.main;
+
+CREATE INDEX weather_data_condition_code_idx ON weather_data (condition_code);
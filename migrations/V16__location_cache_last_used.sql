@@ -0,0 +1,5 @@
+ALTER TABLE weather_location_cache ADD COLUMN last_used_at TIMESTAMP WITH TIME ZONE;
+UPDATE weather_location_cache SET last_used_at = created_at;
+ALTER TABLE weather_location_cache ALTER COLUMN last_used_at SET NOT NULL;
+ALTER TABLE weather_location_cache ALTER COLUMN last_used_at SET DEFAULT now();
+CREATE INDEX weather_location_cache_last_used_at_idx ON weather_location_cache (last_used_at);
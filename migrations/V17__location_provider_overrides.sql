@@ -0,0 +1,6 @@
+CREATE TABLE location_provider_overrides (
+    location_name TEXT NOT NULL PRIMARY KEY,
+    api_key TEXT,
+    api_endpoint TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
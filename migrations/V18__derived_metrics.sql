@@ -0,0 +1,49 @@
+ALTER TABLE weather_data ADD COLUMN dew_point DOUBLE PRECISION;
+ALTER TABLE weather_data ADD COLUMN heat_index DOUBLE PRECISION;
+ALTER TABLE weather_data ADD COLUMN wind_chill DOUBLE PRECISION;
+
+UPDATE weather_data
+SET dew_point = 243.04 * gamma / (17.625 - gamma) + 273.15
+FROM (
+    SELECT id AS gamma_id,
+           ln(least(humidity, 100) / 100.0)
+               + 17.625 * (temperature - 273.15) / (243.04 + temperature - 273.15) AS gamma
+    FROM weather_data
+    WHERE humidity > 0
+) g
+WHERE id = g.gamma_id;
+
+UPDATE weather_data
+SET heat_index = (hi - 32.0) * 5.0 / 9.0 + 273.15
+FROM (
+    SELECT id AS hi_id,
+           -42.379 + 2.04901523 * t + 10.14333127 * rh - 0.22475541 * t * rh
+               - 0.00683783 * t * t - 0.05481717 * rh * rh + 0.00122874 * t * t * rh
+               + 0.00085282 * t * rh * rh - 0.00000199 * t * t * rh * rh
+               - CASE WHEN rh < 13 AND t <= 112
+                      THEN (13 - rh) / 4.0 * sqrt((17 - abs(t - 95)) / 17.0)
+                      ELSE 0 END
+               + CASE WHEN rh > 85 AND t <= 87
+                      THEN (rh - 85) / 10.0 * (87 - t) / 5.0
+                      ELSE 0 END AS hi
+    FROM (
+        SELECT id,
+               (temperature - 273.15) * 9.0 / 5.0 + 32.0 AS t,
+               greatest(least(humidity, 100), 0)::DOUBLE PRECISION AS rh
+        FROM weather_data
+    ) f
+    WHERE t >= 80
+) h
+WHERE id = h.hi_id;
+
+UPDATE weather_data
+SET wind_chill = (35.74 + 0.6215 * t - 35.75 * v + 0.4275 * t * v - 32.0) * 5.0 / 9.0 + 273.15
+FROM (
+    SELECT id AS wc_id,
+           (temperature - 273.15) * 9.0 / 5.0 + 32.0 AS t,
+           power(wind_speed * 2.236936, 0.16) AS v
+    FROM weather_data
+    WHERE (temperature - 273.15) * 9.0 / 5.0 + 32.0 <= 50
+      AND wind_speed * 2.236936 > 3
+) w
+WHERE id = w.wc_id;
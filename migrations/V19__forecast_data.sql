@@ -0,0 +1,15 @@
+CREATE TABLE forecast_data (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    location_name TEXT NOT NULL,
+    server TEXT NOT NULL,
+    fetched_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    forecast_time TIMESTAMP WITH TIME ZONE NOT NULL,
+    temperature DOUBLE PRECISION NOT NULL,
+    humidity INTEGER NOT NULL,
+    pressure DOUBLE PRECISION NOT NULL,
+    wind_speed DOUBLE PRECISION NOT NULL,
+    precipitation DOUBLE PRECISION,
+    UNIQUE (location_name, server, fetched_at, forecast_time)
+);
+
+CREATE INDEX forecast_data_location_forecast_time ON forecast_data (location_name, forecast_time);
@@ -0,0 +1,14 @@
+ALTER TABLE forecast_data RENAME TO weather_forecast;
+ALTER INDEX forecast_data_location_forecast_time RENAME TO weather_forecast_location_forecast_time;
+
+ALTER TABLE weather_forecast ADD COLUMN condition TEXT;
+ALTER TABLE weather_forecast ADD COLUMN condition_code INTEGER;
+ALTER TABLE weather_forecast ADD COLUMN feels_like DOUBLE PRECISION;
+ALTER TABLE weather_forecast ADD COLUMN temperature_minimum DOUBLE PRECISION;
+ALTER TABLE weather_forecast ADD COLUMN temperature_maximum DOUBLE PRECISION;
+ALTER TABLE weather_forecast ADD COLUMN rain DOUBLE PRECISION;
+ALTER TABLE weather_forecast ADD COLUMN snow DOUBLE PRECISION;
+ALTER TABLE weather_forecast ADD COLUMN wind_direction DOUBLE PRECISION;
+ALTER TABLE weather_forecast ADD COLUMN timezone INTEGER;
+
+CREATE INDEX weather_forecast_location_fetched_at ON weather_forecast (location_name, fetched_at);
@@ -0,0 +1,31 @@
+CREATE SEQUENCE weather_data_change_seq;
+
+ALTER TABLE weather_data ADD COLUMN seq BIGINT;
+
+UPDATE weather_data
+SET seq = o.n
+FROM (
+    SELECT id AS seq_id, row_number() OVER (ORDER BY created_at, id) AS n
+    FROM weather_data
+) o
+WHERE id = o.seq_id;
+
+SELECT setval('weather_data_change_seq', coalesce((SELECT max(seq) FROM weather_data), 0) + 1, false);
+
+ALTER TABLE weather_data ALTER COLUMN seq SET DEFAULT nextval('weather_data_change_seq');
+ALTER TABLE weather_data ALTER COLUMN seq SET NOT NULL;
+ALTER SEQUENCE weather_data_change_seq OWNED BY weather_data.seq;
+
+CREATE UNIQUE INDEX weather_data_seq ON weather_data (seq);
+
+-- every update moves the row to the end of the change feed
+CREATE FUNCTION weather_data_bump_seq() RETURNS trigger AS $$
+BEGIN
+    NEW.seq := nextval('weather_data_change_seq');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER weather_data_bump_seq
+BEFORE UPDATE ON weather_data
+FOR EACH ROW EXECUTE FUNCTION weather_data_bump_seq();
@@ -0,0 +1,10 @@
+CREATE TABLE uv_index_data (
+    id UUID NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    location_name TEXT NOT NULL,
+    server TEXT NOT NULL,
+    recorded_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    uvi DOUBLE PRECISION NOT NULL,
+    timezone INTEGER NOT NULL,
+
+    UNIQUE (location_name, server, recorded_at)
+);
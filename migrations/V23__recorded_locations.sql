@@ -0,0 +1,6 @@
+CREATE TABLE recorded_locations (
+    location_name TEXT NOT NULL PRIMARY KEY,
+    location TEXT NOT NULL,
+    interval_seconds INTEGER,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
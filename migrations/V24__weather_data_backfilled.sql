@@ -0,0 +1 @@
+ALTER TABLE weather_data ADD COLUMN backfilled BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,10 @@
+ALTER TABLE weather_data ADD COLUMN inserted_at TIMESTAMP WITH TIME ZONE;
+
+-- the ingestion time of existing rows is unknown, live recordings are
+-- assumed to have been inserted when observed and backfilled rows now
+UPDATE weather_data SET inserted_at = CASE WHEN backfilled THEN now() ELSE created_at END;
+
+ALTER TABLE weather_data ALTER COLUMN inserted_at SET DEFAULT now();
+ALTER TABLE weather_data ALTER COLUMN inserted_at SET NOT NULL;
+
+CREATE INDEX weather_data_inserted_at ON weather_data (inserted_at);
@@ -0,0 +1,19 @@
+-- writers took seq from the sequence before committing, a transaction
+-- committing after a later one left a lower seq behind the next_seq readers
+-- had already moved past. Writers now leave seq NULL and the change feed
+-- numbers the committed rows when it is read
+ALTER TABLE weather_data ALTER COLUMN seq DROP DEFAULT;
+ALTER TABLE weather_data ALTER COLUMN seq DROP NOT NULL;
+
+CREATE INDEX weather_data_seq_pending ON weather_data (created_at, id) WHERE seq IS NULL;
+
+-- numbering by the change feed keeps its value, any other update queues the
+-- row again
+CREATE OR REPLACE FUNCTION weather_data_bump_seq() RETURNS trigger AS $$
+BEGIN
+    IF NEW.seq IS NOT DISTINCT FROM OLD.seq THEN
+        NEW.seq := NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
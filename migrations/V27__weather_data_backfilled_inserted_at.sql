@@ -0,0 +1,7 @@
+-- V25 set the ingestion time of rows backfilled before it to the time of the
+-- migration, hiding them from every earlier `asof`. Like live recordings
+-- they are assumed to have been inserted when observed
+UPDATE weather_data SET inserted_at = created_at
+WHERE backfilled AND inserted_at <= (
+    SELECT applied_on::timestamptz FROM refinery_schema_history WHERE version = 25
+);
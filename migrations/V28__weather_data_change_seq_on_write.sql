@@ -0,0 +1,30 @@
+-- seq is assigned by the writer again, with the advisory lock numbering a row
+-- held until the writer commits, so writers commit in seq order and readers
+-- of the change feed never move past a lower seq that is still uncommitted
+UPDATE weather_data w
+SET seq = p.seq
+FROM (
+    SELECT id, nextval('weather_data_change_seq') AS seq
+    FROM (
+        SELECT id FROM weather_data
+        WHERE seq IS NULL
+        ORDER BY created_at, id
+    ) o
+) p
+WHERE w.id = p.id;
+
+DROP INDEX weather_data_seq_pending;
+ALTER TABLE weather_data ALTER COLUMN seq SET NOT NULL;
+
+CREATE OR REPLACE FUNCTION weather_data_bump_seq() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_advisory_xact_lock(8603389815756780033);
+    NEW.seq := nextval('weather_data_change_seq');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER weather_data_bump_seq ON weather_data;
+CREATE TRIGGER weather_data_bump_seq
+BEFORE INSERT OR UPDATE ON weather_data
+FOR EACH ROW EXECUTE FUNCTION weather_data_bump_seq();
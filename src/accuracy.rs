@@ -0,0 +1,228 @@
+use anyhow::Error;
+use futures::TryStreamExt;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+
+use weather_util_rust::weather_forecast::WeatherForecast;
+
+use crate::{
+    model::{WeatherDataDB, WeatherForecastDB},
+    pgpool::PgPool,
+};
+
+/// Lead times are grouped by the 3 hour step of the forecasts
+pub const LEAD_TIME_BUCKET_HOURS: i64 = 3;
+
+/// Forecast entries without an observation this close are not scored
+const MAX_OBSERVATION_GAP: Duration = Duration::minutes(90);
+
+/// Forecast error statistics of one lead time bucket, temperatures in kelvin
+/// (differences are the same in celsius), pressure in kPa and wind in m/s
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "LeadTimeAccuracy")]
+pub struct LeadTimeAccuracy {
+    #[schema(description = "Start of the Lead Time Bucket (hours)")]
+    pub lead_hours: i64,
+    #[schema(description = "Number of Scored Forecast Entries")]
+    pub count: usize,
+    #[schema(description = "Temperature Mean Absolute Error (K)")]
+    pub temperature_mae: f64,
+    #[schema(description = "Temperature Mean Error, forecast minus observed (K)")]
+    pub temperature_bias: f64,
+    #[schema(description = "Humidity Mean Absolute Error (percent)")]
+    pub humidity_mae: f64,
+    #[schema(description = "Pressure Mean Absolute Error (kPa)")]
+    pub pressure_mae: f64,
+    #[schema(description = "Wind Speed Mean Absolute Error (m/s)")]
+    pub wind_speed_mae: f64,
+}
+
+#[derive(Default)]
+struct ErrorSums {
+    count: usize,
+    temperature: f64,
+    temperature_bias: f64,
+    humidity: f64,
+    pressure: f64,
+    wind_speed: f64,
+}
+
+/// Observation of `observations` (ordered by `created_at`) closest to `time`
+fn closest_observation(
+    observations: &[WeatherDataDB],
+    time: OffsetDateTime,
+) -> Option<&WeatherDataDB> {
+    let idx = observations.partition_point(|o| *o.created_at < time);
+    let before = idx.checked_sub(1).and_then(|i| observations.get(i));
+    let after = observations.get(idx);
+    [before, after]
+        .into_iter()
+        .flatten()
+        .min_by_key(|o| (*o.created_at - time).abs())
+        .filter(|o| (*o.created_at - time).abs() <= MAX_OBSERVATION_GAP)
+}
+
+/// Score every forecast entry against the closest observation, grouped by
+/// lead time (forecast time minus fetch time)
+#[must_use]
+pub fn get_forecast_accuracy(
+    forecasts: &[WeatherForecastDB],
+    observations: &[WeatherDataDB],
+) -> Vec<LeadTimeAccuracy> {
+    let mut buckets: BTreeMap<i64, ErrorSums> = BTreeMap::new();
+    for forecast in forecasts {
+        let lead = *forecast.forecast_time - *forecast.fetched_at;
+        if lead.is_negative() {
+            continue;
+        }
+        let Some(observed) = closest_observation(observations, *forecast.forecast_time) else {
+            continue;
+        };
+        let lead_hours = lead.whole_hours() / LEAD_TIME_BUCKET_HOURS * LEAD_TIME_BUCKET_HOURS;
+        let sums = buckets.entry(lead_hours).or_default();
+        let temperature_error = forecast.temperature - observed.temperature;
+        sums.count += 1;
+        sums.temperature += temperature_error.abs();
+        sums.temperature_bias += temperature_error;
+        sums.humidity += f64::from(forecast.humidity - observed.humidity).abs();
+        sums.pressure += (forecast.pressure - observed.pressure).abs();
+        sums.wind_speed += (forecast.wind_speed - observed.wind_speed).abs();
+    }
+    buckets
+        .into_iter()
+        .map(|(lead_hours, sums)| {
+            let n = sums.count as f64;
+            LeadTimeAccuracy {
+                lead_hours,
+                count: sums.count,
+                temperature_mae: sums.temperature / n,
+                temperature_bias: sums.temperature_bias / n,
+                humidity_mae: sums.humidity / n,
+                pressure_mae: sums.pressure / n,
+                wind_speed_mae: sums.wind_speed / n,
+            }
+        })
+        .collect()
+}
+
+/// Store every entry of a freshly fetched forecast
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn record_forecast(
+    pool: &PgPool,
+    location_name: &str,
+    server: &str,
+    forecast: &WeatherForecast,
+) -> Result<u64, Error> {
+    let fetched_at = OffsetDateTime::now_utc();
+    let mut inserted = 0;
+    for row in WeatherForecastDB::from_forecast(forecast, location_name, server, fetched_at) {
+        inserted += row.insert(pool).await?;
+    }
+    Ok(inserted)
+}
+
+/// Accuracy of the forecasts stored for `name` that were valid between
+/// `start_date` and `end_date` (inclusive) and are already in the past
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn forecast_accuracy(
+    pool: &PgPool,
+    name: &str,
+    server: Option<&str>,
+    start_date: Date,
+    end_date: Date,
+) -> Result<Vec<LeadTimeAccuracy>, Error> {
+    let start = PrimitiveDateTime::new(start_date, Time::MIDNIGHT).assume_utc();
+    let end = PrimitiveDateTime::new(end_date + Duration::days(1), Time::MIDNIGHT)
+        .assume_utc()
+        .min(OffsetDateTime::now_utc());
+    let forecasts: Vec<_> = WeatherForecastDB::get_by_name_dates(pool, name, server, start, end)
+        .await?
+        .try_collect()
+        .await?;
+    let observations: Vec<_> = WeatherDataDB::get_by_name_dates(
+        pool,
+        Some(name),
+        server,
+        Some((start - MAX_OBSERVATION_GAP).date()),
+        Some((end + Duration::days(1)).date()),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?
+    .try_collect()
+    .await?;
+    Ok(get_forecast_accuracy(&forecasts, &observations))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration};
+    use uuid::Uuid;
+
+    use crate::{accuracy::get_forecast_accuracy, bench::demo_rows, model::WeatherForecastDB};
+
+    #[test]
+    fn test_get_forecast_accuracy() {
+        let observations = demo_rows(48);
+        let fetched_at = *observations[0].created_at - Duration::minutes(10);
+        let forecast = |lead_hours: i64, offset: f64| {
+            let forecast_time = fetched_at + Duration::hours(lead_hours);
+            let observed = observations
+                .iter()
+                .min_by_key(|o| (*o.created_at - forecast_time).abs())
+                .unwrap();
+            WeatherForecastDB {
+                id: Uuid::new_v4(),
+                location_name: observed.location_name.clone(),
+                server: observed.server.clone(),
+                fetched_at: fetched_at.into(),
+                forecast_time: observed.created_at,
+                temperature: observed.temperature + offset,
+                humidity: observed.humidity,
+                pressure: observed.pressure,
+                wind_speed: observed.wind_speed + offset.abs() / 2.0,
+                precipitation: None,
+                condition: None,
+                condition_code: None,
+                feels_like: None,
+                temperature_minimum: None,
+                temperature_maximum: None,
+                rain: None,
+                snow: None,
+                wind_direction: None,
+                timezone: None,
+            }
+        };
+        let mut forecasts = vec![
+            forecast(1, 1.0),
+            forecast(2, -1.0),
+            forecast(4, 2.0),
+            forecast(5, 2.0),
+            forecast(7, -3.0),
+        ];
+        let mut stale = forecast(1, 5.0);
+        stale.forecast_time = datetime!(2000-01-01 00:00 UTC).into();
+        forecasts.push(stale);
+
+        let accuracy = get_forecast_accuracy(&forecasts, &observations);
+        let leads: Vec<_> = accuracy.iter().map(|a| a.lead_hours).collect();
+        assert_eq!(leads, [0, 3, 6]);
+        assert_eq!(accuracy[0].count, 2);
+        assert!((accuracy[0].temperature_mae - 1.0).abs() < 1e-9);
+        assert!(accuracy[0].temperature_bias.abs() < 1e-9);
+        assert!((accuracy[0].wind_speed_mae - 0.5).abs() < 1e-9);
+        assert!((accuracy[1].temperature_bias - 2.0).abs() < 1e-9);
+        assert!((accuracy[2].temperature_mae - 3.0).abs() < 1e-9);
+        assert_eq!(accuracy[2].humidity_mae, 0.0);
+        assert_eq!(accuracy[2].pressure_mae, 0.0);
+    }
+}
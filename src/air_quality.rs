@@ -0,0 +1,142 @@
+use anyhow::{format_err, Error};
+use reqwest::Client;
+use serde::Deserialize;
+use stack_string::{format_sstr, StackString};
+use time::OffsetDateTime;
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::{config::Config, metrics::record_upstream_call, model::AirQualityData};
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+struct AirPollutionMain {
+    aqi: i32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+struct AirPollutionComponents {
+    pm2_5: f64,
+    pm10: f64,
+    o3: f64,
+    no2: f64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+struct AirPollutionEntry {
+    #[serde(with = "time::serde::timestamp")]
+    dt: OffsetDateTime,
+    main: AirPollutionMain,
+    components: AirPollutionComponents,
+}
+
+/// Response of the openweathermap `air_pollution` api
+#[derive(Deserialize, Debug)]
+struct AirPollution {
+    list: Vec<AirPollutionEntry>,
+}
+
+impl AirPollution {
+    fn into_air_quality_data(
+        self,
+        location_name: &str,
+        server: &str,
+    ) -> Result<AirQualityData, Error> {
+        let entry = self
+            .list
+            .first()
+            .ok_or_else(|| format_err!("Empty air pollution response"))?;
+        Ok(AirQualityData {
+            id: Uuid::new_v4(),
+            location_name: location_name.into(),
+            server: server.into(),
+            recorded_at: entry.dt.into(),
+            aqi: entry.main.aqi,
+            pm2_5: entry.components.pm2_5,
+            pm10: entry.components.pm10,
+            o3: entry.components.o3,
+            no2: entry.components.no2,
+        })
+    }
+}
+
+/// Description of the openweathermap air quality index
+#[must_use]
+pub fn get_aqi_description(aqi: i32) -> &'static str {
+    match aqi {
+        1 => "Good",
+        2 => "Fair",
+        3 => "Moderate",
+        4 => "Poor",
+        5 => "Very Poor",
+        _ => "Unknown",
+    }
+}
+
+/// Current air quality from the openweathermap air pollution api
+///
+/// # Errors
+/// Return error if the api request fails or returns no readings
+#[instrument(skip(client, config, api_key))]
+pub async fn fetch_air_quality(
+    client: &Client,
+    config: &Config,
+    api_key: &str,
+    location_name: &str,
+    latitude: f64,
+    longitude: f64,
+) -> Result<AirQualityData, Error> {
+    let url = format_sstr!(
+        "https://{}/{}air_pollution",
+        config.api_endpoint,
+        config.api_path
+    );
+    let latitude: StackString = format_sstr!("{latitude}");
+    let longitude: StackString = format_sstr!("{longitude}");
+    let result: Result<AirPollution, Error> = async {
+        let pollution = client
+            .get(url.as_str())
+            .query(&[
+                ("lat", latitude.as_str()),
+                ("lon", longitude.as_str()),
+                ("appid", api_key),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(pollution)
+    }
+    .await;
+    record_upstream_call("openweathermap", "air_pollution", result.is_ok());
+    result?.into_air_quality_data(location_name, &config.server)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::datetime;
+
+    use crate::air_quality::{get_aqi_description, AirPollution};
+
+    #[test]
+    fn test_air_pollution() -> Result<(), Error> {
+        let data = r#"{"coord": {"lon": -93.27, "lat": 44.98}, "list": [{
+            "main": {"aqi": 2},
+            "components": {"co": 201.94, "no": 0.02, "no2": 0.77, "o3": 68.66,
+                "so2": 0.64, "pm2_5": 0.5, "pm10": 0.54, "nh3": 0.12},
+            "dt": 1717243200
+        }]}"#;
+        let pollution: AirPollution = serde_json::from_str(data)?;
+        let air_quality = pollution.into_air_quality_data("Minneapolis", "test")?;
+        assert_eq!(air_quality.aqi, 2);
+        assert_eq!(*air_quality.recorded_at, datetime!(2024-06-01 12:00 UTC));
+        assert!((air_quality.pm2_5 - 0.5).abs() < 1e-9);
+        assert!((air_quality.o3 - 68.66).abs() < 1e-9);
+        assert_eq!(get_aqi_description(air_quality.aqi), "Fair");
+
+        let empty: AirPollution = serde_json::from_str(r#"{"list": []}"#)?;
+        assert!(empty.into_air_quality_data("Minneapolis", "test").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+use anyhow::{format_err, Error};
+use futures::TryStreamExt;
+use log::{error, info};
+use serde::Serialize;
+use stack_string::{format_sstr, StackString};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use weather_api_types::get_parameters;
+use weather_util_rust::weather_forecast::{ForecastEntry, WeatherForecast};
+
+use crate::{
+    app::AppState,
+    date_time_wrapper::DateTimeWrapper,
+    model::{AlertRule, WeatherDataDB},
+    notify::{notify_all, Notification},
+};
+
+/// A rule that fired isn't fired again within this period
+const ALERT_COOLDOWN: Duration = Duration::hours(6);
+/// Observations older than this aren't evaluated (seconds)
+const MAX_OBSERVATION_AGE: i32 = 3600;
+
+/// Quantity compared against the threshold of an `AlertRule`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertMetric {
+    /// Celsius
+    Temperature,
+    /// m/s
+    WindSpeed,
+    /// %
+    Humidity,
+    /// kPa
+    Pressure,
+    /// rain plus snow (mm)
+    Precipitation,
+}
+
+impl AlertMetric {
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Temperature => "temperature",
+            Self::WindSpeed => "wind_speed",
+            Self::Humidity => "humidity",
+            Self::Pressure => "pressure",
+            Self::Precipitation => "precipitation",
+        }
+    }
+
+    #[must_use]
+    pub fn unit(self) -> &'static str {
+        match self {
+            Self::Temperature => "C",
+            Self::WindSpeed => "m/s",
+            Self::Humidity => "%",
+            Self::Pressure => "kPa",
+            Self::Precipitation => "mm",
+        }
+    }
+
+    fn observed(self, row: &WeatherDataDB) -> f64 {
+        match self {
+            Self::Temperature => row.temperature - 273.15,
+            Self::WindSpeed => row.wind_speed,
+            Self::Humidity => row.humidity.into(),
+            Self::Pressure => row.pressure,
+            Self::Precipitation => row.rain.unwrap_or(0.0) + row.snow.unwrap_or(0.0),
+        }
+    }
+
+    fn forecast(self, entry: &ForecastEntry) -> f64 {
+        match self {
+            Self::Temperature => entry.main.temp.celcius(),
+            Self::WindSpeed => entry.wind.speed.mps(),
+            Self::Humidity => {
+                let humidity: i64 = entry.main.humidity.into();
+                humidity as f64
+            }
+            Self::Pressure => entry.main.pressure.kpa(),
+            Self::Precipitation => {
+                let rain = entry
+                    .rain
+                    .as_ref()
+                    .and_then(|r| r.three_hour)
+                    .unwrap_or_default();
+                let snow = entry
+                    .snow
+                    .as_ref()
+                    .and_then(|s| s.three_hour)
+                    .unwrap_or_default();
+                (rain + snow).millimeters()
+            }
+        }
+    }
+}
+
+impl fmt::Display for AlertMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+impl FromStr for AlertMetric {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "temperature" => Ok(Self::Temperature),
+            "wind_speed" => Ok(Self::WindSpeed),
+            "humidity" => Ok(Self::Humidity),
+            "pressure" => Ok(Self::Pressure),
+            "precipitation" => Ok(Self::Precipitation),
+            _ => Err(format_err!("Invalid metric {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertComparison {
+    Above,
+    Below,
+}
+
+impl AlertComparison {
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Above => "above",
+            Self::Below => "below",
+        }
+    }
+
+    #[must_use]
+    pub fn is_triggered(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Above => value > threshold,
+            Self::Below => value < threshold,
+        }
+    }
+}
+
+impl fmt::Display for AlertComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+impl FromStr for AlertComparison {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "above" => Ok(Self::Above),
+            "below" => Ok(Self::Below),
+            _ => Err(format_err!("Invalid comparison {s}")),
+        }
+    }
+}
+
+/// Json body posted to the webhook of a fired rule
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AlertNotification {
+    pub rule_id: Uuid,
+    pub location_name: StackString,
+    pub metric: StackString,
+    pub comparison: StackString,
+    pub threshold: f64,
+    pub value: f64,
+    /// time of the observation or forecast entry that crossed the threshold
+    #[serde(with = "time::serde::rfc3339")]
+    pub valid_at: OffsetDateTime,
+    pub forecast: bool,
+    pub message: StackString,
+}
+
+impl AlertNotification {
+    fn new(rule: &AlertRule, value: f64, valid_at: OffsetDateTime) -> Self {
+        let kind = if rule.forecast_hours.is_some() {
+            "forecast"
+        } else {
+            "observed"
+        };
+        let unit = rule
+            .metric
+            .parse::<AlertMetric>()
+            .map_or("", AlertMetric::unit);
+        let message = format_sstr!(
+            "{} {kind} {} {value:0.1} {unit} is {} {:0.1} {unit}",
+            rule.location_name,
+            rule.metric,
+            rule.comparison,
+            rule.threshold,
+        );
+        Self {
+            rule_id: rule.id,
+            location_name: rule.location_name.clone(),
+            metric: rule.metric.clone(),
+            comparison: rule.comparison.clone(),
+            threshold: rule.threshold,
+            value,
+            valid_at,
+            forecast: rule.forecast_hours.is_some(),
+            message,
+        }
+    }
+}
+
+/// Evaluate a rule against the latest observation or the forecast of its
+/// location, rules that fired within `ALERT_COOLDOWN` are skipped
+///
+/// # Errors
+/// Return error if the rule has an invalid metric or comparison
+pub fn evaluate_rule(
+    rule: &AlertRule,
+    latest: Option<&WeatherDataDB>,
+    forecast: Option<&WeatherForecast>,
+    now: OffsetDateTime,
+) -> Result<Option<AlertNotification>, Error> {
+    let metric: AlertMetric = rule.metric.parse()?;
+    let comparison: AlertComparison = rule.comparison.parse()?;
+    if rule
+        .last_fired_at
+        .is_some_and(|last| now - *last < ALERT_COOLDOWN)
+    {
+        return Ok(None);
+    }
+    let triggered = if let Some(hours) = rule.forecast_hours {
+        let end = now + Duration::hours(hours.into());
+        forecast.and_then(|forecast| {
+            forecast
+                .list
+                .iter()
+                .filter(|entry| entry.dt >= now && entry.dt <= end)
+                .map(|entry| (metric.forecast(entry), entry.dt))
+                .find(|(value, _)| comparison.is_triggered(*value, rule.threshold))
+        })
+    } else {
+        latest
+            .map(|row| (metric.observed(row), *row.created_at))
+            .filter(|(value, _)| comparison.is_triggered(*value, rule.threshold))
+    };
+    Ok(triggered.map(|(value, valid_at)| AlertNotification::new(rule, value, valid_at)))
+}
+
+/// Evaluate every rule, the rules that fired are posted to their webhook and
+/// sent to the configured notification sinks
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn check_alert_rules(app: &AppState) -> Result<(), Error> {
+    let mut rules: BTreeMap<StackString, Vec<AlertRule>> = BTreeMap::new();
+    let mut stream = Box::pin(AlertRule::get_all(&app.pool).await?);
+    while let Some(rule) = stream.try_next().await? {
+        rules
+            .entry(rule.location_name.clone())
+            .or_default()
+            .push(rule);
+    }
+    let now = OffsetDateTime::now_utc();
+    for (location_name, rules) in rules {
+        let latest = WeatherDataDB::get_closest_by_name(
+            &app.pool,
+            &location_name,
+            now.unix_timestamp() as i32,
+            MAX_OBSERVATION_AGE,
+        )
+        .await?;
+        let forecast = if rules.iter().any(|r| r.forecast_hours.is_some()) {
+            let loc = get_parameters(&location_name);
+            match app.weather.get_forecast(&app.api, &loc).await {
+                Ok(forecast) => Some(forecast),
+                Err(e) => {
+                    error!("failed to get forecast for {location_name} {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        for rule in &rules {
+            let notification = match evaluate_rule(rule, latest.as_ref(), forecast.as_ref(), now) {
+                Ok(Some(notification)) => notification,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("invalid alert rule {} {e}", rule.id);
+                    continue;
+                }
+            };
+            info!("alert {}", notification.message);
+            notify_all(
+                &app.client,
+                &app.config,
+                &Notification::new(
+                    format_sstr!("Weather alert {location_name}"),
+                    notification.message.clone(),
+                ),
+            )
+            .await;
+            let result = app
+                .client
+                .post(rule.webhook_url.as_str())
+                .json(&notification)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            match result {
+                Ok(_) => {
+                    rule.set_last_fired_at(&app.pool, DateTimeWrapper::now())
+                        .await?;
+                }
+                Err(e) => error!("webhook for alert rule {} failed {e}", rule.id),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::{macros::datetime, Duration};
+    use uuid::Uuid;
+
+    use crate::{
+        alerts::{evaluate_rule, AlertComparison, AlertMetric},
+        model::{AlertRule, WeatherDataDB},
+    };
+
+    fn rule(metric: &str, comparison: &str, threshold: f64) -> AlertRule {
+        let created_at = datetime!(2024-01-01 00:00 UTC);
+        AlertRule {
+            id: Uuid::new_v4(),
+            email: "user@example.com".into(),
+            location_name: "11106".into(),
+            metric: metric.into(),
+            comparison: comparison.into(),
+            threshold,
+            forecast_hours: None,
+            webhook_url: "https://example.com/hook".into(),
+            last_fired_at: None,
+            created_at: created_at.into(),
+        }
+    }
+
+    fn row(temperature: f64, wind_speed: f64) -> WeatherDataDB {
+        WeatherDataDB {
+            temperature,
+            temperature_minimum: temperature,
+            temperature_maximum: temperature,
+            humidity: 50,
+            wind_speed,
+            ..WeatherDataDB::test_row(datetime!(2024-01-15 12:00 UTC))
+        }
+    }
+
+    #[test]
+    fn test_alert_parse() -> Result<(), Error> {
+        assert_eq!("wind_speed".parse::<AlertMetric>()?, AlertMetric::WindSpeed);
+        assert_eq!("below".parse::<AlertComparison>()?, AlertComparison::Below);
+        assert!("gusts".parse::<AlertMetric>().is_err());
+        assert!(AlertComparison::Above.is_triggered(15.5, 15.0));
+        assert!(!AlertComparison::Below.is_triggered(15.0, 15.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_rule() -> Result<(), Error> {
+        let now = datetime!(2024-01-15 12:10 UTC);
+        let freezing = rule("temperature", "below", 0.0);
+        let cold = row(270.15, 3.0);
+        let notification = evaluate_rule(&freezing, Some(&cold), None, now)?.unwrap();
+        assert!((notification.value + 3.0).abs() < 1e-9);
+        assert!(!notification.forecast);
+        assert_eq!(
+            notification.message,
+            "11106 observed temperature -3.0 C is below 0.0 C"
+        );
+
+        let warm = row(280.15, 3.0);
+        assert!(evaluate_rule(&freezing, Some(&warm), None, now)?.is_none());
+        assert!(evaluate_rule(&freezing, None, None, now)?.is_none());
+
+        let windy = rule("wind_speed", "above", 15.0);
+        assert!(evaluate_rule(&windy, Some(&row(280.15, 16.0)), None, now)?.is_some());
+
+        let mut fired = freezing.clone();
+        fired.last_fired_at = Some((now - Duration::hours(1)).into());
+        assert!(evaluate_rule(&fired, Some(&cold), None, now)?.is_none());
+        fired.last_fired_at = Some((now - Duration::hours(7)).into());
+        assert!(evaluate_rule(&fired, Some(&cold), None, now)?.is_some());
+
+        let invalid = rule("gusts", "above", 15.0);
+        assert!(evaluate_rule(&invalid, Some(&cold), None, now).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use std::{collections::BTreeMap, f64::consts::PI};
+use time::{
+    format_description::FormatItem, macros::format_description, Date, Duration, OffsetDateTime,
+    UtcOffset,
+};
+
+use rweb_helper::DateType;
+use weather_api_types::dto::PrecipitationSummary;
+use weather_util_rust::{weather_data::WeatherData, weather_forecast::WeatherForecast};
+
+use crate::model::WeatherDataDB;
+
+const KELVIN_OFFSET: f64 = 273.15;
+
+/// Extraterrestrial radiation expressed as equivalent evaporation in mm/day
+/// (FAO-56 equation 21).
+#[must_use]
+pub fn extraterrestrial_radiation(latitude: f64, day_of_year: u16) -> f64 {
+    let phi = latitude.to_radians();
+    let angle = 2.0 * PI * f64::from(day_of_year) / 365.0;
+    let inverse_distance = 1.0 + 0.033 * angle.cos();
+    let declination = 0.409 * (angle - 1.39).sin();
+    let sunset_angle = (-phi.tan() * declination.tan()).clamp(-1.0, 1.0).acos();
+    let radiation = 24.0 * 60.0 / PI
+        * 0.0820
+        * inverse_distance
+        * (sunset_angle * phi.sin() * declination.sin()
+            + phi.cos() * declination.cos() * sunset_angle.sin());
+    0.408 * radiation.max(0.0)
+}
+
+/// Reference evapotranspiration in mm/day from the Hargreaves equation, with
+/// temperatures given in Celsius.
+#[must_use]
+pub fn hargreaves_evapotranspiration(
+    latitude: f64,
+    day_of_year: u16,
+    temp_min: f64,
+    temp_max: f64,
+) -> f64 {
+    let radiation = extraterrestrial_radiation(latitude, day_of_year);
+    let temp_mean = (temp_min + temp_max) / 2.0;
+    let temp_range = (temp_max - temp_min).max(0.0);
+    (0.0023 * radiation * (temp_mean + 17.8) * temp_range.sqrt()).max(0.0)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Schema)]
+#[schema(component = "DailyWaterBalance")]
+pub struct DailyWaterBalance {
+    #[schema(description = "Date")]
+    pub date: DateType,
+    #[schema(description = "Rainfall (mm)")]
+    pub rain_mm: f64,
+    #[schema(description = "Estimated Evapotranspiration (mm)")]
+    pub evapotranspiration_mm: f64,
+    #[schema(description = "Is Forecast")]
+    pub forecast: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "WateringAdvice")]
+pub struct WateringAdvice {
+    #[schema(description = "Location Name")]
+    pub name: StackString,
+    #[schema(description = "Recorded Rainfall (mm)")]
+    pub recorded_rain_mm: f64,
+    #[schema(description = "Forecast Rainfall (mm)")]
+    pub forecast_rain_mm: f64,
+    #[schema(description = "Estimated Evapotranspiration (mm)")]
+    pub evapotranspiration_mm: f64,
+    #[schema(description = "Water Balance (mm)")]
+    pub balance_mm: f64,
+    #[schema(description = "Allowed Deficit Before Watering (mm)")]
+    pub threshold_mm: f64,
+    #[schema(description = "Irrigation Needed")]
+    pub irrigate: bool,
+    #[schema(description = "Recommended Irrigation (mm)")]
+    pub recommended_mm: f64,
+    #[schema(description = "Daily Water Balance")]
+    pub days: Vec<DailyWaterBalance>,
+}
+
+#[derive(Default)]
+struct RecordedDay {
+    temp_min: Option<f64>,
+    temp_max: Option<f64>,
+    hourly_rain: BTreeMap<u8, f64>,
+}
+
+/// Combine recorded history and the forecast into a daily water balance,
+/// recommending irrigation when the deficit exceeds `threshold_mm`.
+#[must_use]
+pub fn get_watering_advice(
+    name: &str,
+    history: &[WeatherDataDB],
+    forecast: &WeatherForecast,
+    threshold_mm: f64,
+) -> WateringAdvice {
+    let latitude: f64 = forecast.city.coord.lat.into();
+
+    let mut recorded: BTreeMap<Date, RecordedDay> = BTreeMap::new();
+    for row in history {
+        let Ok(dt) = OffsetDateTime::from_unix_timestamp(i64::from(row.dt)) else {
+            continue;
+        };
+        let temp = row.temperature - KELVIN_OFFSET;
+        let day = recorded.entry(dt.date()).or_default();
+        day.temp_min = Some(day.temp_min.map_or(temp, |t| t.min(temp)));
+        day.temp_max = Some(day.temp_max.map_or(temp, |t| t.max(temp)));
+        // readings report the previous hour of rain, keep one value per hour
+        let rain = day.hourly_rain.entry(dt.hour()).or_default();
+        *rain = rain.max(row.rain.unwrap_or(0.0));
+    }
+
+    let mut days: Vec<DailyWaterBalance> = recorded
+        .iter()
+        .map(|(date, day)| {
+            let temp_min = day.temp_min.unwrap_or_default();
+            let temp_max = day.temp_max.unwrap_or_default();
+            DailyWaterBalance {
+                date: (*date).into(),
+                rain_mm: day.hourly_rain.values().sum(),
+                evapotranspiration_mm: hargreaves_evapotranspiration(
+                    latitude,
+                    date.ordinal(),
+                    temp_min,
+                    temp_max,
+                ),
+                forecast: false,
+            }
+        })
+        .collect();
+    days.extend(
+        forecast
+            .get_high_low()
+            .into_iter()
+            .filter(|(date, _)| !recorded.contains_key(date))
+            .map(|(date, (high, low, rain, snow, _))| DailyWaterBalance {
+                date: date.into(),
+                rain_mm: rain.millimeters() + snow.millimeters(),
+                evapotranspiration_mm: hargreaves_evapotranspiration(
+                    latitude,
+                    date.ordinal(),
+                    low.celcius(),
+                    high.celcius(),
+                ),
+                forecast: true,
+            }),
+    );
+
+    let recorded_rain_mm: f64 = days.iter().filter(|d| !d.forecast).map(|d| d.rain_mm).sum();
+    let forecast_rain_mm: f64 = days.iter().filter(|d| d.forecast).map(|d| d.rain_mm).sum();
+    let evapotranspiration_mm: f64 = days.iter().map(|d| d.evapotranspiration_mm).sum();
+    let balance_mm = recorded_rain_mm + forecast_rain_mm - evapotranspiration_mm;
+    let irrigate = balance_mm < -threshold_mm;
+    let recommended_mm = if irrigate { -balance_mm } else { 0.0 };
+
+    WateringAdvice {
+        name: name.into(),
+        recorded_rain_mm,
+        forecast_rain_mm,
+        evapotranspiration_mm,
+        balance_mm,
+        threshold_mm,
+        irrigate,
+        recommended_mm,
+        days,
+    }
+}
+
+static HOUR_FORMAT: &[FormatItem<'static>] =
+    format_description!("[hour repr:12 padding:none][period case:lower]");
+
+/// Clothing layer appropriate for a given feels-like temperature in
+/// Fahrenheit, warmer layers sort lower.
+#[must_use]
+pub fn clothing_layer(feels_like: f64) -> (u8, &'static str) {
+    if feels_like < 20.0 {
+        (0, "heavy coat, hat and gloves")
+    } else if feels_like < 40.0 {
+        (1, "winter coat")
+    } else if feels_like < 55.0 {
+        (2, "light jacket")
+    } else if feels_like < 65.0 {
+        (3, "sweater")
+    } else if feels_like < 80.0 {
+        (4, "t-shirt")
+    } else {
+        (5, "shorts and t-shirt")
+    }
+}
+
+fn is_precipitation(main: &str) -> bool {
+    matches!(main, "Rain" | "Drizzle" | "Thunderstorm")
+}
+
+fn is_snow(main: &str) -> bool {
+    main == "Snow"
+}
+
+/// Short clothing recommendation from current conditions and the next 12
+/// hours of forecast, e.g. "light jacket, umbrella after 3pm".
+#[must_use]
+pub fn get_clothing_advice(weather: &WeatherData, forecast: &WeatherForecast) -> StackString {
+    let fo: UtcOffset = weather.timezone.into();
+    let horizon = weather.dt + Duration::hours(12);
+    let upcoming: Vec<_> = forecast
+        .list
+        .iter()
+        .filter(|entry| entry.dt > weather.dt && entry.dt <= horizon)
+        .collect();
+
+    let (rank, layer) = clothing_layer(weather.main.feels_like.fahrenheit());
+    let mut advice = vec![StackString::from(layer)];
+
+    if let Some((entry, (_, colder))) = upcoming
+        .iter()
+        .map(|entry| (entry, clothing_layer(entry.main.feels_like.fahrenheit())))
+        .find(|(_, (r, _))| *r < rank)
+    {
+        let hour = entry
+            .dt
+            .to_offset(fo)
+            .format(HOUR_FORMAT)
+            .unwrap_or_default();
+        advice.push(format_sstr!("{colder} after {hour}"));
+    }
+
+    let raining = weather.weather.iter().any(|w| is_precipitation(&w.main));
+    let snowing = weather.weather.iter().any(|w| is_snow(&w.main));
+    if raining {
+        advice.push("umbrella".into());
+    } else if let Some(entry) = upcoming
+        .iter()
+        .find(|entry| entry.weather.iter().any(|w| is_precipitation(&w.main)))
+    {
+        let hour = entry
+            .dt
+            .to_offset(fo)
+            .format(HOUR_FORMAT)
+            .unwrap_or_default();
+        advice.push(format_sstr!("umbrella after {hour}"));
+    }
+    if snowing
+        || upcoming
+            .iter()
+            .any(|entry| entry.weather.iter().any(|w| is_snow(&w.main)))
+    {
+        advice.push("boots".into());
+    }
+
+    let mut output = StackString::new();
+    for (idx, item) in advice.iter().enumerate() {
+        if idx > 0 {
+            output.push_str(", ");
+        }
+        output.push_str(item);
+    }
+    output
+}
+
+/// Recorded rain and snow so far today and this week in the local time of
+/// the most recent row, readings report the previous hour so one value is
+/// kept per hour
+#[must_use]
+pub fn get_precipitation_summary(
+    name: &str,
+    history: &[WeatherDataDB],
+    now: OffsetDateTime,
+) -> PrecipitationSummary {
+    let offset = history
+        .last()
+        .and_then(|row| UtcOffset::from_whole_seconds(row.timezone).ok())
+        .unwrap_or(UtcOffset::UTC);
+    let today = now.to_offset(offset).date();
+    let week_start = today - Duration::days(today.weekday().number_days_from_monday().into());
+
+    let mut hourly: BTreeMap<i64, (Date, f64, f64)> = BTreeMap::new();
+    for row in history {
+        let Ok(dt) = OffsetDateTime::from_unix_timestamp(i64::from(row.dt)) else {
+            continue;
+        };
+        let date = dt.to_offset(offset).date();
+        if date < week_start || date > today {
+            continue;
+        }
+        let hour = hourly
+            .entry(dt.unix_timestamp() / 3600)
+            .or_insert((date, 0.0, 0.0));
+        hour.1 = hour.1.max(row.rain.unwrap_or(0.0));
+        hour.2 = hour.2.max(row.snow.unwrap_or(0.0));
+    }
+
+    let mut summary = PrecipitationSummary {
+        location_name: name.into(),
+        hours: hourly.len(),
+        ..PrecipitationSummary::default()
+    };
+    for (date, rain, snow) in hourly.values() {
+        summary.week_rain += rain;
+        summary.week_snow += snow;
+        if *date == today {
+            summary.today_rain += rain;
+            summary.today_snow += snow;
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::{
+        analysis::{
+            clothing_layer, extraterrestrial_radiation, get_precipitation_summary,
+            hargreaves_evapotranspiration,
+        },
+        model::WeatherDataDB,
+    };
+
+    #[test]
+    fn test_extraterrestrial_radiation() {
+        // FAO-56 example 8: 20S on 3 September, Ra = 32.2 MJ m-2 day-1
+        let radiation = extraterrestrial_radiation(-20.0, 246);
+        assert!((radiation - 0.408 * 32.2).abs() < 0.1);
+        // polar night
+        assert!(extraterrestrial_radiation(80.0, 355).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_hargreaves_evapotranspiration() {
+        let et = hargreaves_evapotranspiration(45.0, 190, 15.0, 30.0);
+        assert!(et > 4.0 && et < 8.0);
+        assert!(hargreaves_evapotranspiration(45.0, 190, 20.0, 20.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_clothing_layer() {
+        assert_eq!(clothing_layer(10.0).1, "heavy coat, hat and gloves");
+        assert_eq!(clothing_layer(50.0).1, "light jacket");
+        assert_eq!(clothing_layer(90.0).1, "shorts and t-shirt");
+        assert!(clothing_layer(30.0).0 < clothing_layer(60.0).0);
+    }
+
+    fn row(created_at: time::OffsetDateTime, rain: Option<f64>) -> WeatherDataDB {
+        WeatherDataDB {
+            rain,
+            timezone: -4 * 3600,
+            ..WeatherDataDB::test_row(created_at)
+        }
+    }
+
+    #[test]
+    fn test_get_precipitation_summary() {
+        // Thursday afternoon in New York
+        let now = datetime!(2024-06-13 20:00 UTC);
+        let history = [
+            // previous week
+            row(datetime!(2024-06-09 12:00 UTC), Some(10.0)),
+            // Monday
+            row(datetime!(2024-06-10 12:00 UTC), Some(2.0)),
+            // Wednesday evening local time, Thursday in UTC
+            row(datetime!(2024-06-13 02:00 UTC), Some(1.0)),
+            // two readings of the same hour
+            row(datetime!(2024-06-13 15:05 UTC), Some(0.5)),
+            row(datetime!(2024-06-13 15:35 UTC), Some(0.75)),
+            row(datetime!(2024-06-13 18:00 UTC), None),
+        ];
+        let summary = get_precipitation_summary("11106", &history, now);
+        assert_eq!(summary.hours, 4);
+        assert!((summary.today_rain - 0.75).abs() < 1e-9);
+        assert!((summary.week_rain - 3.75).abs() < 1e-9);
+        assert!(summary.week_snow.abs() < 1e-9);
+
+        let empty = get_precipitation_summary("11106", &[], now);
+        assert_eq!(empty.hours, 0);
+    }
+}
@@ -3,14 +3,16 @@ use serde::{Deserialize, Serialize};
 use stack_string::{SmallString, StackString};
 use std::borrow::Cow;
 
+use weather_api_types::units::Units;
 use weather_util_rust::weather_api::{WeatherApi, WeatherLocation};
 
 use crate::{
     config::Config, country_code_wrapper::CountryCodeWrapper, errors::ServiceError as Error,
     latitude_wrapper::LatitudeWrapper, longitude_wrapper::LongitudeWrapper,
+    units_wrapper::UnitsWrapper,
 };
 
-#[derive(Serialize, Deserialize, Schema)]
+#[derive(Serialize, Deserialize, Schema, Default)]
 pub struct ApiOptions {
     pub zip: Option<u64>,
     pub country_code: Option<CountryCodeWrapper>,
@@ -18,9 +20,17 @@ pub struct ApiOptions {
     pub lat: Option<LatitudeWrapper>,
     pub lon: Option<LongitudeWrapper>,
     pub appid: Option<SmallString<32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<UnitsWrapper>,
 }
 
 impl ApiOptions {
+    /// Requested unit system, `default` when the `units` parameter is absent
+    #[must_use]
+    pub fn get_units(&self, default: Units) -> Units {
+        self.units.map_or(default, Into::into)
+    }
+
     #[must_use]
     pub fn get_weather_api<'a>(&self, api: &'a WeatherApi) -> Cow<'a, WeatherApi> {
         if let Some(appid) = &self.appid {
@@ -30,6 +40,13 @@ impl ApiOptions {
         }
     }
 
+    /// Whether the request names a location rather than relying on the
+    /// configured default
+    #[must_use]
+    pub fn has_location(&self) -> bool {
+        self.zip.is_some() || self.q.is_some() || (self.lat.is_some() && self.lon.is_some())
+    }
+
     /// # Errors
     /// Returns error if unable to determine location
     pub fn get_weather_location(&self, config: &Config) -> Result<WeatherLocation, Error> {
@@ -87,6 +104,7 @@ mod test {
         env::{remove_var, set_var},
         path::Path,
     };
+    use weather_api_types::units::Units;
     use weather_util_rust::{
         latitude::Latitude,
         longitude::Longitude,
@@ -111,6 +129,11 @@ mod test {
             assert!(false);
         }
 
+        assert_eq!(opt.get_units(Units::Imperial), Units::Imperial);
+        let opt: ApiOptions = serde_json::from_str(r#"{"zip":55427,"units":"metric"}"#)?;
+        assert_eq!(opt.get_units(Units::Imperial), Units::Metric);
+        assert!(serde_urlencoded::to_string(&opt)?.contains("units=metric"));
+
         let opt: ApiOptions = serde_json::from_str(r#"{"appid":"TEST"}"#)?;
 
         set_var("ZIPCODE", "49934");
@@ -1,16 +1,33 @@
 use anyhow::Error;
 use authorized_users::TRIGGER_DB_UPDATE;
-use cached::{proc_macro::cached, TimedSizedCache};
-use log::{error, info};
+use cached::{proc_macro::cached, Cached, TimedSizedCache};
+use futures::TryStreamExt;
+use log::{debug, error, info, warn};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use reqwest::Client;
 use rweb::{
-    filters::BoxedFilter,
-    http::header::CONTENT_TYPE,
+    filters::{path::FullPath, BoxedFilter},
+    http::{header::CONTENT_TYPE, Method},
     openapi::{self, Info},
     reply, Filter, Reply,
 };
+use serde::Deserialize;
 use stack_string::{format_sstr, StackString};
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::{task::spawn, time::interval};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::{
+    task::spawn,
+    time::{interval, sleep, sleep_until, Instant},
+};
 
 use weather_util_rust::{
     weather_api::{WeatherApi, WeatherLocation},
@@ -19,67 +36,450 @@ use weather_util_rust::{
 };
 
 use super::{
-    config::Config,
+    accuracy::record_forecast,
+    alerts::check_alert_rules,
+    attribution::{Attribution, ATTRIBUTION_HEADER},
+    cache_key::WeatherCacheKey,
+    config::{Config, ConfigInner, RecordedLocation},
     errors::{error_response, ServiceError},
-    logged_user::{fill_from_db, get_secrets},
-    model::{WeatherDataDB, WeatherLocationCache},
+    etag::conditional_response,
+    events::{pressure_tendency, PRESSURE_TENDENCY_WINDOW},
+    landing::{get_landing_links, get_startup_banner},
+    lightning::record_lightning_activity,
+    logged_user::{fill_api_keys_from_db, fill_from_db, get_secrets},
+    metrics::record_request,
+    model::{LocationProviderOverride, RecordedLocationDB, WeatherDataDB, WeatherLocationCache},
+    negotiate::{document_binary_formats, negotiate_format},
+    offline_forecast::{get_recorded_offline_forecast, get_recorded_weather},
+    openapi_diff::{get_baseline, latest_baseline, OpenApiDiff, OPENAPI_BASELINES},
     pgpool::PgPool,
+    privacy::{request_span, set_log_locations, LogLocation},
+    providers::{ProviderChain, WeatherProvider, WeatherProviderType},
+    publish::{publish_snapshots, PublishTarget},
+    rate_limit::RateLimiter,
+    render_stats::{load_render_statistics, persist_render_statistics},
+    retention::expire_caches,
     routes::{
-        forecast, forecast_plot, forecast_plots, forecast_precip_plot, forecast_temp_plot,
-        frontpage, geo_direct, geo_reverse, geo_zip, history, history_plot, history_plots,
-        history_precip_plot, history_temp_plot, history_update, locations, statistics,
-        timeseries_js, user, weather,
+        admin::get_admin_path, geo::get_geo_path, history::get_history_path, plots::get_plots_path,
+        user::get_user_path, weather::get_weather_path,
+    },
+    services::{
+        CachedLocationService, CachedWeatherService, HistoryService, LocationService,
+        StoredHistoryService, WeatherService,
     },
+    stream::{publish_weather_update, weather_stream, weather_ws},
+    upstream_queue::{init_upstream_queue, upstream_permit, UpstreamBusy},
+    uv_index::record_uv_index,
 };
 
+#[cfg(feature = "grpc")]
+use super::grpc::run_grpc_server;
+#[cfg(unix)]
+use super::logging::reload_log_filter;
+#[cfg(feature = "otlp")]
+use super::telemetry::init_telemetry;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+
+/// Lifespan (seconds) of cached weather data and forecasts
+pub const WEATHER_CACHE_TTL: u64 = 3600;
+
+/// Number of rows skipped by delta based recording
+pub static SKIPPED_RECORDS: AtomicU64 = AtomicU64::new(0);
+
+/// Resolve a location to latitude / longitude using the location cache,
+/// populating the cache from the geo api when needed, without a database the
+/// geo api is always queried.
+///
+/// # Errors
+/// Returns error if db query fails
+pub async fn resolve_location(
+    pool: &PgPool,
+    api: &WeatherApi,
+    loc: &WeatherLocation,
+) -> Result<WeatherLocation, ServiceError> {
+    if !pool.is_enabled() {
+        let _permit = upstream_permit().await.map_err(Error::from)?;
+        return Ok(WeatherLocationCache::from_weather_location(api, loc)
+            .await
+            .ok()
+            .and_then(|l| l.get_lat_lon_location().ok())
+            .unwrap_or_else(|| loc.clone()));
+    }
+    if let Some(l) = WeatherLocationCache::from_weather_location_cache(pool, loc).await? {
+        l.touch(pool).await?;
+        return Ok(l.get_lat_lon_location()?);
+    }
+    let _permit = upstream_permit().await.map_err(Error::from)?;
+    if let Ok(l) = WeatherLocationCache::from_weather_location(api, loc).await {
+        info!("create_cache {}", LogLocation(&loc));
+        l.insert(pool).await?;
+        Ok(l.get_lat_lon_location()?)
+    } else {
+        Ok(loc.clone())
+    }
+}
+
+/// Provider chain for a location, `metno_locations` override
+/// `weather_provider`
+#[must_use]
+pub fn get_provider(config: &Config, api: &WeatherApi, location_name: &str) -> ProviderChain {
+    let provider_type = if config
+        .metno_locations
+        .iter()
+        .any(|l| format_sstr!("{l}") == location_name)
+    {
+        WeatherProviderType::MetNo
+    } else {
+        config.weather_provider
+    };
+    ProviderChain::new(provider_type, config, api)
+}
+
+/// Provider api of `location_name`, a row of `location_provider_overrides`
+/// takes precedence over `provider_overrides` of the config
+///
+/// # Errors
+/// Returns error if the db query fails
+pub async fn get_location_api<'a>(
+    pool: &PgPool,
+    config: &Config,
+    api: &'a WeatherApi,
+    location_name: &str,
+) -> Result<Cow<'a, WeatherApi>, Error> {
+    let stored = if pool.is_enabled() {
+        LocationProviderOverride::get_by_location_name(pool, location_name)
+            .await?
+            .map(|row| row.provider_override())
+    } else {
+        None
+    };
+    let provider_override = stored
+        .as_ref()
+        .or_else(|| config.provider_overrides.get(location_name));
+    Ok(provider_override.map_or(Cow::Borrowed(api), |o| Cow::Owned(o.apply(config, api))))
+}
+
+fn is_upstream_busy(e: &ServiceError) -> bool {
+    matches!(e, ServiceError::AnyhowError(e) if e.is::<UpstreamBusy>())
+}
+
+/// Current weather of `loc`, the most recent recorded observation is served
+/// when the upstream queue is full
+///
 /// # Errors
 /// Returns error if query fails
+pub async fn get_weather_data(
+    pool: &PgPool,
+    config: &Config,
+    api: &WeatherApi,
+    loc: &WeatherLocation,
+) -> Result<WeatherData, ServiceError> {
+    match fetch_weather_data(pool, config, api, loc).await {
+        Err(e) if is_upstream_busy(&e) && pool.is_enabled() => {
+            let location_name = format_sstr!("{loc}");
+            let recorded =
+                get_recorded_weather(pool, &location_name, OffsetDateTime::now_utc()).await?;
+            let recorded = recorded.ok_or(e)?;
+            warn!(
+                "upstream busy, serving recorded weather of {}",
+                LogLocation(loc)
+            );
+            Ok(recorded.into())
+        }
+        result => result,
+    }
+}
+
+/// Current weather of `loc` from the cache or the provider chain
+///
+/// # Errors
+/// Returns error if query fails or the upstream queue is full
 #[cached(
-    ty = "TimedSizedCache<StackString, WeatherData>",
-    create = "{ TimedSizedCache::with_size_and_lifespan(100, 3600) }",
-    convert = r#"{ format_sstr!("{:?}", loc) }"#,
+    name = "GET_WEATHER_DATA",
+    ty = "TimedSizedCache<WeatherCacheKey, WeatherData>",
+    create = "{ TimedSizedCache::with_size_and_lifespan(100, WEATHER_CACHE_TTL) }",
+    convert = r#"{ WeatherCacheKey::new(api, loc) }"#,
     result = true
 )]
-pub async fn get_weather_data(
+pub async fn fetch_weather_data(
     pool: &PgPool,
     config: &Config,
     api: &WeatherApi,
     loc: &WeatherLocation,
 ) -> Result<WeatherData, ServiceError> {
     let location_name = format_sstr!("{loc}");
-    let loc = {
-        if let Some(l) = WeatherLocationCache::from_weather_location_cache(pool, loc).await? {
-            l.get_lat_lon_location()?
-        } else if let Ok(l) = WeatherLocationCache::from_weather_location(api, loc).await {
-            info!("create_cache {l:?}");
-            l.insert(pool).await?;
-            l.get_lat_lon_location()?
-        } else {
-            loc.clone()
-        }
+    let api = get_location_api(pool, config, api, &location_name).await?;
+    let loc = resolve_location(pool, &api, loc).await?;
+    let mut weather_data = {
+        let _permit = upstream_permit().await.map_err(Error::from)?;
+        get_provider(config, &api, &location_name)
+            .get_weather_data(&loc)
+            .await?
     };
-    let weather_data = api.get_weather_data(&loc).await?;
+    if weather_data.name.is_empty() {
+        weather_data.name = location_name.as_str().into();
+    }
+    if !pool.is_enabled() {
+        return Ok(weather_data);
+    }
     let mut weather_data_db: WeatherDataDB = weather_data.clone().into();
     weather_data_db.set_location_name(&location_name);
     weather_data_db.set_server(&config.server);
-    info!("writing {loc} to db");
+    if config.record_delta {
+        if let Some(last) =
+            WeatherDataDB::get_latest_by_name(pool, &location_name, &config.server).await?
+        {
+            if weather_data_db.is_within_delta(&last, config) {
+                SKIPPED_RECORDS.fetch_add(1, Ordering::Relaxed);
+                info!(
+                    "skipping {}, unchanged since {}",
+                    LogLocation(&loc),
+                    last.created_at
+                );
+                return Ok(weather_data);
+            }
+        }
+    }
+    info!("writing {} to db", LogLocation(&loc));
     weather_data_db.insert(pool).await?;
+    publish_weather_update(&weather_data_db);
     Ok(weather_data)
 }
 
+/// Forecast of `loc`, the offline forecast of the recorded location is
+/// served when the upstream queue is full
+///
 /// # Errors
-/// Will return error if `WeatherApi::run_api` fails
+/// Will return error if every provider in the chain fails
+pub async fn get_weather_forecast(
+    pool: &PgPool,
+    config: &Config,
+    api: &WeatherApi,
+    loc: &WeatherLocation,
+) -> Result<WeatherForecast, ServiceError> {
+    match fetch_weather_forecast(pool, config, api, loc).await {
+        Err(e) if is_upstream_busy(&e) && pool.is_enabled() => {
+            let location_name = format_sstr!("{loc}");
+            let forecast =
+                get_recorded_offline_forecast(pool, &location_name, OffsetDateTime::now_utc())
+                    .await
+                    .map_err(|_| e)?;
+            warn!(
+                "upstream busy, serving offline forecast of {}",
+                LogLocation(loc)
+            );
+            Ok(forecast)
+        }
+        result => result,
+    }
+}
+
+/// Forecast of `loc` from the cache or the provider chain
+///
+/// # Errors
+/// Will return error if every provider in the chain fails or the upstream
+/// queue is full
 #[cached(
-    ty = "TimedSizedCache<StackString, WeatherForecast>",
-    create = "{ TimedSizedCache::with_size_and_lifespan(100, 3600) }",
-    convert = r#"{ format_sstr!("{:?}", loc) }"#,
+    name = "GET_WEATHER_FORECAST",
+    ty = "TimedSizedCache<WeatherCacheKey, WeatherForecast>",
+    create = "{ TimedSizedCache::with_size_and_lifespan(100, WEATHER_CACHE_TTL) }",
+    convert = r#"{ WeatherCacheKey::new(api, loc) }"#,
     result = true
 )]
-pub async fn get_weather_forecast(
+pub async fn fetch_weather_forecast(
+    pool: &PgPool,
+    config: &Config,
     api: &WeatherApi,
     loc: &WeatherLocation,
 ) -> Result<WeatherForecast, ServiceError> {
-    api.get_weather_forecast(loc).await.map_err(Into::into)
+    let location_name = format_sstr!("{loc}");
+    let api = get_location_api(pool, config, api, &location_name).await?;
+    let loc = resolve_location(pool, &api, loc).await?;
+    let forecast = {
+        let _permit = upstream_permit().await.map_err(Error::from)?;
+        get_provider(config, &api, &location_name)
+            .get_weather_forecast(&loc)
+            .await?
+    };
+    if pool.is_enabled() {
+        record_forecast(pool, &location_name, &config.server, &forecast).await?;
+    }
+    Ok(forecast)
+}
+
+/// Remove every entry of `location` whatever account it was fetched with,
+/// returns the number of entries removed
+fn remove_location_entries<V>(
+    cache: &mut TimedSizedCache<WeatherCacheKey, V>,
+    location: &str,
+) -> usize {
+    let keys: Vec<_> = cache
+        .key_order()
+        .filter(|key| key.location == location)
+        .cloned()
+        .collect();
+    keys.iter()
+        .filter(|key| cache.cache_remove(key).is_some())
+        .count()
+}
+
+/// Evict `loc` from the weather data and forecast caches, or clear both when
+/// `loc` is `None`, returns the number of (data, forecast) entries removed
+pub async fn invalidate_weather_caches(loc: Option<&WeatherLocation>) -> (usize, usize) {
+    let mut data_cache = GET_WEATHER_DATA.lock().await;
+    let mut forecast_cache = GET_WEATHER_FORECAST.lock().await;
+    if let Some(loc) = loc {
+        let location = WeatherCacheKey::location_key(loc);
+        (
+            remove_location_entries(&mut data_cache, &location),
+            remove_location_entries(&mut forecast_cache, &location),
+        )
+    } else {
+        let removed = (data_cache.cache_size(), forecast_cache.cache_size());
+        data_cache.cache_clear();
+        forecast_cache.cache_clear();
+        removed
+    }
+}
+
+fn is_active_weather(
+    weather: &WeatherData,
+    pressure_tendency: Option<f64>,
+    threshold: f64,
+) -> bool {
+    let precipitation = weather.rain.is_some()
+        || weather.snow.is_some()
+        || weather
+            .weather
+            .iter()
+            .any(|w| matches!(&*w.main, "Rain" | "Drizzle" | "Thunderstorm" | "Snow"));
+    let rapid_pressure_change = pressure_tendency.is_some_and(|rate| rate.abs() > threshold);
+    precipitation || rapid_pressure_change
+}
+
+/// Recent pressure readings of one location, enough to compute
+/// `pressure_tendency` over `PRESSURE_TENDENCY_WINDOW`
+#[derive(Default)]
+struct PressureReadings(VecDeque<(OffsetDateTime, f64)>);
+
+impl PressureReadings {
+    /// Record a reading, returns the pressure tendency (kPa/h) up to it
+    fn push(&mut self, dt: OffsetDateTime, pressure: f64) -> Option<f64> {
+        // repeated polls of a cached observation carry the same time
+        if self.0.back().is_some_and(|(last, _)| *last >= dt) {
+            self.0.pop_back();
+        }
+        self.0.push_back((dt, pressure));
+        let oldest = dt - PRESSURE_TENDENCY_WINDOW * 2;
+        while self.0.front().is_some_and(|(t, _)| *t < oldest) {
+            self.0.pop_front();
+        }
+        pressure_tendency(self.0.make_contiguous())
+    }
+}
+
+/// Polling interval for the adaptive recorder, never shorter than the
+/// interval allowed by `api_calls_per_day` across all recorded locations.
+fn get_polling_interval(config: &ConfigInner, active: bool, n_locations: usize) -> Duration {
+    let secs = if active {
+        config.polling_interval_min
+    } else {
+        config.polling_interval_max
+    };
+    let quota_floor = config.api_calls_per_day.map_or(0, |quota| {
+        (86400 * n_locations as u64).div_ceil(quota.max(1))
+    });
+    Duration::from_secs(secs.max(quota_floor))
+}
+
+/// Random delay added to each poll of a recorded location, as a fraction of
+/// its interval, so locations sharing an interval don't hit the provider at
+/// the same moment
+const RECORD_JITTER: f64 = 0.1;
+
+fn get_record_jitter(period: Duration, rng: &mut impl Rng) -> Duration {
+    period.mul_f64(RECORD_JITTER * rng.gen::<f64>())
+}
+
+/// How often the recorder reloads `recorded_locations`
+const RECORDED_LOCATIONS_RELOAD: Duration = Duration::from_secs(60);
+
+/// Rows of `recorded_locations`, `locations_to_record` when the table can't
+/// be read
+async fn get_recorded_locations(app: &AppState) -> Vec<RecordedLocation> {
+    if !app.pool.is_enabled() {
+        return Vec::new();
+    }
+    match RecordedLocationDB::get_all(&app.pool).await {
+        Ok(rows) => match rows.map_ok(Into::into).try_collect().await {
+            Ok(recorded) => return recorded,
+            Err(e) => error!("Encountered error {e}"),
+        },
+        Err(e) => error!("Encountered error {e}"),
+    }
+    app.config.locations_to_record.clone()
+}
+
+/// Next poll of each recorded location, every location keeps its own
+/// interval
+struct RecordSchedule {
+    entries: Vec<(Instant, Duration, WeatherLocation)>,
+}
+
+impl RecordSchedule {
+    /// The first polls are spread over the jitter of each interval
+    fn new(
+        config: &Config,
+        recorded: &[RecordedLocation],
+        now: Instant,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let entries = recorded
+            .iter()
+            .map(|r| {
+                let period = Duration::from_secs(r.get_interval(config).max(1));
+                let due = now + get_record_jitter(period, rng);
+                (due, period, r.location.clone())
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Replace the set of locations, locations whose interval didn't change
+    /// keep their next poll, new ones are spread over the jitter
+    fn update(
+        &mut self,
+        config: &Config,
+        recorded: &[RecordedLocation],
+        now: Instant,
+        rng: &mut impl Rng,
+    ) {
+        let mut previous = std::mem::take(&mut self.entries);
+        self.entries = Self::new(config, recorded, now, rng).entries;
+        for (due, period, loc) in &mut self.entries {
+            if let Some(idx) = previous
+                .iter()
+                .position(|(_, p, l)| p == period && l == loc)
+            {
+                *due = previous.swap_remove(idx).0;
+            }
+        }
+    }
+
+    /// Due time of the next poll
+    fn peek(&self) -> Option<Instant> {
+        self.entries.iter().map(|(due, _, _)| *due).min()
+    }
+
+    /// Location due first along with its due time, its next poll is scheduled
+    /// an interval (plus jitter) later
+    fn next_due(&mut self, rng: &mut impl Rng) -> Option<(Instant, WeatherLocation)> {
+        let (due, period, loc) = self.entries.iter_mut().min_by_key(|(due, _, _)| *due)?;
+        let current = *due;
+        *due = current + *period + get_record_jitter(*period, rng);
+        Some((current, loc.clone()))
+    }
 }
 
 #[derive(Clone)]
@@ -87,6 +487,30 @@ pub struct AppState {
     pub api: Arc<WeatherApi>,
     pub config: Config,
     pub pool: PgPool,
+    pub client: Client,
+    pub weather: Arc<dyn WeatherService>,
+    pub history: Arc<dyn HistoryService>,
+    pub locations: Arc<dyn LocationService>,
+}
+
+impl AppState {
+    #[must_use]
+    pub fn new(config: &Config, pool: &PgPool) -> Self {
+        Self {
+            api: Arc::new(WeatherApi::new(
+                &config.api_key,
+                &config.api_endpoint,
+                &config.api_path,
+                &config.geo_path,
+            )),
+            config: config.clone(),
+            pool: pool.clone(),
+            client: Client::new(),
+            weather: Arc::new(CachedWeatherService::new(pool, config)),
+            history: Arc::new(StoredHistoryService::new(pool, config)),
+            locations: Arc::new(CachedLocationService::new(pool)),
+        }
+    }
 }
 
 /// # Errors
@@ -94,53 +518,58 @@ pub struct AppState {
 pub async fn start_app() -> Result<(), Error> {
     let config = Config::init_config(None)?;
     get_secrets(&config.secret_path, &config.jwt_secret_path).await?;
+    #[cfg(feature = "otlp")]
+    let _telemetry = init_telemetry(&config)?;
+    #[cfg(not(feature = "otlp"))]
+    if config.otlp_endpoint.is_some() {
+        warn!("OTLP_ENDPOINT is set but the otlp feature is not enabled");
+    }
 
     let port = config.port;
     run_app(&config, port).await
 }
 
+fn get_api_info() -> Info {
+    Info {
+        title: "Weather App".into(),
+        description: "Web App to disply weather from openweatherapi".into(),
+        version: env!("CARGO_PKG_VERSION").into(),
+        ..Info::default()
+    }
+}
+
+/// Spec served at `/weather/openapi/json`, built without a database for the
+/// `openapi-diff` command
+///
+/// # Errors
+/// Returns error if the spec fails to serialize
+pub fn get_api_spec(config: &Config) -> Result<serde_json::Value, Error> {
+    let app = AppState::new(config, &PgPool::disabled());
+    let (mut spec, _) = openapi::spec()
+        .info(get_api_info())
+        .build(|| get_api_path(&app));
+    document_binary_formats(&mut spec);
+    serde_json::to_value(&spec).map_err(Into::into)
+}
+
+#[derive(Deserialize)]
+struct OpenApiCompatRequest {
+    /// baseline name, the latest one when missing
+    against: Option<StackString>,
+}
+
 fn get_api_path(app: &AppState) -> BoxedFilter<(impl Reply,)> {
-    let frontpage_path = frontpage(app.clone()).boxed();
-    let forecast_plot_path = forecast_plot(app.clone()).boxed();
-    let timeseries_js_path = timeseries_js().boxed();
-    let weather_path = weather(app.clone()).boxed();
-    let forecast_path = forecast(app.clone()).boxed();
-    let statistics_path = statistics().boxed();
-    let locations_path = locations(app.clone()).boxed();
-    let history_path = history(app.clone()).boxed();
-    let history_update_path = history_update(app.clone()).boxed();
-    let history_plot_path = history_plot(app.clone()).boxed();
-    let geo_direct_path = geo_direct(app.clone()).boxed();
-    let geo_zip_path = geo_zip(app.clone()).boxed();
-    let geo_reverse_path = geo_reverse(app.clone()).boxed();
-    let user_path = user().boxed();
-    let forecast_plots_path = forecast_plots(app.clone()).boxed();
-    let history_plots_path = history_plots(app.clone()).boxed();
-    let forecast_temp_plot_path = forecast_temp_plot(app.clone()).boxed();
-    let forecast_precip_plot_path = forecast_precip_plot(app.clone()).boxed();
-    let history_temp_plot_path = history_temp_plot(app.clone()).boxed();
-    let history_precip_plot_path = history_precip_plot(app.clone()).boxed();
-
-    frontpage_path
-        .or(forecast_plot_path)
-        .or(weather_path)
-        .or(forecast_path)
-        .or(statistics_path)
-        .or(timeseries_js_path)
-        .or(locations_path)
-        .or(history_path)
-        .or(history_update_path)
-        .or(history_plot_path)
-        .or(geo_direct_path)
-        .or(geo_zip_path)
-        .or(geo_reverse_path)
-        .or(user_path)
-        .or(forecast_plots_path)
-        .or(history_plots_path)
-        .or(forecast_temp_plot_path)
-        .or(forecast_precip_plot_path)
-        .or(history_temp_plot_path)
-        .or(history_precip_plot_path)
+    get_weather_path(app)
+        .or(get_history_path(app))
+        .unify()
+        .or(get_plots_path(app))
+        .unify()
+        .or(get_geo_path(app))
+        .unify()
+        .or(get_user_path(app))
+        .unify()
+        .or(get_admin_path(app))
+        .unify()
         .boxed()
 }
 
@@ -149,53 +578,275 @@ async fn run_app(config: &Config, port: u32) -> Result<(), Error> {
         let mut i = interval(Duration::from_secs(60));
         loop {
             fill_from_db(&pool).await.unwrap_or(());
+            if let Err(e) = fill_api_keys_from_db(&pool).await {
+                error!("Encountered error {e}");
+            }
             i.tick().await;
         }
     }
 
-    let pool = PgPool::new(&config.database_url)?;
-    let app = AppState {
-        api: Arc::new(WeatherApi::new(
-            &config.api_key,
-            &config.api_endpoint,
-            &config.api_path,
-            &config.geo_path,
-        )),
-        config: config.clone(),
-        pool: pool.clone(),
-    };
+    set_log_locations(config);
+    init_upstream_queue(config);
+    let pool = PgPool::from_config(config)?;
+    let app = AppState::new(config, &pool);
     let mut record_task = None;
+    let mut lightning_task = None;
+    let mut uv_index_task = None;
+    let mut alert_task = None;
+    let mut render_stats_task = None;
     let mut db_task = None;
+    let mut publish_task = None;
+    let mut retention_task = None;
+    #[cfg(feature = "grpc")]
+    let mut grpc_task = None;
+    #[cfg(unix)]
+    let mut hangup_task = None;
 
-    TRIGGER_DB_UPDATE.set();
-    db_task.replace(spawn(update_db(pool.clone())));
-
-    let locations = app.config.locations_to_record.clone();
-    if !locations.is_empty() {
-        async fn update_db(app: AppState, locations: Vec<WeatherLocation>) {
-            let mut i = interval(Duration::from_secs(300));
+    // without a database nothing is recorded and nobody can log in
+    if pool.is_enabled() {
+        TRIGGER_DB_UPDATE.set();
+        db_task.replace(spawn(update_db(pool.clone())));
+        match RecordedLocationDB::seed(&pool, &config.locations_to_record).await {
+            Ok(0) => {}
+            Ok(seeded) => info!("seeded {seeded} recorded locations from LOCATIONS_TO_RECORD"),
+            Err(e) => error!("Encountered error {e}"),
+        }
+    } else {
+        info!("no database configured, running as a stateless proxy");
+    }
+    let locations: Vec<_> = get_recorded_locations(&app)
+        .await
+        .into_iter()
+        .map(|r| r.location)
+        .collect();
+    if pool.is_enabled() {
+        // the set of locations is reloaded so locations added or removed
+        // through `/weather/record-locations` apply without a restart
+        async fn update_db(app: AppState) {
+            let mut rng = StdRng::from_entropy();
+            let recorded = get_recorded_locations(&app).await;
+            let mut schedule =
+                RecordSchedule::new(&app.config, &recorded, Instant::now(), &mut rng);
+            let mut reload = Instant::now() + RECORDED_LOCATIONS_RELOAD;
             loop {
-                for loc in &locations {
+                if schedule.peek().is_some_and(|due| due < reload) {
+                    if let Some((due, loc)) = schedule.next_due(&mut rng) {
+                        sleep_until(due).await;
+                        info!("check {loc}");
+                        if let Err(e) = app.weather.get_weather(&app.api, &loc).await {
+                            error!("Encountered error {e}");
+                        }
+                    }
+                } else {
+                    sleep_until(reload).await;
+                    reload += RECORDED_LOCATIONS_RELOAD;
+                    let recorded = get_recorded_locations(&app).await;
+                    schedule.update(&app.config, &recorded, Instant::now(), &mut rng);
+                }
+            }
+        }
+        async fn adaptive_update_db(app: AppState) {
+            let mut readings: HashMap<StackString, PressureReadings> = HashMap::new();
+            loop {
+                let mut active = false;
+                let locations = get_recorded_locations(&app).await;
+                for RecordedLocation { location: loc, .. } in &locations {
                     info!("check {loc}");
-                    if let Err(e) = get_weather_data(&app.pool, &app.config, &app.api, loc).await {
+                    match fetch_weather_data_prime_cache(&app.pool, &app.config, &app.api, loc)
+                        .await
+                    {
+                        Ok(weather) => {
+                            let tendency = readings
+                                .entry(format_sstr!("{loc}"))
+                                .or_default()
+                                .push(weather.dt, weather.main.pressure.kpa());
+                            if is_active_weather(
+                                &weather,
+                                tendency,
+                                app.config.pressure_change_threshold,
+                            ) {
+                                active = true;
+                            }
+                        }
+                        Err(e) => error!("Encountered error {e}"),
+                    }
+                }
+                let period = get_polling_interval(&app.config, active, locations.len());
+                debug!("active {active} next poll in {period:?}");
+                sleep(period).await;
+            }
+        }
+        let app = app.clone();
+        if app.config.adaptive_polling {
+            record_task.replace(spawn(adaptive_update_db(app)));
+        } else {
+            record_task.replace(spawn(update_db(app)));
+        }
+    }
+    if let Some(url) = app.config.lightning_url.clone() {
+        if !locations.is_empty() {
+            async fn update_lightning(
+                app: AppState,
+                url: StackString,
+                locations: Vec<WeatherLocation>,
+            ) {
+                let mut i = interval(Duration::from_secs(300));
+                let mut since = OffsetDateTime::now_utc() - time::Duration::minutes(5);
+                loop {
+                    i.tick().await;
+                    let now = OffsetDateTime::now_utc();
+                    if let Err(e) = record_lightning_activity(
+                        &app.pool,
+                        &app.config,
+                        &app.client,
+                        &url,
+                        &locations,
+                        since,
+                    )
+                    .await
+                    {
                         error!("Encountered error {e}");
                     }
+                    since = now;
                 }
+            }
+            lightning_task.replace(spawn(update_lightning(app.clone(), url, locations)));
+        }
+    }
+    if app.config.record_uv_index && !locations.is_empty() {
+        async fn update_uv_index(app: AppState, locations: Vec<WeatherLocation>) {
+            let mut i = interval(Duration::from_secs(3600));
+            loop {
                 i.tick().await;
+                if let Err(e) =
+                    record_uv_index(&app.pool, &app.config, &app.client, &locations).await
+                {
+                    error!("Encountered error {e}");
+                }
+            }
+        }
+        uv_index_task.replace(spawn(update_uv_index(app.clone(), locations.clone())));
+    }
+
+    async fn check_alerts(app: AppState) {
+        let mut i = interval(Duration::from_secs(300));
+        loop {
+            i.tick().await;
+            if let Err(e) = check_alert_rules(&app).await {
+                error!("Encountered error {e}");
             }
         }
+    }
+    if pool.is_enabled() {
+        alert_task.replace(spawn(check_alerts(app.clone())));
+    }
+
+    async fn persist_render_stats(app: AppState) {
+        if let Err(e) = load_render_statistics(&app.pool, &app.config).await {
+            error!("Encountered error {e}");
+        }
+        let mut i = interval(Duration::from_secs(300));
+        loop {
+            i.tick().await;
+            if let Err(e) = persist_render_statistics(&app.pool, &app.config).await {
+                error!("Encountered error {e}");
+            }
+        }
+    }
+    if pool.is_enabled() {
+        render_stats_task.replace(spawn(persist_render_stats(app.clone())));
+    }
+
+    async fn publish(app: AppState, target: PublishTarget) {
+        let mut i = interval(Duration::from_secs(app.config.publish_interval.max(60)));
+        loop {
+            i.tick().await;
+            match publish_snapshots(&app.pool, &app.config, &target).await {
+                Ok(summary) => info!("{summary}"),
+                Err(e) => error!("Encountered error {e}"),
+            }
+        }
+    }
+    if let Some(destination) = &app.config.publish_destination {
+        if pool.is_enabled() && !app.config.publish_locations.is_empty() {
+            let target = PublishTarget::parse(destination)?;
+            publish_task.replace(spawn(publish(app.clone(), target)));
+        }
+    }
+
+    async fn expire_cache_entries(app: AppState) {
+        let mut i = interval(Duration::from_secs(app.config.maintenance_interval.max(60)));
+        loop {
+            i.tick().await;
+            match expire_caches(&app.pool, &app.config).await {
+                Ok(report) => info!("{report}"),
+                Err(e) => error!("Encountered error {e}"),
+            }
+        }
+    }
+    if pool.is_enabled() && app.config.maintenance_interval > 0 {
+        retention_task.replace(spawn(expire_cache_entries(app.clone())));
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_port) = app.config.grpc_port {
         let app = app.clone();
-        record_task.replace(spawn(update_db(app, locations)));
+        grpc_task.replace(spawn(async move {
+            if let Err(e) = run_grpc_server(app, grpc_port).await {
+                error!("Encountered error {e}");
+            }
+        }));
+    }
+    #[cfg(not(feature = "grpc"))]
+    if app.config.grpc_port.is_some() {
+        warn!("GRPC_PORT is set but the grpc feature is not enabled");
+    }
+    #[cfg(not(feature = "geoip"))]
+    if app.config.geoip_database.is_some() {
+        warn!("GEOIP_DATABASE is set but the geoip feature is not enabled");
     }
 
-    let (spec, api_path) = openapi::spec()
-        .info(Info {
-            title: "Weather App".into(),
-            description: "Web App to disply weather from openweatherapi".into(),
-            version: env!("CARGO_PKG_VERSION").into(),
-            ..Info::default()
-        })
+    // SIGHUP applies RUST_LOG of the config file without a restart
+    #[cfg(unix)]
+    {
+        async fn reload_logging_on_hangup() -> Result<(), Error> {
+            let mut hangup = signal(SignalKind::hangup())?;
+            let config_file = Config::get_config_file(None);
+            while hangup.recv().await.is_some() {
+                if let Err(e) = reload_log_filter(&config_file) {
+                    error!("Encountered error {e}");
+                }
+            }
+            Ok(())
+        }
+        hangup_task.replace(spawn(reload_logging_on_hangup()));
+    }
+
+    let (mut spec, api_path) = openapi::spec()
+        .info(get_api_info())
         .build(|| get_api_path(&app));
+    let rate_limiter = RateLimiter::new(config).with_routes(spec.paths.keys().map(AsRef::as_ref));
+    let api_path = rate_limiter
+        .filter()
+        .and(api_path)
+        .map({
+            let config = config.clone();
+            move |reply| {
+                let attribution = Attribution::header_value(&config, OffsetDateTime::now_utc());
+                reply::with_header(reply, ATTRIBUTION_HEADER, attribution.as_str())
+            }
+        })
+        .and(rweb::header::optional::<StackString>("accept"))
+        .then(negotiate_format)
+        .and(rweb::filters::path::full())
+        .and(rweb::filters::method::method())
+        .and(rweb::header::optional::<StackString>("if-none-match"))
+        .then(
+            |response, path: FullPath, method: Method, etag: Option<StackString>| async move {
+                conditional_response(response, path.as_str(), &method, etag.as_deref()).await
+            },
+        );
+    document_binary_formats(&mut spec);
     let spec = Arc::new(spec);
     let spec_json_path = rweb::path!("weather" / "openapi" / "json")
         .and(rweb::path::end())
@@ -203,6 +854,27 @@ async fn run_app(config: &Config, port: u32) -> Result<(), Error> {
             let spec = spec.clone();
             move || reply::json(spec.as_ref())
         });
+    // the baselines don't change while running, so every diff is computed
+    // once
+    let spec_value = serde_json::to_value(spec.as_ref())?;
+    let compat: HashMap<&str, OpenApiDiff> = OPENAPI_BASELINES
+        .iter()
+        .map(|(name, _)| Ok((*name, OpenApiDiff::new(&get_baseline(name)?, &spec_value))))
+        .collect::<Result<_, Error>>()?;
+    let spec_compat_path = rweb::path!("weather" / "openapi" / "compat")
+        .and(rweb::path::end())
+        .and(rweb::filters::query::query::<OpenApiCompatRequest>())
+        .and_then(move |query: OpenApiCompatRequest| {
+            let against = query.against.unwrap_or_else(|| latest_baseline().into());
+            let diff = compat.get(against.as_str()).cloned();
+            async move {
+                diff.map(|diff| reply::json(&diff)).ok_or_else(|| {
+                    rweb::reject::custom(ServiceError::NotFound(format_sstr!(
+                        "no openapi baseline {against}"
+                    )))
+                })
+            }
+        });
     let spec_yaml = serde_yml::to_string(spec.as_ref())?;
     let spec_yaml_path = rweb::path!("weather" / "openapi" / "yaml")
         .and(rweb::path::end())
@@ -220,10 +892,22 @@ async fn run_app(config: &Config, port: u32) -> Result<(), Error> {
     let routes = api_path
         .or(spec_json_path)
         .or(spec_yaml_path)
+        .or(spec_compat_path)
+        .or(weather_stream())
+        .or(weather_ws())
         .recover(error_response)
-        .with(cors);
+        .with(cors)
+        .with(rweb::filters::log::custom(record_request))
+        .with(rweb::filters::trace::trace({
+            let anonymize_ips = config.anonymize_ips;
+            move |info| request_span(&info, anonymize_ips)
+        }));
     let host = &config.host;
     let addr: SocketAddr = format_sstr!("{host}:{port}").parse()?;
+    if config.startup_banner {
+        let links = get_landing_links(config.wasm_app_url.as_deref());
+        info!("{}", get_startup_banner(&addr, config.landing_page, &links));
+    }
     rweb::serve(routes).bind(addr).await;
 
     Ok(())
@@ -233,14 +917,165 @@ async fn run_app(config: &Config, port: u32) -> Result<(), Error> {
 mod test {
     use anyhow::Error;
     use log::info;
+    use rand::{rngs::StdRng, SeedableRng};
     use stack_string::format_sstr;
-    use std::convert::TryInto;
-    use time::UtcOffset;
+    use std::{collections::HashMap, convert::TryInto, time::Duration};
+    use time::{macros::datetime, UtcOffset};
     use time_tz::{timezones::db::us::CENTRAL, Offset, TimeZone};
+    use tokio::time::Instant;
 
+    use weather_api_types::get_parameters;
     use weather_util_rust::{weather_data::WeatherData, weather_forecast::WeatherForecast};
 
-    use crate::{app::run_app, config::Config, routes::StatisticsObject};
+    use crate::{
+        app::{get_polling_interval, is_active_weather, run_app, PressureReadings, RecordSchedule},
+        config::{Config, ConfigInner, RecordedLocation},
+        model::WeatherDataDB,
+        routes::admin::StatisticsObject,
+    };
+
+    #[test]
+    fn test_adaptive_polling() {
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let calm: WeatherData = WeatherDataDB::test_row(now).into();
+        assert!(!is_active_weather(&calm, None, 0.1));
+        assert!(!is_active_weather(&calm, Some(-0.05), 0.1));
+        assert!(is_active_weather(&calm, Some(-0.2), 0.1));
+        assert!(is_active_weather(&calm, Some(0.2), 0.1));
+        let rain: WeatherData = WeatherDataDB {
+            rain: Some(1.0),
+            ..WeatherDataDB::test_row(now)
+        }
+        .into();
+        assert!(is_active_weather(&rain, None, 0.1));
+
+        // 0.1 kPa reading ticks between five minute polls stay calm
+        let mut readings = PressureReadings::default();
+        for i in 0..72 {
+            let pressure = if i % 2 == 0 { 101.3 } else { 101.2 };
+            let tendency = readings.push(now + time::Duration::minutes(i * 5), pressure);
+            assert!(!is_active_weather(&calm, tendency, 0.1));
+        }
+        // a steady fall of 0.24 kPa/h
+        let mut readings = PressureReadings::default();
+        let tendencies: Vec<_> = (0..=36)
+            .map(|i| {
+                let dt = now + time::Duration::minutes(i * 5);
+                readings.push(dt, 101.3 - 0.02 * i as f64)
+            })
+            .collect();
+        assert_eq!(tendencies[0], None);
+        assert!(is_active_weather(&calm, tendencies[36], 0.1));
+
+        let config = ConfigInner {
+            polling_interval_min: 60,
+            polling_interval_max: 900,
+            ..ConfigInner::default()
+        };
+        assert_eq!(
+            get_polling_interval(&config, true, 4),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            get_polling_interval(&config, false, 4),
+            Duration::from_secs(900)
+        );
+        // 1000 calls a day shared by 4 locations allow a poll every 345.6s
+        let config = ConfigInner {
+            api_calls_per_day: Some(1000),
+            ..config
+        };
+        assert_eq!(
+            get_polling_interval(&config, true, 4),
+            Duration::from_secs(346)
+        );
+        assert_eq!(
+            get_polling_interval(&config, false, 4),
+            Duration::from_secs(900)
+        );
+        assert_eq!(
+            get_polling_interval(&config, true, 1),
+            Duration::from_secs(87)
+        );
+        let config = ConfigInner {
+            api_calls_per_day: Some(0),
+            ..config
+        };
+        assert_eq!(
+            get_polling_interval(&config, false, 4),
+            Duration::from_secs(4 * 86400)
+        );
+    }
+
+    #[test]
+    fn test_record_schedule() {
+        let config = Config::default();
+        let recorded = [
+            RecordedLocation {
+                name: "10001".into(),
+                location: get_parameters("10001"),
+                interval: Some(300),
+            },
+            RecordedLocation {
+                name: "11106".into(),
+                location: get_parameters("11106"),
+                interval: Some(300),
+            },
+            RecordedLocation {
+                name: "Paris".into(),
+                location: get_parameters("Paris"),
+                interval: Some(1800),
+            },
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        let start = Instant::now();
+        let mut schedule = RecordSchedule::new(&config, &recorded, start, &mut rng);
+
+        let mut polls = HashMap::new();
+        let mut previous = start;
+        let mut first_polls = Vec::new();
+        while let Some((due, loc)) = schedule.next_due(&mut rng) {
+            if due > start + Duration::from_secs(3600) {
+                break;
+            }
+            assert!(due >= previous);
+            previous = due;
+            if first_polls.len() < 3 {
+                first_polls.push(due);
+            }
+            *polls.entry(format_sstr!("{loc}")).or_insert(0) += 1;
+        }
+        // 300s plus up to 10% jitter
+        for name in ["10001", "11106"] {
+            let count = polls[&format_sstr!("{}", get_parameters(name))];
+            assert!((11..=12).contains(&count), "{name} {count}");
+        }
+        assert_eq!(polls[&format_sstr!("{}", get_parameters("Paris"))], 2);
+        // the first polls are jittered rather than simultaneous
+        assert!(first_polls.windows(2).all(|w| w[0] != w[1]));
+        assert!(first_polls
+            .iter()
+            .all(|due| *due <= start + Duration::from_secs(180)));
+
+        // unchanged locations keep their next poll, changed or new ones are
+        // rescheduled and removed ones dropped
+        let now = start + Duration::from_secs(3600);
+        let kept = schedule.entries[0].clone();
+        let mut updated = recorded[..2].to_vec();
+        updated[1].interval = Some(600);
+        updated.push(RecordedLocation {
+            name: "Berlin".into(),
+            location: get_parameters("Berlin"),
+            interval: None,
+        });
+        schedule.update(&config, &updated, now, &mut rng);
+        assert_eq!(schedule.entries.len(), 3);
+        assert_eq!(schedule.entries[0], kept);
+        assert_eq!(schedule.entries[1].1, Duration::from_secs(600));
+        assert!(schedule.entries[1].0 <= now + Duration::from_secs(60));
+        assert_eq!(schedule.entries[2].2, get_parameters("Berlin"));
+        assert!(schedule.peek().is_some());
+    }
 
     #[tokio::test]
     async fn test_run_app() -> Result<(), Error> {
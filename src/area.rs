@@ -0,0 +1,221 @@
+use anyhow::{format_err, Error};
+use postgres_query::Parameter;
+use stack_string::{format_sstr, StackString};
+
+use crate::lightning::haversine_distance;
+
+/// Great circle distance in km from `($area_lat, $area_lon)`, same formula as
+/// `haversine_distance`
+const DISTANCE_SQL: &str = "2 * 6371.0 * asin(sqrt(
+    power(sin(radians(latitude - $area_lat) / 2), 2)
+    + cos(radians($area_lat)) * cos(radians(latitude))
+    * power(sin(radians(longitude - $area_lon) / 2), 2)
+))";
+
+/// Observations within a bounding box or within `radius_km` of a point,
+/// regardless of the recorded location name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AreaFilter {
+    /// `min_lon > max_lon` wraps across the antimeridian
+    BoundingBox {
+        min_lat: f64,
+        max_lat: f64,
+        min_lon: f64,
+        max_lon: f64,
+    },
+    Radius {
+        lat: f64,
+        lon: f64,
+        radius_km: f64,
+    },
+}
+
+fn check_lat(lat: f64) -> Result<f64, Error> {
+    if (-90.0..=90.0).contains(&lat) {
+        Ok(lat)
+    } else {
+        Err(format_err!("Invalid latitude {lat}"))
+    }
+}
+
+fn check_lon(lon: f64) -> Result<f64, Error> {
+    if (-180.0..=180.0).contains(&lon) {
+        Ok(lon)
+    } else {
+        Err(format_err!("Invalid longitude {lon}"))
+    }
+}
+
+impl AreaFilter {
+    /// # Errors
+    /// Return error if a coordinate is out of range or `min_lat > max_lat`
+    pub fn bounding_box(
+        min_lat: f64,
+        max_lat: f64,
+        min_lon: f64,
+        max_lon: f64,
+    ) -> Result<Self, Error> {
+        if check_lat(min_lat)? > check_lat(max_lat)? {
+            return Err(format_err!("min_lat is larger than max_lat"));
+        }
+        Ok(Self::BoundingBox {
+            min_lat,
+            max_lat,
+            min_lon: check_lon(min_lon)?,
+            max_lon: check_lon(max_lon)?,
+        })
+    }
+
+    /// # Errors
+    /// Return error if a coordinate is out of range or the radius isn't
+    /// positive
+    pub fn radius(lat: f64, lon: f64, radius_km: f64) -> Result<Self, Error> {
+        if radius_km.is_nan() || radius_km <= 0.0 {
+            return Err(format_err!("Invalid radius {radius_km}"));
+        }
+        Ok(Self::Radius {
+            lat: check_lat(lat)?,
+            lon: check_lon(lon)?,
+            radius_km,
+        })
+    }
+
+    /// Filter of the `min_lat/max_lat/min_lon/max_lon` or `lat/lon/radius_km`
+    /// query parameters, `None` when none of them is set
+    ///
+    /// # Errors
+    /// Return error if only some of a set or both sets are given
+    pub fn from_params(
+        bounding_box: [Option<f64>; 4],
+        radius: [Option<f64>; 3],
+    ) -> Result<Option<Self>, Error> {
+        match (bounding_box, radius) {
+            ([None, None, None, None], [None, None, None]) => Ok(None),
+            ([Some(min_lat), Some(max_lat), Some(min_lon), Some(max_lon)], [None, None, None]) => {
+                Self::bounding_box(min_lat, max_lat, min_lon, max_lon).map(Some)
+            }
+            ([None, None, None, None], [Some(lat), Some(lon), Some(radius_km)]) => {
+                Self::radius(lat, lon, radius_km).map(Some)
+            }
+            ([None, None, None, None], _) => {
+                Err(format_err!("lat, lon and radius_km are all required"))
+            }
+            (_, [None, None, None]) => Err(format_err!(
+                "min_lat, max_lat, min_lon and max_lon are all required"
+            )),
+            _ => Err(format_err!("Use either a bounding box or a radius")),
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        match *self {
+            Self::BoundingBox {
+                min_lat,
+                max_lat,
+                min_lon,
+                max_lon,
+            } => {
+                let lon_matches = if min_lon <= max_lon {
+                    (min_lon..=max_lon).contains(&longitude)
+                } else {
+                    longitude >= min_lon || longitude <= max_lon
+                };
+                (min_lat..=max_lat).contains(&latitude) && lon_matches
+            }
+            Self::Radius {
+                lat,
+                lon,
+                radius_km,
+            } => haversine_distance(lat, lon, latitude, longitude) <= radius_km,
+        }
+    }
+
+    /// Constraint on the `latitude` / `longitude` columns, the parameters are
+    /// named `area_*`
+    #[must_use]
+    pub fn constraint(&self) -> StackString {
+        match self {
+            Self::BoundingBox {
+                min_lon, max_lon, ..
+            } => {
+                let lon_op = if min_lon <= max_lon { "AND" } else { "OR" };
+                format_sstr!(
+                    "latitude BETWEEN $area_min_lat AND $area_max_lat AND (longitude >= \
+                     $area_min_lon {lon_op} longitude <= $area_max_lon)"
+                )
+            }
+            Self::Radius { .. } => format_sstr!("{DISTANCE_SQL} <= $area_radius_km"),
+        }
+    }
+
+    #[must_use]
+    pub fn bindings(&self) -> Vec<(&'static str, Parameter<'_>)> {
+        match self {
+            Self::BoundingBox {
+                min_lat,
+                max_lat,
+                min_lon,
+                max_lon,
+            } => vec![
+                ("area_min_lat", min_lat as Parameter),
+                ("area_max_lat", max_lat as Parameter),
+                ("area_min_lon", min_lon as Parameter),
+                ("area_max_lon", max_lon as Parameter),
+            ],
+            Self::Radius {
+                lat,
+                lon,
+                radius_km,
+            } => vec![
+                ("area_lat", lat as Parameter),
+                ("area_lon", lon as Parameter),
+                ("area_radius_km", radius_km as Parameter),
+            ],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::area::AreaFilter;
+
+    #[test]
+    fn test_area_filter() -> Result<(), Error> {
+        let area = AreaFilter::from_params(
+            [Some(40.0), Some(41.0), Some(-75.0), Some(-73.0)],
+            [None; 3],
+        )?
+        .unwrap();
+        assert!(area.contains(40.7, -74.0));
+        assert!(!area.contains(42.0, -74.0));
+        assert!(area.constraint().contains("AND longitude"));
+
+        let antimeridian = AreaFilter::bounding_box(-20.0, -10.0, 170.0, -170.0)?;
+        assert!(antimeridian.contains(-15.0, 179.0));
+        assert!(antimeridian.contains(-15.0, -175.0));
+        assert!(!antimeridian.contains(-15.0, 0.0));
+        assert!(antimeridian.constraint().contains("OR longitude"));
+
+        let area =
+            AreaFilter::from_params([None; 4], [Some(40.7), Some(-74.0), Some(10.0)])?.unwrap();
+        assert!(area.contains(40.75, -74.05));
+        assert!(!area.contains(40.9, -74.0));
+        assert_eq!(area.bindings().len(), 3);
+
+        assert_eq!(AreaFilter::from_params([None; 4], [None; 3])?, None);
+        assert!(AreaFilter::from_params([Some(40.0), None, None, None], [None; 3]).is_err());
+        assert!(AreaFilter::from_params([None; 4], [Some(40.7), Some(-74.0), None]).is_err());
+        assert!(AreaFilter::from_params(
+            [Some(40.0), Some(41.0), Some(-75.0), Some(-73.0)],
+            [Some(40.7), Some(-74.0), Some(10.0)]
+        )
+        .is_err());
+        assert!(AreaFilter::bounding_box(41.0, 40.0, -75.0, -73.0).is_err());
+        assert!(AreaFilter::radius(91.0, 0.0, 10.0).is_err());
+        assert!(AreaFilter::radius(40.0, 0.0, 0.0).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use std::f64::consts::PI;
+use time::{macros::format_description, Date, Duration, OffsetDateTime, UtcOffset};
+
+use weather_api_types::dto::{DaylightSummary, MoonSummary};
+
+use crate::providers::{get_sun_crossings, SunCrossings, SUNRISE_ALTITUDE};
+
+/// Length of a lunation (days)
+const SYNODIC_MONTH: f64 = 29.530_588_853;
+/// Julian day of the new moon of 2000-01-06 18:14 UTC
+const REFERENCE_NEW_MOON: f64 = 2_451_550.26;
+/// Altitude of the moon's center at rise and set, parallax less refraction
+/// and semi-diameter (degrees)
+const MOONRISE_ALTITUDE: f64 = 0.125;
+/// Step of the moonrise / moonset search (seconds)
+const SEARCH_STEP: i64 = 600;
+/// Altitude of the sun's center at civil dawn and dusk (degrees)
+const CIVIL_TWILIGHT_ALTITUDE: f64 = -6.0;
+
+fn julian_day(t: OffsetDateTime) -> f64 {
+    t.unix_timestamp() as f64 / 86400.0 + 2_440_587.5
+}
+
+/// Phase of the moon, computed from the mean lunation so within about half a
+/// day of the true phase
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoonPhase {
+    /// days since the last new moon
+    pub age: f64,
+    /// fraction of the lunation, 0 new moon, 0.5 full moon
+    pub phase: f64,
+    /// illuminated fraction of the disc
+    pub illumination: f64,
+}
+
+impl MoonPhase {
+    #[must_use]
+    pub fn new(t: OffsetDateTime) -> Self {
+        let age = (julian_day(t) - REFERENCE_NEW_MOON).rem_euclid(SYNODIC_MONTH);
+        let phase = age / SYNODIC_MONTH;
+        let illumination = (1.0 - (2.0 * PI * phase).cos()) / 2.0;
+        Self {
+            age,
+            phase,
+            illumination,
+        }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        const NAMES: [&str; 8] = [
+            "New Moon",
+            "Waxing Crescent",
+            "First Quarter",
+            "Waxing Gibbous",
+            "Full Moon",
+            "Waning Gibbous",
+            "Last Quarter",
+            "Waning Crescent",
+        ];
+        NAMES[((self.phase * 8.0).round() as usize) % 8]
+    }
+}
+
+/// Altitude of the moon (degrees) from the low precision lunar position
+fn moon_altitude(t: OffsetDateTime, latitude: f64, longitude: f64) -> f64 {
+    let d = julian_day(t) - 2_451_545.0;
+    let mean_longitude = (218.316 + 13.176_396 * d).to_radians();
+    let mean_anomaly = (134.963 + 13.064_993 * d).to_radians();
+    let mean_distance = (93.272 + 13.229_350 * d).to_radians();
+    let l = mean_longitude + 6.289_f64.to_radians() * mean_anomaly.sin();
+    let b = 5.128_f64.to_radians() * mean_distance.sin();
+    let e = 23.4397_f64.to_radians();
+    let right_ascension = (l.sin() * e.cos() - b.tan() * e.sin()).atan2(l.cos());
+    let declination = (b.sin() * e.cos() + b.cos() * e.sin() * l.sin()).asin();
+    let sidereal_time = (280.16 + 360.985_623_5 * d + longitude).to_radians();
+    let hour_angle = sidereal_time - right_ascension;
+    let phi = latitude.to_radians();
+    (phi.sin() * declination.sin() + phi.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+/// Moonrise and moonset during the local day `date`, either is `None` on the
+/// days the moon doesn't rise or set
+#[must_use]
+pub fn get_moonrise_moonset(
+    date: Date,
+    offset: UtcOffset,
+    latitude: f64,
+    longitude: f64,
+) -> (Option<OffsetDateTime>, Option<OffsetDateTime>) {
+    let start = date
+        .midnight()
+        .assume_offset(offset)
+        .to_offset(UtcOffset::UTC);
+    let step = Duration::seconds(SEARCH_STEP);
+    let height = |t| moon_altitude(t, latitude, longitude) - MOONRISE_ALTITUDE;
+    let mut moonrise = None;
+    let mut moonset = None;
+    let mut previous = height(start);
+    for i in 1..=(86400 / SEARCH_STEP) {
+        let t = start + step * i as i32;
+        let current = height(t);
+        // interpolate the crossing within the step
+        if previous < 0.0 && current >= 0.0 && moonrise.is_none() {
+            moonrise = Some(t - step * (current / (current - previous)));
+        } else if previous >= 0.0 && current < 0.0 && moonset.is_none() {
+            moonset = Some(t - step * (current / (current - previous)));
+        }
+        previous = current;
+    }
+    (moonrise, moonset)
+}
+
+/// Moon phase at `t` with the moonrise / moonset of its local day
+#[must_use]
+pub fn get_moon_summary(
+    t: OffsetDateTime,
+    offset: UtcOffset,
+    latitude: f64,
+    longitude: f64,
+) -> MoonSummary {
+    let format = format_description!("[hour]:[minute]");
+    let phase = MoonPhase::new(t);
+    let (moonrise, moonset) =
+        get_moonrise_moonset(t.to_offset(offset).date(), offset, latitude, longitude);
+    let local_time = |t: OffsetDateTime| t.to_offset(offset).format(format).ok();
+    MoonSummary {
+        phase: phase.phase,
+        phase_name: phase.name().into(),
+        illumination: phase.illumination * 100.0,
+        moonrise: moonrise.and_then(local_time),
+        moonset: moonset.and_then(local_time),
+    }
+}
+
+/// Sunrise / sunset, civil twilight and daylight duration of the local day
+/// `date`
+#[must_use]
+pub fn get_daylight_summary(
+    date: Date,
+    offset: UtcOffset,
+    latitude: f64,
+    longitude: f64,
+) -> DaylightSummary {
+    let format = format_description!("[hour]:[minute]");
+    let local_time = |t: OffsetDateTime| t.to_offset(offset).format(format).ok();
+    let (sunrise, sunset, daylight) =
+        match get_sun_crossings(date, latitude, longitude, SUNRISE_ALTITUDE) {
+            SunCrossings::RiseSet(rise, set) => (
+                local_time(rise),
+                local_time(set),
+                (set - rise).whole_seconds(),
+            ),
+            SunCrossings::AlwaysAbove => (None, None, 86400),
+            SunCrossings::AlwaysBelow => (None, None, 0),
+        };
+    let (civil_dawn, civil_dusk) =
+        match get_sun_crossings(date, latitude, longitude, CIVIL_TWILIGHT_ALTITUDE) {
+            SunCrossings::RiseSet(dawn, dusk) => (local_time(dawn), local_time(dusk)),
+            SunCrossings::AlwaysAbove | SunCrossings::AlwaysBelow => (None, None),
+        };
+    DaylightSummary {
+        sunrise,
+        sunset,
+        civil_dawn,
+        civil_dusk,
+        daylight,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{
+        macros::{date, datetime, offset},
+        UtcOffset,
+    };
+
+    use crate::astronomy::{
+        get_daylight_summary, get_moon_summary, get_moonrise_moonset, MoonPhase,
+    };
+
+    #[test]
+    fn test_moon_phase() {
+        let full = MoonPhase::new(datetime!(2024-01-25 17:54 UTC));
+        assert!(full.illumination > 0.99);
+        assert_eq!(full.name(), "Full Moon");
+
+        let new = MoonPhase::new(datetime!(2024-01-11 11:57 UTC));
+        assert!(new.illumination < 0.01);
+        assert_eq!(new.name(), "New Moon");
+
+        let quarter = MoonPhase::new(datetime!(2024-06-14 05:18 UTC));
+        assert!((quarter.illumination - 0.5).abs() < 0.05);
+        assert_eq!(quarter.name(), "First Quarter");
+    }
+
+    #[test]
+    fn test_get_moonrise_moonset() {
+        // full moon rising around sunset in new york
+        let (moonrise, moonset) =
+            get_moonrise_moonset(date!(2024 - 01 - 25), offset!(-5), 40.71, -74.0);
+        let moonrise = moonrise.unwrap().to_offset(UtcOffset::UTC);
+        let moonset = moonset.unwrap().to_offset(UtcOffset::UTC);
+        assert!(moonrise > datetime!(2024-01-25 21:30 UTC));
+        assert!(moonrise < datetime!(2024-01-25 22:30 UTC));
+        assert!(moonset > datetime!(2024-01-25 12:00 UTC));
+        assert!(moonset < datetime!(2024-01-25 13:15 UTC));
+
+        let summary = get_moon_summary(datetime!(2024-06-14 16:00 UTC), offset!(-4), 40.71, -74.0);
+        assert_eq!(summary.phase_name, "First Quarter");
+        assert_eq!(summary.moonrise.as_deref(), Some("13:20"));
+        assert_eq!(summary.moonset.as_deref(), Some("01:18"));
+    }
+
+    #[test]
+    fn test_get_daylight_summary() {
+        // Minneapolis summer solstice, sunrise 5:26 CDT, sunset 21:03 CDT
+        let summary = get_daylight_summary(date!(2024 - 06 - 21), offset!(-5), 44.98, -93.27);
+        let sunrise = summary.sunrise.unwrap();
+        let sunset = summary.sunset.unwrap();
+        assert!(sunrise.as_str() >= "05:21" && sunrise.as_str() <= "05:31");
+        assert!(sunset.as_str() >= "20:58" && sunset.as_str() <= "21:08");
+        assert!(summary.civil_dawn.unwrap() < sunrise);
+        assert!(summary.civil_dusk.unwrap() > sunset);
+        assert!((summary.daylight - (15 * 3600 + 37 * 60)).abs() <= 600);
+
+        // polar night in svalbard, the sun stays below the civil twilight
+        // altitude as well
+        let summary = get_daylight_summary(date!(2024 - 12 - 21), offset!(+1), 78.22, 15.65);
+        assert_eq!(summary.sunrise, None);
+        assert_eq!(summary.civil_dawn, None);
+        assert_eq!(summary.daylight, 0);
+
+        // midnight sun
+        let summary = get_daylight_summary(date!(2024 - 06 - 21), offset!(+2), 78.22, 15.65);
+        assert_eq!(summary.sunset, None);
+        assert_eq!(summary.daylight, 86400);
+    }
+}
@@ -0,0 +1,75 @@
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use time::OffsetDateTime;
+
+use rweb_helper::DateTimeType;
+
+use crate::{config::Config, date_time_wrapper::DateTimeWrapper};
+
+/// Header attached to every api response
+pub const ATTRIBUTION_HEADER: &str = "x-weather-attribution";
+
+/// Data provider, license and retrieval time, required when re-exposing
+/// provider data
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "Attribution")]
+pub struct Attribution {
+    #[schema(description = "Data Provider")]
+    pub provider: StackString,
+    #[schema(description = "Data License")]
+    pub license: StackString,
+    #[schema(description = "Provider URL")]
+    pub url: StackString,
+    #[schema(description = "Retrieved At")]
+    pub retrieved_at: DateTimeType,
+}
+
+impl Attribution {
+    #[must_use]
+    pub fn new(config: &Config, retrieved_at: OffsetDateTime) -> Self {
+        Self {
+            provider: config.attribution_provider.clone(),
+            license: config.attribution_license.clone(),
+            url: config.attribution_url.clone(),
+            retrieved_at: retrieved_at.into(),
+        }
+    }
+
+    /// Value of the `X-Weather-Attribution` header, e.g.
+    /// `provider="OpenWeatherMap"; license="CC BY-SA 4.0"; url="...";
+    /// retrieved_at="2024-06-01T00:00:00Z"`
+    #[must_use]
+    pub fn header_value(config: &Config, retrieved_at: OffsetDateTime) -> StackString {
+        let retrieved_at = DateTimeWrapper::from(retrieved_at);
+        format_sstr!(
+            r#"provider="{}"; license="{}"; url="{}"; retrieved_at="{retrieved_at}""#,
+            quote_safe(&config.attribution_provider),
+            quote_safe(&config.attribution_license),
+            quote_safe(&config.attribution_url),
+        )
+    }
+}
+
+/// Strip characters that can't appear inside a quoted header parameter
+fn quote_safe(s: &str) -> StackString {
+    s.chars()
+        .filter(|c| c.is_ascii() && !c.is_ascii_control() && *c != '"')
+        .collect::<String>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::attribution::quote_safe;
+
+    #[test]
+    fn test_quote_safe() {
+        assert_eq!(quote_safe("Met.no \"MET\"").as_str(), "Met.no MET");
+        assert_eq!(
+            quote_safe("Deutscher Wetterdienst\n").as_str(),
+            "Deutscher Wetterdienst"
+        );
+        assert_eq!(quote_safe("Météo").as_str(), "Mto");
+    }
+}
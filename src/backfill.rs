@@ -0,0 +1,522 @@
+use anyhow::{format_err, Error};
+use futures::TryStreamExt;
+use log::error;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use stack_string::{format_sstr, StackString};
+use std::{fmt, str::FromStr};
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+use weather_util_rust::weather_data::WeatherData;
+
+use crate::{
+    config::Config,
+    date_time_wrapper::DateTimeWrapper,
+    metrics::record_upstream_call,
+    model::WeatherDataDB,
+    onecall::OneCallCurrent,
+    pgpool::PgPool,
+    providers::{
+        open_meteo::wmo_to_condition, precipitation_json, sun_times, weather_condition_json,
+        KELVIN_OFFSET,
+    },
+};
+
+/// Spacing between recorded samples reported as a gap by default
+pub const DEFAULT_BACKFILL_GAP: Duration = Duration::minutes(30);
+
+const OPEN_METEO_ARCHIVE_URL: &str = "https://archive-api.open-meteo.com/v1/archive";
+const ARCHIVE_VARIABLES: &str = "temperature_2m,relative_humidity_2m,is_day,weather_code,\
+                                 pressure_msl,wind_speed_10m,wind_direction_10m,rain,snowfall";
+
+/// Historical api gaps are filled from, both report hourly observations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackfillSource {
+    /// open-meteo.com archive, no api key required
+    #[default]
+    OpenMeteo,
+    /// openweathermap One Call 3.0 timemachine, one request per hour
+    OpenWeatherMap,
+}
+
+impl BackfillSource {
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::OpenMeteo => "open-meteo",
+            Self::OpenWeatherMap => "openweathermap",
+        }
+    }
+}
+
+impl fmt::Display for BackfillSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+impl FromStr for BackfillSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open-meteo" => Ok(Self::OpenMeteo),
+            "openweathermap" | "owm" => Ok(Self::OpenWeatherMap),
+            _ => Err(format_err!(
+                "Invalid backfill source {s}, expected open-meteo or openweathermap"
+            )),
+        }
+    }
+}
+
+/// Period between two consecutive samples longer than the allowed spacing
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    pub start: DateTimeWrapper,
+    pub end: DateTimeWrapper,
+}
+
+impl Gap {
+    /// Whole hours strictly between the surrounding samples
+    #[must_use]
+    pub fn get_hours(&self) -> Vec<OffsetDateTime> {
+        let start = self.start.unix_timestamp();
+        let end = self.end.unix_timestamp();
+        let first = start.div_euclid(3600) * 3600 + 3600;
+        (first..end)
+            .step_by(3600)
+            .filter_map(|t| OffsetDateTime::from_unix_timestamp(t).ok())
+            .collect()
+    }
+}
+
+/// Gaps longer than `min_gap` between the samples of `rows`
+#[must_use]
+pub fn find_gaps(rows: &[WeatherDataDB], min_gap: Duration) -> Vec<Gap> {
+    let mut times: Vec<_> = rows.iter().map(|row| *row.created_at).collect();
+    times.sort();
+    times.dedup();
+    times
+        .windows(2)
+        .filter(|w| w[1] - w[0] > min_gap)
+        .map(|w| Gap {
+            start: w[0].into(),
+            end: w[1].into(),
+        })
+        .collect()
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenMeteoArchive {
+    utc_offset_seconds: i32,
+    hourly: OpenMeteoArchiveHourly,
+}
+
+/// Variables of the archive are null where the reanalysis isn't available
+/// yet (the last few days)
+#[derive(Deserialize, Debug)]
+struct OpenMeteoArchiveHourly {
+    time: Vec<i64>,
+    /// Celsius
+    temperature_2m: Vec<Option<f64>>,
+    relative_humidity_2m: Vec<Option<f64>>,
+    is_day: Vec<Option<u8>>,
+    weather_code: Vec<Option<u8>>,
+    /// hPa
+    pressure_msl: Vec<Option<f64>>,
+    /// m/s
+    wind_speed_10m: Vec<Option<f64>>,
+    wind_direction_10m: Vec<Option<f64>>,
+    /// mm
+    rain: Vec<Option<f64>>,
+    /// cm
+    snowfall: Vec<Option<f64>>,
+}
+
+/// Observations of the archive at the `hours` of a gap, hours without a
+/// temperature are left out
+fn weather_data_from_archive(
+    archive: &OpenMeteoArchive,
+    latitude: f64,
+    longitude: f64,
+    hours: &[OffsetDateTime],
+) -> Result<Vec<WeatherData>, Error> {
+    let hourly = &archive.hourly;
+    let mut observations = Vec::new();
+    for (idx, dt) in hourly.time.iter().enumerate() {
+        if !hours.iter().any(|h| h.unix_timestamp() == *dt) {
+            continue;
+        }
+        let value = |v: &[Option<f64>]| v.get(idx).copied().flatten();
+        let Some(temperature) = value(&hourly.temperature_2m) else {
+            continue;
+        };
+        let code = hourly.weather_code.get(idx).copied().flatten().unwrap_or(0);
+        let is_day = hourly.is_day.get(idx).copied().flatten().unwrap_or(1);
+        let (id, main, description, icon) = wmo_to_condition(code);
+        let suffix = if is_day == 0 { "n" } else { "d" };
+        let weather =
+            weather_condition_json(id, main, description, &format_sstr!("{icon}{suffix}"));
+        let snowfall = value(&hourly.snowfall).unwrap_or(0.0);
+        let (rain, snow) = if snowfall > 0.0 {
+            precipitation_json(true, "1h", snowfall * 10.0)
+        } else {
+            precipitation_json(false, "1h", value(&hourly.rain).unwrap_or(0.0))
+        };
+        let date = OffsetDateTime::from_unix_timestamp(*dt)?.date();
+        let (sunrise, sunset) = sun_times(date, latitude, longitude);
+        let temp = temperature + KELVIN_OFFSET;
+        let data = json!({
+            "coord": {"lon": longitude, "lat": latitude},
+            "weather": weather,
+            "base": "open-meteo archive",
+            "main": {
+                "temp": temp,
+                "feels_like": temp,
+                "temp_min": temp,
+                "temp_max": temp,
+                "pressure": value(&hourly.pressure_msl).unwrap_or(1013.25),
+                "humidity": value(&hourly.relative_humidity_2m).unwrap_or(0.0).round() as i64,
+            },
+            "wind": {
+                "speed": value(&hourly.wind_speed_10m).unwrap_or(0.0),
+                "deg": value(&hourly.wind_direction_10m),
+            },
+            "rain": rain,
+            "snow": snow,
+            "dt": dt,
+            "sys": {"sunrise": sunrise, "sunset": sunset},
+            "timezone": archive.utc_offset_seconds,
+            "name": "",
+        });
+        observations.push(serde_json::from_value(data)?);
+    }
+    Ok(observations)
+}
+
+async fn fetch_open_meteo_archive(
+    client: &Client,
+    latitude: f64,
+    longitude: f64,
+    start_date: Date,
+    end_date: Date,
+) -> Result<OpenMeteoArchive, Error> {
+    let date_format = format_description!("[year]-[month]-[day]");
+    let result: Result<OpenMeteoArchive, Error> = async {
+        let archive = client
+            .get(OPEN_METEO_ARCHIVE_URL)
+            .query(&[
+                ("latitude", format_sstr!("{latitude:0.4}")),
+                ("longitude", format_sstr!("{longitude:0.4}")),
+                ("start_date", start_date.format(date_format)?.into()),
+                ("end_date", end_date.format(date_format)?.into()),
+                ("hourly", ARCHIVE_VARIABLES.into()),
+                ("wind_speed_unit", "ms".into()),
+                ("timeformat", "unixtime".into()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(archive)
+    }
+    .await;
+    record_upstream_call("open-meteo", "archive", result.is_ok());
+    result
+}
+
+/// Response of the One Call 3.0 timemachine api
+#[derive(Deserialize, Debug)]
+struct TimeMachine {
+    timezone_offset: i32,
+    data: Vec<OneCallCurrent>,
+}
+
+fn weather_data_from_timemachine(
+    current: &OneCallCurrent,
+    timezone_offset: i32,
+    latitude: f64,
+    longitude: f64,
+) -> Result<WeatherData, Error> {
+    let data = json!({
+        "coord": {"lon": longitude, "lat": latitude},
+        "weather": current.weather,
+        "base": "onecall timemachine",
+        "main": {
+            "temp": current.temp,
+            "feels_like": current.feels_like,
+            "temp_min": current.temp,
+            "temp_max": current.temp,
+            "pressure": current.pressure,
+            "humidity": current.humidity,
+        },
+        "visibility": current.visibility,
+        "wind": {"speed": current.wind_speed, "deg": current.wind_deg},
+        "rain": current.rain,
+        "snow": current.snow,
+        "dt": current.dt.unix_timestamp(),
+        "sys": {
+            "sunrise": current.sunrise.unix_timestamp(),
+            "sunset": current.sunset.unix_timestamp(),
+        },
+        "timezone": timezone_offset,
+        "name": "",
+    });
+    serde_json::from_value(data).map_err(Into::into)
+}
+
+async fn fetch_timemachine(
+    client: &Client,
+    config: &Config,
+    latitude: f64,
+    longitude: f64,
+    dt: OffsetDateTime,
+) -> Result<TimeMachine, Error> {
+    let url = format_sstr!(
+        "https://{}/{}onecall/timemachine",
+        config.api_endpoint,
+        config.onecall_path
+    );
+    let result: Result<TimeMachine, Error> = async {
+        let timemachine = client
+            .get(url.as_str())
+            .query(&[
+                ("lat", format_sstr!("{latitude}")),
+                ("lon", format_sstr!("{longitude}")),
+                ("dt", format_sstr!("{}", dt.unix_timestamp())),
+                ("appid", config.api_key.as_str().into()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(timemachine)
+    }
+    .await;
+    record_upstream_call("openweathermap", "timemachine", result.is_ok());
+    result
+}
+
+/// Hourly observations filling `gap` from `source`
+///
+/// # Errors
+/// Return error if the api request fails
+pub async fn fetch_gap(
+    client: &Client,
+    config: &Config,
+    source: BackfillSource,
+    gap: &Gap,
+    latitude: f64,
+    longitude: f64,
+) -> Result<Vec<WeatherData>, Error> {
+    let hours = gap.get_hours();
+    let (Some(first), Some(last)) = (hours.first(), hours.last()) else {
+        return Ok(Vec::new());
+    };
+    match source {
+        BackfillSource::OpenMeteo => {
+            let archive =
+                fetch_open_meteo_archive(client, latitude, longitude, first.date(), last.date())
+                    .await?;
+            weather_data_from_archive(&archive, latitude, longitude, &hours)
+        }
+        BackfillSource::OpenWeatherMap => {
+            let mut observations = Vec::new();
+            for hour in &hours {
+                let timemachine =
+                    fetch_timemachine(client, config, latitude, longitude, *hour).await?;
+                for current in &timemachine.data {
+                    observations.push(weather_data_from_timemachine(
+                        current,
+                        timemachine.timezone_offset,
+                        latitude,
+                        longitude,
+                    )?);
+                }
+            }
+            Ok(observations)
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct BackfillSummary {
+    pub location_name: StackString,
+    pub source: StackString,
+    pub gaps: Vec<Gap>,
+    /// rows inserted, or that would be inserted in a dry run
+    pub inserted: usize,
+    /// gaps whose api request failed
+    pub failed: usize,
+}
+
+impl fmt::Display for BackfillSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} gaps, backfilled {} rows from {}",
+            self.location_name,
+            self.gaps.len(),
+            self.inserted,
+            self.source
+        )?;
+        if self.failed > 0 {
+            write!(f, ", {} gaps failed", self.failed)?;
+        }
+        for gap in &self.gaps {
+            write!(f, "\n{} - {}", gap.start, gap.end)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fill the gaps longer than `min_gap` in the history of `name` recorded by
+/// `server`, inserted rows carry the same location name and server and are
+/// flagged `backfilled`, nothing is written when `dry_run` is set
+///
+/// # Errors
+/// Return error if db query fails
+#[allow(clippy::too_many_arguments)]
+pub async fn backfill_location(
+    pool: &PgPool,
+    config: &Config,
+    client: &Client,
+    name: &str,
+    server: &str,
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+    min_gap: Duration,
+    source: BackfillSource,
+    dry_run: bool,
+) -> Result<BackfillSummary, Error> {
+    let rows: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates(
+        pool,
+        Some(name),
+        Some(server),
+        start_date,
+        end_date,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await?
+    .try_collect()
+    .await?;
+    let gaps = find_gaps(&rows, min_gap);
+    let mut summary = BackfillSummary {
+        location_name: name.into(),
+        source: source.to_str().into(),
+        ..BackfillSummary::default()
+    };
+    for gap in &gaps {
+        // the coordinates of the sample opening the gap
+        let Some(row) = rows.iter().find(|row| row.created_at == gap.start) else {
+            continue;
+        };
+        if dry_run {
+            summary.inserted += gap.get_hours().len();
+            continue;
+        }
+        let observations =
+            match fetch_gap(client, config, source, gap, row.latitude, row.longitude).await {
+                Ok(observations) => observations,
+                Err(e) => {
+                    error!("failed to backfill {name} {} - {} {e}", gap.start, gap.end);
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+        for observation in observations {
+            let mut backfilled: WeatherDataDB = observation.into();
+            backfilled.set_location_name(name);
+            backfilled.set_server(server);
+            summary.inserted += backfilled.insert_backfilled(pool).await? as usize;
+        }
+    }
+    summary.gaps = gaps;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::{macros::datetime, Duration, OffsetDateTime};
+
+    use crate::{
+        backfill::{
+            find_gaps, weather_data_from_archive, BackfillSource, OpenMeteoArchive,
+            DEFAULT_BACKFILL_GAP,
+        },
+        model::WeatherDataDB,
+    };
+
+    fn row(created_at: OffsetDateTime) -> WeatherDataDB {
+        WeatherDataDB {
+            server: "N/A".into(),
+            ..WeatherDataDB::test_row(created_at)
+        }
+    }
+
+    #[test]
+    fn test_backfill() -> Result<(), Error> {
+        assert_eq!(
+            "open-meteo".parse::<BackfillSource>()?,
+            BackfillSource::OpenMeteo
+        );
+        assert!("archive".parse::<BackfillSource>().is_err());
+
+        let start = datetime!(2024-06-01 12:05 UTC);
+        let rows: Vec<_> = [0, 5, 10, 190, 200, 235]
+            .into_iter()
+            .map(|minutes| row(start + Duration::minutes(minutes)))
+            .collect();
+        let gaps = find_gaps(&rows, DEFAULT_BACKFILL_GAP);
+        assert_eq!(gaps.len(), 2);
+        assert_eq!(*gaps[0].start, datetime!(2024-06-01 12:15 UTC));
+        assert_eq!(*gaps[0].end, datetime!(2024-06-01 15:15 UTC));
+        assert_eq!(
+            gaps[0].get_hours(),
+            [
+                datetime!(2024-06-01 13:00 UTC),
+                datetime!(2024-06-01 14:00 UTC),
+                datetime!(2024-06-01 15:00 UTC),
+            ]
+        );
+        assert!(gaps[1].get_hours().is_empty());
+
+        let archive: OpenMeteoArchive = serde_json::from_str(
+            r#"{"utc_offset_seconds": 0, "hourly": {
+                "time": [1717243200, 1717246800, 1717250400, 1717254000],
+                "temperature_2m": [21.0, 22.5, null, 24.0],
+                "relative_humidity_2m": [60, 55, null, 50],
+                "is_day": [1, 1, null, 1],
+                "weather_code": [0, 61, null, 73],
+                "pressure_msl": [1012.0, 1011.5, null, 1011.0],
+                "wind_speed_10m": [3.0, 4.0, null, 5.0],
+                "wind_direction_10m": [180, 190, null, 200],
+                "rain": [0.0, 1.2, null, 0.0],
+                "snowfall": [0.0, 0.0, null, 0.3]
+            }}"#,
+        )?;
+        let observations: Vec<WeatherDataDB> =
+            weather_data_from_archive(&archive, 40.76, -73.93, &gaps[0].get_hours())?
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        assert_eq!(observations.len(), 2);
+        let rainy = &observations[0];
+        assert_eq!(rainy.dt, 1_717_246_800);
+        assert!((rainy.temperature - 295.65).abs() < 1e-9);
+        assert_eq!(rainy.humidity, 55);
+        assert_eq!(rainy.rain, Some(1.2));
+        assert!(rainy.condition.contains("light rain"));
+        let snowy = &observations[1];
+        assert!((snowy.snow.unwrap_or_default() - 3.0).abs() < 1e-9);
+        Ok(())
+    }
+}
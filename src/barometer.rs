@@ -0,0 +1,212 @@
+use anyhow::Error;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::fmt;
+
+use weather_util_rust::weather_data::WeatherData;
+
+use crate::{model::WeatherDataDB, pgpool::PgPool};
+
+/// Period the tendency is reported over (seconds)
+const TENDENCY_PERIOD: i32 = 3 * 3600;
+/// Recorded rows further than this from `TENDENCY_PERIOD` ago aren't used
+/// (seconds)
+const TENDENCY_MAX_OFFSET: i32 = 3600;
+/// Changes smaller than this over 3 hours are steady (kPa)
+const STEADY_THRESHOLD: f64 = 0.1;
+
+/// Zambretti forecasts, from settled to stormy
+const ZAMBRETTI_FORECASTS: [&str; 26] = [
+    "Settled fine",
+    "Fine weather",
+    "Becoming fine",
+    "Fine, becoming less settled",
+    "Fine, possible showers",
+    "Fairly fine, improving",
+    "Fairly fine, possible showers early",
+    "Fairly fine, showery later",
+    "Showery early, improving",
+    "Changeable, mending",
+    "Fairly fine, showers likely",
+    "Rather unsettled clearing later",
+    "Unsettled, probably improving",
+    "Showery, bright intervals",
+    "Showery, becoming less settled",
+    "Changeable, some rain",
+    "Unsettled, short fine intervals",
+    "Unsettled, rain later",
+    "Unsettled, some rain",
+    "Mostly very unsettled",
+    "Occasional rain, worsening",
+    "Rain at times, very unsettled",
+    "Rain at frequent intervals",
+    "Rain, very unsettled",
+    "Stormy, may improve",
+    "Stormy, much rain",
+];
+
+/// Forecast letters (indices of `ZAMBRETTI_FORECASTS`) of each trend
+const FALLING_FORECASTS: [usize; 9] = [0, 1, 3, 7, 14, 17, 20, 23, 25];
+const STEADY_FORECASTS: [usize; 10] = [0, 1, 4, 10, 13, 15, 18, 22, 23, 25];
+const RISING_FORECASTS: [usize; 13] = [0, 1, 2, 5, 6, 8, 9, 11, 12, 16, 19, 24, 25];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressureTrend {
+    Rising,
+    Falling,
+    Steady,
+}
+
+impl PressureTrend {
+    /// Trend of a pressure change over 3 hours (kPa)
+    #[must_use]
+    pub fn from_change(change: f64) -> Self {
+        if change >= STEADY_THRESHOLD {
+            Self::Rising
+        } else if change <= -STEADY_THRESHOLD {
+            Self::Falling
+        } else {
+            Self::Steady
+        }
+    }
+
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Rising => "rising",
+            Self::Falling => "falling",
+            Self::Steady => "steady",
+        }
+    }
+}
+
+impl fmt::Display for PressureTrend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+/// Zambretti forecast from sea level pressure (kPa), its trend and the wind,
+/// winds blowing from the equator side make the forecast one step worse and
+/// winds from the pole side one step better
+#[must_use]
+pub fn zambretti_forecast(
+    pressure: f64,
+    trend: PressureTrend,
+    wind_direction: Option<f64>,
+    latitude: f64,
+) -> &'static str {
+    let hpa = pressure * 10.0;
+    let (z, letters): (f64, &[usize]) = match trend {
+        PressureTrend::Falling => (127.0 - 0.12 * hpa, &FALLING_FORECASTS),
+        PressureTrend::Steady => (144.0 - 0.13 * hpa - 9.0, &STEADY_FORECASTS),
+        PressureTrend::Rising => (185.0 - 0.16 * hpa - 19.0, &RISING_FORECASTS),
+    };
+    let mut index = z.round() as i64 - 1;
+    if let Some(direction) = wind_direction {
+        let southerly = (90.0..=270.0).contains(&direction.rem_euclid(360.0));
+        let equatorward = if latitude >= 0.0 {
+            southerly
+        } else {
+            !southerly
+        };
+        index += if equatorward { 1 } else { -1 };
+    }
+    let index = index.clamp(0, letters.len() as i64 - 1) as usize;
+    ZAMBRETTI_FORECASTS[letters[index]]
+}
+
+/// Pressure tendency over the last 3 hours with a barometric forecast
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "PressureTendency")]
+pub struct PressureTendency {
+    #[schema(description = "Trend (rising, falling or steady)")]
+    pub trend: StackString,
+    #[schema(description = "Pressure Change over 3 Hours (kPa)")]
+    pub change: f64,
+    #[schema(description = "Rate of Change (kPa per hour)")]
+    pub rate: f64,
+    #[schema(description = "Zambretti Forecast")]
+    pub forecast: StackString,
+}
+
+impl PressureTendency {
+    /// Tendency between a recorded row and the current conditions, `None`
+    /// unless the row is at least an hour older
+    #[must_use]
+    pub fn new(weather: &WeatherData, previous: &WeatherDataDB) -> Option<Self> {
+        let hours = (weather.dt.unix_timestamp() - i64::from(previous.dt)) as f64 / 3600.0;
+        if hours < 1.0 {
+            return None;
+        }
+        let pressure = weather.main.pressure.kpa();
+        let rate = (pressure - previous.pressure) / hours;
+        let change = rate * 3.0;
+        let trend = PressureTrend::from_change(change);
+        let forecast = zambretti_forecast(
+            pressure,
+            trend,
+            weather.wind.deg.map(|d| d.deg()),
+            weather.coord.lat.into(),
+        );
+        Some(Self {
+            trend: trend.to_str().into(),
+            change,
+            rate,
+            forecast: forecast.into(),
+        })
+    }
+}
+
+/// Tendency of `weather` from the row recorded for `location_name` closest
+/// to 3 hours earlier
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn get_pressure_tendency(
+    pool: &PgPool,
+    location_name: &str,
+    weather: &WeatherData,
+) -> Result<Option<PressureTendency>, Error> {
+    let dt = weather.dt.unix_timestamp() as i32 - TENDENCY_PERIOD;
+    let previous =
+        WeatherDataDB::get_closest_by_name(pool, location_name, dt, TENDENCY_MAX_OFFSET).await?;
+    Ok(previous.and_then(|previous| PressureTendency::new(weather, &previous)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::barometer::{zambretti_forecast, PressureTrend};
+
+    #[test]
+    fn test_pressure_trend() {
+        assert_eq!(PressureTrend::from_change(0.25), PressureTrend::Rising);
+        assert_eq!(PressureTrend::from_change(-0.1), PressureTrend::Falling);
+        assert_eq!(PressureTrend::from_change(0.05), PressureTrend::Steady);
+    }
+
+    #[test]
+    fn test_zambretti_forecast() {
+        assert_eq!(
+            zambretti_forecast(104.0, PressureTrend::Rising, None, 40.0),
+            "Settled fine"
+        );
+        assert_eq!(
+            zambretti_forecast(101.5, PressureTrend::Steady, None, 40.0),
+            "Fine, possible showers"
+        );
+        assert_eq!(
+            zambretti_forecast(101.5, PressureTrend::Steady, Some(200.0), 40.0),
+            "Fairly fine, showers likely"
+        );
+        assert_eq!(
+            zambretti_forecast(101.5, PressureTrend::Steady, Some(200.0), -33.0),
+            "Fine weather"
+        );
+        assert_eq!(
+            zambretti_forecast(98.0, PressureTrend::Falling, None, 40.0),
+            "Stormy, much rain"
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use anyhow::Error;
+use futures::{Future, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use std::{fmt, path::Path, time::Instant};
+use time::{macros::date, Date};
+
+use weather_api_types::units::Units;
+use weather_util_rust::weather_data::WeatherData;
+
+use crate::{
+    config::Config,
+    demo::generate_demo_history,
+    get_history_temperature_plot,
+    model::WeatherDataDB,
+    pgpool::PgPool,
+    precision::{JsonPrecision, Rounded},
+    PlotPointWrapper,
+};
+
+#[cfg(feature = "analysis")]
+use crate::polars_analysis::{get_by_name_dates, insert_rows_into_parquet};
+
+/// Fixed end date so every run benchmarks the same synthetic rows
+const BENCH_END_DATE: Date = date!(2024 - 01 - 01);
+const BENCH_SEED: u64 = 0;
+
+/// `n` rows of synthetic hourly history of a single location
+#[must_use]
+pub fn demo_rows(n: usize) -> Vec<WeatherDataDB> {
+    let days = n.div_ceil(24).max(1) as u32;
+    let mut rows = generate_demo_history(1, days, BENCH_END_DATE, BENCH_SEED);
+    rows.truncate(n);
+    rows
+}
+
+/// Temperature plot json of `rows`, as served by the history plot endpoint
+///
+/// # Errors
+/// Return error if serialization fails
+pub fn plot_json(rows: &[WeatherDataDB], precision: &JsonPrecision) -> Result<Vec<u8>, Error> {
+    let history: Vec<WeatherData> = rows.iter().cloned().map(Into::into).collect();
+    let plots: Vec<PlotPointWrapper> = get_history_temperature_plot(&history, Units::Imperial)
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let plots = Rounded::new(plots, precision.clone()).with_metric("temperature");
+    serde_json::to_vec(&plots).map_err(Into::into)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: StackString,
+    pub rows: usize,
+    pub iterations: usize,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+}
+
+impl BenchResult {
+    #[must_use]
+    pub fn rows_per_second(&self) -> f64 {
+        if self.mean_ms > 0.0 {
+            self.rows as f64 * 1000.0 / self.mean_ms
+        } else {
+            0.0
+        }
+    }
+}
+
+async fn time_async<F, Fut>(
+    name: StackString,
+    iterations: usize,
+    mut f: F,
+) -> Result<BenchResult, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<usize, Error>>,
+{
+    let iterations = iterations.max(1);
+    let mut total = 0.0;
+    let mut min_ms = f64::MAX;
+    let mut rows = 0;
+    for _ in 0..iterations {
+        let start = Instant::now();
+        rows = f().await?;
+        let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+        total += elapsed;
+        min_ms = min_ms.min(elapsed);
+    }
+    Ok(BenchResult {
+        name,
+        rows,
+        iterations,
+        mean_ms: total / iterations as f64,
+        min_ms,
+    })
+}
+
+/// Results of a run, stored as json to serve as the baseline of later runs
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchReport {
+    /// Compare with `baseline`, a benchmark regresses when its mean is more
+    /// than `threshold` (fraction) slower
+    #[must_use]
+    pub fn compare(&self, baseline: Option<&Self>, threshold: f64) -> BenchSummary {
+        let comparisons: Vec<_> = self
+            .results
+            .iter()
+            .map(|result| {
+                let baseline_ms = baseline
+                    .and_then(|b| b.results.iter().find(|r| r.name == result.name))
+                    .map(|r| r.mean_ms);
+                let change = baseline_ms
+                    .filter(|b| *b > 0.0)
+                    .map(|b| (result.mean_ms - b) / b);
+                BenchComparison {
+                    result: result.clone(),
+                    baseline_ms,
+                    change,
+                    regression: change.is_some_and(|c| c > threshold),
+                }
+            })
+            .collect();
+        let regressions = comparisons.iter().filter(|c| c.regression).count();
+        BenchSummary {
+            comparisons,
+            regressions,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchComparison {
+    pub result: BenchResult,
+    pub baseline_ms: Option<f64>,
+    /// relative change of the mean from the baseline
+    pub change: Option<f64>,
+    pub regression: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchSummary {
+    pub comparisons: Vec<BenchComparison>,
+    pub regressions: usize,
+}
+
+impl fmt::Display for BenchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for BenchComparison {
+            result,
+            baseline_ms,
+            change,
+            regression,
+        } in &self.comparisons
+        {
+            write!(
+                f,
+                "{:32} {:>9} rows {:>10.2} ms (min {:>10.2}) {:>12.0} rows/s",
+                result.name,
+                result.rows,
+                result.mean_ms,
+                result.min_ms,
+                result.rows_per_second(),
+            )?;
+            if let (Some(baseline_ms), Some(change)) = (baseline_ms, change) {
+                write!(f, " baseline {baseline_ms:.2} ms {:+.1}%", change * 100.0)?;
+            }
+            if *regression {
+                write!(f, " REGRESSION")?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "{} regressions", self.regressions)
+    }
+}
+
+/// Plot json serialization and parquet archive throughput for each of
+/// `sizes` (synthetic rows), and history query latency of `location` from
+/// the db and the parquet archive in `config.cache_dir`
+///
+/// # Errors
+/// Return error if a query, serialization or parquet read / write fails
+pub async fn run_benchmarks(
+    pool: &PgPool,
+    config: &Config,
+    sizes: &[usize],
+    iterations: usize,
+    location: Option<&str>,
+) -> Result<BenchReport, Error> {
+    let precision = &JsonPrecision::from_config(config);
+    let mut results = Vec::new();
+    for &size in sizes {
+        let rows = &demo_rows(size);
+        let name = format_sstr!("plot_json/{size}");
+        results.push(
+            time_async(name, iterations, move || async move {
+                plot_json(rows, precision).map(|_| rows.len())
+            })
+            .await?,
+        );
+        #[cfg(feature = "analysis")]
+        {
+            let dirname = format_sstr!("weather_api_bench_{}_{size}", std::process::id());
+            let directory = &std::env::temp_dir().join(dirname.as_str());
+            let name = format_sstr!("parquet_write/{size}");
+            results.push(
+                time_async(name, iterations, move || async move {
+                    if directory.exists() {
+                        tokio::fs::remove_dir_all(directory).await?;
+                    }
+                    tokio::fs::create_dir_all(directory).await?;
+                    insert_rows_into_parquet(rows.iter().cloned(), directory)?;
+                    Ok::<_, Error>(rows.len())
+                })
+                .await?,
+            );
+            let name = format_sstr!("parquet_read/{size}");
+            results.push(
+                time_async(name, iterations, move || {
+                    read_parquet(directory, None, None)
+                })
+                .await?,
+            );
+            tokio::fs::remove_dir_all(directory).await?;
+        }
+    }
+    if let Some(location) = location {
+        let limit = sizes.iter().max().copied();
+        if pool.is_enabled() {
+            let name = format_sstr!("history_db/{location}");
+            results.push(
+                time_async(name, iterations, move || async move {
+                    let rows: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates(
+                        pool,
+                        Some(location),
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        limit,
+                    )
+                    .await?
+                    .try_collect()
+                    .await?;
+                    Ok::<_, Error>(rows.len())
+                })
+                .await?,
+            );
+        }
+        #[cfg(feature = "analysis")]
+        if config.cache_dir.exists() {
+            let cache_dir = &config.cache_dir;
+            let name = format_sstr!("history_parquet/{location}");
+            results.push(
+                time_async(name, iterations, move || {
+                    read_parquet(cache_dir, Some(location), limit)
+                })
+                .await?,
+            );
+        }
+    }
+    Ok(BenchReport { results })
+}
+
+#[cfg(feature = "analysis")]
+async fn read_parquet(
+    directory: &Path,
+    location: Option<&str>,
+    limit: Option<usize>,
+) -> Result<usize, Error> {
+    get_by_name_dates(directory, location, None, None, None, None, limit)
+        .await
+        .map(|rows| rows.len())
+}
+
+/// # Errors
+/// Return error if the file can't be read or parsed
+pub async fn read_baseline(path: &Path) -> Result<BenchReport, Error> {
+    let data = tokio::fs::read(path).await?;
+    serde_json::from_slice(&data).map_err(Into::into)
+}
+
+/// # Errors
+/// Return error if the file can't be written
+pub async fn write_baseline(path: &Path, report: &BenchReport) -> Result<(), Error> {
+    tokio::fs::write(path, serde_json::to_vec_pretty(report)?)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use serde_json::Value;
+
+    use crate::{
+        bench::{demo_rows, plot_json, BenchReport, BenchResult},
+        precision::JsonPrecision,
+    };
+
+    fn result(name: &str, mean_ms: f64) -> BenchResult {
+        BenchResult {
+            name: name.into(),
+            rows: 1000,
+            iterations: 5,
+            mean_ms,
+            min_ms: mean_ms,
+        }
+    }
+
+    #[test]
+    fn test_bench_report() -> Result<(), Error> {
+        let rows = demo_rows(100);
+        assert_eq!(rows.len(), 100);
+        let json: Value = serde_json::from_slice(&plot_json(&rows, &JsonPrecision::default())?)?;
+        assert_eq!(json.as_array().map(Vec::len), Some(100));
+
+        let baseline = BenchReport {
+            results: vec![
+                result("plot_json/1000", 10.0),
+                result("parquet_read/1000", 10.0),
+            ],
+        };
+        let report = BenchReport {
+            results: vec![
+                result("plot_json/1000", 11.0),
+                result("parquet_read/1000", 15.0),
+                result("history_db/10001", 5.0),
+            ],
+        };
+        let summary = report.compare(Some(&baseline), 0.2);
+        assert_eq!(summary.regressions, 1);
+        assert!(!summary.comparisons[0].regression);
+        assert!(summary.comparisons[1].regression);
+        assert_eq!(summary.comparisons[2].baseline_ms, None);
+        assert!((summary.comparisons[1].change.unwrap_or_default() - 0.5).abs() < 1e-9);
+        assert!((summary.comparisons[0].result.rows_per_second() - 1e6 / 11.0).abs() < 1e-6);
+        assert!(summary.to_string().contains("+50.0% REGRESSION"));
+        assert_eq!(report.compare(None, 0.2).regressions, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use stack_string::{format_sstr, StackString};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use weather_util_rust::weather_api::{WeatherApi, WeatherLocation};
+
+/// Key of the weather data and forecast caches, every parameter that changes
+/// the upstream response is part of the key so requests made with another
+/// account never share an entry. Upstream is always asked for standard units
+/// and the default language, responses are converted after the cache
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WeatherCacheKey {
+    /// location as requested
+    pub location: StackString,
+    /// fingerprint of the provider account (api key and endpoint), the key
+    /// itself is not kept in the cache
+    pub account: u64,
+}
+
+impl WeatherCacheKey {
+    #[must_use]
+    pub fn new(api: &WeatherApi, loc: &WeatherLocation) -> Self {
+        let mut hasher = DefaultHasher::new();
+        api.hash(&mut hasher);
+        Self {
+            location: Self::location_key(loc),
+            account: hasher.finish(),
+        }
+    }
+
+    /// Location part of the key, shared by every entry of `loc`
+    #[must_use]
+    pub fn location_key(loc: &WeatherLocation) -> StackString {
+        format_sstr!("{loc:?}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use weather_util_rust::weather_api::{WeatherApi, WeatherLocation};
+
+    use crate::cache_key::WeatherCacheKey;
+
+    #[test]
+    fn test_weather_cache_key() {
+        let api = WeatherApi::default();
+        let other_key = api.clone().with_key("other_key");
+        let other_endpoint = WeatherApi::new("", "other.example.com", "data/2.5/", "geo/1.0/");
+        let zip = WeatherLocation::from_zipcode(55427);
+        let city = WeatherLocation::from_city_name("Minneapolis");
+
+        let key = WeatherCacheKey::new(&api, &zip);
+        assert_eq!(key, WeatherCacheKey::new(&api.clone(), &zip));
+        assert_eq!(key.location, WeatherCacheKey::location_key(&zip));
+
+        let keys = [
+            key.clone(),
+            WeatherCacheKey::new(&api, &city),
+            WeatherCacheKey::new(&other_key, &zip),
+            WeatherCacheKey::new(&other_endpoint, &zip),
+        ];
+        let unique: HashSet<_> = keys.iter().collect();
+        assert_eq!(unique.len(), keys.len());
+    }
+}
@@ -0,0 +1,192 @@
+use rweb::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    openapi::{
+        ComponentDescriptor, ComponentOrInlineSchema, Entity, MediaType, Response, ResponseEntity,
+        Responses, Schema, Type,
+    },
+    reply, Reply,
+};
+use std::{borrow::Cow, convert::TryFrom};
+
+use weather_util_rust::{weather_data::WeatherData, weather_forecast::WeatherForecast};
+
+pub const COMPACT_MAGIC: &[u8; 4] = b"WXCB";
+pub const COMPACT_VERSION: u8 = 1;
+pub const COMPACT_FORECAST_POINTS: usize = 8;
+pub const COMPACT_HEADER_SIZE: usize = 36;
+pub const COMPACT_POINT_SIZE: usize = 12;
+
+/// Layout of the `/weather/compact.bin` payload, all fields little-endian.
+pub const COMPACT_DESCRIPTION: &str = "Fixed layout little-endian binary weather payload \
+    (132 bytes). Header (36 bytes): 0 [u8;4] magic \"WXCB\"; 4 u8 version (1); 5 u8 forecast \
+    point count (8); 6 i16 utc offset (minutes); 8 u32 observation time (unix seconds); 12 i16 \
+    temperature (0.01 C); 14 i16 feels like (0.01 C); 16 u16 pressure (0.1 hPa); 18 u8 humidity \
+    (%); 19 u8 reserved; 20 u16 wind speed (0.01 m/s); 22 u16 wind direction (degrees, 0xFFFF if \
+    unknown); 24 u16 condition id (openweathermap); 26 u16 precipitation over the previous hour \
+    (0.01 mm); 28 u32 sunrise (unix seconds); 32 u32 sunset (unix seconds). Followed by 8 \
+    forecast points (12 bytes each): 0 u32 time (unix seconds); 4 i16 temperature (0.01 C); 6 \
+    u16 precipitation over three hours (0.01 mm); 8 u16 condition id; 10 u8 humidity (%); 11 u8 \
+    reserved. Missing forecast points are zero filled.";
+
+fn scaled_i16(value: f64, scale: f64) -> i16 {
+    (value * scale)
+        .round()
+        .clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+}
+
+fn scaled_u16(value: f64, scale: f64) -> u16 {
+    (value * scale).round().clamp(0.0, f64::from(u16::MAX)) as u16
+}
+
+fn unix_u32(timestamp: i64) -> u32 {
+    u32::try_from(timestamp).unwrap_or(0)
+}
+
+fn humidity_u8(humidity: i64) -> u8 {
+    u8::try_from(humidity.clamp(0, 100)).unwrap_or(0)
+}
+
+/// Encode current conditions and the next eight forecast points into the
+/// layout documented in `COMPACT_DESCRIPTION`.
+#[must_use]
+pub fn encode_compact(weather: &WeatherData, forecast: &WeatherForecast) -> Vec<u8> {
+    let mut buf =
+        Vec::with_capacity(COMPACT_HEADER_SIZE + COMPACT_FORECAST_POINTS * COMPACT_POINT_SIZE);
+
+    let timezone: i32 = weather.timezone.into();
+    let humidity: i64 = weather.main.humidity.into();
+    let condition_id = weather
+        .weather
+        .first()
+        .map_or(0, |w| u16::try_from(w.id).unwrap_or(0));
+    let precipitation = weather
+        .rain
+        .as_ref()
+        .and_then(|r| r.one_hour)
+        .unwrap_or_default()
+        + weather
+            .snow
+            .as_ref()
+            .and_then(|s| s.one_hour)
+            .unwrap_or_default();
+
+    buf.extend_from_slice(COMPACT_MAGIC);
+    buf.push(COMPACT_VERSION);
+    buf.push(COMPACT_FORECAST_POINTS as u8);
+    buf.extend_from_slice(&i16::try_from(timezone / 60).unwrap_or(0).to_le_bytes());
+    buf.extend_from_slice(&unix_u32(weather.dt.unix_timestamp()).to_le_bytes());
+    buf.extend_from_slice(&scaled_i16(weather.main.temp.celcius(), 100.0).to_le_bytes());
+    buf.extend_from_slice(&scaled_i16(weather.main.feels_like.celcius(), 100.0).to_le_bytes());
+    buf.extend_from_slice(&scaled_u16(weather.main.pressure.kpa(), 100.0).to_le_bytes());
+    buf.push(humidity_u8(humidity));
+    buf.push(0);
+    buf.extend_from_slice(&scaled_u16(weather.wind.speed.mps(), 100.0).to_le_bytes());
+    buf.extend_from_slice(
+        &weather
+            .wind
+            .deg
+            .map_or(u16::MAX, |d| scaled_u16(d.deg(), 1.0))
+            .to_le_bytes(),
+    );
+    buf.extend_from_slice(&condition_id.to_le_bytes());
+    buf.extend_from_slice(&scaled_u16(precipitation.millimeters(), 100.0).to_le_bytes());
+    buf.extend_from_slice(&unix_u32(weather.sys.sunrise.unix_timestamp()).to_le_bytes());
+    buf.extend_from_slice(&unix_u32(weather.sys.sunset.unix_timestamp()).to_le_bytes());
+
+    let mut points = 0;
+    for entry in forecast
+        .list
+        .iter()
+        .filter(|entry| entry.dt > weather.dt)
+        .take(COMPACT_FORECAST_POINTS)
+    {
+        let humidity: i64 = entry.main.humidity.into();
+        let condition_id = entry
+            .weather
+            .first()
+            .map_or(0, |w| u16::try_from(w.id).unwrap_or(0));
+        let precipitation = entry
+            .rain
+            .as_ref()
+            .and_then(|r| r.three_hour)
+            .unwrap_or_default()
+            + entry
+                .snow
+                .as_ref()
+                .and_then(|s| s.three_hour)
+                .unwrap_or_default();
+        buf.extend_from_slice(&unix_u32(entry.dt.unix_timestamp()).to_le_bytes());
+        buf.extend_from_slice(&scaled_i16(entry.main.temp.celcius(), 100.0).to_le_bytes());
+        buf.extend_from_slice(&scaled_u16(precipitation.millimeters(), 100.0).to_le_bytes());
+        buf.extend_from_slice(&condition_id.to_le_bytes());
+        buf.push(humidity_u8(humidity));
+        buf.push(0);
+        points += 1;
+    }
+    buf.resize(
+        buf.len() + (COMPACT_FORECAST_POINTS - points) * COMPACT_POINT_SIZE,
+        0,
+    );
+    buf
+}
+
+pub struct CompactBinResponse(pub Vec<u8>);
+
+impl Reply for CompactBinResponse {
+    fn into_response(self) -> reply::Response {
+        reply::with_header(self.0, CONTENT_TYPE, "application/octet-stream").into_response()
+    }
+}
+
+impl Entity for CompactBinResponse {
+    fn type_name() -> Cow<'static, str> {
+        "compact_bin".into()
+    }
+
+    fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        ComponentOrInlineSchema::Inline(Schema {
+            schema_type: Some(Type::String),
+            format: "binary".into(),
+            description: COMPACT_DESCRIPTION.into(),
+            ..Schema::default()
+        })
+    }
+}
+
+impl ResponseEntity for CompactBinResponse {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        let mut response = Response {
+            description: Cow::Borrowed(COMPACT_DESCRIPTION),
+            ..Response::default()
+        };
+        response.content.insert(
+            Cow::Borrowed("application/octet-stream"),
+            MediaType {
+                schema: Some(Self::describe(comp_d)),
+                ..MediaType::default()
+            },
+        );
+        let mut map = Responses::new();
+        map.insert(Cow::Owned(StatusCode::OK.as_str().into()), response);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compact::{
+        scaled_i16, scaled_u16, COMPACT_FORECAST_POINTS, COMPACT_HEADER_SIZE, COMPACT_POINT_SIZE,
+    };
+
+    #[test]
+    fn test_scaled_values() {
+        assert_eq!(scaled_i16(21.456, 100.0), 2146);
+        assert_eq!(scaled_i16(-500.0, 100.0), i16::MIN);
+        assert_eq!(scaled_u16(-1.0, 100.0), 0);
+        assert_eq!(scaled_u16(101.325, 100.0), 10133);
+        assert_eq!(
+            COMPACT_HEADER_SIZE + COMPACT_FORECAST_POINTS * COMPACT_POINT_SIZE,
+            132
+        );
+    }
+}
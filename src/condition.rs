@@ -0,0 +1,262 @@
+use anyhow::{format_err, Error};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Openweathermap condition id, main group and description, the `condition`
+/// column stores `"{main} {description} "` of each condition joined by `", "`
+const OWM_CONDITIONS: [(i32, &str, &str); 55] = [
+    (200, "Thunderstorm", "thunderstorm with light rain"),
+    (201, "Thunderstorm", "thunderstorm with rain"),
+    (202, "Thunderstorm", "thunderstorm with heavy rain"),
+    (210, "Thunderstorm", "light thunderstorm"),
+    (211, "Thunderstorm", "thunderstorm"),
+    (212, "Thunderstorm", "heavy thunderstorm"),
+    (221, "Thunderstorm", "ragged thunderstorm"),
+    (230, "Thunderstorm", "thunderstorm with light drizzle"),
+    (231, "Thunderstorm", "thunderstorm with drizzle"),
+    (232, "Thunderstorm", "thunderstorm with heavy drizzle"),
+    (300, "Drizzle", "light intensity drizzle"),
+    (301, "Drizzle", "drizzle"),
+    (302, "Drizzle", "heavy intensity drizzle"),
+    (310, "Drizzle", "light intensity drizzle rain"),
+    (311, "Drizzle", "drizzle rain"),
+    (312, "Drizzle", "heavy intensity drizzle rain"),
+    (313, "Drizzle", "shower rain and drizzle"),
+    (314, "Drizzle", "heavy shower rain and drizzle"),
+    (321, "Drizzle", "shower drizzle"),
+    (500, "Rain", "light rain"),
+    (501, "Rain", "moderate rain"),
+    (502, "Rain", "heavy intensity rain"),
+    (503, "Rain", "very heavy rain"),
+    (504, "Rain", "extreme rain"),
+    (511, "Rain", "freezing rain"),
+    (520, "Rain", "light intensity shower rain"),
+    (521, "Rain", "shower rain"),
+    (522, "Rain", "heavy intensity shower rain"),
+    (531, "Rain", "ragged shower rain"),
+    (600, "Snow", "light snow"),
+    (601, "Snow", "snow"),
+    (602, "Snow", "heavy snow"),
+    (611, "Snow", "sleet"),
+    (612, "Snow", "light shower sleet"),
+    (613, "Snow", "shower sleet"),
+    (615, "Snow", "light rain and snow"),
+    (616, "Snow", "rain and snow"),
+    (620, "Snow", "light shower snow"),
+    (621, "Snow", "shower snow"),
+    (622, "Snow", "heavy shower snow"),
+    (701, "Mist", "mist"),
+    (711, "Smoke", "smoke"),
+    (721, "Haze", "haze"),
+    (731, "Dust", "sand/dust whirls"),
+    (741, "Fog", "fog"),
+    (751, "Sand", "sand"),
+    (761, "Dust", "dust"),
+    (762, "Ash", "volcanic ash"),
+    (771, "Squall", "squalls"),
+    (781, "Tornado", "tornado"),
+    (800, "Clear", "clear sky"),
+    (801, "Clouds", "few clouds"),
+    (802, "Clouds", "scattered clouds"),
+    (803, "Clouds", "broken clouds"),
+    (804, "Clouds", "overcast clouds"),
+];
+
+/// Code of a main group whose description isn't known, e.g. `Rain` from a
+/// provider other than openweathermap
+fn main_code(main: &str) -> Option<i32> {
+    let code = match main.to_ascii_lowercase().as_str() {
+        "thunderstorm" => 211,
+        "drizzle" => 301,
+        "rain" => 501,
+        "snow" => 601,
+        "mist" => 701,
+        "smoke" => 711,
+        "haze" => 721,
+        "fog" => 741,
+        "sand" => 751,
+        "dust" => 761,
+        "ash" => 762,
+        "squall" => 771,
+        "tornado" => 781,
+        "clear" => 800,
+        "clouds" => 803,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Condition code of the first condition of a `condition` column value,
+/// e.g. `"Rain light rain , Clouds broken clouds "` is 500
+#[must_use]
+pub fn condition_code(condition: &str) -> Option<i32> {
+    let first = condition.split(',').next()?.trim();
+    let (main, description) = first.split_once(' ').unwrap_or((first, ""));
+    let description = description.trim();
+    OWM_CONDITIONS
+        .iter()
+        .find(|(_, m, d)| m.eq_ignore_ascii_case(main) && d.eq_ignore_ascii_case(description))
+        .or_else(|| {
+            OWM_CONDITIONS
+                .iter()
+                .find(|(_, _, d)| d.eq_ignore_ascii_case(description))
+        })
+        .map(|(code, ..)| *code)
+        .or_else(|| main_code(main))
+}
+
+/// Groups of condition codes, by hundreds (700 - 799 are all atmospheric,
+/// 800 is clear and 801 - 804 cloudy)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConditionGroup {
+    Thunderstorm,
+    Drizzle,
+    Rain,
+    Snow,
+    Atmosphere,
+    Clear,
+    Clouds,
+}
+
+impl ConditionGroup {
+    #[must_use]
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            200..=299 => Some(Self::Thunderstorm),
+            300..=399 => Some(Self::Drizzle),
+            500..=599 => Some(Self::Rain),
+            600..=699 => Some(Self::Snow),
+            700..=799 => Some(Self::Atmosphere),
+            800 => Some(Self::Clear),
+            801..=899 => Some(Self::Clouds),
+            _ => None,
+        }
+    }
+
+    /// Inclusive range of the codes of the group
+    #[must_use]
+    pub fn code_range(self) -> (i32, i32) {
+        match self {
+            Self::Thunderstorm => (200, 299),
+            Self::Drizzle => (300, 399),
+            Self::Rain => (500, 599),
+            Self::Snow => (600, 699),
+            Self::Atmosphere => (700, 799),
+            Self::Clear => (800, 800),
+            Self::Clouds => (801, 899),
+        }
+    }
+
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Thunderstorm => "thunderstorm",
+            Self::Drizzle => "drizzle",
+            Self::Rain => "rain",
+            Self::Snow => "snow",
+            Self::Atmosphere => "atmosphere",
+            Self::Clear => "clear",
+            Self::Clouds => "clouds",
+        }
+    }
+}
+
+impl fmt::Display for ConditionGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+impl FromStr for ConditionGroup {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "thunderstorm" => Ok(Self::Thunderstorm),
+            "drizzle" => Ok(Self::Drizzle),
+            "rain" => Ok(Self::Rain),
+            "snow" => Ok(Self::Snow),
+            "atmosphere" => Ok(Self::Atmosphere),
+            "clear" => Ok(Self::Clear),
+            "clouds" => Ok(Self::Clouds),
+            _ => Err(format_err!("Invalid condition group {s}")),
+        }
+    }
+}
+
+/// `condition=` filter of the history endpoints, a group (`rain`) or a single
+/// code (`501`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionFilter {
+    Group(ConditionGroup),
+    Code(i32),
+}
+
+impl ConditionFilter {
+    /// Inclusive range of matching codes
+    #[must_use]
+    pub fn code_range(self) -> (i32, i32) {
+        match self {
+            Self::Group(group) => group.code_range(),
+            Self::Code(code) => (code, code),
+        }
+    }
+
+    #[must_use]
+    pub fn matches(self, code: Option<i32>) -> bool {
+        let (min, max) = self.code_range();
+        code.is_some_and(|code| (min..=max).contains(&code))
+    }
+}
+
+impl FromStr for ConditionFilter {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(code) = s.trim().parse() {
+            Ok(Self::Code(code))
+        } else {
+            s.parse().map(Self::Group)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::condition::{condition_code, ConditionFilter, ConditionGroup, OWM_CONDITIONS};
+
+    #[test]
+    fn test_condition_code() {
+        assert_eq!(
+            condition_code("Rain light rain , Clouds broken clouds "),
+            Some(500)
+        );
+        assert_eq!(condition_code("Clear clear sky "), Some(800));
+        assert_eq!(condition_code("Dust sand/dust whirls "), Some(731));
+        assert_eq!(condition_code("Dust dust "), Some(761));
+        assert_eq!(condition_code("Snow light snow "), Some(600));
+        assert_eq!(condition_code("Rain"), Some(501));
+        assert_eq!(condition_code("Clouds"), Some(803));
+        assert_eq!(condition_code(""), None);
+        assert_eq!(condition_code("Unknown something "), None);
+        assert!(OWM_CONDITIONS
+            .iter()
+            .all(|(code, ..)| ConditionGroup::from_code(*code).is_some()));
+    }
+
+    #[test]
+    fn test_condition_filter() -> Result<(), Error> {
+        let filter: ConditionFilter = "Rain".parse()?;
+        assert_eq!(filter, ConditionFilter::Group(ConditionGroup::Rain));
+        assert!(filter.matches(Some(501)));
+        assert!(!filter.matches(Some(600)));
+        assert!(!filter.matches(None));
+        let filter: ConditionFilter = "800".parse()?;
+        assert!(filter.matches(Some(800)));
+        assert!(!filter.matches(Some(801)));
+        assert!(ConditionFilter::Group(ConditionGroup::Clouds).matches(Some(804)));
+        assert!("windy".parse::<ConditionFilter>().is_err());
+        Ok(())
+    }
+}
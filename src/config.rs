@@ -1,18 +1,32 @@
 use anyhow::Error;
 use isocountry::CountryCode;
-use serde::{Deserialize, Deserializer};
+use serde::{
+    de::{self, IntoDeserializer},
+    Deserialize, Deserializer,
+};
 use stack_string::{format_sstr, SmallString, StackString};
 use std::{
+    collections::HashMap,
+    net::IpAddr,
     ops::Deref,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-use weather_api_common::get_parameters;
-use weather_util_rust::{latitude::Latitude, longitude::Longitude, weather_api::WeatherLocation};
+use weather_api_types::{get_parameters, units::Units};
+use weather_util_rust::{
+    latitude::Latitude,
+    longitude::Longitude,
+    weather_api::{WeatherApi, WeatherLocation},
+};
+
+use crate::{
+    fusion::{parse_fusion_source, FusionMode, FusionSource, FUSION_METRICS},
+    providers::WeatherProviderType,
+};
 
 /// Configuration data
-#[derive(Default, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Default, Debug, Deserialize, PartialEq)]
 pub struct ConfigInner {
     /// openweathermap.org api key
     pub api_key: SmallString<32>,
@@ -25,6 +39,9 @@ pub struct ConfigInner {
     /// Geo Api path (default is `geo/1.0/`)
     #[serde(default = "default_geo_path")]
     pub geo_path: StackString,
+    /// One Call Api path (default is `data/3.0/`)
+    #[serde(default = "default_onecall_path")]
+    pub onecall_path: StackString,
     /// optional default zipcode
     pub zipcode: Option<u64>,
     /// optional default country code
@@ -39,9 +56,28 @@ pub struct ConfigInner {
     pub host: StackString,
     #[serde(default = "default_port")]
     pub port: u32,
-    #[serde(deserialize_with = "deserialize_semi_colon_delimited_locations", default = "Vec::new")]
-    pub locations_to_record: Vec<WeatherLocation>,
-    pub database_url: StackString,
+    /// locations recorded by the daemon, each with an optional polling
+    /// interval, e.g. `10001@5m;Paris@30m`, seeds the `recorded_locations`
+    /// table which is managed through `/weather/record-locations` afterwards
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_recorded_locations",
+        default = "Vec::new"
+    )]
+    pub locations_to_record: Vec<RecordedLocation>,
+    /// seconds between polls of recorded locations without an interval
+    #[serde(default = "default_record_interval")]
+    pub record_interval: u64,
+    /// per location provider accounts, `name:api_key;name:api_key@endpoint`
+    /// with names in the format of `locations_to_record`, rows of the
+    /// `location_provider_overrides` table take precedence
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_overrides",
+        default = "HashMap::new"
+    )]
+    pub provider_overrides: HashMap<StackString, ProviderOverride>,
+    /// optional postgres url, without it the server runs as a stateless
+    /// caching proxy (no recording, history or location cache)
+    pub database_url: Option<StackString>,
     #[serde(default = "default_server")]
     pub server: StackString,
     #[serde(default = "default_secret_path")]
@@ -50,8 +86,256 @@ pub struct ConfigInner {
     pub jwt_secret_path: PathBuf,
     #[serde(default = "default_cache_dir")]
     pub cache_dir: PathBuf,
+    /// parquet archive of the recorded forecasts, kept apart from
+    /// `cache_dir` which only holds observation files
+    #[serde(default = "default_forecast_cache_dir")]
+    pub forecast_cache_dir: PathBuf,
     #[serde(default = "default_s3_bucket")]
     pub s3_bucket: StackString,
+    /// water deficit (mm) allowed before the watering advisor recommends
+    /// irrigation
+    #[serde(default = "default_watering_threshold")]
+    pub watering_threshold: u32,
+    /// per location overrides of `watering_threshold`, `name:mm;name:mm`
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_thresholds",
+        default = "HashMap::new"
+    )]
+    pub watering_thresholds: HashMap<StackString, u32>,
+    /// skip recording a row when all metrics are within the `record_delta_*`
+    /// epsilons of the last stored row for the location
+    #[serde(default)]
+    pub record_delta: bool,
+    /// always record if the last stored row is older than this (minutes)
+    #[serde(default = "default_record_delta_max_age")]
+    pub record_delta_max_age: u32,
+    /// temperature epsilon (K)
+    #[serde(default = "default_record_delta_temperature")]
+    pub record_delta_temperature: f64,
+    /// pressure epsilon (kPa)
+    #[serde(default = "default_record_delta_pressure")]
+    pub record_delta_pressure: f64,
+    /// humidity epsilon (%)
+    #[serde(default = "default_record_delta_humidity")]
+    pub record_delta_humidity: i32,
+    /// wind speed epsilon (m/s)
+    #[serde(default = "default_record_delta_wind_speed")]
+    pub record_delta_wind_speed: f64,
+    /// rain / snow epsilon (mm)
+    #[serde(default = "default_record_delta_precipitation")]
+    pub record_delta_precipitation: f64,
+    /// shorten the recording interval during active weather and relax it
+    /// during stable conditions
+    #[serde(default)]
+    pub adaptive_polling: bool,
+    /// polling interval during precipitation or rapid pressure change
+    /// (seconds)
+    #[serde(default = "default_polling_interval_min")]
+    pub polling_interval_min: u64,
+    /// polling interval during stable conditions (seconds)
+    #[serde(default = "default_polling_interval_max")]
+    pub polling_interval_max: u64,
+    /// pressure change rate considered rapid (kPa per hour, over the last
+    /// 3 hours)
+    #[serde(default = "default_pressure_change_threshold")]
+    pub pressure_change_threshold: f64,
+    /// optional daily api call quota, bounds the minimum polling interval
+    pub api_calls_per_day: Option<u64>,
+    /// optional lightning strike feed, a json array of `{time, lat, lon}`
+    /// objects (e.g. a relay of the blitzortung.org stream)
+    pub lightning_url: Option<StackString>,
+    /// radius around each recorded location within which strikes are
+    /// counted (km)
+    #[serde(default = "default_lightning_radius_km")]
+    pub lightning_radius_km: f64,
+    /// record the uv index of `locations_to_record` hourly, each reading is
+    /// a One Call api request
+    #[serde(default)]
+    pub record_uv_index: bool,
+    /// active tropical cyclone feed (default is the NHC `CurrentStorms.json`)
+    #[serde(default = "default_tropical_url")]
+    pub tropical_url: StackString,
+    /// storms farther than this from a location are not reported (km)
+    #[serde(default = "default_tropical_distance_km")]
+    pub tropical_distance_km: f64,
+    /// data provider named in the attribution header and `meta` block
+    #[serde(default = "default_attribution_provider")]
+    pub attribution_provider: StackString,
+    /// license of the provider data
+    #[serde(default = "default_attribution_license")]
+    pub attribution_license: StackString,
+    /// provider url
+    #[serde(default = "default_attribution_url")]
+    pub attribution_url: StackString,
+    /// weather backend, one of `openweathermap` (default), `open-meteo`,
+    /// `nws` or `metno`
+    #[serde(default)]
+    pub weather_provider: WeatherProviderType,
+    /// providers tried, in order, when `weather_provider` fails or is
+    /// unhealthy, e.g. `open-meteo;metno`
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_providers",
+        default = "Vec::new"
+    )]
+    pub weather_provider_fallbacks: Vec<WeatherProviderType>,
+    /// locations served by the met.no locationforecast api instead of
+    /// `weather_provider`, same format as `locations_to_record`
+    #[serde(deserialize_with = "deserialize_semi_colon_delimited_locations", default = "Vec::new")]
+    pub metno_locations: Vec<WeatherLocation>,
+    /// fall back to met.no when `weather_provider` and
+    /// `weather_provider_fallbacks` fail (e.g. the openweathermap quota is
+    /// exhausted)
+    #[serde(default)]
+    pub metno_fallback: bool,
+    /// met.no and api.weather.gov require an identifying `User-Agent` with
+    /// contact information
+    #[serde(default = "default_metno_user_agent")]
+    pub metno_user_agent: StackString,
+    /// optional altitude passed to met.no (meters above sea level)
+    pub metno_altitude: Option<i32>,
+    /// optional OTLP collector (e.g. `http://localhost:4317`), route, db and
+    /// upstream spans are exported when set
+    pub otlp_endpoint: Option<StackString>,
+    /// service name reported to the OTLP collector
+    #[serde(default = "default_otlp_service_name")]
+    pub otlp_service_name: StackString,
+    /// optional webhook receiving the json of every notification
+    pub notify_webhook_url: Option<StackString>,
+    /// ntfy server (default `https://ntfy.sh`)
+    #[serde(default = "default_ntfy_url")]
+    pub ntfy_url: StackString,
+    /// optional ntfy topic, notifications are published when set
+    pub ntfy_topic: Option<StackString>,
+    /// optional ntfy access token
+    pub ntfy_token: Option<StackString>,
+    /// optional SMTP relay, notifications are emailed when `smtp_host`,
+    /// `notify_email_from` and `notify_email_to` are set
+    pub smtp_host: Option<StackString>,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    pub smtp_username: Option<StackString>,
+    pub smtp_password: Option<StackString>,
+    pub notify_email_from: Option<StackString>,
+    pub notify_email_to: Option<StackString>,
+    /// optional decimal places of floats in history and plot json, full
+    /// precision when unset
+    pub json_precision: Option<u32>,
+    /// per field (or plot metric) overrides of `json_precision`,
+    /// `name:digits;name:digits` e.g. `temperature:1;latitude:4`
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_thresholds",
+        default = "HashMap::new"
+    )]
+    pub json_precision_overrides: HashMap<StackString, u32>,
+    /// locations whose daily summaries are published as static json and html
+    #[serde(deserialize_with = "deserialize_semi_colon_delimited_locations", default = "Vec::new")]
+    pub publish_locations: Vec<WeatherLocation>,
+    /// optional directory or `s3://bucket/prefix`, the daemon publishes every
+    /// `publish_interval` seconds when set
+    pub publish_destination: Option<StackString>,
+    #[serde(default = "default_publish_interval")]
+    pub publish_interval: u64,
+    /// days of history published
+    #[serde(default = "default_publish_days")]
+    pub publish_days: u32,
+    /// units of the published html
+    #[serde(default = "default_publish_units")]
+    pub publish_units: Units,
+    /// optional port of the grpc api (requires the grpc feature)
+    pub grpc_port: Option<u32>,
+    /// optional MaxMind GeoLite2 / GeoIP2 City database, when set
+    /// `/weather/index.html` without a location shows the weather of the
+    /// visitor's location unless the request carries `DNT: 1` or `Sec-GPC: 1`
+    pub geoip_database: Option<PathBuf>,
+    /// requests per minute of each anonymous client ip, 0 (the default)
+    /// disables the limit, behind a reverse proxy it needs `trusted_proxies`
+    /// or every visitor shares the proxy's limit
+    #[serde(default)]
+    pub rate_limit_anonymous: u32,
+    /// requests per minute of each logged in user
+    #[serde(default = "default_rate_limit_authenticated")]
+    pub rate_limit_authenticated: u32,
+    /// addresses of reverse proxies in front of the app, `ip;ip`, only
+    /// requests from these peers have their `X-Forwarded-For` header read
+    /// (for rate limiting and geoip), the client is the rightmost untrusted
+    /// hop
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_ips",
+        default = "Vec::new"
+    )]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// truncate client ips in request logs and traces, ipv4 to /24 and ipv6
+    /// to /48
+    #[serde(default)]
+    pub anonymize_ips: bool,
+    /// store the weather of geoip derived visitor locations in the db, when
+    /// off (the default) visitor locations never reach `weather_data` or
+    /// `weather_location_cache`
+    #[serde(default)]
+    pub geoip_persist: bool,
+    /// include queried locations in log messages
+    #[serde(default = "default_log_locations")]
+    pub log_locations: bool,
+    /// days after which location cache entries that weren't looked up are
+    /// removed, 0 keeps them forever
+    #[serde(default = "default_location_cache_retention_days")]
+    pub location_cache_retention_days: u32,
+    /// remove `key_item_cache` rows of files deleted both locally and in s3
+    #[serde(default = "default_purge_orphaned_key_items")]
+    pub purge_orphaned_key_items: bool,
+    /// seconds between runs of the cache retention job, 0 disables it
+    #[serde(default = "default_maintenance_interval")]
+    pub maintenance_interval: u64,
+    /// concurrent upstream (provider and geocoding) requests, 0 is unlimited
+    #[serde(default = "default_upstream_concurrency")]
+    pub upstream_concurrency: usize,
+    /// upstream requests started per minute, requests over the budget wait
+    /// in a queue, 0 is unlimited
+    #[serde(default = "default_upstream_calls_per_minute")]
+    pub upstream_calls_per_minute: u32,
+    /// seconds a request waits in the upstream queue before recorded data is
+    /// served instead
+    #[serde(default = "default_upstream_queue_timeout")]
+    pub upstream_queue_timeout: u64,
+    /// points in each history plot series, longer series are downsampled
+    /// with LTTB (largest-triangle-three-buckets), 0 keeps every point
+    #[serde(default = "default_history_plot_max_points")]
+    pub history_plot_max_points: usize,
+    /// secret of the signed history export urls, signing is disabled
+    /// without it
+    pub url_signing_key: Option<StackString>,
+    /// serve the `/weather` landing page, locked down deployments can
+    /// disable it to keep the root path a 404
+    #[serde(default = "default_landing_page")]
+    pub landing_page: bool,
+    /// log the listen address and the served pages at startup
+    #[serde(default = "default_startup_banner")]
+    pub startup_banner: bool,
+    /// optional url of the wasm frontend, linked from the landing page
+    pub wasm_app_url: Option<StackString>,
+    /// combine observations of one location recorded by several sources
+    /// (`server`), `off` (the default), `priority` or `weighted`, history
+    /// requested for a specific server still returns its raw rows
+    #[serde(default)]
+    pub fusion_mode: FusionMode,
+    /// per metric overrides of `fusion_mode`, `metric:mode;metric:mode` e.g.
+    /// `temperature:weighted;rain:priority`
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_fusion_modes",
+        default = "HashMap::new"
+    )]
+    pub fusion_metric_modes: HashMap<StackString, FusionMode>,
+    /// sources in priority order with optional weights,
+    /// `server:weight;server:weight` e.g. `station:3;N/A:1`
+    #[serde(
+        deserialize_with = "deserialize_semi_colon_delimited_fusion_sources",
+        default = "Vec::new"
+    )]
+    pub fusion_sources: Vec<FusionSource>,
+    /// seconds of the windows in which observations of different sources
+    /// are combined
+    #[serde(default = "default_fusion_window")]
+    pub fusion_window: u64,
 }
 fn default_host() -> StackString {
     "0.0.0.0".into()
@@ -68,6 +352,12 @@ fn default_api_path() -> StackString {
 fn default_geo_path() -> StackString {
     "geo/1.0/".into()
 }
+fn default_onecall_path() -> StackString {
+    "data/3.0/".into()
+}
+fn default_record_interval() -> u64 {
+    300
+}
 fn default_server() -> StackString {
     "N/A".into()
 }
@@ -82,12 +372,195 @@ fn default_cache_dir() -> PathBuf {
         .expect("No home directory")
         .join(".weather-data-cache")
 }
+fn default_forecast_cache_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("No home directory")
+        .join(".weather-forecast-cache")
+}
 fn default_s3_bucket() -> StackString {
     format_sstr!("weather-data-backup-ddboline")
 }
+fn default_watering_threshold() -> u32 {
+    12
+}
+fn default_record_delta_max_age() -> u32 {
+    60
+}
+fn default_record_delta_temperature() -> f64 {
+    0.5
+}
+fn default_record_delta_pressure() -> f64 {
+    0.1
+}
+fn default_record_delta_humidity() -> i32 {
+    2
+}
+fn default_record_delta_wind_speed() -> f64 {
+    1.0
+}
+fn default_record_delta_precipitation() -> f64 {
+    0.1
+}
+fn default_polling_interval_min() -> u64 {
+    60
+}
+fn default_polling_interval_max() -> u64 {
+    900
+}
+fn default_pressure_change_threshold() -> f64 {
+    0.1
+}
+fn default_lightning_radius_km() -> f64 {
+    50.0
+}
+fn default_tropical_url() -> StackString {
+    "https://www.nhc.noaa.gov/CurrentStorms.json".into()
+}
+fn default_tropical_distance_km() -> f64 {
+    1500.0
+}
+fn default_attribution_provider() -> StackString {
+    "OpenWeatherMap".into()
+}
+fn default_attribution_license() -> StackString {
+    "CC BY-SA 4.0".into()
+}
+fn default_attribution_url() -> StackString {
+    "https://openweathermap.org/".into()
+}
+fn default_otlp_service_name() -> StackString {
+    "weather-api-rust".into()
+}
+fn default_ntfy_url() -> StackString {
+    "https://ntfy.sh".into()
+}
+fn default_smtp_port() -> u16 {
+    587
+}
+fn default_publish_interval() -> u64 {
+    3600
+}
+fn default_publish_days() -> u32 {
+    30
+}
+fn default_publish_units() -> Units {
+    Units::Imperial
+}
+fn default_rate_limit_authenticated() -> u32 {
+    600
+}
+fn default_log_locations() -> bool {
+    true
+}
+fn default_location_cache_retention_days() -> u32 {
+    365
+}
+fn default_purge_orphaned_key_items() -> bool {
+    true
+}
+fn default_maintenance_interval() -> u64 {
+    86400
+}
+fn default_upstream_concurrency() -> usize {
+    8
+}
+fn default_upstream_calls_per_minute() -> u32 {
+    60
+}
+fn default_upstream_queue_timeout() -> u64 {
+    10
+}
+fn default_history_plot_max_points() -> usize {
+    2000
+}
+fn default_landing_page() -> bool {
+    true
+}
+fn default_startup_banner() -> bool {
+    true
+}
+fn default_fusion_window() -> u64 {
+    900
+}
+fn default_metno_user_agent() -> StackString {
+    format_sstr!(
+        "weather_api_rust/{} github.com/ddboline/weather_api_rust",
+        env!("CARGO_PKG_VERSION")
+    )
+}
 
-/// Configuration struct
+/// Provider account of a location, unset fields fall back to `api_key` and
+/// `api_endpoint`
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ProviderOverride {
+    pub api_key: Option<StackString>,
+    pub api_endpoint: Option<StackString>,
+}
+
+impl ProviderOverride {
+    /// `api` using the key and endpoint of the override
+    #[must_use]
+    pub fn apply(&self, config: &Config, api: &WeatherApi) -> WeatherApi {
+        match (&self.api_key, &self.api_endpoint) {
+            (api_key, Some(api_endpoint)) => WeatherApi::new(
+                api_key.as_deref().unwrap_or(&config.api_key),
+                api_endpoint,
+                &config.api_path,
+                &config.geo_path,
+            ),
+            (Some(api_key), None) => api.clone().with_key(api_key),
+            (None, None) => api.clone(),
+        }
+    }
+}
+
+/// Location of `locations_to_record` along with its polling interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedLocation {
+    /// location as configured, e.g. `10001` or `Paris`
+    pub name: StackString,
+    pub location: WeatherLocation,
+    /// seconds between polls, `record_interval` when unset
+    pub interval: Option<u64>,
+}
+
+impl RecordedLocation {
+    #[must_use]
+    pub fn get_interval(&self, config: &Config) -> u64 {
+        self.interval.unwrap_or(config.record_interval)
+    }
+}
+
+/// Interval in seconds, minutes or hours (`90`, `90s`, `5m`, `1h`)
+#[must_use]
+pub fn parse_interval(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (value, unit) = match s.char_indices().last()? {
+        (i, 's') => (&s[..i], 1),
+        (i, 'm') => (&s[..i], 60),
+        (i, 'h') => (&s[..i], 3600),
+        _ => (s, 1),
+    };
+    let value: u64 = value.trim().parse().ok()?;
+    (value > 0).then_some(value * unit)
+}
+
+/// `location` or `location@interval`
+fn parse_recorded_location(entry: &str) -> Option<RecordedLocation> {
+    let (name, interval) = match entry.rsplit_once('@') {
+        Some((name, interval)) => (name, Some(parse_interval(interval)?)),
+        None => (entry, None),
+    };
+    let name = name.trim();
+    Some(RecordedLocation {
+        name: name.into(),
+        location: get_parameters(name),
+        interval,
+    })
+}
+
+/// Configuration struct
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Config(Arc<ConfigInner>);
 
 impl Config {
@@ -124,18 +597,23 @@ impl Config {
     /// # Ok(())
     /// # }
     /// ```
+    /// Env file read by `init_config`, `config_path` or `config.env` when it
+    /// exists, otherwise `config.env` of the user config directory
+    #[must_use]
+    pub fn get_config_file(config_path: Option<&Path>) -> PathBuf {
+        let fname = config_path.unwrap_or_else(|| Path::new("config.env"));
+        if fname.exists() {
+            fname.to_path_buf()
+        } else {
+            let config_dir = dirs::config_dir().unwrap_or_else(|| "./".into());
+            config_dir.join("weather_api_rust").join("config.env")
+        }
+    }
+
     /// # Errors
     /// Return error if deserializing environment variables fails
     pub fn init_config(config_path: Option<&Path>) -> Result<Self, Error> {
-        let fname = config_path.unwrap_or_else(|| Path::new("config.env"));
-        let config_dir = dirs::config_dir().unwrap_or_else(|| "./".into());
-        let default_fname = config_dir.join("weather_api_rust").join("config.env");
-
-        let env_file = if fname.exists() {
-            fname
-        } else {
-            &default_fname
-        };
+        let env_file = &Self::get_config_file(config_path);
 
         dotenvy::dotenv().ok();
 
@@ -168,11 +646,152 @@ where
         .map_err(Into::into)
 }
 
+fn deserialize_semi_colon_delimited_recorded_locations<'de, D>(
+    deserializer: D,
+) -> Result<Vec<RecordedLocation>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .map(|entry| {
+            parse_recorded_location(entry)
+                .ok_or_else(|| de::Error::custom(format_sstr!("invalid recorded location {entry}")))
+        })
+        .collect()
+}
+
+fn deserialize_semi_colon_delimited_thresholds<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<StackString, u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, threshold) = entry
+                .rsplit_once(':')
+                .ok_or_else(|| de::Error::custom(format_sstr!("invalid threshold {entry}")))?;
+            let threshold = threshold.trim().parse().map_err(de::Error::custom)?;
+            Ok((name.trim().into(), threshold))
+        })
+        .collect()
+}
+
+fn deserialize_semi_colon_delimited_fusion_modes<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<StackString, FusionMode>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (metric, mode) = entry
+                .split_once(':')
+                .ok_or_else(|| de::Error::custom(format_sstr!("invalid fusion mode {entry}")))?;
+            let metric = metric.trim();
+            if !FUSION_METRICS.contains(&metric) {
+                return Err(de::Error::custom(format_sstr!(
+                    "invalid fusion metric {metric}"
+                )));
+            }
+            let mode = FusionMode::deserialize(mode.trim().into_deserializer())?;
+            Ok((metric.into(), mode))
+        })
+        .collect()
+}
+
+fn deserialize_semi_colon_delimited_fusion_sources<'de, D>(
+    deserializer: D,
+) -> Result<Vec<FusionSource>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            parse_fusion_source(entry)
+                .ok_or_else(|| de::Error::custom(format_sstr!("invalid fusion source {entry}")))
+        })
+        .collect()
+}
+
+fn deserialize_semi_colon_delimited_ips<'de, D>(deserializer: D) -> Result<Vec<IpAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| entry.parse().map_err(de::Error::custom))
+        .collect()
+}
+
+fn parse_provider_override(entry: &str) -> Option<(StackString, ProviderOverride)> {
+    let (name, value) = entry.split_once(':')?;
+    let (api_key, api_endpoint) = match value.split_once('@') {
+        Some((api_key, api_endpoint)) => (api_key, Some(api_endpoint)),
+        None => (value, None),
+    };
+    let non_empty = |s: &str| {
+        let s = s.trim();
+        (!s.is_empty()).then(|| s.into())
+    };
+    let name = format_sstr!("{}", get_parameters(name.trim()));
+    let provider_override = ProviderOverride {
+        api_key: non_empty(api_key),
+        api_endpoint: api_endpoint.and_then(non_empty),
+    };
+    Some((name, provider_override))
+}
+
+fn deserialize_semi_colon_delimited_overrides<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<StackString, ProviderOverride>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            parse_provider_override(entry)
+                .ok_or_else(|| de::Error::custom(format_sstr!("invalid provider override {entry}")))
+        })
+        .collect()
+}
+
+fn deserialize_semi_colon_delimited_providers<'de, D>(
+    deserializer: D,
+) -> Result<Vec<WeatherProviderType>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| WeatherProviderType::deserialize(entry.into_deserializer()))
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use anyhow::Error;
+    use stack_string::format_sstr;
 
-    use crate::config::{default_api_endpoint, Config};
+    use weather_api_types::get_parameters;
+
+    use crate::config::{
+        default_api_endpoint, parse_interval, parse_provider_override, parse_recorded_location,
+        Config, ProviderOverride,
+    };
 
     #[test]
     fn test_config() -> Result<(), Error> {
@@ -188,4 +807,58 @@ mod test {
         assert_eq!(&default_api_endpoint(), "api.openweathermap.org");
         Ok(())
     }
+
+    #[test]
+    fn test_parse_provider_override() {
+        let (name, provider_override) = parse_provider_override("11106:work_key").unwrap();
+        assert_eq!(name, format_sstr!("{}", get_parameters("11106")));
+        assert_eq!(
+            provider_override,
+            ProviderOverride {
+                api_key: Some("work_key".into()),
+                api_endpoint: None,
+            }
+        );
+
+        let (_, provider_override) =
+            parse_provider_override(" Astoria : key@localhost:8080").unwrap();
+        assert_eq!(provider_override.api_key.as_deref(), Some("key"));
+        assert_eq!(
+            provider_override.api_endpoint.as_deref(),
+            Some("localhost:8080")
+        );
+
+        let (_, provider_override) = parse_provider_override("11106:@api.example.com").unwrap();
+        assert_eq!(provider_override.api_key, None);
+        assert_eq!(
+            provider_override.api_endpoint.as_deref(),
+            Some("api.example.com")
+        );
+
+        assert!(parse_provider_override("11106").is_none());
+    }
+
+    #[test]
+    fn test_parse_recorded_location() {
+        assert_eq!(parse_interval("90"), Some(90));
+        assert_eq!(parse_interval("90s"), Some(90));
+        assert_eq!(parse_interval(" 5m"), Some(300));
+        assert_eq!(parse_interval("1h"), Some(3600));
+        assert_eq!(parse_interval("0m"), None);
+        assert_eq!(parse_interval("5d"), None);
+        assert_eq!(parse_interval(""), None);
+
+        let recorded = parse_recorded_location("10001@5m").unwrap();
+        assert_eq!(recorded.location, get_parameters("10001"));
+        assert_eq!(recorded.interval, Some(300));
+
+        let recorded = parse_recorded_location(" Paris ").unwrap();
+        assert_eq!(recorded.name, "Paris");
+        assert_eq!(recorded.location, get_parameters("Paris"));
+        assert_eq!(recorded.interval, None);
+        let config = Config::default();
+        assert_eq!(recorded.get_interval(&config), config.record_interval);
+
+        assert!(parse_recorded_location("Paris@soon").is_none());
+    }
 }
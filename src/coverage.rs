@@ -0,0 +1,191 @@
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::collections::BTreeMap;
+use time::{Date, Duration, Time};
+
+use rweb_helper::DateType;
+use weather_api_types::plot::PlotPoint;
+
+use crate::{config::Config, model::WeatherDataDB, PlotDataWrapper};
+
+/// Interval of the fixed rate recorder (`run_app` records every location
+/// every 5 minutes)
+const RECORDER_INTERVAL: u64 = 300;
+
+/// Seconds between recordings the daemon aims for, the slowest polling
+/// interval when `adaptive_polling` is set
+#[must_use]
+pub fn expected_interval(config: &Config) -> u64 {
+    if config.adaptive_polling {
+        config.polling_interval_max.max(1)
+    } else {
+        RECORDER_INTERVAL
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Schema)]
+#[schema(component = "DailyCoverage")]
+pub struct DailyCoverage {
+    #[schema(description = "Date (UTC)")]
+    pub date: DateType,
+    #[schema(description = "Observations")]
+    pub observations: usize,
+    #[schema(description = "Completeness (%)")]
+    pub completeness: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "HistoryCoverage")]
+pub struct HistoryCoverage {
+    #[schema(description = "Location Name")]
+    pub name: StackString,
+    #[schema(description = "First Date (UTC)")]
+    pub start_date: DateType,
+    #[schema(description = "Last Date (UTC)")]
+    pub end_date: DateType,
+    #[schema(description = "Expected Interval Between Observations (seconds)")]
+    pub expected_interval: u64,
+    #[schema(description = "Observations")]
+    pub observations: usize,
+    #[schema(description = "Average Interval Between Observations (seconds)")]
+    pub average_interval: Option<f64>,
+    #[schema(description = "Longest Interval Between Observations (seconds)")]
+    pub longest_gap: Option<i64>,
+    #[schema(description = "Completeness (%)")]
+    pub completeness: f64,
+    #[schema(description = "Observations per Day")]
+    pub days: Vec<DailyCoverage>,
+    #[schema(description = "Daily Completeness Plot")]
+    pub plot: Option<PlotDataWrapper>,
+}
+
+impl HistoryCoverage {
+    /// Completeness of each day as a plot series
+    #[must_use]
+    pub fn completeness_plot(&self) -> Vec<PlotPoint> {
+        self.days
+            .iter()
+            .map(|day| PlotPoint {
+                datetime: Date::from(day.date).with_time(Time::MIDNIGHT).assume_utc(),
+                value: day.completeness,
+            })
+            .collect()
+    }
+}
+
+/// Percentage of `expected` observations recorded, capped at 100
+#[must_use]
+pub fn completeness(observations: usize, expected: f64) -> f64 {
+    if expected > 0.0 {
+        (observations as f64 / expected * 100.0).min(100.0)
+    } else {
+        0.0
+    }
+}
+
+/// Recorded observations per UTC day between `start_date` and `end_date`
+/// (inclusive) compared with one observation every `expected_interval`
+/// seconds, days without observations are included
+#[must_use]
+pub fn get_history_coverage(
+    name: &str,
+    history: &[WeatherDataDB],
+    start_date: Date,
+    end_date: Date,
+    expected_interval: u64,
+) -> HistoryCoverage {
+    let mut days: BTreeMap<Date, usize> = BTreeMap::new();
+    let mut date = start_date;
+    while date <= end_date {
+        days.insert(date, 0);
+        date += Duration::days(1);
+    }
+    let mut timestamps: Vec<i64> = Vec::with_capacity(history.len());
+    for row in history {
+        if let Some(count) = days.get_mut(&row.created_at.date()) {
+            *count += 1;
+            timestamps.push(row.created_at.unix_timestamp());
+        }
+    }
+    timestamps.sort_unstable();
+    let gaps: Vec<i64> = timestamps.windows(2).map(|w| w[1] - w[0]).collect();
+    let average_interval = if gaps.is_empty() {
+        None
+    } else {
+        Some(gaps.iter().sum::<i64>() as f64 / gaps.len() as f64)
+    };
+    let longest_gap = gaps.iter().max().copied();
+
+    let expected_per_day = 86400.0 / expected_interval.max(1) as f64;
+    let observations = timestamps.len();
+    let completeness_total = completeness(observations, expected_per_day * days.len() as f64);
+    let days = days
+        .into_iter()
+        .map(|(date, observations)| DailyCoverage {
+            date: date.into(),
+            observations,
+            completeness: completeness(observations, expected_per_day),
+        })
+        .collect();
+    HistoryCoverage {
+        name: name.into(),
+        start_date: start_date.into(),
+        end_date: end_date.into(),
+        expected_interval,
+        observations,
+        average_interval,
+        longest_gap,
+        completeness: completeness_total,
+        days,
+        plot: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{
+        macros::{date, datetime},
+        Duration, OffsetDateTime,
+    };
+
+    use crate::{coverage::get_history_coverage, model::WeatherDataDB};
+
+    fn row(created_at: OffsetDateTime) -> WeatherDataDB {
+        WeatherDataDB {
+            timezone: -4 * 3600,
+            ..WeatherDataDB::test_row(created_at)
+        }
+    }
+
+    #[test]
+    fn test_history_coverage() {
+        let start = datetime!(2024-06-01 00:00 UTC);
+        // every 10 minutes on the first day, every 20 minutes on the second,
+        // nothing on the third
+        let history: Vec<WeatherDataDB> = (0..144)
+            .map(|i| start + Duration::minutes(i * 10))
+            .chain((0..72).map(|i| start + Duration::days(1) + Duration::minutes(i * 20)))
+            .chain([start - Duration::minutes(10)])
+            .map(row)
+            .collect();
+        let coverage = get_history_coverage(
+            "test",
+            &history,
+            date!(2024 - 06 - 01),
+            date!(2024 - 06 - 03),
+            600,
+        );
+        assert_eq!(coverage.observations, 216);
+        assert_eq!(coverage.days.len(), 3);
+        assert_eq!(coverage.days[0].observations, 144);
+        assert!((coverage.days[0].completeness - 100.0).abs() < 1e-9);
+        assert!((coverage.days[1].completeness - 50.0).abs() < 1e-9);
+        assert_eq!(coverage.days[2].observations, 0);
+        assert!((coverage.completeness - 50.0).abs() < 1e-9);
+        assert_eq!(coverage.longest_gap, Some(1200));
+        let average = coverage.average_interval.unwrap();
+        assert!(average > 600.0 && average < 1200.0);
+        assert_eq!(coverage.completeness_plot().len(), 3);
+    }
+}
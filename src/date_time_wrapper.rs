@@ -1,8 +1,9 @@
+use anyhow::Error;
 use bytes::BytesMut;
 use derive_more::{Deref, DerefMut, From, Into};
 use postgres_types::{FromSql, IsNull, ToSql, Type};
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{fmt, str::FromStr};
 use time::OffsetDateTime;
 
 #[derive(
@@ -50,6 +51,13 @@ impl fmt::Display for DateTimeWrapper {
     }
 }
 
+impl FromStr for DateTimeWrapper {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        iso8601::convert_str_to_datetime(s).map(Self)
+    }
+}
+
 mod iso8601 {
     use anyhow::Error;
     use serde::{de, Deserialize, Deserializer, Serializer};
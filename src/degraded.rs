@@ -0,0 +1,136 @@
+use log::warn;
+use rweb::{
+    http::header::HeaderValue,
+    openapi::{ComponentDescriptor, ComponentOrInlineSchema, Entity, ResponseEntity, Responses},
+    reply, Reply,
+};
+use std::{borrow::Cow, fmt};
+
+/// Response header listing the parts of a page that couldn't be loaded, e.g.
+/// `forecast, precipitation`, absent when the page is complete
+pub const DEGRADED_HEADER: &str = "x-weather-degraded";
+
+/// Parts of a page that failed to load while the rest is still rendered
+#[derive(Debug, Default, Clone)]
+pub struct Degraded {
+    parts: Vec<&'static str>,
+    warnings: Vec<String>,
+}
+
+impl Degraded {
+    /// Record a failed part, `warning` is shown in the banner of the page
+    pub fn add(&mut self, part: &'static str, warning: &str, error: &impl fmt::Display) {
+        warn!("{part} unavailable: {error}");
+        self.parts.push(part);
+        self.warnings.push(warning.into());
+    }
+
+    /// Value of a part, `None` after recording the failure
+    pub fn check<T, E: fmt::Display>(
+        &mut self,
+        part: &'static str,
+        warning: &str,
+        result: Result<T, E>,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.add(part, warning, &e);
+                None
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn is_degraded(&self) -> bool {
+        !self.parts.is_empty()
+    }
+
+    #[must_use]
+    pub fn warnings(&self) -> Vec<String> {
+        self.warnings.clone()
+    }
+
+    /// Value of `DEGRADED_HEADER`
+    #[must_use]
+    pub fn header_value(&self) -> Option<String> {
+        self.is_degraded().then(|| self.parts.join(", "))
+    }
+}
+
+/// Response setting `DEGRADED_HEADER` when parts are missing, documented as
+/// the wrapped response
+pub struct DegradedResponse<T> {
+    inner: T,
+    degraded: Option<String>,
+}
+
+impl<T> DegradedResponse<T> {
+    pub fn new(inner: T, degraded: &Degraded) -> Self {
+        Self {
+            inner,
+            degraded: degraded.header_value(),
+        }
+    }
+}
+
+impl<T: Reply> Reply for DegradedResponse<T> {
+    fn into_response(self) -> reply::Response {
+        let mut response = self.inner.into_response();
+        if let Some(value) = self.degraded.and_then(|d| HeaderValue::from_str(&d).ok()) {
+            response.headers_mut().insert(DEGRADED_HEADER, value);
+        }
+        response
+    }
+}
+
+impl<T: Entity> Entity for DegradedResponse<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    fn describe(comp_d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(comp_d)
+    }
+}
+
+impl<T: ResponseEntity> ResponseEntity for DegradedResponse<T> {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        T::describe_responses(comp_d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{format_err, Error};
+    use rweb::Reply;
+
+    use crate::degraded::{Degraded, DegradedResponse, DEGRADED_HEADER};
+
+    #[test]
+    fn test_degraded() {
+        let mut degraded = Degraded::default();
+        assert_eq!(degraded.check::<_, Error>("weather", "", Ok(1)), Some(1));
+        assert!(!degraded.is_degraded());
+        assert_eq!(degraded.header_value(), None);
+        let response = DegradedResponse::new("complete", &degraded).into_response();
+        assert!(response.headers().get(DEGRADED_HEADER).is_none());
+
+        let forecast: Result<(), _> = Err(format_err!("timeout"));
+        assert_eq!(
+            degraded.check("forecast", "Forecast is unavailable", forecast),
+            None
+        );
+        degraded.add("precipitation", "Precipitation is unavailable", &"db");
+        assert_eq!(
+            degraded.header_value().as_deref(),
+            Some("forecast, precipitation")
+        );
+        assert_eq!(degraded.warnings().len(), 2);
+        let response = DegradedResponse::new("partial", &degraded).into_response();
+        assert_eq!(
+            response.headers().get(DEGRADED_HEADER).unwrap(),
+            "forecast, precipitation"
+        );
+    }
+}
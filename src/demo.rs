@@ -0,0 +1,308 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use stack_string::{format_sstr, StackString};
+use std::f64::consts::PI;
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+use uuid::Builder;
+
+use crate::{condition::condition_code, model::WeatherDataDB};
+
+/// Server recorded for generated rows
+pub const DEMO_SERVER: &str = "demo";
+const KELVIN_OFFSET: f64 = 273.15;
+
+/// Location and rough climate of a demo location
+struct DemoLocation {
+    name: &'static str,
+    latitude: f64,
+    longitude: f64,
+    country: &'static str,
+    /// hours from utc
+    timezone: i32,
+    /// annual mean temperature (C)
+    mean: f64,
+    /// half the summer / winter difference (C)
+    seasonal: f64,
+    /// day / night difference (C)
+    diurnal: f64,
+    /// chance of precipitation per hour of low pressure
+    wet: f64,
+}
+
+const DEMO_LOCATIONS: [DemoLocation; 10] = [
+    DemoLocation {
+        name: "Minneapolis",
+        latitude: 44.98,
+        longitude: -93.27,
+        country: "US",
+        timezone: -6,
+        mean: 7.5,
+        seasonal: 16.0,
+        diurnal: 10.0,
+        wet: 0.25,
+    },
+    DemoLocation {
+        name: "Phoenix",
+        latitude: 33.45,
+        longitude: -112.07,
+        country: "US",
+        timezone: -7,
+        mean: 24.0,
+        seasonal: 10.0,
+        diurnal: 14.0,
+        wet: 0.05,
+    },
+    DemoLocation {
+        name: "Seattle",
+        latitude: 47.61,
+        longitude: -122.33,
+        country: "US",
+        timezone: -8,
+        mean: 11.5,
+        seasonal: 7.0,
+        diurnal: 7.0,
+        wet: 0.45,
+    },
+    DemoLocation {
+        name: "Miami",
+        latitude: 25.76,
+        longitude: -80.19,
+        country: "US",
+        timezone: -5,
+        mean: 25.0,
+        seasonal: 4.0,
+        diurnal: 7.0,
+        wet: 0.35,
+    },
+    DemoLocation {
+        name: "Denver",
+        latitude: 39.74,
+        longitude: -104.99,
+        country: "US",
+        timezone: -7,
+        mean: 10.5,
+        seasonal: 12.0,
+        diurnal: 15.0,
+        wet: 0.15,
+    },
+    DemoLocation {
+        name: "London",
+        latitude: 51.51,
+        longitude: -0.13,
+        country: "GB",
+        timezone: 0,
+        mean: 11.5,
+        seasonal: 7.0,
+        diurnal: 7.0,
+        wet: 0.4,
+    },
+    DemoLocation {
+        name: "Oslo",
+        latitude: 59.91,
+        longitude: 10.75,
+        country: "NO",
+        timezone: 1,
+        mean: 6.5,
+        seasonal: 11.0,
+        diurnal: 6.0,
+        wet: 0.35,
+    },
+    DemoLocation {
+        name: "Sydney",
+        latitude: -33.87,
+        longitude: 151.21,
+        country: "AU",
+        timezone: 10,
+        mean: 18.5,
+        seasonal: 5.0,
+        diurnal: 8.0,
+        wet: 0.3,
+    },
+    DemoLocation {
+        name: "Singapore",
+        latitude: 1.35,
+        longitude: 103.82,
+        country: "SG",
+        timezone: 8,
+        mean: 27.5,
+        seasonal: 1.0,
+        diurnal: 6.0,
+        wet: 0.5,
+    },
+    DemoLocation {
+        name: "Reykjavik",
+        latitude: 64.15,
+        longitude: -21.94,
+        country: "IS",
+        timezone: 0,
+        mean: 5.0,
+        seasonal: 6.0,
+        diurnal: 4.0,
+        wet: 0.45,
+    },
+];
+
+/// Standard normal deviate (sum of 12 uniforms)
+fn gaussian(rng: &mut StdRng) -> f64 {
+    (0..12).map(|_| rng.gen::<f64>()).sum::<f64>() - 6.0
+}
+
+/// Utc sunrise and sunset of `date` from the solar declination, clamped to
+/// noon during polar day / night
+fn sunrise_sunset(date: Date, latitude: f64, longitude: f64) -> (OffsetDateTime, OffsetDateTime) {
+    let day_of_year = f64::from(date.ordinal());
+    let declination = (23.44 * (2.0 * PI * (284.0 + day_of_year) / 365.0).sin()).to_radians();
+    let cos_hour_angle = -latitude.to_radians().tan() * declination.tan();
+    let half_day = cos_hour_angle.clamp(-1.0, 1.0).acos() / PI * 12.0;
+    let solar_noon = 12.0 - longitude / 15.0;
+    let midnight = PrimitiveDateTime::new(date, Time::MIDNIGHT).assume_utc();
+    let at = |hours: f64| midnight + Duration::seconds((hours * 3600.0) as i64);
+    (at(solar_noon - half_day), at(solar_noon + half_day))
+}
+
+/// Hourly synthetic history of `n_locations` demo locations over the `days`
+/// days before `end_date`: seasonal and diurnal temperature cycles plus
+/// persistent (AR(1)) temperature and pressure anomalies, precipitation
+/// during low pressure.  The same seed always produces the same rows.
+#[must_use]
+pub fn generate_demo_history(
+    n_locations: usize,
+    days: u32,
+    end_date: Date,
+    seed: u64,
+) -> Vec<WeatherDataDB> {
+    let start =
+        PrimitiveDateTime::new(end_date - Duration::days(days.into()), Time::MIDNIGHT).assume_utc();
+    let hours = i64::from(days) * 24;
+    let mut rows = Vec::with_capacity(n_locations * hours as usize);
+    for idx in 0..n_locations {
+        let location = &DEMO_LOCATIONS[idx % DEMO_LOCATIONS.len()];
+        let copy = idx / DEMO_LOCATIONS.len();
+        let location_name: StackString = if copy == 0 {
+            format_sstr!("{} (demo)", location.name)
+        } else {
+            format_sstr!("{} {} (demo)", location.name, copy + 1)
+        };
+        let latitude = location.latitude + copy as f64 * 0.1;
+        let longitude = location.longitude + copy as f64 * 0.1;
+        let hemisphere = if latitude < 0.0 { -1.0 } else { 1.0 };
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(idx as u64));
+        let mut temperature_anomaly = 0.0;
+        let mut pressure_anomaly = 0.0;
+
+        for hour in 0..hours {
+            let t = start + Duration::hours(hour);
+            let local_hour = f64::from((i32::from(t.hour()) + location.timezone).rem_euclid(24));
+            let season = -(2.0 * PI * (f64::from(t.ordinal()) - 15.0) / 365.25).cos();
+            let diurnal = (2.0 * PI * (local_hour - 15.0) / 24.0).cos();
+
+            temperature_anomaly = 0.95 * temperature_anomaly + 0.6 * gaussian(&mut rng);
+            pressure_anomaly = 0.97 * pressure_anomaly + 0.08 * gaussian(&mut rng);
+            let temperature = KELVIN_OFFSET
+                + location.mean
+                + hemisphere * location.seasonal * season
+                + 0.5 * location.diurnal * diurnal
+                + temperature_anomaly;
+
+            let precipitating = pressure_anomaly < -0.3 && rng.gen::<f64>() < location.wet;
+            let amount = if precipitating {
+                -(1.0 - rng.gen::<f64>()).ln()
+            } else {
+                0.0
+            };
+            let is_snow = temperature < KELVIN_OFFSET;
+            let humidity = (65.0 - 15.0 * diurnal
+                + if precipitating { 25.0 } else { 0.0 }
+                + 5.0 * gaussian(&mut rng))
+            .clamp(10.0, 100.0);
+            let condition = match (precipitating, is_snow) {
+                (true, true) => "Snow light snow ",
+                (true, false) => "Rain light rain ",
+                (false, _) if humidity > 80.0 => "Clouds overcast clouds ",
+                (false, _) => "Clear clear sky ",
+            };
+            let wind_speed = (3.0 - 3.0 * pressure_anomaly + gaussian(&mut rng)).max(0.0);
+            let (sunrise, sunset) = sunrise_sunset(t.date(), latitude, longitude);
+
+            let mut row = WeatherDataDB {
+                id: Builder::from_random_bytes(rng.gen()).into_uuid(),
+                dt: t.unix_timestamp() as i32,
+                created_at: t.into(),
+                location_name: location_name.clone(),
+                latitude,
+                longitude,
+                condition: condition.into(),
+                condition_code: condition_code(condition),
+                temperature,
+                temperature_minimum: temperature - 0.5,
+                temperature_maximum: temperature + 0.5,
+                pressure: 101.3 + pressure_anomaly,
+                humidity: humidity.round() as i32,
+                visibility: Some(if precipitating { 5000.0 } else { 10000.0 }),
+                rain: (precipitating && !is_snow).then_some(amount),
+                snow: (precipitating && is_snow).then_some(amount),
+                wind_speed,
+                wind_direction: Some(rng.gen_range(0.0..360.0)),
+                country: location.country.into(),
+                sunrise: sunrise.into(),
+                sunset: sunset.into(),
+                timezone: location.timezone * 3600,
+                server: DEMO_SERVER.into(),
+                dew_point: None,
+                heat_index: None,
+                wind_chill: None,
+                backfilled: false,
+                inserted_at: None,
+            };
+            row.set_derived_metrics();
+            rows.push(row);
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::date;
+
+    use crate::demo::{generate_demo_history, DEMO_SERVER};
+
+    #[test]
+    fn test_generate_demo_history() {
+        let rows = generate_demo_history(12, 365, date!(2024 - 06 - 01), 42);
+        assert_eq!(rows.len(), 12 * 365 * 24);
+        assert!(rows.iter().all(|row| row.server == DEMO_SERVER));
+        assert_eq!(rows[0].location_name, "Minneapolis (demo)");
+        assert_eq!(rows[11 * 365 * 24].location_name, "Phoenix 2 (demo)");
+
+        let again = generate_demo_history(12, 365, date!(2024 - 06 - 01), 42);
+        assert!(rows
+            .iter()
+            .zip(&again)
+            .all(|(a, b)| a.id == b.id && a.temperature == b.temperature && a.rain == b.rain));
+        let other = generate_demo_history(1, 365, date!(2024 - 06 - 01), 43);
+        assert_ne!(rows[0].temperature, other[0].temperature);
+
+        // summer is warmer than winter in Minneapolis, the other way around
+        // in Sydney
+        let mean = |name: &str, month: time::Month| {
+            let temps: Vec<_> = rows
+                .iter()
+                .filter(|row| row.location_name == name && row.created_at.month() == month)
+                .map(|row| row.temperature)
+                .collect();
+            temps.iter().sum::<f64>() / temps.len() as f64
+        };
+        assert!(
+            mean("Minneapolis (demo)", time::Month::July)
+                > mean("Minneapolis (demo)", time::Month::January) + 20.0
+        );
+        assert!(
+            mean("Sydney (demo)", time::Month::January) > mean("Sydney (demo)", time::Month::July)
+        );
+        assert!(rows
+            .iter()
+            .all(|row| (10..=100).contains(&row.humidity) && row.wind_speed >= 0.0));
+        assert!(rows.iter().any(|row| row.rain.is_some()));
+        assert!(rows.iter().any(|row| row.snow.is_some()));
+    }
+}
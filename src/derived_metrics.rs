@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+const ZERO_CELSIUS: f64 = 273.15;
+const MPS_TO_MPH: f64 = 2.236_936;
+
+fn kelvin_to_fahrenheit(temperature: f64) -> f64 {
+    (temperature - ZERO_CELSIUS) * 9.0 / 5.0 + 32.0
+}
+
+fn fahrenheit_to_kelvin(temperature: f64) -> f64 {
+    (temperature - 32.0) * 5.0 / 9.0 + ZERO_CELSIUS
+}
+
+/// Dew point (K) from the Magnus formula, `None` when `humidity` (percent)
+/// is not positive
+#[must_use]
+pub fn dew_point(temperature: f64, humidity: i32) -> Option<f64> {
+    const A: f64 = 17.625;
+    const B: f64 = 243.04;
+    if humidity <= 0 {
+        return None;
+    }
+    let celsius = temperature - ZERO_CELSIUS;
+    let gamma = (f64::from(humidity.min(100)) / 100.0).ln() + A * celsius / (B + celsius);
+    Some(B * gamma / (A - gamma) + ZERO_CELSIUS)
+}
+
+/// Heat index (K) from the NWS Rothfusz regression, only defined from 80°F
+#[must_use]
+pub fn heat_index(temperature: f64, humidity: i32) -> Option<f64> {
+    let t = kelvin_to_fahrenheit(temperature);
+    if t < 80.0 {
+        return None;
+    }
+    let rh = f64::from(humidity.clamp(0, 100));
+    let mut index = -42.379 + 2.049_015_23 * t + 10.143_331_27 * rh
+        - 0.224_755_41 * t * rh
+        - 0.006_837_83 * t * t
+        - 0.054_817_17 * rh * rh
+        + 0.001_228_74 * t * t * rh
+        + 0.000_852_82 * t * rh * rh
+        - 0.000_001_99 * t * t * rh * rh;
+    if rh < 13.0 && t <= 112.0 {
+        index -= (13.0 - rh) / 4.0 * ((17.0 - (t - 95.0).abs()) / 17.0).sqrt();
+    } else if rh > 85.0 && t <= 87.0 {
+        index += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+    }
+    Some(fahrenheit_to_kelvin(index))
+}
+
+/// Wind chill (K) from the NWS formula, only defined at or below 50°F with
+/// wind above 3 mph
+#[must_use]
+pub fn wind_chill(temperature: f64, wind_speed: f64) -> Option<f64> {
+    let t = kelvin_to_fahrenheit(temperature);
+    let v = wind_speed * MPS_TO_MPH;
+    if t > 50.0 || v <= 3.0 {
+        return None;
+    }
+    let v = v.powf(0.16);
+    let chill = 35.74 + 0.6215 * t - 35.75 * v + 0.4275 * t * v;
+    Some(fahrenheit_to_kelvin(chill))
+}
+
+/// Metrics derived from a stored observation, all temperatures in kelvin
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct DerivedMetrics {
+    pub dew_point: Option<f64>,
+    pub heat_index: Option<f64>,
+    pub wind_chill: Option<f64>,
+}
+
+impl DerivedMetrics {
+    /// `temperature` in kelvin, `humidity` in percent and `wind_speed` in m/s
+    #[must_use]
+    pub fn new(temperature: f64, humidity: i32, wind_speed: f64) -> Self {
+        Self {
+            dew_point: dew_point(temperature, humidity),
+            heat_index: heat_index(temperature, humidity),
+            wind_chill: wind_chill(temperature, wind_speed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::derived_metrics::{
+        dew_point, fahrenheit_to_kelvin, heat_index, kelvin_to_fahrenheit, wind_chill,
+        DerivedMetrics,
+    };
+
+    #[test]
+    fn test_derived_metrics() {
+        let dp = dew_point(293.15, 50).unwrap() - 273.15;
+        assert!((dp - 9.26).abs() < 0.05, "{dp}");
+        assert!((dew_point(293.15, 100).unwrap() - 293.15).abs() < 1e-9);
+        assert_eq!(dew_point(293.15, 0), None);
+
+        let hi = kelvin_to_fahrenheit(heat_index(fahrenheit_to_kelvin(90.0), 60).unwrap());
+        assert!((hi - 100.0).abs() < 1.0, "{hi}");
+        assert_eq!(heat_index(fahrenheit_to_kelvin(70.0), 60), None);
+
+        let wc =
+            kelvin_to_fahrenheit(wind_chill(fahrenheit_to_kelvin(0.0), 15.0 / 2.236_936).unwrap());
+        assert!((wc + 19.0).abs() < 0.5, "{wc}");
+        assert_eq!(wind_chill(fahrenheit_to_kelvin(60.0), 10.0), None);
+        assert_eq!(wind_chill(fahrenheit_to_kelvin(0.0), 1.0), None);
+
+        let metrics = DerivedMetrics::new(293.15, 50, 5.0);
+        assert!(metrics.dew_point.is_some());
+        assert_eq!(metrics.heat_index, None);
+        assert_eq!(metrics.wind_chill, None);
+    }
+}
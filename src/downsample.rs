@@ -0,0 +1,77 @@
+use weather_api_types::plot::PlotPoint;
+
+/// Largest-triangle-three-buckets downsampling to at most `threshold` points,
+/// the first and last points are kept and every bucket in between keeps the
+/// point forming the largest triangle with the point kept from the previous
+/// bucket and the mean of the next bucket, so peaks and dips survive
+///
+/// Every point is kept when `threshold` is below 3 or not below the number
+/// of points
+#[must_use]
+pub fn lttb(points: &[PlotPoint], threshold: usize) -> Vec<PlotPoint> {
+    if threshold < 3 || points.len() <= threshold {
+        return points.to_vec();
+    }
+    let origin = points[0].datetime.unix_timestamp();
+    let x = |p: &PlotPoint| (p.datetime.unix_timestamp() - origin) as f64;
+    let every = (points.len() - 2) as f64 / (threshold - 2) as f64;
+
+    let mut sampled = Vec::with_capacity(threshold);
+    sampled.push(points[0]);
+    let mut a = 0;
+    for i in 0..threshold - 2 {
+        let next_start = ((i + 1) as f64 * every) as usize + 1;
+        let next_end = (((i + 2) as f64 * every) as usize + 1).min(points.len());
+        let next = &points[next_start..next_end];
+        let n = next.len() as f64;
+        let next_x = next.iter().map(x).sum::<f64>() / n;
+        let next_y = next.iter().map(|p| p.value).sum::<f64>() / n;
+
+        let (ax, ay) = (x(&points[a]), points[a].value);
+        let start = (i as f64 * every) as usize + 1;
+        let mut max_area = -1.0;
+        for (j, point) in points.iter().enumerate().take(next_start).skip(start) {
+            let area = ((ax - next_x) * (point.value - ay) - (ax - x(point)) * (next_y - ay)).abs();
+            if area > max_area {
+                max_area = area;
+                a = j;
+            }
+        }
+        sampled.push(points[a]);
+    }
+    sampled.push(points[points.len() - 1]);
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration};
+
+    use weather_api_types::plot::PlotPoint;
+
+    use crate::downsample::lttb;
+
+    #[test]
+    fn test_lttb() {
+        let start = datetime!(2024-01-01 00:00 UTC);
+        let mut points: Vec<_> = (0..10_000)
+            .map(|i| PlotPoint {
+                datetime: start + Duration::minutes(5 * i),
+                value: (i as f64 / 288.0).sin() * 10.0,
+            })
+            .collect();
+        points[5000].value = 100.0;
+        points[7000].value = -100.0;
+
+        let sampled = lttb(&points, 500);
+        assert_eq!(sampled.len(), 500);
+        assert_eq!(sampled[0], points[0]);
+        assert_eq!(sampled[499], points[9999]);
+        assert!(sampled.windows(2).all(|w| w[0].datetime < w[1].datetime));
+        assert!(sampled.contains(&points[5000]));
+        assert!(sampled.contains(&points[7000]));
+
+        assert_eq!(lttb(&points[..100], 500).len(), 100);
+        assert_eq!(lttb(&points, 0).len(), points.len());
+    }
+}
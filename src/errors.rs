@@ -1,8 +1,9 @@
 use anyhow::Error as AnyhowError;
 use log::error;
 use postgres_query::Error as PgError;
+use reqwest::Error as ReqwestError;
 use rweb::{
-    http::{Error as HTTPError, StatusCode},
+    http::{header::RETRY_AFTER, Error as HTTPError, StatusCode},
     openapi::{
         ComponentDescriptor, ComponentOrInlineSchema, Entity, Response, ResponseEntity, Responses,
     },
@@ -24,7 +25,7 @@ use thiserror::Error;
 use time::error::Format as FormatError;
 use weather_util_rust::Error as WeatherUtilError;
 
-use crate::logged_user::LOGIN_HTML;
+use crate::{logged_user::LOGIN_HTML, pgpool::DatabaseDisabled, upstream_queue::UpstreamBusy};
 
 fn login_html() -> impl Reply {
     rweb::reply::html(LOGIN_HTML)
@@ -38,6 +39,12 @@ pub enum ServiceError {
     InternalServerError,
     #[error("BadRequest: {}", _0)]
     BadRequest(StackString),
+    #[error("NotFound: {}", _0)]
+    NotFound(StackString),
+    #[error("Forbidden: {}", _0)]
+    Forbidden(StackString),
+    #[error("Too Many Requests, retry after {0} seconds")]
+    TooManyRequests(u64),
     #[error("Weather-util error {0}")]
     WeatherUtilError(#[from] WeatherUtilError),
     #[error("io Error {0}")]
@@ -60,6 +67,8 @@ pub enum ServiceError {
     UrlEncodedError(#[from] UrlEncodedError),
     #[error("FmtError {0}")]
     FmtError(#[from] FmtError),
+    #[error("ReqwestError {0}")]
+    ReqwestError(#[from] ReqwestError),
 }
 
 impl Reject for ServiceError {}
@@ -88,9 +97,34 @@ pub async fn error_response(err: Rejection) -> Result<Box<dyn Reply>, Infallible
                 code = StatusCode::BAD_REQUEST;
                 message = msg.as_str();
             }
+            ServiceError::NotFound(msg) => {
+                code = StatusCode::NOT_FOUND;
+                message = msg.as_str();
+            }
+            ServiceError::Forbidden(msg) => {
+                code = StatusCode::FORBIDDEN;
+                message = msg.as_str();
+            }
             ServiceError::Unauthorized => {
                 return Ok(Box::new(login_html()));
             }
+            ServiceError::TooManyRequests(retry_after) => {
+                let json = rweb::reply::json(&ErrorMessage {
+                    code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+                    message: "Too Many Requests".into(),
+                });
+                let reply = rweb::reply::with_status(json, StatusCode::TOO_MANY_REQUESTS);
+                let reply = rweb::reply::with_header(reply, RETRY_AFTER, retry_after.to_string());
+                return Ok(Box::new(reply));
+            }
+            ServiceError::AnyhowError(e) if e.is::<DatabaseDisabled>() => {
+                code = StatusCode::NOT_IMPLEMENTED;
+                message = "Not available without a database";
+            }
+            ServiceError::AnyhowError(e) if e.is::<UpstreamBusy>() => {
+                code = StatusCode::SERVICE_UNAVAILABLE;
+                message = "Upstream busy, please try again later";
+            }
             _ => {
                 error!("{service_err:?}");
                 code = StatusCode::INTERNAL_SERVER_ERROR;
@@ -131,7 +165,14 @@ impl ResponseEntity for ServiceError {
             (StatusCode::NOT_FOUND, "Not Found"),
             (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
             (StatusCode::BAD_REQUEST, "Bad Request"),
+            (StatusCode::FORBIDDEN, "Forbidden"),
             (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+            (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests"),
+            (
+                StatusCode::NOT_IMPLEMENTED,
+                "Not available without a database",
+            ),
+            (StatusCode::SERVICE_UNAVAILABLE, "Upstream busy"),
         ];
 
         for (code, msg) in &error_responses {
@@ -153,7 +194,11 @@ mod test {
     use anyhow::Error;
     use rweb::Reply;
 
-    use crate::errors::{error_response, ServiceError};
+    use crate::{
+        errors::{error_response, ServiceError},
+        pgpool::DatabaseDisabled,
+        upstream_queue::UpstreamBusy,
+    };
 
     #[tokio::test]
     async fn test_service_error() -> Result<(), Error> {
@@ -161,9 +206,25 @@ mod test {
         let resp = error_response(err).await?.into_response();
         assert_eq!(resp.status().as_u16(), 400);
 
+        let err = ServiceError::NotFound("TEST ERROR".into()).into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 404);
+
+        let err = ServiceError::Forbidden("TEST ERROR".into()).into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 403);
+
         let err = ServiceError::InternalServerError.into();
         let resp = error_response(err).await?.into_response();
         assert_eq!(resp.status().as_u16(), 500);
+
+        let err = ServiceError::from(Error::from(DatabaseDisabled)).into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 501);
+
+        let err = ServiceError::from(Error::from(UpstreamBusy)).into();
+        let resp = error_response(err).await?.into_response();
+        assert_eq!(resp.status().as_u16(), 503);
         Ok(())
     }
 }
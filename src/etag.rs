@@ -0,0 +1,194 @@
+use log::error;
+use rweb::{
+    http::{
+        header::{HeaderValue, CACHE_CONTROL, CONTENT_LENGTH, ETAG},
+        Method, StatusCode,
+    },
+    hyper::{
+        body::{to_bytes, HttpBody},
+        Body,
+    },
+    reply::Response,
+};
+use stack_string::{format_sstr, StackString};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::app::WEATHER_CACHE_TTL;
+
+/// Routes served from the weather data / forecast caches, polling clients
+/// of these get an `ETag` and `Cache-Control`. Paths are matched exactly,
+/// e.g. `/weather/forecast/blend` isn't served from the forecast cache
+const ETAG_PATHS: [&str; 18] = [
+    "/weather/weather",
+    "/weather/forecast",
+    "/weather/forecast-plots",
+    "/weather/forecast-plots/humidity",
+    "/weather/forecast-plots/precipitation",
+    "/weather/forecast-plots/pressure",
+    "/weather/forecast-plots/temperature",
+    "/weather/forecast-plots/wind",
+    "/weather/history-plots",
+    "/weather/history-plots/dew-point",
+    "/weather/history-plots/heat-index",
+    "/weather/history-plots/humidity",
+    "/weather/history-plots/precipitation",
+    "/weather/history-plots/pressure",
+    "/weather/history-plots/temperature",
+    "/weather/history-plots/uv-index",
+    "/weather/history-plots/wind",
+    "/weather/history-plots/wind-chill",
+];
+
+fn has_etag(path: &str) -> bool {
+    ETAG_PATHS.contains(&path)
+}
+
+/// Strong entity tag of a response body
+#[must_use]
+pub fn compute_etag(body: &[u8]) -> StackString {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format_sstr!("\"{:016x}\"", hasher.finish())
+}
+
+/// Whether an `If-None-Match` header matches `etag`, weak comparison as
+/// required for `GET`
+#[must_use]
+pub fn if_none_match(header: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    header.split(',').map(str::trim).any(|tag| {
+        let tag = tag.trim_start_matches("W/");
+        tag == "*" || tag == etag
+    })
+}
+
+/// Add `ETag` and `Cache-Control` to successful `GET` responses of
+/// `ETAG_PATHS`, replying `304 Not Modified` when the client already holds
+/// the current body
+pub async fn conditional_response(
+    response: Response,
+    path: &str,
+    method: &Method,
+    if_none_match_header: Option<&str>,
+) -> Response {
+    if *method != Method::GET || response.status() != StatusCode::OK || !has_etag(path) {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    // a streamed body is passed through rather than buffered to hash it
+    if body.size_hint().exact().is_none() {
+        return Response::from_parts(parts, body);
+    }
+    let body = match to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("failed to read response body {e}");
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return response;
+        }
+    };
+    let etag = compute_etag(&body);
+    let cache_control = format_sstr!("max-age={WEATHER_CACHE_TTL}");
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        parts.headers.insert(ETAG, etag);
+    }
+    if let Ok(cache_control) = HeaderValue::from_str(&cache_control) {
+        parts.headers.insert(CACHE_CONTROL, cache_control);
+    }
+    if if_none_match_header.is_some_and(|header| if_none_match(header, &etag)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(CONTENT_LENGTH);
+        return Response::from_parts(parts, Body::empty());
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use rweb::{
+        http::{
+            header::{CACHE_CONTROL, ETAG},
+            Method, StatusCode,
+        },
+        hyper::{body::to_bytes, Body},
+        reply::{self, Reply, Response},
+    };
+    use serde_json::json;
+
+    use crate::etag::{compute_etag, conditional_response, has_etag, if_none_match};
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = compute_etag(b"{}");
+        assert_eq!(etag, compute_etag(b"{}"));
+        assert_ne!(etag, compute_etag(b"[]"));
+        assert!(if_none_match(&etag, &etag));
+        assert!(if_none_match(&format!("\"abc\", W/{etag}"), &etag));
+        assert!(if_none_match("*", &etag));
+        assert!(!if_none_match("\"abc\"", &etag));
+
+        assert!(has_etag("/weather/weather"));
+        assert!(has_etag("/weather/forecast-plots/temperature"));
+        assert!(!has_etag("/weather/forecast-blend"));
+        assert!(!has_etag("/weather/forecast/blend"));
+        assert!(!has_etag("/weather/forecast/offline"));
+        assert!(!has_etag("/weather/forecast/accuracy"));
+        assert!(!has_etag("/weather/history-plots/temperature/extra"));
+        assert!(!has_etag("/weather/history"));
+    }
+
+    #[tokio::test]
+    async fn test_conditional_response() -> Result<(), Error> {
+        let path = "/weather/weather";
+        let value = json!({"temperature": 293.15});
+
+        let response = conditional_response(
+            reply::json(&value).into_response(),
+            path,
+            &Method::GET,
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CACHE_CONTROL], "max-age=3600");
+        let etag = response.headers()[ETAG].to_str()?.to_string();
+        let body = to_bytes(response.into_body()).await?;
+        assert_eq!(etag, compute_etag(&body).as_str());
+
+        let response = conditional_response(
+            reply::json(&value).into_response(),
+            path,
+            &Method::GET,
+            Some(&etag),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], etag.as_str());
+        assert!(to_bytes(response.into_body()).await?.is_empty());
+
+        let response = conditional_response(
+            reply::json(&value).into_response(),
+            path,
+            &Method::POST,
+            Some(&etag),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(ETAG).is_none());
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_conditional_response_streamed() -> Result<(), Error> {
+        let (mut sender, body) = Body::channel();
+        sender.send_data("{}".into()).await?;
+        drop(sender);
+        let response =
+            conditional_response(Response::new(body), "/weather/weather", &Method::GET, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(ETAG).is_none());
+        assert_eq!(to_bytes(response.into_body()).await?, "{}");
+        Ok(())
+    }
+}
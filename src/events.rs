@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    date_time_wrapper::DateTimeWrapper,
+    model::{WeatherDataDB, WeatherEvent},
+};
+
+/// Thresholds used to flag a recorded row as stormy
+#[derive(Debug, Clone, Copy)]
+pub struct StormThresholds {
+    /// precipitation rate (mm/h)
+    pub precipitation: f64,
+    /// wind speed (m/s)
+    pub wind_speed: f64,
+    /// pressure drop rate (kPa/h) over `PRESSURE_TENDENCY_WINDOW`
+    pub pressure_drop_rate: f64,
+    /// maximum gap between stormy rows belonging to the same event
+    pub max_gap: Duration,
+    /// minimum duration of a sustained event
+    pub min_duration: Duration,
+}
+
+impl Default for StormThresholds {
+    fn default() -> Self {
+        Self {
+            precipitation: 2.0,
+            wind_speed: 12.0,
+            pressure_drop_rate: 0.15,
+            max_gap: Duration::minutes(90),
+            min_duration: Duration::minutes(60),
+        }
+    }
+}
+
+/// Span over which pressure tendencies are computed, between consecutive
+/// rows a single 0.1 kPa reading tick would look like a rapid change
+pub const PRESSURE_TENDENCY_WINDOW: Duration = Duration::hours(3);
+
+/// Pressure change rate (kPa/h, negative when falling) of the last of
+/// `readings` (time, pressure) against the earlier reading nearest
+/// `PRESSURE_TENDENCY_WINDOW` before it, `None` until a reading at least half
+/// a window back is available, `readings` must be sorted by time
+#[must_use]
+pub fn pressure_tendency(readings: &[(OffsetDateTime, f64)]) -> Option<f64> {
+    let (&(dt, pressure), earlier) = readings.split_last()?;
+    let target = dt - PRESSURE_TENDENCY_WINDOW;
+    let idx = earlier.partition_point(|(t, _)| *t <= target);
+    let (reference_dt, reference_pressure) = [idx.checked_sub(1), Some(idx)]
+        .into_iter()
+        .flatten()
+        .filter_map(|i| earlier.get(i))
+        .min_by_key(|(t, _)| (*t - target).abs())?;
+    let elapsed = dt - *reference_dt;
+    if elapsed < PRESSURE_TENDENCY_WINDOW / 2 {
+        return None;
+    }
+    Some((pressure - reference_pressure) / (elapsed.as_seconds_f64() / 3600.0))
+}
+
+struct StormRow<'a> {
+    row: &'a WeatherDataDB,
+    pressure_drop_rate: f64,
+}
+
+fn precipitation(row: &WeatherDataDB) -> f64 {
+    row.rain.unwrap_or(0.0) + row.snow.unwrap_or(0.0)
+}
+
+fn build_event(rows: &[StormRow], thresholds: &StormThresholds) -> Option<WeatherEvent> {
+    let first = rows.first()?.row;
+    let last = rows.last()?.row;
+    if *last.created_at - *first.created_at < thresholds.min_duration {
+        return None;
+    }
+    // rain and snow report the previous hour, keep one value per hour
+    let mut hourly: BTreeMap<i64, f64> = BTreeMap::new();
+    for r in rows {
+        let hour = r.row.created_at.unix_timestamp() / 3600;
+        let value = hourly.entry(hour).or_default();
+        *value = value.max(precipitation(r.row));
+    }
+    Some(WeatherEvent {
+        id: Uuid::new_v4(),
+        location_name: first.location_name.clone(),
+        server: first.server.clone(),
+        event_type: "storm".into(),
+        start_time: first.created_at,
+        end_time: last.created_at,
+        peak_wind_speed: rows.iter().map(|r| r.row.wind_speed).fold(0.0, f64::max),
+        total_precipitation: hourly.values().sum(),
+        min_pressure: rows
+            .iter()
+            .map(|r| r.row.pressure)
+            .fold(f64::INFINITY, f64::min),
+        max_pressure_drop_rate: rows
+            .iter()
+            .map(|r| r.pressure_drop_rate)
+            .fold(0.0, f64::max),
+        created_at: DateTimeWrapper::now(),
+    })
+}
+
+/// Detect storm events from the recorded rows of a single location, rows
+/// must be sorted by `created_at`.
+#[must_use]
+pub fn detect_storm_events(
+    history: &[WeatherDataDB],
+    thresholds: &StormThresholds,
+) -> Vec<WeatherEvent> {
+    let mut events = Vec::new();
+    let mut current: Vec<StormRow> = Vec::new();
+    let mut last_stormy: Option<DateTimeWrapper> = None;
+
+    let readings: Vec<_> = history
+        .iter()
+        .map(|row| (*row.created_at, row.pressure))
+        .collect();
+    for (idx, row) in history.iter().enumerate() {
+        let pressure_drop_rate = pressure_tendency(&readings[..=idx]).map_or(0.0, |t| -t);
+        let stormy = precipitation(row) >= thresholds.precipitation
+            || row.wind_speed >= thresholds.wind_speed
+            || pressure_drop_rate >= thresholds.pressure_drop_rate;
+        if !stormy {
+            continue;
+        }
+        if last_stormy.is_some_and(|last| *row.created_at - *last > thresholds.max_gap) {
+            events.extend(build_event(&current, thresholds));
+            current.clear();
+        }
+        current.push(StormRow {
+            row,
+            pressure_drop_rate,
+        });
+        last_stormy.replace(row.created_at);
+    }
+    events.extend(build_event(&current, thresholds));
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration};
+    use uuid::Uuid;
+
+    use crate::{
+        events::{detect_storm_events, pressure_tendency, StormThresholds},
+        model::WeatherDataDB,
+    };
+
+    fn row(minutes: i64, rain: f64, wind_speed: f64, pressure: f64) -> WeatherDataDB {
+        let created_at = datetime!(2024-06-01 00:00 UTC) + Duration::minutes(minutes);
+        WeatherDataDB {
+            id: Uuid::nil(),
+            location_name: "test".into(),
+            latitude: 0.0,
+            longitude: 0.0,
+            pressure,
+            humidity: 50,
+            rain: Some(rain),
+            wind_speed,
+            ..WeatherDataDB::test_row(created_at)
+        }
+    }
+
+    #[test]
+    fn test_detect_storm_events() {
+        let history = vec![
+            row(0, 0.0, 2.0, 101.3),
+            row(60, 5.0, 8.0, 101.0),
+            row(120, 8.0, 15.0, 100.7),
+            row(180, 0.0, 3.0, 101.0),
+            // isolated gust, too short to count as sustained
+            row(600, 0.0, 14.0, 100.8),
+        ];
+        let events = detect_storm_events(&history, &StormThresholds::default());
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(*event.start_time, datetime!(2024-06-01 01:00 UTC));
+        assert_eq!(*event.end_time, datetime!(2024-06-01 02:00 UTC));
+        assert!((event.total_precipitation - 13.0).abs() < 1e-6);
+        assert!((event.peak_wind_speed - 15.0).abs() < 1e-6);
+        assert!((event.min_pressure - 100.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_short_storm_ignored() {
+        // two stormy rows closer together than min_duration
+        let history = vec![
+            row(0, 0.0, 2.0, 101.3),
+            row(60, 5.0, 14.0, 101.3),
+            row(65, 6.0, 15.0, 101.3),
+            row(120, 0.0, 2.0, 101.3),
+        ];
+        assert!(detect_storm_events(&history, &StormThresholds::default()).is_empty());
+    }
+
+    #[test]
+    fn test_pressure_tendency() {
+        // 0.1 kPa reading ticks between five minute rows aren't a storm
+        let history: Vec<_> = (0..72)
+            .map(|i| row(i * 5, 0.0, 2.0, if i % 2 == 0 { 101.3 } else { 101.2 }))
+            .collect();
+        assert!(detect_storm_events(&history, &StormThresholds::default()).is_empty());
+
+        let start = datetime!(2024-06-01 00:00 UTC);
+        let readings: Vec<_> = (0..=36)
+            .map(|i| (start + Duration::minutes(i * 5), 101.3 - 0.02 * i as f64))
+            .collect();
+        assert_eq!(pressure_tendency(&readings[..12]), None);
+        let tendency = pressure_tendency(&readings).unwrap();
+        assert!((tendency + 0.24).abs() < 1e-9);
+        let tendency = pressure_tendency(&readings[..25]).unwrap();
+        assert!((tendency + 0.24).abs() < 1e-9);
+        assert_eq!(pressure_tendency(&[]), None);
+    }
+}
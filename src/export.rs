@@ -0,0 +1,116 @@
+use anyhow::Error;
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt};
+use log::error;
+use rweb::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    hyper::Body,
+    openapi::{
+        ComponentDescriptor, ComponentOrInlineSchema, Entity, MediaType, Response, ResponseEntity,
+        Responses, Schema, Type,
+    },
+    reply, Reply,
+};
+use serde::Serialize;
+use std::{borrow::Cow, pin::Pin};
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+const NDJSON_DESCRIPTION: &str = "Newline delimited json, one object per line";
+
+/// Json of `value` terminated by a newline
+///
+/// # Errors
+/// Return error if serialization fails
+pub fn ndjson_line<T: Serialize>(value: &T) -> Result<Bytes, Error> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    Ok(line.into())
+}
+
+/// Body streamed to the client as it is produced, an error ends the response
+/// early
+pub struct NdjsonResponse(Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>);
+
+impl NdjsonResponse {
+    pub fn new<S, T, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<T, E>> + Send + 'static,
+        T: Serialize + Send + 'static,
+        E: Into<Error> + 'static,
+    {
+        let lines = stream
+            .map_err(Into::into)
+            .and_then(|value| async move { ndjson_line(&value) })
+            .inspect_err(|e| error!("export failed {e}"));
+        Self(Box::pin(lines))
+    }
+}
+
+impl Reply for NdjsonResponse {
+    fn into_response(self) -> reply::Response {
+        reply::with_header(
+            reply::Response::new(Body::wrap_stream(self.0)),
+            CONTENT_TYPE,
+            NDJSON_CONTENT_TYPE,
+        )
+        .into_response()
+    }
+}
+
+impl Entity for NdjsonResponse {
+    fn type_name() -> Cow<'static, str> {
+        "ndjson".into()
+    }
+
+    fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        ComponentOrInlineSchema::Inline(Schema {
+            schema_type: Some(Type::String),
+            description: NDJSON_DESCRIPTION.into(),
+            ..Schema::default()
+        })
+    }
+}
+
+impl ResponseEntity for NdjsonResponse {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        let mut response = Response {
+            description: Cow::Borrowed(NDJSON_DESCRIPTION),
+            ..Response::default()
+        };
+        response.content.insert(
+            Cow::Borrowed(NDJSON_CONTENT_TYPE),
+            MediaType {
+                schema: Some(Self::describe(comp_d)),
+                ..MediaType::default()
+            },
+        );
+        let mut map = Responses::new();
+        map.insert(Cow::Owned(StatusCode::OK.as_str().into()), response);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use futures::{stream, TryStreamExt};
+    use serde_json::{json, Value};
+
+    use crate::export::{ndjson_line, NdjsonResponse};
+
+    #[tokio::test]
+    async fn test_ndjson_response() -> Result<(), Error> {
+        assert_eq!(ndjson_line(&json!({"a": 1}))?, "{\"a\":1}\n");
+
+        let rows = stream::iter([Ok::<_, Error>(json!({"a": 1})), Ok(json!({"a": 2}))]);
+        let lines: Vec<_> = NdjsonResponse::new(rows).0.try_collect().await?;
+        let body: Vec<u8> = lines.concat();
+        let values: Vec<Value> = body
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(serde_json::from_slice)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(values, vec![json!({"a": 1}), json!({"a": 2})]);
+        Ok(())
+    }
+}
@@ -0,0 +1,485 @@
+use anyhow::Error;
+use image::{codecs::png::PngEncoder, ExtendedColorType, ImageEncoder};
+use plotters::{
+    backend::BitMapBackend,
+    chart::ChartBuilder,
+    drawing::IntoDrawingArea,
+    series::LineSeries,
+    style::{RGBColor, WHITE},
+};
+use rweb::{
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        StatusCode,
+    },
+    openapi::{
+        ComponentDescriptor, ComponentOrInlineSchema, Entity, MediaType, Response, ResponseEntity,
+        Responses, Schema, Type,
+    },
+    reply, Reply,
+};
+use serde_json::{json, Map, Value};
+use stack_string::{format_sstr, StackString};
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    io::{Cursor, Write},
+};
+use time::{Date, OffsetDateTime};
+use zip::{
+    write::{SimpleFileOptions, ZipWriter},
+    CompressionMethod,
+};
+
+use weather_api_types::{plot::PlotPoint, units::Units};
+use weather_util_rust::weather_data::WeatherData;
+
+use crate::{
+    config::Config, date_time_wrapper::DateTimeWrapper, get_history_precip_plot,
+    get_history_temperature_plot, model::WeatherDataDB,
+};
+
+pub const ZIP_CONTENT_TYPE: &str = "application/zip";
+const PACKAGE_DESCRIPTION: &str =
+    "Zip of data.csv, schema.json, temperature.png, precipitation.png and README.md";
+
+const PLOT_WIDTH: u32 = 1024;
+const PLOT_HEIGHT: u32 = 480;
+const TEMPERATURE_COLOR: RGBColor = RGBColor(220, 60, 40);
+const PRECIPITATION_COLOR: RGBColor = RGBColor(40, 90, 200);
+
+/// Column, json schema type, unit and description of each `data.csv` column
+const COLUMNS: [(&str, &str, Option<&str>, &str); 28] = [
+    ("id", "string", None, "Row uuid"),
+    (
+        "dt",
+        "integer",
+        Some("s"),
+        "Observation time (unix timestamp)",
+    ),
+    (
+        "created_at",
+        "string",
+        None,
+        "Time the row was recorded (RFC 3339)",
+    ),
+    ("location_name", "string", None, "Location as queried"),
+    ("latitude", "number", Some("deg"), "Latitude"),
+    ("longitude", "number", Some("deg"), "Longitude"),
+    ("condition", "string", None, "Weather condition"),
+    (
+        "condition_code",
+        "integer",
+        None,
+        "Openweathermap condition id, empty when unknown",
+    ),
+    ("temperature", "number", Some("K"), "Temperature"),
+    (
+        "temperature_minimum",
+        "number",
+        Some("K"),
+        "Minimum temperature",
+    ),
+    (
+        "temperature_maximum",
+        "number",
+        Some("K"),
+        "Maximum temperature",
+    ),
+    ("pressure", "number", Some("kPa"), "Atmospheric pressure"),
+    ("humidity", "integer", Some("%"), "Relative humidity"),
+    (
+        "visibility",
+        "number",
+        Some("m"),
+        "Visibility, empty when unknown",
+    ),
+    (
+        "rain",
+        "number",
+        Some("mm"),
+        "Rain of the last hour, empty when none",
+    ),
+    (
+        "snow",
+        "number",
+        Some("mm"),
+        "Snow of the last hour, empty when none",
+    ),
+    ("wind_speed", "number", Some("m/s"), "Wind speed"),
+    (
+        "wind_direction",
+        "number",
+        Some("deg"),
+        "Wind direction, empty when unknown",
+    ),
+    ("country", "string", None, "Country code"),
+    ("sunrise", "string", None, "Sunrise (RFC 3339)"),
+    ("sunset", "string", None, "Sunset (RFC 3339)"),
+    ("timezone", "integer", Some("s"), "Offset from utc"),
+    ("server", "string", None, "Server that recorded the row"),
+    ("dew_point", "number", Some("K"), "Dew point"),
+    (
+        "heat_index",
+        "number",
+        Some("K"),
+        "Heat index, empty below 80°F",
+    ),
+    (
+        "wind_chill",
+        "number",
+        Some("K"),
+        "Wind chill, empty above 50°F or with wind of at most 3 mph",
+    ),
+    (
+        "backfilled",
+        "boolean",
+        None,
+        "Filled in from a historical api rather than observed",
+    ),
+    (
+        "inserted_at",
+        "string",
+        None,
+        "Time the row was written to the database (RFC 3339), empty when unknown",
+    ),
+];
+
+/// Location and range a package was requested for
+pub struct PackageInfo<'a> {
+    pub name: &'a str,
+    pub start_date: Option<Date>,
+    pub end_date: Option<Date>,
+    pub units: Units,
+    pub exported_at: OffsetDateTime,
+}
+
+/// Json schema (draft 2020-12) of a `data.csv` row
+#[must_use]
+pub fn csv_schema() -> Value {
+    let nullable = [
+        "condition_code",
+        "visibility",
+        "rain",
+        "snow",
+        "wind_direction",
+        "dew_point",
+        "heat_index",
+        "wind_chill",
+        "inserted_at",
+    ];
+    let properties: Map<String, Value> = COLUMNS
+        .iter()
+        .map(|(name, column_type, unit, description)| {
+            let column_type = if nullable.contains(name) {
+                json!([column_type, "null"])
+            } else {
+                json!(column_type)
+            };
+            let mut property = json!({"type": column_type, "description": description});
+            if let Some(unit) = unit {
+                property["unit"] = json!(unit);
+            }
+            ((*name).into(), property)
+        })
+        .collect();
+    let required: Vec<_> = COLUMNS
+        .iter()
+        .map(|(name, ..)| *name)
+        .filter(|name| !nullable.contains(name))
+        .collect();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "WeatherDataDB",
+        "description": "One row of data.csv, columns in this order",
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// # Errors
+/// Return error if serialization fails
+pub fn rows_to_csv(rows: &[WeatherDataDB]) -> Result<Vec<u8>, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// Line plot of `points` encoded as png, the axes are described in the
+/// README rather than drawn so no fonts are needed
+///
+/// # Errors
+/// Return error if drawing or encoding fails
+pub fn plot_png(points: &[PlotPoint], color: RGBColor) -> Result<Vec<u8>, Error> {
+    let mut buffer = vec![0; (PLOT_WIDTH * PLOT_HEIGHT * 3) as usize];
+    {
+        let root =
+            BitMapBackend::with_buffer(&mut buffer, (PLOT_WIDTH, PLOT_HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let (x_min, x_max) = value_range(points.iter().map(|p| p.datetime.unix_timestamp()));
+        let (y_min, y_max) = value_range(points.iter().map(|p| p.value));
+        let mut chart = ChartBuilder::on(&root)
+            .margin(20)
+            .build_cartesian_2d(x_min..x_max.max(x_min + 1), y_min..y_max.max(y_min + 1.0))?;
+        chart.draw_series(LineSeries::new(
+            points
+                .iter()
+                .map(|p| (p.datetime.unix_timestamp(), p.value)),
+            &color,
+        ))?;
+        root.present()?;
+    }
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(
+        &buffer,
+        PLOT_WIDTH,
+        PLOT_HEIGHT,
+        ExtendedColorType::Rgb8,
+    )?;
+    Ok(png)
+}
+
+fn value_range<T: PartialOrd + Copy + Default>(values: impl Iterator<Item = T>) -> (T, T) {
+    values
+        .fold(None, |range: Option<(T, T)>, v| match range {
+            None => Some((v, v)),
+            Some((min, max)) => {
+                Some((if v < min { v } else { min }, if v > max { v } else { max }))
+            }
+        })
+        .unwrap_or_default()
+}
+
+fn readme(
+    config: &Config,
+    info: &PackageInfo,
+    rows: &[WeatherDataDB],
+    temperature: &[PlotPoint],
+) -> String {
+    let date_str = |d: Option<Date>| d.map_or_else(|| "-".into(), |d| format_sstr!("{d}"));
+    let timestamp_str = |row: Option<&WeatherDataDB>| {
+        row.map_or_else(|| "-".into(), |r| format_sstr!("{}", r.created_at))
+    };
+    let (t_min, t_max) = value_range(temperature.iter().map(|p| p.value));
+    let exported_at = DateTimeWrapper::from(info.exported_at);
+    let units = info.units;
+
+    let mut text = format!("# Weather history of {}\n\n", info.name);
+    let _ = writeln!(
+        text,
+        "Requested range: {} to {}",
+        date_str(info.start_date),
+        date_str(info.end_date)
+    );
+    let _ = writeln!(
+        text,
+        "Recorded range: {} to {}",
+        timestamp_str(rows.first()),
+        timestamp_str(rows.last())
+    );
+    let _ = writeln!(text, "Rows: {}", rows.len());
+    let _ = writeln!(text, "Exported at: {exported_at}\n");
+    text.push_str("## Files\n\n");
+    text.push_str("- `data.csv`: one row per observation, columns in db units\n");
+    text.push_str("- `schema.json`: json schema of a `data.csv` row with units\n");
+    let _ = writeln!(
+        text,
+        "- `temperature.png`: temperature ({}) over time, {t_min:.1} to {t_max:.1}",
+        units.temperature_unit()
+    );
+    let _ = writeln!(
+        text,
+        "- `precipitation.png`: hourly rain and snow ({}) over time",
+        units.precipitation_unit()
+    );
+    text.push_str("\nPlots span the recorded range on the x axis, the y axis spans the");
+    text.push_str(" minimum to maximum value.\n\n");
+    text.push_str("## Attribution\n\n");
+    let _ = writeln!(
+        text,
+        "Data provided by {} ({}) under {}.",
+        config.attribution_provider, config.attribution_url, config.attribution_license
+    );
+    text
+}
+
+/// Zip of the csv rows, their json schema, temperature and precipitation
+/// plots and a README
+///
+/// # Errors
+/// Return error if serialization, plotting or compression fails
+pub fn build_export_package(
+    config: &Config,
+    info: &PackageInfo,
+    rows: &[WeatherDataDB],
+) -> Result<Vec<u8>, Error> {
+    let history: Vec<WeatherData> = rows.iter().cloned().map(Into::into).collect();
+    let temperature = get_history_temperature_plot(&history, info.units);
+    let precipitation = get_history_precip_plot(&history, info.units);
+
+    let files = [
+        (
+            "README.md",
+            readme(config, info, rows, &temperature).into_bytes(),
+        ),
+        ("data.csv", rows_to_csv(rows)?),
+        ("schema.json", serde_json::to_vec_pretty(&csv_schema())?),
+        (
+            "temperature.png",
+            plot_png(&temperature, TEMPERATURE_COLOR)?,
+        ),
+        (
+            "precipitation.png",
+            plot_png(&precipitation, PRECIPITATION_COLOR)?,
+        ),
+    ];
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (filename, data) in files {
+        let method = if filename.ends_with(".png") {
+            CompressionMethod::Stored
+        } else {
+            CompressionMethod::Deflated
+        };
+        zip.start_file(
+            filename,
+            SimpleFileOptions::default().compression_method(method),
+        )?;
+        zip.write_all(&data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// File name of the package, e.g. `weather_11106_2024-01-01_2024-02-01.zip`
+#[must_use]
+pub fn package_filename(info: &PackageInfo) -> StackString {
+    let name: String = info
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let mut filename = format!("weather_{name}");
+    for date in [info.start_date, info.end_date].into_iter().flatten() {
+        let _ = write!(filename, "_{date}");
+    }
+    filename.push_str(".zip");
+    filename.into()
+}
+
+pub struct ExportPackageResponse {
+    pub filename: StackString,
+    pub body: Vec<u8>,
+}
+
+impl Reply for ExportPackageResponse {
+    fn into_response(self) -> reply::Response {
+        let disposition = format_sstr!("attachment; filename=\"{}\"", self.filename);
+        let reply = reply::with_header(self.body, CONTENT_TYPE, ZIP_CONTENT_TYPE);
+        reply::with_header(reply, CONTENT_DISPOSITION, disposition.as_str()).into_response()
+    }
+}
+
+impl Entity for ExportPackageResponse {
+    fn type_name() -> Cow<'static, str> {
+        "export_package".into()
+    }
+
+    fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        ComponentOrInlineSchema::Inline(Schema {
+            schema_type: Some(Type::String),
+            format: "binary".into(),
+            description: PACKAGE_DESCRIPTION.into(),
+            ..Schema::default()
+        })
+    }
+}
+
+impl ResponseEntity for ExportPackageResponse {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        let mut response = Response {
+            description: Cow::Borrowed(PACKAGE_DESCRIPTION),
+            ..Response::default()
+        };
+        response.content.insert(
+            Cow::Borrowed(ZIP_CONTENT_TYPE),
+            MediaType {
+                schema: Some(Self::describe(comp_d)),
+                ..MediaType::default()
+            },
+        );
+        let mut map = Responses::new();
+        map.insert(Cow::Owned(StatusCode::OK.as_str().into()), response);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use std::io::{Cursor, Read};
+    use time::{macros::date, OffsetDateTime};
+    use zip::ZipArchive;
+
+    use weather_api_types::units::Units;
+
+    use crate::{
+        bench::demo_rows,
+        config::Config,
+        export_package::{build_export_package, csv_schema, package_filename, PackageInfo},
+    };
+
+    #[test]
+    fn test_build_export_package() -> Result<(), Error> {
+        let rows = demo_rows(48);
+        let info = PackageInfo {
+            name: "Minneapolis (demo)",
+            start_date: Some(date!(2023 - 12 - 30)),
+            end_date: None,
+            units: Units::Metric,
+            exported_at: OffsetDateTime::now_utc(),
+        };
+        assert_eq!(
+            package_filename(&info),
+            "weather_Minneapolis__demo__2023-12-30.zip"
+        );
+        let body = build_export_package(&Config::default(), &info, &rows)?;
+        let mut zip = ZipArchive::new(Cursor::new(body))?;
+        let mut names: Vec<_> = zip.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            vec![
+                "README.md",
+                "data.csv",
+                "precipitation.png",
+                "schema.json",
+                "temperature.png"
+            ]
+        );
+
+        let mut csv = String::new();
+        zip.by_name("data.csv")?.read_to_string(&mut csv)?;
+        let mut lines = csv.lines();
+        let header: Vec<_> = lines.next().unwrap_or_default().split(',').collect();
+        let schema = csv_schema();
+        assert_eq!(
+            header.len(),
+            schema["properties"].as_object().map_or(0, |p| p.len())
+        );
+        assert!(header
+            .iter()
+            .all(|c| schema["properties"].get(*c).is_some()));
+        assert_eq!(lines.count(), 48);
+
+        let mut png = Vec::new();
+        zip.by_name("temperature.png")?.read_to_end(&mut png)?;
+        assert!(png.starts_with(b"\x89PNG"));
+
+        let mut readme = String::new();
+        zip.by_name("README.md")?.read_to_string(&mut readme)?;
+        assert!(readme.contains("Rows: 48"));
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use rweb::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    openapi::{
+        ComponentDescriptor, ComponentOrInlineSchema, Entity, MediaType, Response, ResponseEntity,
+        Responses, Schema, Type,
+    },
+    reply, Reply,
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+use time::{format_description::well_known::Rfc3339, Date, OffsetDateTime, UtcOffset};
+
+use weather_api_types::units::Units;
+use weather_util_rust::weather_forecast::WeatherForecast;
+
+pub const ATOM_CONTENT_TYPE: &str = "application/atom+xml";
+const ATOM_DESCRIPTION: &str = "Atom feed with one entry per forecast day";
+
+/// Forecast aggregated over one local day
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForecastDay {
+    pub date: Date,
+    /// kelvin
+    pub low: f64,
+    /// kelvin
+    pub high: f64,
+    /// mm
+    pub rain: f64,
+    /// mm
+    pub snow: f64,
+    /// most frequent condition of the day
+    pub conditions: String,
+}
+
+/// Daily high / low, precipitation and prevailing conditions of the 3 hour
+/// forecast entries
+#[must_use]
+pub fn get_forecast_days(forecast: &WeatherForecast) -> Vec<ForecastDay> {
+    let fo: UtcOffset = forecast.city.timezone.into();
+    let mut days: BTreeMap<Date, (ForecastDay, HashMap<&str, usize>)> = BTreeMap::new();
+    for entry in &forecast.list {
+        let date = entry.dt.to_offset(fo).date();
+        let (day, conditions) = days.entry(date).or_insert_with(|| {
+            let day = ForecastDay {
+                date,
+                low: f64::INFINITY,
+                high: f64::NEG_INFINITY,
+                rain: 0.0,
+                snow: 0.0,
+                conditions: String::new(),
+            };
+            (day, HashMap::new())
+        });
+        day.low = day.low.min(entry.main.temp_min.kelvin());
+        day.high = day.high.max(entry.main.temp_max.kelvin());
+        day.rain += entry
+            .rain
+            .as_ref()
+            .and_then(|r| r.three_hour)
+            .map_or(0.0, |r| r.millimeters());
+        day.snow += entry
+            .snow
+            .as_ref()
+            .and_then(|s| s.three_hour)
+            .map_or(0.0, |s| s.millimeters());
+        for weather in &entry.weather {
+            *conditions.entry(weather.description.as_str()).or_default() += 1;
+        }
+    }
+    days.into_values()
+        .map(|(mut day, conditions)| {
+            if let Some((description, _)) = conditions
+                .into_iter()
+                .max_by(|(a, x), (b, y)| x.cmp(y).then_with(|| b.cmp(a)))
+            {
+                day.conditions = description.into();
+            }
+            day
+        })
+        .collect()
+}
+
+#[must_use]
+pub fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Atom feed of the daily forecast of `location`, entry ids are stable per
+/// location and date so readers update rather than duplicate them
+#[must_use]
+pub fn render_forecast_feed(
+    location: &str,
+    forecast: &WeatherForecast,
+    units: Units,
+    updated: OffsetDateTime,
+) -> String {
+    let updated = updated.format(&Rfc3339).unwrap_or_default();
+    let query = serde_urlencoded::to_string([("loc", location)]).unwrap_or_default();
+    let feed_id = format!("tag:weather-api-rust,2024:forecast?{query}");
+    let temperature_unit = units.temperature_unit();
+    let precipitation_unit = units.precipitation_unit();
+
+    let mut feed = String::new();
+    feed.push_str(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    feed.push('\n');
+    feed.push_str(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#);
+    feed.push('\n');
+    let _ = writeln!(
+        feed,
+        "  <title>Weather Forecast {}</title>",
+        xml_escape(location)
+    );
+    let _ = writeln!(feed, "  <id>{}</id>", xml_escape(&feed_id));
+    let _ = writeln!(feed, "  <updated>{updated}</updated>");
+    let _ = writeln!(
+        feed,
+        r#"  <link rel="self" href="/weather/feed.xml?{}"/>"#,
+        xml_escape(&query)
+    );
+    feed.push_str("  <author><name>weather_api_rust</name></author>\n");
+    for day in get_forecast_days(forecast) {
+        let mut summary = format!(
+            "High: {:0.1} {temperature_unit} / Low: {:0.1} {temperature_unit}",
+            units.temperature(day.high),
+            units.temperature(day.low),
+        );
+        if !day.conditions.is_empty() {
+            let _ = write!(summary, ", {}", day.conditions);
+        }
+        if day.rain > 0.0 {
+            let _ = write!(
+                summary,
+                ", Rain: {:0.2} {precipitation_unit}",
+                units.precipitation(day.rain)
+            );
+        }
+        if day.snow > 0.0 {
+            let _ = write!(
+                summary,
+                ", Snow: {:0.2} {precipitation_unit}",
+                units.precipitation(day.snow)
+            );
+        }
+        let title = format!("{} {}", day.date.weekday(), day.date);
+        feed.push_str("  <entry>\n");
+        let _ = writeln!(feed, "    <title>{}</title>", xml_escape(&title));
+        let _ = writeln!(feed, "    <id>{}#{}</id>", xml_escape(&feed_id), day.date);
+        let _ = writeln!(feed, "    <updated>{updated}</updated>");
+        let _ = writeln!(
+            feed,
+            r#"    <content type="text">{}</content>"#,
+            xml_escape(&summary)
+        );
+        feed.push_str("  </entry>\n");
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+pub struct AtomResponse(pub String);
+
+impl Reply for AtomResponse {
+    fn into_response(self) -> reply::Response {
+        reply::with_header(self.0, CONTENT_TYPE, ATOM_CONTENT_TYPE).into_response()
+    }
+}
+
+impl Entity for AtomResponse {
+    fn type_name() -> Cow<'static, str> {
+        "atom".into()
+    }
+
+    fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        ComponentOrInlineSchema::Inline(Schema {
+            schema_type: Some(Type::String),
+            description: ATOM_DESCRIPTION.into(),
+            ..Schema::default()
+        })
+    }
+}
+
+impl ResponseEntity for AtomResponse {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        let mut response = Response {
+            description: Cow::Borrowed(ATOM_DESCRIPTION),
+            ..Response::default()
+        };
+        response.content.insert(
+            Cow::Borrowed(ATOM_CONTENT_TYPE),
+            MediaType {
+                schema: Some(Self::describe(comp_d)),
+                ..MediaType::default()
+            },
+        );
+        let mut map = Responses::new();
+        map.insert(Cow::Owned(StatusCode::OK.as_str().into()), response);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use serde_json::json;
+    use time::macros::{date, datetime};
+
+    use weather_api_types::units::Units;
+    use weather_util_rust::weather_forecast::WeatherForecast;
+
+    use crate::feed::{get_forecast_days, render_forecast_feed, xml_escape};
+
+    fn entry(dt: i64, temp: f64, description: &str, rain: Option<f64>) -> serde_json::Value {
+        json!({
+            "dt": dt,
+            "main": {
+                "temp": temp, "feels_like": temp, "temp_min": temp, "temp_max": temp,
+                "pressure": 1013.0, "sea_level": 1013.0, "grnd_level": 1013.0, "humidity": 50,
+            },
+            "weather": [{"id": 500, "main": "Rain", "description": description, "icon": "10d"}],
+            "wind": {"speed": 2.0},
+            "rain": rain.map(|r| json!({"3h": r})),
+        })
+    }
+
+    #[test]
+    fn test_forecast_feed() -> Result<(), Error> {
+        let day = datetime!(2024-06-03 00:00 UTC).unix_timestamp();
+        let forecast: WeatherForecast = serde_json::from_value(json!({
+            "list": [
+                entry(day, 290.0, "light rain", Some(1.5)),
+                entry(day + 10800, 295.0, "light rain", Some(0.5)),
+                entry(day + 21600, 300.0, "clear sky", None),
+                entry(day + 86400, 285.0, "clear sky", None),
+            ],
+            "city": {"timezone": 0, "sunrise": 0, "sunset": 0},
+        }))?;
+
+        let days = get_forecast_days(&forecast);
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, date!(2024 - 06 - 03));
+        assert_eq!(days[0].low, 290.0);
+        assert_eq!(days[0].high, 300.0);
+        assert!((days[0].rain - 2.0).abs() < 1e-9);
+        assert_eq!(days[0].conditions, "light rain");
+        assert_eq!(days[1].conditions, "clear sky");
+
+        let feed = render_forecast_feed(
+            "Saint Louis Park, MN",
+            &forecast,
+            Units::Metric,
+            datetime!(2024-06-02 12:00 UTC),
+        );
+        assert!(feed.contains("<title>Weather Forecast Saint Louis Park, MN</title>"));
+        assert!(feed.contains("<updated>2024-06-02T12:00:00Z</updated>"));
+        assert!(feed.contains("<title>Monday 2024-06-03</title>"));
+        assert!(feed.contains("High: 26.9 C / Low: 16.9 C, light rain, Rain: 2.00 mm"));
+        assert_eq!(feed.matches("<entry>").count(), 2);
+
+        assert_eq!(xml_escape("<a & 'b'>"), "&lt;a &amp; &apos;b&apos;&gt;");
+        Ok(())
+    }
+}
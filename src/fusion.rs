@@ -0,0 +1,305 @@
+use serde::Deserialize;
+use stack_string::StackString;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{config::Config, derived_metrics::DerivedMetrics, model::WeatherDataDB};
+
+/// `server` of observations synthesized from several sources
+pub const FUSED_SERVER: &str = "fused";
+
+/// Metrics combined by `fusion_mode` and `fusion_metric_modes`, the minimum
+/// and maximum temperature follow `temperature`
+pub const FUSION_METRICS: [&str; 7] = [
+    "temperature",
+    "pressure",
+    "humidity",
+    "wind_speed",
+    "rain",
+    "snow",
+    "visibility",
+];
+
+/// How observations of one location from several sources (`server`) within
+/// `fusion_window` seconds are combined
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FusionMode {
+    /// Raw per-source observations
+    #[default]
+    Off,
+    /// Value of the first source in `fusion_sources` reporting the metric
+    Priority,
+    /// Average weighted by the `fusion_sources` weights
+    Weighted,
+}
+
+/// Source (`server`) of recorded observations and its weight
+#[derive(Debug, Clone, PartialEq)]
+pub struct FusionSource {
+    pub server: StackString,
+    pub weight: f64,
+}
+
+/// Combine co-located observations of several sources into one series
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fusion {
+    pub mode: FusionMode,
+    /// per metric overrides of `mode`
+    pub metric_modes: HashMap<StackString, FusionMode>,
+    /// sources in priority order, unlisted sources come last with weight 1
+    pub sources: Vec<FusionSource>,
+    /// seconds of the windows observations are grouped in
+    pub window: i64,
+}
+
+impl Fusion {
+    /// `None` when fusion is disabled by `fusion_mode` and
+    /// `fusion_metric_modes`
+    #[must_use]
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let fusion = Self {
+            mode: config.fusion_mode,
+            metric_modes: config.fusion_metric_modes.clone(),
+            sources: config.fusion_sources.clone(),
+            window: config.fusion_window as i64,
+        };
+        fusion.is_enabled().then_some(fusion)
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.window > 0
+            && (self.mode != FusionMode::Off
+                || self.metric_modes.values().any(|m| *m != FusionMode::Off))
+    }
+
+    fn get_mode(&self, metric: &str) -> FusionMode {
+        self.metric_modes.get(metric).copied().unwrap_or(self.mode)
+    }
+
+    fn get_priority(&self, server: &str) -> usize {
+        self.sources
+            .iter()
+            .position(|s| s.server == server)
+            .unwrap_or(self.sources.len())
+    }
+
+    fn get_weight(&self, server: &str) -> f64 {
+        self.sources
+            .iter()
+            .find(|s| s.server == server)
+            .map_or(1.0, |s| s.weight)
+    }
+
+    /// Weighted average or first value by priority of `metric`, sources
+    /// without a positive weight only fill in when no other source reports
+    /// the metric
+    fn combine(
+        &self,
+        metric: &str,
+        rows: &[&WeatherDataDB],
+        value: impl Fn(&WeatherDataDB) -> Option<f64>,
+    ) -> Option<f64> {
+        let first = || rows.iter().find_map(|row| value(row));
+        if self.get_mode(metric) != FusionMode::Weighted {
+            return first();
+        }
+        let (sum, total) = rows
+            .iter()
+            .filter_map(|row| Some((self.get_weight(&row.server), value(row)?)))
+            .filter(|(weight, _)| *weight > 0.0)
+            .fold((0.0, 0.0), |(sum, total), (weight, v)| {
+                (sum + weight * v, total + weight)
+            });
+        if total > 0.0 {
+            Some(sum / total)
+        } else {
+            first()
+        }
+    }
+
+    /// Observations of one window, the latest row of each source, ordered
+    /// by priority
+    fn fuse_window(&self, rows: Vec<WeatherDataDB>) -> WeatherDataDB {
+        let mut latest: HashMap<StackString, WeatherDataDB> = HashMap::new();
+        for row in rows {
+            match latest.get(&row.server) {
+                Some(existing) if existing.dt >= row.dt => {}
+                _ => {
+                    latest.insert(row.server.clone(), row);
+                }
+            }
+        }
+        let mut rows: Vec<_> = latest.into_values().collect();
+        rows.sort_by(|a, b| {
+            (self.get_priority(&a.server), &a.server)
+                .cmp(&(self.get_priority(&b.server), &b.server))
+        });
+        if rows.len() == 1 {
+            return rows.remove(0);
+        }
+        let sources: Vec<_> = rows.iter().collect();
+        let combine = |metric, value: fn(&WeatherDataDB) -> f64| {
+            self.combine(metric, &sources, |row| Some(value(row)))
+                .unwrap_or_else(|| value(sources[0]))
+        };
+        let temperature = combine("temperature", |row| row.temperature);
+        let temperature_minimum = combine("temperature", |row| row.temperature_minimum);
+        let temperature_maximum = combine("temperature", |row| row.temperature_maximum);
+        let pressure = combine("pressure", |row| row.pressure);
+        let humidity = combine("humidity", |row| f64::from(row.humidity)).round() as i32;
+        let wind_speed = combine("wind_speed", |row| row.wind_speed);
+        let rain = self.combine("rain", &sources, |row| row.rain);
+        let snow = self.combine("snow", &sources, |row| row.snow);
+        let visibility = self.combine("visibility", &sources, |row| row.visibility);
+        // directions can't be averaged linearly, the highest priority
+        // reading is kept
+        let wind_direction = sources.iter().find_map(|row| row.wind_direction);
+        let derived = DerivedMetrics::new(temperature, humidity, wind_speed);
+
+        let mut fused = rows.swap_remove(0);
+        fused.temperature = temperature;
+        fused.temperature_minimum = temperature_minimum;
+        fused.temperature_maximum = temperature_maximum;
+        fused.pressure = pressure;
+        fused.humidity = humidity;
+        fused.wind_speed = wind_speed;
+        fused.wind_direction = wind_direction;
+        fused.rain = rain;
+        fused.snow = snow;
+        fused.visibility = visibility;
+        fused.dew_point = derived.dew_point;
+        fused.heat_index = derived.heat_index;
+        fused.wind_chill = derived.wind_chill;
+        fused.server = FUSED_SERVER.into();
+        fused
+    }
+
+    /// One observation per location and `window`, windows with a single
+    /// source keep its latest row, otherwise the metrics of all sources are
+    /// combined into a row with `server` `fused` carrying the id, time and
+    /// conditions of the highest priority source
+    #[must_use]
+    pub fn fuse(&self, rows: Vec<WeatherDataDB>) -> Vec<WeatherDataDB> {
+        if !self.is_enabled() {
+            return rows;
+        }
+        let mut windows: BTreeMap<(i64, StackString), Vec<WeatherDataDB>> = BTreeMap::new();
+        for row in rows {
+            let window = i64::from(row.dt).div_euclid(self.window);
+            windows
+                .entry((window, row.location_name.clone()))
+                .or_default()
+                .push(row);
+        }
+        windows
+            .into_values()
+            .map(|rows| self.fuse_window(rows))
+            .collect()
+    }
+}
+
+/// `server:weight;server:weight` in priority order, the weight is optional
+/// and defaults to 1, e.g. `station:2;N/A`
+#[must_use]
+pub fn parse_fusion_source(entry: &str) -> Option<FusionSource> {
+    let (server, weight) = match entry.rsplit_once(':') {
+        Some((server, weight)) => (server, weight.trim().parse().ok()?),
+        None => (entry, 1.0),
+    };
+    let server = server.trim();
+    if server.is_empty() {
+        return None;
+    }
+    Some(FusionSource {
+        server: server.into(),
+        weight,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::hashmap;
+    use time::{macros::datetime, Duration, OffsetDateTime};
+
+    use crate::{
+        fusion::{parse_fusion_source, Fusion, FusionMode, FusionSource, FUSED_SERVER},
+        model::WeatherDataDB,
+    };
+
+    fn row(created_at: OffsetDateTime, server: &str, temperature: f64) -> WeatherDataDB {
+        WeatherDataDB {
+            temperature,
+            temperature_minimum: temperature,
+            temperature_maximum: temperature,
+            server: server.into(),
+            ..WeatherDataDB::test_row(created_at)
+        }
+    }
+
+    #[test]
+    fn test_fuse() {
+        assert_eq!(
+            parse_fusion_source("station:3"),
+            Some(FusionSource {
+                server: "station".into(),
+                weight: 3.0
+            })
+        );
+        assert_eq!(parse_fusion_source("N/A").map(|s| s.weight), Some(1.0));
+        assert!(parse_fusion_source("station:x").is_none());
+        assert!(parse_fusion_source(":2").is_none());
+
+        let start = datetime!(2024-06-01 12:00 UTC);
+        let mut station = row(start + Duration::minutes(2), "station", 290.0);
+        station.rain = Some(1.0);
+        let mut owm = row(start + Duration::minutes(5), "N/A", 294.0);
+        owm.pressure = 101.7;
+        owm.humidity = 60;
+        owm.wind_direction = Some(180.0);
+        let stale = row(start, "station", 280.0);
+        let later = row(start + Duration::minutes(40), "N/A", 295.0);
+        let later_id = later.id;
+        let rows = vec![owm, station.clone(), stale, later];
+
+        let mut fusion = Fusion {
+            mode: FusionMode::Priority,
+            metric_modes: hashmap! {"temperature".into() => FusionMode::Weighted},
+            sources: vec![
+                FusionSource {
+                    server: "station".into(),
+                    weight: 3.0,
+                },
+                FusionSource {
+                    server: "N/A".into(),
+                    weight: 1.0,
+                },
+            ],
+            window: 1800,
+        };
+        let fused = fusion.fuse(rows.clone());
+        assert_eq!(fused.len(), 2);
+        assert_eq!(fused[0].server, FUSED_SERVER);
+        assert_eq!(fused[0].id, station.id);
+        assert_eq!(fused[0].dt, station.dt);
+        assert!((fused[0].temperature - 291.0).abs() < 1e-9);
+        assert!((fused[0].pressure - 101.3).abs() < 1e-9);
+        assert_eq!(fused[0].humidity, 80);
+        assert_eq!(fused[0].rain, Some(1.0));
+        assert_eq!(fused[0].wind_direction, Some(180.0));
+        assert!(fused[0].dew_point.is_some());
+        assert_eq!(fused[1].id, later_id);
+        assert_eq!(fused[1].server, "N/A");
+
+        fusion.mode = FusionMode::Weighted;
+        let fused = fusion.fuse(rows.clone());
+        assert!((fused[0].pressure - 101.4).abs() < 1e-9);
+        assert_eq!(fused[0].humidity, 75);
+
+        fusion.mode = FusionMode::Off;
+        fusion.metric_modes.clear();
+        assert!(!fusion.is_enabled());
+        let ids: Vec<_> = fusion.fuse(rows.clone()).iter().map(|r| r.id).collect();
+        assert_eq!(ids, rows.iter().map(|r| r.id).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,128 @@
+use rweb::{
+    filters::{addr::remote, header::optional},
+    Filter, Rejection,
+};
+use stack_string::StackString;
+use std::net::{IpAddr, SocketAddr};
+
+#[cfg(feature = "geoip")]
+use log::{debug, error};
+#[cfg(feature = "geoip")]
+use maxminddb::{geoip2::City, Reader};
+#[cfg(feature = "geoip")]
+use once_cell::sync::OnceCell;
+
+use weather_util_rust::weather_api::WeatherLocation;
+
+use crate::{config::Config, rate_limit::client_ip};
+
+#[cfg(feature = "geoip")]
+static GEOIP_READER: OnceCell<Option<Reader<Vec<u8>>>> = OnceCell::new();
+
+#[cfg(feature = "geoip")]
+fn get_reader(config: &Config) -> Option<&'static Reader<Vec<u8>>> {
+    GEOIP_READER
+        .get_or_init(|| {
+            let path = config.geoip_database.as_ref()?;
+            Reader::open_readfile(path)
+                .map_err(|e| error!("failed to open geoip database {path:?} {e}"))
+                .ok()
+        })
+        .as_ref()
+}
+
+/// Whether the request asks not to be tracked (`DNT: 1` or `Sec-GPC: 1`)
+#[must_use]
+pub fn opted_out(dnt: Option<&str>, gpc: Option<&str>) -> bool {
+    [dnt, gpc]
+        .into_iter()
+        .flatten()
+        .any(|value| value.trim() == "1")
+}
+
+/// Peer address and `X-Forwarded-For` header of a visitor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisitorAddress {
+    pub forwarded_for: Option<StackString>,
+    pub remote: Option<SocketAddr>,
+}
+
+impl VisitorAddress {
+    /// The header is only read when the peer is one of `trusted_proxies`,
+    /// see `client_ip`
+    #[must_use]
+    pub fn ip(&self, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+        client_ip(self.forwarded_for.as_deref(), self.remote, trusted_proxies)
+    }
+}
+
+/// Address of the visitor, `None` when the visitor opted out of tracking
+pub fn visitor_ip() -> impl Filter<Extract = (Option<VisitorAddress>,), Error = Rejection> + Clone {
+    optional::<StackString>("x-forwarded-for")
+        .and(remote())
+        .and(optional::<StackString>("dnt"))
+        .and(optional::<StackString>("sec-gpc"))
+        .map(
+            |forwarded_for: Option<StackString>,
+             remote: Option<SocketAddr>,
+             dnt: Option<StackString>,
+             gpc: Option<StackString>| {
+                if opted_out(dnt.as_deref(), gpc.as_deref()) {
+                    None
+                } else {
+                    Some(VisitorAddress {
+                        forwarded_for,
+                        remote,
+                    })
+                }
+            },
+        )
+}
+
+/// Approximate (city level) location of `ip`, `None` without a configured
+/// database or for private / unknown addresses
+#[cfg(feature = "geoip")]
+#[must_use]
+pub fn lookup_location(config: &Config, ip: IpAddr) -> Option<WeatherLocation> {
+    let reader = get_reader(config)?;
+    let city: City = reader
+        .lookup(ip)
+        .map_err(|e| debug!("geoip lookup failed {e}"))
+        .ok()?;
+    let location = city.location?;
+    Some(WeatherLocation::from_lat_lon(
+        location.latitude?.try_into().ok()?,
+        location.longitude?.try_into().ok()?,
+    ))
+}
+
+/// Without the geoip feature there is no database to look visitors up in
+#[cfg(not(feature = "geoip"))]
+#[must_use]
+pub fn lookup_location(_: &Config, _: IpAddr) -> Option<WeatherLocation> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::{
+        config::Config,
+        geoip::{lookup_location, opted_out},
+    };
+
+    #[test]
+    fn test_opted_out() {
+        assert!(opted_out(Some("1"), None));
+        assert!(opted_out(None, Some("1")));
+        assert!(!opted_out(Some("0"), None));
+        assert!(!opted_out(None, None));
+    }
+
+    #[test]
+    fn test_lookup_without_database() {
+        let config = Config::default();
+        assert!(lookup_location(&config, Ipv4Addr::LOCALHOST.into()).is_none());
+    }
+}
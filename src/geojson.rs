@@ -0,0 +1,207 @@
+use rweb::Schema;
+use rweb_helper::DateTimeType;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::collections::HashMap;
+
+use crate::model::{WeatherDataDB, WeatherLocationCache};
+
+/// Most recent observation attached to a location feature
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "GeoJsonObservation")]
+pub struct GeoJsonObservation {
+    #[schema(description = "Observed At")]
+    pub observed_at: DateTimeType,
+    #[schema(description = "Server")]
+    pub server: StackString,
+    #[schema(description = "Conditions")]
+    pub condition: StackString,
+    #[schema(description = "Temperature (K)")]
+    pub temperature: f64,
+    #[schema(description = "Pressure (kPa)")]
+    pub pressure: f64,
+    #[schema(description = "Humidity (%)")]
+    pub humidity: i32,
+    #[schema(description = "Wind Speed (m/s)")]
+    pub wind_speed: f64,
+    #[schema(description = "Wind Direction (degrees)")]
+    pub wind_direction: Option<f64>,
+    #[schema(description = "Rain (mm)")]
+    pub rain: Option<f64>,
+    #[schema(description = "Snow (mm)")]
+    pub snow: Option<f64>,
+}
+
+impl From<WeatherDataDB> for GeoJsonObservation {
+    fn from(value: WeatherDataDB) -> Self {
+        Self {
+            observed_at: value.created_at.to_offsetdatetime().into(),
+            server: value.server,
+            condition: value.condition.trim().into(),
+            temperature: value.temperature,
+            pressure: value.pressure,
+            humidity: value.humidity,
+            wind_speed: value.wind_speed,
+            wind_direction: value.wind_direction,
+            rain: value.rain,
+            snow: value.snow,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "GeoJsonProperties")]
+pub struct GeoJsonProperties {
+    #[schema(description = "Location Name")]
+    pub location_name: StackString,
+    #[schema(description = "City Name")]
+    pub city_name: Option<StackString>,
+    #[schema(description = "Zipcode")]
+    pub zipcode: Option<i32>,
+    #[schema(description = "Country Code")]
+    pub country_code: Option<StackString>,
+    #[schema(description = "Most Recent Observation")]
+    pub observation: Option<GeoJsonObservation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "GeoJsonPoint")]
+pub struct GeoJsonPoint {
+    #[serde(rename = "type")]
+    #[schema(description = "Geometry Type (Point)")]
+    pub geometry_type: StackString,
+    #[schema(description = "Coordinates (longitude, latitude)")]
+    pub coordinates: Vec<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "GeoJsonFeature")]
+pub struct GeoJsonFeature {
+    #[serde(rename = "type")]
+    #[schema(description = "Object Type (Feature)")]
+    pub feature_type: StackString,
+    #[schema(description = "Geometry")]
+    pub geometry: GeoJsonPoint,
+    #[schema(description = "Properties")]
+    pub properties: GeoJsonProperties,
+}
+
+impl GeoJsonFeature {
+    #[must_use]
+    pub fn new(location: WeatherLocationCache, observation: Option<WeatherDataDB>) -> Self {
+        Self {
+            feature_type: "Feature".into(),
+            geometry: GeoJsonPoint {
+                geometry_type: "Point".into(),
+                coordinates: vec![location.longitude, location.latitude],
+            },
+            properties: GeoJsonProperties {
+                location_name: location.location_name,
+                city_name: location.city_name,
+                zipcode: location.zipcode,
+                country_code: location.country_code,
+                observation: observation.map(Into::into),
+            },
+        }
+    }
+}
+
+/// RFC 7946 feature collection of points
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "GeoJsonFeatureCollection")]
+pub struct GeoJsonFeatureCollection {
+    #[serde(rename = "type")]
+    #[schema(description = "Object Type (FeatureCollection)")]
+    pub collection_type: StackString,
+    #[schema(description = "Features")]
+    pub features: Vec<GeoJsonFeature>,
+}
+
+impl GeoJsonFeatureCollection {
+    /// One feature per cached location, with the latest observation recorded
+    /// under its location name
+    #[must_use]
+    pub fn new(
+        locations: impl IntoIterator<Item = WeatherLocationCache>,
+        observations: impl IntoIterator<Item = WeatherDataDB>,
+    ) -> Self {
+        let mut observations: HashMap<StackString, WeatherDataDB> = observations
+            .into_iter()
+            .map(|obs| (obs.location_name.clone(), obs))
+            .collect();
+        let features = locations
+            .into_iter()
+            .map(|location| {
+                let observation = observations.remove(&location.location_name);
+                GeoJsonFeature::new(location, observation)
+            })
+            .collect();
+        Self {
+            collection_type: "FeatureCollection".into(),
+            features,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use serde_json::json;
+    use time::macros::datetime;
+
+    use crate::{
+        geojson::GeoJsonFeatureCollection,
+        model::{WeatherDataDB, WeatherLocationCache},
+    };
+
+    #[test]
+    fn test_feature_collection() -> Result<(), Error> {
+        let paris = WeatherLocationCache {
+            location_name: "Paris".into(),
+            latitude: 48.86,
+            longitude: 2.35,
+            country_code: Some("FR".into()),
+            ..WeatherLocationCache::default()
+        };
+        let ny = WeatherLocationCache {
+            location_name: "10001".into(),
+            latitude: 40.75,
+            longitude: -73.99,
+            zipcode: Some(10001),
+            ..WeatherLocationCache::default()
+        };
+        let created_at = datetime!(2024-06-01 12:00 UTC);
+        let observation = WeatherDataDB {
+            location_name: "Paris".into(),
+            latitude: 48.86,
+            longitude: 2.35,
+            condition: "Clear clear sky ".into(),
+            condition_code: Some(800),
+            temperature: 293.15,
+            temperature_minimum: 291.0,
+            temperature_maximum: 295.0,
+            humidity: 50,
+            wind_speed: 3.0,
+            wind_direction: Some(180.0),
+            country: "FR".into(),
+            timezone: 7200,
+            ..WeatherDataDB::test_row(created_at)
+        };
+
+        let collection = GeoJsonFeatureCollection::new([paris, ny], [observation]);
+        let value = serde_json::to_value(&collection)?;
+        assert_eq!(value["type"], "FeatureCollection");
+        assert_eq!(value["features"][0]["type"], "Feature");
+        assert_eq!(
+            value["features"][0]["geometry"],
+            json!({"type": "Point", "coordinates": [2.35, 48.86]})
+        );
+        assert_eq!(value["features"][0]["properties"]["country_code"], "FR");
+        let observation = &value["features"][0]["properties"]["observation"];
+        assert_eq!(observation["temperature"], 293.15);
+        assert_eq!(observation["condition"], "Clear clear sky");
+        assert_eq!(value["features"][1]["properties"]["zipcode"], 10001);
+        assert!(value["features"][1]["properties"]["observation"].is_null());
+        Ok(())
+    }
+}
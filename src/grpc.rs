@@ -0,0 +1,229 @@
+use anyhow::Error;
+use futures::{Stream, TryStreamExt};
+use log::info;
+use stack_string::format_sstr;
+use std::{net::SocketAddr, pin::Pin};
+use time::{macros::format_description, Date};
+use tonic::{transport::Server, Request, Response, Status};
+
+use weather_api_types::get_parameters;
+use weather_util_rust::weather_forecast::{ForecastEntry as WeatherForecastEntry, WeatherForecast};
+
+use crate::{app::AppState, errors::ServiceError, model::WeatherDataDB, pgpool::DatabaseDisabled};
+
+pub mod proto {
+    #![allow(clippy::pedantic, clippy::nursery)]
+    tonic::include_proto!("weather");
+}
+
+use proto::{
+    weather_service_server::{WeatherService, WeatherServiceServer},
+    Forecast, ForecastEntry, HistoryRequest, LocationRequest, Observation,
+};
+
+impl From<WeatherDataDB> for Observation {
+    fn from(value: WeatherDataDB) -> Self {
+        Self {
+            dt: value.dt.into(),
+            location_name: value.location_name.into(),
+            latitude: value.latitude,
+            longitude: value.longitude,
+            condition: value.condition.trim().into(),
+            temperature: value.temperature,
+            temperature_minimum: value.temperature_minimum,
+            temperature_maximum: value.temperature_maximum,
+            pressure: value.pressure,
+            humidity: value.humidity,
+            visibility: value.visibility,
+            rain: value.rain,
+            snow: value.snow,
+            wind_speed: value.wind_speed,
+            wind_direction: value.wind_direction,
+            country: value.country.into(),
+            sunrise: value.sunrise.unix_timestamp(),
+            sunset: value.sunset.unix_timestamp(),
+            timezone: value.timezone,
+            server: value.server.into(),
+        }
+    }
+}
+
+impl From<&WeatherForecastEntry> for ForecastEntry {
+    fn from(entry: &WeatherForecastEntry) -> Self {
+        let conditions: Vec<_> = entry
+            .weather
+            .iter()
+            .map(|w| format_sstr!("{} {}", w.main, w.description))
+            .collect();
+        Self {
+            dt: entry.dt.unix_timestamp(),
+            temperature: entry.main.temp.kelvin(),
+            feels_like: entry.main.feels_like.kelvin(),
+            temperature_minimum: entry.main.temp_min.kelvin(),
+            temperature_maximum: entry.main.temp_max.kelvin(),
+            pressure: entry.main.pressure.kpa(),
+            humidity: entry.main.humidity.into(),
+            wind_speed: entry.wind.speed.mps(),
+            wind_direction: entry.wind.deg.map(|d| d.deg()),
+            condition: conditions.join(", "),
+            rain: entry
+                .rain
+                .as_ref()
+                .and_then(|r| r.three_hour)
+                .map(|r| r.millimeters()),
+            snow: entry
+                .snow
+                .as_ref()
+                .and_then(|s| s.three_hour)
+                .map(|s| s.millimeters()),
+        }
+    }
+}
+
+impl From<WeatherForecast> for Forecast {
+    fn from(value: WeatherForecast) -> Self {
+        Self {
+            timezone: value.city.timezone.into(),
+            sunrise: value.city.sunrise.unix_timestamp(),
+            sunset: value.city.sunset.unix_timestamp(),
+            entries: value.list.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<ServiceError> for Status {
+    fn from(value: ServiceError) -> Self {
+        match value {
+            ServiceError::BadRequest(message) => Self::invalid_argument(message.as_str()),
+            ServiceError::Unauthorized => Self::unauthenticated("Unauthorized"),
+            ServiceError::Forbidden(message) => Self::permission_denied(message.as_str()),
+            ServiceError::AnyhowError(e) => anyhow_status(&e),
+            e => Self::internal(e.to_string()),
+        }
+    }
+}
+
+fn anyhow_status(e: &Error) -> Status {
+    if e.downcast_ref::<DatabaseDisabled>().is_some() {
+        Status::unimplemented(e.to_string())
+    } else {
+        Status::internal(e.to_string())
+    }
+}
+
+fn parse_date(s: Option<&str>) -> Result<Option<Date>, Status> {
+    s.map(|s| {
+        Date::parse(s, format_description!("[year]-[month]-[day]"))
+            .map_err(|e| Status::invalid_argument(format!("invalid date {s}: {e}")))
+    })
+    .transpose()
+}
+
+/// gRPC front end of the `AppState` shared with the REST api
+pub struct WeatherGrpc {
+    app: AppState,
+}
+
+#[tonic::async_trait]
+impl WeatherService for WeatherGrpc {
+    type StreamHistoryStream = Pin<Box<dyn Stream<Item = Result<Observation, Status>> + Send>>;
+
+    async fn get_weather(
+        &self,
+        request: Request<LocationRequest>,
+    ) -> Result<Response<Observation>, Status> {
+        let loc = get_parameters(&request.into_inner().location);
+        let app = &self.app;
+        let weather = app.weather.get_weather(&app.api, &loc).await?;
+        let mut observation: Observation = WeatherDataDB::from(weather).into();
+        observation.server = app.config.server.to_string();
+        Ok(Response::new(observation))
+    }
+
+    async fn get_forecast(
+        &self,
+        request: Request<LocationRequest>,
+    ) -> Result<Response<Forecast>, Status> {
+        let loc = get_parameters(&request.into_inner().location);
+        let app = &self.app;
+        let forecast = app.weather.get_forecast(&app.api, &loc).await?;
+        Ok(Response::new(forecast.into()))
+    }
+
+    async fn stream_history(
+        &self,
+        request: Request<HistoryRequest>,
+    ) -> Result<Response<Self::StreamHistoryStream>, Status> {
+        let request = request.into_inner();
+        let start_date = parse_date(request.start_date.as_deref())?;
+        let end_date = parse_date(request.end_date.as_deref())?;
+        let rows = WeatherDataDB::get_by_name_dates(
+            &self.app.pool,
+            request.name.as_deref(),
+            request.server.as_deref(),
+            start_date,
+            end_date,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .map_err(|e| anyhow_status(&e))?
+        .map_ok(Into::into)
+        .map_err(|e| Status::internal(e.to_string()));
+        Ok(Response::new(Box::pin(rows)))
+    }
+}
+
+/// Serve the gRPC api on `port` until the server fails
+///
+/// # Errors
+/// Returns error if the address can't be bound
+pub async fn run_grpc_server(app: AppState, port: u32) -> Result<(), Error> {
+    let host = &app.config.host;
+    let addr: SocketAddr = format_sstr!("{host}:{port}").parse()?;
+    info!("grpc listening on {addr}");
+    Server::builder()
+        .add_service(WeatherServiceServer::new(WeatherGrpc { app }))
+        .serve(addr)
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::{grpc::proto::Observation, model::WeatherDataDB};
+
+    #[test]
+    fn test_observation_from_db() {
+        let created_at = datetime!(2024-06-01 12:00 UTC);
+        let row = WeatherDataDB {
+            location_name: "Paris".into(),
+            latitude: 48.86,
+            longitude: 2.35,
+            condition: "Clear clear sky ".into(),
+            condition_code: Some(800),
+            temperature: 293.15,
+            temperature_minimum: 291.0,
+            temperature_maximum: 295.0,
+            humidity: 50,
+            rain: Some(0.5),
+            wind_speed: 3.0,
+            wind_direction: Some(180.0),
+            country: "FR".into(),
+            timezone: 7200,
+            ..WeatherDataDB::test_row(created_at)
+        };
+        let observation: Observation = row.into();
+        assert_eq!(observation.dt, created_at.unix_timestamp());
+        assert_eq!(observation.condition, "Clear clear sky");
+        assert_eq!(observation.rain, Some(0.5));
+        assert_eq!(observation.snow, None);
+        assert_eq!(observation.sunrise, created_at.unix_timestamp());
+        assert_eq!(observation.server, "test");
+    }
+}
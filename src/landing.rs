@@ -0,0 +1,181 @@
+use dioxus::prelude::{component, dioxus_elements, rsx, Element, IntoDynNode, Props};
+use stack_string::{format_sstr, StackString};
+use std::{fmt::Write, net::SocketAddr};
+
+use weather_util_rust::weather_data::WeatherData;
+
+/// Page listed on the `/weather` landing page and in the startup banner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LandingLink {
+    pub title: StackString,
+    pub url: StackString,
+    pub description: StackString,
+}
+
+impl LandingLink {
+    fn new(title: &str, url: &str, description: &str) -> Self {
+        Self {
+            title: title.into(),
+            url: url.into(),
+            description: description.into(),
+        }
+    }
+}
+
+/// Pages served by this deployment, the wasm app is only listed when its url
+/// is configured
+#[must_use]
+pub fn get_landing_links(wasm_app_url: Option<&str>) -> Vec<LandingLink> {
+    let mut links = vec![
+        LandingLink::new(
+            "Current Weather",
+            "/weather/index.html",
+            "current conditions and forecast",
+        ),
+        LandingLink::new(
+            "Forecast Plots",
+            "/weather/plot.html",
+            "temperature and precipitation forecast",
+        ),
+        LandingLink::new(
+            "History Plots",
+            "/weather/history_plot.html",
+            "recorded observations",
+        ),
+        LandingLink::new(
+            "Compared to Yesterday",
+            "/weather/compare/yesterday.html",
+            "current conditions against the same time yesterday",
+        ),
+        LandingLink::new(
+            "Tropical Storms",
+            "/weather/tropical.html",
+            "active storms near the default location",
+        ),
+    ];
+    if let Some(wasm_app_url) = wasm_app_url {
+        links.push(LandingLink::new(
+            "Weather App",
+            wasm_app_url,
+            "interactive wasm frontend",
+        ));
+    }
+    links.push(LandingLink::new(
+        "API Docs (json)",
+        "/weather/openapi/json",
+        "openapi specification",
+    ));
+    links.push(LandingLink::new(
+        "API Docs (yaml)",
+        "/weather/openapi/yaml",
+        "openapi specification",
+    ));
+    links
+}
+
+/// One line summary of the current conditions shown on the landing page
+#[must_use]
+pub fn get_conditions_summary(weather: &WeatherData) -> String {
+    let conditions = weather
+        .weather
+        .iter()
+        .map(|w| w.description.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let humidity: i64 = weather.main.humidity.into();
+    format!(
+        "{}: {:0.1} F / {:0.1} C, {humidity}% humidity, {conditions}",
+        weather.name,
+        weather.main.temp.fahrenheit(),
+        weather.main.temp.celcius(),
+    )
+}
+
+/// Log message listing the listen address and the served pages
+#[must_use]
+pub fn get_startup_banner(
+    addr: &SocketAddr,
+    landing_page: bool,
+    links: &[LandingLink],
+) -> StackString {
+    let mut banner = format_sstr!(
+        "weather_api_rust {} listening on http://{addr}",
+        env!("CARGO_PKG_VERSION")
+    );
+    if landing_page {
+        write!(banner, "\n  {:<24} http://{addr}/weather", "Landing Page").unwrap_or(());
+    }
+    for link in links {
+        if link.url.starts_with('/') {
+            write!(banner, "\n  {:<24} http://{addr}{}", link.title, link.url).unwrap_or(());
+        } else {
+            write!(banner, "\n  {:<24} {}", link.title, link.url).unwrap_or(());
+        }
+    }
+    banner
+}
+
+#[component]
+pub fn LandingComponent(
+    title: String,
+    conditions: Option<String>,
+    links: Vec<LandingLink>,
+) -> Element {
+    let conditions = conditions.unwrap_or_else(|| "Current conditions are unavailable".into());
+    let rows = links.iter().map(|link| {
+        let url = &link.url;
+        let link_title = &link.title;
+        let description = &link.description;
+        rsx! {
+            tr {
+                key: "link-{url}",
+                td { a { href: "{url}", "{link_title}" } },
+                td {"{description}"},
+            }
+        }
+    });
+    rsx! {
+        head {
+            title: "{title}",
+            style {
+                {include_str!("../templates/style.css")}
+            }
+        },
+        body {
+            h3 {"{title}"},
+            p {"{conditions}"},
+            table {
+                tbody {
+                    {rows}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use std::net::SocketAddr;
+
+    use crate::landing::{get_landing_links, get_startup_banner};
+
+    #[test]
+    fn test_startup_banner() -> Result<(), Error> {
+        let addr: SocketAddr = "127.0.0.1:3097".parse()?;
+        let links = get_landing_links(None);
+        assert!(links.iter().all(|link| link.title != "Weather App"));
+        let banner = get_startup_banner(&addr, true, &links);
+        assert!(banner.contains("listening on http://127.0.0.1:3097"));
+        assert!(banner.contains("http://127.0.0.1:3097/weather\n"));
+        assert!(banner.contains("http://127.0.0.1:3097/weather/index.html"));
+        assert!(banner.contains("http://127.0.0.1:3097/weather/openapi/yaml"));
+
+        let links = get_landing_links(Some("https://example.com/weather_app"));
+        assert_eq!(links.len(), 8);
+        let banner = get_startup_banner(&addr, false, &links);
+        assert!(!banner.contains("http://127.0.0.1:3097/weather\n"));
+        assert!(banner.contains(" https://example.com/weather_app"));
+        Ok(())
+    }
+}
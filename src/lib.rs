@@ -10,21 +10,82 @@
 #![allow(clippy::unsafe_derive_deserialize)]
 #![allow(clippy::missing_errors_doc)]
 
+pub mod accuracy;
+pub mod air_quality;
+pub mod alerts;
+pub mod analysis;
 pub mod api_options;
 pub mod app;
+pub mod area;
+pub mod astronomy;
+pub mod attribution;
+pub mod backfill;
+pub mod barometer;
+pub mod bench;
+pub mod cache_key;
+pub mod compact;
+pub mod condition;
 pub mod config;
 pub mod country_code_wrapper;
+pub mod coverage;
 pub mod date_time_wrapper;
+pub mod degraded;
+pub mod demo;
+pub mod derived_metrics;
+pub mod downsample;
 pub mod errors;
+pub mod etag;
+pub mod events;
+pub mod export;
+#[cfg(feature = "export-package")]
+pub mod export_package;
+pub mod feed;
+pub mod fusion;
+pub mod geoip;
+pub mod geojson;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod landing;
 pub mod latitude_wrapper;
+pub mod lightning;
 pub mod logged_user;
+pub mod logging;
 pub mod longitude_wrapper;
+pub mod metrics;
 pub mod model;
+pub mod negotiate;
+pub mod notify;
+pub mod offline_forecast;
+pub mod onecall;
+pub mod openapi_diff;
 pub mod parse_opts;
 pub mod pgpool;
+#[cfg(feature = "analysis")]
 pub mod polars_analysis;
+pub mod precision;
+pub mod privacy;
+pub mod providers;
+pub mod publish;
+pub mod quality;
+pub mod rate_limit;
+pub mod render_stats;
+pub mod report;
+#[cfg(feature = "analysis")]
+pub mod resample;
+pub mod retention;
 pub mod routes;
+#[cfg(feature = "s3-sync")]
 pub mod s3_sync;
+pub mod services;
+pub mod signed_url;
+pub mod snapshots;
+pub mod stream;
+#[cfg(feature = "otlp")]
+pub mod telemetry;
+pub mod tropical;
+pub mod units_wrapper;
+pub mod upstream_queue;
+pub mod uv_index;
 
 use anyhow::{format_err, Error};
 use api_options::ApiOptions;
@@ -34,15 +95,26 @@ use rand::{
     distributions::{Distribution, Uniform},
     thread_rng,
 };
-use rweb::Schema;
-use rweb_helper::{derive_rweb_schema, DateTimeType, UuidWrapper};
-use serde::{Deserialize, Serialize};
+use rweb::{
+    openapi::{ComponentDescriptor, ComponentOrInlineSchema, Entity},
+    Schema,
+};
+use rweb_helper::{derive_rweb_schema, DateTimeType, DateType, UuidWrapper};
+use serde::{ser, Deserialize, Serialize, Serializer};
 use stack_string::StackString;
-use std::{future::Future, path::Path, time::Duration};
+use std::{borrow::Cow, future::Future, path::Path, time::Duration};
 use time::UtcOffset;
 use tokio::{process::Command, time::sleep};
-
-use weather_api_common::weather_element::{PlotData, PlotPoint};
+use tracing::instrument;
+
+use weather_api_types::{
+    dto::{
+        ComparisonReading, LocationCount, PaginatedLocationCount, Pagination, PrecipitationSummary,
+        WeatherComparison,
+    },
+    plot::{PlotData, PlotPoint},
+    units::Units,
+};
 use weather_util_rust::{
     precipitation::Precipitation,
     weather_api::GeoLocation,
@@ -51,7 +123,17 @@ use weather_util_rust::{
     StringType,
 };
 
-use crate::model::WeatherDataDB;
+use crate::{
+    attribution::Attribution,
+    barometer::PressureTendency,
+    derived_metrics::DerivedMetrics,
+    model::{HistoryFields, UvIndexData, WeatherDataChange, WeatherDataDB, WeatherForecastDB},
+    onecall::{
+        DailyFeelsLike, DailyTemperature, OneCall, OneCallCurrent, OneCallDaily, OneCallHourly,
+        OneCallMinutely, OneHourPrecipitation,
+    },
+    publish::DailySummary,
+};
 
 #[derive(Into, From, Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct CoordWrapper(Coord);
@@ -91,6 +173,8 @@ struct _WeatherDataDBWrapper {
     longitude: f64,
     #[schema(description = "Condition")]
     condition: StringType,
+    #[schema(description = "Condition Code (openweathermap id)")]
+    condition_code: Option<i32>,
     #[schema(description = "Temperature (K)")]
     temperature: f64,
     #[schema(description = "Minimum Temperature (K)")]
@@ -121,6 +205,113 @@ struct _WeatherDataDBWrapper {
     timezone: i32,
     #[schema(description = "Server (dilepton-tower/dilepton-cloud)")]
     server: StringType,
+    #[schema(description = "Dew Point (K)")]
+    dew_point: Option<f64>,
+    #[schema(description = "Heat Index (K)")]
+    heat_index: Option<f64>,
+    #[schema(description = "Wind Chill (K)")]
+    wind_chill: Option<f64>,
+    #[schema(description = "Filled In From A Historical Api")]
+    backfilled: bool,
+    #[schema(description = "Inserted Into The Database Datetime")]
+    inserted_at: Option<DateTimeType>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct WeatherDataChangeWrapper(WeatherDataChange);
+
+derive_rweb_schema!(WeatherDataChangeWrapper, _WeatherDataChangeWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "WeatherDataChange")]
+struct _WeatherDataChangeWrapper {
+    #[schema(description = "Change Sequence Number")]
+    seq: i64,
+    #[schema(description = "Inserted or Updated Row")]
+    data: WeatherDataDBWrapper,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct WeatherForecastDBWrapper(WeatherForecastDB);
+
+derive_rweb_schema!(WeatherForecastDBWrapper, _WeatherForecastDBWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "WeatherForecastDB")]
+struct _WeatherForecastDBWrapper {
+    #[schema(description = "ID")]
+    id: UuidWrapper,
+    #[schema(description = "Location Name")]
+    location_name: StringType,
+    #[schema(description = "Server (dilepton-tower/dilepton-cloud)")]
+    server: StringType,
+    #[schema(description = "Fetched At Datetime")]
+    fetched_at: DateTimeType,
+    #[schema(description = "Forecast Datetime")]
+    forecast_time: DateTimeType,
+    #[schema(description = "Temperature (K)")]
+    temperature: f64,
+    #[schema(description = "Humidity (percent)")]
+    humidity: i32,
+    #[schema(description = "Pressure (kPa)")]
+    pressure: f64,
+    #[schema(description = "Wind Speed (m/s)")]
+    wind_speed: f64,
+    #[schema(description = "Rain and Snow (mm per 3 hours)")]
+    precipitation: Option<f64>,
+    #[schema(description = "Condition")]
+    condition: Option<StringType>,
+    #[schema(description = "Condition Code (openweathermap id)")]
+    condition_code: Option<i32>,
+    #[schema(description = "Feels Like Temperature (K)")]
+    feels_like: Option<f64>,
+    #[schema(description = "Minimum Temperature (K)")]
+    temperature_minimum: Option<f64>,
+    #[schema(description = "Maximum Temperature (K)")]
+    temperature_maximum: Option<f64>,
+    #[schema(description = "Rain (mm per 3 hours)")]
+    rain: Option<f64>,
+    #[schema(description = "Snow (mm per 3 hours)")]
+    snow: Option<f64>,
+    #[schema(description = "Wind Direction (degrees)")]
+    wind_direction: Option<f64>,
+    #[schema(description = "Timezone UTC Offset (seconds)")]
+    timezone: Option<i32>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct DailySummaryWrapper(DailySummary);
+
+derive_rweb_schema!(DailySummaryWrapper, _DailySummaryWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "DailySummary")]
+struct _DailySummaryWrapper {
+    #[schema(description = "Location Name")]
+    location_name: StringType,
+    #[schema(description = "Local Date")]
+    date: DateType,
+    #[schema(description = "Minimum Temperature (K)")]
+    temperature_low: f64,
+    #[schema(description = "Maximum Temperature (K)")]
+    temperature_high: f64,
+    #[schema(description = "Average Temperature (K)")]
+    temperature_mean: f64,
+    #[schema(description = "Average Humidity (%)")]
+    humidity_mean: f64,
+    #[schema(description = "Average Wind Speed (m/s)")]
+    wind_speed_mean: f64,
+    #[schema(description = "Total Rain (mm)")]
+    rain: f64,
+    #[schema(description = "Total Snow (mm)")]
+    snow: f64,
+    #[schema(description = "Most Frequent Condition")]
+    conditions: StringType,
+    #[schema(description = "Number of Observations")]
+    observations: usize,
 }
 
 // Weather Data
@@ -153,6 +344,129 @@ struct _WeatherDataWrapper {
     name: StringType,
 }
 
+/// Response body with the temperatures, wind speeds and precipitation of `T`
+/// converted to `units` when serialized, `T` keeps the standard units.
+#[derive(Debug, Clone)]
+pub struct WithUnits<T> {
+    pub data: T,
+    pub units: Units,
+}
+
+impl<T> WithUnits<T> {
+    pub fn new(data: T, units: Units) -> Self {
+        Self { data, units }
+    }
+}
+
+impl<T: Serialize> Serialize for WithUnits<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut value = serde_json::to_value(&self.data).map_err(ser::Error::custom)?;
+        self.units.convert_json(&mut value);
+        value.serialize(serializer)
+    }
+}
+
+impl<T: Entity> Entity for WithUnits<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    #[inline]
+    fn describe(comp_d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(comp_d)
+    }
+}
+
+/// Rows `T` reduced to `fields` when serialized, documented as the full rows
+#[derive(Debug, Clone)]
+pub struct WithFields<T> {
+    pub data: T,
+    pub fields: Option<HistoryFields>,
+}
+
+impl<T> WithFields<T> {
+    pub fn new(data: T, fields: Option<HistoryFields>) -> Self {
+        Self { data, fields }
+    }
+}
+
+impl<T: Serialize> Serialize for WithFields<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let Some(fields) = &self.fields else {
+            return self.data.serialize(serializer);
+        };
+        let mut value = serde_json::to_value(&self.data).map_err(ser::Error::custom)?;
+        if let serde_json::Value::Array(rows) = &mut value {
+            rows.iter_mut().for_each(|row| fields.project(row));
+        } else {
+            fields.project(&mut value);
+        }
+        value.serialize(serializer)
+    }
+}
+
+impl<T: Entity> Entity for WithFields<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    #[inline]
+    fn describe(comp_d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(comp_d)
+    }
+}
+
+// Weather Data with optional clothing advice
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WeatherDataAdviceWrapper {
+    #[serde(flatten)]
+    pub weather: WeatherData,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub advice: Option<StackString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Attribution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tendency: Option<PressureTendency>,
+}
+
+derive_rweb_schema!(WeatherDataAdviceWrapper, _WeatherDataAdviceWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "WeatherDataAdvice")]
+struct _WeatherDataAdviceWrapper {
+    #[schema(description = "Coordinates")]
+    coord: CoordWrapper,
+    #[schema(description = "Weather Conditions")]
+    weather: Vec<WeatherCondWrapper>,
+    base: StringType,
+    main: WeatherMainWrapper,
+    #[schema(description = "Visibility (m)")]
+    visibility: Option<f64>,
+    wind: WindWrapper,
+    rain: Option<RainWrapper>,
+    snow: Option<SnowWrapper>,
+    #[schema(description = "Current Datetime (Unix Timestamp)")]
+    dt: DateTimeType,
+    sys: SysWrapper,
+    #[schema(description = "Timezone (seconds offset from UTC)")]
+    timezone: i32,
+    #[schema(description = "Location Name")]
+    name: StringType,
+    #[schema(description = "Clothing Advice")]
+    advice: Option<StringType>,
+    #[schema(description = "Attribution")]
+    meta: Option<Attribution>,
+    #[schema(description = "Pressure Tendency over 3 Hours from Recorded Data")]
+    tendency: Option<PressureTendency>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WeatherCondWrapper(WeatherCond);
 
@@ -277,6 +591,202 @@ struct _WeatherForecastWrapper {
     city: CityEntryWrapper,
 }
 
+// Weather Forecast with optional attribution
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WeatherForecastMetaWrapper {
+    #[serde(flatten)]
+    pub forecast: WeatherForecast,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Attribution>,
+}
+
+derive_rweb_schema!(WeatherForecastMetaWrapper, _WeatherForecastMetaWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "WeatherForecastMeta")]
+struct _WeatherForecastMetaWrapper {
+    #[schema(description = "Main Forecast Entries")]
+    list: Vec<ForecastEntryWrapper>,
+    #[schema(description = "City Information")]
+    city: CityEntryWrapper,
+    #[schema(description = "Attribution")]
+    meta: Option<Attribution>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct OneCallCurrentWrapper(OneCallCurrent);
+
+derive_rweb_schema!(OneCallCurrentWrapper, _OneCallCurrentWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "OneCallCurrent")]
+struct _OneCallCurrentWrapper {
+    #[schema(description = "Current Datetime (Unix Timestamp)")]
+    dt: DateTimeType,
+    #[schema(description = "Sunrise (Unix Timestamp)")]
+    sunrise: DateTimeType,
+    #[schema(description = "Sunset (Unix Timestamp)")]
+    sunset: DateTimeType,
+    #[schema(description = "Temperature (K)")]
+    temp: f64,
+    #[schema(description = "Feels Like Temperature (K)")]
+    feels_like: f64,
+    #[schema(description = "Atmospheric Pressure (hPa, h=10^2)")]
+    pressure: f64,
+    #[schema(description = "Humidity %")]
+    humidity: i64,
+    #[schema(description = "Dew Point (K)")]
+    dew_point: f64,
+    #[schema(description = "UV Index")]
+    uvi: f64,
+    #[schema(description = "Cloudiness %")]
+    clouds: i64,
+    #[schema(description = "Visibility (m)")]
+    visibility: Option<f64>,
+    #[schema(description = "Wind Speed (m/s)")]
+    wind_speed: f64,
+    #[schema(description = "Wind Direction (degrees)")]
+    wind_deg: f64,
+    #[schema(description = "Wind Gust (m/s)")]
+    wind_gust: Option<f64>,
+    #[schema(description = "Weather Conditions")]
+    weather: Vec<WeatherCondWrapper>,
+    rain: Option<OneHourPrecipitation>,
+    snow: Option<OneHourPrecipitation>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct OneCallMinutelyWrapper(OneCallMinutely);
+
+derive_rweb_schema!(OneCallMinutelyWrapper, _OneCallMinutelyWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "OneCallMinutely")]
+struct _OneCallMinutelyWrapper {
+    #[schema(description = "Forecasted Datetime (Unix Timestamp)")]
+    dt: DateTimeType,
+    #[schema(description = "Precipitation (mm/h)")]
+    precipitation: f64,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct OneCallHourlyWrapper(OneCallHourly);
+
+derive_rweb_schema!(OneCallHourlyWrapper, _OneCallHourlyWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "OneCallHourly")]
+struct _OneCallHourlyWrapper {
+    #[schema(description = "Forecasted Datetime (Unix Timestamp)")]
+    dt: DateTimeType,
+    #[schema(description = "Temperature (K)")]
+    temp: f64,
+    #[schema(description = "Feels Like Temperature (K)")]
+    feels_like: f64,
+    #[schema(description = "Atmospheric Pressure (hPa, h=10^2)")]
+    pressure: f64,
+    #[schema(description = "Humidity %")]
+    humidity: i64,
+    #[schema(description = "Dew Point (K)")]
+    dew_point: f64,
+    #[schema(description = "UV Index")]
+    uvi: f64,
+    #[schema(description = "Cloudiness %")]
+    clouds: i64,
+    #[schema(description = "Visibility (m)")]
+    visibility: Option<f64>,
+    #[schema(description = "Wind Speed (m/s)")]
+    wind_speed: f64,
+    #[schema(description = "Wind Direction (degrees)")]
+    wind_deg: f64,
+    #[schema(description = "Wind Gust (m/s)")]
+    wind_gust: Option<f64>,
+    #[schema(description = "Weather Conditions")]
+    weather: Vec<WeatherCondWrapper>,
+    #[schema(description = "Probability of Precipitation")]
+    pop: f64,
+    rain: Option<OneHourPrecipitation>,
+    snow: Option<OneHourPrecipitation>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct OneCallDailyWrapper(OneCallDaily);
+
+derive_rweb_schema!(OneCallDailyWrapper, _OneCallDailyWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "OneCallDaily")]
+struct _OneCallDailyWrapper {
+    #[schema(description = "Forecasted Date, Local Noon (Unix Timestamp)")]
+    dt: DateTimeType,
+    #[schema(description = "Sunrise (Unix Timestamp)")]
+    sunrise: DateTimeType,
+    #[schema(description = "Sunset (Unix Timestamp)")]
+    sunset: DateTimeType,
+    #[schema(description = "Moon Phase (0 and 1 are new moon, 0.5 is full moon)")]
+    moon_phase: f64,
+    #[schema(description = "Human Readable Summary")]
+    summary: Option<StringType>,
+    temp: DailyTemperature,
+    feels_like: DailyFeelsLike,
+    #[schema(description = "Atmospheric Pressure (hPa, h=10^2)")]
+    pressure: f64,
+    #[schema(description = "Humidity %")]
+    humidity: i64,
+    #[schema(description = "Dew Point (K)")]
+    dew_point: f64,
+    #[schema(description = "Wind Speed (m/s)")]
+    wind_speed: f64,
+    #[schema(description = "Wind Direction (degrees)")]
+    wind_deg: f64,
+    #[schema(description = "Wind Gust (m/s)")]
+    wind_gust: Option<f64>,
+    #[schema(description = "Weather Conditions")]
+    weather: Vec<WeatherCondWrapper>,
+    #[schema(description = "Cloudiness %")]
+    clouds: i64,
+    #[schema(description = "Probability of Precipitation")]
+    pop: f64,
+    #[schema(description = "Rain (mm)")]
+    rain: Option<f64>,
+    #[schema(description = "Snow (mm)")]
+    snow: Option<f64>,
+    #[schema(description = "UV Index")]
+    uvi: f64,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct OneCallWrapper(OneCall);
+
+derive_rweb_schema!(OneCallWrapper, _OneCallWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "OneCall")]
+struct _OneCallWrapper {
+    #[schema(description = "Latitude")]
+    lat: f64,
+    #[schema(description = "Longitude")]
+    lon: f64,
+    #[schema(description = "Timezone Name")]
+    timezone: StringType,
+    #[schema(description = "Timezone (seconds offset from UTC)")]
+    timezone_offset: i32,
+    #[schema(description = "Current Conditions")]
+    current: Option<OneCallCurrentWrapper>,
+    #[schema(description = "Minutely Precipitation Forecast (1 hour)")]
+    minutely: Option<Vec<OneCallMinutelyWrapper>>,
+    #[schema(description = "Hourly Forecast (48 hours)")]
+    hourly: Option<Vec<OneCallHourlyWrapper>>,
+    #[schema(description = "Daily Forecast (8 days)")]
+    daily: Option<Vec<OneCallDailyWrapper>>,
+}
+
 #[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
 pub struct GeoLocationWrapper(GeoLocation);
 
@@ -407,6 +917,117 @@ struct _PlotDataWrapper {
     xaxis: String,
     #[schema(description = "Plot Y-axis Label")]
     yaxis: String,
+    #[schema(description = "Event Markers Url")]
+    markers_url: Option<String>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct PaginationWrapper(Pagination);
+
+derive_rweb_schema!(PaginationWrapper, _PaginationWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "Pagination")]
+struct _PaginationWrapper {
+    #[schema(description = "Number of Entries Returned")]
+    limit: usize,
+    #[schema(description = "Number of Entries to Skip")]
+    offset: usize,
+    #[schema(description = "Total Number of Entries")]
+    total: usize,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct LocationCountWrapper(LocationCount);
+
+derive_rweb_schema!(LocationCountWrapper, _LocationCountWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "LocationCount")]
+struct _LocationCountWrapper {
+    #[schema(description = "Location String")]
+    location: StackString,
+    #[schema(description = "Count")]
+    count: i64,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct PaginatedLocationCountWrapper(PaginatedLocationCount);
+
+derive_rweb_schema!(
+    PaginatedLocationCountWrapper,
+    _PaginatedLocationCountWrapper
+);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "PaginatedLocationCount")]
+struct _PaginatedLocationCountWrapper {
+    #[schema(description = "Pagination")]
+    pagination: PaginationWrapper,
+    #[schema(description = "Location Counts")]
+    data: Vec<LocationCountWrapper>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct ComparisonReadingWrapper(ComparisonReading);
+
+derive_rweb_schema!(ComparisonReadingWrapper, _ComparisonReadingWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "ComparisonReading")]
+struct _ComparisonReadingWrapper {
+    #[schema(description = "Created At")]
+    created_at: StackString,
+    #[schema(description = "Temperature (K)")]
+    temperature: f64,
+    #[schema(description = "Humidity (%)")]
+    humidity: i32,
+    #[schema(description = "Pressure (kPa)")]
+    pressure: f64,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct WeatherComparisonWrapper(WeatherComparison);
+
+derive_rweb_schema!(WeatherComparisonWrapper, _WeatherComparisonWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "WeatherComparison")]
+struct _WeatherComparisonWrapper {
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Most Recent Entry")]
+    current: ComparisonReadingWrapper,
+    #[schema(description = "Entry Closest to 24 Hours Earlier")]
+    yesterday: Option<ComparisonReadingWrapper>,
+}
+
+#[derive(Into, From, Deserialize, Serialize, Debug, Clone)]
+pub struct PrecipitationSummaryWrapper(PrecipitationSummary);
+
+derive_rweb_schema!(PrecipitationSummaryWrapper, _PrecipitationSummaryWrapper);
+
+#[allow(dead_code)]
+#[derive(Schema)]
+#[schema(component = "PrecipitationSummary")]
+struct _PrecipitationSummaryWrapper {
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Rain Since Local Midnight (mm)")]
+    today_rain: f64,
+    #[schema(description = "Snow Since Local Midnight (mm)")]
+    today_snow: f64,
+    #[schema(description = "Rain Since Monday (mm)")]
+    week_rain: f64,
+    #[schema(description = "Snow Since Monday (mm)")]
+    week_snow: f64,
+    #[schema(description = "Hours Recorded This Week")]
+    hours: usize,
 }
 
 /// # Errors
@@ -462,19 +1083,17 @@ pub fn get_forecast_plots(
     weather: &WeatherData,
 ) -> Result<Vec<PlotData>, Error> {
     let mut plots = Vec::new();
+    let units = options.get_units(Units::Imperial);
 
     let options = serde_urlencoded::to_string(options)?;
     let plot_url = format!("/weather/forecast-plots/temperature?{options}");
 
     plots.push(PlotData {
         plot_url,
-        title: format!(
-            "Temperature Forecast {:0.1} F / {:0.1} C",
-            weather.main.temp.fahrenheit(),
-            weather.main.temp.celcius()
-        ),
+        title: get_temperature_title(weather, units),
         xaxis: String::new(),
-        yaxis: "F".into(),
+        yaxis: units.temperature_unit().into(),
+        markers_url: None,
     });
 
     let plot_url = format!("/weather/forecast-plots/precipitation?{options}");
@@ -483,20 +1102,62 @@ pub fn get_forecast_plots(
         plot_url,
         title: "Precipitation Forecast".into(),
         xaxis: String::new(),
-        yaxis: "in".into(),
+        yaxis: units.precipitation_unit().into(),
+        markers_url: None,
     });
 
+    plots.extend(get_condition_plots("forecast-plots", &options, units, None));
+
     Ok(plots)
 }
 
+/// Humidity, pressure and wind speed plots of `/weather/{path}/{series}`
+fn get_condition_plots(
+    path: &str,
+    query: &str,
+    units: Units,
+    markers_url: Option<&str>,
+) -> Vec<PlotData> {
+    [
+        ("humidity", "Relative Humidity", "%"),
+        ("pressure", "Pressure", "kPa"),
+        ("wind", "Wind Speed", units.speed_unit()),
+    ]
+    .into_iter()
+    .map(|(series, title, yaxis)| PlotData {
+        plot_url: format!("/weather/{path}/{series}?{query}"),
+        title: title.into(),
+        xaxis: String::new(),
+        yaxis: yaxis.into(),
+        markers_url: markers_url.map(Into::into),
+    })
+    .collect()
+}
+
+fn get_temperature_title(weather: &WeatherData, units: Units) -> String {
+    if units == Units::Imperial {
+        format!(
+            "Temperature Forecast {:0.1} F / {:0.1} C",
+            weather.main.temp.fahrenheit(),
+            weather.main.temp.celcius()
+        )
+    } else {
+        format!(
+            "Temperature Forecast {:0.1} {}",
+            units.temperature(weather.main.temp.kelvin()),
+            units.temperature_unit()
+        )
+    }
+}
+
 #[must_use]
-pub fn get_forecast_temp_plot(forecast: &WeatherForecast) -> Vec<PlotPoint> {
+pub fn get_forecast_temp_plot(forecast: &WeatherForecast, units: Units) -> Vec<PlotPoint> {
     let fo: UtcOffset = forecast.city.timezone.into();
     forecast
         .list
         .iter()
         .map(|entry| {
-            let temp = entry.main.temp.fahrenheit();
+            let temp = units.temperature(entry.main.temp.kelvin());
             PlotPoint {
                 datetime: entry.dt.to_offset(fo),
                 value: temp,
@@ -506,7 +1167,7 @@ pub fn get_forecast_temp_plot(forecast: &WeatherForecast) -> Vec<PlotPoint> {
 }
 
 #[must_use]
-pub fn get_forecast_precip_plot(forecast: &WeatherForecast) -> Vec<PlotPoint> {
+pub fn get_forecast_precip_plot(forecast: &WeatherForecast, units: Units) -> Vec<PlotPoint> {
     let fo: UtcOffset = forecast.city.timezone.into();
     forecast
         .list
@@ -524,27 +1185,60 @@ pub fn get_forecast_precip_plot(forecast: &WeatherForecast) -> Vec<PlotPoint> {
             };
             PlotPoint {
                 datetime: entry.dt.to_offset(fo),
-                value: (rain + snow).inches(),
+                value: units.precipitation((rain + snow).millimeters()),
             }
         })
         .collect()
 }
 
+fn get_forecast_series(
+    forecast: &WeatherForecast,
+    value: impl Fn(&ForecastEntry) -> f64,
+) -> Vec<PlotPoint> {
+    let fo: UtcOffset = forecast.city.timezone.into();
+    forecast
+        .list
+        .iter()
+        .map(|entry| PlotPoint {
+            datetime: entry.dt.to_offset(fo),
+            value: value(entry),
+        })
+        .collect()
+}
+
+/// Relative humidity (%) of each forecast entry
 #[must_use]
-pub fn get_history_plots(query: &str, weather: &WeatherData) -> Vec<PlotData> {
+pub fn get_forecast_humidity_plot(forecast: &WeatherForecast) -> Vec<PlotPoint> {
+    get_forecast_series(forecast, |entry| {
+        let humidity: i64 = entry.main.humidity.into();
+        humidity as f64
+    })
+}
+
+/// Pressure (kPa) of each forecast entry
+#[must_use]
+pub fn get_forecast_pressure_plot(forecast: &WeatherForecast) -> Vec<PlotPoint> {
+    get_forecast_series(forecast, |entry| entry.main.pressure.kpa())
+}
+
+#[must_use]
+pub fn get_forecast_wind_plot(forecast: &WeatherForecast, units: Units) -> Vec<PlotPoint> {
+    get_forecast_series(forecast, |entry| units.speed(entry.wind.speed.mps()))
+}
+
+#[must_use]
+#[instrument(skip_all)]
+pub fn get_history_plots(query: &str, weather: &WeatherData, units: Units) -> Vec<PlotData> {
     let mut plots = Vec::new();
 
     let plot_url = format!("/weather/history-plots/temperature?{query}");
 
     plots.push(PlotData {
         plot_url,
-        title: format!(
-            "Temperature Forecast {:0.1} F / {:0.1} C",
-            weather.main.temp.fahrenheit(),
-            weather.main.temp.celcius()
-        ),
+        title: get_temperature_title(weather, units),
         xaxis: String::new(),
-        yaxis: "F".into(),
+        yaxis: units.temperature_unit().into(),
+        markers_url: Some(format!("/weather/events?{query}")),
     });
 
     let plot_url = format!("/weather/history-plots/precipitation?{query}");
@@ -553,20 +1247,52 @@ pub fn get_history_plots(query: &str, weather: &WeatherData) -> Vec<PlotData> {
         plot_url,
         title: "Precipitation Forecast".into(),
         xaxis: String::new(),
-        yaxis: "in".into(),
+        yaxis: units.precipitation_unit().into(),
+        markers_url: Some(format!("/weather/events?{query}")),
+    });
+
+    plots.extend(get_condition_plots(
+        "history-plots",
+        query,
+        units,
+        Some(&format!("/weather/events?{query}")),
+    ));
+
+    plots.extend(
+        [
+            ("dew-point", "Dew Point"),
+            ("heat-index", "Heat Index"),
+            ("wind-chill", "Wind Chill"),
+        ]
+        .into_iter()
+        .map(|(series, title)| PlotData {
+            plot_url: format!("/weather/history-plots/{series}?{query}"),
+            title: title.into(),
+            xaxis: String::new(),
+            yaxis: units.temperature_unit().into(),
+            markers_url: Some(format!("/weather/events?{query}")),
+        }),
+    );
+
+    plots.push(PlotData {
+        plot_url: format!("/weather/history-plots/uv-index?{query}"),
+        title: "UV Index".into(),
+        xaxis: String::new(),
+        yaxis: "UVI".into(),
+        markers_url: None,
     });
 
     plots
 }
 
 #[must_use]
-pub fn get_history_temperature_plot(history: &[WeatherData]) -> Vec<PlotPoint> {
+pub fn get_history_temperature_plot(history: &[WeatherData], units: Units) -> Vec<PlotPoint> {
     if let Some(weather) = history.last() {
         let fo: UtcOffset = weather.timezone.into();
         history
             .iter()
             .map(|w| {
-                let temp = w.main.temp.fahrenheit();
+                let temp = units.temperature(w.main.temp.kelvin());
                 PlotPoint {
                     datetime: w.dt.to_offset(fo),
                     value: temp,
@@ -579,7 +1305,7 @@ pub fn get_history_temperature_plot(history: &[WeatherData]) -> Vec<PlotPoint> {
 }
 
 #[must_use]
-pub fn get_history_precip_plot(history: &[WeatherData]) -> Vec<PlotPoint> {
+pub fn get_history_precip_plot(history: &[WeatherData], units: Units) -> Vec<PlotPoint> {
     if let Some(weather) = history.last() {
         let fo: UtcOffset = weather.timezone.into();
         history
@@ -597,7 +1323,7 @@ pub fn get_history_precip_plot(history: &[WeatherData]) -> Vec<PlotPoint> {
                 };
                 PlotPoint {
                     datetime: w.dt.to_offset(fo),
-                    value: (rain + snow).inches(),
+                    value: units.precipitation((rain + snow).millimeters()),
                 }
             })
             .collect()
@@ -606,16 +1332,129 @@ pub fn get_history_precip_plot(history: &[WeatherData]) -> Vec<PlotPoint> {
     }
 }
 
+fn get_history_series(
+    history: &[WeatherData],
+    value: impl Fn(&WeatherData) -> f64,
+) -> Vec<PlotPoint> {
+    if let Some(weather) = history.last() {
+        let fo: UtcOffset = weather.timezone.into();
+        history
+            .iter()
+            .map(|w| PlotPoint {
+                datetime: w.dt.to_offset(fo),
+                value: value(w),
+            })
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Relative humidity (%) of each observation
+#[must_use]
+pub fn get_history_humidity_plot(history: &[WeatherData]) -> Vec<PlotPoint> {
+    get_history_series(history, |w| {
+        let humidity: i64 = w.main.humidity.into();
+        humidity as f64
+    })
+}
+
+/// Pressure (kPa) of each observation
+#[must_use]
+pub fn get_history_pressure_plot(history: &[WeatherData]) -> Vec<PlotPoint> {
+    get_history_series(history, |w| w.main.pressure.kpa())
+}
+
+#[must_use]
+pub fn get_history_wind_plot(history: &[WeatherData], units: Units) -> Vec<PlotPoint> {
+    get_history_series(history, |w| units.speed(w.wind.speed.mps()))
+}
+
+/// Derived temperature of each observation it is defined for
+fn get_history_derived_series(
+    history: &[WeatherData],
+    units: Units,
+    metric: impl Fn(&DerivedMetrics) -> Option<f64>,
+) -> Vec<PlotPoint> {
+    if let Some(weather) = history.last() {
+        let fo: UtcOffset = weather.timezone.into();
+        history
+            .iter()
+            .filter_map(|w| {
+                let humidity: i64 = w.main.humidity.into();
+                let derived =
+                    DerivedMetrics::new(w.main.temp.kelvin(), humidity as i32, w.wind.speed.mps());
+                metric(&derived).map(|value| PlotPoint {
+                    datetime: w.dt.to_offset(fo),
+                    value: units.temperature(value),
+                })
+            })
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+#[must_use]
+pub fn get_history_dew_point_plot(history: &[WeatherData], units: Units) -> Vec<PlotPoint> {
+    get_history_derived_series(history, units, |d| d.dew_point)
+}
+
+/// Heat index of the observations at or above 80°F
+#[must_use]
+pub fn get_history_heat_index_plot(history: &[WeatherData], units: Units) -> Vec<PlotPoint> {
+    get_history_derived_series(history, units, |d| d.heat_index)
+}
+
+/// Wind chill of the observations at or below 50°F with wind above 3 mph
+#[must_use]
+pub fn get_history_wind_chill_plot(history: &[WeatherData], units: Units) -> Vec<PlotPoint> {
+    get_history_derived_series(history, units, |d| d.wind_chill)
+}
+
+/// Uv index readings in the local time of the most recent reading
+#[must_use]
+pub fn get_history_uv_index_plot(readings: &[UvIndexData]) -> Vec<PlotPoint> {
+    let Some(latest) = readings.last() else {
+        return Vec::new();
+    };
+    let fo = UtcOffset::from_whole_seconds(latest.timezone).unwrap_or(UtcOffset::UTC);
+    readings
+        .iter()
+        .map(|r| PlotPoint {
+            datetime: r.recorded_at.to_offset(fo),
+            value: r.uvi,
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
+    use anyhow::Error;
     use rweb_helper::derive_rweb_test;
+    use serde_json::json;
+
+    use weather_api_types::{
+        dto::{LocationCount, PaginatedLocationCount, Pagination},
+        units::Units,
+    };
+    use weather_util_rust::weather_data::WeatherData;
 
     use crate::{
-        CityEntryWrapper, CoordWrapper, ForecastEntryWrapper, ForecastMainWrapper, SysWrapper,
-        WeatherCondWrapper, WeatherDataWrapper, WeatherForecastWrapper, WeatherMainWrapper,
-        WindWrapper, _CityEntryWrapper, _CoordWrapper, _ForecastEntryWrapper, _ForecastMainWrapper,
-        _SysWrapper, _WeatherCondWrapper, _WeatherDataWrapper, _WeatherForecastWrapper,
-        _WeatherMainWrapper, _WindWrapper,
+        _CityEntryWrapper, _ComparisonReadingWrapper, _CoordWrapper, _ForecastEntryWrapper,
+        _ForecastMainWrapper, _LocationCountWrapper, _OneCallCurrentWrapper, _OneCallDailyWrapper,
+        _OneCallHourlyWrapper, _OneCallMinutelyWrapper, _OneCallWrapper,
+        _PaginatedLocationCountWrapper, _PaginationWrapper, _PrecipitationSummaryWrapper,
+        _SysWrapper, _WeatherComparisonWrapper, _WeatherCondWrapper, _WeatherDataWrapper,
+        _WeatherForecastWrapper, _WeatherMainWrapper, _WindWrapper, bench::demo_rows,
+        get_history_dew_point_plot, get_history_heat_index_plot, get_history_humidity_plot,
+        get_history_plots, get_history_pressure_plot, get_history_wind_chill_plot,
+        get_history_wind_plot, CityEntryWrapper, ComparisonReadingWrapper, CoordWrapper,
+        ForecastEntryWrapper, ForecastMainWrapper, LocationCountWrapper, OneCallCurrentWrapper,
+        OneCallDailyWrapper, OneCallHourlyWrapper, OneCallMinutelyWrapper, OneCallWrapper,
+        PaginatedLocationCountWrapper, PaginationWrapper, PrecipitationSummaryWrapper, SysWrapper,
+        WeatherComparisonWrapper, WeatherCondWrapper, WeatherDataWrapper, WeatherForecastWrapper,
+        WeatherMainWrapper, WindWrapper, WithUnits,
     };
 
     #[test]
@@ -630,5 +1469,112 @@ mod test {
         derive_rweb_test!(ForecastEntryWrapper, _ForecastEntryWrapper);
         derive_rweb_test!(CityEntryWrapper, _CityEntryWrapper);
         derive_rweb_test!(ForecastMainWrapper, _ForecastMainWrapper);
+        derive_rweb_test!(PaginationWrapper, _PaginationWrapper);
+        derive_rweb_test!(LocationCountWrapper, _LocationCountWrapper);
+        derive_rweb_test!(
+            PaginatedLocationCountWrapper,
+            _PaginatedLocationCountWrapper
+        );
+        derive_rweb_test!(ComparisonReadingWrapper, _ComparisonReadingWrapper);
+        derive_rweb_test!(WeatherComparisonWrapper, _WeatherComparisonWrapper);
+        derive_rweb_test!(PrecipitationSummaryWrapper, _PrecipitationSummaryWrapper);
+    }
+
+    #[test]
+    fn test_with_units() -> Result<(), Error> {
+        let data = json!({
+            "main": {"temp": 273.15, "feels_like": 283.15, "humidity": 50},
+            "wind": {"speed": 10.0, "deg": 90.0},
+            "rain": {"1h": 25.4},
+            "visibility": 10000.0,
+        });
+        let standard = serde_json::to_value(WithUnits::new(data.clone(), Units::Standard))?;
+        assert_eq!(standard, data);
+
+        let metric = serde_json::to_value(WithUnits::new(data.clone(), Units::Metric))?;
+        assert!(metric["main"]["temp"].as_f64().unwrap().abs() < 1e-9);
+        assert!((metric["main"]["feels_like"].as_f64().unwrap() - 10.0).abs() < 1e-9);
+        assert!((metric["rain"]["1h"].as_f64().unwrap() - 25.4).abs() < 1e-9);
+
+        let imperial = serde_json::to_value(WithUnits::new(data, Units::Imperial))?;
+        assert!((imperial["main"]["temp"].as_f64().unwrap() - 32.0).abs() < 1e-9);
+        assert!((imperial["wind"]["speed"].as_f64().unwrap() - 22.369).abs() < 1e-3);
+        assert!((imperial["rain"]["1h"].as_f64().unwrap() - 1.0).abs() < 1e-9);
+        assert_eq!(imperial["main"]["humidity"], json!(50));
+        assert_eq!(imperial["wind"]["deg"], json!(90.0));
+        assert_eq!(imperial["visibility"], json!(10000.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_paginated_location_count_wire_format() -> Result<(), Error> {
+        let expected = json!({
+            "pagination": {"limit": 10, "offset": 0, "total": 1},
+            "data": [{"location": "10001", "count": 42}],
+        });
+        let counts = PaginatedLocationCount {
+            pagination: Pagination {
+                limit: 10,
+                offset: 0,
+                total: 1,
+            },
+            data: vec![LocationCount {
+                location: "10001".into(),
+                count: 42,
+            }],
+        };
+        let wrapper: PaginatedLocationCountWrapper = counts.clone().into();
+        assert_eq!(serde_json::to_value(&wrapper)?, expected);
+
+        let client: PaginatedLocationCount = serde_json::from_value(expected)?;
+        assert_eq!(client, counts);
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_condition_plots() {
+        let rows = demo_rows(48);
+        let history: Vec<WeatherData> = rows.iter().cloned().map(Into::into).collect();
+
+        let humidity = get_history_humidity_plot(&history);
+        assert_eq!(humidity.len(), 48);
+        assert_eq!(humidity[0].value, f64::from(rows[0].humidity));
+
+        let pressure = get_history_pressure_plot(&history);
+        assert_eq!(pressure.len(), 48);
+        assert!(pressure.iter().all(|p| p.value > 0.0));
+
+        let wind = get_history_wind_plot(&history, Units::Imperial);
+        assert!((wind[0].value - rows[0].wind_speed * 3600.0 / 1609.344).abs() < 1e-6);
+
+        let plots = get_history_plots("name=test", &history[0], Units::Imperial);
+        let urls: Vec<_> = plots.iter().map(|p| p.plot_url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "/weather/history-plots/temperature?name=test",
+                "/weather/history-plots/precipitation?name=test",
+                "/weather/history-plots/humidity?name=test",
+                "/weather/history-plots/pressure?name=test",
+                "/weather/history-plots/wind?name=test",
+                "/weather/history-plots/dew-point?name=test",
+                "/weather/history-plots/heat-index?name=test",
+                "/weather/history-plots/wind-chill?name=test",
+                "/weather/history-plots/uv-index?name=test",
+            ]
+        );
+        assert_eq!(plots[4].yaxis, "mph");
+        assert_eq!(plots[5].yaxis, "F");
+
+        let dew_point = get_history_dew_point_plot(&history, Units::Metric);
+        assert_eq!(dew_point.len(), 48);
+        assert!(dew_point
+            .iter()
+            .zip(&history)
+            .all(|(p, w)| p.value <= w.main.temp.celcius() + 1e-6));
+        let heat_index = get_history_heat_index_plot(&history, Units::Imperial);
+        assert!(heat_index.iter().all(|p| p.value >= 70.0));
+        let wind_chill = get_history_wind_chill_plot(&history, Units::Imperial);
+        assert!(wind_chill.len() <= 48);
     }
 }
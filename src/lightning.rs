@@ -0,0 +1,205 @@
+use anyhow::Error;
+use futures::TryStreamExt;
+use log::{error, info};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use stack_string::format_sstr;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use weather_util_rust::weather_api::WeatherLocation;
+
+use crate::{
+    config::Config,
+    model::{LightningActivity, WeatherLocationCache},
+    pgpool::PgPool,
+};
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Single strike from the lightning feed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct LightningStrike {
+    #[serde(with = "time::serde::timestamp")]
+    pub time: OffsetDateTime,
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Alert condition triggered by strikes close to a location
+#[derive(Debug, Clone, Copy)]
+pub struct LightningAlertCondition {
+    /// strikes closer than this trigger the alert (km)
+    pub distance_km: f64,
+    /// how far back to look for strikes
+    pub window: Duration,
+}
+
+impl Default for LightningAlertCondition {
+    fn default() -> Self {
+        Self {
+            distance_km: 10.0,
+            window: Duration::minutes(30),
+        }
+    }
+}
+
+impl LightningAlertCondition {
+    /// True if any recorded activity within the window saw a strike within
+    /// `distance_km`.
+    #[must_use]
+    pub fn is_triggered(&self, activity: &[LightningActivity], now: OffsetDateTime) -> bool {
+        activity.iter().any(|a| {
+            *a.recorded_at >= now - self.window
+                && a.nearest_distance_km.is_some_and(|d| d <= self.distance_km)
+        })
+    }
+}
+
+/// Great circle distance in km between two points given in degrees
+#[must_use]
+pub fn haversine_distance(lat0: f64, lon0: f64, lat1: f64, lon1: f64) -> f64 {
+    let dlat = (lat1 - lat0).to_radians();
+    let dlon = (lon1 - lon0).to_radians();
+    let a = (dlat / 2.0).sin().powi(2)
+        + lat0.to_radians().cos() * lat1.to_radians().cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Count the strikes within `radius_km` of a point, along with the distance
+/// to the nearest of them.
+#[must_use]
+pub fn summarize_strikes(
+    strikes: &[LightningStrike],
+    latitude: f64,
+    longitude: f64,
+    radius_km: f64,
+) -> (i32, Option<f64>) {
+    strikes
+        .iter()
+        .map(|s| haversine_distance(latitude, longitude, s.lat, s.lon))
+        .filter(|d| *d <= radius_km)
+        .fold((0, None), |(count, nearest), d| {
+            (count + 1, Some(nearest.map_or(d, |n: f64| n.min(d))))
+        })
+}
+
+/// # Errors
+/// Return error if the feed request fails or can't be deserialized
+pub async fn fetch_strikes(
+    client: &Client,
+    url: &str,
+    since: OffsetDateTime,
+) -> Result<Vec<LightningStrike>, Error> {
+    let strikes: Vec<LightningStrike> = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(strikes.into_iter().filter(|s| s.time >= since).collect())
+}
+
+/// Fetch the lightning feed once and record strike activity for each
+/// location with a cached latitude / longitude.
+///
+/// # Errors
+/// Return error if the feed request fails
+pub async fn record_lightning_activity(
+    pool: &PgPool,
+    config: &Config,
+    client: &Client,
+    url: &str,
+    locations: &[WeatherLocation],
+    since: OffsetDateTime,
+) -> Result<(), Error> {
+    let recorded_at = OffsetDateTime::now_utc();
+    let strikes = fetch_strikes(client, url, since).await?;
+    for loc in locations {
+        let Some(cache) = WeatherLocationCache::from_weather_location_cache(pool, loc).await?
+        else {
+            info!("no cached coordinates for {loc}, skipping lightning");
+            continue;
+        };
+        let (strike_count, nearest_distance_km) = summarize_strikes(
+            &strikes,
+            cache.latitude,
+            cache.longitude,
+            config.lightning_radius_km,
+        );
+        let activity = LightningActivity {
+            id: Uuid::new_v4(),
+            location_name: format_sstr!("{loc}"),
+            server: config.server.clone(),
+            recorded_at: recorded_at.into(),
+            radius_km: config.lightning_radius_km,
+            strike_count,
+            nearest_distance_km,
+        };
+        if let Err(e) = activity.insert(pool).await {
+            error!("failed to record lightning for {loc} {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Recent lightning activity for a location
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn get_recent_activity(
+    pool: &PgPool,
+    name: &str,
+    server: Option<&str>,
+    since: OffsetDateTime,
+) -> Result<Vec<LightningActivity>, Error> {
+    LightningActivity::get_recent(pool, name, server, since)
+        .await?
+        .try_collect()
+        .await
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::lightning::{haversine_distance, summarize_strikes, LightningStrike};
+
+    #[test]
+    fn test_haversine_distance() {
+        // one degree of latitude is ~111.2 km
+        assert!((haversine_distance(0.0, 0.0, 1.0, 0.0) - 111.19).abs() < 0.1);
+        // Minneapolis to Saint Paul, ~15 km
+        let d = haversine_distance(44.9778, -93.2650, 44.9537, -93.0900);
+        assert!(d > 13.0 && d < 15.0);
+        assert!(haversine_distance(45.0, -93.0, 45.0, -93.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_summarize_strikes() {
+        let time = datetime!(2024-06-01 00:00 UTC);
+        let strikes = [
+            LightningStrike {
+                time,
+                lat: 45.05,
+                lon: -93.0,
+            },
+            LightningStrike {
+                time,
+                lat: 45.2,
+                lon: -93.0,
+            },
+            LightningStrike {
+                time,
+                lat: 47.0,
+                lon: -93.0,
+            },
+        ];
+        let (count, nearest) = summarize_strikes(&strikes, 45.0, -93.0, 50.0);
+        assert_eq!(count, 2);
+        assert!((nearest.unwrap() - 5.56).abs() < 0.05);
+        assert_eq!(summarize_strikes(&strikes, 0.0, 0.0, 50.0), (0, None));
+    }
+}
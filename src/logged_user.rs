@@ -5,8 +5,10 @@ pub use authorized_users::{
 use futures::TryStreamExt;
 use log::debug;
 use maplit::hashmap;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
 use rweb::{
-    filters::{cookie::cookie, BoxedFilter},
+    filters::{cookie::cookie, header::header, BoxedFilter},
     Filter, FromRequest, Rejection, Schema,
 };
 use rweb_helper::UuidWrapper;
@@ -21,7 +23,15 @@ use std::{
 use time::OffsetDateTime;
 use uuid::Uuid;
 
-use crate::{errors::ServiceError as Error, model::AuthorizedUsers, pgpool::PgPool};
+use crate::{
+    errors::ServiceError as Error,
+    model::{ApiKey, AuthorizedUsers},
+    pgpool::PgPool,
+};
+
+/// Users of active api keys by sha256 of the key
+static API_KEYS: Lazy<RwLock<HashMap<StackString, LoggedUser>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Schema)]
 #[schema(component = "LoggedUser")]
@@ -46,8 +56,22 @@ impl LoggedUser {
         }
     }
 
+    /// User of an active api key
+    ///
+    /// # Errors
+    /// Return error if the key is unknown or revoked
+    pub fn from_api_key(key: &str) -> Result<Self, Error> {
+        API_KEYS
+            .read()
+            .get(&ApiKey::hash_key(key))
+            .cloned()
+            .ok_or(Error::Unauthorized)
+    }
+
+    /// Session cookies of a browser only, for key management and admin
+    /// routes a leaked api key must not reach
     #[must_use]
-    pub fn filter() -> impl Filter<Extract = (Self,), Error = Rejection> + Copy {
+    pub fn session_filter() -> impl Filter<Extract = (Self,), Error = Rejection> + Copy {
         cookie("session-id")
             .and(cookie("jwt"))
             .and_then(|id: Uuid, user: Self| async move {
@@ -56,6 +80,15 @@ impl LoggedUser {
                     .map_err(rweb::reject::custom)
             })
     }
+
+    /// Session cookies of a browser, or the `X-Api-Key` header of a script
+    #[must_use]
+    pub fn filter() -> impl Filter<Extract = (Self,), Error = Rejection> + Copy {
+        let api_key = header::<StackString>("x-api-key").and_then(|key: StackString| async move {
+            Self::from_api_key(&key).map_err(rweb::reject::custom)
+        });
+        Self::session_filter().or(api_key).unify()
+    }
 }
 
 impl FromRequest for LoggedUser {
@@ -145,3 +178,27 @@ pub async fn fill_from_db(pool: &PgPool) -> Result<(), Error> {
     debug!("AUTHORIZED_USERS {:?}", *AUTHORIZED_USERS);
     Ok(())
 }
+
+/// Reload active api keys, revoked keys stop working once this runs
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn fill_api_keys_from_db(pool: &PgPool) -> Result<(), Error> {
+    let keys: HashMap<StackString, LoggedUser> = ApiKey::get_active(pool)
+        .await?
+        .map_ok(|key| {
+            (
+                key.key_hash,
+                LoggedUser {
+                    email: key.email,
+                    session: key.id.into(),
+                    secret_key: StackString::default(),
+                },
+            )
+        })
+        .try_collect()
+        .await?;
+    debug!("api keys {}", keys.len());
+    *API_KEYS.write() = keys;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use anyhow::{format_err, Error};
+use env_filter::{Builder as FilterBuilder, Filter};
+use log::{info, LevelFilter, Log, Metadata, Record};
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use stack_string::StackString;
+use std::{env::var, path::Path, str::FromStr};
+
+/// Filter used when `RUST_LOG` isn't set, same as `env_logger`
+const DEFAULT_FILTER: &str = "error";
+
+static LOGGER: OnceCell<ReloadableLogger> = OnceCell::new();
+
+struct LogFilter {
+    spec: StackString,
+    filter: Filter,
+}
+
+impl LogFilter {
+    fn new(spec: &str) -> Result<Self, Error> {
+        validate_filter(spec)?;
+        let filter = FilterBuilder::new().parse(spec).build();
+        Ok(Self {
+            spec: spec.into(),
+            filter,
+        })
+    }
+}
+
+/// `env_logger` output behind a filter that can be replaced at runtime
+struct ReloadableLogger {
+    inner: env_logger::Logger,
+    startup_spec: StackString,
+    filter: RwLock<LogFilter>,
+}
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.read().filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.filter.read().filter.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Reject directives `env_filter` would silently ignore, e.g. `s3_sync=verbose`
+fn validate_filter(spec: &str) -> Result<(), Error> {
+    let directives = spec.split('/').next().unwrap_or("");
+    for directive in directives.split(',').map(str::trim) {
+        if let Some((target, level)) = directive.split_once('=') {
+            if target.trim().is_empty() {
+                return Err(format_err!("missing target in {directive}"));
+            }
+            LevelFilter::from_str(level.trim())
+                .map_err(|_| format_err!("invalid level in {directive}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Install the logger, the initial filter is read from `RUST_LOG`
+///
+/// # Errors
+/// Return error if a logger was already installed or `RUST_LOG` is invalid
+pub fn init_logging() -> Result<(), Error> {
+    let spec: StackString = var("RUST_LOG").map_or_else(|_| DEFAULT_FILTER.into(), Into::into);
+    let filter = LogFilter::new(&spec)?;
+    let max_level = filter.filter.filter();
+    let logger = ReloadableLogger {
+        inner: env_logger::Builder::new()
+            .filter_level(LevelFilter::Trace)
+            .build(),
+        startup_spec: spec,
+        filter: RwLock::new(filter),
+    };
+    let logger = LOGGER
+        .try_insert(logger)
+        .map_err(|_| format_err!("logger already initialized"))?;
+    log::set_logger(logger)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Current filter, `None` when `init_logging` wasn't called
+#[must_use]
+pub fn get_log_filter() -> Option<StackString> {
+    LOGGER.get().map(|logger| logger.filter.read().spec.clone())
+}
+
+/// Filter `RUST_LOG` set at startup
+#[must_use]
+pub fn get_startup_log_filter() -> Option<StackString> {
+    LOGGER.get().map(|logger| logger.startup_spec.clone())
+}
+
+/// Replace the filter, e.g. `info,weather_api_rust::s3_sync=debug`, the
+/// startup filter is restored when `spec` is `None`
+///
+/// # Errors
+/// Return error if `spec` is invalid or `init_logging` wasn't called
+pub fn set_log_filter(spec: Option<&str>) -> Result<StackString, Error> {
+    let logger = LOGGER
+        .get()
+        .ok_or_else(|| format_err!("logger not initialized"))?;
+    let filter = LogFilter::new(spec.unwrap_or(&logger.startup_spec))?;
+    let spec = filter.spec.clone();
+    log::set_max_level(filter.filter.filter());
+    *logger.filter.write() = filter;
+    info!("log filter set to {spec}");
+    Ok(spec)
+}
+
+/// Apply `RUST_LOG` of the env file `path`, or restore the startup filter
+/// when the file doesn't set it
+///
+/// # Errors
+/// Return error if the file can't be read or the filter is invalid
+pub fn reload_log_filter(path: &Path) -> Result<StackString, Error> {
+    let mut spec = None;
+    if path.exists() {
+        for item in dotenvy::from_path_iter(path)? {
+            let (key, value) = item?;
+            if key == "RUST_LOG" {
+                spec.replace(value);
+            }
+        }
+    }
+    set_log_filter(spec.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::validate_filter;
+
+    #[test]
+    fn test_validate_filter() {
+        assert!(validate_filter("info").is_ok());
+        assert!(validate_filter("info,weather_api_rust::s3_sync=debug").is_ok());
+        assert!(validate_filter("warn,rweb=off/timeout").is_ok());
+        assert!(validate_filter("weather_api_rust").is_ok());
+        assert!(validate_filter("s3_sync=verbose").is_err());
+        assert!(validate_filter("=debug").is_err());
+    }
+}
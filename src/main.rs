@@ -1,10 +1,10 @@
 use anyhow::Error;
 
-use weather_api_rust::parse_opts::ParseOpts;
+use weather_api_rust::{logging::init_logging, parse_opts::ParseOpts};
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
-    env_logger::init();
+    init_logging()?;
 
     ParseOpts::process_args().await
 }
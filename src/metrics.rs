@@ -0,0 +1,321 @@
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rweb::{
+    filters::log::Info,
+    http::{header::CONTENT_TYPE, StatusCode},
+    openapi::{
+        ComponentDescriptor, ComponentOrInlineSchema, Entity, MediaType, Response, ResponseEntity,
+        Responses, Schema, Type,
+    },
+    reply, Reply,
+};
+use stack_string::StackString;
+use std::{borrow::Cow, collections::BTreeMap, fmt::Write};
+
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+const METRICS_DESCRIPTION: &str = "Metrics in Prometheus text format";
+
+/// Upper bounds (seconds) of the request latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// Request counts keyed by (method, route, status)
+static REQUEST_COUNTS: Lazy<Mutex<BTreeMap<(StackString, StackString, u16), u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Request latency keyed by route
+static REQUEST_LATENCY: Lazy<Mutex<BTreeMap<StackString, Histogram>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Upstream api calls keyed by (provider, endpoint, success)
+static UPSTREAM_CALLS: Lazy<Mutex<BTreeMap<(&'static str, &'static str, bool), u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Hits and misses of one of the cached upstream lookups
+pub struct CacheMetrics {
+    pub name: &'static str,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Size of the postgres connection pool, all zero when the database is
+/// disabled
+#[derive(Default)]
+pub struct PoolMetrics {
+    pub max_size: usize,
+    pub size: usize,
+    pub available: usize,
+    pub waiting: usize,
+}
+
+/// Record a served request, unmatched paths share a single label so that
+/// scanners can't blow up the number of series
+pub fn record_request(info: Info) {
+    let status = info.status();
+    let route: StackString = if status == StatusCode::NOT_FOUND {
+        "unmatched".into()
+    } else {
+        info.path().into()
+    };
+    let method: StackString = info.method().as_str().into();
+    *REQUEST_COUNTS
+        .lock()
+        .entry((method, route.clone(), status.as_u16()))
+        .or_default() += 1;
+    REQUEST_LATENCY
+        .lock()
+        .entry(route)
+        .or_default()
+        .observe(info.elapsed().as_secs_f64());
+}
+
+/// Record a call to an upstream weather api
+pub fn record_upstream_call(provider: &'static str, endpoint: &'static str, success: bool) {
+    *UPSTREAM_CALLS
+        .lock()
+        .entry((provider, endpoint, success))
+        .or_default() += 1;
+}
+
+fn write_header(output: &mut String, name: &str, metric_type: &str, help: &str) {
+    writeln!(output, "# HELP {name} {help}").unwrap();
+    writeln!(output, "# TYPE {name} {metric_type}").unwrap();
+}
+
+/// Render every metric in the Prometheus text exposition format
+#[must_use]
+pub fn render_metrics(
+    caches: &[CacheMetrics],
+    pool: &PoolMetrics,
+    skipped_records: u64,
+) -> StackString {
+    let mut output = String::new();
+
+    write_header(
+        &mut output,
+        "weather_http_requests_total",
+        "counter",
+        "Requests served by route and status",
+    );
+    for ((method, route, status), count) in REQUEST_COUNTS.lock().iter() {
+        writeln!(
+            output,
+            "weather_http_requests_total{{method=\"{method}\",route=\"{route}\",\
+             status=\"{status}\"}} {count}"
+        )
+        .unwrap();
+    }
+
+    write_header(
+        &mut output,
+        "weather_http_request_duration_seconds",
+        "histogram",
+        "Request latency by route",
+    );
+    for (route, histogram) in REQUEST_LATENCY.lock().iter() {
+        let name = "weather_http_request_duration_seconds";
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+            writeln!(
+                output,
+                "{name}_bucket{{route=\"{route}\",le=\"{bound}\"}} {bucket}"
+            )
+            .unwrap();
+        }
+        let count = histogram.count;
+        writeln!(
+            output,
+            "{name}_bucket{{route=\"{route}\",le=\"+Inf\"}} {count}"
+        )
+        .unwrap();
+        writeln!(output, "{name}_sum{{route=\"{route}\"}} {}", histogram.sum).unwrap();
+        writeln!(output, "{name}_count{{route=\"{route}\"}} {count}").unwrap();
+    }
+
+    write_header(
+        &mut output,
+        "weather_cache_hits_total",
+        "counter",
+        "Cache hits by cache",
+    );
+    for cache in caches {
+        let name = cache.name;
+        writeln!(
+            output,
+            "weather_cache_hits_total{{cache=\"{name}\"}} {}",
+            cache.hits
+        )
+        .unwrap();
+    }
+    write_header(
+        &mut output,
+        "weather_cache_misses_total",
+        "counter",
+        "Cache misses by cache",
+    );
+    for cache in caches {
+        let name = cache.name;
+        writeln!(
+            output,
+            "weather_cache_misses_total{{cache=\"{name}\"}} {}",
+            cache.misses
+        )
+        .unwrap();
+    }
+
+    write_header(
+        &mut output,
+        "weather_upstream_calls_total",
+        "counter",
+        "Calls to upstream weather apis by provider, endpoint and outcome",
+    );
+    for ((provider, endpoint, success), count) in UPSTREAM_CALLS.lock().iter() {
+        let outcome = if *success { "success" } else { "failure" };
+        writeln!(
+            output,
+            "weather_upstream_calls_total{{provider=\"{provider}\",endpoint=\"{endpoint}\",\
+             outcome=\"{outcome}\"}} {count}"
+        )
+        .unwrap();
+    }
+
+    write_header(
+        &mut output,
+        "weather_skipped_records_total",
+        "counter",
+        "Rows skipped by delta based recording",
+    );
+    writeln!(output, "weather_skipped_records_total {skipped_records}").unwrap();
+
+    for (name, help, value) in [
+        (
+            "weather_db_pool_max_size",
+            "Maximum size of the db pool",
+            pool.max_size,
+        ),
+        (
+            "weather_db_pool_size",
+            "Connections in the db pool",
+            pool.size,
+        ),
+        (
+            "weather_db_pool_available",
+            "Idle connections in the db pool",
+            pool.available,
+        ),
+        (
+            "weather_db_pool_waiting",
+            "Tasks waiting for a db connection",
+            pool.waiting,
+        ),
+    ]
+    .iter()
+    {
+        write_header(&mut output, name, "gauge", help);
+        writeln!(output, "{name} {value}").unwrap();
+    }
+
+    output.into()
+}
+
+pub struct MetricsResponse(pub StackString);
+
+impl Reply for MetricsResponse {
+    fn into_response(self) -> reply::Response {
+        reply::with_header(String::from(self.0), CONTENT_TYPE, METRICS_CONTENT_TYPE).into_response()
+    }
+}
+
+impl Entity for MetricsResponse {
+    fn type_name() -> Cow<'static, str> {
+        "metrics".into()
+    }
+
+    fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        ComponentOrInlineSchema::Inline(Schema {
+            schema_type: Some(Type::String),
+            description: METRICS_DESCRIPTION.into(),
+            ..Schema::default()
+        })
+    }
+}
+
+impl ResponseEntity for MetricsResponse {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        let mut response = Response {
+            description: Cow::Borrowed(METRICS_DESCRIPTION),
+            ..Response::default()
+        };
+        response.content.insert(
+            Cow::Borrowed("text/plain"),
+            MediaType {
+                schema: Some(Self::describe(comp_d)),
+                ..MediaType::default()
+            },
+        );
+        let mut map = Responses::new();
+        map.insert(Cow::Owned(StatusCode::OK.as_str().into()), response);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{
+        record_upstream_call, render_metrics, CacheMetrics, Histogram, PoolMetrics,
+    };
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.02);
+        histogram.observe(3.0);
+        assert_eq!(histogram.count, 2);
+        assert_eq!(histogram.buckets[0], 0);
+        assert_eq!(histogram.buckets[2], 1);
+        assert_eq!(histogram.buckets[9], 2);
+        assert!((histogram.sum - 3.02).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        record_upstream_call("test-provider", "weather", true);
+        record_upstream_call("test-provider", "weather", false);
+        let caches = [CacheMetrics {
+            name: "weather_data",
+            hits: 3,
+            misses: 1,
+        }];
+        let pool = PoolMetrics {
+            max_size: 16,
+            size: 4,
+            available: 2,
+            waiting: 0,
+        };
+        let output = render_metrics(&caches, &pool, 5);
+        assert!(output.contains("# TYPE weather_http_request_duration_seconds histogram"));
+        assert!(output.contains("weather_cache_hits_total{cache=\"weather_data\"} 3"));
+        assert!(output.contains("weather_cache_misses_total{cache=\"weather_data\"} 1"));
+        assert!(output.contains(
+            "weather_upstream_calls_total{provider=\"test-provider\",endpoint=\"weather\",\
+             outcome=\"failure\"} 1"
+        ));
+        assert!(output.contains("weather_skipped_records_total 5"));
+        assert!(output.contains("weather_db_pool_size 4"));
+    }
+}
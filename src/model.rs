@@ -4,21 +4,34 @@ use isocountry::CountryCode;
 use postgres_query::{
     client::GenericClient, query, query_dyn, Error as PgError, FromSqlRow, Parameter,
 };
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use stack_string::{format_sstr, StackString};
-use std::convert::TryInto;
-use time::{macros::time, Date, OffsetDateTime, PrimitiveDateTime};
+use std::{convert::TryInto, fmt, str::FromStr};
+use time::{macros::time, Date, Duration, OffsetDateTime, PrimitiveDateTime};
+use tracing::instrument;
 use uuid::Uuid;
 
+use weather_api_types::get_parameters;
 use weather_util_rust::{
     direction::Direction,
     distance::Distance,
     precipitation::Precipitation,
     weather_api::{WeatherApi, WeatherLocation},
     weather_data::{Coord, Rain, Snow, Sys, WeatherCond, WeatherData, WeatherMain, Wind},
+    weather_forecast::WeatherForecast,
 };
 
-use crate::{date_time_wrapper::DateTimeWrapper, pgpool::PgPool};
+use crate::{
+    area::AreaFilter,
+    condition::ConditionFilter,
+    config::{ConfigInner, ProviderOverride, RecordedLocation},
+    date_time_wrapper::DateTimeWrapper,
+    derived_metrics::DerivedMetrics,
+    pgpool::PgPool,
+    publish::DailySummary,
+};
 
 #[derive(FromSqlRow, Clone, Debug)]
 pub struct AuthorizedUsers {
@@ -70,6 +83,8 @@ pub struct WeatherDataDB {
     pub latitude: f64,
     pub longitude: f64,
     pub condition: StackString,
+    /// openweathermap condition id of the first condition
+    pub condition_code: Option<i32>,
     pub temperature: f64,
     pub temperature_minimum: f64,
     pub temperature_maximum: f64,
@@ -85,6 +100,58 @@ pub struct WeatherDataDB {
     pub sunset: DateTimeWrapper,
     pub timezone: i32,
     pub server: StackString,
+    /// dew point (K) derived from temperature and humidity
+    pub dew_point: Option<f64>,
+    /// heat index (K), only defined from 80°F
+    pub heat_index: Option<f64>,
+    /// wind chill (K), only defined at or below 50°F with wind above 3 mph
+    pub wind_chill: Option<f64>,
+    /// filled in from a historical api rather than observed
+    #[serde(default)]
+    pub backfilled: bool,
+    /// when the row was written to the db, `None` for rows that weren't read
+    /// from it
+    #[serde(default)]
+    pub inserted_at: Option<DateTimeWrapper>,
+}
+
+#[cfg(test)]
+impl WeatherDataDB {
+    /// Mild observation at `created_at` for unit tests, override the fields
+    /// a test cares about with `..WeatherDataDB::test_row(created_at)`
+    #[must_use]
+    pub fn test_row(created_at: OffsetDateTime) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            dt: created_at.unix_timestamp() as i32,
+            created_at: created_at.into(),
+            location_name: "11106".into(),
+            latitude: 40.76,
+            longitude: -73.93,
+            condition: "".into(),
+            condition_code: None,
+            temperature: 290.0,
+            temperature_minimum: 290.0,
+            temperature_maximum: 290.0,
+            pressure: 101.3,
+            humidity: 80,
+            visibility: None,
+            rain: None,
+            snow: None,
+            wind_speed: 2.0,
+            wind_direction: None,
+            country: "US".into(),
+            sunrise: created_at.into(),
+            sunset: created_at.into(),
+            timezone: 0,
+            server: "test".into(),
+            dew_point: None,
+            heat_index: None,
+            wind_chill: None,
+            backfilled: false,
+            inserted_at: None,
+        }
+    }
 }
 
 impl From<WeatherData> for WeatherDataDB {
@@ -96,6 +163,11 @@ impl From<WeatherData> for WeatherDataDB {
             .collect();
         let tz: i32 = value.timezone.into();
         let humidity: i64 = value.main.humidity.into();
+        let derived = DerivedMetrics::new(
+            value.main.temp.kelvin(),
+            humidity as i32,
+            value.wind.speed.mps(),
+        );
         Self {
             id: Uuid::new_v4(),
             dt: value.dt.unix_timestamp() as i32,
@@ -104,6 +176,7 @@ impl From<WeatherData> for WeatherDataDB {
             latitude: value.coord.lat.into(),
             longitude: value.coord.lon.into(),
             condition: conditions.join(", ").into(),
+            condition_code: value.weather.first().and_then(|w| w.id.try_into().ok()),
             temperature: value.main.temp.kelvin(),
             temperature_minimum: value.main.temp_min.kelvin(),
             temperature_maximum: value.main.temp_max.kelvin(),
@@ -123,6 +196,11 @@ impl From<WeatherData> for WeatherDataDB {
             sunset: value.sys.sunset.into(),
             timezone: tz,
             server: "N/A".into(),
+            dew_point: derived.dew_point,
+            heat_index: derived.heat_index,
+            wind_chill: derived.wind_chill,
+            backfilled: false,
+            inserted_at: None,
         }
     }
 }
@@ -135,7 +213,10 @@ impl From<WeatherDataDB> for WeatherData {
                 lat: value.latitude.try_into().unwrap(),
             },
             weather: vec![WeatherCond {
-                id: 0,
+                id: value
+                    .condition_code
+                    .and_then(|c| c.try_into().ok())
+                    .unwrap_or(0),
                 main: value.condition.into(),
                 description: String::new(),
                 icon: String::new(),
@@ -174,6 +255,274 @@ impl From<WeatherDataDB> for WeatherData {
     }
 }
 
+impl fmt::Display for WeatherDataDB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {:0.2}K {:0.2}kPa {}% {}",
+            self.created_at,
+            self.location_name,
+            self.server,
+            self.temperature,
+            self.pressure,
+            self.humidity,
+            self.condition.trim(),
+        )
+    }
+}
+
+/// Keyset position in the weather history, the `created_at` and `id` of the
+/// last row returned, rendered as `<created_at>_<id>`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HistoryCursor {
+    pub created_at: DateTimeWrapper,
+    pub id: Uuid,
+}
+
+impl From<&WeatherDataDB> for HistoryCursor {
+    fn from(value: &WeatherDataDB) -> Self {
+        Self {
+            created_at: value.created_at,
+            id: value.id,
+        }
+    }
+}
+
+impl fmt::Display for HistoryCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.created_at, self.id)
+    }
+}
+
+impl FromStr for HistoryCursor {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (created_at, id) = s
+            .split_once('_')
+            .ok_or_else(|| format_err!("Invalid history cursor {s}"))?;
+        Ok(Self {
+            created_at: created_at.parse()?,
+            id: id.parse()?,
+        })
+    }
+}
+
+/// Column the history is ordered by, ties are broken by `created_at` and `id`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HistorySort {
+    #[default]
+    CreatedAt,
+    Temperature,
+    WindSpeed,
+}
+
+impl HistorySort {
+    #[must_use]
+    pub fn column(self) -> &'static str {
+        match self {
+            Self::CreatedAt => "created_at",
+            Self::Temperature => "temperature",
+            Self::WindSpeed => "wind_speed",
+        }
+    }
+}
+
+impl FromStr for HistorySort {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "created_at" => Ok(Self::CreatedAt),
+            "temperature" => Ok(Self::Temperature),
+            "wind_speed" => Ok(Self::WindSpeed),
+            _ => Err(format_err!("Invalid sort {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    #[must_use]
+    pub fn to_sql(self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "asc" => Ok(Self::Asc),
+            "desc" => Ok(Self::Desc),
+            _ => Err(format_err!("Invalid order {s}")),
+        }
+    }
+}
+
+/// `ORDER BY` clause of the history queries
+#[must_use]
+pub fn history_order_by(sort: HistorySort, order: SortOrder) -> StackString {
+    let order = order.to_sql();
+    match sort {
+        HistorySort::CreatedAt => format_sstr!("ORDER BY created_at {order}, id {order}"),
+        _ => format_sstr!(
+            "ORDER BY {} {order}, created_at {order}, id {order}",
+            sort.column()
+        ),
+    }
+}
+
+/// Filters shared by the `weather_data` history queries
+struct HistoryConstraints<'a> {
+    name: Option<&'a str>,
+    server: Option<&'a str>,
+    start_date: Option<OffsetDateTime>,
+    end_date: Option<OffsetDateTime>,
+    condition_range: Option<(i32, i32)>,
+    area: Option<AreaFilter>,
+    asof: Option<OffsetDateTime>,
+}
+
+impl<'a> HistoryConstraints<'a> {
+    fn new(
+        name: Option<&'a str>,
+        server: Option<&'a str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        condition: Option<ConditionFilter>,
+        area: Option<AreaFilter>,
+        asof: Option<OffsetDateTime>,
+    ) -> Self {
+        Self {
+            name,
+            server,
+            start_date: start_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc()),
+            end_date: end_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc()),
+            condition_range: condition.map(ConditionFilter::code_range),
+            area,
+            asof,
+        }
+    }
+
+    /// `WHERE` constraints and their bindings, joined with `AND` by the
+    /// caller
+    fn build_constraints(&self) -> (Vec<StackString>, Vec<(&'static str, Parameter<'_>)>) {
+        let mut bindings = Vec::new();
+        let mut constraints = Vec::new();
+        if let Some(name) = &self.name {
+            constraints.push(format_sstr!("location_name = $name"));
+            bindings.push(("name", name as Parameter));
+        }
+        if let Some(server) = &self.server {
+            constraints.push(format_sstr!("server = $server"));
+            bindings.push(("server", server as Parameter));
+        }
+        if let Some(start_date) = &self.start_date {
+            constraints.push(format_sstr!("created_at >= $start_date"));
+            bindings.push(("start_date", start_date as Parameter));
+        }
+        if let Some(end_date) = &self.end_date {
+            constraints.push(format_sstr!("created_at <= $end_date"));
+            bindings.push(("end_date", end_date as Parameter));
+        }
+        if let Some((condition_min, condition_max)) = &self.condition_range {
+            constraints.push(format_sstr!(
+                "condition_code BETWEEN $condition_min AND $condition_max"
+            ));
+            bindings.push(("condition_min", condition_min as Parameter));
+            bindings.push(("condition_max", condition_max as Parameter));
+        }
+        if let Some(area) = &self.area {
+            constraints.push(area.constraint());
+            bindings.extend(area.bindings());
+        }
+        if let Some(asof) = &self.asof {
+            constraints.push(format_sstr!("inserted_at <= $asof"));
+            bindings.push(("asof", asof as Parameter));
+        }
+        (constraints, bindings)
+    }
+}
+
+/// Columns of `weather_data` that can be selected with `fields=`
+const HISTORY_FIELDS: [&str; 28] = [
+    "id",
+    "dt",
+    "created_at",
+    "location_name",
+    "latitude",
+    "longitude",
+    "condition",
+    "condition_code",
+    "temperature",
+    "temperature_minimum",
+    "temperature_maximum",
+    "pressure",
+    "humidity",
+    "visibility",
+    "rain",
+    "snow",
+    "wind_speed",
+    "wind_direction",
+    "country",
+    "sunrise",
+    "sunset",
+    "timezone",
+    "server",
+    "dew_point",
+    "heat_index",
+    "wind_chill",
+    "backfilled",
+    "inserted_at",
+];
+
+/// Subset of the history columns returned to the client, parsed from a comma
+/// separated list e.g. `created_at,temperature`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryFields(Vec<&'static str>);
+
+impl HistoryFields {
+    #[must_use]
+    pub fn contains(&self, field: &str) -> bool {
+        self.0.contains(&field)
+    }
+
+    /// Remove every other field of a serialized row
+    pub fn project(&self, row: &mut serde_json::Value) {
+        if let serde_json::Value::Object(map) = row {
+            map.retain(|k, _| self.contains(k));
+        }
+    }
+}
+
+impl FromStr for HistoryFields {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = Vec::new();
+        for field in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let field = HISTORY_FIELDS
+                .iter()
+                .find(|f| **f == field)
+                .ok_or_else(|| format_err!("Invalid field {field}"))?;
+            if !fields.contains(field) {
+                fields.push(*field);
+            }
+        }
+        if fields.is_empty() {
+            return Err(format_err!("No fields selected"));
+        }
+        Ok(Self(fields))
+    }
+}
+
 impl WeatherDataDB {
     pub fn set_location_name(&mut self, name: &str) {
         self.location_name = name.into();
@@ -183,6 +532,156 @@ impl WeatherDataDB {
         self.server = server.into();
     }
 
+    /// Dew point, heat index and wind chill recomputed from the stored
+    /// temperature, humidity and wind speed
+    #[must_use]
+    pub fn derived_metrics(&self) -> DerivedMetrics {
+        DerivedMetrics::new(self.temperature, self.humidity, self.wind_speed)
+    }
+
+    /// Overwrite the derived columns from the current observation
+    pub fn set_derived_metrics(&mut self) {
+        let derived = self.derived_metrics();
+        self.dew_point = derived.dew_point;
+        self.heat_index = derived.heat_index;
+        self.wind_chill = derived.wind_chill;
+    }
+
+    /// Returns true if every metric is within the configured `record_delta_*`
+    /// epsilons of `last` and `last` is recent enough to stand in for this
+    /// row.
+    #[must_use]
+    pub fn is_within_delta(&self, last: &Self, config: &ConfigInner) -> bool {
+        let max_age = Duration::minutes(config.record_delta_max_age.into());
+        let close = |a: f64, b: f64, eps: f64| (a - b).abs() <= eps;
+        let close_opt = |a: Option<f64>, b: Option<f64>, eps: f64| {
+            close(a.unwrap_or(0.0), b.unwrap_or(0.0), eps)
+        };
+        *self.created_at - *last.created_at < max_age
+            && self.condition == last.condition
+            && close(
+                self.temperature,
+                last.temperature,
+                config.record_delta_temperature,
+            )
+            && close(self.pressure, last.pressure, config.record_delta_pressure)
+            && (self.humidity - last.humidity).abs() <= config.record_delta_humidity
+            && close(
+                self.wind_speed,
+                last.wind_speed,
+                config.record_delta_wind_speed,
+            )
+            && close_opt(self.rain, last.rain, config.record_delta_precipitation)
+            && close_opt(self.snow, last.snow, config.record_delta_precipitation)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    #[instrument(skip(pool))]
+    pub async fn get_latest_by_name(
+        pool: &PgPool,
+        name: &str,
+        server: &str,
+    ) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM weather_data
+                WHERE location_name = $name AND server = $server
+                ORDER BY created_at DESC
+                LIMIT 1
+            "#,
+            name = name,
+            server = server,
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// Most recent entry (from any server) of every location
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_latest_by_location(
+        pool: &PgPool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!(
+            r#"
+                SELECT DISTINCT ON (location_name) *
+                FROM weather_data
+                ORDER BY location_name, created_at DESC
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// Entry for `name` (from any server) observed closest to the unix
+    /// timestamp `dt`, at most `max_offset` seconds away
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    #[instrument(skip(pool))]
+    pub async fn get_closest_by_name(
+        pool: &PgPool,
+        name: &str,
+        dt: i32,
+        max_offset: i32,
+    ) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM weather_data
+                WHERE location_name = $name AND dt >= $min_dt AND dt <= $max_dt
+                ORDER BY abs(dt - $dt), created_at DESC
+                LIMIT 1
+            "#,
+            name = name,
+            min_dt = dt - max_offset,
+            max_dt = dt + max_offset,
+            dt = dt,
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// Mean conditions of `name` by local hour of day over every year of
+    /// history, using the days within `window` days of the day of year `doy`
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_hourly_normals(
+        pool: &PgPool,
+        name: &str,
+        doy: i32,
+        window: i32,
+    ) -> Result<Vec<HourlyNormal>, Error> {
+        let query = query!(
+            r#"
+                SELECT extract(
+                           hour FROM (created_at AT TIME ZONE 'UTC') + timezone * interval '1 second'
+                       )::int AS hour,
+                       avg(temperature) AS temperature,
+                       avg(humidity)::float8 AS humidity,
+                       avg(pressure) AS pressure,
+                       avg(wind_speed) AS wind_speed,
+                       avg(coalesce(rain, 0.0) + coalesce(snow, 0.0)) AS precipitation,
+                       count(*) AS count
+                FROM weather_data
+                WHERE location_name = $name
+                  AND least(
+                        abs(extract(doy FROM created_at)::int - $doy),
+                        366 - abs(extract(doy FROM created_at)::int - $doy)
+                      ) <= $window
+                GROUP BY 1
+                ORDER BY 1
+            "#,
+            name = name,
+            doy = doy,
+            window = window,
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
     /// # Errors
     /// Return error if db query fails
     pub async fn get_by_id(pool: &PgPool, id: Uuid) -> Result<Option<Self>, Error> {
@@ -219,38 +718,26 @@ impl WeatherDataDB {
 
     /// # Errors
     /// Returns error if query fails
+    #[instrument(skip(pool))]
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_total_by_name_dates(
         pool: &PgPool,
         name: Option<&str>,
         server: Option<&str>,
         start_date: Option<Date>,
         end_date: Option<Date>,
+        condition: Option<ConditionFilter>,
+        area: Option<AreaFilter>,
+        asof: Option<OffsetDateTime>,
     ) -> Result<usize, Error> {
         #[derive(FromSqlRow)]
         struct Count {
             count: i64,
         }
 
-        let start_date = start_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
-        let end_date = end_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
-        let mut bindings = Vec::new();
-        let mut constraints = Vec::new();
-        if let Some(name) = &name {
-            constraints.push(format_sstr!("location_name = $name"));
-            bindings.push(("name", name as Parameter));
-        }
-        if let Some(server) = &server {
-            constraints.push(format_sstr!("server = $server"));
-            bindings.push(("server", server as Parameter));
-        }
-        if let Some(start_date) = &start_date {
-            constraints.push(format_sstr!("created_at >= $start_date"));
-            bindings.push(("start_date", start_date as Parameter));
-        }
-        if let Some(end_date) = &end_date {
-            constraints.push(format_sstr!("created_at <= $end_date"));
-            bindings.push(("end_date", end_date as Parameter));
-        }
+        let filter =
+            HistoryConstraints::new(name, server, start_date, end_date, condition, area, asof);
+        let (constraints, bindings) = filter.build_constraints();
         let where_str = if constraints.is_empty() {
             "".into()
         } else {
@@ -268,48 +755,181 @@ impl WeatherDataDB {
         Ok(count.count.try_into()?)
     }
 
+    /// Daily summaries aggregated by the database, same as
+    /// `publish::get_daily_summaries` of the rows
+    ///
+    /// # Errors
+    /// Returns error if query fails
+    #[instrument(skip(pool))]
+    pub async fn get_daily_summaries(
+        pool: &PgPool,
+        name: &str,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<Vec<DailySummary>, Error> {
+        #[derive(FromSqlRow)]
+        struct DailyRow {
+            date: Date,
+            temperature_low: f64,
+            temperature_high: f64,
+            temperature_mean: f64,
+            humidity_mean: f64,
+            wind_speed_mean: f64,
+            rain: f64,
+            snow: f64,
+            conditions: Option<StackString>,
+            observations: i64,
+        }
+
+        let filter =
+            HistoryConstraints::new(Some(name), server, start_date, end_date, None, None, asof);
+        let (constraints, bindings) = filter.build_constraints();
+        let where_str = constraints.join(" AND ");
+        // days are local to the row's timezone, readings report the previous
+        // hour so one precipitation value is kept per hour
+        let query = format_sstr!(
+            r#"
+                WITH observations AS (
+                    SELECT (to_timestamp(dt) AT TIME ZONE 'UTC'
+                            + make_interval(secs => timezone))::date AS date,
+                           dt / 3600 AS hour,
+                           temperature,
+                           humidity,
+                           wind_speed,
+                           coalesce(rain, 0) AS rain,
+                           coalesce(snow, 0) AS snow,
+                           trim(condition) AS condition
+                    FROM weather_data
+                    WHERE {where_str}
+                ), hourly AS (
+                    SELECT date,
+                           min(temperature) AS temperature_low,
+                           max(temperature) AS temperature_high,
+                           sum(temperature) AS temperature_sum,
+                           sum(humidity) AS humidity_sum,
+                           sum(wind_speed) AS wind_speed_sum,
+                           max(rain) AS rain,
+                           max(snow) AS snow,
+                           count(*) AS observations
+                    FROM observations
+                    GROUP BY date, hour
+                ), conditions AS (
+                    SELECT date, mode() WITHIN GROUP (ORDER BY condition) AS conditions
+                    FROM observations
+                    WHERE condition <> ''
+                    GROUP BY date
+                )
+                SELECT h.date,
+                       min(h.temperature_low) AS temperature_low,
+                       max(h.temperature_high) AS temperature_high,
+                       sum(h.temperature_sum) / sum(h.observations)::float8 AS temperature_mean,
+                       sum(h.humidity_sum)::float8 / sum(h.observations)::float8 AS humidity_mean,
+                       sum(h.wind_speed_sum) / sum(h.observations)::float8 AS wind_speed_mean,
+                       sum(h.rain) AS rain,
+                       sum(h.snow) AS snow,
+                       c.conditions,
+                       sum(h.observations)::int8 AS observations
+                FROM hourly h
+                LEFT JOIN conditions c ON c.date = h.date
+                GROUP BY h.date, c.conditions
+                ORDER BY h.date
+            "#
+        );
+        let query = query_dyn!(&query, ..bindings)?;
+        let conn = pool.get().await?;
+        let rows: Vec<DailyRow> = query.fetch(&conn).await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(DailySummary {
+                    location_name: name.into(),
+                    date: row.date,
+                    temperature_low: row.temperature_low,
+                    temperature_high: row.temperature_high,
+                    temperature_mean: row.temperature_mean,
+                    humidity_mean: row.humidity_mean,
+                    wind_speed_mean: row.wind_speed_mean,
+                    rain: row.rain,
+                    snow: row.snow,
+                    conditions: row.conditions.unwrap_or_default(),
+                    observations: row.observations.try_into()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Rows matching the filters, `asof` leaves out rows inserted after it so
+    /// that a query can be repeated with the same result after backfills
+    ///
     /// # Errors
     /// Return error if db query fails
+    #[instrument(skip(pool))]
+    #[allow(clippy::too_many_arguments)]
     pub async fn get_by_name_dates(
         pool: &PgPool,
         name: Option<&str>,
         server: Option<&str>,
         start_date: Option<Date>,
         end_date: Option<Date>,
+        condition: Option<ConditionFilter>,
+        area: Option<AreaFilter>,
+        asof: Option<OffsetDateTime>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        Self::get_by_name_dates_sorted(
+            pool,
+            name,
+            server,
+            start_date,
+            end_date,
+            condition,
+            area,
+            asof,
+            HistorySort::default(),
+            SortOrder::default(),
+            offset,
+            limit,
+        )
+        .await
+    }
+
+    /// Same as `get_by_name_dates` ordered by `sort`
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    #[instrument(skip(pool))]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_by_name_dates_sorted(
+        pool: &PgPool,
+        name: Option<&str>,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        condition: Option<ConditionFilter>,
+        area: Option<AreaFilter>,
+        asof: Option<OffsetDateTime>,
+        sort: HistorySort,
+        order: SortOrder,
         offset: Option<usize>,
         limit: Option<usize>,
     ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
         let conn = pool.get().await?;
-        let start_date = start_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
-        let end_date = end_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
-        let mut bindings = Vec::new();
-        let mut constraints = Vec::new();
-        if let Some(name) = &name {
-            constraints.push(format_sstr!("location_name = $name"));
-            bindings.push(("name", name as Parameter));
-        }
-        if let Some(server) = &server {
-            constraints.push(format_sstr!("server = $server"));
-            bindings.push(("server", server as Parameter));
-        }
-        if let Some(start_date) = &start_date {
-            constraints.push(format_sstr!("created_at >= $start_date"));
-            bindings.push(("start_date", start_date as Parameter));
-        }
-        if let Some(end_date) = &end_date {
-            constraints.push(format_sstr!("created_at <= $end_date"));
-            bindings.push(("end_date", end_date as Parameter));
-        }
+        let filter =
+            HistoryConstraints::new(name, server, start_date, end_date, condition, area, asof);
+        let (constraints, bindings) = filter.build_constraints();
         let where_str = if constraints.is_empty() {
             "".into()
         } else {
             format_sstr!("WHERE {}", constraints.join(" AND "))
         };
+        let order_by = history_order_by(sort, order);
         let mut query = format_sstr!(
             r#"
                 SELECT * FROM weather_data
                 {where_str}
-                ORDER BY created_at
+                {order_by}
             "#
         );
         if let Some(offset) = &offset {
@@ -322,6 +942,60 @@ impl WeatherDataDB {
         query.fetch_streaming(&conn).await.map_err(Into::into)
     }
 
+    /// Page through the history ordered by `(created_at, id)` (descending
+    /// when `order` is `Desc`), starting after `cursor` rather than skipping
+    /// `OFFSET` rows.
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    #[instrument(skip(pool))]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_by_name_dates_after(
+        pool: &PgPool,
+        name: Option<&str>,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        condition: Option<ConditionFilter>,
+        area: Option<AreaFilter>,
+        asof: Option<OffsetDateTime>,
+        cursor: Option<HistoryCursor>,
+        order: SortOrder,
+        limit: usize,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let conn = pool.get().await?;
+        let filter =
+            HistoryConstraints::new(name, server, start_date, end_date, condition, area, asof);
+        let (mut constraints, mut bindings) = filter.build_constraints();
+        if let Some(cursor) = &cursor {
+            let op = match order {
+                SortOrder::Asc => ">",
+                SortOrder::Desc => "<",
+            };
+            constraints.push(format_sstr!(
+                "(created_at, id) {op} ($cursor_created_at, $cursor_id)"
+            ));
+            bindings.push(("cursor_created_at", &cursor.created_at as Parameter));
+            bindings.push(("cursor_id", &cursor.id as Parameter));
+        }
+        let where_str = if constraints.is_empty() {
+            "".into()
+        } else {
+            format_sstr!("WHERE {}", constraints.join(" AND "))
+        };
+        let order_by = history_order_by(HistorySort::CreatedAt, order);
+        let query = format_sstr!(
+            r#"
+                SELECT * FROM weather_data
+                {where_str}
+                {order_by}
+                LIMIT {limit}
+            "#
+        );
+        let query = query_dyn!(&query, ..bindings)?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
     /// # Errors
     /// Return error if db query fails
     pub async fn get_total_locations(pool: &PgPool) -> Result<usize, Error> {
@@ -338,6 +1012,7 @@ impl WeatherDataDB {
 
     /// # Errors
     /// Return error if db query fails
+    #[instrument(skip(pool))]
     pub async fn get_locations(
         pool: &PgPool,
         offset: Option<usize>,
@@ -385,13 +1060,24 @@ impl WeatherDataDB {
     /// Return error if db query fails
     pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
         let conn = pool.get().await?;
-        self.insert_conn(&conn).await
+        self.insert_conn(&conn, self.backfilled).await
     }
 
-    async fn insert_conn<C>(&self, conn: &C) -> Result<u64, Error>
+    /// Insert an observation filled in from a historical api, flagged with
+    /// `backfilled`
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert_backfilled(&self, pool: &PgPool) -> Result<u64, Error> {
+        let conn = pool.get().await?;
+        self.insert_conn(&conn, true).await
+    }
+
+    async fn insert_conn<C>(&self, conn: &C, backfilled: bool) -> Result<u64, Error>
     where
         C: GenericClient + Sync,
     {
+        let derived = self.derived_metrics();
         let query = query!(
             r#"
                 INSERT INTO weather_data (
@@ -401,6 +1087,7 @@ impl WeatherDataDB {
                     latitude,
                     longitude,
                     condition,
+                    condition_code,
                     temperature,
                     temperature_minimum,
                     temperature_maximum,
@@ -415,7 +1102,11 @@ impl WeatherDataDB {
                     sunrise,
                     sunset,
                     timezone,
-                    server
+                    server,
+                    dew_point,
+                    heat_index,
+                    wind_chill,
+                    backfilled
                 ) VALUES (
                     $dt,
                     $created_at,
@@ -423,6 +1114,7 @@ impl WeatherDataDB {
                     $latitude,
                     $longitude,
                     $condition,
+                    $condition_code,
                     $temperature,
                     $temperature_minimum,
                     $temperature_maximum,
@@ -437,7 +1129,11 @@ impl WeatherDataDB {
                     $sunrise,
                     $sunset,
                     $timezone,
-                    $server
+                    $server,
+                    $dew_point,
+                    $heat_index,
+                    $wind_chill,
+                    $backfilled
                 ) ON CONFLICT DO NOTHING
             "#,
             dt = self.dt,
@@ -446,6 +1142,7 @@ impl WeatherDataDB {
             latitude = self.latitude,
             longitude = self.longitude,
             condition = self.condition,
+            condition_code = self.condition_code,
             temperature = self.temperature,
             temperature_minimum = self.temperature_minimum,
             temperature_maximum = self.temperature_maximum,
@@ -461,9 +1158,113 @@ impl WeatherDataDB {
             sunset = self.sunset,
             timezone = self.timezone,
             server = self.server,
+            dew_point = derived.dew_point,
+            heat_index = derived.heat_index,
+            wind_chill = derived.wind_chill,
+            backfilled = backfilled,
         );
         query.execute(conn).await.map_err(Into::into)
     }
+
+    /// Overwrite the stored row with the same id, `dt`, `created_at` and
+    /// `server` are left unchanged
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn update(&self, pool: &PgPool) -> Result<u64, Error> {
+        let conn = pool.get().await?;
+        let derived = self.derived_metrics();
+        let query = query!(
+            r#"
+                UPDATE weather_data
+                SET location_name = $location_name,
+                    latitude = $latitude,
+                    longitude = $longitude,
+                    condition = $condition,
+                    condition_code = $condition_code,
+                    temperature = $temperature,
+                    temperature_minimum = $temperature_minimum,
+                    temperature_maximum = $temperature_maximum,
+                    pressure = $pressure,
+                    humidity = $humidity,
+                    visibility = $visibility,
+                    rain = $rain,
+                    snow = $snow,
+                    wind_speed = $wind_speed,
+                    wind_direction = $wind_direction,
+                    country = $country,
+                    sunrise = $sunrise,
+                    sunset = $sunset,
+                    timezone = $timezone,
+                    dew_point = $dew_point,
+                    heat_index = $heat_index,
+                    wind_chill = $wind_chill
+                WHERE id = $id
+            "#,
+            id = self.id,
+            location_name = self.location_name,
+            latitude = self.latitude,
+            longitude = self.longitude,
+            condition = self.condition,
+            condition_code = self.condition_code,
+            temperature = self.temperature,
+            temperature_minimum = self.temperature_minimum,
+            temperature_maximum = self.temperature_maximum,
+            pressure = self.pressure,
+            humidity = self.humidity,
+            visibility = self.visibility,
+            rain = self.rain,
+            snow = self.snow,
+            wind_speed = self.wind_speed,
+            wind_direction = self.wind_direction,
+            country = self.country,
+            sunrise = self.sunrise,
+            sunset = self.sunset,
+            timezone = self.timezone,
+            dew_point = derived.dew_point,
+            heat_index = derived.heat_index,
+            wind_chill = derived.wind_chill,
+        );
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+/// Row of the change feed, `seq` is assigned by the `weather_data_bump_seq`
+/// trigger on every insert and update, in commit order
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct WeatherDataChange {
+    pub seq: i64,
+    #[row(flatten)]
+    pub data: WeatherDataDB,
+}
+
+impl WeatherDataChange {
+    /// Rows inserted or updated after `since_seq`, ordered by `seq`
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_since(pool: &PgPool, since_seq: i64, limit: i64) -> Result<Vec<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM weather_data
+                WHERE seq > $since_seq
+                ORDER BY seq
+                LIMIT $limit
+            "#,
+            since_seq = since_seq,
+            limit = limit,
+        );
+        let conn = pool.get().await?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    /// `next_seq` to request the following page with and whether the page
+    /// was full (more rows may be waiting)
+    #[must_use]
+    pub fn get_next_seq(changes: &[Self], since_seq: i64, limit: usize) -> (i64, bool) {
+        let next_seq = changes.last().map_or(since_seq, |change| change.seq);
+        (next_seq, changes.len() >= limit)
+    }
 }
 
 #[derive(FromSqlRow, Serialize, Deserialize, Debug)]
@@ -476,6 +1277,7 @@ pub struct WeatherLocationCache {
     pub country_code: Option<StackString>,
     pub city_name: Option<StackString>,
     pub created_at: OffsetDateTime,
+    pub last_used_at: OffsetDateTime,
 }
 
 impl Default for WeatherLocationCache {
@@ -489,6 +1291,7 @@ impl Default for WeatherLocationCache {
             country_code: None,
             city_name: None,
             created_at: OffsetDateTime::now_utc(),
+            last_used_at: OffsetDateTime::now_utc(),
         }
     }
 }
@@ -503,6 +1306,24 @@ impl WeatherLocationCache {
         })
     }
 
+    /// Most recently cached entry of every location name
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_all(
+        pool: &PgPool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!(
+            r#"
+                SELECT DISTINCT ON (location_name) *
+                FROM weather_location_cache
+                ORDER BY location_name, created_at DESC
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
     /// # Errors
     /// Return error if db query fails
     pub async fn get_by_id(pool: &PgPool, id: Uuid) -> Result<Option<Self>, Error> {
@@ -596,9 +1417,11 @@ impl WeatherLocationCache {
         let query = query!(
             r#"
                 INSERT INTO weather_location_cache (
-                    location_name, latitude, longitude, zipcode, country_code, city_name, created_at
+                    location_name, latitude, longitude, zipcode, country_code, city_name,
+                    created_at, last_used_at
                 ) VALUES (
-                    $location_name, $latitude, $longitude, $zipcode, $country_code, $city_name, now()
+                    $location_name, $latitude, $longitude, $zipcode, $country_code, $city_name,
+                    now(), now()
                 )
             "#,
             location_name = self.location_name,
@@ -612,6 +1435,41 @@ impl WeatherLocationCache {
         query.execute(&conn).await.map_err(Into::into)
     }
 
+    /// Record a cache hit, `last_used_at` is only written once a day to keep
+    /// lookups cheap
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn touch(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                UPDATE weather_location_cache
+                SET last_used_at = now()
+                WHERE id = $id AND last_used_at < now() - interval '1 day'
+            "#,
+            id = self.id,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// Remove entries not used in the last `retention_days` days
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn delete_unused(pool: &PgPool, retention_days: u32) -> Result<u64, Error> {
+        let retention_days = i32::try_from(retention_days)?;
+        let query = query!(
+            r#"
+                DELETE FROM weather_location_cache
+                WHERE last_used_at < now() - make_interval(days => $retention_days)
+            "#,
+            retention_days = retention_days,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
     /// # Errors
     /// Return error if api call fails
     pub async fn from_weather_location(
@@ -703,46 +1561,224 @@ impl WeatherLocationCache {
             }
         }
     }
+
+    /// Remove every cached entry that `from_weather_location_cache` could
+    /// return for `location`
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn delete_by_weather_location(
+        pool: &PgPool,
+        location: &WeatherLocation,
+    ) -> Result<u64, Error> {
+        let conn = pool.get().await?;
+        match location {
+            WeatherLocation::LatLon {
+                latitude,
+                longitude,
+            } => {
+                let lat: f64 = (*latitude).into();
+                let lon: f64 = (*longitude).into();
+                let query = query!(
+                    r#"
+                        DELETE FROM weather_location_cache
+                        WHERE abs(latitude - $lat) < 0.007
+                          AND abs(longitude - $lon) < 0.008
+                    "#,
+                    lat = lat,
+                    lon = lon,
+                );
+                query.execute(&conn).await.map_err(Into::into)
+            }
+            WeatherLocation::ZipCode {
+                zipcode,
+                country_code,
+            } => {
+                let zip = *zipcode as i32;
+                let country_code = country_code.map(|c| format_sstr!("{c}"));
+                let mut constraints = vec!["zipcode=$zip"];
+                let mut bindings = vec![("zip", &zip as Parameter)];
+                if let Some(country_code) = &country_code {
+                    constraints.push("country_code=$country_code");
+                    bindings.push(("country_code", country_code as Parameter));
+                }
+                let query = format_sstr!(
+                    "DELETE FROM weather_location_cache WHERE {}",
+                    constraints.join(" AND "),
+                );
+                let query = query_dyn!(&query, ..bindings)?;
+                query.execute(&conn).await.map_err(Into::into)
+            }
+            WeatherLocation::CityName(city_name) => {
+                let query = query!(
+                    r#"
+                        DELETE FROM weather_location_cache
+                        WHERE city_name = $name OR location_name = $name
+                    "#,
+                    name = city_name.as_str(),
+                );
+                query.execute(&conn).await.map_err(Into::into)
+            }
+        }
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn delete_all(pool: &PgPool) -> Result<u64, Error> {
+        let conn = pool.get().await?;
+        let query = query!("DELETE FROM weather_location_cache");
+        query.execute(&conn).await.map_err(Into::into)
+    }
 }
 
 #[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
-pub struct KeyItemCache {
-    pub s3_key: StackString,
-    pub etag: StackString,
-    pub s3_timestamp: i64,
-    pub s3_size: i64,
-    pub has_local: bool,
-    pub has_remote: bool,
+pub struct WeatherEvent {
+    pub id: Uuid,
+    pub location_name: StackString,
+    pub server: StackString,
+    pub event_type: StackString,
+    pub start_time: DateTimeWrapper,
+    pub end_time: DateTimeWrapper,
+    pub peak_wind_speed: f64,
+    pub total_precipitation: f64,
+    pub min_pressure: f64,
+    pub max_pressure_drop_rate: f64,
+    pub created_at: DateTimeWrapper,
 }
 
-impl KeyItemCache {
+impl WeatherEvent {
     /// # Errors
     /// Return error if db query fails
-    pub async fn get_by_key(pool: &PgPool, s3_key: &str) -> Result<Option<Self>, Error> {
+    pub async fn get_events(
+        pool: &PgPool,
+        name: Option<&str>,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let conn = pool.get().await?;
+        let start_date = start_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
+        let end_date = end_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
+        let mut bindings = Vec::new();
+        let mut constraints = Vec::new();
+        if let Some(name) = &name {
+            constraints.push(format_sstr!("location_name = $name"));
+            bindings.push(("name", name as Parameter));
+        }
+        if let Some(server) = &server {
+            constraints.push(format_sstr!("server = $server"));
+            bindings.push(("server", server as Parameter));
+        }
+        if let Some(start_date) = &start_date {
+            constraints.push(format_sstr!("end_time >= $start_date"));
+            bindings.push(("start_date", start_date as Parameter));
+        }
+        if let Some(end_date) = &end_date {
+            constraints.push(format_sstr!("start_time <= $end_date"));
+            bindings.push(("end_date", end_date as Parameter));
+        }
+        let where_str = if constraints.is_empty() {
+            "".into()
+        } else {
+            format_sstr!("WHERE {}", constraints.join(" AND "))
+        };
+        let query = format_sstr!(
+            r#"
+                SELECT * FROM weather_events
+                {where_str}
+                ORDER BY start_time
+            "#
+        );
+        let query = query_dyn!(&query, ..bindings)?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn upsert(&self, pool: &PgPool) -> Result<u64, Error> {
         let query = query!(
-            "SELECT * FROM key_item_cache WHERE s3_key = $s3_key",
-            s3_key = s3_key
+            r#"
+                INSERT INTO weather_events (
+                    location_name,
+                    server,
+                    event_type,
+                    start_time,
+                    end_time,
+                    peak_wind_speed,
+                    total_precipitation,
+                    min_pressure,
+                    max_pressure_drop_rate
+                ) VALUES (
+                    $location_name,
+                    $server,
+                    $event_type,
+                    $start_time,
+                    $end_time,
+                    $peak_wind_speed,
+                    $total_precipitation,
+                    $min_pressure,
+                    $max_pressure_drop_rate
+                ) ON CONFLICT (location_name, server, start_time) DO UPDATE SET
+                    end_time = EXCLUDED.end_time,
+                    peak_wind_speed = EXCLUDED.peak_wind_speed,
+                    total_precipitation = EXCLUDED.total_precipitation,
+                    min_pressure = EXCLUDED.min_pressure,
+                    max_pressure_drop_rate = EXCLUDED.max_pressure_drop_rate
+            "#,
+            location_name = self.location_name,
+            server = self.server,
+            event_type = self.event_type,
+            start_time = self.start_time,
+            end_time = self.end_time,
+            peak_wind_speed = self.peak_wind_speed,
+            total_precipitation = self.total_precipitation,
+            min_pressure = self.min_pressure,
+            max_pressure_drop_rate = self.max_pressure_drop_rate,
         );
         let conn = pool.get().await?;
-        query.fetch_opt(&conn).await.map_err(Into::into)
+        query.execute(&conn).await.map_err(Into::into)
     }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct LightningActivity {
+    pub id: Uuid,
+    pub location_name: StackString,
+    pub server: StackString,
+    pub recorded_at: DateTimeWrapper,
+    pub radius_km: f64,
+    pub strike_count: i32,
+    pub nearest_distance_km: Option<f64>,
+}
 
+impl LightningActivity {
     /// # Errors
     /// Return error if db query fails
-    pub async fn get_files(
+    pub async fn get_recent(
         pool: &PgPool,
-        has_remote: bool,
-        has_local: bool,
+        name: &str,
+        server: Option<&str>,
+        since: OffsetDateTime,
     ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
-        let query = query!(
+        let conn = pool.get().await?;
+        let mut bindings = vec![("name", &name as Parameter), ("since", &since as Parameter)];
+        let mut constraints = vec![
+            format_sstr!("location_name = $name"),
+            format_sstr!("recorded_at >= $since"),
+        ];
+        if let Some(server) = &server {
+            constraints.push(format_sstr!("server = $server"));
+            bindings.push(("server", server as Parameter));
+        }
+        let query = format_sstr!(
             r#"
-                SELECT * FROM key_item_cache
-                WHERE has_remote = $has_remote AND has_local = $has_local
+                SELECT * FROM lightning_activity
+                WHERE {}
+                ORDER BY recorded_at
             "#,
-            has_remote = has_remote,
-            has_local = has_local,
+            constraints.join(" AND ")
         );
-        let conn = pool.get().await?;
+        let query = query_dyn!(&query, ..bindings)?;
         query.fetch_streaming(&conn).await.map_err(Into::into)
     }
 
@@ -751,52 +1787,1178 @@ impl KeyItemCache {
     pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
         let query = query!(
             r#"
-                INSERT INTO key_item_cache (
-                    s3_key,
-                    etag,
-                    s3_timestamp,
-                    s3_size,
-                    has_local,
-                    has_remote
+                INSERT INTO lightning_activity (
+                    location_name,
+                    server,
+                    recorded_at,
+                    radius_km,
+                    strike_count,
+                    nearest_distance_km
                 ) VALUES (
-                    $s3_key,
-                    $etag,
-                    $s3_timestamp,
-                    $s3_size,
-                    $has_local,
-                    $has_remote
-                ) ON CONFLICT (s3_key) DO UPDATE
-                    SET etag=$etag,
-                        s3_timestamp=$s3_timestamp,
-                        s3_size=$s3_size,
-                        has_local=$has_local,
-                        has_remote=$has_remote
+                    $location_name,
+                    $server,
+                    $recorded_at,
+                    $radius_km,
+                    $strike_count,
+                    $nearest_distance_km
+                ) ON CONFLICT (location_name, server, recorded_at) DO NOTHING
             "#,
-            s3_key = self.s3_key,
-            etag = self.etag,
-            s3_timestamp = self.s3_timestamp,
-            s3_size = self.s3_size,
-            has_local = self.has_local,
-            has_remote = self.has_remote,
+            location_name = self.location_name,
+            server = self.server,
+            recorded_at = self.recorded_at,
+            radius_km = self.radius_km,
+            strike_count = self.strike_count,
+            nearest_distance_km = self.nearest_distance_km,
         );
         let conn = pool.get().await?;
         query.execute(&conn).await.map_err(Into::into)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use anyhow::Error;
-    use log::info;
-
-    use weather_util_rust::weather_api::{WeatherApi, WeatherLocation};
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct AirQualityData {
+    pub id: Uuid,
+    pub location_name: StackString,
+    pub server: StackString,
+    pub recorded_at: DateTimeWrapper,
+    /// openweathermap air quality index, 1 (good) to 5 (very poor)
+    pub aqi: i32,
+    /// concentrations in μg/m³
+    pub pm2_5: f64,
+    pub pm10: f64,
+    pub o3: f64,
+    pub no2: f64,
+}
 
-    use crate::{config::Config, model::WeatherDataDB, pgpool::PgPool};
+impl AirQualityData {
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_latest(
+        pool: &PgPool,
+        name: &str,
+        server: &str,
+    ) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM air_quality_data
+                WHERE location_name = $name AND server = $server
+                ORDER BY recorded_at DESC
+                LIMIT 1
+            "#,
+            name = name,
+            server = server,
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
 
-    #[tokio::test]
-    #[ignore]
-    async fn test_weather_data_db() -> Result<(), Error> {
-        let config = Config::init_config(None)?;
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO air_quality_data (
+                    location_name,
+                    server,
+                    recorded_at,
+                    aqi,
+                    pm2_5,
+                    pm10,
+                    o3,
+                    no2
+                ) VALUES (
+                    $location_name,
+                    $server,
+                    $recorded_at,
+                    $aqi,
+                    $pm2_5,
+                    $pm10,
+                    $o3,
+                    $no2
+                ) ON CONFLICT (location_name, server, recorded_at) DO NOTHING
+            "#,
+            location_name = self.location_name,
+            server = self.server,
+            recorded_at = self.recorded_at,
+            aqi = self.aqi,
+            pm2_5 = self.pm2_5,
+            pm10 = self.pm10,
+            o3 = self.o3,
+            no2 = self.no2,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct UvIndexData {
+    pub id: Uuid,
+    pub location_name: StackString,
+    pub server: StackString,
+    pub recorded_at: DateTimeWrapper,
+    /// uv index, 0 to 11+
+    pub uvi: f64,
+    /// utc offset (seconds) of the location
+    pub timezone: i32,
+}
+
+impl UvIndexData {
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_latest(
+        pool: &PgPool,
+        name: &str,
+        server: &str,
+    ) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM uv_index_data
+                WHERE location_name = $name AND server = $server
+                ORDER BY recorded_at DESC
+                LIMIT 1
+            "#,
+            name = name,
+            server = server,
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// Readings of `name` recorded between `start_date` and `end_date`
+    /// (inclusive), ordered by `recorded_at`
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_name_dates(
+        pool: &PgPool,
+        name: &str,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+    ) -> Result<Vec<Self>, Error> {
+        let conn = pool.get().await?;
+        let mut bindings = vec![("name", &name as Parameter)];
+        let mut constraints = vec![format_sstr!("location_name = $name")];
+        if let Some(server) = &server {
+            constraints.push(format_sstr!("server = $server"));
+            bindings.push(("server", server as Parameter));
+        }
+        if let Some(start_date) = &start_date {
+            constraints.push(format_sstr!("date(recorded_at) >= $start_date"));
+            bindings.push(("start_date", start_date as Parameter));
+        }
+        if let Some(end_date) = &end_date {
+            constraints.push(format_sstr!("date(recorded_at) <= $end_date"));
+            bindings.push(("end_date", end_date as Parameter));
+        }
+        let query = format_sstr!(
+            r#"
+                SELECT * FROM uv_index_data
+                WHERE {}
+                ORDER BY recorded_at
+            "#,
+            constraints.join(" AND ")
+        );
+        let query = query_dyn!(&query, ..bindings)?;
+        query.fetch(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO uv_index_data (
+                    location_name,
+                    server,
+                    recorded_at,
+                    uvi,
+                    timezone
+                ) VALUES (
+                    $location_name,
+                    $server,
+                    $recorded_at,
+                    $uvi,
+                    $timezone
+                ) ON CONFLICT (location_name, server, recorded_at) DO NOTHING
+            "#,
+            location_name = self.location_name,
+            server = self.server,
+            recorded_at = self.recorded_at,
+            uvi = self.uvi,
+            timezone = self.timezone,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct WeatherSnapshot {
+    pub id: Uuid,
+    pub location_name: StackString,
+    pub taken_at: DateTimeWrapper,
+    pub image_url: Option<StackString>,
+    pub s3_key: Option<StackString>,
+    pub content_type: Option<StackString>,
+    pub created_at: DateTimeWrapper,
+}
+
+impl WeatherSnapshot {
+    /// Url of the image, snapshots uploaded to s3 are served by
+    /// `/weather/snapshots/image`
+    #[must_use]
+    pub fn get_url(&self) -> StackString {
+        self.image_url.clone().unwrap_or_else(|| {
+            let id = self.id;
+            format_sstr!("/weather/snapshots/image?id={id}")
+        })
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_id(pool: &PgPool, id: Uuid) -> Result<Option<Self>, Error> {
+        let query = query!("SELECT * FROM weather_snapshots WHERE id = $id", id = id);
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_latest(pool: &PgPool, name: &str) -> Result<Option<Self>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM weather_snapshots
+                WHERE location_name = $name
+                ORDER BY taken_at DESC
+                LIMIT 1
+            "#,
+            name = name
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_snapshots(
+        pool: &PgPool,
+        name: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let conn = pool.get().await?;
+        let start_date = start_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
+        let end_date = end_date.map(|d| PrimitiveDateTime::new(d, time!(00:00)).assume_utc());
+        let mut bindings = Vec::new();
+        let mut constraints = Vec::new();
+        if let Some(name) = &name {
+            constraints.push(format_sstr!("location_name = $name"));
+            bindings.push(("name", name as Parameter));
+        }
+        if let Some(start_date) = &start_date {
+            constraints.push(format_sstr!("taken_at >= $start_date"));
+            bindings.push(("start_date", start_date as Parameter));
+        }
+        if let Some(end_date) = &end_date {
+            constraints.push(format_sstr!("taken_at <= $end_date"));
+            bindings.push(("end_date", end_date as Parameter));
+        }
+        let where_str = if constraints.is_empty() {
+            "".into()
+        } else {
+            format_sstr!("WHERE {}", constraints.join(" AND "))
+        };
+        let query = format_sstr!(
+            r#"
+                SELECT * FROM weather_snapshots
+                {where_str}
+                ORDER BY taken_at
+            "#
+        );
+        let query = query_dyn!(&query, ..bindings)?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO weather_snapshots (
+                    id, location_name, taken_at, image_url, s3_key, content_type
+                ) VALUES (
+                    $id, $location_name, $taken_at, $image_url, $s3_key, $content_type
+                )
+            "#,
+            id = self.id,
+            location_name = self.location_name,
+            taken_at = self.taken_at,
+            image_url = self.image_url,
+            s3_key = self.s3_key,
+            content_type = self.content_type,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+/// User defined threshold on the observations or forecast of a location, a
+/// webhook is posted when it is crossed
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct AlertRule {
+    pub id: Uuid,
+    pub email: StackString,
+    pub location_name: StackString,
+    /// one of `AlertMetric`
+    pub metric: StackString,
+    /// `above` or `below`
+    pub comparison: StackString,
+    pub threshold: f64,
+    /// evaluate the forecast for the next `forecast_hours` rather than the
+    /// latest recorded observation
+    pub forecast_hours: Option<i32>,
+    pub webhook_url: StackString,
+    pub last_fired_at: Option<DateTimeWrapper>,
+    pub created_at: DateTimeWrapper,
+}
+
+impl AlertRule {
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_all(
+        pool: &PgPool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!("SELECT * FROM alert_rules ORDER BY location_name, created_at");
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_email(
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!(
+            "SELECT * FROM alert_rules WHERE email = $email ORDER BY created_at",
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO alert_rules (
+                    id, email, location_name, metric, comparison, threshold,
+                    forecast_hours, webhook_url, created_at
+                ) VALUES (
+                    $id, $email, $location_name, $metric, $comparison, $threshold,
+                    $forecast_hours, $webhook_url, $created_at
+                )
+            "#,
+            id = self.id,
+            email = self.email,
+            location_name = self.location_name,
+            metric = self.metric,
+            comparison = self.comparison,
+            threshold = self.threshold,
+            forecast_hours = self.forecast_hours,
+            webhook_url = self.webhook_url,
+            created_at = self.created_at,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// Delete a rule, only rules owned by `email` are removed
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn delete(pool: &PgPool, id: Uuid, email: &str) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM alert_rules WHERE id = $id AND email = $email",
+            id = id,
+            email = email,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn set_last_fired_at(
+        &self,
+        pool: &PgPool,
+        last_fired_at: DateTimeWrapper,
+    ) -> Result<u64, Error> {
+        let query = query!(
+            "UPDATE alert_rules SET last_fired_at = $last_fired_at WHERE id = $id",
+            id = self.id,
+            last_fired_at = last_fired_at,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct KeyItemCache {
+    pub s3_key: StackString,
+    pub etag: StackString,
+    pub s3_timestamp: i64,
+    pub s3_size: i64,
+    pub has_local: bool,
+    pub has_remote: bool,
+}
+
+impl KeyItemCache {
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_key(pool: &PgPool, s3_key: &str) -> Result<Option<Self>, Error> {
+        let query = query!(
+            "SELECT * FROM key_item_cache WHERE s3_key = $s3_key",
+            s3_key = s3_key
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_files(
+        pool: &PgPool,
+        has_remote: bool,
+        has_local: bool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM key_item_cache
+                WHERE has_remote = $has_remote AND has_local = $has_local
+            "#,
+            has_remote = has_remote,
+            has_local = has_local,
+        );
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// Remove entries of files deleted both locally and remotely
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn delete_orphaned(pool: &PgPool) -> Result<u64, Error> {
+        let query = query!("DELETE FROM key_item_cache WHERE NOT has_local AND NOT has_remote");
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO key_item_cache (
+                    s3_key,
+                    etag,
+                    s3_timestamp,
+                    s3_size,
+                    has_local,
+                    has_remote
+                ) VALUES (
+                    $s3_key,
+                    $etag,
+                    $s3_timestamp,
+                    $s3_size,
+                    $has_local,
+                    $has_remote
+                ) ON CONFLICT (s3_key) DO UPDATE
+                    SET etag=$etag,
+                        s3_timestamp=$s3_timestamp,
+                        s3_size=$s3_size,
+                        has_local=$has_local,
+                        has_remote=$has_remote
+            "#,
+            s3_key = self.s3_key,
+            etag = self.etag,
+            s3_timestamp = self.s3_timestamp,
+            s3_size = self.s3_size,
+            has_local = self.has_local,
+            has_remote = self.has_remote,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+/// Key of a programmatic client, only the sha256 of the key is stored
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub email: StackString,
+    pub key_hash: StackString,
+    pub description: Option<StackString>,
+    pub created_at: DateTimeWrapper,
+    pub revoked_at: Option<DateTimeWrapper>,
+}
+
+impl ApiKey {
+    /// New key of `email`, returns the row and the key itself which is
+    /// never stored
+    #[must_use]
+    pub fn generate(email: &str, description: Option<StackString>) -> (Self, StackString) {
+        let bytes: [u8; 24] = rand::thread_rng().gen();
+        let mut key = StackString::from("wak_");
+        for b in bytes {
+            key.push_str(&format_sstr!("{b:02x}"));
+        }
+        let api_key = Self {
+            id: Uuid::new_v4(),
+            email: email.into(),
+            key_hash: Self::hash_key(&key),
+            description,
+            created_at: DateTimeWrapper::now(),
+            revoked_at: None,
+        };
+        (api_key, key)
+    }
+
+    #[must_use]
+    pub fn hash_key(key: &str) -> StackString {
+        let digest = Sha256::digest(key.as_bytes());
+        let mut output = StackString::new();
+        for b in digest {
+            output.push_str(&format_sstr!("{b:02x}"));
+        }
+        output
+    }
+
+    /// Keys that aren't revoked of users that are still authorized
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_active(
+        pool: &PgPool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!(
+            r#"
+                SELECT a.*
+                FROM api_keys a
+                JOIN authorized_users u ON u.email = a.email
+                WHERE a.revoked_at IS NULL AND u.deleted_at IS NULL
+            "#
+        );
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_email(
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!(
+            "SELECT * FROM api_keys WHERE email = $email ORDER BY created_at",
+            email = email
+        );
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO api_keys (id, email, key_hash, description, created_at)
+                VALUES ($id, $email, $key_hash, $description, $created_at)
+            "#,
+            id = self.id,
+            email = self.email,
+            key_hash = self.key_hash,
+            description = self.description,
+            created_at = self.created_at,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// Revoke a key, only keys owned by `email` are revoked
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn revoke(pool: &PgPool, id: Uuid, email: &str) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                UPDATE api_keys SET revoked_at = now()
+                WHERE id = $id AND email = $email AND revoked_at IS NULL
+            "#,
+            id = id,
+            email = email,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+/// Provider account of a location, see `ProviderOverride`
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct LocationProviderOverride {
+    pub location_name: StackString,
+    pub api_key: Option<StackString>,
+    pub api_endpoint: Option<StackString>,
+    pub created_at: DateTimeWrapper,
+}
+
+impl LocationProviderOverride {
+    #[must_use]
+    pub fn provider_override(&self) -> ProviderOverride {
+        ProviderOverride {
+            api_key: self.api_key.clone(),
+            api_endpoint: self.api_endpoint.clone(),
+        }
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_all(
+        pool: &PgPool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!("SELECT * FROM location_provider_overrides ORDER BY location_name");
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_location_name(pool: &PgPool, name: &str) -> Result<Option<Self>, Error> {
+        let query = query!(
+            "SELECT * FROM location_provider_overrides WHERE location_name = $name",
+            name = name
+        );
+        let conn = pool.get().await?;
+        query.fetch_opt(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn upsert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO location_provider_overrides (
+                    location_name, api_key, api_endpoint, created_at
+                ) VALUES (
+                    $location_name, $api_key, $api_endpoint, $created_at
+                ) ON CONFLICT (location_name) DO UPDATE SET
+                    api_key = EXCLUDED.api_key,
+                    api_endpoint = EXCLUDED.api_endpoint
+            "#,
+            location_name = self.location_name,
+            api_key = self.api_key,
+            api_endpoint = self.api_endpoint,
+            created_at = self.created_at,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn delete(pool: &PgPool, name: &str) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM location_provider_overrides WHERE location_name = $name",
+            name = name
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+/// Location recorded by the daemon, replaces `locations_to_record` once the
+/// table has any rows
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedLocationDB {
+    pub location_name: StackString,
+    /// location as given, e.g. `10001` or `Paris`
+    pub location: StackString,
+    /// seconds between polls, `record_interval` when unset
+    pub interval_seconds: Option<i32>,
+    pub created_at: DateTimeWrapper,
+}
+
+impl From<&RecordedLocation> for RecordedLocationDB {
+    fn from(value: &RecordedLocation) -> Self {
+        Self {
+            location_name: format_sstr!("{}", value.location),
+            location: value.name.clone(),
+            interval_seconds: value.interval.and_then(|i| i.try_into().ok()),
+            created_at: DateTimeWrapper::now(),
+        }
+    }
+}
+
+impl From<RecordedLocationDB> for RecordedLocation {
+    fn from(value: RecordedLocationDB) -> Self {
+        Self {
+            location: get_parameters(&value.location),
+            name: value.location,
+            interval: value.interval_seconds.and_then(|i| i.try_into().ok()),
+        }
+    }
+}
+
+impl RecordedLocationDB {
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_all(
+        pool: &PgPool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!("SELECT * FROM recorded_locations ORDER BY location_name");
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn upsert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO recorded_locations (
+                    location_name, location, interval_seconds, created_at
+                ) VALUES (
+                    $location_name, $location, $interval_seconds, $created_at
+                ) ON CONFLICT (location_name) DO UPDATE SET
+                    location = EXCLUDED.location,
+                    interval_seconds = EXCLUDED.interval_seconds
+            "#,
+            location_name = self.location_name,
+            location = self.location,
+            interval_seconds = self.interval_seconds,
+            created_at = self.created_at,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn delete(pool: &PgPool, name: &str) -> Result<u64, Error> {
+        let query = query!(
+            "DELETE FROM recorded_locations WHERE location_name = $name",
+            name = name
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+
+    /// Fill an empty table with `locations_to_record`, later changes to the
+    /// config are ignored once the table has rows
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn seed(pool: &PgPool, recorded: &[RecordedLocation]) -> Result<u64, Error> {
+        #[derive(FromSqlRow)]
+        struct Count {
+            count: i64,
+        }
+
+        let query = query!("SELECT count(*) as count FROM recorded_locations");
+        let conn = pool.get().await?;
+        let count: Count = query.fetch_one(&conn).await?;
+        if count.count > 0 {
+            return Ok(0);
+        }
+        let mut inserted = 0;
+        for entry in recorded {
+            inserted += Self::from(entry).upsert(pool).await?;
+        }
+        Ok(inserted)
+    }
+}
+
+/// One 3 hour entry of a forecast as it was fetched, kept for the history
+/// of forecasts and to compare against the observations recorded later
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone)]
+pub struct WeatherForecastDB {
+    pub id: Uuid,
+    pub location_name: StackString,
+    pub server: StackString,
+    pub fetched_at: DateTimeWrapper,
+    pub forecast_time: DateTimeWrapper,
+    pub temperature: f64,
+    pub humidity: i32,
+    pub pressure: f64,
+    pub wind_speed: f64,
+    /// rain and snow of the 3 hours ending at `forecast_time` (mm)
+    pub precipitation: Option<f64>,
+    /// the remaining columns are null for rows recorded before the whole
+    /// entry was stored
+    pub condition: Option<StackString>,
+    /// openweathermap condition id of the first condition
+    pub condition_code: Option<i32>,
+    pub feels_like: Option<f64>,
+    pub temperature_minimum: Option<f64>,
+    pub temperature_maximum: Option<f64>,
+    /// rain of the 3 hours ending at `forecast_time` (mm)
+    pub rain: Option<f64>,
+    /// snow of the 3 hours ending at `forecast_time` (mm)
+    pub snow: Option<f64>,
+    pub wind_direction: Option<f64>,
+    /// utc offset (seconds) of the forecast location
+    pub timezone: Option<i32>,
+}
+
+impl WeatherForecastDB {
+    /// Rows of every entry of `forecast`
+    #[must_use]
+    pub fn from_forecast(
+        forecast: &WeatherForecast,
+        location_name: &str,
+        server: &str,
+        fetched_at: OffsetDateTime,
+    ) -> Vec<Self> {
+        let timezone: i32 = forecast.city.timezone.into();
+        forecast
+            .list
+            .iter()
+            .map(|entry| {
+                let conditions: Vec<_> = entry
+                    .weather
+                    .iter()
+                    .map(|w| format_sstr!("{} {} ", w.main, w.description))
+                    .collect();
+                let humidity: i64 = entry.main.humidity.into();
+                let rain = entry
+                    .rain
+                    .as_ref()
+                    .and_then(|r| r.three_hour.map(Precipitation::millimeters));
+                let snow = entry
+                    .snow
+                    .as_ref()
+                    .and_then(|s| s.three_hour.map(Precipitation::millimeters));
+                let precipitation = match (rain, snow) {
+                    (None, None) => None,
+                    (rain, snow) => Some(rain.unwrap_or(0.0) + snow.unwrap_or(0.0)),
+                };
+                Self {
+                    id: Uuid::new_v4(),
+                    location_name: location_name.into(),
+                    server: server.into(),
+                    fetched_at: fetched_at.into(),
+                    forecast_time: entry.dt.into(),
+                    temperature: entry.main.temp.kelvin(),
+                    humidity: humidity as i32,
+                    pressure: entry.main.pressure.kpa(),
+                    wind_speed: entry.wind.speed.mps(),
+                    precipitation,
+                    condition: Some(conditions.join(", ").into()),
+                    condition_code: entry.weather.first().and_then(|w| w.id.try_into().ok()),
+                    feels_like: Some(entry.main.feels_like.kelvin()),
+                    temperature_minimum: Some(entry.main.temp_min.kelvin()),
+                    temperature_maximum: Some(entry.main.temp_max.kelvin()),
+                    rain,
+                    snow,
+                    wind_direction: entry.wind.deg.map(|d| d.deg()),
+                    timezone: Some(timezone),
+                }
+            })
+            .collect()
+    }
+
+    /// Every forecast fetched for `name` between `start_date` and `end_date`
+    /// (inclusive), ordered by fetch time and then forecast time
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_name_fetched(
+        pool: &PgPool,
+        name: &str,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let conn = pool.get().await?;
+        let mut bindings = vec![("name", &name as Parameter)];
+        let mut constraints = vec![format_sstr!("location_name = $name")];
+        if let Some(server) = &server {
+            constraints.push(format_sstr!("server = $server"));
+            bindings.push(("server", server as Parameter));
+        }
+        if let Some(start_date) = &start_date {
+            constraints.push(format_sstr!("date(fetched_at) >= $start_date"));
+            bindings.push(("start_date", start_date as Parameter));
+        }
+        if let Some(end_date) = &end_date {
+            constraints.push(format_sstr!("date(fetched_at) <= $end_date"));
+            bindings.push(("end_date", end_date as Parameter));
+        }
+        let mut query = format_sstr!(
+            r#"
+                SELECT * FROM weather_forecast
+                WHERE {}
+                ORDER BY fetched_at, forecast_time
+            "#,
+            constraints.join(" AND ")
+        );
+        if let Some(offset) = offset {
+            query.push_str(&format_sstr!(" OFFSET {offset}"));
+        }
+        if let Some(limit) = limit {
+            query.push_str(&format_sstr!(" LIMIT {limit}"));
+        }
+        let query = query_dyn!(&query, ..bindings)?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// Entries forecast for `start..=end`, ordered by `forecast_time`
+    ///
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_name_dates(
+        pool: &PgPool,
+        name: &str,
+        server: Option<&str>,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let conn = pool.get().await?;
+        let mut bindings = vec![
+            ("name", &name as Parameter),
+            ("start", &start as Parameter),
+            ("end", &end as Parameter),
+        ];
+        let mut constraints = vec![
+            format_sstr!("location_name = $name"),
+            format_sstr!("forecast_time >= $start"),
+            format_sstr!("forecast_time <= $end"),
+        ];
+        if let Some(server) = &server {
+            constraints.push(format_sstr!("server = $server"));
+            bindings.push(("server", server as Parameter));
+        }
+        let query = format_sstr!(
+            r#"
+                SELECT * FROM weather_forecast
+                WHERE {}
+                ORDER BY forecast_time, fetched_at
+            "#,
+            constraints.join(" AND ")
+        );
+        let query = query_dyn!(&query, ..bindings)?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn insert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO weather_forecast (
+                    location_name,
+                    server,
+                    fetched_at,
+                    forecast_time,
+                    temperature,
+                    humidity,
+                    pressure,
+                    wind_speed,
+                    precipitation,
+                    condition,
+                    condition_code,
+                    feels_like,
+                    temperature_minimum,
+                    temperature_maximum,
+                    rain,
+                    snow,
+                    wind_direction,
+                    timezone
+                ) VALUES (
+                    $location_name,
+                    $server,
+                    $fetched_at,
+                    $forecast_time,
+                    $temperature,
+                    $humidity,
+                    $pressure,
+                    $wind_speed,
+                    $precipitation,
+                    $condition,
+                    $condition_code,
+                    $feels_like,
+                    $temperature_minimum,
+                    $temperature_maximum,
+                    $rain,
+                    $snow,
+                    $wind_direction,
+                    $timezone
+                ) ON CONFLICT (location_name, server, fetched_at, forecast_time) DO NOTHING
+            "#,
+            location_name = self.location_name,
+            server = self.server,
+            fetched_at = self.fetched_at,
+            forecast_time = self.forecast_time,
+            temperature = self.temperature,
+            humidity = self.humidity,
+            pressure = self.pressure,
+            wind_speed = self.wind_speed,
+            precipitation = self.precipitation,
+            condition = self.condition,
+            condition_code = self.condition_code,
+            feels_like = self.feels_like,
+            temperature_minimum = self.temperature_minimum,
+            temperature_maximum = self.temperature_maximum,
+            rain = self.rain,
+            snow = self.snow,
+            wind_direction = self.wind_direction,
+            timezone = self.timezone,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+/// Rendered payload sizes of a server rendered route, one row per route,
+/// deployed version and server
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RenderStatistics {
+    pub route: StackString,
+    pub version: StackString,
+    pub server: StackString,
+    pub count: i64,
+    pub p50_length: i64,
+    pub p95_length: i64,
+    pub max_length: i64,
+    pub last_render_at: Option<DateTimeWrapper>,
+    pub updated_at: DateTimeWrapper,
+}
+
+impl RenderStatistics {
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_all(
+        pool: &PgPool,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!("SELECT * FROM render_statistics ORDER BY route, updated_at");
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn get_by_version(
+        pool: &PgPool,
+        version: &str,
+        server: &str,
+    ) -> Result<impl Stream<Item = Result<Self, PgError>>, Error> {
+        let query = query!(
+            r#"
+                SELECT * FROM render_statistics
+                WHERE version = $version AND server = $server
+                ORDER BY route
+            "#,
+            version = version,
+            server = server,
+        );
+        let conn = pool.get().await?;
+        query.fetch_streaming(&conn).await.map_err(Into::into)
+    }
+
+    /// # Errors
+    /// Return error if db query fails
+    pub async fn upsert(&self, pool: &PgPool) -> Result<u64, Error> {
+        let query = query!(
+            r#"
+                INSERT INTO render_statistics (
+                    route, version, server, count, p50_length, p95_length,
+                    max_length, last_render_at, updated_at
+                ) VALUES (
+                    $route, $version, $server, $count, $p50_length, $p95_length,
+                    $max_length, $last_render_at, $updated_at
+                ) ON CONFLICT (route, version, server) DO UPDATE SET
+                    count = EXCLUDED.count,
+                    p50_length = EXCLUDED.p50_length,
+                    p95_length = EXCLUDED.p95_length,
+                    max_length = EXCLUDED.max_length,
+                    last_render_at = EXCLUDED.last_render_at,
+                    updated_at = EXCLUDED.updated_at
+            "#,
+            route = self.route,
+            version = self.version,
+            server = self.server,
+            count = self.count,
+            p50_length = self.p50_length,
+            p95_length = self.p95_length,
+            max_length = self.max_length,
+            last_render_at = self.last_render_at,
+            updated_at = self.updated_at,
+        );
+        let conn = pool.get().await?;
+        query.execute(&conn).await.map_err(Into::into)
+    }
+}
+
+/// Climatological mean conditions at one local hour of the day
+#[derive(FromSqlRow, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HourlyNormal {
+    pub hour: i32,
+    pub temperature: f64,
+    pub humidity: f64,
+    pub pressure: f64,
+    pub wind_speed: f64,
+    /// mean hourly rain and snow (mm)
+    pub precipitation: f64,
+    pub count: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use futures::TryStreamExt;
+    use log::info;
+    use postgres_query::{query, FromSqlRow};
+    use serde_json::json;
+    use time::{macros::datetime, Duration, OffsetDateTime};
+
+    use weather_util_rust::weather_api::{WeatherApi, WeatherLocation};
+
+    use crate::{
+        config::{Config, ConfigInner},
+        model::{
+            history_order_by, ApiKey, HistoryCursor, HistoryFields, HistorySort, SortOrder,
+            WeatherDataChange, WeatherDataDB,
+        },
+        pgpool::{PgPool, PgTransaction},
+    };
+
+    #[test]
+    fn test_api_key_generate() {
+        let (api_key, key) = ApiKey::generate("user@test", Some("script".into()));
+        assert!(key.starts_with("wak_"));
+        assert_eq!(key.len(), 4 + 48);
+        assert_eq!(api_key.key_hash, ApiKey::hash_key(&key));
+        assert_eq!(api_key.key_hash.len(), 64);
+        assert_ne!(api_key.key_hash, key);
+        let (other, other_key) = ApiKey::generate("user@test", None);
+        assert_ne!(key, other_key);
+        assert_ne!(api_key.key_hash, other.key_hash);
+        assert_eq!(
+            ApiKey::hash_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_weather_data_db() -> Result<(), Error> {
+        let config = Config::init_config(None)?;
         let api = WeatherApi::new(
             &config.api_key,
             &config.api_endpoint,
@@ -809,15 +2971,373 @@ mod tests {
         };
         let weather = api.get_weather_data(&loc).await?;
         let weather_db: WeatherDataDB = weather.into();
-        let db_url = &config.database_url;
-        let pool = PgPool::new(db_url)?;
+        let pool = PgPool::from_config(&config)?;
         let written = weather_db.insert(&pool).await?;
         info!("written {written}");
 
         let weather_fromcache =
             WeatherDataDB::get_by_dt_name(&pool, weather_db.dt, &weather_db.location_name).await?;
         assert!(weather_fromcache.is_some());
-        weather_fromcache.unwrap().delete(&pool).await?;
+        let mut weather_fromcache = weather_fromcache.unwrap();
+        weather_fromcache.temperature += 1.0;
+        assert_eq!(weather_fromcache.update(&pool).await?, 1);
+        let updated = WeatherDataDB::get_by_id(&pool, weather_fromcache.id)
+            .await?
+            .unwrap();
+        assert_eq!(updated.temperature, weather_fromcache.temperature);
+        weather_fromcache.delete(&pool).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_change_feed() -> Result<(), Error> {
+        #[derive(FromSqlRow)]
+        struct MaxSeq {
+            seq: i64,
+        }
+
+        async fn read_feed(pool: &PgPool, mut since_seq: i64) -> Result<Vec<(i64, i32)>, Error> {
+            let mut feed = Vec::new();
+            loop {
+                let changes = WeatherDataChange::get_since(pool, since_seq, 2).await?;
+                let (next_seq, has_more) = WeatherDataChange::get_next_seq(&changes, since_seq, 2);
+                feed.extend(
+                    changes
+                        .into_iter()
+                        .filter(|change| change.data.location_name == "test_change_feed")
+                        .map(|change| (change.seq, change.data.dt)),
+                );
+                since_seq = next_seq;
+                if !has_more {
+                    return Ok(feed);
+                }
+            }
+        }
+
+        let config = Config::init_config(None)?;
+        let pool = PgPool::from_config(&config)?;
+        let conn = pool.get().await?;
+        let query = query!("SELECT coalesce(max(seq), 0)::int8 AS seq FROM weather_data");
+        let max_seq: MaxSeq = query.fetch_one(&conn).await?;
+        let since_seq = max_seq.seq;
+
+        let start = datetime!(2001-02-03 04:05 UTC);
+        let rows: Vec<_> = (0..3)
+            .map(|i| WeatherDataDB {
+                location_name: "test_change_feed".into(),
+                ..WeatherDataDB::test_row(start + Duration::minutes(i))
+            })
+            .collect();
+        for row in &rows {
+            row.insert(&pool).await?;
+        }
+        let dts: Vec<_> = rows.iter().map(|row| row.dt).collect();
+
+        let feed = read_feed(&pool, since_seq).await?;
+        assert_eq!(feed.iter().map(|(_, dt)| *dt).collect::<Vec<_>>(), dts);
+        assert!(feed.windows(2).all(|w| w[0].0 < w[1].0));
+        assert!(feed[0].0 > since_seq);
+
+        // an update moves the row to the end of the feed
+        let mut first = WeatherDataDB::get_by_dt_name(&pool, dts[0], "test_change_feed")
+            .await?
+            .unwrap();
+        first.temperature += 1.0;
+        first.update(&pool).await?;
+        let updated = read_feed(&pool, since_seq).await?;
+        assert_eq!(
+            updated.iter().map(|(_, dt)| *dt).collect::<Vec<_>>(),
+            vec![dts[1], dts[2], dts[0]]
+        );
+        assert!(updated.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(read_feed(&pool, updated[2].0).await?, Vec::new());
+
+        for dt in dts {
+            if let Some(row) = WeatherDataDB::get_by_dt_name(&pool, dt, "test_change_feed").await? {
+                row.delete(&pool).await?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_change_feed_paging() {
+        let start = datetime!(2024-06-01 00:00 UTC);
+        let feed: Vec<_> = [3, 4, 7, 9, 10]
+            .into_iter()
+            .map(|seq| WeatherDataChange {
+                seq,
+                data: WeatherDataDB::test_row(start),
+            })
+            .collect();
+        let page = |since_seq: i64| -> Vec<WeatherDataChange> {
+            feed.iter()
+                .filter(|change| change.seq > since_seq)
+                .take(2)
+                .cloned()
+                .collect()
+        };
+        let mut since_seq = 0;
+        let mut pages = Vec::new();
+        loop {
+            let changes = page(since_seq);
+            let (next_seq, has_more) = WeatherDataChange::get_next_seq(&changes, since_seq, 2);
+            pages.push((
+                changes.iter().map(|c| c.seq).collect::<Vec<_>>(),
+                next_seq,
+                has_more,
+            ));
+            since_seq = next_seq;
+            if !has_more {
+                break;
+            }
+        }
+        assert_eq!(
+            pages,
+            vec![
+                (vec![3, 4], 4, true),
+                (vec![7, 9], 9, true),
+                (vec![10], 10, false),
+            ]
+        );
+        // a full last page takes one more empty request
+        let (next_seq, has_more) = WeatherDataChange::get_next_seq(&page(7), 7, 2);
+        assert_eq!((next_seq, has_more), (10, true));
+        let (next_seq, has_more) = WeatherDataChange::get_next_seq(&page(10), 10, 2);
+        assert_eq!((next_seq, has_more), (10, false));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_change_feed_out_of_order_commit() -> Result<(), Error> {
+        let config = Config::init_config(None)?;
+        let pool = PgPool::from_config(&config)?;
+        let name = "change feed test";
+        WeatherDataChange::get_since(&pool, i64::MAX, 1).await?;
+        let since_seq: i64 = pool
+            .get()
+            .await?
+            .query_one("SELECT coalesce(max(seq), 0) FROM weather_data", &[])
+            .await?
+            .get(0);
+
+        let now = OffsetDateTime::now_utc();
+        let first = WeatherDataDB {
+            location_name: name.into(),
+            ..WeatherDataDB::test_row(now)
+        };
+        let second = WeatherDataDB {
+            location_name: name.into(),
+            ..WeatherDataDB::test_row(now + time::Duration::seconds(1))
+        };
+        // `first` is written before `second` but commits after it
+        let mut conn = pool.get().await?;
+        let tran = conn.transaction().await?;
+        let tran_conn: &PgTransaction = &tran;
+        first.insert_conn(tran_conn, false).await?;
+        second.insert(&pool).await?;
+
+        let changes = WeatherDataChange::get_since(&pool, since_seq, 100).await?;
+        let dts: Vec<_> = changes
+            .iter()
+            .filter(|c| c.data.location_name == name)
+            .map(|c| c.data.dt)
+            .collect();
+        assert_eq!(dts, [second.dt]);
+        let (next_seq, _) = WeatherDataChange::get_next_seq(&changes, since_seq, 100);
+        tran.commit().await?;
+
+        let changes = WeatherDataChange::get_since(&pool, next_seq, 100).await?;
+        let dts: Vec<_> = changes
+            .iter()
+            .filter(|c| c.data.location_name == name)
+            .map(|c| c.data.dt)
+            .collect();
+        assert_eq!(dts, [first.dt]);
+
+        pool.get()
+            .await?
+            .execute(
+                "DELETE FROM weather_data WHERE location_name = $1",
+                &[&name],
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_within_delta() {
+        let config = ConfigInner {
+            record_delta_max_age: 60,
+            record_delta_temperature: 0.5,
+            record_delta_pressure: 0.1,
+            record_delta_humidity: 2,
+            record_delta_wind_speed: 1.0,
+            record_delta_precipitation: 0.1,
+            ..ConfigInner::default()
+        };
+        let created_at = datetime!(2024-06-01 12:00 UTC);
+        let last = WeatherDataDB {
+            condition: "Clouds overcast clouds ".into(),
+            rain: Some(0.2),
+            ..WeatherDataDB::test_row(created_at)
+        };
+        let row = |minutes: i64| WeatherDataDB {
+            condition: last.condition.clone(),
+            temperature: last.temperature + 0.4,
+            pressure: last.pressure - 0.05,
+            humidity: last.humidity + 2,
+            wind_speed: last.wind_speed + 1.0,
+            rain: Some(0.25),
+            ..WeatherDataDB::test_row(created_at + time::Duration::minutes(minutes))
+        };
+
+        assert!(row(10).is_within_delta(&last, &config));
+        assert!(!row(60).is_within_delta(&last, &config));
+
+        let out_of_delta = [
+            WeatherDataDB {
+                temperature: last.temperature + 0.6,
+                ..row(10)
+            },
+            WeatherDataDB {
+                pressure: last.pressure + 0.2,
+                ..row(10)
+            },
+            WeatherDataDB {
+                humidity: last.humidity - 3,
+                ..row(10)
+            },
+            WeatherDataDB {
+                wind_speed: last.wind_speed + 1.5,
+                ..row(10)
+            },
+            WeatherDataDB {
+                rain: None,
+                ..row(10)
+            },
+            WeatherDataDB {
+                snow: Some(0.5),
+                ..row(10)
+            },
+        ];
+        for row in &out_of_delta {
+            assert!(!row.is_within_delta(&last, &config));
+        }
+
+        let changed_condition = WeatherDataDB {
+            condition: "Rain light rain ".into(),
+            ..row(10)
+        };
+        assert!(!changed_condition.is_within_delta(&last, &config));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_history_asof() -> Result<(), Error> {
+        let config = Config::init_config(None)?;
+        let pool = PgPool::from_config(&config)?;
+        let name = "asof test";
+
+        let row = WeatherDataDB {
+            location_name: name.into(),
+            ..WeatherDataDB::test_row(OffsetDateTime::now_utc())
+        };
+        row.insert(&pool).await?;
+        let history = |asof| {
+            let pool = pool.clone();
+            async move {
+                let rows: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates(
+                    &pool,
+                    Some(name),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    asof,
+                    None,
+                    None,
+                )
+                .await?
+                .try_collect()
+                .await?;
+                Ok::<_, Error>(rows)
+            }
+        };
+        let rows = history(None).await?;
+        assert_eq!(rows.len(), 1);
+        let inserted_at = *rows[0].inserted_at.expect("inserted_at is not null");
+        assert!(!rows[0].backfilled);
+
+        let before = inserted_at - time::Duration::seconds(1);
+        assert!(history(Some(before)).await?.is_empty());
+        assert_eq!(history(Some(inserted_at)).await?.len(), 1);
+        let count = WeatherDataDB::get_total_by_name_dates(
+            &pool,
+            Some(name),
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(before),
+        )
+        .await?;
+        assert_eq!(count, 0);
+        let days =
+            WeatherDataDB::get_daily_summaries(&pool, name, None, None, None, Some(before)).await?;
+        assert!(days.is_empty());
+        let days =
+            WeatherDataDB::get_daily_summaries(&pool, name, None, None, None, Some(inserted_at))
+                .await?;
+        assert_eq!(days.len(), 1);
+
+        pool.get()
+            .await?
+            .execute(
+                "DELETE FROM weather_data WHERE location_name = $1",
+                &[&name],
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_cursor() -> Result<(), Error> {
+        let cursor: HistoryCursor =
+            "2024-06-01T12:00:00.123456Z_5c1e3f2a-8d4b-4f6e-9a7c-0b1d2e3f4a5b".parse()?;
+        assert_eq!(
+            cursor.to_string(),
+            "2024-06-01T12:00:00.123456Z_5c1e3f2a-8d4b-4f6e-9a7c-0b1d2e3f4a5b"
+        );
+        assert!("2024-06-01T12:00:00Z".parse::<HistoryCursor>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_sort_fields() -> Result<(), Error> {
+        assert_eq!(
+            history_order_by(HistorySort::default(), SortOrder::default()).as_str(),
+            "ORDER BY created_at ASC, id ASC"
+        );
+        assert_eq!(
+            history_order_by("temperature".parse()?, "desc".parse()?).as_str(),
+            "ORDER BY temperature DESC, created_at DESC, id DESC"
+        );
+        assert!("humidity; DROP TABLE".parse::<HistorySort>().is_err());
+
+        let fields: HistoryFields = "created_at, temperature,created_at".parse()?;
+        let mut row =
+            json!({"id": "x", "created_at": "2024-06-01T12:00:00Z", "temperature": 290.0});
+        fields.project(&mut row);
+        assert_eq!(
+            row,
+            json!({"created_at": "2024-06-01T12:00:00Z", "temperature": 290.0})
+        );
+        assert!("temperature,password".parse::<HistoryFields>().is_err());
+        assert!(",".parse::<HistoryFields>().is_err());
         Ok(())
     }
 }
@@ -0,0 +1,212 @@
+use anyhow::Error;
+use log::error;
+use rweb::{
+    http::{
+        header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+        StatusCode,
+    },
+    hyper::{body::to_bytes, Body},
+    openapi::{Operation, Spec},
+    reply::Response,
+    Reply,
+};
+use serde_json::Value;
+use stack_string::StackString;
+use std::borrow::Cow;
+
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+pub const CBOR_CONTENT_TYPE: &str = "application/cbor";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Binary encodings of json responses selected by the `Accept` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFormat {
+    MessagePack,
+    Cbor,
+}
+
+impl BinaryFormat {
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::MessagePack => MSGPACK_CONTENT_TYPE,
+            Self::Cbor => CBOR_CONTENT_TYPE,
+        }
+    }
+
+    /// Preferred binary format of an `Accept` header, json wins ties and
+    /// wildcards
+    #[must_use]
+    pub fn from_accept(accept: &str) -> Option<Self> {
+        let mut json_quality = 0.0;
+        let mut best: Option<(Self, f64)> = None;
+        for media_range in accept.split(',') {
+            let mut params = media_range.split(';');
+            let media_type = params.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f64>().ok())
+                .unwrap_or(1.0);
+            let format = match media_type.as_str() {
+                JSON_CONTENT_TYPE => {
+                    json_quality = f64::max(json_quality, quality);
+                    continue;
+                }
+                "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                    Self::MessagePack
+                }
+                CBOR_CONTENT_TYPE => Self::Cbor,
+                _ => continue,
+            };
+            if quality > 0.0 && !best.is_some_and(|(_, q)| q >= quality) {
+                best = Some((format, quality));
+            }
+        }
+        best.filter(|(_, q)| *q > json_quality).map(|(f, _)| f)
+    }
+
+    /// Re-encode a json body
+    ///
+    /// # Errors
+    /// Return error if `json` isn't valid json or encoding fails
+    pub fn encode(self, json: &[u8]) -> Result<Vec<u8>, Error> {
+        let value: Value = serde_json::from_slice(json)?;
+        match self {
+            Self::MessagePack => rmp_serde::to_vec_named(&value).map_err(Into::into),
+            Self::Cbor => {
+                let mut buf = Vec::with_capacity(json.len());
+                ciborium::into_writer(&value, &mut buf)?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with(JSON_CONTENT_TYPE))
+}
+
+/// Encode json responses as MessagePack or CBOR when the client prefers it,
+/// any other response is passed through
+pub async fn negotiate_format(reply: impl Reply, accept: Option<StackString>) -> Response {
+    let mut response = reply.into_response();
+    if !is_json(&response) {
+        return response;
+    }
+    response
+        .headers_mut()
+        .insert(VARY, HeaderValue::from_static("accept"));
+    let Some(format) = accept.as_deref().and_then(BinaryFormat::from_accept) else {
+        return response;
+    };
+    let (mut parts, body) = response.into_parts();
+    let json = match to_bytes(body).await {
+        Ok(json) => json,
+        Err(e) => {
+            error!("failed to read response body {e}");
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            return response;
+        }
+    };
+    let body = match format.encode(&json) {
+        Ok(encoded) => {
+            parts.headers.remove(CONTENT_LENGTH);
+            parts.headers.insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            );
+            Body::from(encoded)
+        }
+        Err(e) => {
+            error!("failed to encode {format:?} {e}");
+            Body::from(json)
+        }
+    };
+    Response::from_parts(parts, body)
+}
+
+/// List the binary formats alongside every json response of the spec
+pub fn document_binary_formats(spec: &mut Spec) {
+    for item in spec.paths.values_mut() {
+        let operations: [&mut Option<Operation>; 5] = [
+            &mut item.get,
+            &mut item.post,
+            &mut item.put,
+            &mut item.patch,
+            &mut item.delete,
+        ];
+        for operation in operations.into_iter().flatten() {
+            for response in operation.responses.values_mut() {
+                if let Some(media_type) = response.content.get(JSON_CONTENT_TYPE).cloned() {
+                    for content_type in [MSGPACK_CONTENT_TYPE, CBOR_CONTENT_TYPE] {
+                        response
+                            .content
+                            .insert(Cow::Borrowed(content_type), media_type.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use rweb::{http::header::CONTENT_TYPE, hyper::body::to_bytes, reply};
+    use serde_json::{json, Value};
+
+    use crate::negotiate::{negotiate_format, BinaryFormat, CBOR_CONTENT_TYPE};
+
+    #[test]
+    fn test_from_accept() {
+        assert_eq!(
+            BinaryFormat::from_accept("application/msgpack"),
+            Some(BinaryFormat::MessagePack)
+        );
+        assert_eq!(
+            BinaryFormat::from_accept("application/cbor, */*;q=0.1"),
+            Some(BinaryFormat::Cbor)
+        );
+        assert_eq!(
+            BinaryFormat::from_accept("application/cbor;q=0.5, application/x-msgpack;q=0.8"),
+            Some(BinaryFormat::MessagePack)
+        );
+        assert_eq!(
+            BinaryFormat::from_accept("application/json, application/cbor"),
+            None
+        );
+        assert_eq!(BinaryFormat::from_accept("application/cbor;q=0"), None);
+        assert_eq!(BinaryFormat::from_accept("*/*"), None);
+    }
+
+    #[tokio::test]
+    async fn test_negotiate_format() -> Result<(), Error> {
+        let value = json!({"temperature": 293.15, "name": "Saint Louis Park", "list": [1, 2]});
+
+        let msgpack = BinaryFormat::MessagePack.encode(&serde_json::to_vec(&value)?)?;
+        let decoded: Value = rmp_serde::from_slice(&msgpack)?;
+        assert_eq!(decoded, value);
+
+        let response = negotiate_format(reply::json(&value), Some("application/cbor".into())).await;
+        assert_eq!(response.headers()[CONTENT_TYPE], CBOR_CONTENT_TYPE);
+        let body = to_bytes(response.into_body()).await?;
+        let decoded: Value = ciborium::from_reader(body.as_ref())?;
+        assert_eq!(decoded, value);
+
+        let response = negotiate_format(reply::json(&value), None).await;
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let body = to_bytes(response.into_body()).await?;
+        assert_eq!(serde_json::from_slice::<Value>(&body)?, value);
+
+        let response = negotiate_format(reply::html("<p/>"), Some("application/cbor".into())).await;
+        assert!(response.headers()[CONTENT_TYPE]
+            .to_str()?
+            .starts_with("text/html"));
+        Ok(())
+    }
+}
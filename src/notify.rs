@@ -0,0 +1,321 @@
+use anyhow::{format_err, Error};
+use log::error;
+use reqwest::Client;
+use serde::Serialize;
+use stack_string::{format_sstr, StackString};
+use std::future::Future;
+
+#[cfg(feature = "email")]
+use lettre::{
+    message::{header::ContentType, Attachment as MailAttachment, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+#[cfg(not(feature = "email"))]
+use log::warn;
+
+use crate::config::Config;
+
+/// File attached to email notifications, e.g. a climate report
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub filename: StackString,
+    pub content_type: StackString,
+    pub body: Vec<u8>,
+}
+
+/// Message delivered to every configured sink
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub title: StackString,
+    pub message: StackString,
+    /// Only sent by the email sink
+    #[serde(skip)]
+    pub attachment: Option<Attachment>,
+}
+
+impl Notification {
+    #[must_use]
+    pub fn new(title: impl Into<StackString>, message: impl Into<StackString>) -> Self {
+        Self {
+            title: title.into(),
+            message: message.into(),
+            attachment: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_attachment(mut self, attachment: Attachment) -> Self {
+        self.attachment = Some(attachment);
+        self
+    }
+}
+
+/// Destination of notifications (alerts, daemon failures)
+pub trait Notifier {
+    fn get_name(&self) -> &'static str;
+
+    fn send(
+        &self,
+        client: &Client,
+        notification: &Notification,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+/// Posts the json `Notification` to `NOTIFY_WEBHOOK_URL`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookSink {
+    pub url: StackString,
+}
+
+impl Notifier for WebhookSink {
+    fn get_name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn send(&self, client: &Client, notification: &Notification) -> Result<(), Error> {
+        client
+            .post(self.url.as_str())
+            .json(notification)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Publishes to an ntfy.sh (or self hosted ntfy) topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct NtfySink {
+    pub url: StackString,
+    pub topic: StackString,
+    pub token: Option<StackString>,
+}
+
+impl NtfySink {
+    fn topic_url(&self) -> StackString {
+        format_sstr!("{}/{}", self.url.trim_end_matches('/'), self.topic)
+    }
+}
+
+impl Notifier for NtfySink {
+    fn get_name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    async fn send(&self, client: &Client, notification: &Notification) -> Result<(), Error> {
+        let mut request = client
+            .post(self.topic_url().as_str())
+            .header("Title", notification.title.as_str())
+            .body(notification.message.to_string());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Sends an email through an SMTP relay (STARTTLS)
+#[cfg(feature = "email")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailSink {
+    pub host: StackString,
+    pub port: u16,
+    pub username: Option<StackString>,
+    pub password: Option<StackString>,
+    pub from: StackString,
+    pub to: StackString,
+}
+
+#[cfg(feature = "email")]
+impl EmailSink {
+    fn message(&self, notification: &Notification) -> Result<Message, Error> {
+        let from: Mailbox = self.from.parse()?;
+        let to: Mailbox = self.to.parse()?;
+        let builder = Message::builder()
+            .from(from)
+            .to(to)
+            .subject(notification.title.as_str());
+        let message = match &notification.attachment {
+            Some(attachment) => {
+                let content_type = ContentType::parse(&attachment.content_type)?;
+                builder.multipart(
+                    MultiPart::mixed()
+                        .singlepart(SinglePart::plain(notification.message.to_string()))
+                        .singlepart(
+                            MailAttachment::new(attachment.filename.to_string())
+                                .body(attachment.body.clone(), content_type),
+                        ),
+                )
+            }
+            None => builder.body(notification.message.to_string()),
+        };
+        message.map_err(Into::into)
+    }
+}
+
+#[cfg(feature = "email")]
+impl Notifier for EmailSink {
+    fn get_name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn send(&self, _: &Client, notification: &Notification) -> Result<(), Error> {
+        let message = self.message(notification)?;
+        let mut transport =
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?.port(self.port);
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            transport =
+                transport.credentials(Credentials::new(username.to_string(), password.to_string()));
+        }
+        transport.build().send(message).await?;
+        Ok(())
+    }
+}
+
+/// Dispatch over the configured sinks
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotifySink {
+    Webhook(WebhookSink),
+    Ntfy(NtfySink),
+    #[cfg(feature = "email")]
+    Email(EmailSink),
+}
+
+impl NotifySink {
+    /// Every sink with complete settings in `config`
+    #[must_use]
+    pub fn from_config(config: &Config) -> Vec<Self> {
+        let mut sinks = Vec::new();
+        if let Some(url) = &config.notify_webhook_url {
+            sinks.push(Self::Webhook(WebhookSink { url: url.clone() }));
+        }
+        if let Some(topic) = &config.ntfy_topic {
+            sinks.push(Self::Ntfy(NtfySink {
+                url: config.ntfy_url.clone(),
+                topic: topic.clone(),
+                token: config.ntfy_token.clone(),
+            }));
+        }
+        #[cfg(feature = "email")]
+        if let (Some(host), Some(from), Some(to)) = (
+            &config.smtp_host,
+            &config.notify_email_from,
+            &config.notify_email_to,
+        ) {
+            sinks.push(Self::Email(EmailSink {
+                host: host.clone(),
+                port: config.smtp_port,
+                username: config.smtp_username.clone(),
+                password: config.smtp_password.clone(),
+                from: from.clone(),
+                to: to.clone(),
+            }));
+        }
+        #[cfg(not(feature = "email"))]
+        if config.smtp_host.is_some() {
+            warn!("SMTP_HOST is set but the email feature is not enabled");
+        }
+        sinks
+    }
+}
+
+impl Notifier for NotifySink {
+    fn get_name(&self) -> &'static str {
+        match self {
+            Self::Webhook(sink) => sink.get_name(),
+            Self::Ntfy(sink) => sink.get_name(),
+            #[cfg(feature = "email")]
+            Self::Email(sink) => sink.get_name(),
+        }
+    }
+
+    async fn send(&self, client: &Client, notification: &Notification) -> Result<(), Error> {
+        match self {
+            Self::Webhook(sink) => sink.send(client, notification).await,
+            Self::Ntfy(sink) => sink.send(client, notification).await,
+            #[cfg(feature = "email")]
+            Self::Email(sink) => sink.send(client, notification).await,
+        }
+    }
+}
+
+/// Send `notification` to every configured sink, returns the outcome per sink
+pub async fn notify_all(
+    client: &Client,
+    config: &Config,
+    notification: &Notification,
+) -> Vec<(&'static str, Result<(), Error>)> {
+    let mut results = Vec::new();
+    for sink in NotifySink::from_config(config) {
+        let result = sink
+            .send(client, notification)
+            .await
+            .map_err(|e| format_err!("{} notification failed {e}", sink.get_name()));
+        if let Err(e) = &result {
+            error!("{e}");
+        }
+        results.push((sink.get_name(), result));
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        notify::{NotifySink, NtfySink},
+    };
+
+    #[cfg(feature = "email")]
+    use anyhow::Error;
+
+    #[cfg(feature = "email")]
+    use crate::notify::{Attachment, EmailSink, Notification};
+
+    #[test]
+    fn test_notify_sinks() {
+        assert!(NotifySink::from_config(&Config::default()).is_empty());
+
+        let ntfy = NtfySink {
+            url: "https://ntfy.sh/".into(),
+            topic: "weather".into(),
+            token: None,
+        };
+        assert_eq!(ntfy.topic_url(), "https://ntfy.sh/weather");
+    }
+
+    #[cfg(feature = "email")]
+    #[test]
+    fn test_email_sink() -> Result<(), Error> {
+        let email = EmailSink {
+            host: "smtp.example.com".into(),
+            port: 587,
+            username: None,
+            password: None,
+            from: "Weather <weather@example.com>".into(),
+            to: "user@example.com".into(),
+        };
+        let notification = Notification::new("Test", "test notification");
+        let message = String::from_utf8(email.message(&notification)?.formatted())?;
+        assert!(message.contains("Subject: Test"));
+        assert!(message.contains("To: user@example.com"));
+
+        let with_report = notification.clone().with_attachment(Attachment {
+            filename: "report.html".into(),
+            content_type: "text/html".into(),
+            body: b"<html></html>".to_vec(),
+        });
+        let message = String::from_utf8(email.message(&with_report)?.formatted())?;
+        assert!(message.contains("multipart/mixed"));
+        assert!(message.contains("report.html"));
+
+        let invalid = EmailSink {
+            to: "not an address".into(),
+            ..email
+        };
+        assert!(invalid.message(&notification).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::{format_err, Error};
+use serde_json::json;
+use time::OffsetDateTime;
+
+use weather_util_rust::weather_forecast::WeatherForecast;
+
+use crate::{
+    model::{HourlyNormal, WeatherDataDB},
+    pgpool::PgPool,
+    providers::{precipitation_json, weather_condition_json, FORECAST_ENTRIES, KELVIN_OFFSET},
+};
+
+/// Label reported as the provider of offline forecasts
+pub const OFFLINE_PROVIDER: &str = "offline (persistence + climatology)";
+/// Days either side of the forecast date used for the normals
+const CLIMATOLOGY_WINDOW_DAYS: i32 = 15;
+/// e-folding time of the current anomaly (hours)
+const PERSISTENCE_DECAY_HOURS: f64 = 24.0;
+/// Most recent observation used for persistence (seconds)
+const MAX_OBSERVATION_AGE: i32 = 6 * 3600;
+/// Mean precipitation over 3 hours reported as rain or snow (mm)
+const PRECIPITATION_THRESHOLD: f64 = 0.3;
+const STEP: i64 = 3 * 3600;
+
+fn local_hour(dt: i64, timezone: i32) -> i32 {
+    ((dt + i64::from(timezone)) / 3600).rem_euclid(24) as i32
+}
+
+fn get_normal(normals: &[HourlyNormal], hour: i32) -> Option<&HourlyNormal> {
+    normals.iter().find(|n| n.hour == hour)
+}
+
+/// Climatological normal of the local hour plus the current departure from
+/// normal, decaying with lead time.  Only the normals are used without a
+/// recent observation, only persistence without normals.
+///
+/// # Errors
+/// Returns error if there is neither an observation nor normals
+pub fn get_offline_forecast(
+    current: Option<&WeatherDataDB>,
+    normals: &[HourlyNormal],
+    now: OffsetDateTime,
+) -> Result<WeatherForecast, Error> {
+    if current.is_none() && normals.is_empty() {
+        return Err(format_err!("No recorded data for an offline forecast"));
+    }
+    let timezone = current.map_or(0, |c| c.timezone);
+    let current_normal =
+        current.and_then(|c| get_normal(normals, local_hour(c.dt.into(), timezone)));
+    let start = now.unix_timestamp() - now.unix_timestamp().rem_euclid(STEP);
+
+    let mut list = Vec::with_capacity(FORECAST_ENTRIES);
+    for step in 1..=FORECAST_ENTRIES as i64 {
+        let dt = start + step * STEP;
+        let hour = local_hour(dt, timezone);
+        let normal = get_normal(normals, hour);
+        let weight = current.map_or(0.0, |c| {
+            let lead = (dt - i64::from(c.dt)).max(0) as f64 / 3600.0;
+            (-lead / PERSISTENCE_DECAY_HOURS).exp()
+        });
+        let value =
+            |observed: Option<f64>, climate: fn(&HourlyNormal) -> f64| match (observed, normal) {
+                (Some(observed), Some(normal)) => {
+                    let anomaly = observed - climate(current_normal.unwrap_or(normal));
+                    climate(normal) + anomaly * weight
+                }
+                (Some(observed), None) => observed,
+                (None, Some(normal)) => climate(normal),
+                (None, None) => 0.0,
+            };
+        let temperature = value(current.map(|c| c.temperature), |n| n.temperature);
+        let humidity = value(current.map(|c| c.humidity.into()), |n| n.humidity).clamp(0.0, 100.0);
+        let pressure = value(current.map(|c| c.pressure), |n| n.pressure);
+        let wind_speed = value(current.map(|c| c.wind_speed), |n| n.wind_speed).max(0.0);
+        let precipitation: f64 = (0..3)
+            .filter_map(|h| get_normal(normals, (hour + h) % 24))
+            .map(|n| n.precipitation)
+            .sum();
+
+        let is_snow = temperature < KELVIN_OFFSET;
+        let (condition, amount) = if precipitation < PRECIPITATION_THRESHOLD {
+            (
+                weather_condition_json(802, "Clouds", "scattered clouds", "03d"),
+                0.0,
+            )
+        } else if is_snow {
+            (
+                weather_condition_json(600, "Snow", "light snow", "13d"),
+                precipitation,
+            )
+        } else {
+            (
+                weather_condition_json(500, "Rain", "light rain", "10d"),
+                precipitation,
+            )
+        };
+        let (rain, snow) = precipitation_json(is_snow, "3h", amount);
+        list.push(json!({
+            "dt": dt,
+            "main": {
+                "temp": temperature,
+                "feels_like": temperature,
+                "temp_min": temperature,
+                "temp_max": temperature,
+                "pressure": pressure * 10.0,
+                "sea_level": pressure * 10.0,
+                "grnd_level": pressure * 10.0,
+                "humidity": humidity.round() as i64,
+            },
+            "weather": condition,
+            "wind": {
+                "speed": wind_speed,
+                "deg": current.and_then(|c| c.wind_direction),
+            },
+            "rain": rain,
+            "snow": snow,
+        }));
+    }
+    let value = json!({
+        "list": list,
+        "city": {
+            "timezone": timezone,
+            "sunrise": current.map_or(0, |c| c.sunrise.unix_timestamp()),
+            "sunset": current.map_or(0, |c| c.sunset.unix_timestamp()),
+        },
+    });
+    serde_json::from_value(value).map_err(Into::into)
+}
+
+/// Most recent observation of `location_name`, if recent enough for
+/// persistence
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn get_recorded_weather(
+    pool: &PgPool,
+    location_name: &str,
+    now: OffsetDateTime,
+) -> Result<Option<WeatherDataDB>, Error> {
+    WeatherDataDB::get_closest_by_name(
+        pool,
+        location_name,
+        now.unix_timestamp() as i32,
+        MAX_OBSERVATION_AGE,
+    )
+    .await
+}
+
+/// Offline forecast of the recorded location `location_name`
+///
+/// # Errors
+/// Return error if db query fails or nothing was recorded for the location
+pub async fn get_recorded_offline_forecast(
+    pool: &PgPool,
+    location_name: &str,
+    now: OffsetDateTime,
+) -> Result<WeatherForecast, Error> {
+    let current = get_recorded_weather(pool, location_name, now).await?;
+    let normals = WeatherDataDB::get_hourly_normals(
+        pool,
+        location_name,
+        now.ordinal().into(),
+        CLIMATOLOGY_WINDOW_DAYS,
+    )
+    .await?;
+    get_offline_forecast(current.as_ref(), &normals, now)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::datetime;
+
+    use crate::{
+        model::{HourlyNormal, WeatherDataDB},
+        offline_forecast::get_offline_forecast,
+        providers::FORECAST_ENTRIES,
+    };
+
+    fn normal(hour: i32) -> HourlyNormal {
+        // warmest in the afternoon, rain in the early morning
+        let temperature = 285.0 + 5.0 * (f64::from(hour - 15) * std::f64::consts::PI / 12.0).cos();
+        HourlyNormal {
+            hour,
+            temperature,
+            humidity: 60.0,
+            pressure: 101.5,
+            wind_speed: 3.0,
+            precipitation: if (3..6).contains(&hour) { 0.5 } else { 0.0 },
+            count: 100,
+        }
+    }
+
+    #[test]
+    fn test_offline_forecast() -> Result<(), Error> {
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let normals: Vec<_> = (0..24).map(normal).collect();
+        let current = WeatherDataDB {
+            location_name: "test".into(),
+            latitude: 40.0,
+            longitude: -74.0,
+            temperature: normal(12).temperature + 6.0,
+            temperature_minimum: 0.0,
+            temperature_maximum: 0.0,
+            pressure: 100.5,
+            humidity: 90,
+            wind_speed: 10.0,
+            wind_direction: Some(270.0),
+            ..WeatherDataDB::test_row(now)
+        };
+
+        let forecast = get_offline_forecast(Some(&current), &normals, now)?;
+        assert_eq!(forecast.list.len(), FORECAST_ENTRIES);
+        let first = &forecast.list[0];
+        assert_eq!(first.dt.unix_timestamp(), now.unix_timestamp() + 3 * 3600);
+        // the anomaly persists early on and decays towards the normals
+        let anomaly = |idx: usize| {
+            let entry = &forecast.list[idx];
+            let hour = ((entry.dt.unix_timestamp() / 3600) % 24) as i32;
+            entry.main.temp.kelvin() - normal(hour).temperature
+        };
+        assert!(anomaly(0) > 5.0);
+        assert!(anomaly(39) < 0.1);
+        assert!(anomaly(39) > 0.0);
+        // early morning rain from the normals
+        assert!(forecast.list.iter().any(|entry| entry.rain.is_some()));
+
+        let climatology = get_offline_forecast(None, &normals, now)?;
+        assert!((climatology.list[0].main.temp.kelvin() - normal(15).temperature).abs() < 1e-6);
+
+        assert!(get_offline_forecast(None, &[], now).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use anyhow::Error;
+use reqwest::Client;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use time::OffsetDateTime;
+use tracing::instrument;
+
+use weather_api_types::activity::ActivityConditions;
+use weather_util_rust::weather_data::WeatherCond;
+
+use crate::{config::Config, metrics::record_upstream_call};
+
+/// Sections of the One Call response that can be left out of the request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OneCallPart {
+    Current,
+    Minutely,
+    Hourly,
+    Daily,
+    Alerts,
+}
+
+impl OneCallPart {
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Current => "current",
+            Self::Minutely => "minutely",
+            Self::Hourly => "hourly",
+            Self::Daily => "daily",
+            Self::Alerts => "alerts",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Schema)]
+#[schema(component = "OneHourPrecipitation")]
+pub struct OneHourPrecipitation {
+    #[serde(rename = "1h")]
+    #[schema(description = "Precipitation (mm over previous hour)")]
+    pub one_hour: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OneCallCurrent {
+    #[serde(with = "time::serde::timestamp")]
+    pub dt: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp")]
+    pub sunrise: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp")]
+    pub sunset: OffsetDateTime,
+    pub temp: f64,
+    pub feels_like: f64,
+    pub pressure: f64,
+    pub humidity: i64,
+    pub dew_point: f64,
+    pub uvi: f64,
+    pub clouds: i64,
+    pub visibility: Option<f64>,
+    pub wind_speed: f64,
+    pub wind_deg: f64,
+    pub wind_gust: Option<f64>,
+    pub weather: Vec<WeatherCond>,
+    pub rain: Option<OneHourPrecipitation>,
+    pub snow: Option<OneHourPrecipitation>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct OneCallMinutely {
+    #[serde(with = "time::serde::timestamp")]
+    pub dt: OffsetDateTime,
+    pub precipitation: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OneCallHourly {
+    #[serde(with = "time::serde::timestamp")]
+    pub dt: OffsetDateTime,
+    pub temp: f64,
+    pub feels_like: f64,
+    pub pressure: f64,
+    pub humidity: i64,
+    pub dew_point: f64,
+    pub uvi: f64,
+    pub clouds: i64,
+    pub visibility: Option<f64>,
+    pub wind_speed: f64,
+    pub wind_deg: f64,
+    pub wind_gust: Option<f64>,
+    pub weather: Vec<WeatherCond>,
+    pub pop: f64,
+    pub rain: Option<OneHourPrecipitation>,
+    pub snow: Option<OneHourPrecipitation>,
+}
+
+impl OneCallHourly {
+    /// Conditions of the hour for the activity score, unlike the three hour
+    /// forecast these include the precipitation probability and UV index
+    #[must_use]
+    pub fn activity_conditions(&self) -> ActivityConditions {
+        let rain = self.rain.map_or(0.0, |r| r.one_hour);
+        let snow = self.snow.map_or(0.0, |s| s.one_hour);
+        ActivityConditions {
+            temperature: self.temp - 273.15,
+            humidity: self.humidity as f64,
+            wind_speed: self.wind_speed,
+            precipitation: rain + snow,
+            pop: Some(self.pop),
+            uv_index: Some(self.uvi),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Schema)]
+#[schema(component = "DailyTemperature")]
+pub struct DailyTemperature {
+    #[schema(description = "Day Temperature (K)")]
+    pub day: f64,
+    #[schema(description = "Minimum Temperature (K)")]
+    pub min: f64,
+    #[schema(description = "Maximum Temperature (K)")]
+    pub max: f64,
+    #[schema(description = "Night Temperature (K)")]
+    pub night: f64,
+    #[schema(description = "Evening Temperature (K)")]
+    pub eve: f64,
+    #[schema(description = "Morning Temperature (K)")]
+    pub morn: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Schema)]
+#[schema(component = "DailyFeelsLike")]
+pub struct DailyFeelsLike {
+    #[schema(description = "Day Feels Like Temperature (K)")]
+    pub day: f64,
+    #[schema(description = "Night Feels Like Temperature (K)")]
+    pub night: f64,
+    #[schema(description = "Evening Feels Like Temperature (K)")]
+    pub eve: f64,
+    #[schema(description = "Morning Feels Like Temperature (K)")]
+    pub morn: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OneCallDaily {
+    #[serde(with = "time::serde::timestamp")]
+    pub dt: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp")]
+    pub sunrise: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp")]
+    pub sunset: OffsetDateTime,
+    pub moon_phase: f64,
+    pub summary: Option<StackString>,
+    pub temp: DailyTemperature,
+    pub feels_like: DailyFeelsLike,
+    pub pressure: f64,
+    pub humidity: i64,
+    pub dew_point: f64,
+    pub wind_speed: f64,
+    pub wind_deg: f64,
+    pub wind_gust: Option<f64>,
+    pub weather: Vec<WeatherCond>,
+    pub clouds: i64,
+    pub pop: f64,
+    pub rain: Option<f64>,
+    pub snow: Option<f64>,
+    pub uvi: f64,
+}
+
+/// Response of the openweathermap One Call 3.0 api
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OneCall {
+    pub lat: f64,
+    pub lon: f64,
+    pub timezone: StackString,
+    pub timezone_offset: i32,
+    pub current: Option<OneCallCurrent>,
+    pub minutely: Option<Vec<OneCallMinutely>>,
+    pub hourly: Option<Vec<OneCallHourly>>,
+    pub daily: Option<Vec<OneCallDaily>>,
+}
+
+/// Current conditions plus minutely, hourly and daily forecasts from the
+/// openweathermap One Call 3.0 api, leaving out the `exclude` sections
+///
+/// # Errors
+/// Return error if the api request fails
+#[instrument(skip(client, config, api_key))]
+pub async fn fetch_onecall(
+    client: &Client,
+    config: &Config,
+    api_key: &str,
+    latitude: f64,
+    longitude: f64,
+    exclude: &[OneCallPart],
+) -> Result<OneCall, Error> {
+    let url = format_sstr!(
+        "https://{}/{}onecall",
+        config.api_endpoint,
+        config.onecall_path
+    );
+    let latitude: StackString = format_sstr!("{latitude}");
+    let longitude: StackString = format_sstr!("{longitude}");
+    let exclude: Vec<_> = exclude.iter().map(|p| p.to_str()).collect();
+    let exclude = exclude.join(",");
+    let mut options = vec![
+        ("lat", latitude.as_str()),
+        ("lon", longitude.as_str()),
+        ("appid", api_key),
+    ];
+    if !exclude.is_empty() {
+        options.push(("exclude", exclude.as_str()));
+    }
+    let result: Result<OneCall, Error> = async {
+        let onecall = client
+            .get(url.as_str())
+            .query(&options)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(onecall)
+    }
+    .await;
+    record_upstream_call("openweathermap", "onecall", result.is_ok());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::datetime;
+
+    use crate::onecall::OneCall;
+
+    #[test]
+    fn test_onecall() -> Result<(), Error> {
+        let data = r#"{
+            "lat": 33.44, "lon": -94.04, "timezone": "America/Chicago",
+            "timezone_offset": -18000,
+            "current": {"dt": 1684929490, "sunrise": 1684926645, "sunset": 1684977332,
+                "temp": 292.55, "feels_like": 292.87, "pressure": 1014, "humidity": 89,
+                "dew_point": 290.69, "uvi": 0.16, "clouds": 53, "visibility": 10000,
+                "wind_speed": 3.13, "wind_deg": 93, "wind_gust": 6.71,
+                "weather": [{"id": 803, "main": "Clouds", "description": "broken clouds",
+                    "icon": "04d"}]},
+            "minutely": [{"dt": 1684929540, "precipitation": 0}],
+            "hourly": [{"dt": 1684926000, "temp": 292.01, "feels_like": 292.33,
+                "pressure": 1014, "humidity": 91, "dew_point": 290.51, "uvi": 0,
+                "clouds": 54, "visibility": 10000, "wind_speed": 2.58, "wind_deg": 86,
+                "wind_gust": 5.88,
+                "weather": [{"id": 803, "main": "Clouds", "description": "broken clouds",
+                    "icon": "04n"}],
+                "pop": 0.15, "rain": {"1h": 0.25}}],
+            "daily": [{"dt": 1684951200, "sunrise": 1684926645, "sunset": 1684977332,
+                "moonrise": 1684941060, "moonset": 1684905480, "moon_phase": 0.16,
+                "summary": "Expect a day of partly cloudy with rain",
+                "temp": {"day": 299.03, "min": 290.69, "max": 300.35, "night": 291.45,
+                    "eve": 297.51, "morn": 292.55},
+                "feels_like": {"day": 299.21, "night": 291.37, "eve": 297.86,
+                    "morn": 292.87},
+                "pressure": 1016, "humidity": 59, "dew_point": 290.48, "wind_speed": 3.98,
+                "wind_deg": 76, "wind_gust": 8.92,
+                "weather": [{"id": 500, "main": "Rain", "description": "light rain",
+                    "icon": "10d"}],
+                "clouds": 92, "pop": 0.47, "rain": 0.15, "uvi": 9.23}]
+        }"#;
+        let onecall: OneCall = serde_json::from_str(data)?;
+        let current = onecall.current.as_ref().unwrap();
+        assert_eq!(current.dt, datetime!(2023-05-24 11:58:10 UTC));
+        assert_eq!(current.weather[0].main, "Clouds");
+        let hourly = onecall.hourly.as_ref().unwrap();
+        assert_eq!(hourly[0].rain.map(|r| r.one_hour), Some(0.25));
+        let conditions = hourly[0].activity_conditions();
+        assert!((conditions.temperature - 18.86).abs() < 1e-9);
+        assert_eq!(conditions.precipitation, 0.25);
+        assert_eq!(conditions.pop, Some(0.15));
+        assert_eq!(conditions.uv_index, Some(0.0));
+        let daily = onecall.daily.as_ref().unwrap();
+        assert!((daily[0].temp.max - 300.35).abs() < 1e-9);
+        assert_eq!(daily[0].rain, Some(0.15));
+
+        let serialized = serde_json::to_string(&onecall)?;
+        let onecall: OneCall = serde_json::from_str(&serialized)?;
+        assert_eq!(onecall.minutely.map(|m| m.len()), Some(1));
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::{format_err, Error};
+use serde::Serialize;
+use serde_json::Value;
+use stack_string::{format_sstr, StackString};
+use std::fmt;
+
+/// Stored specs of past releases, `/weather/openapi/compat?against=<name>`
+/// compares the live spec with one of them
+pub const OPENAPI_BASELINES: [(&str, &str); 1] = [("v1", include_str!("../scripts/openapi.yaml"))];
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+/// Nesting followed when comparing schemas, guards against recursive `$ref`s
+const MAX_SCHEMA_DEPTH: usize = 16;
+
+/// Name of the most recent stored baseline
+#[must_use]
+pub fn latest_baseline() -> &'static str {
+    OPENAPI_BASELINES[OPENAPI_BASELINES.len() - 1].0
+}
+
+/// # Errors
+/// Return error if there is no baseline of that name or it fails to parse
+pub fn get_baseline(name: &str) -> Result<Value, Error> {
+    let (_, spec) = OPENAPI_BASELINES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .ok_or_else(|| format_err!("no openapi baseline {name}"))?;
+    serde_yml::from_str(spec).map_err(Into::into)
+}
+
+/// Change to the spec that can break an existing client
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BreakingChange {
+    /// operation, parameter or response field, e.g.
+    /// `GET /weather/weather 200 application/json .coord.lat`
+    pub location: StackString,
+    pub description: StackString,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OpenApiDiff {
+    pub baseline_version: StackString,
+    pub current_version: StackString,
+    pub breaking_changes: Vec<BreakingChange>,
+}
+
+impl OpenApiDiff {
+    /// Compare `current` with `baseline`, removed paths, operations,
+    /// parameters and response fields, newly required parameters and type
+    /// changes are reported, additions are not
+    #[must_use]
+    pub fn new(baseline: &Value, current: &Value) -> Self {
+        let version = |spec: &Value| {
+            spec.pointer("/info/version")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .into()
+        };
+        let mut differ = SpecDiffer {
+            baseline,
+            current,
+            changes: Vec::new(),
+        };
+        differ.diff_paths();
+        Self {
+            baseline_version: version(baseline),
+            current_version: version(current),
+            breaking_changes: differ.changes,
+        }
+    }
+
+    #[must_use]
+    pub fn is_compatible(&self) -> bool {
+        self.breaking_changes.is_empty()
+    }
+}
+
+impl fmt::Display for OpenApiDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} breaking changes from {} to {}",
+            self.breaking_changes.len(),
+            self.baseline_version,
+            self.current_version
+        )?;
+        for change in &self.breaking_changes {
+            write!(f, "\n{}: {}", change.location, change.description)?;
+        }
+        Ok(())
+    }
+}
+
+struct SpecDiffer<'a> {
+    baseline: &'a Value,
+    current: &'a Value,
+    changes: Vec<BreakingChange>,
+}
+
+impl SpecDiffer<'_> {
+    fn push(&mut self, location: &str, description: &str) {
+        self.changes.push(BreakingChange {
+            location: location.into(),
+            description: description.into(),
+        });
+    }
+
+    fn diff_paths(&mut self) {
+        let Some(paths) = self.baseline.get("paths").and_then(Value::as_object) else {
+            return;
+        };
+        for (path, item) in paths {
+            let Some(current_item) = self.current.get("paths").and_then(|p| p.get(path)) else {
+                self.push(path, "path removed");
+                continue;
+            };
+            for method in METHODS {
+                let Some(operation) = item.get(method) else {
+                    continue;
+                };
+                let location = format_sstr!("{} {path}", method.to_uppercase());
+                match current_item.get(method) {
+                    Some(current_operation) => {
+                        self.diff_parameters(&location, operation, current_operation);
+                        self.diff_responses(&location, operation, current_operation);
+                    }
+                    None => self.push(&location, "operation removed"),
+                }
+            }
+        }
+    }
+
+    fn diff_parameters(&mut self, location: &str, operation: &Value, current: &Value) {
+        let key = |p: &Value| {
+            (
+                p.get("name").and_then(Value::as_str).unwrap_or_default(),
+                p.get("in").and_then(Value::as_str).unwrap_or_default(),
+            )
+        };
+        let is_required = |p: &Value| p.get("required").and_then(Value::as_bool) == Some(true);
+        let baseline_params = get_parameters(operation);
+        let current_params = get_parameters(current);
+        for param in &baseline_params {
+            let (name, position) = key(param);
+            let location = format_sstr!("{location} {position} parameter {name}");
+            match current_params.iter().find(|p| key(p) == (name, position)) {
+                Some(current_param) => {
+                    if is_required(current_param) && !is_required(param) {
+                        self.push(&location, "parameter is now required");
+                    }
+                    if let (Some(schema), Some(current_schema)) =
+                        (param.get("schema"), current_param.get("schema"))
+                    {
+                        self.diff_schema(&location, schema, current_schema, 0);
+                    }
+                }
+                None => self.push(&location, "parameter removed"),
+            }
+        }
+        for param in current_params {
+            let (name, position) = key(param);
+            if is_required(param) && !baseline_params.iter().any(|p| key(p) == (name, position)) {
+                let location = format_sstr!("{location} {position} parameter {name}");
+                self.push(&location, "required parameter added");
+            }
+        }
+    }
+
+    fn diff_responses(&mut self, location: &str, operation: &Value, current: &Value) {
+        let Some(responses) = operation.get("responses").and_then(Value::as_object) else {
+            return;
+        };
+        for (status, response) in responses {
+            let Some(content) = response.get("content").and_then(Value::as_object) else {
+                continue;
+            };
+            let current_content = current
+                .get("responses")
+                .and_then(|r| r.get(status))
+                .and_then(|r| r.get("content"));
+            for (content_type, media) in content {
+                let location = format_sstr!("{location} {status} {content_type}");
+                let Some(current_media) = current_content.and_then(|c| c.get(content_type)) else {
+                    self.push(&location, "response removed");
+                    continue;
+                };
+                if let (Some(schema), Some(current_schema)) =
+                    (media.get("schema"), current_media.get("schema"))
+                {
+                    self.diff_schema(&location, schema, current_schema, 0);
+                }
+            }
+        }
+    }
+
+    fn diff_schema(&mut self, location: &str, schema: &Value, current: &Value, depth: usize) {
+        if depth > MAX_SCHEMA_DEPTH {
+            return;
+        }
+        let schema = resolve_ref(self.baseline, schema);
+        let current = resolve_ref(self.current, current);
+        let schema_type = schema.get("type").and_then(Value::as_str);
+        let current_type = current.get("type").and_then(Value::as_str);
+        if let (Some(schema_type), Some(current_type)) = (schema_type, current_type) {
+            if schema_type != current_type {
+                let description = format_sstr!("type changed from {schema_type} to {current_type}");
+                self.push(location, &description);
+                return;
+            }
+        }
+        let is_nullable = |s: &Value| s.get("nullable").and_then(Value::as_bool) == Some(true);
+        if is_nullable(current) && !is_nullable(schema) {
+            self.push(location, "may now be null");
+        }
+        // fields are appended to the operation as `.coord.lat` or `.weather[].id`
+        let separator = if depth == 0 { " " } else { "" };
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                let location = format_sstr!("{location}{separator}.{name}");
+                match current.get("properties").and_then(|p| p.get(name)) {
+                    Some(current_property) => {
+                        self.diff_schema(&location, property, current_property, depth + 1);
+                    }
+                    None => self.push(&location, "field removed"),
+                }
+            }
+        }
+        if let (Some(items), Some(current_items)) = (schema.get("items"), current.get("items")) {
+            let location = format_sstr!("{location}{separator}[]");
+            self.diff_schema(&location, items, current_items, depth + 1);
+        }
+    }
+}
+
+fn get_parameters(operation: &Value) -> Vec<&Value> {
+    operation
+        .get("parameters")
+        .and_then(Value::as_array)
+        .map(|p| p.iter().collect())
+        .unwrap_or_default()
+}
+
+/// Follow a `#/components/schemas/...` reference within `spec`
+fn resolve_ref<'a>(spec: &'a Value, schema: &'a Value) -> &'a Value {
+    schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|r| r.strip_prefix('#'))
+        .and_then(|pointer| spec.pointer(pointer))
+        .unwrap_or(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use serde_json::json;
+
+    use crate::openapi_diff::{get_baseline, OpenApiDiff};
+
+    #[test]
+    fn test_openapi_diff() -> Result<(), Error> {
+        let baseline = get_baseline("v1")?;
+        let diff = OpenApiDiff::new(&baseline, &baseline);
+        assert!(diff.is_compatible());
+        assert!(get_baseline("v0").is_err());
+
+        let baseline = json!({
+            "info": {"version": "0.9.13"},
+            "paths": {
+                "/weather/weather": {"get": {
+                    "parameters": [
+                        {"name": "zip", "in": "query", "required": false,
+                         "schema": {"type": "integer", "nullable": true}},
+                        {"name": "q", "in": "query", "required": false,
+                         "schema": {"type": "string", "nullable": true}},
+                    ],
+                    "responses": {"200": {"content": {"application/json": {
+                        "schema": {"$ref": "#/components/schemas/WeatherData"}}}}},
+                }},
+                "/weather/forecast": {"get": {"responses": {}}},
+            },
+            "components": {"schemas": {"WeatherData": {"properties": {
+                "name": {"type": "string"},
+                "dt": {"type": "integer"},
+                "weather": {"type": "array", "items": {"properties": {
+                    "id": {"type": "integer"}, "icon": {"type": "string"}}}},
+            }}}},
+        });
+        let current = json!({
+            "info": {"version": "0.10.0"},
+            "paths": {
+                "/weather/weather": {"get": {
+                    "parameters": [
+                        {"name": "zip", "in": "query", "required": true,
+                         "schema": {"type": "integer", "nullable": true}},
+                        {"name": "units", "in": "query", "required": true,
+                         "schema": {"type": "string"}},
+                    ],
+                    "responses": {"200": {"content": {"application/json": {
+                        "schema": {"$ref": "#/components/schemas/WeatherData"}}}}},
+                }},
+                "/weather/history": {"get": {"responses": {}}},
+            },
+            "components": {"schemas": {"WeatherData": {"properties": {
+                "name": {"type": "string", "nullable": true},
+                "dt": {"type": "string"},
+                "weather": {"type": "array", "items": {"properties": {
+                    "id": {"type": "integer"}}}},
+                "timezone": {"type": "integer"},
+            }}}},
+        });
+        let diff = OpenApiDiff::new(&baseline, &current);
+        assert!(!diff.is_compatible());
+        assert_eq!(diff.baseline_version, "0.9.13");
+        assert_eq!(diff.current_version, "0.10.0");
+        let changes: Vec<_> = diff
+            .breaking_changes
+            .iter()
+            .map(|c| format!("{}: {}", c.location, c.description))
+            .collect();
+        let expected = [
+            "GET /weather/weather query parameter zip: parameter is now required",
+            "GET /weather/weather query parameter q: parameter removed",
+            "GET /weather/weather query parameter units: required parameter added",
+            "GET /weather/weather 200 application/json .name: may now be null",
+            "GET /weather/weather 200 application/json .dt: type changed from integer to string",
+            "GET /weather/weather 200 application/json .weather[].icon: field removed",
+            "/weather/forecast: path removed",
+        ];
+        for e in expected {
+            assert!(changes.iter().any(|c| c == e), "missing {e} in {changes:?}");
+        }
+        assert_eq!(changes.len(), expected.len());
+        Ok(())
+    }
+}
@@ -1,34 +1,314 @@
-use anyhow::Error;
-use clap::Parser;
+use anyhow::{format_err, Error};
+use clap::{Parser, Subcommand, ValueEnum};
 use futures::{future::try_join_all, TryStreamExt};
 use refinery::embed_migrations;
+use reqwest::Client;
 use rweb_helper::DateType;
+use serde::Serialize;
 use stack_string::{format_sstr, StackString};
-use std::path::PathBuf;
-use time::{macros::format_description, Date};
+use std::{collections::BTreeMap, fmt, path::PathBuf, time::Duration};
+use time::{macros::format_description, Date, OffsetDateTime};
 use tokio::{
-    fs::{read, File},
-    io::{stdin, stdout, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    fs::{read, write, File},
+    io::{stdin, stdout, AsyncReadExt, AsyncWriteExt},
+    time::interval,
 };
 
+#[cfg(feature = "s3-sync")]
+use futures::{stream, StreamExt};
+#[cfg(feature = "analysis")]
+use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "analysis")]
+use tokio::fs::create_dir_all;
+
+use weather_api_types::{get_parameters, units::Units};
+
 use crate::{
-    app::start_app,
+    app::{get_api_spec, start_app},
+    backfill::{backfill_location, BackfillSource},
+    bench::{read_baseline, run_benchmarks, write_baseline},
     config::Config,
+    coverage::expected_interval,
+    date_time_wrapper::DateTimeWrapper,
+    demo::generate_demo_history,
+    events::{detect_storm_events, StormThresholds},
+    model::{HistoryCursor, LocationProviderOverride, SortOrder},
+    notify::{Notification, Notifier, NotifySink},
+    openapi_diff::{get_baseline, latest_baseline, OpenApiDiff},
     pgpool::PgPool,
-    polars_analysis::{get_by_name_dates, insert_db_into_parquet},
-    s3_sync::S3Sync,
+    publish::{publish_snapshots, PublishTarget},
+    quality::get_history_quality,
+    report::{default_report_end_date, get_climate_report, ReportPeriod},
+    retention::expire_caches,
+    services::StoredHistoryService,
     WeatherDataDB,
 };
 
+#[cfg(feature = "analysis")]
+use crate::polars_analysis::{
+    get_anomalies, get_by_name_dates, get_climatology, get_db_months, insert_db_into_parquet,
+    insert_forecasts_into_parquet, insert_rows_into_parquet, rebuild_parquet_from_db, ArchiveMonth,
+    ParquetWriteSummary,
+};
+#[cfg(feature = "s3-sync")]
+use crate::s3_sync::{RowCountCheck, S3Sync};
+
 embed_migrations!("migrations");
 
+/// Rows fetched per database query by `query`
+const QUERY_BATCH_SIZE: usize = 1000;
+
 fn parse_date_from_str(s: &str) -> Result<DateType, String> {
     Date::parse(s, format_description!("[year]-[month]-[day]"))
         .map(Into::into)
         .map_err(|e| format!("{e}"))
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    async fn write<T>(self, output: &T) -> Result<(), Error>
+    where
+        T: Serialize + fmt::Display,
+    {
+        let mut buf = match self {
+            Self::Text => output.to_string(),
+            Self::Json => serde_json::to_string(output)?,
+        };
+        buf.push('\n');
+        stdout().write_all(buf.as_bytes()).await?;
+        Ok(())
+    }
+}
+
 #[derive(Parser, Debug)]
+struct Opts {
+    #[clap(long, value_enum, global = true, default_value = "text")]
+    /// Output format for command results
+    output: OutputFormat,
+    #[clap(subcommand)]
+    command: ParseOpts,
+}
+
+#[derive(Serialize)]
+struct MigrationSummary {
+    applied: Vec<StackString>,
+}
+
+impl fmt::Display for MigrationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "applied {}", self.applied.join(" "))
+    }
+}
+
+#[derive(Serialize)]
+struct ImportSummary {
+    written: u64,
+}
+
+impl fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "written {}", self.written)
+    }
+}
+
+#[derive(Serialize)]
+struct ReportSummary {
+    filepath: StackString,
+    days: usize,
+    anomalies: usize,
+    emailed: bool,
+}
+
+impl fmt::Display for ReportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "wrote {} ({} days, {} anomalies)",
+            self.filepath, self.days, self.anomalies
+        )?;
+        if self.emailed {
+            write!(f, ", emailed")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct ExportSummary {
+    rows: usize,
+    destination: StackString,
+}
+
+impl fmt::Display for ExportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exported {} to {}", self.rows, self.destination)
+    }
+}
+
+fn parse_s3_url(s: &str) -> Option<Result<(&str, &str), Error>> {
+    let path = s.strip_prefix("s3://")?;
+    Some(match path.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok((bucket, key)),
+        _ => Err(format_err!("invalid s3 url {s}, expected s3://bucket/key")),
+    })
+}
+
+#[cfg(feature = "analysis")]
+#[derive(Serialize)]
+struct ParquetSummary {
+    files: Vec<ParquetWriteSummary>,
+}
+
+#[cfg(feature = "analysis")]
+impl fmt::Display for ParquetSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<_> = self.files.iter().map(ToString::to_string).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(feature = "s3-sync")]
+#[derive(Serialize)]
+struct RowCountSummary {
+    checks: Vec<RowCountCheck>,
+}
+
+#[cfg(feature = "s3-sync")]
+impl fmt::Display for RowCountSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<_> = self.checks.iter().map(ToString::to_string).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(feature = "analysis")]
+#[derive(Serialize)]
+struct ReadSummary {
+    rows: usize,
+}
+
+#[cfg(feature = "analysis")]
+impl fmt::Display for ReadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rows)
+    }
+}
+
+/// Filters of the `query` subcommand
+struct QueryFilter<'a> {
+    name: Option<&'a str>,
+    server: Option<&'a str>,
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+}
+
+impl QueryFilter<'_> {
+    /// Write the matching rows after `cursor` by `(created_at, id)`, returns
+    /// the position after the last row written
+    async fn write_rows_after(
+        &self,
+        pool: &PgPool,
+        mut cursor: Option<HistoryCursor>,
+        output: OutputFormat,
+    ) -> Result<Option<HistoryCursor>, Error> {
+        loop {
+            let rows: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates_after(
+                pool,
+                self.name,
+                self.server,
+                self.start_date,
+                self.end_date,
+                None,
+                None,
+                None,
+                cursor,
+                SortOrder::Asc,
+                QUERY_BATCH_SIZE,
+            )
+            .await?
+            .try_collect()
+            .await?;
+            for row in &rows {
+                output.write(row).await?;
+            }
+            if let Some(last) = rows.last() {
+                cursor.replace(last.into());
+            }
+            if rows.len() < QUERY_BATCH_SIZE {
+                return Ok(cursor);
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EventSummary {
+    locations: usize,
+    events: usize,
+}
+
+impl fmt::Display for EventSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "detected {} events across {} locations",
+            self.events, self.locations
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct ProviderOverrideEntry {
+    location_name: StackString,
+    has_api_key: bool,
+    api_endpoint: Option<StackString>,
+}
+
+#[derive(Serialize)]
+struct ProviderOverrideSummary {
+    updated: u64,
+    overrides: Vec<ProviderOverrideEntry>,
+}
+
+impl fmt::Display for ProviderOverrideSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "updated {}", self.updated)?;
+        for entry in &self.overrides {
+            write!(
+                f,
+                "\n{} key {} endpoint {}",
+                entry.location_name,
+                if entry.has_api_key { "set" } else { "default" },
+                entry.api_endpoint.as_deref().unwrap_or("default"),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct SeedSummary {
+    locations: usize,
+    rows: usize,
+    destination: StackString,
+}
+
+impl fmt::Display for SeedSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "seeded {} rows for {} locations into {}",
+            self.rows, self.locations, self.destination
+        )
+    }
+}
+
+#[derive(Subcommand, Debug)]
 pub enum ParseOpts {
     /// Run migrations
     RunMigrations,
@@ -53,7 +333,7 @@ pub enum ParseOpts {
         /// End date
         end_time: Option<DateType>,
         #[clap(short, long)]
-        /// Output file (if missinge will read from stdin)
+        /// Output file or s3://bucket/key url (if missing will write to stdout)
         filepath: Option<PathBuf>,
         #[clap(short, long)]
         table: Option<StackString>,
@@ -63,10 +343,36 @@ pub enum ParseOpts {
         limit: Option<usize>,
     },
     /// Export DB data into parquet files
+    #[cfg(feature = "analysis")]
     Db {
         #[clap(short = 'd', long = "directory")]
         directory: Option<PathBuf>,
+        #[clap(long)]
+        /// Export the recorded forecasts instead of the observations, into
+        /// `forecast_cache_dir` unless a directory is given
+        forecasts: bool,
     },
+    /// Regenerate the monthly parquet files from scratch, replacing the
+    /// existing files instead of merging into them
+    #[cfg(feature = "analysis")]
+    RebuildArchive {
+        #[clap(short = 'd', long = "directory")]
+        directory: Option<PathBuf>,
+        #[clap(long)]
+        /// Read the months from the db (the only source for now)
+        from_db: bool,
+        #[clap(long, value_parser=parse_date_from_str)]
+        /// First month to rebuild (default every month in the db)
+        since: Option<DateType>,
+        #[clap(short, long, default_value = "4")]
+        /// Months processed in parallel
+        jobs: usize,
+        #[cfg(feature = "s3-sync")]
+        #[clap(long)]
+        /// Compare the row counts of the rebuilt files with the s3 copies
+        verify: bool,
+    },
+    #[cfg(feature = "analysis")]
     Read {
         #[clap(short = 'd', long = "directory")]
         directory: Option<PathBuf>,
@@ -83,10 +389,209 @@ pub enum ParseOpts {
         #[clap(short = 'l', long = "limit")]
         limit: Option<usize>,
     },
+    /// Print the rows of the database history oldest first, unlike `read`
+    /// this sees live observations before they reach the parquet archive
+    Query {
+        #[clap(short = 'n', long = "name")]
+        name: Option<StackString>,
+        #[clap(short = 's', long = "server")]
+        server: Option<StackString>,
+        #[clap(short='b', long="start_date", value_parser=parse_date_from_str)]
+        start_date: Option<DateType>,
+        #[clap(short='e', long="end_date", value_parser=parse_date_from_str)]
+        end_date: Option<DateType>,
+        #[clap(short = 'w', long = "watch")]
+        /// Keep polling for rows recorded after the last row shown
+        watch: bool,
+        #[clap(short = 'i', long = "interval", default_value = "60")]
+        /// Polling interval in seconds for watch mode
+        interval: u64,
+    },
+    /// Monthly normals of a location over every year of the parquet archive
+    #[cfg(feature = "analysis")]
+    Climatology {
+        #[clap(short = 'd', long = "directory")]
+        directory: Option<PathBuf>,
+        #[clap(short = 'n', long = "name")]
+        name: StackString,
+        #[clap(short = 's', long = "server")]
+        server: Option<StackString>,
+    },
+    /// Flag readings deviating from the rolling window in the parquet
+    /// archive
+    #[cfg(feature = "analysis")]
+    Anomalies {
+        #[clap(short = 'd', long = "directory")]
+        directory: Option<PathBuf>,
+        #[clap(short = 'n', long = "name")]
+        name: StackString,
+        #[clap(short = 's', long = "server")]
+        server: Option<StackString>,
+        #[clap(short='b', long="start_date", value_parser=parse_date_from_str)]
+        /// Default 30 days ago
+        start_date: Option<DateType>,
+        #[clap(short='e', long="end_date", value_parser=parse_date_from_str)]
+        /// Default today
+        end_date: Option<DateType>,
+        #[clap(short = 't', long = "threshold", default_value = "4")]
+        /// Standard deviations from the window mean
+        threshold: f64,
+        #[clap(short = 'w', long = "window_days", default_value = "30")]
+        window_days: i64,
+    },
+    #[cfg(feature = "s3-sync")]
     Sync {
         #[clap(short = 'd', long = "directory")]
         directory: Option<PathBuf>,
     },
+    /// Detect storm events in recorded data and store them in `weather_events`
+    DetectEvents {
+        #[clap(short = 'n', long = "name")]
+        name: Option<StackString>,
+        #[clap(short = 's', long = "server")]
+        server: Option<StackString>,
+        #[clap(short='b', long="start_date", value_parser=parse_date_from_str)]
+        start_date: Option<DateType>,
+        #[clap(short='e', long="end_date", value_parser=parse_date_from_str)]
+        end_date: Option<DateType>,
+    },
+    /// Fill gaps in the recorded history of a location from a historical
+    /// api, inserted rows are flagged `backfilled`
+    Backfill {
+        #[clap(short = 'n', long = "name")]
+        name: StackString,
+        #[clap(short = 's', long = "server")]
+        /// Server whose samples are checked and filled (default `SERVER`)
+        server: Option<StackString>,
+        #[clap(short='b', long="start_date", value_parser=parse_date_from_str)]
+        start_date: Option<DateType>,
+        #[clap(short='e', long="end_date", value_parser=parse_date_from_str)]
+        end_date: Option<DateType>,
+        #[clap(short, long, default_value = "30")]
+        /// Minutes between samples reported as a gap
+        gap: i64,
+        #[clap(long, default_value = "open-meteo")]
+        /// open-meteo or openweathermap (One Call timemachine)
+        source: BackfillSource,
+        #[clap(long)]
+        /// Only report the gaps, nothing is fetched or inserted
+        dry_run: bool,
+    },
+    /// Report missing intervals, duplicate timestamps, impossible values and
+    /// monthly coverage of the recorded history of a location
+    Quality {
+        #[clap(short = 'n', long = "name")]
+        name: StackString,
+        #[clap(short = 's', long = "server")]
+        server: Option<StackString>,
+        #[clap(short='b', long="start_date", value_parser=parse_date_from_str)]
+        /// First day checked (default a year before `end_date`)
+        start_date: Option<DateType>,
+        #[clap(short='e', long="end_date", value_parser=parse_date_from_str)]
+        /// Last day checked (default today)
+        end_date: Option<DateType>,
+        #[clap(short, long)]
+        /// Expected seconds between observations (default the recorder
+        /// interval)
+        interval: Option<u64>,
+    },
+    /// Publish static json and html daily summaries of `PUBLISH_LOCATIONS`
+    Publish {
+        #[clap(short, long)]
+        /// Output directory or s3://bucket/prefix url (default
+        /// `PUBLISH_DESTINATION`)
+        destination: Option<StackString>,
+    },
+    /// Write the weekly or monthly climate report of a location to an html
+    /// file
+    Report {
+        #[clap(short = 'n', long = "name")]
+        name: StackString,
+        #[clap(short = 'p', long = "period", default_value = "weekly")]
+        /// weekly or monthly
+        period: ReportPeriod,
+        #[clap(short = 's', long = "server")]
+        server: Option<StackString>,
+        #[clap(short='e', long="end_date", value_parser=parse_date_from_str)]
+        /// Last day of the report (default yesterday)
+        end_date: Option<DateType>,
+        #[clap(short, long)]
+        /// standard, metric or imperial (default `PUBLISH_UNITS`)
+        units: Option<Units>,
+        #[clap(short, long)]
+        /// Output file (default `<location>-<period>-<end_date>.html`)
+        filepath: Option<PathBuf>,
+        #[clap(long)]
+        /// Also send the report as an attachment through the email sink
+        email: bool,
+    },
+    /// Remove unused location cache entries and orphaned key item cache rows
+    ExpireCaches,
+    /// Set or remove the provider api key / endpoint of a location, then
+    /// list the stored overrides
+    ProviderOverride {
+        #[clap(short = 'n', long = "name")]
+        /// Location in the format of `LOCATIONS_TO_RECORD`
+        name: Option<StackString>,
+        #[clap(long)]
+        api_key: Option<StackString>,
+        #[clap(long)]
+        api_endpoint: Option<StackString>,
+        #[clap(long)]
+        /// Remove the override of the location
+        delete: bool,
+    },
+    /// Generate synthetic hourly history of demo locations (server `demo`)
+    SeedDemo {
+        #[clap(short, long, default_value = "5")]
+        locations: usize,
+        #[clap(short, long, default_value = "365")]
+        days: u32,
+        #[clap(short='e', long="end_date", value_parser=parse_date_from_str)]
+        /// Last day of generated data (default today)
+        end_date: Option<DateType>,
+        #[clap(long, default_value = "0")]
+        /// Random seed, the same seed generates the same data
+        seed: u64,
+        #[cfg(feature = "analysis")]
+        #[clap(short = 'p', long = "parquet")]
+        /// Write parquet files into this directory instead of the db
+        parquet: Option<PathBuf>,
+    },
+    /// Benchmark plot json serialization, parquet archive throughput and
+    /// history query latency, compared against a stored baseline
+    Bench {
+        #[clap(
+            short,
+            long,
+            value_delimiter = ',',
+            default_value = "1000,10000,100000"
+        )]
+        /// Number of synthetic rows per benchmark
+        sizes: Vec<usize>,
+        #[clap(short, long, default_value = "5")]
+        iterations: usize,
+        #[clap(short = 'n', long = "name")]
+        /// Recorded location used for the db / parquet history queries
+        name: Option<StackString>,
+        #[clap(short, long)]
+        /// Baseline json file
+        baseline: Option<PathBuf>,
+        #[clap(long)]
+        /// Store the results as the new baseline
+        save_baseline: bool,
+        #[clap(short, long, default_value = "0.2")]
+        /// Slowdown (fraction of the baseline mean) reported as a regression
+        threshold: f64,
+    },
+    /// Report the breaking changes of the current api spec against a stored
+    /// baseline, fails when there are any
+    OpenapiDiff {
+        #[clap(short, long)]
+        /// Baseline yaml or json spec, e.g. scripts/openapi.yaml (default the
+        /// latest stored baseline)
+        baseline: Option<PathBuf>,
+    },
 }
 
 impl ParseOpts {
@@ -95,20 +600,26 @@ impl ParseOpts {
     /// # Panics
     /// Panics if no db url when calling run migrations
     pub async fn process_args() -> Result<(), Error> {
-        let opts = ParseOpts::parse();
+        let Opts { output, command } = Opts::parse();
         let config = Config::init_config(None)?;
 
-        match opts {
+        match command {
             Self::RunMigrations => {
-                let pool = PgPool::new(&config.database_url)?;
+                let pool = PgPool::from_config(&config)?;
                 let mut client = pool.get().await?;
-                migrations::runner().run_async(&mut **client).await?;
+                let report = migrations::runner().run_async(&mut **client).await?;
+                let applied = report
+                    .applied_migrations()
+                    .iter()
+                    .map(|m| format_sstr!("{m}"))
+                    .collect();
+                output.write(&MigrationSummary { applied }).await?;
             }
             Self::Daemon => {
                 tokio::spawn(async move { start_app().await }).await??;
             }
             Self::Import { filepath, table: _ } => {
-                let pool = PgPool::new(&config.database_url)?;
+                let pool = PgPool::from_config(&config)?;
 
                 let data = if let Some(filepath) = filepath {
                     read(&filepath).await?
@@ -125,9 +636,7 @@ impl ParseOpts {
                 });
                 let results: Result<Vec<u64>, Error> = try_join_all(futures).await;
                 let written: u64 = results?.into_iter().sum();
-                stdout()
-                    .write_all(format_sstr!("written {written}\n").as_bytes())
-                    .await?;
+                output.write(&ImportSummary { written }).await?;
             }
             Self::Export {
                 server,
@@ -138,42 +647,129 @@ impl ParseOpts {
                 offset,
                 limit,
             } => {
-                let pool = PgPool::new(&config.database_url)?;
-                let results: Vec<_> = WeatherDataDB::get_by_name_dates(
+                let pool = PgPool::from_config(&config)?;
+                let rows = WeatherDataDB::get_by_name_dates(
                     &pool,
                     None,
                     server.as_ref().map(StackString::as_str),
                     start_time.map(Into::into),
                     end_time.map(Into::into),
+                    None,
+                    None,
+                    None,
                     offset,
                     limit,
                 )
-                .await?
-                .try_collect()
                 .await?;
 
-                let mut file: Box<dyn AsyncWrite + Unpin + Send + Sync> =
-                    if let Some(filepath) = filepath {
-                        Box::new(File::create(&filepath).await?)
-                    } else {
-                        Box::new(stdout())
-                    };
+                let s3_url = filepath
+                    .as_ref()
+                    .and_then(|f| f.to_str())
+                    .and_then(parse_s3_url)
+                    .transpose()?;
+                #[cfg(not(feature = "s3-sync"))]
+                if s3_url.is_some() {
+                    return Err(format_err!("exporting to s3 requires the s3-sync feature"));
+                }
+                #[cfg(feature = "s3-sync")]
+                if let Some((bucket, key)) = s3_url {
+                    let mut nrows = 0;
+                    let body = rows.map_err(Into::<Error>::into).and_then(|row| {
+                        let mut buf = if nrows == 0 { Vec::new() } else { vec![b','] };
+                        nrows += 1;
+                        let result = serde_json::to_writer(&mut buf, &row)
+                            .map(|()| buf)
+                            .map_err(Into::into);
+                        async move { result }
+                    });
+                    let body = stream::once(async { Ok(b"[".to_vec()) })
+                        .chain(body)
+                        .chain(stream::once(async { Ok(b"]".to_vec()) }));
+                    let aws_config = aws_config::load_from_env().await;
+                    let sync = S3Sync::new(&aws_config);
+                    sync.upload_stream(bucket, key, Box::pin(body)).await?;
+                    let destination = format_sstr!("s3://{bucket}/{key}");
+                    output
+                        .write(&ExportSummary {
+                            rows: nrows,
+                            destination,
+                        })
+                        .await?;
+                    return Ok(());
+                }
+                let results: Vec<_> = rows.try_collect().await?;
 
-                file.write_all(&serde_json::to_vec(&results)?).await?;
+                if let Some(filepath) = filepath {
+                    let mut file = File::create(&filepath).await?;
+                    file.write_all(&serde_json::to_vec(&results)?).await?;
+                    let destination = filepath.to_string_lossy().as_ref().into();
+                    output
+                        .write(&ExportSummary {
+                            rows: results.len(),
+                            destination,
+                        })
+                        .await?;
+                } else {
+                    stdout().write_all(&serde_json::to_vec(&results)?).await?;
+                }
             }
-            Self::Db { directory } => {
+            #[cfg(feature = "analysis")]
+            Self::Db {
+                directory,
+                forecasts,
+            } => {
+                let pool = PgPool::from_config(&config)?;
+                let files = if forecasts {
+                    let directory = directory.unwrap_or_else(|| config.forecast_cache_dir.clone());
+                    create_dir_all(&directory).await?;
+                    insert_forecasts_into_parquet(&pool, &directory).await?
+                } else {
+                    let directory = directory.unwrap_or_else(|| config.cache_dir.clone());
+                    insert_db_into_parquet(&pool, &directory).await?
+                };
+                output.write(&ParquetSummary { files }).await?;
+            }
+            #[cfg(feature = "analysis")]
+            Self::RebuildArchive {
+                directory,
+                from_db,
+                since,
+                jobs,
+                #[cfg(feature = "s3-sync")]
+                verify,
+            } => {
+                if !from_db {
+                    return Err(format_err!("no source to rebuild from, pass --from-db"));
+                }
                 let directory = directory.unwrap_or_else(|| config.cache_dir.clone());
-                let pool = PgPool::new(&config.database_url)?;
-                stdout()
-                    .write_all(
-                        insert_db_into_parquet(&pool, &directory)
-                            .await?
-                            .join("\n")
-                            .as_bytes(),
-                    )
-                    .await?;
-                stdout().write_all(b"\n").await?;
+                let pool = PgPool::from_config(&config)?;
+                let months = get_db_months(&pool, since.map(Into::into)).await?;
+                let progress = ProgressBar::new(months.len() as u64).with_style(
+                    ProgressStyle::with_template("{bar:40} {pos}/{len} months {msg}")?,
+                );
+                let files = rebuild_parquet_from_db(&pool, &directory, &months, jobs, |summary| {
+                    progress.set_message(summary.filename.to_string());
+                    progress.inc(1);
+                })
+                .await?;
+                progress.finish_and_clear();
+                output.write(&ParquetSummary { files }).await?;
+                #[cfg(feature = "s3-sync")]
+                if verify {
+                    let aws_config = aws_config::load_from_env().await;
+                    let sync = S3Sync::new(&aws_config);
+                    let keys: Vec<_> = months.iter().map(ArchiveMonth::filename).collect();
+                    let checks = sync
+                        .verify_row_counts(&directory, &config.s3_bucket, &keys)
+                        .await?;
+                    let mismatches = checks.iter().filter(|c| !c.matches).count();
+                    output.write(&RowCountSummary { checks }).await?;
+                    if mismatches > 0 {
+                        return Err(format_err!("{mismatches} files differ from s3"));
+                    }
+                }
             }
+            #[cfg(feature = "analysis")]
             Self::Read {
                 directory,
                 name,
@@ -194,24 +790,356 @@ impl ParseOpts {
                     limit,
                 )
                 .await?;
-                stdout()
-                    .write_all(format_sstr!("{}\n", rows.len()).as_bytes())
-                    .await?;
+                output.write(&ReadSummary { rows: rows.len() }).await?;
+            }
+            Self::Query {
+                name,
+                server,
+                start_date,
+                end_date,
+                watch,
+                interval: interval_secs,
+            } => {
+                let pool = PgPool::from_config(&config)?;
+                let filter = QueryFilter {
+                    name: name.as_ref().map(StackString::as_str),
+                    server: server.as_ref().map(StackString::as_str),
+                    start_date: start_date.map(Into::into),
+                    end_date: end_date.map(Into::into),
+                };
+                let mut cursor = filter.write_rows_after(&pool, None, output).await?;
+                if watch {
+                    let mut i = interval(Duration::from_secs(interval_secs.max(1)));
+                    i.tick().await;
+                    loop {
+                        i.tick().await;
+                        cursor = filter.write_rows_after(&pool, cursor, output).await?;
+                    }
+                }
+            }
+            #[cfg(feature = "analysis")]
+            Self::Climatology {
+                directory,
+                name,
+                server,
+            } => {
+                let directory = directory.unwrap_or_else(|| config.cache_dir.clone());
+                let server = server.as_ref().map(StackString::as_str);
+                let climatology = get_climatology(&directory, &name, server).await?;
+                output.write(&climatology).await?;
+            }
+            #[cfg(feature = "analysis")]
+            Self::Anomalies {
+                directory,
+                name,
+                server,
+                start_date,
+                end_date,
+                threshold,
+                window_days,
+            } => {
+                let directory = directory.unwrap_or_else(|| config.cache_dir.clone());
+                let server = server.as_ref().map(StackString::as_str);
+                let end_date: Date =
+                    end_date.map_or_else(|| OffsetDateTime::now_utc().date(), Into::into);
+                let start_date: Date =
+                    start_date.map_or_else(|| end_date - time::Duration::days(30), Into::into);
+                let report = get_anomalies(
+                    &directory,
+                    &name,
+                    server,
+                    start_date,
+                    end_date,
+                    window_days.max(1),
+                    threshold,
+                )
+                .await?;
+                output.write(&report).await?;
             }
+            #[cfg(feature = "s3-sync")]
             Self::Sync { directory } => {
                 let aws_config = aws_config::load_from_env().await;
                 let sync = S3Sync::new(&aws_config);
                 let directory = directory.unwrap_or_else(|| config.cache_dir.clone());
-                let pool = PgPool::new(&config.database_url)?;
+                let pool = PgPool::from_config(&config)?;
 
-                stdout()
-                    .write_all(
-                        sync.sync_dir("weather-data", &directory, &config.s3_bucket, &pool)
-                            .await?
-                            .as_bytes(),
+                let summary = sync
+                    .sync_dir("weather-data", &directory, &config.s3_bucket, &pool)
+                    .await?;
+                output.write(&summary).await?;
+            }
+            Self::DetectEvents {
+                name,
+                server,
+                start_date,
+                end_date,
+            } => {
+                let pool = PgPool::from_config(&config)?;
+                let names: Vec<StackString> = if let Some(name) = name {
+                    vec![name]
+                } else {
+                    WeatherDataDB::get_locations(&pool, None, None)
+                        .await?
+                        .map_ok(|(name, _)| name)
+                        .try_collect()
+                        .await?
+                };
+                let thresholds = StormThresholds::default();
+                let mut events = 0;
+                for name in &names {
+                    let rows: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates(
+                        &pool,
+                        Some(name),
+                        server.as_ref().map(StackString::as_str),
+                        start_date.map(Into::into),
+                        end_date.map(Into::into),
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
                     )
+                    .await?
+                    .try_collect()
+                    .await?;
+                    let mut by_server: BTreeMap<StackString, Vec<WeatherDataDB>> = BTreeMap::new();
+                    for row in rows {
+                        by_server.entry(row.server.clone()).or_default().push(row);
+                    }
+                    for rows in by_server.values() {
+                        for event in detect_storm_events(rows, &thresholds) {
+                            event.upsert(&pool).await?;
+                            events += 1;
+                        }
+                    }
+                }
+                output
+                    .write(&EventSummary {
+                        locations: names.len(),
+                        events,
+                    })
+                    .await?;
+            }
+            Self::Backfill {
+                name,
+                server,
+                start_date,
+                end_date,
+                gap,
+                source,
+                dry_run,
+            } => {
+                if gap < 1 {
+                    return Err(format_err!("gap must be at least a minute"));
+                }
+                let pool = PgPool::from_config(&config)?;
+                let server = server.unwrap_or_else(|| config.server.clone());
+                let summary = backfill_location(
+                    &pool,
+                    &config,
+                    &Client::new(),
+                    &name,
+                    &server,
+                    start_date.map(Into::into),
+                    end_date.map(Into::into),
+                    time::Duration::minutes(gap),
+                    source,
+                    dry_run,
+                )
+                .await?;
+                output.write(&summary).await?;
+            }
+            Self::Quality {
+                name,
+                server,
+                start_date,
+                end_date,
+                interval,
+            } => {
+                let pool = PgPool::from_config(&config)?;
+                let end_date: Date =
+                    end_date.map_or_else(|| OffsetDateTime::now_utc().date(), Into::into);
+                let start_date: Date =
+                    start_date.map_or_else(|| end_date - time::Duration::days(365), Into::into);
+                if start_date > end_date {
+                    return Err(format_err!("start_date must not be after end_date"));
+                }
+                let interval = interval
+                    .filter(|i| *i > 0)
+                    .unwrap_or_else(|| expected_interval(&config));
+                let quality = get_history_quality(
+                    &pool,
+                    &name,
+                    server.as_deref(),
+                    start_date,
+                    end_date,
+                    interval,
+                    None,
+                )
+                .await?;
+                output.write(&quality).await?;
+            }
+            Self::Report {
+                name,
+                period,
+                server,
+                end_date,
+                units,
+                filepath,
+                email,
+            } => {
+                let pool = PgPool::from_config(&config)?;
+                let history = StoredHistoryService::new(&pool, &config);
+                let end_date = end_date.map_or_else(default_report_end_date, Into::into);
+                let server = server.as_ref().map(StackString::as_str);
+                let report =
+                    get_climate_report(&history, &name, server, period, end_date, None).await?;
+                let units = units.unwrap_or(config.publish_units);
+                let filepath = filepath.unwrap_or_else(|| report.filename().as_str().into());
+                write(&filepath, report.render_html(units)?).await?;
+                if email {
+                    let sink = NotifySink::from_config(&config)
+                        .into_iter()
+                        .find(|sink| matches!(sink, NotifySink::Email(_)))
+                        .ok_or_else(|| format_err!("no email sink configured"))?;
+                    let notification = Notification::new(report.title(), report.title())
+                        .with_attachment(report.attachment(units)?);
+                    sink.send(&Client::new(), &notification).await?;
+                }
+                let summary = ReportSummary {
+                    filepath: filepath.to_string_lossy().as_ref().into(),
+                    days: report.days.len(),
+                    anomalies: report.anomalies.len(),
+                    emailed: email,
+                };
+                output.write(&summary).await?;
+            }
+            Self::Publish { destination } => {
+                let destination = destination
+                    .or_else(|| config.publish_destination.clone())
+                    .ok_or_else(|| format_err!("no publish destination"))?;
+                let target = PublishTarget::parse(&destination)?;
+                let pool = PgPool::from_config(&config)?;
+                let summary = publish_snapshots(&pool, &config, &target).await?;
+                output.write(&summary).await?;
+            }
+            Self::ExpireCaches => {
+                let pool = PgPool::from_config(&config)?;
+                let report = expire_caches(&pool, &config).await?;
+                output.write(&report).await?;
+            }
+            Self::ProviderOverride {
+                name,
+                api_key,
+                api_endpoint,
+                delete,
+            } => {
+                let pool = PgPool::from_config(&config)?;
+                let mut updated = 0;
+                if let Some(name) = name {
+                    let location_name = format_sstr!("{}", get_parameters(&name));
+                    updated = if delete {
+                        LocationProviderOverride::delete(&pool, &location_name).await?
+                    } else if api_key.is_none() && api_endpoint.is_none() {
+                        return Err(format_err!("set --api-key and/or --api-endpoint"));
+                    } else {
+                        LocationProviderOverride {
+                            location_name,
+                            api_key,
+                            api_endpoint,
+                            created_at: DateTimeWrapper::now(),
+                        }
+                        .upsert(&pool)
+                        .await?
+                    };
+                }
+                let overrides: Vec<_> = LocationProviderOverride::get_all(&pool)
+                    .await?
+                    .map_ok(|row| ProviderOverrideEntry {
+                        location_name: row.location_name,
+                        has_api_key: row.api_key.is_some(),
+                        api_endpoint: row.api_endpoint,
+                    })
+                    .try_collect()
+                    .await?;
+                output
+                    .write(&ProviderOverrideSummary { updated, overrides })
+                    .await?;
+            }
+            Self::SeedDemo {
+                locations,
+                days,
+                end_date,
+                seed,
+                #[cfg(feature = "analysis")]
+                parquet,
+            } => {
+                let end_date =
+                    end_date.map_or_else(|| OffsetDateTime::now_utc().date(), Into::into);
+                let history = generate_demo_history(locations, days, end_date, seed);
+                let rows = history.len();
+                #[cfg(feature = "analysis")]
+                if let Some(directory) = parquet {
+                    insert_rows_into_parquet(history, &directory)?;
+                    let destination = directory.to_string_lossy().as_ref().into();
+                    output
+                        .write(&SeedSummary {
+                            locations,
+                            rows,
+                            destination,
+                        })
+                        .await?;
+                    return Ok(());
+                }
+                let pool = PgPool::from_config(&config)?;
+                for chunk in history.chunks(1000) {
+                    try_join_all(chunk.iter().map(|row| row.insert(&pool))).await?;
+                }
+                output
+                    .write(&SeedSummary {
+                        locations,
+                        rows,
+                        destination: "db".into(),
+                    })
                     .await?;
-                stdout().write_all(b"\n").await?;
+            }
+            Self::Bench {
+                sizes,
+                iterations,
+                name,
+                baseline,
+                save_baseline,
+                threshold,
+            } => {
+                let pool = PgPool::from_config(&config)?;
+                let report =
+                    run_benchmarks(&pool, &config, &sizes, iterations, name.as_deref()).await?;
+                let previous = match &baseline {
+                    Some(path) if path.exists() => Some(read_baseline(path).await?),
+                    _ => None,
+                };
+                let summary = report.compare(previous.as_ref(), threshold);
+                output.write(&summary).await?;
+                if save_baseline {
+                    let path = baseline.ok_or_else(|| format_err!("no baseline file"))?;
+                    write_baseline(&path, &report).await?;
+                } else if summary.regressions > 0 {
+                    return Err(format_err!("{} benchmarks regressed", summary.regressions));
+                }
+            }
+            Self::OpenapiDiff { baseline } => {
+                let baseline = match baseline {
+                    Some(path) => serde_yml::from_slice(&read(&path).await?)?,
+                    None => get_baseline(latest_baseline())?,
+                };
+                let diff = OpenApiDiff::new(&baseline, &get_api_spec(&config)?);
+                output.write(&diff).await?;
+                if !diff.is_compatible() {
+                    return Err(format_err!(
+                        "{} breaking api changes",
+                        diff.breaking_changes.len()
+                    ));
+                }
             }
         }
         Ok(())
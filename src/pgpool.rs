@@ -1,18 +1,26 @@
 use anyhow::Error;
-use deadpool_postgres::{Client, Config, Pool};
-use derive_more::Deref;
+use deadpool_postgres::{Client, Config, Pool, Status};
 use std::{fmt, sync::Arc};
+use thiserror::Error;
 use tokio_postgres::{Config as PgConfig, NoTls};
+use tracing::instrument;
 
 pub use tokio_postgres::Transaction as PgTransaction;
 
 use stack_string::StackString;
 
-#[derive(Clone, Deref)]
+use crate::config::Config as AppConfig;
+
+/// Returned by `PgPool::get` when no `DATABASE_URL` is configured
+#[derive(Error, Debug)]
+#[error("Database is disabled")]
+pub struct DatabaseDisabled;
+
+#[derive(Clone)]
 pub struct PgPool {
     pgurl: Arc<StackString>,
-    #[deref]
-    pool: Pool,
+    /// `None` in stateless proxy mode
+    pool: Option<Pool>,
 }
 
 impl fmt::Debug for PgPool {
@@ -48,13 +56,62 @@ impl PgPool {
 
         Ok(Self {
             pgurl: Arc::new(pgurl.into()),
-            pool,
+            pool: Some(pool),
         })
     }
 
+    /// Pool without a database, every `get` fails with `DatabaseDisabled`
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self {
+            pgurl: Arc::new(StackString::new()),
+            pool: None,
+        }
+    }
+
+    /// Pool for `DATABASE_URL`, disabled when it isn't set
+    ///
+    /// # Errors
+    /// Returns error pool setup fails
+    pub fn from_config(config: &AppConfig) -> Result<Self, Error> {
+        config
+            .database_url
+            .as_ref()
+            .map_or_else(|| Ok(Self::disabled()), |pgurl| Self::new(pgurl))
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    #[must_use]
+    pub fn status(&self) -> Option<Status> {
+        self.pool.as_ref().map(Pool::status)
+    }
+
     /// # Errors
-    /// Return error if getting connection fails
+    /// Return error if getting connection fails or the database is disabled
+    #[instrument(name = "pg_pool_get", skip_all)]
     pub async fn get(&self) -> Result<Client, Error> {
-        self.pool.get().await.map_err(Into::into)
+        let pool = self.pool.as_ref().ok_or(DatabaseDisabled)?;
+        pool.get().await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use crate::pgpool::{DatabaseDisabled, PgPool};
+
+    #[tokio::test]
+    async fn test_disabled_pool() -> Result<(), Error> {
+        let pool = PgPool::disabled();
+        assert!(!pool.is_enabled());
+        assert!(pool.status().is_none());
+        let err = pool.get().await.unwrap_err();
+        assert!(err.is::<DatabaseDisabled>());
+        Ok(())
     }
 }
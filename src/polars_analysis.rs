@@ -1,32 +1,130 @@
 use anyhow::{format_err, Error};
-use chrono::{DateTime, NaiveDateTime};
-use futures::TryStreamExt;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
 use log::{debug, info};
 use polars::{
     df as dataframe,
     io::SerReader,
     prelude::{
-        col, lit, DataFrame, LazyFrame, ParquetReader, ParquetWriter, ScanArgsParquet,
-        SortMultipleOptions, TimeUnit, UniqueKeepStrategy,
+        col, lit, DataFrame, DataType, LazyFrame, ParquetReader, ParquetWriter, ScanArgsParquet,
+        Series, SortMultipleOptions, TimeUnit, UniqueKeepStrategy,
     },
 };
 use postgres_query::{query, FromSqlRow};
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
 use stack_string::{format_sstr, StackString};
-use std::{fs::File, path::Path};
-use time::{Date, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use std::{
+    collections::{btree_map::Entry, BTreeMap, VecDeque},
+    fmt,
+    fs::{self, File},
+    io::Cursor,
+    path::{Path, PathBuf},
+    vec,
+};
+use time::{macros::date, Date, Duration, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use tokio::task::spawn_blocking;
+use tracing::instrument;
 use uuid::Uuid;
 
-use crate::{model::WeatherDataDB, pgpool::PgPool};
+use rweb_helper::{DateTimeType, UuidWrapper};
+
+use crate::{
+    condition::condition_code,
+    derived_metrics::DerivedMetrics,
+    model::{WeatherDataDB, WeatherForecastDB},
+    pgpool::PgPool,
+    publish::{get_daily_summaries, DailySummary},
+};
+
+/// Timestamps are stored as utc milliseconds
+const TIMESTAMP_COLUMNS: [&str; 3] = ["created_at", "sunrise", "sunset"];
+
+fn timestamp_dtype() -> DataType {
+    DataType::Datetime(TimeUnit::Milliseconds, Some("UTC".into()))
+}
+
+fn timestamp_millis(input: OffsetDateTime) -> i64 {
+    (input.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+fn millis_timestamp(input: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(input) * 1_000_000)
+        .expect("Invalid timestamp")
+}
+
+/// Timestamp columns of the forecast archive
+const FORECAST_TIMESTAMP_COLUMNS: [&str; 2] = ["fetched_at", "forecast_time"];
+
+fn cast_timestamps(mut df: DataFrame, names: &[&str]) -> Result<DataFrame, Error> {
+    for name in names {
+        let column = df.column(name)?.cast(&timestamp_dtype())?;
+        df.with_column(column)?;
+    }
+    Ok(df)
+}
+
+/// Tag timestamp columns as utc millisecond datetimes, archives written
+/// before the time zone was recorded hold naive (utc) datetimes
+fn normalize_timestamps(df: DataFrame) -> Result<DataFrame, Error> {
+    cast_timestamps(df, &TIMESTAMP_COLUMNS)
+}
+
+/// Derived metric columns, written after `server`
+const DERIVED_COLUMNS: [&str; 3] = ["dew_point", "heat_index", "wind_chill"];
+
+/// Archives written before the derived metrics were stored lack their
+/// columns, compute them from temperature, humidity and wind speed so the
+/// frames can be stacked with new rows
+fn add_derived_columns(mut df: DataFrame) -> Result<DataFrame, Error> {
+    if DERIVED_COLUMNS.iter().all(|name| df.column(name).is_ok()) {
+        return Ok(df);
+    }
+    let temperature = df.column("temperature")?.f64()?;
+    let humidity = df.column("humidity")?.i32()?;
+    let wind_speed = df.column("wind_speed")?.f64()?;
+    let derived: Vec<_> = temperature
+        .into_iter()
+        .zip(humidity)
+        .zip(wind_speed)
+        .map(|((t, h), w)| match (t, h, w) {
+            (Some(t), Some(h), Some(w)) => DerivedMetrics::new(t, h, w),
+            _ => DerivedMetrics::default(),
+        })
+        .collect();
+    let columns = [
+        derived.iter().map(|d| d.dew_point).collect::<Vec<_>>(),
+        derived.iter().map(|d| d.heat_index).collect(),
+        derived.iter().map(|d| d.wind_chill).collect(),
+    ];
+    for (name, values) in DERIVED_COLUMNS.into_iter().zip(columns) {
+        df.with_column(Series::new(name.into(), values))?;
+    }
+    Ok(df)
+}
+
+/// Archives written before the provenance was stored lack the `backfilled`
+/// column, every row of them was observed
+fn add_backfilled_column(mut df: DataFrame) -> Result<DataFrame, Error> {
+    if df.column("backfilled").is_err() {
+        let backfilled = vec![false; df.height()];
+        df.with_column(Series::new("backfilled".into(), backfilled))?;
+    }
+    Ok(df)
+}
 
-fn convert_offset_naive(input: OffsetDateTime) -> NaiveDateTime {
-    let d: OffsetDateTime = input.to_offset(UtcOffset::UTC);
-    DateTime::from_timestamp(d.unix_timestamp(), d.nanosecond())
-        .expect("Invalid Timestamp")
-        .naive_utc()
+/// Bring an archive written by an older version to the current columns
+fn upgrade_archive(df: DataFrame) -> Result<DataFrame, Error> {
+    add_backfilled_column(add_derived_columns(normalize_timestamps(df)?)?)
 }
 
-fn convert_naive_offset(input: NaiveDateTime) -> OffsetDateTime {
-    OffsetDateTime::from_unix_timestamp(input.and_utc().timestamp()).expect("Invalid timestamp")
+fn timestamp_column(df: &DataFrame, name: &str) -> Result<Vec<i64>, Error> {
+    Ok(df
+        .column(name)?
+        .cast(&timestamp_dtype())?
+        .datetime()?
+        .into_iter()
+        .flatten()
+        .collect())
 }
 
 fn stackstring_to_series(col: &[StackString]) -> Vec<&str> {
@@ -36,7 +134,7 @@ fn stackstring_to_series(col: &[StackString]) -> Vec<&str> {
 struct WeatherDataColumns {
     id: Vec<StackString>,
     dt: Vec<i32>,
-    created_at: Vec<NaiveDateTime>,
+    created_at: Vec<i64>,
     location_name: Vec<StackString>,
     latitude: Vec<f64>,
     longitude: Vec<f64>,
@@ -52,10 +150,15 @@ struct WeatherDataColumns {
     wind_speed: Vec<f64>,
     wind_direction: Vec<Option<f64>>,
     country: Vec<StackString>,
-    sunrise: Vec<NaiveDateTime>,
-    sunset: Vec<NaiveDateTime>,
+    sunrise: Vec<i64>,
+    sunset: Vec<i64>,
     timezone: Vec<i32>,
     server: Vec<StackString>,
+    /// only filled when writing, reading recomputes the derived metrics
+    dew_point: Vec<Option<f64>>,
+    heat_index: Vec<Option<f64>>,
+    wind_chill: Vec<Option<f64>>,
+    backfilled: Vec<bool>,
 }
 
 impl WeatherDataColumns {
@@ -83,14 +186,19 @@ impl WeatherDataColumns {
             sunset: Vec::with_capacity(cap),
             timezone: Vec::with_capacity(cap),
             server: Vec::with_capacity(cap),
+            dew_point: Vec::with_capacity(cap),
+            heat_index: Vec::with_capacity(cap),
+            wind_chill: Vec::with_capacity(cap),
+            backfilled: Vec::with_capacity(cap),
         }
     }
 
     fn add_row(&mut self, row: WeatherDataDB) {
+        let derived = row.derived_metrics();
         self.id.push(format_sstr!("{}", row.id));
         self.dt.push(row.dt);
         self.created_at
-            .push(convert_offset_naive(row.created_at.into()));
+            .push(timestamp_millis(row.created_at.into()));
         self.location_name.push(row.location_name);
         self.latitude.push(row.latitude);
         self.longitude.push(row.longitude);
@@ -106,14 +214,18 @@ impl WeatherDataColumns {
         self.wind_speed.push(row.wind_speed);
         self.wind_direction.push(row.wind_direction);
         self.country.push(row.country);
-        self.sunrise.push(convert_offset_naive(row.sunrise.into()));
-        self.sunset.push(convert_offset_naive(row.sunset.into()));
+        self.sunrise.push(timestamp_millis(row.sunrise.into()));
+        self.sunset.push(timestamp_millis(row.sunset.into()));
         self.timezone.push(row.timezone);
         self.server.push(row.server);
+        self.dew_point.push(derived.dew_point);
+        self.heat_index.push(derived.heat_index);
+        self.wind_chill.push(derived.wind_chill);
+        self.backfilled.push(row.backfilled);
     }
 
     fn get_dataframe(&self) -> Result<DataFrame, Error> {
-        dataframe!(
+        let df = dataframe!(
             "id" => stackstring_to_series(&self.id),
             "dt" => &self.dt,
             "created_at" => &self.created_at,
@@ -136,442 +248,1651 @@ impl WeatherDataColumns {
             "sunset" => &self.sunset,
             "timezone" => &self.timezone,
             "server" => stackstring_to_series(&self.server),
-        )
-        .map_err(Into::into)
-    }
-
-    fn into_weather_data(self) -> Vec<WeatherDataDB> {
-        debug!("cap {}", self.id.len());
-        let mut output = Vec::with_capacity(self.id.len());
-        for i in 0..self.id.len() {
-            output.push(WeatherDataDB {
-                id: Uuid::parse_str(&self.id[i]).expect("Invalid uuid"),
-                dt: self.dt[i],
-                created_at: convert_naive_offset(self.created_at[i]).into(),
-                location_name: self.location_name[i].clone(),
-                latitude: self.latitude[i],
-                longitude: self.longitude[i],
-                condition: self.condition[i].clone(),
-                temperature: self.temperature[i],
-                temperature_minimum: self.temperature_minimum[i],
-                temperature_maximum: self.temperature_maximum[i],
-                pressure: self.pressure[i],
-                humidity: self.humidity[i],
-                visibility: self.visibility[i],
-                rain: self.rain[i],
-                snow: self.snow[i],
-                wind_speed: self.wind_speed[i],
-                wind_direction: self.wind_direction[i],
-                country: self.country[i].clone(),
-                sunrise: convert_naive_offset(self.sunrise[i]).into(),
-                sunset: convert_naive_offset(self.sunset[i]).into(),
-                timezone: self.timezone[i],
-                server: self.server[i].clone(),
-            });
-        }
-        debug!("output {}", output.len());
-        output
-    }
-}
-
-/// # Errors
-/// Returns error if db query fails
-pub async fn insert_db_into_parquet(
-    pool: &PgPool,
-    outdir: &Path,
-) -> Result<Vec<StackString>, Error> {
-    #[derive(FromSqlRow)]
-    struct Wrap {
-        year: i32,
-        month: i32,
-        count: i64,
-    }
-
-    let mut output = Vec::new();
-
-    let query = query!(
-        r#"
-            SELECT cast(extract(year from created_at at time zone 'utc') as int) as year,
-                   cast(extract(month from created_at at time zone 'utc') as int) as month,
-                   count(*) as count
-            FROM weather_data
-            GROUP BY 1,2
-            ORDER BY 1,2
-        "#
-    );
-    let conn = pool.get().await?;
-    let rows: Vec<Wrap> = query.fetch(&conn).await?;
-    if rows.is_empty() {
-        return Ok(output);
-    }
-
-    for Wrap { year, month, count } in rows {
-        let query = query!(
-            r#"
-                SELECT *
-                FROM weather_data
-                WHERE cast(extract(year from created_at at time zone 'utc') as int) = $year
-                  AND cast(extract(month from created_at at time zone 'utc') as int) = $month
-            "#,
-            year = year,
-            month = month,
-        );
-
-        let weather_rows: WeatherDataColumns = query
-            .fetch_streaming::<WeatherDataDB, _>(&conn)
-            .await?
-            .try_fold(
-                WeatherDataColumns::new(count as usize),
-                |mut acc, row| async move {
-                    acc.add_row(row);
-                    Ok(acc)
-                },
-            )
-            .await?;
-
-        let new_df = weather_rows.get_dataframe()?;
-        output.push(format_sstr!("{:?}", new_df.shape()));
-
-        let filename = format_sstr!("weather_data_{year:04}_{month:02}.parquet");
-        let file = outdir.join(&filename);
-        let mut df = if file.exists() {
-            let df = ParquetReader::new(File::open(&file)?).finish()?;
-            output.push(format_sstr!("{:?}", df.shape()));
-            let existing_entries = df.shape().0;
-            let combined_df =
-                df.vstack(&new_df)?
-                    .unique_stable(None, UniqueKeepStrategy::First, None)?;
-            if combined_df.shape().0 == existing_entries {
-                continue;
-            }
-            combined_df
-        } else {
-            new_df
-        };
-        ParquetWriter::new(File::create(&file)?).finish(&mut df)?;
-        output.push(format_sstr!("wrote {filename} {:?}", df.shape()));
-    }
-
-    Ok(output)
-}
-
-/// # Errors
-/// Returns error if input/output doesn't exist or cannot be read
-pub fn merge_parquet_files(input: &Path, output: &Path) -> Result<(), Error> {
-    info!("input {:?} output {:?}", input, output);
-    if !input.exists() {
-        return Err(format_err!("input {input:?} does not exist"));
-    }
-    if !output.exists() {
-        return Err(format_err!("output {output:?} does not exist"));
+            "dew_point" => &self.dew_point,
+            "heat_index" => &self.heat_index,
+            "wind_chill" => &self.wind_chill,
+            "backfilled" => &self.backfilled,
+        )?;
+        normalize_timestamps(df)
     }
-    let df0 = ParquetReader::new(File::open(input)?).finish()?;
-    let entries0 = df0.shape().0;
-    info!("input {entries0}");
-    let df1 = ParquetReader::new(File::open(output)?).finish()?;
-    let entries1 = df1.shape().0;
-    info!("output {entries1}");
-
-    if entries0 == 0 {
-        return Ok(());
-    }
-
-    let mut df = df1
-        .vstack(&df0)?
-        .unique_stable(None, UniqueKeepStrategy::First, None)?;
-    info!("final {:?}", df.shape());
-    ParquetWriter::new(File::create(output)?).finish(&mut df)?;
-    info!("wrote {:?} {:?}", output, df.shape());
-    Ok(())
-}
 
-/// # Errors
-/// Returns error if path does not exist
-pub async fn get_by_name_dates(
-    input: &Path,
-    name: Option<&str>,
-    server: Option<&str>,
-    start_date: Option<Date>,
-    end_date: Option<Date>,
-    offset: Option<usize>,
-    limit: Option<usize>,
-) -> Result<Vec<WeatherDataDB>, Error> {
-    if !input.exists() {
-        return Err(format_err!("Path does not exist"));
-    }
-    let input_files = if input.is_dir() {
-        let v: Result<Vec<_>, Error> = input
-            .read_dir()?
-            .map(|p| p.map(|p| p.path()).map_err(Into::into))
-            .collect();
-        let mut v = v?;
-        v.sort();
-        v
-    } else {
-        vec![input.to_path_buf()]
-    };
-    debug!("{input_files:?}");
-    let mut total = 0;
-    let mut output = Vec::new();
-    for input_file in input_files {
-        let df = get_by_name_dates_file(&input_file, name, server, start_date, end_date).await?;
-        debug!("df {input_file:?} {:?}", df.shape());
-        let (file_total, _) = df.shape();
-        let mut skip = 0;
-        let mut take = file_total;
-        if let Some(offset) = offset {
-            if offset > total + file_total {
-                total += file_total;
-                continue;
-            }
-            if offset > total {
-                skip = offset - total;
-            }
-        }
-        if let Some(limit) = limit {
-            if limit <= output.len() {
-                break;
-            }
-            if limit - output.len() < file_total {
-                take = limit - output.len();
-            }
-        }
-        debug!("total {total} file_total {file_total} skip {skip} take {take}");
-        total += file_total;
-        let columns = WeatherDataColumns {
+    fn from_dataframe(df: &DataFrame) -> Result<Self, Error> {
+        Ok(Self {
             id: df
                 .column("id")?
                 .str()?
                 .into_iter()
                 .filter_map(|i| i.map(Into::into))
-                .skip(skip)
-                .take(take)
-                .collect(),
-            dt: df
-                .column("dt")?
-                .i32()?
-                .into_iter()
-                .flatten()
-                .skip(skip)
-                .take(take)
-                .collect(),
-            created_at: df
-                .column("created_at")?
-                .datetime()?
-                .into_iter()
-                .filter_map(|t| {
-                    t.and_then(|t| DateTime::from_timestamp_millis(t).map(|d| d.naive_utc()))
-                })
-                .skip(skip)
-                .take(take)
                 .collect(),
+            dt: df.column("dt")?.i32()?.into_iter().flatten().collect(),
+            created_at: timestamp_column(df, "created_at")?,
             location_name: df
                 .column("location_name")?
                 .str()?
                 .into_iter()
                 .filter_map(|i| i.map(Into::into))
-                .skip(skip)
-                .take(take)
                 .collect(),
             latitude: df
                 .column("latitude")?
                 .f64()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
                 .collect(),
             longitude: df
                 .column("longitude")?
                 .f64()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
                 .collect(),
             condition: df
                 .column("condition")?
                 .str()?
                 .into_iter()
                 .filter_map(|i| i.map(Into::into))
-                .skip(skip)
-                .take(take)
                 .collect(),
             temperature: df
                 .column("temperature")?
                 .f64()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
                 .collect(),
             temperature_minimum: df
                 .column("temperature_minimum")?
                 .f64()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
                 .collect(),
             temperature_maximum: df
                 .column("temperature_maximum")?
                 .f64()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
                 .collect(),
             pressure: df
                 .column("pressure")?
                 .f64()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
                 .collect(),
             humidity: df
                 .column("humidity")?
                 .i32()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
-                .collect(),
-            visibility: df
-                .column("visibility")?
-                .f64()?
-                .into_iter()
-                .skip(skip)
-                .take(take)
-                .collect(),
-            rain: df
-                .column("rain")?
-                .f64()?
-                .into_iter()
-                .skip(skip)
-                .take(take)
-                .collect(),
-            snow: df
-                .column("snow")?
-                .f64()?
-                .into_iter()
-                .skip(skip)
-                .take(take)
                 .collect(),
+            visibility: df.column("visibility")?.f64()?.into_iter().collect(),
+            rain: df.column("rain")?.f64()?.into_iter().collect(),
+            snow: df.column("snow")?.f64()?.into_iter().collect(),
             wind_speed: df
                 .column("wind_speed")?
                 .f64()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
-                .collect(),
-            wind_direction: df
-                .column("wind_direction")?
-                .f64()?
-                .into_iter()
-                .skip(skip)
-                .take(take)
                 .collect(),
+            wind_direction: df.column("wind_direction")?.f64()?.into_iter().collect(),
             country: df
                 .column("country")?
                 .str()?
                 .into_iter()
                 .filter_map(|i| i.map(Into::into))
-                .skip(skip)
-                .take(take)
-                .collect(),
-            sunrise: df
-                .column("sunrise")?
-                .datetime()?
-                .into_iter()
-                .filter_map(|t| {
-                    t.and_then(|t_| DateTime::from_timestamp_millis(t_).map(|d| d.naive_utc()))
-                })
-                .skip(skip)
-                .take(take)
-                .collect(),
-            sunset: df
-                .column("sunset")?
-                .datetime()?
-                .into_iter()
-                .filter_map(|t| {
-                    t.and_then(|t_| DateTime::from_timestamp_millis(t_).map(|d| d.naive_utc()))
-                })
-                .skip(skip)
-                .take(take)
                 .collect(),
+            sunrise: timestamp_column(df, "sunrise")?,
+            sunset: timestamp_column(df, "sunset")?,
             timezone: df
                 .column("timezone")?
                 .i32()?
                 .into_iter()
                 .flatten()
-                .skip(skip)
-                .take(take)
                 .collect(),
             server: df
                 .column("server")?
                 .str()?
                 .into_iter()
                 .filter_map(|i| i.map(Into::into))
-                .skip(skip)
-                .take(take)
                 .collect(),
-        };
-        let rows = columns.into_weather_data();
-        debug!("rows {}", rows.len());
-        output.extend(rows);
+            dew_point: Vec::new(),
+            heat_index: Vec::new(),
+            wind_chill: Vec::new(),
+            backfilled: match df.column("backfilled") {
+                Ok(column) => column
+                    .bool()?
+                    .into_iter()
+                    .map(Option::unwrap_or_default)
+                    .collect(),
+                Err(_) => vec![false; df.height()],
+            },
+        })
+    }
+
+    /// Rows of the columns, moving every value out instead of cloning
+    fn into_rows(self) -> WeatherDataRows {
+        WeatherDataRows {
+            id: self.id.into_iter(),
+            dt: self.dt.into_iter(),
+            created_at: self.created_at.into_iter(),
+            location_name: self.location_name.into_iter(),
+            latitude: self.latitude.into_iter(),
+            longitude: self.longitude.into_iter(),
+            condition: self.condition.into_iter(),
+            temperature: self.temperature.into_iter(),
+            temperature_minimum: self.temperature_minimum.into_iter(),
+            temperature_maximum: self.temperature_maximum.into_iter(),
+            pressure: self.pressure.into_iter(),
+            humidity: self.humidity.into_iter(),
+            visibility: self.visibility.into_iter(),
+            rain: self.rain.into_iter(),
+            snow: self.snow.into_iter(),
+            wind_speed: self.wind_speed.into_iter(),
+            wind_direction: self.wind_direction.into_iter(),
+            country: self.country.into_iter(),
+            sunrise: self.sunrise.into_iter(),
+            sunset: self.sunset.into_iter(),
+            timezone: self.timezone.into_iter(),
+            server: self.server.into_iter(),
+            backfilled: self.backfilled.into_iter(),
+        }
     }
-    Ok(output)
 }
 
-async fn get_by_name_dates_file(
-    input: &Path,
-    name: Option<&str>,
-    server: Option<&str>,
-    start_date: Option<Date>,
-    end_date: Option<Date>,
-) -> Result<DataFrame, Error> {
-    let args = ScanArgsParquet::default();
-    let mut df = LazyFrame::scan_parquet(input, args)?;
-    if let Some(name) = name {
-        df = df.filter(col("location_name").eq(lit(name)));
+struct WeatherDataRows {
+    id: vec::IntoIter<StackString>,
+    dt: vec::IntoIter<i32>,
+    created_at: vec::IntoIter<i64>,
+    location_name: vec::IntoIter<StackString>,
+    latitude: vec::IntoIter<f64>,
+    longitude: vec::IntoIter<f64>,
+    condition: vec::IntoIter<StackString>,
+    temperature: vec::IntoIter<f64>,
+    temperature_minimum: vec::IntoIter<f64>,
+    temperature_maximum: vec::IntoIter<f64>,
+    pressure: vec::IntoIter<f64>,
+    humidity: vec::IntoIter<i32>,
+    visibility: vec::IntoIter<Option<f64>>,
+    rain: vec::IntoIter<Option<f64>>,
+    snow: vec::IntoIter<Option<f64>>,
+    wind_speed: vec::IntoIter<f64>,
+    wind_direction: vec::IntoIter<Option<f64>>,
+    country: vec::IntoIter<StackString>,
+    sunrise: vec::IntoIter<i64>,
+    sunset: vec::IntoIter<i64>,
+    timezone: vec::IntoIter<i32>,
+    server: vec::IntoIter<StackString>,
+    backfilled: vec::IntoIter<bool>,
+}
+
+impl Iterator for WeatherDataRows {
+    type Item = WeatherDataDB;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let condition = self.condition.next()?;
+        let mut row = WeatherDataDB {
+            id: Uuid::parse_str(&self.id.next()?).expect("Invalid uuid"),
+            dt: self.dt.next()?,
+            created_at: millis_timestamp(self.created_at.next()?).into(),
+            location_name: self.location_name.next()?,
+            latitude: self.latitude.next()?,
+            longitude: self.longitude.next()?,
+            condition_code: condition_code(&condition),
+            condition,
+            temperature: self.temperature.next()?,
+            temperature_minimum: self.temperature_minimum.next()?,
+            temperature_maximum: self.temperature_maximum.next()?,
+            pressure: self.pressure.next()?,
+            humidity: self.humidity.next()?,
+            visibility: self.visibility.next()?,
+            rain: self.rain.next()?,
+            snow: self.snow.next()?,
+            wind_speed: self.wind_speed.next()?,
+            wind_direction: self.wind_direction.next()?,
+            country: self.country.next()?,
+            sunrise: millis_timestamp(self.sunrise.next()?).into(),
+            sunset: millis_timestamp(self.sunset.next()?).into(),
+            timezone: self.timezone.next()?,
+            server: self.server.next()?,
+            dew_point: None,
+            heat_index: None,
+            wind_chill: None,
+            backfilled: self.backfilled.next()?,
+            inserted_at: None,
+        };
+        row.set_derived_metrics();
+        Some(row)
     }
-    if let Some(server) = server {
-        df = df.filter(col("server").eq(lit(server)));
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.id.size_hint()
     }
-    if let Some(start_date) = start_date {
-        let timestamp = PrimitiveDateTime::new(start_date, Time::from_hms(0, 0, 0)?)
-            .assume_utc()
-            .unix_timestamp()
-            * 1000;
-        df = df.filter(
-            col("created_at")
-                .dt()
-                .timestamp(TimeUnit::Milliseconds)
-                .gt_eq(timestamp),
-        );
+}
+
+struct WeatherForecastColumns {
+    id: Vec<StackString>,
+    location_name: Vec<StackString>,
+    server: Vec<StackString>,
+    fetched_at: Vec<i64>,
+    forecast_time: Vec<i64>,
+    temperature: Vec<f64>,
+    humidity: Vec<i32>,
+    pressure: Vec<f64>,
+    wind_speed: Vec<f64>,
+    precipitation: Vec<Option<f64>>,
+    condition: Vec<Option<StackString>>,
+    condition_code: Vec<Option<i32>>,
+    feels_like: Vec<Option<f64>>,
+    temperature_minimum: Vec<Option<f64>>,
+    temperature_maximum: Vec<Option<f64>>,
+    rain: Vec<Option<f64>>,
+    snow: Vec<Option<f64>>,
+    wind_direction: Vec<Option<f64>>,
+    timezone: Vec<Option<i32>>,
+}
+
+impl WeatherForecastColumns {
+    fn new(cap: usize) -> Self {
+        Self {
+            id: Vec::with_capacity(cap),
+            location_name: Vec::with_capacity(cap),
+            server: Vec::with_capacity(cap),
+            fetched_at: Vec::with_capacity(cap),
+            forecast_time: Vec::with_capacity(cap),
+            temperature: Vec::with_capacity(cap),
+            humidity: Vec::with_capacity(cap),
+            pressure: Vec::with_capacity(cap),
+            wind_speed: Vec::with_capacity(cap),
+            precipitation: Vec::with_capacity(cap),
+            condition: Vec::with_capacity(cap),
+            condition_code: Vec::with_capacity(cap),
+            feels_like: Vec::with_capacity(cap),
+            temperature_minimum: Vec::with_capacity(cap),
+            temperature_maximum: Vec::with_capacity(cap),
+            rain: Vec::with_capacity(cap),
+            snow: Vec::with_capacity(cap),
+            wind_direction: Vec::with_capacity(cap),
+            timezone: Vec::with_capacity(cap),
+        }
     }
-    if let Some(end_date) = end_date {
-        let timestamp = PrimitiveDateTime::new(end_date, Time::from_hms(0, 0, 0)?)
-            .assume_utc()
-            .unix_timestamp()
-            * 1000;
-        df = df.filter(
-            col("created_at")
-                .dt()
-                .timestamp(TimeUnit::Milliseconds)
-                .lt_eq(timestamp),
-        );
+
+    fn add_row(&mut self, row: WeatherForecastDB) {
+        self.id.push(format_sstr!("{}", row.id));
+        self.location_name.push(row.location_name);
+        self.server.push(row.server);
+        self.fetched_at
+            .push(timestamp_millis(row.fetched_at.into()));
+        self.forecast_time
+            .push(timestamp_millis(row.forecast_time.into()));
+        self.temperature.push(row.temperature);
+        self.humidity.push(row.humidity);
+        self.pressure.push(row.pressure);
+        self.wind_speed.push(row.wind_speed);
+        self.precipitation.push(row.precipitation);
+        self.condition.push(row.condition);
+        self.condition_code.push(row.condition_code);
+        self.feels_like.push(row.feels_like);
+        self.temperature_minimum.push(row.temperature_minimum);
+        self.temperature_maximum.push(row.temperature_maximum);
+        self.rain.push(row.rain);
+        self.snow.push(row.snow);
+        self.wind_direction.push(row.wind_direction);
+        self.timezone.push(row.timezone);
+    }
+
+    fn get_dataframe(&self) -> Result<DataFrame, Error> {
+        let condition: Vec<_> = self.condition.iter().map(|c| c.as_deref()).collect();
+        let df = dataframe!(
+            "id" => stackstring_to_series(&self.id),
+            "location_name" => stackstring_to_series(&self.location_name),
+            "server" => stackstring_to_series(&self.server),
+            "fetched_at" => &self.fetched_at,
+            "forecast_time" => &self.forecast_time,
+            "temperature" => &self.temperature,
+            "humidity" => &self.humidity,
+            "pressure" => &self.pressure,
+            "wind_speed" => &self.wind_speed,
+            "precipitation" => &self.precipitation,
+            "condition" => condition,
+            "condition_code" => &self.condition_code,
+            "feels_like" => &self.feels_like,
+            "temperature_minimum" => &self.temperature_minimum,
+            "temperature_maximum" => &self.temperature_maximum,
+            "rain" => &self.rain,
+            "snow" => &self.snow,
+            "wind_direction" => &self.wind_direction,
+            "timezone" => &self.timezone,
+        )?;
+        cast_timestamps(df, &FORECAST_TIMESTAMP_COLUMNS)
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ParquetWriteSummary {
+    pub filename: StackString,
+    pub new_shape: (usize, usize),
+    pub existing_shape: Option<(usize, usize)>,
+    pub written_shape: Option<(usize, usize)>,
+}
+
+impl fmt::Display for ParquetWriteSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.new_shape)?;
+        if let Some(shape) = &self.existing_shape {
+            write!(f, "\n{shape:?}")?;
+        }
+        if let Some(shape) = &self.written_shape {
+            write!(f, "\nwrote {} {shape:?}", self.filename)?;
+        }
+        Ok(())
+    }
+}
+
+/// Utc month of the archive, each month is stored in its own parquet file
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveMonth {
+    pub year: i32,
+    pub month: i32,
+    /// rows of the month in the db
+    pub rows: i64,
+}
+
+impl ArchiveMonth {
+    #[must_use]
+    pub fn filename(&self) -> StackString {
+        parquet_filename(self.year, self.month)
+    }
+}
+
+fn parquet_filename(year: i32, month: i32) -> StackString {
+    format_sstr!("weather_data_{year:04}_{month:02}.parquet")
+}
+
+/// Forecasts are archived by utc month of `fetched_at`, in a directory of
+/// their own so they never mix with the observation files
+fn forecast_parquet_filename(year: i32, month: i32) -> StackString {
+    format_sstr!("weather_forecast_{year:04}_{month:02}.parquet")
+}
+
+/// Months of `weather_data` with any rows, starting with the month of
+/// `since`
+///
+/// # Errors
+/// Returns error if db query fails
+pub async fn get_db_months(pool: &PgPool, since: Option<Date>) -> Result<Vec<ArchiveMonth>, Error> {
+    #[derive(FromSqlRow)]
+    struct Wrap {
+        year: i32,
+        month: i32,
+        count: i64,
+    }
+
+    let since = since.map_or(date!(1970 - 01 - 01), |d| d.replace_day(1).unwrap_or(d));
+    let since = PrimitiveDateTime::new(since, Time::MIDNIGHT).assume_utc();
+    let query = query!(
+        r#"
+            SELECT cast(extract(year from created_at at time zone 'utc') as int) as year,
+                   cast(extract(month from created_at at time zone 'utc') as int) as month,
+                   count(*) as count
+            FROM weather_data
+            WHERE created_at >= $since
+            GROUP BY 1,2
+            ORDER BY 1,2
+        "#,
+        since = since,
+    );
+    let conn = pool.get().await?;
+    let rows: Vec<Wrap> = query.fetch(&conn).await?;
+    Ok(rows
+        .into_iter()
+        .map(|Wrap { year, month, count }| ArchiveMonth {
+            year,
+            month,
+            rows: count,
+        })
+        .collect())
+}
+
+async fn get_db_month_dataframe(pool: &PgPool, month: ArchiveMonth) -> Result<DataFrame, Error> {
+    let ArchiveMonth { year, month, rows } = month;
+    let query = query!(
+        r#"
+            SELECT *
+            FROM weather_data
+            WHERE cast(extract(year from created_at at time zone 'utc') as int) = $year
+              AND cast(extract(month from created_at at time zone 'utc') as int) = $month
+        "#,
+        year = year,
+        month = month,
+    );
+    let conn = pool.get().await?;
+    let weather_rows: WeatherDataColumns = query
+        .fetch_streaming::<WeatherDataDB, _>(&conn)
+        .await?
+        .try_fold(
+            WeatherDataColumns::new(rows as usize),
+            |mut acc, row| async move {
+                acc.add_row(row);
+                Ok(acc)
+            },
+        )
+        .await?;
+    weather_rows.get_dataframe()
+}
+
+/// # Errors
+/// Returns error if db query fails
+pub async fn insert_db_into_parquet(
+    pool: &PgPool,
+    outdir: &Path,
+) -> Result<Vec<ParquetWriteSummary>, Error> {
+    let mut output = Vec::new();
+    for month in get_db_months(pool, None).await? {
+        let new_df = get_db_month_dataframe(pool, month).await?;
+        output.push(write_parquet_month(
+            outdir,
+            month.year,
+            month.month,
+            new_df,
+        )?);
+    }
+    Ok(output)
+}
+
+/// Months of `weather_forecast` with any rows, by utc month of `fetched_at`
+///
+/// # Errors
+/// Returns error if db query fails
+pub async fn get_db_forecast_months(pool: &PgPool) -> Result<Vec<ArchiveMonth>, Error> {
+    #[derive(FromSqlRow)]
+    struct Wrap {
+        year: i32,
+        month: i32,
+        count: i64,
+    }
+
+    let query = query!(
+        r#"
+            SELECT cast(extract(year from fetched_at at time zone 'utc') as int) as year,
+                   cast(extract(month from fetched_at at time zone 'utc') as int) as month,
+                   count(*) as count
+            FROM weather_forecast
+            GROUP BY 1,2
+            ORDER BY 1,2
+        "#
+    );
+    let conn = pool.get().await?;
+    let rows: Vec<Wrap> = query.fetch(&conn).await?;
+    Ok(rows
+        .into_iter()
+        .map(|Wrap { year, month, count }| ArchiveMonth {
+            year,
+            month,
+            rows: count,
+        })
+        .collect())
+}
+
+async fn get_db_forecast_month_dataframe(
+    pool: &PgPool,
+    month: ArchiveMonth,
+) -> Result<DataFrame, Error> {
+    let ArchiveMonth { year, month, rows } = month;
+    let query = query!(
+        r#"
+            SELECT *
+            FROM weather_forecast
+            WHERE cast(extract(year from fetched_at at time zone 'utc') as int) = $year
+              AND cast(extract(month from fetched_at at time zone 'utc') as int) = $month
+        "#,
+        year = year,
+        month = month,
+    );
+    let conn = pool.get().await?;
+    let forecast_rows: WeatherForecastColumns = query
+        .fetch_streaming::<WeatherForecastDB, _>(&conn)
+        .await?
+        .try_fold(
+            WeatherForecastColumns::new(rows as usize),
+            |mut acc, row| async move {
+                acc.add_row(row);
+                Ok(acc)
+            },
+        )
+        .await?;
+    forecast_rows.get_dataframe()
+}
+
+/// Merge the recorded forecasts into monthly `weather_forecast` parquet
+/// files in `outdir`
+///
+/// # Errors
+/// Returns error if db query fails
+pub async fn insert_forecasts_into_parquet(
+    pool: &PgPool,
+    outdir: &Path,
+) -> Result<Vec<ParquetWriteSummary>, Error> {
+    let mut output = Vec::new();
+    for month in get_db_forecast_months(pool).await? {
+        let new_df = get_db_forecast_month_dataframe(pool, month).await?;
+        output.push(write_forecast_parquet_month(
+            outdir,
+            month.year,
+            month.month,
+            new_df,
+        )?);
+    }
+    Ok(output)
+}
+
+/// Regenerate the parquet files of `months` from the db, existing files are
+/// replaced rather than merged into, `jobs` months are processed at once and
+/// `on_progress` is called as each month is written
+///
+/// # Errors
+/// Returns error if db query or writing a parquet file fails
+pub async fn rebuild_parquet_from_db(
+    pool: &PgPool,
+    outdir: &Path,
+    months: &[ArchiveMonth],
+    jobs: usize,
+    on_progress: impl Fn(&ParquetWriteSummary),
+) -> Result<Vec<ParquetWriteSummary>, Error> {
+    let mut output: Vec<ParquetWriteSummary> = stream::iter(months.iter().copied())
+        .map(|month| async move {
+            let df = get_db_month_dataframe(pool, month).await?;
+            let outdir = outdir.to_path_buf();
+            spawn_blocking(move || replace_parquet_month(&outdir, month.year, month.month, df))
+                .await?
+        })
+        .buffer_unordered(jobs.max(1))
+        .inspect_ok(|summary| on_progress(summary))
+        .try_collect()
+        .await?;
+    output.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(output)
+}
+
+/// Write `df` as the monthly parquet file without reading the existing file,
+/// through a temporary file so a failed write leaves the old file in place
+fn replace_parquet_month(
+    outdir: &Path,
+    year: i32,
+    month: i32,
+    mut df: DataFrame,
+) -> Result<ParquetWriteSummary, Error> {
+    let filename = parquet_filename(year, month);
+    let file = outdir.join(filename.as_str());
+    let tmp = outdir.join(format_sstr!("{filename}.tmp").as_str());
+    ParquetWriter::new(File::create(&tmp)?).finish(&mut df)?;
+    fs::rename(&tmp, &file)?;
+    Ok(ParquetWriteSummary {
+        filename,
+        new_shape: df.shape(),
+        existing_shape: None,
+        written_shape: Some(df.shape()),
+    })
+}
+
+/// Merge `new_df` into the monthly parquet file, the file is only rewritten
+/// when new rows were added
+fn write_parquet_month(
+    outdir: &Path,
+    year: i32,
+    month: i32,
+    new_df: DataFrame,
+) -> Result<ParquetWriteSummary, Error> {
+    merge_into_parquet_file(outdir, parquet_filename(year, month), new_df, |df| {
+        upgrade_archive(df)
+    })
+}
+
+/// Merge `new_df` into the monthly forecast parquet file
+fn write_forecast_parquet_month(
+    outdir: &Path,
+    year: i32,
+    month: i32,
+    new_df: DataFrame,
+) -> Result<ParquetWriteSummary, Error> {
+    merge_into_parquet_file(
+        outdir,
+        forecast_parquet_filename(year, month),
+        new_df,
+        |df| cast_timestamps(df, &FORECAST_TIMESTAMP_COLUMNS),
+    )
+}
+
+/// Merge `new_df` into `outdir/filename`, `prepare` brings the existing file
+/// to the current columns, the file is only rewritten when new rows were
+/// added
+fn merge_into_parquet_file(
+    outdir: &Path,
+    filename: StackString,
+    new_df: DataFrame,
+    prepare: impl FnOnce(DataFrame) -> Result<DataFrame, Error>,
+) -> Result<ParquetWriteSummary, Error> {
+    let mut summary = ParquetWriteSummary {
+        filename,
+        new_shape: new_df.shape(),
+        existing_shape: None,
+        written_shape: None,
+    };
+
+    let file = outdir.join(&summary.filename);
+    let mut df = if file.exists() {
+        let df = prepare(ParquetReader::new(File::open(&file)?).finish()?)?;
+        summary.existing_shape.replace(df.shape());
+        let existing_entries = df.shape().0;
+        let combined_df =
+            df.vstack(&new_df)?
+                .unique_stable(None, UniqueKeepStrategy::First, None)?;
+        if combined_df.shape().0 == existing_entries {
+            return Ok(summary);
+        }
+        combined_df
+    } else {
+        new_df
+    };
+    ParquetWriter::new(File::create(&file)?).finish(&mut df)?;
+    summary.written_shape.replace(df.shape());
+    Ok(summary)
+}
+
+/// Write rows straight into the monthly parquet files (by utc month of
+/// `created_at`), bypassing the db
+///
+/// # Errors
+/// Returns error if reading or writing a parquet file fails
+pub fn insert_rows_into_parquet(
+    rows: impl IntoIterator<Item = WeatherDataDB>,
+    outdir: &Path,
+) -> Result<Vec<ParquetWriteSummary>, Error> {
+    let mut months: BTreeMap<(i32, i32), WeatherDataColumns> = BTreeMap::new();
+    for row in rows {
+        let created_at = row.created_at.to_offsetdatetime().to_offset(UtcOffset::UTC);
+        let key = (created_at.year(), i32::from(u8::from(created_at.month())));
+        months
+            .entry(key)
+            .or_insert_with(|| WeatherDataColumns::new(0))
+            .add_row(row);
+    }
+    months
+        .into_iter()
+        .map(|((year, month), columns)| {
+            write_parquet_month(outdir, year, month, columns.get_dataframe()?)
+        })
+        .collect()
+}
+
+/// # Errors
+/// Returns error if input/output doesn't exist or cannot be read
+pub fn merge_parquet_files(input: &Path, output: &Path) -> Result<(), Error> {
+    info!("input {:?} output {:?}", input, output);
+    if !input.exists() {
+        return Err(format_err!("input {input:?} does not exist"));
+    }
+    if !output.exists() {
+        return Err(format_err!("output {output:?} does not exist"));
+    }
+    let df0 = upgrade_archive(ParquetReader::new(File::open(input)?).finish()?)?;
+    let entries0 = df0.shape().0;
+    info!("input {entries0}");
+    let df1 = upgrade_archive(ParquetReader::new(File::open(output)?).finish()?)?;
+    let entries1 = df1.shape().0;
+    info!("output {entries1}");
+
+    if entries0 == 0 {
+        return Ok(());
+    }
+
+    let mut df = df1
+        .vstack(&df0)?
+        .unique_stable(None, UniqueKeepStrategy::First, None)?;
+    info!("final {:?}", df.shape());
+    ParquetWriter::new(File::create(output)?).finish(&mut df)?;
+    info!("wrote {:?} {:?}", output, df.shape());
+    Ok(())
+}
+
+/// # Errors
+/// Returns error if file cannot be opened or is not valid parquet
+pub fn get_parquet_row_count(input: &Path) -> Result<usize, Error> {
+    let mut reader = ParquetReader::new(File::open(input)?);
+    reader.num_rows().map_err(Into::into)
+}
+
+/// Row count of a parquet file held in memory, e.g. downloaded from s3
+///
+/// # Errors
+/// Returns error if `data` is not valid parquet
+pub fn get_parquet_bytes_row_count(data: Vec<u8>) -> Result<usize, Error> {
+    let mut reader = ParquetReader::new(Cursor::new(data));
+    reader.num_rows().map_err(Into::into)
+}
+
+/// Rows converted from a parquet frame at a time
+const CHUNK_ROWS: usize = 10_000;
+
+/// Parquet files of an archive read one at a time, each frame converted to
+/// rows in chunks of `CHUNK_ROWS`
+struct ParquetScan<'a> {
+    input_files: vec::IntoIter<PathBuf>,
+    name: Option<&'a str>,
+    server: Option<&'a str>,
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+    /// current frame, next row and number of rows
+    frame: Option<(DataFrame, usize, usize)>,
+    /// rows still to skip
+    offset: usize,
+    /// rows still to return
+    remaining: Option<usize>,
+}
+
+impl ParquetScan<'_> {
+    async fn next_chunk(&mut self) -> Result<Option<WeatherDataRows>, Error> {
+        loop {
+            if self.remaining == Some(0) {
+                return Ok(None);
+            }
+            if let Some((df, position, end)) = &mut self.frame {
+                if *position < *end {
+                    let mut len = (*end - *position).min(CHUNK_ROWS);
+                    if let Some(remaining) = &mut self.remaining {
+                        len = len.min(*remaining);
+                        *remaining -= len;
+                    }
+                    let chunk = df.slice(*position as i64, len);
+                    *position += len;
+                    debug!("chunk {:?}", chunk.shape());
+                    return Ok(Some(
+                        WeatherDataColumns::from_dataframe(&chunk)?.into_rows(),
+                    ));
+                }
+                self.frame = None;
+            }
+            let Some(input_file) = self.input_files.next() else {
+                return Ok(None);
+            };
+            let df = get_by_name_dates_file(
+                &input_file,
+                self.name,
+                self.server,
+                self.start_date,
+                self.end_date,
+            )
+            .await?;
+            debug!("df {input_file:?} {:?}", df.shape());
+            let (file_total, _) = df.shape();
+            let skip = self.offset.min(file_total);
+            self.offset -= skip;
+            self.frame = Some((df, skip, file_total));
+        }
+    }
+}
+
+/// Stream rows of a parquet file, or of every file of a directory, without
+/// materializing the whole date range
+///
+/// # Errors
+/// Returns error if path does not exist
+pub fn stream_by_name_dates<'a>(
+    input: &Path,
+    name: Option<&'a str>,
+    server: Option<&'a str>,
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<impl Stream<Item = Result<WeatherDataDB, Error>> + 'a, Error> {
+    if !input.exists() {
+        return Err(format_err!("Path does not exist"));
+    }
+    let input_files = if input.is_dir() {
+        let v: Result<Vec<_>, Error> = input
+            .read_dir()?
+            .map(|p| p.map(|p| p.path()).map_err(Into::into))
+            .collect();
+        let mut v = v?;
+        v.sort();
+        v
+    } else {
+        vec![input.to_path_buf()]
+    };
+    debug!("{input_files:?}");
+    let scan = ParquetScan {
+        input_files: input_files.into_iter(),
+        name,
+        server,
+        start_date,
+        end_date,
+        frame: None,
+        offset: offset.unwrap_or(0),
+        remaining: limit,
+    };
+    let chunks = stream::try_unfold(scan, |mut scan| async move {
+        Ok(scan.next_chunk().await?.map(|rows| (rows, scan)))
+    });
+    Ok(chunks
+        .map_ok(|rows| stream::iter(rows.map(Ok)))
+        .try_flatten())
+}
+
+/// # Errors
+/// Returns error if path does not exist
+#[instrument(skip_all)]
+pub async fn get_by_name_dates(
+    input: &Path,
+    name: Option<&str>,
+    server: Option<&str>,
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Vec<WeatherDataDB>, Error> {
+    let output: Vec<_> =
+        stream_by_name_dates(input, name, server, start_date, end_date, offset, limit)?
+            .try_collect()
+            .await?;
+    debug!("rows {}", output.len());
+    Ok(output)
+}
+
+/// Normals of one calendar month across the years of the archive
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Schema)]
+#[schema(component = "MonthlyNormal")]
+pub struct MonthlyNormal {
+    #[schema(description = "Month (1-12)")]
+    pub month: u8,
+    #[schema(description = "Years with Observations")]
+    pub years: usize,
+    #[schema(description = "Days with Observations")]
+    pub days: usize,
+    #[schema(description = "Mean Temperature (K)")]
+    pub temperature_mean: f64,
+    #[schema(description = "Mean Daily Low Temperature (K)")]
+    pub temperature_low: f64,
+    #[schema(description = "Mean Daily High Temperature (K)")]
+    pub temperature_high: f64,
+    #[schema(description = "Lowest Temperature (K)")]
+    pub record_low: f64,
+    #[schema(description = "Highest Temperature (K)")]
+    pub record_high: f64,
+    #[schema(description = "Mean Monthly Precipitation (mm)")]
+    pub precipitation: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "Climatology")]
+pub struct Climatology {
+    #[schema(description = "Location Name")]
+    pub location_name: StackString,
+    #[schema(description = "Monthly Normals")]
+    pub months: Vec<MonthlyNormal>,
+}
+
+impl fmt::Display for Climatology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\nmonth years days mean low high record_low record_high precip",
+            self.location_name
+        )?;
+        for m in &self.months {
+            write!(
+                f,
+                "\n{:5} {:5} {:4} {:.1} {:.1} {:.1} {:.1} {:.1} {:.1}",
+                m.month,
+                m.years,
+                m.days,
+                m.temperature_mean,
+                m.temperature_low,
+                m.temperature_high,
+                m.record_low,
+                m.record_high,
+                m.precipitation,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Combine two partial summaries of the same day
+fn merge_daily_summary(day: &mut DailySummary, other: &DailySummary) {
+    let (n0, n1) = (day.observations as f64, other.observations as f64);
+    let mean = |a: f64, b: f64| (a * n0 + b * n1) / (n0 + n1);
+    day.temperature_mean = mean(day.temperature_mean, other.temperature_mean);
+    day.humidity_mean = mean(day.humidity_mean, other.humidity_mean);
+    day.wind_speed_mean = mean(day.wind_speed_mean, other.wind_speed_mean);
+    day.temperature_low = day.temperature_low.min(other.temperature_low);
+    day.temperature_high = day.temperature_high.max(other.temperature_high);
+    day.rain += other.rain;
+    day.snow += other.snow;
+    day.observations += other.observations;
+}
+
+fn add_daily_summaries(
+    days: &mut BTreeMap<Date, DailySummary>,
+    name: &str,
+    rows: &[WeatherDataDB],
+) {
+    for summary in get_daily_summaries(name, rows) {
+        match days.entry(summary.date) {
+            Entry::Vacant(entry) => {
+                entry.insert(summary);
+            }
+            Entry::Occupied(mut entry) => merge_daily_summary(entry.get_mut(), &summary),
+        }
+    }
+}
+
+/// Per calendar month normals of daily summaries: temperatures are averaged
+/// over every day of the month, precipitation totals over the years
+#[must_use]
+pub fn get_monthly_normals<'a>(
+    days: impl IntoIterator<Item = &'a DailySummary>,
+) -> Vec<MonthlyNormal> {
+    #[derive(Default)]
+    struct Accumulator {
+        precipitation: BTreeMap<i32, f64>,
+        days: usize,
+        temperature: f64,
+        observations: usize,
+        low: f64,
+        high: f64,
+        record_low: Option<f64>,
+        record_high: Option<f64>,
+    }
+
+    let mut months: BTreeMap<u8, Accumulator> = BTreeMap::new();
+    for day in days {
+        if day.observations == 0 {
+            continue;
+        }
+        let month = months.entry(day.date.month().into()).or_default();
+        *month.precipitation.entry(day.date.year()).or_default() += day.rain + day.snow;
+        month.days += 1;
+        month.temperature += day.temperature_mean * day.observations as f64;
+        month.observations += day.observations;
+        month.low += day.temperature_low;
+        month.high += day.temperature_high;
+        month.record_low = Some(
+            month
+                .record_low
+                .map_or(day.temperature_low, |t| t.min(day.temperature_low)),
+        );
+        month.record_high = Some(
+            month
+                .record_high
+                .map_or(day.temperature_high, |t| t.max(day.temperature_high)),
+        );
+    }
+    months
+        .into_iter()
+        .map(|(month, acc)| {
+            let days = acc.days as f64;
+            let years = acc.precipitation.len();
+            MonthlyNormal {
+                month,
+                years,
+                days: acc.days,
+                temperature_mean: acc.temperature / acc.observations as f64,
+                temperature_low: acc.low / days,
+                temperature_high: acc.high / days,
+                record_low: acc.record_low.unwrap_or_default(),
+                record_high: acc.record_high.unwrap_or_default(),
+                precipitation: acc.precipitation.values().sum::<f64>() / years as f64,
+            }
+        })
+        .collect()
+}
+
+/// Monthly normals of a location across every year of the parquet archive,
+/// rows are summarized per local day (see `get_daily_summaries`) in chunks so
+/// the archive is never loaded at once
+///
+/// # Errors
+/// Returns error if path does not exist or a file can't be read
+pub async fn get_climatology(
+    input: &Path,
+    name: &str,
+    server: Option<&str>,
+) -> Result<Climatology, Error> {
+    let mut days: BTreeMap<Date, DailySummary> = BTreeMap::new();
+    let mut rows: Vec<WeatherDataDB> = Vec::with_capacity(CHUNK_ROWS);
+    let mut stream = Box::pin(stream_by_name_dates(
+        input,
+        Some(name),
+        server,
+        None,
+        None,
+        None,
+        None,
+    )?);
+    while let Some(row) = stream.try_next().await? {
+        // only split at an hour boundary, precipitation is kept once per hour
+        if rows.len() >= CHUNK_ROWS
+            && rows
+                .last()
+                .is_some_and(|last| last.dt / 3600 != row.dt / 3600)
+        {
+            add_daily_summaries(&mut days, name, &rows);
+            rows.clear();
+        }
+        rows.push(row);
+    }
+    add_daily_summaries(&mut days, name, &rows);
+    Ok(Climatology {
+        location_name: name.into(),
+        months: get_monthly_normals(days.values()),
+    })
+}
+
+/// Default number of standard deviations from the trailing window that flags
+/// an observation
+pub const ANOMALY_THRESHOLD: f64 = 4.0;
+/// Default length of the trailing window (days)
+pub const ANOMALY_WINDOW_DAYS: i64 = 30;
+/// Observations needed in the window before values are checked
+const ANOMALY_MIN_OBSERVATIONS: usize = 24;
+
+#[derive(Clone, Copy)]
+enum AnomalyField {
+    Temperature,
+    Pressure,
+    WindSpeed,
+}
+
+impl AnomalyField {
+    const ALL: [Self; 3] = [Self::Temperature, Self::Pressure, Self::WindSpeed];
+
+    fn value(self, row: &WeatherDataDB) -> f64 {
+        match self {
+            Self::Temperature => row.temperature,
+            Self::Pressure => row.pressure,
+            Self::WindSpeed => row.wind_speed,
+        }
+    }
+
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Temperature => "temperature",
+            Self::Pressure => "pressure",
+            Self::WindSpeed => "wind_speed",
+        }
+    }
+}
+
+/// Running mean and variance of the values of the last `window` seconds,
+/// values are shifted by the first one to limit cancellation
+#[derive(Default)]
+struct RollingWindow {
+    values: VecDeque<(i64, f64)>,
+    shift: Option<f64>,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl RollingWindow {
+    fn expire(&mut self, before: i64) {
+        while let Some(&(timestamp, value)) = self.values.front() {
+            if timestamp >= before {
+                break;
+            }
+            self.values.pop_front();
+            self.sum -= value;
+            self.sum_squares -= value * value;
+        }
+    }
+
+    fn push(&mut self, timestamp: i64, value: f64) {
+        let value = value - *self.shift.get_or_insert(value);
+        self.values.push_back((timestamp, value));
+        self.sum += value;
+        self.sum_squares += value * value;
+    }
+
+    /// Mean and standard deviation, `None` until the window holds enough
+    /// values
+    fn stats(&self) -> Option<(f64, f64)> {
+        let n = self.values.len();
+        if n < ANOMALY_MIN_OBSERVATIONS {
+            return None;
+        }
+        let n = n as f64;
+        let mean = self.sum / n;
+        let variance = (self.sum_squares / n - mean * mean).max(0.0);
+        Some((mean + self.shift.unwrap_or_default(), variance.sqrt()))
+    }
+}
+
+/// Observation deviating from the trailing window of its location
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "HistoryAnomaly")]
+pub struct HistoryAnomaly {
+    #[schema(description = "History Record ID")]
+    pub id: UuidWrapper,
+    #[schema(description = "Created At")]
+    pub created_at: DateTimeType,
+    #[schema(description = "Field (temperature, pressure or wind_speed)")]
+    pub field: StackString,
+    #[schema(description = "Observed Value")]
+    pub value: f64,
+    #[schema(description = "Mean of the Window")]
+    pub mean: f64,
+    #[schema(description = "Standard Deviation of the Window")]
+    pub std_dev: f64,
+    #[schema(description = "Deviation in Standard Deviations")]
+    pub z_score: f64,
+}
+
+/// Checks rows in chronological order against rolling windows of
+/// temperature, pressure and wind speed, flagged values are kept out of the
+/// windows so a glitch doesn't widen the spread
+pub struct AnomalyDetector {
+    window: i64,
+    threshold: f64,
+    windows: [RollingWindow; 3],
+}
+
+impl AnomalyDetector {
+    #[must_use]
+    pub fn new(window_days: i64, threshold: f64) -> Self {
+        Self {
+            window: window_days.max(1) * 86400,
+            threshold,
+            windows: Default::default(),
+        }
+    }
+
+    /// Values of `row` deviating more than `threshold` standard deviations
+    /// from the window preceding it
+    pub fn check(&mut self, row: &WeatherDataDB) -> Vec<HistoryAnomaly> {
+        let timestamp = row.created_at.unix_timestamp();
+        let mut anomalies = Vec::new();
+        for (field, window) in AnomalyField::ALL.into_iter().zip(&mut self.windows) {
+            let value = field.value(row);
+            if !value.is_finite() {
+                continue;
+            }
+            window.expire(timestamp - self.window);
+            if let Some((mean, std_dev)) = window.stats() {
+                let z_score = if std_dev > f64::EPSILON {
+                    (value - mean) / std_dev
+                } else {
+                    0.0
+                };
+                if z_score.abs() > self.threshold {
+                    anomalies.push(HistoryAnomaly {
+                        id: row.id.into(),
+                        created_at: (*row.created_at).into(),
+                        field: field.to_str().into(),
+                        value,
+                        mean,
+                        std_dev,
+                        z_score,
+                    });
+                    continue;
+                }
+            }
+            window.push(timestamp, value);
+        }
+        anomalies
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "AnomalyReport")]
+pub struct AnomalyReport {
+    #[schema(description = "Location Name")]
+    pub location_name: StackString,
+    #[schema(description = "Threshold (standard deviations)")]
+    pub threshold: f64,
+    #[schema(description = "Trailing Window (days)")]
+    pub window_days: i64,
+    #[schema(description = "Anomalies")]
+    pub anomalies: Vec<HistoryAnomaly>,
+}
+
+impl fmt::Display for AnomalyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} anomalies (threshold {}, window {} days)",
+            self.location_name,
+            self.anomalies.len(),
+            self.threshold,
+            self.window_days
+        )?;
+        for a in &self.anomalies {
+            write!(
+                f,
+                "\n{} {} {} {:.2} mean {:.2} std {:.2} z {:.1}",
+                OffsetDateTime::from(a.created_at),
+                a.id,
+                a.field,
+                a.value,
+                a.mean,
+                a.std_dev,
+                a.z_score,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Anomalies of rows created on or after `start_date`, earlier rows only
+/// fill the window
+#[must_use]
+pub fn find_anomalies<'a>(
+    rows: impl IntoIterator<Item = &'a WeatherDataDB>,
+    start_date: Date,
+    window_days: i64,
+    threshold: f64,
+) -> Vec<HistoryAnomaly> {
+    let mut detector = AnomalyDetector::new(window_days, threshold);
+    rows.into_iter()
+        .flat_map(|row| {
+            let anomalies = detector.check(row);
+            if row.created_at.date() < start_date {
+                Vec::new()
+            } else {
+                anomalies
+            }
+        })
+        .collect()
+}
+
+/// Anomalies of a location between `start_date` and `end_date` (inclusive)
+/// in the parquet archive, the window is filled from the `window_days`
+/// before `start_date`
+///
+/// # Errors
+/// Returns error if path does not exist or a file can't be read
+pub async fn get_anomalies(
+    input: &Path,
+    name: &str,
+    server: Option<&str>,
+    start_date: Date,
+    end_date: Date,
+    window_days: i64,
+    threshold: f64,
+) -> Result<AnomalyReport, Error> {
+    let mut detector = AnomalyDetector::new(window_days, threshold);
+    let mut anomalies = Vec::new();
+    // end_date is compared with midnight, include the whole last day
+    let mut stream = Box::pin(stream_by_name_dates(
+        input,
+        Some(name),
+        server,
+        Some(start_date - Duration::days(window_days)),
+        Some(end_date + Duration::days(1)),
+        None,
+        None,
+    )?);
+    while let Some(row) = stream.try_next().await? {
+        let found = detector.check(&row);
+        if row.created_at.date() >= start_date {
+            anomalies.extend(found);
+        }
+    }
+    Ok(AnomalyReport {
+        location_name: name.into(),
+        threshold,
+        window_days,
+        anomalies,
+    })
+}
+
+async fn get_by_name_dates_file(
+    input: &Path,
+    name: Option<&str>,
+    server: Option<&str>,
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+) -> Result<DataFrame, Error> {
+    let args = ScanArgsParquet::default();
+    let mut df = LazyFrame::scan_parquet(input, args)?
+        .with_columns(TIMESTAMP_COLUMNS.map(|name| col(name).cast(timestamp_dtype())));
+    if let Some(name) = name {
+        df = df.filter(col("location_name").eq(lit(name)));
+    }
+    if let Some(server) = server {
+        df = df.filter(col("server").eq(lit(server)));
+    }
+    if let Some(start_date) = start_date {
+        let timestamp = PrimitiveDateTime::new(start_date, Time::from_hms(0, 0, 0)?)
+            .assume_utc()
+            .unix_timestamp()
+            * 1000;
+        df = df.filter(
+            col("created_at")
+                .dt()
+                .timestamp(TimeUnit::Milliseconds)
+                .gt_eq(timestamp),
+        );
+    }
+    if let Some(end_date) = end_date {
+        let timestamp = PrimitiveDateTime::new(end_date, Time::from_hms(0, 0, 0)?)
+            .assume_utc()
+            .unix_timestamp()
+            * 1000;
+        df = df.filter(
+            col("created_at")
+                .dt()
+                .timestamp(TimeUnit::Milliseconds)
+                .lt_eq(timestamp),
+        );
+    }
+    let df = df
+        .sort(
+            ["created_at"],
+            SortMultipleOptions {
+                descending: vec![false],
+                ..SortMultipleOptions::default()
+            },
+        )
+        .collect()?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use futures::TryStreamExt;
+    use polars::{
+        io::SerReader,
+        prelude::{ParquetReader, ParquetWriter},
+    };
+    use std::fs::File;
+    use time::{
+        macros::{date, datetime},
+        Duration, OffsetDateTime,
+    };
+    use uuid::Uuid;
+
+    use crate::{
+        demo::generate_demo_history,
+        model::WeatherForecastDB,
+        polars_analysis::{
+            add_derived_columns, find_anomalies, get_anomalies, get_climatology,
+            get_monthly_normals, get_parquet_bytes_row_count, get_parquet_row_count,
+            insert_rows_into_parquet, replace_parquet_month, stream_by_name_dates, upgrade_archive,
+            write_forecast_parquet_month, WeatherDataColumns, WeatherForecastColumns,
+            ANOMALY_THRESHOLD, ANOMALY_WINDOW_DAYS, CHUNK_ROWS, DERIVED_COLUMNS,
+        },
+        publish::get_daily_summaries,
+    };
+
+    #[tokio::test]
+    async fn test_stream_by_name_dates() -> Result<(), Error> {
+        let rows = generate_demo_history(2, 62, date!(2024 - 03 - 01), 0);
+        let dirname = format!("weather_api_stream_test_{}", std::process::id());
+        let directory = std::env::temp_dir().join(dirname);
+        std::fs::create_dir_all(&directory)?;
+        insert_rows_into_parquet(rows.iter().cloned(), &directory)?;
+
+        let name = "Minneapolis (demo)";
+        let expected: Vec<_> = rows
+            .iter()
+            .filter(|row| row.location_name == name)
+            .collect();
+        let all: Vec<_> =
+            stream_by_name_dates(&directory, Some(name), None, None, None, None, None)?
+                .try_collect()
+                .await?;
+        assert_eq!(all.len(), expected.len());
+        assert!(all.iter().zip(&expected).all(|(a, b)| a.id == b.id
+            && a.location_name == b.location_name
+            && a.created_at == b.created_at
+            && a.sunrise.unix_timestamp() == b.sunrise.unix_timestamp()));
+
+        // offset and limit across chunk and file boundaries
+        let offset = expected.len() / 3;
+        let limit = CHUNK_ROWS.min(expected.len() / 2);
+        let page: Vec<_> = stream_by_name_dates(
+            &directory,
+            Some(name),
+            None,
+            None,
+            None,
+            Some(offset),
+            Some(limit),
+        )?
+        .try_collect()
+        .await?;
+        assert_eq!(page.len(), limit);
+        assert_eq!(page[0].id, expected[offset].id);
+        assert_eq!(page[limit - 1].id, expected[offset + limit - 1].id);
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_parquet_month() -> Result<(), Error> {
+        let rows = generate_demo_history(1, 10, date!(2024 - 03 - 10), 0);
+        let dirname = format!("weather_api_rebuild_test_{}", std::process::id());
+        let directory = std::env::temp_dir().join(dirname);
+        std::fs::create_dir_all(&directory)?;
+        let path = directory.join("weather_data_2024_03.parquet");
+        std::fs::write(&path, b"corrupted")?;
+        assert!(get_parquet_row_count(&path).is_err());
+
+        let mut columns = WeatherDataColumns::new(rows.len());
+        for row in rows.iter().cloned() {
+            columns.add_row(row);
+        }
+        let summary = replace_parquet_month(&directory, 2024, 3, columns.get_dataframe()?)?;
+        assert_eq!(summary.filename, "weather_data_2024_03.parquet");
+        assert_eq!(summary.existing_shape, None);
+        assert_eq!(summary.written_shape.map(|s| s.0), Some(rows.len()));
+        assert_eq!(get_parquet_row_count(&path)?, rows.len());
+        assert_eq!(
+            get_parquet_bytes_row_count(std::fs::read(&path)?)?,
+            rows.len()
+        );
+        assert!(!directory.join("weather_data_2024_03.parquet.tmp").exists());
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_forecast_parquet_month() -> Result<(), Error> {
+        let fetched_at = datetime!(2024-03-10 12:00 UTC);
+        let rows: Vec<_> = (0..40)
+            .map(|i| WeatherForecastDB {
+                id: Uuid::new_v4(),
+                location_name: "Minneapolis".into(),
+                server: "N/A".into(),
+                fetched_at: fetched_at.into(),
+                forecast_time: (fetched_at + Duration::hours(3 * i)).into(),
+                temperature: 280.0 + i as f64 / 4.0,
+                humidity: 60,
+                pressure: 101.3,
+                wind_speed: 4.0,
+                precipitation: None,
+                condition: (i > 0).then(|| "Clouds broken clouds ".into()),
+                condition_code: (i > 0).then_some(803),
+                feels_like: None,
+                temperature_minimum: None,
+                temperature_maximum: None,
+                rain: None,
+                snow: None,
+                wind_direction: Some(180.0),
+                timezone: Some(-18000),
+            })
+            .collect();
+        let dirname = format!("weather_api_forecast_test_{}", std::process::id());
+        let directory = std::env::temp_dir().join(dirname);
+        std::fs::create_dir_all(&directory)?;
+
+        let mut columns = WeatherForecastColumns::new(rows.len());
+        for row in rows.iter().cloned() {
+            columns.add_row(row);
+        }
+        let df = columns.get_dataframe()?;
+        assert_eq!(df.shape(), (40, 19));
+        let summary = write_forecast_parquet_month(&directory, 2024, 3, df.clone())?;
+        assert_eq!(summary.filename, "weather_forecast_2024_03.parquet");
+        assert_eq!(summary.written_shape, Some((40, 19)));
+        let path = directory.join("weather_forecast_2024_03.parquet");
+        assert_eq!(get_parquet_row_count(&path)?, rows.len());
+
+        // the same rows again leave the file untouched
+        let summary = write_forecast_parquet_month(&directory, 2024, 3, df)?;
+        assert_eq!(summary.existing_shape, Some((40, 19)));
+        assert_eq!(summary.written_shape, None);
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_derived_columns() -> Result<(), Error> {
+        let rows = generate_demo_history(1, 10, date!(2024 - 01 - 10), 0);
+        let mut columns = WeatherDataColumns::new(rows.len());
+        for row in rows.iter().cloned() {
+            columns.add_row(row);
+        }
+        let df = columns.get_dataframe()?;
+        assert!(add_derived_columns(df.clone())?.equals_missing(&df));
+
+        let old = df.drop_many(DERIVED_COLUMNS).drop("backfilled")?;
+        assert!(old.column("dew_point").is_err());
+        assert!(upgrade_archive(old)?.equals_missing(&df));
+        assert!(rows.iter().any(|row| row.dew_point.is_some()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backfilled_round_trip() -> Result<(), Error> {
+        let mut rows = generate_demo_history(1, 10, date!(2024 - 04 - 10), 0);
+        for row in rows.iter_mut().step_by(3) {
+            row.backfilled = true;
+        }
+        let dirname = format!("weather_api_backfilled_test_{}", std::process::id());
+        let directory = std::env::temp_dir().join(dirname);
+        std::fs::create_dir_all(&directory)?;
+        insert_rows_into_parquet(rows.iter().cloned(), &directory)?;
+
+        let read: Vec<_> = stream_by_name_dates(&directory, None, None, None, None, None, None)?
+            .try_collect()
+            .await?;
+        assert_eq!(read.len(), rows.len());
+        for (a, b) in read.iter().zip(&rows) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.backfilled, b.backfilled);
+        }
+        assert!(read.iter().any(|row| row.backfilled));
+
+        // archives written before the column existed read as observed
+        let path = directory.join("weather_data_2024_04.parquet");
+        let mut old = ParquetReader::new(File::open(&path)?)
+            .finish()?
+            .drop("backfilled")?;
+        ParquetWriter::new(File::create(&path)?).finish(&mut old)?;
+        let read: Vec<_> = stream_by_name_dates(&directory, None, None, None, None, None, None)?
+            .try_collect()
+            .await?;
+        assert_eq!(read.len(), rows.len());
+        assert!(read.iter().all(|row| !row.backfilled));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_climatology() -> Result<(), Error> {
+        let rows = generate_demo_history(2, 62, date!(2024 - 03 - 01), 0);
+        let dirname = format!("weather_api_climatology_test_{}", std::process::id());
+        let directory = std::env::temp_dir().join(dirname);
+        std::fs::create_dir_all(&directory)?;
+        insert_rows_into_parquet(rows.iter().cloned(), &directory)?;
+
+        let name = "Minneapolis (demo)";
+        let expected: Vec<_> = rows
+            .iter()
+            .filter(|row| row.location_name == name)
+            .cloned()
+            .collect();
+        let days = get_daily_summaries(name, &expected);
+        let normals = get_monthly_normals(&days);
+
+        let climatology = get_climatology(&directory, name, None).await?;
+        assert_eq!(climatology.location_name, name);
+        assert_eq!(climatology.months.len(), normals.len());
+        assert_eq!(
+            climatology.months.iter().map(|m| m.days).sum::<usize>(),
+            days.len()
+        );
+        for (month, normal) in climatology.months.iter().zip(&normals) {
+            assert_eq!(month.month, normal.month);
+            assert_eq!(month.years, 1);
+            assert!((month.temperature_mean - normal.temperature_mean).abs() < 1e-6);
+            assert!((month.precipitation - normal.precipitation).abs() < 1e-6);
+            assert!(month.record_low <= month.temperature_low);
+            assert!(month.temperature_low <= month.temperature_high);
+            assert!(month.temperature_high <= month.record_high);
+        }
+        assert!(climatology.months.iter().any(|m| m.month == 1));
+        assert!(climatology.months.iter().any(|m| m.month == 2));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_anomalies() -> Result<(), Error> {
+        let name = "Minneapolis (demo)";
+        let mut rows: Vec<_> = generate_demo_history(2, 62, date!(2024 - 03 - 01), 0)
+            .into_iter()
+            .filter(|row| row.location_name == name)
+            .collect();
+        // sensor glitches late in the range
+        let temperature_glitch = rows.len() - 100;
+        rows[temperature_glitch].temperature += 40.0;
+        let pressure_glitch = rows.len() - 50;
+        rows[pressure_glitch].pressure = 0.0;
+        let temperature_id = rows[temperature_glitch].id;
+        let pressure_id = rows[pressure_glitch].id;
+
+        let start_date = date!(2024 - 02 - 01);
+        let window_start = start_date - Duration::days(ANOMALY_WINDOW_DAYS);
+        let expected = find_anomalies(
+            rows.iter()
+                .filter(|row| row.created_at.date() >= window_start),
+            start_date,
+            ANOMALY_WINDOW_DAYS,
+            ANOMALY_THRESHOLD,
+        );
+        assert!(expected
+            .iter()
+            .any(|a| a.id == temperature_id && a.field == "temperature"));
+        assert!(expected
+            .iter()
+            .any(|a| a.id == pressure_id && a.field == "pressure"));
+        assert!(expected.iter().all(|a| a.z_score.abs() > ANOMALY_THRESHOLD
+            && OffsetDateTime::from(a.created_at).date() >= start_date));
+
+        let dirname = format!("weather_api_anomalies_test_{}", std::process::id());
+        let directory = std::env::temp_dir().join(dirname);
+        std::fs::create_dir_all(&directory)?;
+        insert_rows_into_parquet(rows.iter().cloned(), &directory)?;
+        let report = get_anomalies(
+            &directory,
+            name,
+            None,
+            start_date,
+            date!(2024 - 03 - 01),
+            ANOMALY_WINDOW_DAYS,
+            ANOMALY_THRESHOLD,
+        )
+        .await?;
+        assert_eq!(report.anomalies.len(), expected.len());
+        assert!(report
+            .anomalies
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| a.id == b.id && a.field == b.field));
+
+        std::fs::remove_dir_all(&directory)?;
+        Ok(())
     }
-    let df = df
-        .sort(
-            ["created_at"],
-            SortMultipleOptions {
-                descending: vec![false],
-                ..SortMultipleOptions::default()
-            },
-        )
-        .collect()?;
-    Ok(df)
 }
@@ -0,0 +1,210 @@
+use rweb::openapi::{ComponentDescriptor, ComponentOrInlineSchema, Entity};
+use serde::{ser, Serialize, Serializer};
+use serde_json::{Map, Value};
+use stack_string::StackString;
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::config::Config;
+
+/// Shortened field names of the minified plot json
+const SHORT_FIELD_NAMES: [(&str, &str); 2] = [("datetime", "t"), ("value", "v")];
+
+/// Decimal places of floats in json responses, `JSON_PRECISION` for every
+/// field unless overridden by name in `JSON_PRECISION_OVERRIDES`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonPrecision {
+    default: Option<u32>,
+    overrides: HashMap<StackString, u32>,
+}
+
+impl JsonPrecision {
+    #[must_use]
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            default: config.json_precision,
+            overrides: config.json_precision_overrides.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.default.is_some() || !self.overrides.is_empty()
+    }
+
+    /// Round the floats of `value`, fields use the precision of their name
+    /// falling back to that of the enclosing field, top level values (e.g.
+    /// plot points) use the precision of `metric`
+    pub fn apply(&self, value: &mut Value, metric: Option<&str>) {
+        let digits = metric
+            .and_then(|metric| self.overrides.get(metric).copied())
+            .or(self.default);
+        self.round(value, digits);
+    }
+
+    fn round(&self, value: &mut Value, digits: Option<u32>) {
+        match value {
+            Value::Number(number) if number.is_f64() => {
+                if let Some(rounded) = digits
+                    .zip(number.as_f64())
+                    .and_then(|(digits, x)| serde_json::Number::from_f64(round_to(x, digits)))
+                {
+                    *number = rounded;
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    self.round(value, digits);
+                }
+            }
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    let digits = self.overrides.get(key.as_str()).copied().or(digits);
+                    self.round(value, digits);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[must_use]
+pub fn round_to(x: f64, digits: u32) -> f64 {
+    let scale = 10_f64.powi(digits as i32);
+    (x * scale).round() / scale
+}
+
+/// Replace the field names of `value` with their `SHORT_FIELD_NAMES`
+pub fn minify_field_names(value: &mut Value) {
+    match value {
+        Value::Array(values) => values.iter_mut().for_each(minify_field_names),
+        Value::Object(fields) => {
+            let minified: Map<String, Value> = std::mem::take(fields)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    minify_field_names(&mut value);
+                    let key = SHORT_FIELD_NAMES
+                        .iter()
+                        .find(|(long, _)| *long == key)
+                        .map_or(key, |(_, short)| (*short).into());
+                    (key, value)
+                })
+                .collect();
+            *fields = minified;
+        }
+        _ => {}
+    }
+}
+
+/// Json payload serialized with `JsonPrecision` and optionally minified field
+/// names, documented as the wrapped type
+pub struct Rounded<T> {
+    data: T,
+    precision: JsonPrecision,
+    metric: Option<&'static str>,
+    minify: bool,
+}
+
+impl<T> Rounded<T> {
+    #[must_use]
+    pub fn new(data: T, precision: JsonPrecision) -> Self {
+        Self {
+            data,
+            precision,
+            metric: None,
+            minify: false,
+        }
+    }
+
+    #[must_use]
+    pub fn with_metric(mut self, metric: &'static str) -> Self {
+        self.metric = Some(metric);
+        self
+    }
+
+    #[must_use]
+    pub fn with_minify(mut self, minify: bool) -> Self {
+        self.minify = minify;
+        self
+    }
+}
+
+impl<T: Serialize> Serialize for Rounded<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if !self.precision.is_enabled() && !self.minify {
+            return self.data.serialize(serializer);
+        }
+        let mut value = serde_json::to_value(&self.data).map_err(ser::Error::custom)?;
+        self.precision.apply(&mut value, self.metric);
+        if self.minify {
+            minify_field_names(&mut value);
+        }
+        value.serialize(serializer)
+    }
+}
+
+impl<T: Entity> Entity for Rounded<T> {
+    fn type_name() -> Cow<'static, str> {
+        T::type_name()
+    }
+
+    fn describe(comp_d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        T::describe(comp_d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use maplit::hashmap;
+    use serde_json::json;
+
+    use crate::precision::{round_to, JsonPrecision, Rounded};
+
+    #[test]
+    fn test_json_precision() -> Result<(), Error> {
+        assert_eq!(round_to(283.149_999_999_999_9, 2), 283.15);
+        assert_eq!(round_to(-0.126, 1), -0.1);
+
+        let precision = JsonPrecision {
+            default: Some(2),
+            overrides: hashmap! {"temperature".into() => 1, "latitude".into() => 4},
+        };
+        let mut value = json!({
+            "temperature": 283.149_999_999_999_9,
+            "pressure": 101.325_000_1,
+            "humidity": 50,
+            "coord": {"latitude": 40.712_776_1},
+        });
+        precision.apply(&mut value, None);
+        assert_eq!(
+            value,
+            json!({
+                "temperature": 283.1,
+                "pressure": 101.33,
+                "humidity": 50,
+                "coord": {"latitude": 40.7128},
+            })
+        );
+
+        let points = json!([{"datetime": "2024-06-01T00:00:00Z", "value": 70.123_456}]);
+        let rounded = Rounded::new(points.clone(), precision.clone()).with_metric("temperature");
+        assert_eq!(
+            serde_json::to_string(&rounded)?,
+            r#"[{"datetime":"2024-06-01T00:00:00Z","value":70.1}]"#
+        );
+        let minified = Rounded::new(points.clone(), precision)
+            .with_metric("precipitation")
+            .with_minify(true);
+        assert_eq!(
+            serde_json::to_string(&minified)?,
+            r#"[{"t":"2024-06-01T00:00:00Z","v":70.12}]"#
+        );
+
+        let unchanged = Rounded::new(points.clone(), JsonPrecision::default());
+        assert_eq!(serde_json::to_value(&unchanged)?, points);
+        Ok(())
+    }
+}
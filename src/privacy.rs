@@ -0,0 +1,118 @@
+use rweb::{filters::log::Info, Schema};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tracing::{info_span, Span};
+
+use crate::config::Config;
+
+/// Text logged in place of a location when `log_locations` is off
+const REDACTED: &str = "<redacted>";
+
+static LOG_LOCATIONS: AtomicBool = AtomicBool::new(true);
+
+/// Zero the host part of `ip`, ipv4 addresses are truncated to /24 and ipv6
+/// addresses to /48
+#[must_use]
+pub fn anonymize_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let [a, b, c, ..] = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+/// Span of each request, the remote address is truncated when
+/// `anonymize_ips` is set
+#[must_use]
+pub fn request_span(info: &Info, anonymize_ips: bool) -> Span {
+    let remote_addr = info.remote_addr().map(|addr| addr.ip()).map(|ip| {
+        if anonymize_ips {
+            anonymize_ip(ip)
+        } else {
+            ip
+        }
+    });
+    info_span!(
+        "request",
+        remote.addr = ?remote_addr,
+        method = %info.method(),
+        path = %info.path(),
+        version = ?info.version(),
+    )
+}
+
+/// Apply `log_locations`, called once at startup
+pub fn set_log_locations(config: &Config) {
+    LOG_LOCATIONS.store(config.log_locations, Ordering::Relaxed);
+}
+
+/// Display a queried location in log messages, `<redacted>` when
+/// `log_locations` is off
+pub struct LogLocation<'a, T>(pub &'a T);
+
+impl<T: fmt::Display> fmt::Display for LogLocation<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if LOG_LOCATIONS.load(Ordering::Relaxed) {
+            self.0.fmt(f)
+        } else {
+            f.write_str(REDACTED)
+        }
+    }
+}
+
+/// What the service does with visitor ips and locations, reported by
+/// `/weather/version`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Schema)]
+#[schema(component = "PrivacyPolicy")]
+pub struct PrivacyPolicy {
+    #[schema(description = "Client IPs Truncated to /24 (IPv4) or /48 (IPv6) in Logs")]
+    pub anonymize_ips: bool,
+    #[schema(description = "Visitor Location Looked Up From IP")]
+    pub geoip_enabled: bool,
+    #[schema(description = "Requests With DNT or Sec-GPC Skip the GeoIP Lookup")]
+    pub geoip_honors_do_not_track: bool,
+    #[schema(description = "Weather of GeoIP Locations Stored in the Database")]
+    pub geoip_persist: bool,
+    #[schema(description = "Queried Locations Written to Logs")]
+    pub log_locations: bool,
+}
+
+impl PrivacyPolicy {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            anonymize_ips: config.anonymize_ips,
+            geoip_enabled: cfg!(feature = "geoip") && config.geoip_database.is_some(),
+            geoip_honors_do_not_track: true,
+            geoip_persist: config.geoip_persist,
+            log_locations: config.log_locations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use crate::privacy::anonymize_ip;
+
+    #[test]
+    fn test_anonymize_ip() {
+        let ip: IpAddr = "192.168.1.57".parse().unwrap();
+        assert_eq!(anonymize_ip(ip), "192.168.1.0".parse::<IpAddr>().unwrap());
+        let ip: IpAddr = "2001:db8:85a3:8d3:1319:8a2e:370:7348".parse().unwrap();
+        assert_eq!(
+            anonymize_ip(ip),
+            "2001:db8:85a3::".parse::<IpAddr>().unwrap()
+        );
+    }
+}
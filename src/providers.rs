@@ -0,0 +1,489 @@
+pub mod blend;
+pub mod metno;
+pub mod nws;
+pub mod open_meteo;
+
+use anyhow::{format_err, Error};
+use log::warn;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use stack_string::StackString;
+use std::{
+    collections::{HashMap, VecDeque},
+    f64::consts::PI,
+    future::Future,
+    time::{Duration, Instant},
+};
+use time::{Date, OffsetDateTime};
+use tracing::{info_span, Instrument};
+
+use weather_util_rust::{
+    weather_api::{WeatherApi, WeatherLocation},
+    weather_data::WeatherData,
+    weather_forecast::WeatherForecast,
+};
+
+use crate::{
+    config::Config,
+    metrics::record_upstream_call,
+    providers::{metno::MetNoApi, nws::NwsApi, open_meteo::OpenMeteoApi},
+};
+
+pub const KELVIN_OFFSET: f64 = 273.15;
+/// Number of 3 hour forecast entries returned by openweathermap
+pub const FORECAST_ENTRIES: usize = 40;
+/// Number of recent requests used to score each provider
+const HEALTH_WINDOW: usize = 20;
+/// Providers scoring below this are tried last
+pub const MIN_HEALTH_SCORE: f64 = 0.5;
+/// Average latency at which the health score is halved
+const LATENCY_SCALE_SECONDS: f64 = 5.0;
+
+/// Outcome (success, latency in seconds) of recent requests per provider
+static PROVIDER_HEALTH: Lazy<Mutex<HashMap<&'static str, VecDeque<(bool, f64)>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Source of current conditions and forecasts, responses are mapped into the
+/// openweathermap `WeatherData` / `WeatherForecast` types.
+pub trait WeatherProvider {
+    fn get_name(&self) -> &'static str;
+
+    fn get_weather_data(
+        &self,
+        loc: &WeatherLocation,
+    ) -> impl Future<Output = Result<WeatherData, Error>> + Send;
+
+    fn get_weather_forecast(
+        &self,
+        loc: &WeatherLocation,
+    ) -> impl Future<Output = Result<WeatherForecast, Error>> + Send;
+}
+
+impl WeatherProvider for WeatherApi {
+    fn get_name(&self) -> &'static str {
+        "openweathermap"
+    }
+
+    async fn get_weather_data(&self, loc: &WeatherLocation) -> Result<WeatherData, Error> {
+        WeatherApi::get_weather_data(self, loc)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn get_weather_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error> {
+        WeatherApi::get_weather_forecast(self, loc)
+            .await
+            .map_err(Into::into)
+    }
+}
+
+/// Backend selected with `WEATHER_PROVIDER`
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WeatherProviderType {
+    #[default]
+    #[serde(rename = "openweathermap")]
+    OpenWeatherMap,
+    #[serde(rename = "open-meteo")]
+    OpenMeteo,
+    #[serde(rename = "nws")]
+    Nws,
+    #[serde(rename = "metno")]
+    MetNo,
+}
+
+/// Dispatch over the available providers, every provider other than
+/// openweathermap requires a latitude / longitude location.
+#[derive(Clone)]
+pub enum Provider {
+    OpenWeatherMap(WeatherApi),
+    OpenMeteo(OpenMeteoApi),
+    Nws(NwsApi),
+    MetNo(MetNoApi),
+}
+
+impl Provider {
+    #[must_use]
+    pub fn new(provider_type: WeatherProviderType, config: &Config, api: &WeatherApi) -> Self {
+        match provider_type {
+            WeatherProviderType::OpenWeatherMap => Self::OpenWeatherMap(api.clone()),
+            WeatherProviderType::OpenMeteo => Self::OpenMeteo(OpenMeteoApi::new()),
+            WeatherProviderType::Nws => Self::Nws(NwsApi::new(config)),
+            WeatherProviderType::MetNo => Self::MetNo(MetNoApi::new(config)),
+        }
+    }
+}
+
+impl WeatherProvider for Provider {
+    fn get_name(&self) -> &'static str {
+        match self {
+            Self::OpenWeatherMap(p) => WeatherProvider::get_name(p),
+            Self::OpenMeteo(p) => p.get_name(),
+            Self::Nws(p) => p.get_name(),
+            Self::MetNo(p) => p.get_name(),
+        }
+    }
+
+    async fn get_weather_data(&self, loc: &WeatherLocation) -> Result<WeatherData, Error> {
+        match self {
+            Self::OpenWeatherMap(p) => WeatherProvider::get_weather_data(p, loc).await,
+            Self::OpenMeteo(p) => p.get_weather_data(loc).await,
+            Self::Nws(p) => p.get_weather_data(loc).await,
+            Self::MetNo(p) => p.get_weather_data(loc).await,
+        }
+    }
+
+    async fn get_weather_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error> {
+        match self {
+            Self::OpenWeatherMap(p) => WeatherProvider::get_weather_forecast(p, loc).await,
+            Self::OpenMeteo(p) => p.get_weather_forecast(loc).await,
+            Self::Nws(p) => p.get_weather_forecast(loc).await,
+            Self::MetNo(p) => p.get_weather_forecast(loc).await,
+        }
+    }
+}
+
+/// Health of a provider over its most recent requests
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Schema)]
+#[schema(component = "ProviderHealth")]
+pub struct ProviderHealth {
+    #[schema(description = "Provider Name")]
+    pub name: StackString,
+    #[schema(description = "Recent Requests")]
+    pub requests: usize,
+    #[schema(description = "Recent Successful Requests")]
+    pub successes: usize,
+    #[schema(description = "Average Latency (ms)")]
+    pub average_latency_ms: f64,
+    #[schema(description = "Health Score (0 to 1)")]
+    pub score: f64,
+}
+
+impl ProviderHealth {
+    fn new(name: &str, outcomes: &VecDeque<(bool, f64)>) -> Self {
+        let requests = outcomes.len();
+        let successes = outcomes.iter().filter(|(success, _)| *success).count();
+        let average_latency = if requests == 0 {
+            0.0
+        } else {
+            outcomes.iter().map(|(_, latency)| latency).sum::<f64>() / requests as f64
+        };
+        let score = if requests == 0 {
+            1.0
+        } else {
+            let success_rate = successes as f64 / requests as f64;
+            success_rate / (1.0 + average_latency / LATENCY_SCALE_SECONDS)
+        };
+        Self {
+            name: name.into(),
+            requests,
+            successes,
+            average_latency_ms: average_latency * 1000.0,
+            score,
+        }
+    }
+}
+
+fn record_outcome(name: &'static str, success: bool, latency: Duration) {
+    let mut health = PROVIDER_HEALTH.lock();
+    let outcomes = health.entry(name).or_default();
+    if outcomes.len() >= HEALTH_WINDOW {
+        outcomes.pop_front();
+    }
+    outcomes.push_back((success, latency.as_secs_f64()));
+}
+
+/// Health score of a provider, providers without recent requests score 1.0
+#[must_use]
+pub fn get_health_score(name: &str) -> f64 {
+    PROVIDER_HEALTH
+        .lock()
+        .get(name)
+        .map_or(1.0, |outcomes| ProviderHealth::new(name, outcomes).score)
+}
+
+/// Health of every provider that has served a request, sorted by name
+#[must_use]
+pub fn get_provider_health() -> Vec<ProviderHealth> {
+    let mut health: Vec<_> = PROVIDER_HEALTH
+        .lock()
+        .iter()
+        .map(|(name, outcomes)| ProviderHealth::new(name, outcomes))
+        .collect();
+    health.sort_by(|a, b| a.name.cmp(&b.name));
+    health
+}
+
+/// Primary provider followed by the configured fallbacks, each request is
+/// tried against healthy providers (in order) before unhealthy ones.
+#[derive(Clone)]
+pub struct ProviderChain {
+    providers: Vec<Provider>,
+}
+
+impl ProviderChain {
+    /// Chain of `primary`, `weather_provider_fallbacks` and met.no when
+    /// `metno_fallback` is set
+    #[must_use]
+    pub fn new(primary: WeatherProviderType, config: &Config, api: &WeatherApi) -> Self {
+        let mut provider_types = vec![primary];
+        let fallbacks = config
+            .weather_provider_fallbacks
+            .iter()
+            .copied()
+            .chain(config.metno_fallback.then_some(WeatherProviderType::MetNo));
+        for provider_type in fallbacks {
+            if !provider_types.contains(&provider_type) {
+                provider_types.push(provider_type);
+            }
+        }
+        let providers = provider_types
+            .into_iter()
+            .map(|t| Provider::new(t, config, api))
+            .collect();
+        Self { providers }
+    }
+
+    #[must_use]
+    pub fn get_providers(&self) -> &[Provider] {
+        &self.providers
+    }
+
+    fn ordered(&self) -> Vec<&Provider> {
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = self
+            .providers
+            .iter()
+            .partition(|p| get_health_score(p.get_name()) >= MIN_HEALTH_SCORE);
+        healthy.into_iter().chain(unhealthy).collect()
+    }
+}
+
+impl WeatherProvider for ProviderChain {
+    fn get_name(&self) -> &'static str {
+        self.providers
+            .first()
+            .map_or("openweathermap", WeatherProvider::get_name)
+    }
+
+    async fn get_weather_data(&self, loc: &WeatherLocation) -> Result<WeatherData, Error> {
+        let mut last_error = None;
+        for provider in self.ordered() {
+            let start = Instant::now();
+            let name = provider.get_name();
+            let span = info_span!("upstream", provider = name, endpoint = "weather", %loc);
+            match provider.get_weather_data(loc).instrument(span).await {
+                Ok(weather_data) => {
+                    record_outcome(provider.get_name(), true, start.elapsed());
+                    record_upstream_call(provider.get_name(), "weather", true);
+                    return Ok(weather_data);
+                }
+                Err(e) => {
+                    record_outcome(provider.get_name(), false, start.elapsed());
+                    record_upstream_call(provider.get_name(), "weather", false);
+                    warn!("{} failed for {loc}: {e}", provider.get_name());
+                    last_error.replace(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| format_err!("No weather providers configured")))
+    }
+
+    async fn get_weather_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error> {
+        let mut last_error = None;
+        for provider in self.ordered() {
+            let start = Instant::now();
+            let name = provider.get_name();
+            let span = info_span!("upstream", provider = name, endpoint = "forecast", %loc);
+            match provider.get_weather_forecast(loc).instrument(span).await {
+                Ok(forecast) => {
+                    record_outcome(provider.get_name(), true, start.elapsed());
+                    record_upstream_call(provider.get_name(), "forecast", true);
+                    return Ok(forecast);
+                }
+                Err(e) => {
+                    record_outcome(provider.get_name(), false, start.elapsed());
+                    record_upstream_call(provider.get_name(), "forecast", false);
+                    warn!("{} forecast failed for {loc}: {e}", provider.get_name());
+                    last_error.replace(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| format_err!("No weather providers configured")))
+    }
+}
+
+/// # Errors
+/// Return error if the location hasn't been resolved to latitude / longitude
+pub fn get_lat_lon(loc: &WeatherLocation) -> Result<(f64, f64), Error> {
+    if let WeatherLocation::LatLon {
+        latitude,
+        longitude,
+    } = loc
+    {
+        Ok(((*latitude).into(), (*longitude).into()))
+    } else {
+        Err(format_err!("{loc} is not a latitude / longitude location"))
+    }
+}
+
+/// Altitude of the sun's center at sunrise and sunset, refraction and
+/// semi-diameter (degrees)
+pub const SUNRISE_ALTITUDE: f64 = -0.833;
+
+/// Times the sun crosses an altitude on a given day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SunCrossings {
+    /// rising and setting crossing (UTC)
+    RiseSet(OffsetDateTime, OffsetDateTime),
+    /// the sun stays above the altitude all day
+    AlwaysAbove,
+    /// the sun stays below the altitude all day
+    AlwaysBelow,
+}
+
+/// Sunrise and sunset (UTC) from the sunrise equation, `None` during polar
+/// day or night.
+#[must_use]
+pub fn get_sunrise_sunset(
+    date: Date,
+    latitude: f64,
+    longitude: f64,
+) -> Option<(OffsetDateTime, OffsetDateTime)> {
+    match get_sun_crossings(date, latitude, longitude, SUNRISE_ALTITUDE) {
+        SunCrossings::RiseSet(rise, set) => Some((rise, set)),
+        SunCrossings::AlwaysAbove | SunCrossings::AlwaysBelow => None,
+    }
+}
+
+/// Times the sun's center crosses `altitude` (degrees), from the sunrise
+/// equation, e.g. -6 for civil dawn and dusk
+#[must_use]
+pub fn get_sun_crossings(date: Date, latitude: f64, longitude: f64, altitude: f64) -> SunCrossings {
+    let midnight = date.midnight().assume_utc();
+    let julian_day = midnight.unix_timestamp() as f64 / 86400.0 + 2_440_587.5;
+    let n = (julian_day - 2_451_545.0 + 0.0008).ceil();
+    let mean_solar_time = n - longitude / 360.0;
+    let anomaly = (357.5291 + 0.985_600_28 * mean_solar_time).rem_euclid(360.0);
+    let m = anomaly.to_radians();
+    let center = 1.9148 * m.sin() + 0.0200 * (2.0 * m).sin() + 0.0003 * (3.0 * m).sin();
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+    let lambda = ecliptic_longitude.to_radians();
+    let transit = 2_451_545.0 + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2.0 * lambda).sin();
+    let declination = (lambda.sin() * 23.4397_f64.to_radians().sin()).asin();
+    let phi = latitude.to_radians();
+    let cos_hour_angle = (altitude.to_radians().sin() - phi.sin() * declination.sin())
+        / (phi.cos() * declination.cos());
+    if cos_hour_angle < -1.0 {
+        return SunCrossings::AlwaysAbove;
+    } else if cos_hour_angle > 1.0 {
+        return SunCrossings::AlwaysBelow;
+    }
+    let hour_angle = cos_hour_angle.acos() * 180.0 / PI;
+    let to_datetime = |julian: f64| {
+        OffsetDateTime::from_unix_timestamp(((julian - 2_440_587.5) * 86400.0).round() as i64)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+    };
+    SunCrossings::RiseSet(
+        to_datetime(transit - hour_angle / 360.0),
+        to_datetime(transit + hour_angle / 360.0),
+    )
+}
+
+/// Unix timestamps of sunrise and sunset, midnight during polar day or night
+#[must_use]
+pub fn sun_times(date: Date, latitude: f64, longitude: f64) -> (i64, i64) {
+    get_sunrise_sunset(date, latitude, longitude).map_or_else(
+        || {
+            let midnight = date.midnight().assume_utc().unix_timestamp();
+            (midnight, midnight)
+        },
+        |(rise, set)| (rise.unix_timestamp(), set.unix_timestamp()),
+    )
+}
+
+/// Approximate the utc offset from the longitude, for providers that only
+/// report UTC
+#[must_use]
+pub fn approximate_timezone(longitude: f64) -> i32 {
+    (longitude / 15.0).round() as i32 * 3600
+}
+
+#[must_use]
+pub fn weather_condition_json(
+    id: usize,
+    main: &str,
+    description: &str,
+    icon: &str,
+) -> serde_json::Value {
+    json!([{"id": id, "main": main, "description": description, "icon": icon}])
+}
+
+/// `rain` and `snow` objects keyed by `1h` or `3h`
+#[must_use]
+pub fn precipitation_json(
+    is_snow: bool,
+    key: &str,
+    amount: f64,
+) -> (serde_json::Value, serde_json::Value) {
+    if amount <= 0.0 {
+        (serde_json::Value::Null, serde_json::Value::Null)
+    } else if is_snow {
+        (serde_json::Value::Null, json!({key: amount}))
+    } else {
+        (json!({key: amount}), serde_json::Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::{date, datetime};
+
+    use std::collections::VecDeque;
+
+    use crate::providers::{approximate_timezone, get_sunrise_sunset, ProviderHealth};
+
+    #[test]
+    fn test_get_sunrise_sunset() {
+        // Minneapolis summer solstice, sunrise 5:26 CDT, sunset 21:03 CDT
+        let (sunrise, sunset) = get_sunrise_sunset(date!(2024 - 06 - 21), 44.98, -93.27).unwrap();
+        assert!(
+            (sunrise - datetime!(2024-06-21 10:26 UTC))
+                .whole_minutes()
+                .abs()
+                <= 5
+        );
+        assert!(
+            (sunset - datetime!(2024-06-22 02:03 UTC))
+                .whole_minutes()
+                .abs()
+                <= 5
+        );
+        // polar night
+        assert!(get_sunrise_sunset(date!(2024 - 12 - 21), 80.0, 15.0).is_none());
+    }
+
+    #[test]
+    fn test_approximate_timezone() {
+        assert_eq!(approximate_timezone(-93.27), -6 * 3600);
+        assert_eq!(approximate_timezone(10.75), 3600);
+    }
+
+    #[test]
+    fn test_provider_health() {
+        let health = ProviderHealth::new("test", &VecDeque::new());
+        assert!((health.score - 1.0).abs() < 1e-9);
+
+        let outcomes = VecDeque::from(vec![(true, 0.0), (true, 0.0), (false, 0.0), (true, 0.0)]);
+        let health = ProviderHealth::new("test", &outcomes);
+        assert_eq!((health.requests, health.successes), (4, 3));
+        assert!((health.score - 0.75).abs() < 1e-9);
+
+        // slow responses lower the score
+        let outcomes = VecDeque::from(vec![(true, 5.0), (true, 5.0)]);
+        let health = ProviderHealth::new("test", &outcomes);
+        assert!((health.score - 0.5).abs() < 1e-9);
+        assert!((health.average_latency_ms - 5000.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,142 @@
+use rweb::Schema;
+use rweb_helper::DateTimeType;
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+
+use weather_util_rust::weather_forecast::{ForecastEntry, WeatherForecast};
+
+/// Consensus of the provider forecasts for a single timestep
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "BlendedForecastEntry")]
+pub struct BlendedForecastEntry {
+    #[schema(description = "Forecasted DateTime")]
+    pub dt: DateTimeType,
+    #[schema(description = "Providers Forecasting this Timestep")]
+    pub providers: Vec<StackString>,
+    #[schema(description = "Mean Temperature (K)")]
+    pub temperature_mean: f64,
+    #[schema(description = "Minimum Temperature (K)")]
+    pub temperature_min: f64,
+    #[schema(description = "Maximum Temperature (K)")]
+    pub temperature_max: f64,
+    #[schema(description = "Temperature Spread, max - min (K)")]
+    pub temperature_spread: f64,
+    #[schema(description = "Fraction of Providers Forecasting Precipitation")]
+    pub precipitation_probability: f64,
+    #[schema(description = "Mean Precipitation (mm per 3 hours)")]
+    pub precipitation_mean: f64,
+    #[schema(description = "Precipitation Spread, max - min (mm)")]
+    pub precipitation_spread: f64,
+}
+
+fn get_precipitation(entry: &ForecastEntry) -> f64 {
+    let rain = entry
+        .rain
+        .as_ref()
+        .and_then(|r| r.three_hour)
+        .unwrap_or_default();
+    let snow = entry
+        .snow
+        .as_ref()
+        .and_then(|s| s.three_hour)
+        .unwrap_or_default();
+    (rain + snow).millimeters()
+}
+
+fn min_max(values: &[f64]) -> (f64, f64) {
+    values
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+            (min.min(*v), max.max(*v))
+        })
+}
+
+/// Align the provider forecasts on their timesteps and summarize each one,
+/// timesteps only present in a single provider's forecast are kept.
+#[must_use]
+pub fn blend_forecasts(forecasts: &[(&str, WeatherForecast)]) -> Vec<BlendedForecastEntry> {
+    let mut timesteps: BTreeMap<OffsetDateTime, Vec<(&str, &ForecastEntry)>> = BTreeMap::new();
+    for (name, forecast) in forecasts {
+        for entry in &forecast.list {
+            timesteps.entry(entry.dt).or_default().push((*name, entry));
+        }
+    }
+    timesteps
+        .into_iter()
+        .map(|(dt, entries)| {
+            let count = entries.len() as f64;
+            let temperatures: Vec<_> = entries.iter().map(|(_, e)| e.main.temp.kelvin()).collect();
+            let precipitation: Vec<_> = entries.iter().map(|(_, e)| get_precipitation(e)).collect();
+            let (temperature_min, temperature_max) = min_max(&temperatures);
+            let (precipitation_min, precipitation_max) = min_max(&precipitation);
+            BlendedForecastEntry {
+                dt: dt.into(),
+                providers: entries.iter().map(|(name, _)| (*name).into()).collect(),
+                temperature_mean: temperatures.iter().sum::<f64>() / count,
+                temperature_min,
+                temperature_max,
+                temperature_spread: temperature_max - temperature_min,
+                precipitation_probability: precipitation.iter().filter(|p| **p > 0.0).count()
+                    as f64
+                    / count,
+                precipitation_mean: precipitation.iter().sum::<f64>() / count,
+                precipitation_spread: precipitation_max - precipitation_min,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use serde_json::json;
+
+    use weather_util_rust::weather_forecast::WeatherForecast;
+
+    use crate::providers::blend::blend_forecasts;
+
+    fn forecast(entries: &[(i64, f64, f64)]) -> Result<WeatherForecast, Error> {
+        let list: Vec<_> = entries
+            .iter()
+            .map(|(dt, temp, rain)| {
+                json!({
+                    "dt": dt,
+                    "main": {"temp": temp, "feels_like": temp, "temp_min": temp, "temp_max": temp,
+                        "pressure": 1013.0, "sea_level": 1013.0, "grnd_level": 1013.0,
+                        "humidity": 50},
+                    "weather": [{"id": 800, "main": "Clear", "description": "clear sky",
+                        "icon": "01d"}],
+                    "rain": if *rain > 0.0 { json!({"3h": rain}) } else { json!(null) },
+                })
+            })
+            .collect();
+        let value = json!({
+            "list": list,
+            "city": {"timezone": 0, "sunrise": 0, "sunset": 0},
+        });
+        serde_json::from_value(value).map_err(Into::into)
+    }
+
+    #[test]
+    fn test_blend_forecasts() -> Result<(), Error> {
+        let forecasts = [
+            (
+                "openweathermap",
+                forecast(&[(1_717_243_200, 290.0, 1.0), (1_717_254_000, 292.0, 0.0)])?,
+            ),
+            ("open-meteo", forecast(&[(1_717_243_200, 294.0, 0.0)])?),
+        ];
+        let blend = blend_forecasts(&forecasts);
+        assert_eq!(blend.len(), 2);
+        assert_eq!(blend[0].providers.len(), 2);
+        assert!((blend[0].temperature_mean - 292.0).abs() < 1e-6);
+        assert!((blend[0].temperature_spread - 4.0).abs() < 1e-6);
+        assert!((blend[0].precipitation_probability - 0.5).abs() < 1e-6);
+        assert!((blend[0].precipitation_mean - 0.5).abs() < 1e-6);
+        assert_eq!(blend[1].providers.len(), 1);
+        assert!(blend[1].temperature_spread.abs() < 1e-6);
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+use anyhow::{format_err, Error};
+use log::debug;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use reqwest::{
+    header::{EXPIRES, IF_MODIFIED_SINCE, LAST_MODIFIED, USER_AGENT},
+    Client, StatusCode,
+};
+use serde::Deserialize;
+use serde_json::json;
+use stack_string::{format_sstr, StackString};
+use std::{collections::HashMap, sync::Arc};
+use time::{
+    format_description::FormatItem, macros::format_description, Duration, OffsetDateTime,
+    PrimitiveDateTime,
+};
+
+use weather_util_rust::{
+    weather_api::WeatherLocation, weather_data::WeatherData, weather_forecast::WeatherForecast,
+};
+
+use crate::{
+    config::Config,
+    providers::{
+        approximate_timezone, get_lat_lon, precipitation_json, sun_times, weather_condition_json,
+        WeatherProvider, FORECAST_ENTRIES, KELVIN_OFFSET,
+    },
+};
+
+const METNO_URL: &str = "https://api.met.no/weatherapi/locationforecast/2.0/compact";
+
+static HTTP_DATE_FORMAT: &[FormatItem<'static>] = format_description!(
+    "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+);
+
+static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+
+/// Responses are kept until their `Expires` header passes, after which the
+/// `Last-Modified` value is sent back as `If-Modified-Since`.
+static METNO_CACHE: Lazy<Mutex<HashMap<StackString, CachedResponse>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Clone)]
+struct CachedResponse {
+    expires: OffsetDateTime,
+    last_modified: Option<StackString>,
+    response: Arc<MetNoResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoResponse {
+    properties: MetNoProperties,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoProperties {
+    timeseries: Vec<MetNoTimeStep>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoTimeStep {
+    #[serde(with = "time::serde::rfc3339")]
+    time: OffsetDateTime,
+    data: MetNoData,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoData {
+    instant: MetNoInstant,
+    next_1_hours: Option<MetNoPeriod>,
+    next_6_hours: Option<MetNoPeriod>,
+    next_12_hours: Option<MetNoPeriod>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoInstant {
+    details: MetNoInstantDetails,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct MetNoInstantDetails {
+    /// Celsius
+    air_temperature: f64,
+    /// hPa
+    air_pressure_at_sea_level: f64,
+    /// percent
+    relative_humidity: f64,
+    /// m/s
+    wind_speed: f64,
+    /// degrees
+    wind_from_direction: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoPeriod {
+    summary: Option<MetNoSummary>,
+    details: Option<MetNoPeriodDetails>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoSummary {
+    symbol_code: StackString,
+}
+
+#[derive(Deserialize, Debug)]
+struct MetNoPeriodDetails {
+    /// mm
+    precipitation_amount: Option<f64>,
+}
+
+impl MetNoPeriod {
+    fn symbol_code(&self) -> Option<&str> {
+        self.summary.as_ref().map(|s| s.symbol_code.as_str())
+    }
+
+    fn precipitation(&self) -> f64 {
+        self.details
+            .as_ref()
+            .and_then(|d| d.precipitation_amount)
+            .unwrap_or(0.0)
+    }
+}
+
+impl MetNoData {
+    fn symbol_code(&self) -> &str {
+        [&self.next_1_hours, &self.next_6_hours, &self.next_12_hours]
+            .iter()
+            .find_map(|p| p.as_ref().and_then(MetNoPeriod::symbol_code))
+            .unwrap_or("clearsky_day")
+    }
+}
+
+/// Map a met.no symbol code (e.g. `lightrainshowers_day`) onto the
+/// openweathermap condition id, main, description and icon.
+#[must_use]
+pub fn symbol_to_condition(symbol_code: &str) -> (usize, &'static str, &'static str, StackString) {
+    let (symbol, period) = symbol_code.split_once('_').unwrap_or((symbol_code, "day"));
+    let (id, main, description, icon) = if symbol.contains("thunder") {
+        (211, "Thunderstorm", "thunderstorm", "11")
+    } else if symbol.contains("sleet") {
+        (611, "Snow", "sleet", "13")
+    } else if symbol.contains("snow") {
+        if symbol.starts_with("light") {
+            (600, "Snow", "light snow", "13")
+        } else if symbol.starts_with("heavy") {
+            (602, "Snow", "heavy snow", "13")
+        } else {
+            (601, "Snow", "snow", "13")
+        }
+    } else if symbol.contains("rain") {
+        if symbol.contains("showers") {
+            (521, "Rain", "shower rain", "09")
+        } else if symbol.starts_with("light") {
+            (500, "Rain", "light rain", "10")
+        } else if symbol.starts_with("heavy") {
+            (502, "Rain", "heavy intensity rain", "10")
+        } else {
+            (501, "Rain", "moderate rain", "10")
+        }
+    } else {
+        match symbol {
+            "fog" => (741, "Fog", "fog", "50"),
+            "cloudy" => (804, "Clouds", "overcast clouds", "04"),
+            "partlycloudy" => (802, "Clouds", "scattered clouds", "03"),
+            "fair" => (801, "Clouds", "few clouds", "02"),
+            _ => (800, "Clear", "clear sky", "01"),
+        }
+    };
+    let suffix = if period == "night" { "n" } else { "d" };
+    (id, main, description, format_sstr!("{icon}{suffix}"))
+}
+
+fn parse_http_date(s: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(s, HTTP_DATE_FORMAT)
+        .ok()
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+fn is_snow(symbol_code: &str) -> bool {
+    symbol_code.contains("snow") || symbol_code.contains("sleet")
+}
+
+fn condition_json(symbol_code: &str) -> serde_json::Value {
+    let (id, main, description, icon) = symbol_to_condition(symbol_code);
+    weather_condition_json(id, main, description, &icon)
+}
+
+/// Client for the met.no Locationforecast 2.0 compact api
+#[derive(Clone)]
+pub struct MetNoApi {
+    client: Client,
+    user_agent: StackString,
+    altitude: Option<i32>,
+}
+
+impl MetNoApi {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            client: CLIENT.clone(),
+            user_agent: config.metno_user_agent.clone(),
+            altitude: config.metno_altitude,
+        }
+    }
+
+    async fn get_locationforecast(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> Result<Arc<MetNoResponse>, Error> {
+        // met.no asks for at most four decimals to keep its cache effective
+        let latitude = format_sstr!("{latitude:0.4}");
+        let longitude = format_sstr!("{longitude:0.4}");
+        let key = format_sstr!("{latitude},{longitude}");
+        let now = OffsetDateTime::now_utc();
+        let cached = METNO_CACHE.lock().get(&key).cloned();
+        if let Some(cached) = &cached {
+            if cached.expires > now {
+                return Ok(cached.response.clone());
+            }
+        }
+        let mut request = self
+            .client
+            .get(METNO_URL)
+            .header(USER_AGENT, self.user_agent.as_str())
+            .query(&[("lat", &latitude), ("lon", &longitude)]);
+        if let Some(altitude) = self.altitude {
+            request = request.query(&[("altitude", altitude)]);
+        }
+        if let Some(last_modified) = cached.as_ref().and_then(|c| c.last_modified.as_ref()) {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+        let response = request.send().await?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(StackString::from)
+        };
+        let expires = header(EXPIRES)
+            .and_then(|e| parse_http_date(&e))
+            .unwrap_or(now + Duration::minutes(30));
+        let last_modified = header(LAST_MODIFIED);
+        let body = if response.status() == StatusCode::NOT_MODIFIED {
+            debug!("met.no {key} not modified");
+            cached
+                .map(|c| c.response)
+                .ok_or_else(|| format_err!("Not modified without cached response"))?
+        } else {
+            Arc::new(response.error_for_status()?.json().await?)
+        };
+        METNO_CACHE.lock().insert(
+            key,
+            CachedResponse {
+                expires,
+                last_modified,
+                response: body.clone(),
+            },
+        );
+        Ok(body)
+    }
+}
+
+fn weather_data_from_metno(
+    response: &MetNoResponse,
+    latitude: f64,
+    longitude: f64,
+    now: OffsetDateTime,
+) -> Result<WeatherData, Error> {
+    let timeseries = &response.properties.timeseries;
+    let idx = timeseries.partition_point(|t| t.time <= now);
+    let step = timeseries
+        .get(idx.saturating_sub(1))
+        .ok_or_else(|| format_err!("Empty met.no timeseries"))?;
+    let details = &step.data.instant.details;
+    let symbol_code = step.data.symbol_code();
+    let precipitation = step
+        .data
+        .next_1_hours
+        .as_ref()
+        .map_or(0.0, MetNoPeriod::precipitation);
+    let (rain, snow) = precipitation_json(is_snow(symbol_code), "1h", precipitation);
+    let (sunrise, sunset) = sun_times(step.time.date(), latitude, longitude);
+    let temp = details.air_temperature + KELVIN_OFFSET;
+    let value = json!({
+        "coord": {"lon": longitude, "lat": latitude},
+        "weather": condition_json(symbol_code),
+        "base": "met.no",
+        "main": {
+            "temp": temp,
+            "feels_like": temp,
+            "temp_min": temp,
+            "temp_max": temp,
+            "pressure": details.air_pressure_at_sea_level,
+            "humidity": details.relative_humidity.round() as i64,
+        },
+        "wind": {"speed": details.wind_speed, "deg": details.wind_from_direction},
+        "rain": rain,
+        "snow": snow,
+        "dt": step.time.unix_timestamp(),
+        "sys": {"sunrise": sunrise, "sunset": sunset},
+        "timezone": approximate_timezone(longitude),
+        "name": "",
+    });
+    serde_json::from_value(value).map_err(Into::into)
+}
+
+fn weather_forecast_from_metno(
+    response: &MetNoResponse,
+    latitude: f64,
+    longitude: f64,
+) -> Result<WeatherForecast, Error> {
+    let timeseries = &response.properties.timeseries;
+    let mut list = Vec::new();
+    for (idx, step) in timeseries.iter().enumerate() {
+        if step.time.hour() % 3 != 0 {
+            continue;
+        }
+        let symbol_code = step.data.symbol_code();
+        // sum three hourly values where available, otherwise half of the
+        // six hour period
+        let precipitation = if step.data.next_1_hours.is_some() {
+            timeseries[idx..]
+                .iter()
+                .take_while(|t| t.time < step.time + Duration::hours(3))
+                .filter_map(|t| t.data.next_1_hours.as_ref())
+                .map(MetNoPeriod::precipitation)
+                .sum()
+        } else {
+            step.data
+                .next_6_hours
+                .as_ref()
+                .map_or(0.0, |p| p.precipitation() / 2.0)
+        };
+        let (rain, snow) = precipitation_json(is_snow(symbol_code), "3h", precipitation);
+        let details = &step.data.instant.details;
+        let temp = details.air_temperature + KELVIN_OFFSET;
+        list.push(json!({
+            "dt": step.time.unix_timestamp(),
+            "main": {
+                "temp": temp,
+                "feels_like": temp,
+                "temp_min": temp,
+                "temp_max": temp,
+                "pressure": details.air_pressure_at_sea_level,
+                "sea_level": details.air_pressure_at_sea_level,
+                "grnd_level": details.air_pressure_at_sea_level,
+                "humidity": details.relative_humidity.round() as i64,
+            },
+            "weather": condition_json(symbol_code),
+            "wind": {"speed": details.wind_speed, "deg": details.wind_from_direction},
+            "rain": rain,
+            "snow": snow,
+        }));
+        if list.len() >= FORECAST_ENTRIES {
+            break;
+        }
+    }
+    let today = timeseries
+        .first()
+        .map_or_else(|| OffsetDateTime::now_utc().date(), |t| t.time.date());
+    let (sunrise, sunset) = sun_times(today, latitude, longitude);
+    let value = json!({
+        "list": list,
+        "city": {
+            "timezone": approximate_timezone(longitude),
+            "sunrise": sunrise,
+            "sunset": sunset,
+        },
+    });
+    serde_json::from_value(value).map_err(Into::into)
+}
+
+impl WeatherProvider for MetNoApi {
+    fn get_name(&self) -> &'static str {
+        "met.no"
+    }
+
+    async fn get_weather_data(&self, loc: &WeatherLocation) -> Result<WeatherData, Error> {
+        let (latitude, longitude) = get_lat_lon(loc)?;
+        let response = self.get_locationforecast(latitude, longitude).await?;
+        weather_data_from_metno(&response, latitude, longitude, OffsetDateTime::now_utc())
+    }
+
+    async fn get_weather_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error> {
+        let (latitude, longitude) = get_lat_lon(loc)?;
+        let response = self.get_locationforecast(latitude, longitude).await?;
+        weather_forecast_from_metno(&response, latitude, longitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::datetime;
+
+    use crate::providers::metno::{
+        parse_http_date, symbol_to_condition, weather_data_from_metno, weather_forecast_from_metno,
+        MetNoResponse,
+    };
+
+    #[test]
+    fn test_symbol_to_condition() {
+        let (id, main, _, icon) = symbol_to_condition("lightrainshowers_night");
+        assert_eq!((id, main, icon.as_str()), (521, "Rain", "09n"));
+        let (id, main, _, icon) = symbol_to_condition("heavysnow");
+        assert_eq!((id, main, icon.as_str()), (602, "Snow", "13d"));
+        assert_eq!(symbol_to_condition("rainandthunder").0, 211);
+        assert_eq!(symbol_to_condition("clearsky_day").0, 800);
+    }
+
+    #[test]
+    fn test_parse_http_date() {
+        assert_eq!(
+            parse_http_date("Sat, 01 Jun 2024 12:30:00 GMT"),
+            Some(datetime!(2024-06-01 12:30 UTC))
+        );
+    }
+
+    #[test]
+    fn test_metno_mapping() -> Result<(), Error> {
+        let data = r#"{"type": "Feature", "properties": {"timeseries": [
+            {"time": "2024-06-01T12:00:00Z", "data": {
+                "instant": {"details": {"air_pressure_at_sea_level": 1012.5,
+                    "air_temperature": 15.0, "relative_humidity": 72.4,
+                    "wind_from_direction": 180.0, "wind_speed": 4.2}},
+                "next_1_hours": {"summary": {"symbol_code": "lightrain"},
+                    "details": {"precipitation_amount": 0.6}}}},
+            {"time": "2024-06-01T13:00:00Z", "data": {
+                "instant": {"details": {"air_pressure_at_sea_level": 1012.0,
+                    "air_temperature": 16.0, "relative_humidity": 70.0,
+                    "wind_from_direction": 190.0, "wind_speed": 4.0}},
+                "next_1_hours": {"summary": {"symbol_code": "cloudy"},
+                    "details": {"precipitation_amount": 0.4}}}},
+            {"time": "2024-06-01T15:00:00Z", "data": {
+                "instant": {"details": {"air_pressure_at_sea_level": 1011.0,
+                    "air_temperature": 17.0, "relative_humidity": 65.0,
+                    "wind_from_direction": 200.0, "wind_speed": 3.0}},
+                "next_6_hours": {"summary": {"symbol_code": "fair_day"},
+                    "details": {"precipitation_amount": 0.0}}}}
+        ]}}"#;
+        let response: MetNoResponse = serde_json::from_str(data)?;
+
+        let weather =
+            weather_data_from_metno(&response, 59.91, 10.75, datetime!(2024-06-01 12:20 UTC))?;
+        assert!((weather.main.temp.celcius() - 15.0).abs() < 1e-6);
+        assert_eq!(weather.weather[0].main, "Rain");
+        assert!((weather.rain.and_then(|r| r.one_hour).unwrap().millimeters() - 0.6).abs() < 1e-6);
+
+        let forecast = weather_forecast_from_metno(&response, 59.91, 10.75)?;
+        assert_eq!(forecast.list.len(), 2);
+        let rain = forecast.list[0]
+            .rain
+            .as_ref()
+            .and_then(|r| r.three_hour)
+            .unwrap();
+        assert!((rain.millimeters() - 1.0).abs() < 1e-6);
+        Ok(())
+    }
+}
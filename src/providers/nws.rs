@@ -0,0 +1,623 @@
+use anyhow::{format_err, Error};
+use futures::try_join;
+use once_cell::sync::Lazy;
+use reqwest::{header::USER_AGENT, Client};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use stack_string::{format_sstr, StackString};
+use time::{Duration, OffsetDateTime, UtcOffset};
+
+use weather_util_rust::{
+    weather_api::WeatherLocation, weather_data::WeatherData, weather_forecast::WeatherForecast,
+};
+
+use crate::{
+    config::Config,
+    providers::{
+        approximate_timezone, get_lat_lon, precipitation_json, sun_times, weather_condition_json,
+        WeatherProvider, FORECAST_ENTRIES, KELVIN_OFFSET,
+    },
+};
+
+const NWS_URL: &str = "https://api.weather.gov";
+/// the hourly forecast doesn't include pressure
+const STANDARD_PRESSURE_HPA: f64 = 1013.25;
+
+static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NwsPointProperties {
+    forecast_hourly: StackString,
+    forecast_grid_data: StackString,
+    observation_stations: StackString,
+}
+
+#[derive(Deserialize, Debug)]
+struct NwsPoint {
+    properties: NwsPointProperties,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NwsStations {
+    observation_stations: Vec<StackString>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+struct NwsValue {
+    value: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NwsObservationProperties {
+    #[serde(with = "time::serde::rfc3339")]
+    timestamp: OffsetDateTime,
+    #[serde(default)]
+    text_description: StackString,
+    /// Celsius
+    temperature: NwsValue,
+    /// km/h
+    wind_speed: NwsValue,
+    wind_direction: NwsValue,
+    /// Pa
+    barometric_pressure: NwsValue,
+    /// Pa
+    sea_level_pressure: NwsValue,
+    relative_humidity: NwsValue,
+    /// meters
+    #[serde(default)]
+    visibility: NwsValue,
+    /// mm
+    #[serde(default)]
+    precipitation_last_hour: NwsValue,
+    #[serde(default)]
+    heat_index: NwsValue,
+    #[serde(default)]
+    wind_chill: NwsValue,
+}
+
+#[derive(Deserialize, Debug)]
+struct NwsObservation {
+    properties: NwsObservationProperties,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NwsPeriod {
+    #[serde(with = "time::serde::rfc3339")]
+    start_time: OffsetDateTime,
+    is_daytime: bool,
+    /// Celsius (requested with `units=si`)
+    temperature: f64,
+    /// e.g. `15 km/h` or `10 to 20 km/h`
+    wind_speed: StackString,
+    /// e.g. `NW`
+    wind_direction: StackString,
+    short_forecast: StackString,
+    #[serde(default)]
+    relative_humidity: NwsValue,
+}
+
+#[derive(Deserialize, Debug)]
+struct NwsForecastProperties {
+    periods: Vec<NwsPeriod>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NwsForecast {
+    properties: NwsForecastProperties,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NwsGridValue {
+    /// e.g. `2024-06-01T18:00:00+00:00/PT6H`
+    valid_time: StackString,
+    value: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct NwsGridSeries {
+    values: Vec<NwsGridValue>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct NwsGridProperties {
+    /// mm
+    #[serde(default)]
+    quantitative_precipitation: NwsGridSeries,
+}
+
+#[derive(Deserialize, Debug)]
+struct NwsGridData {
+    properties: NwsGridProperties,
+}
+
+/// Active watch, warning or advisory
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NwsAlert {
+    pub id: StackString,
+    pub event: StackString,
+    #[serde(default)]
+    pub headline: Option<StackString>,
+    #[serde(default)]
+    pub description: Option<StackString>,
+    #[serde(default)]
+    pub instruction: Option<StackString>,
+    pub severity: StackString,
+    pub certainty: StackString,
+    pub urgency: StackString,
+    #[serde(with = "time::serde::rfc3339")]
+    pub effective: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option", default)]
+    pub expires: Option<OffsetDateTime>,
+    pub area_desc: StackString,
+    pub sender_name: StackString,
+}
+
+#[derive(Deserialize, Debug)]
+struct NwsAlertFeature {
+    properties: NwsAlert,
+}
+
+#[derive(Deserialize, Debug)]
+struct NwsAlerts {
+    features: Vec<NwsAlertFeature>,
+}
+
+/// Map an NWS text description (e.g. `Chance Light Rain`) onto the
+/// openweathermap condition id, main, description and icon.
+#[must_use]
+pub fn text_to_condition(
+    text: &str,
+    is_day: bool,
+) -> (usize, &'static str, &'static str, StackString) {
+    let text = text.to_lowercase();
+    let (id, main, description, icon) = if text.contains("thunder") {
+        (211, "Thunderstorm", "thunderstorm", "11")
+    } else if text.contains("sleet") || text.contains("freezing") {
+        (611, "Snow", "sleet", "13")
+    } else if text.contains("snow") || text.contains("flurries") {
+        if text.contains("light") || text.contains("flurries") {
+            (600, "Snow", "light snow", "13")
+        } else if text.contains("heavy") {
+            (602, "Snow", "heavy snow", "13")
+        } else {
+            (601, "Snow", "snow", "13")
+        }
+    } else if text.contains("showers") {
+        (521, "Rain", "shower rain", "09")
+    } else if text.contains("drizzle") {
+        (300, "Drizzle", "light intensity drizzle", "09")
+    } else if text.contains("rain") {
+        if text.contains("light") {
+            (500, "Rain", "light rain", "10")
+        } else if text.contains("heavy") {
+            (502, "Rain", "heavy intensity rain", "10")
+        } else {
+            (501, "Rain", "moderate rain", "10")
+        }
+    } else if text.contains("fog") {
+        (741, "Fog", "fog", "50")
+    } else if text.contains("haze") {
+        (721, "Haze", "haze", "50")
+    } else if text.contains("smoke") {
+        (711, "Smoke", "smoke", "50")
+    } else if text.contains("mostly cloudy") {
+        (803, "Clouds", "broken clouds", "04")
+    } else if text.contains("cloudy") || text.contains("overcast") {
+        (804, "Clouds", "overcast clouds", "04")
+    } else if text.contains("partly") {
+        (802, "Clouds", "scattered clouds", "03")
+    } else if text.contains("mostly") {
+        (801, "Clouds", "few clouds", "02")
+    } else {
+        (800, "Clear", "clear sky", "01")
+    };
+    let suffix = if is_day { "d" } else { "n" };
+    (id, main, description, format_sstr!("{icon}{suffix}"))
+}
+
+fn condition_json(text: &str, is_day: bool) -> serde_json::Value {
+    let (id, main, description, icon) = text_to_condition(text, is_day);
+    weather_condition_json(id, main, description, &icon)
+}
+
+fn is_snow(text: &str) -> bool {
+    let text = text.to_lowercase();
+    text.contains("snow") || text.contains("sleet") || text.contains("flurries")
+}
+
+/// Parse `15 km/h` or `10 to 20 km/h` into m/s, taking the upper bound
+fn parse_wind_speed(wind_speed: &str) -> f64 {
+    wind_speed
+        .split_whitespace()
+        .rev()
+        .find_map(|s| s.parse::<f64>().ok())
+        .map_or(0.0, |kmh| kmh / 3.6)
+}
+
+/// Convert a compass point (e.g. `NNW`) to degrees
+fn parse_wind_direction(wind_direction: &str) -> Option<f64> {
+    const POINTS: [&str; 16] = [
+        "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW",
+        "NW", "NNW",
+    ];
+    POINTS
+        .iter()
+        .position(|p| *p == wind_direction)
+        .map(|idx| idx as f64 * 22.5)
+}
+
+/// Parse an ISO 8601 duration such as `PT6H` or `P1DT12H`
+fn parse_iso_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.strip_prefix('P')?;
+    let (days, hours) = duration.split_once('T').unwrap_or((duration, ""));
+    let mut total = Duration::ZERO;
+    if !days.is_empty() {
+        total += Duration::days(days.strip_suffix('D')?.parse().ok()?);
+    }
+    let mut number = String::new();
+    for c in hours.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: i64 = number.parse().ok()?;
+        number.clear();
+        total += match c {
+            'H' => Duration::hours(value),
+            'M' => Duration::minutes(value),
+            'S' => Duration::seconds(value),
+            _ => return None,
+        };
+    }
+    Some(total)
+}
+
+/// Parse a gridpoint `validTime` interval into its start and end
+fn parse_valid_time(valid_time: &str) -> Option<(OffsetDateTime, OffsetDateTime)> {
+    let (start, duration) = valid_time.split_once('/')?;
+    let start =
+        OffsetDateTime::parse(start, &time::format_description::well_known::Rfc3339).ok()?;
+    Some((start, start + parse_iso_duration(duration)?))
+}
+
+/// Precipitation between `start` and `end`, each gridpoint interval is
+/// prorated by its overlap with the requested window
+fn precipitation_between(
+    series: &NwsGridSeries,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> f64 {
+    series
+        .values
+        .iter()
+        .filter_map(|v| {
+            let (valid_start, valid_end) = parse_valid_time(&v.valid_time)?;
+            let value = v.value?;
+            let overlap = valid_end.min(end) - valid_start.max(start);
+            let interval = valid_end - valid_start;
+            if overlap.is_positive() && interval.is_positive() {
+                Some(value * overlap.as_seconds_f64() / interval.as_seconds_f64())
+            } else {
+                None
+            }
+        })
+        .sum()
+}
+
+/// Client for the US National Weather Service api (api.weather.gov), only
+/// covers US locations
+#[derive(Clone)]
+pub struct NwsApi {
+    user_agent: StackString,
+}
+
+impl NwsApi {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            user_agent: config.metno_user_agent.clone(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        CLIENT
+            .get(url)
+            .header(USER_AGENT, self.user_agent.as_str())
+            .query(&[("units", "si")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn get_point(&self, latitude: f64, longitude: f64) -> Result<NwsPoint, Error> {
+        let url = format_sstr!("{NWS_URL}/points/{latitude:0.4},{longitude:0.4}");
+        self.get(&url).await
+    }
+
+    /// Active watches, warnings and advisories for a point
+    ///
+    /// # Errors
+    /// Return error if the api request fails
+    pub async fn get_active_alerts(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> Result<Vec<NwsAlert>, Error> {
+        let url = format_sstr!("{NWS_URL}/alerts/active?point={latitude:0.4},{longitude:0.4}");
+        let alerts: NwsAlerts = self.get(&url).await?;
+        Ok(alerts.features.into_iter().map(|f| f.properties).collect())
+    }
+}
+
+fn weather_data_from_nws(
+    observation: &NwsObservationProperties,
+    latitude: f64,
+    longitude: f64,
+) -> Result<WeatherData, Error> {
+    let temperature = observation
+        .temperature
+        .value
+        .ok_or_else(|| format_err!("Observation missing temperature"))?;
+    let feels_like = observation
+        .heat_index
+        .value
+        .or(observation.wind_chill.value)
+        .unwrap_or(temperature);
+    let pressure = observation
+        .sea_level_pressure
+        .value
+        .or(observation.barometric_pressure.value)
+        .map_or(STANDARD_PRESSURE_HPA, |p| p / 100.0);
+    let (sunrise, sunset) = sun_times(observation.timestamp.date(), latitude, longitude);
+    let now = observation.timestamp.unix_timestamp();
+    let is_day = now >= sunrise && now < sunset;
+    let (rain, snow) = precipitation_json(
+        is_snow(&observation.text_description),
+        "1h",
+        observation.precipitation_last_hour.value.unwrap_or(0.0),
+    );
+    let temp = temperature + KELVIN_OFFSET;
+    let value = json!({
+        "coord": {"lon": longitude, "lat": latitude},
+        "weather": condition_json(&observation.text_description, is_day),
+        "base": "nws",
+        "main": {
+            "temp": temp,
+            "feels_like": feels_like + KELVIN_OFFSET,
+            "temp_min": temp,
+            "temp_max": temp,
+            "pressure": pressure,
+            "humidity": observation.relative_humidity.value.unwrap_or(0.0).round() as i64,
+        },
+        "visibility": observation.visibility.value,
+        "wind": {
+            "speed": observation.wind_speed.value.unwrap_or(0.0) / 3.6,
+            "deg": observation.wind_direction.value,
+        },
+        "rain": rain,
+        "snow": snow,
+        "dt": now,
+        "sys": {"country": "US", "sunrise": sunrise, "sunset": sunset},
+        "timezone": approximate_timezone(longitude),
+        "name": "",
+    });
+    serde_json::from_value(value).map_err(Into::into)
+}
+
+fn weather_forecast_from_nws(
+    forecast: &NwsForecast,
+    grid: &NwsGridProperties,
+    latitude: f64,
+    longitude: f64,
+) -> Result<WeatherForecast, Error> {
+    let periods = &forecast.properties.periods;
+    let list: Vec<_> = periods
+        .iter()
+        .filter(|p| p.start_time.to_offset(UtcOffset::UTC).hour() % 3 == 0)
+        .take(FORECAST_ENTRIES)
+        .map(|period| {
+            let temp = period.temperature + KELVIN_OFFSET;
+            let precipitation = precipitation_between(
+                &grid.quantitative_precipitation,
+                period.start_time,
+                period.start_time + Duration::hours(3),
+            );
+            let (rain, snow) =
+                precipitation_json(is_snow(&period.short_forecast), "3h", precipitation);
+            json!({
+                "dt": period.start_time.unix_timestamp(),
+                "main": {
+                    "temp": temp,
+                    "feels_like": temp,
+                    "temp_min": temp,
+                    "temp_max": temp,
+                    "pressure": STANDARD_PRESSURE_HPA,
+                    "sea_level": STANDARD_PRESSURE_HPA,
+                    "grnd_level": STANDARD_PRESSURE_HPA,
+                    "humidity": period.relative_humidity.value.unwrap_or(0.0).round() as i64,
+                },
+                "weather": condition_json(&period.short_forecast, period.is_daytime),
+                "wind": {
+                    "speed": parse_wind_speed(&period.wind_speed),
+                    "deg": parse_wind_direction(&period.wind_direction),
+                },
+                "rain": rain,
+                "snow": snow,
+            })
+        })
+        .collect();
+    let first = periods
+        .first()
+        .ok_or_else(|| format_err!("Empty NWS forecast"))?;
+    let (sunrise, sunset) = sun_times(first.start_time.date(), latitude, longitude);
+    let value = json!({
+        "list": list,
+        "city": {
+            "timezone": first.start_time.offset().whole_seconds(),
+            "sunrise": sunrise,
+            "sunset": sunset,
+        },
+    });
+    serde_json::from_value(value).map_err(Into::into)
+}
+
+impl WeatherProvider for NwsApi {
+    fn get_name(&self) -> &'static str {
+        "nws"
+    }
+
+    async fn get_weather_data(&self, loc: &WeatherLocation) -> Result<WeatherData, Error> {
+        let (latitude, longitude) = get_lat_lon(loc)?;
+        let point = self.get_point(latitude, longitude).await?;
+        let stations: NwsStations = self.get(&point.properties.observation_stations).await?;
+        let station = stations
+            .observation_stations
+            .first()
+            .ok_or_else(|| format_err!("No observation stations for {loc}"))?;
+        let url = format_sstr!("{station}/observations/latest");
+        let observation: NwsObservation = self.get(&url).await?;
+        weather_data_from_nws(&observation.properties, latitude, longitude)
+    }
+
+    async fn get_weather_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error> {
+        let (latitude, longitude) = get_lat_lon(loc)?;
+        let point = self.get_point(latitude, longitude).await?;
+        let (forecast, grid): (NwsForecast, NwsGridData) = try_join!(
+            self.get(&point.properties.forecast_hourly),
+            self.get(&point.properties.forecast_grid_data),
+        )?;
+        weather_forecast_from_nws(&forecast, &grid.properties, latitude, longitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+
+    use time::macros::datetime;
+
+    use crate::providers::nws::{
+        parse_iso_duration, parse_wind_direction, parse_wind_speed, precipitation_between,
+        text_to_condition, weather_data_from_nws, weather_forecast_from_nws, NwsAlerts,
+        NwsForecast, NwsGridData, NwsObservation,
+    };
+
+    #[test]
+    fn test_text_to_condition() {
+        assert_eq!(text_to_condition("Chance Light Rain", true).0, 500);
+        assert_eq!(text_to_condition("Mostly Cloudy", false).3.as_str(), "04n");
+        assert_eq!(
+            text_to_condition("Slight Chance Showers And Thunderstorms", true).0,
+            211
+        );
+        assert_eq!(text_to_condition("Sunny", true).0, 800);
+    }
+
+    #[test]
+    fn test_parse_wind() {
+        assert!((parse_wind_speed("18 km/h") - 5.0).abs() < 1e-6);
+        assert!((parse_wind_speed("9 to 18 km/h") - 5.0).abs() < 1e-6);
+        assert_eq!(parse_wind_direction("SW"), Some(225.0));
+        assert_eq!(parse_wind_direction("calm"), None);
+    }
+
+    #[test]
+    fn test_nws_mapping() -> Result<(), Error> {
+        let data = r#"{"properties": {
+            "timestamp": "2024-06-01T17:53:00+00:00",
+            "textDescription": "Light Rain",
+            "temperature": {"value": 18.3},
+            "windSpeed": {"value": 18.0},
+            "windDirection": {"value": 200},
+            "barometricPressure": {"value": 101250},
+            "seaLevelPressure": {"value": null},
+            "relativeHumidity": {"value": 80.2},
+            "visibility": {"value": 16090},
+            "precipitationLastHour": {"value": 1.2},
+            "heatIndex": {"value": null},
+            "windChill": {"value": null}
+        }}"#;
+        let observation: NwsObservation = serde_json::from_str(data)?;
+        let weather = weather_data_from_nws(&observation.properties, 44.98, -93.27)?;
+        assert!((weather.main.temp.celcius() - 18.3).abs() < 1e-6);
+        assert!((weather.main.pressure.kpa() - 101.25).abs() < 1e-6);
+        assert_eq!(weather.weather[0].main, "Rain");
+
+        let data = r#"{"properties": {"periods": [
+            {"startTime": "2024-06-01T13:00:00-05:00", "isDaytime": true, "temperature": 20,
+             "windSpeed": "9 km/h", "windDirection": "S", "shortForecast": "Sunny",
+             "relativeHumidity": {"value": 60}},
+            {"startTime": "2024-06-01T14:00:00-05:00", "isDaytime": true, "temperature": 21,
+             "windSpeed": "9 km/h", "windDirection": "S", "shortForecast": "Sunny",
+             "relativeHumidity": {"value": 58}},
+            {"startTime": "2024-06-01T16:00:00-05:00", "isDaytime": true, "temperature": 22,
+             "windSpeed": "11 km/h", "windDirection": "SSW", "shortForecast": "Partly Sunny",
+             "relativeHumidity": {"value": 55}}
+        ]}}"#;
+        let forecast: NwsForecast = serde_json::from_str(data)?;
+        let data = r#"{"properties": {"quantitativePrecipitation": {"values": [
+            {"validTime": "2024-06-01T18:00:00+00:00/PT6H", "value": 3.0}
+        ]}}}"#;
+        let grid: NwsGridData = serde_json::from_str(data)?;
+        let forecast = weather_forecast_from_nws(&forecast, &grid.properties, 44.98, -93.27)?;
+        assert_eq!(forecast.list.len(), 2);
+        let rain = forecast.list[0]
+            .rain
+            .as_ref()
+            .and_then(|r| r.three_hour)
+            .unwrap();
+        assert!((rain.millimeters() - 1.5).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_precipitation_between() -> Result<(), Error> {
+        assert_eq!(
+            parse_iso_duration("P1DT12H"),
+            Some(time::Duration::hours(36))
+        );
+        assert_eq!(parse_iso_duration("PT6H"), Some(time::Duration::hours(6)));
+        assert_eq!(parse_iso_duration("6H"), None);
+
+        let data = r#"{"values": [
+            {"validTime": "2024-06-01T12:00:00+00:00/PT6H", "value": 6.0},
+            {"validTime": "2024-06-01T18:00:00+00:00/PT6H", "value": 12.0}
+        ]}"#;
+        let series = serde_json::from_str(data)?;
+        let amount = precipitation_between(
+            &series,
+            datetime!(2024-06-01 16:00 UTC),
+            datetime!(2024-06-01 19:00 UTC),
+        );
+        assert!((amount - 4.0).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_alerts() -> Result<(), Error> {
+        let data = r#"{"features": [{"properties": {
+            "id": "urn:oid:2.49.0.1.840.0.1", "event": "Severe Thunderstorm Warning",
+            "headline": "Severe Thunderstorm Warning issued June 1 at 5:05PM CDT",
+            "description": "...", "instruction": null, "severity": "Severe",
+            "certainty": "Observed", "urgency": "Immediate",
+            "effective": "2024-06-01T17:05:00-05:00", "expires": "2024-06-01T17:45:00-05:00",
+            "areaDesc": "Hennepin, MN", "senderName": "NWS Twin Cities/Chanhassen MN"
+        }}]}"#;
+        let alerts: NwsAlerts = serde_json::from_str(data)?;
+        assert_eq!(alerts.features.len(), 1);
+        let alert = &alerts.features[0].properties;
+        assert_eq!(alert.severity.as_str(), "Severe");
+        assert_eq!(alert.expires, Some(datetime!(2024-06-01 22:45 UTC)));
+        Ok(())
+    }
+}
@@ -0,0 +1,335 @@
+use anyhow::{format_err, Error};
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use stack_string::format_sstr;
+use time::OffsetDateTime;
+
+use weather_util_rust::{
+    weather_api::WeatherLocation, weather_data::WeatherData, weather_forecast::WeatherForecast,
+};
+
+use crate::providers::{
+    get_lat_lon, precipitation_json, weather_condition_json, WeatherProvider, FORECAST_ENTRIES,
+    KELVIN_OFFSET,
+};
+
+const OPEN_METEO_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const VARIABLES: &str = "temperature_2m,relative_humidity_2m,apparent_temperature,is_day,\
+                         weather_code,pressure_msl,wind_speed_10m,wind_direction_10m,rain,\
+                         showers,snowfall";
+
+static CLIENT: Lazy<Client> = Lazy::new(Client::new);
+
+#[derive(Deserialize, Debug)]
+struct OpenMeteoResponse {
+    utc_offset_seconds: i32,
+    current: OpenMeteoCurrent,
+    hourly: OpenMeteoHourly,
+    daily: OpenMeteoDaily,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenMeteoCurrent {
+    time: i64,
+    /// Celsius
+    temperature_2m: f64,
+    relative_humidity_2m: f64,
+    apparent_temperature: f64,
+    is_day: u8,
+    weather_code: u8,
+    /// hPa
+    pressure_msl: f64,
+    /// m/s
+    wind_speed_10m: f64,
+    wind_direction_10m: f64,
+    /// mm
+    rain: f64,
+    /// mm
+    showers: f64,
+    /// cm
+    snowfall: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenMeteoHourly {
+    time: Vec<i64>,
+    temperature_2m: Vec<f64>,
+    relative_humidity_2m: Vec<f64>,
+    apparent_temperature: Vec<f64>,
+    is_day: Vec<u8>,
+    weather_code: Vec<u8>,
+    pressure_msl: Vec<f64>,
+    wind_speed_10m: Vec<f64>,
+    wind_direction_10m: Vec<f64>,
+    rain: Vec<f64>,
+    showers: Vec<f64>,
+    snowfall: Vec<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OpenMeteoDaily {
+    sunrise: Vec<i64>,
+    sunset: Vec<i64>,
+}
+
+/// Map a WMO weather interpretation code onto the openweathermap condition
+/// id, main, description and icon (without the day / night suffix).
+#[must_use]
+pub fn wmo_to_condition(code: u8) -> (usize, &'static str, &'static str, &'static str) {
+    match code {
+        1 => (801, "Clouds", "few clouds", "02"),
+        2 => (802, "Clouds", "scattered clouds", "03"),
+        3 => (804, "Clouds", "overcast clouds", "04"),
+        45 | 48 => (741, "Fog", "fog", "50"),
+        51 => (300, "Drizzle", "light intensity drizzle", "09"),
+        53 => (301, "Drizzle", "drizzle", "09"),
+        55 => (302, "Drizzle", "heavy intensity drizzle", "09"),
+        56 | 57 | 66 | 67 => (511, "Rain", "freezing rain", "13"),
+        61 => (500, "Rain", "light rain", "10"),
+        63 => (501, "Rain", "moderate rain", "10"),
+        65 => (502, "Rain", "heavy intensity rain", "10"),
+        71 | 77 => (600, "Snow", "light snow", "13"),
+        73 => (601, "Snow", "snow", "13"),
+        75 => (602, "Snow", "heavy snow", "13"),
+        80 => (520, "Rain", "light intensity shower rain", "09"),
+        81 => (521, "Rain", "shower rain", "09"),
+        82 => (522, "Rain", "heavy intensity shower rain", "09"),
+        85 => (620, "Snow", "light shower snow", "13"),
+        86 => (621, "Snow", "shower snow", "13"),
+        95 => (211, "Thunderstorm", "thunderstorm", "11"),
+        96 | 99 => (202, "Thunderstorm", "thunderstorm with heavy rain", "11"),
+        _ => (800, "Clear", "clear sky", "01"),
+    }
+}
+
+fn condition_json(code: u8, is_day: u8) -> serde_json::Value {
+    let (id, main, description, icon) = wmo_to_condition(code);
+    let suffix = if is_day == 0 { "n" } else { "d" };
+    weather_condition_json(id, main, description, &format_sstr!("{icon}{suffix}"))
+}
+
+/// open-meteo reports snowfall in cm, the openweathermap types use mm
+fn precipitation(rain: f64, showers: f64, snowfall: f64) -> (bool, f64) {
+    if snowfall > 0.0 {
+        (true, snowfall * 10.0)
+    } else {
+        (false, rain + showers)
+    }
+}
+
+/// Client for the open-meteo.com forecast api, no api key required
+#[derive(Clone, Default)]
+pub struct OpenMeteoApi {}
+
+impl OpenMeteoApi {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    async fn get_forecast(
+        &self,
+        latitude: f64,
+        longitude: f64,
+    ) -> Result<OpenMeteoResponse, Error> {
+        CLIENT
+            .get(OPEN_METEO_URL)
+            .query(&[
+                ("latitude", format_sstr!("{latitude:0.4}")),
+                ("longitude", format_sstr!("{longitude:0.4}")),
+                ("current", VARIABLES.into()),
+                ("hourly", VARIABLES.into()),
+                ("daily", "sunrise,sunset".into()),
+                ("wind_speed_unit", "ms".into()),
+                ("timeformat", "unixtime".into()),
+                ("timezone", "auto".into()),
+                ("forecast_days", "6".into()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .map_err(Into::into)
+    }
+}
+
+fn weather_data_from_open_meteo(
+    response: &OpenMeteoResponse,
+    latitude: f64,
+    longitude: f64,
+) -> Result<WeatherData, Error> {
+    let current = &response.current;
+    let (is_snow, amount) = precipitation(current.rain, current.showers, current.snowfall);
+    let (rain, snow) = precipitation_json(is_snow, "1h", amount);
+    let sunrise = response.daily.sunrise.first().copied().unwrap_or_default();
+    let sunset = response.daily.sunset.first().copied().unwrap_or_default();
+    let temp = current.temperature_2m + KELVIN_OFFSET;
+    let value = json!({
+        "coord": {"lon": longitude, "lat": latitude},
+        "weather": condition_json(current.weather_code, current.is_day),
+        "base": "open-meteo",
+        "main": {
+            "temp": temp,
+            "feels_like": current.apparent_temperature + KELVIN_OFFSET,
+            "temp_min": temp,
+            "temp_max": temp,
+            "pressure": current.pressure_msl,
+            "humidity": current.relative_humidity_2m.round() as i64,
+        },
+        "wind": {"speed": current.wind_speed_10m, "deg": current.wind_direction_10m},
+        "rain": rain,
+        "snow": snow,
+        "dt": current.time,
+        "sys": {"sunrise": sunrise, "sunset": sunset},
+        "timezone": response.utc_offset_seconds,
+        "name": "",
+    });
+    serde_json::from_value(value).map_err(Into::into)
+}
+
+fn weather_forecast_from_open_meteo(
+    response: &OpenMeteoResponse,
+    now: OffsetDateTime,
+) -> Result<WeatherForecast, Error> {
+    let hourly = &response.hourly;
+    let now = now.unix_timestamp();
+    let mut list = Vec::new();
+    for (idx, dt) in hourly.time.iter().enumerate() {
+        if *dt < now || (dt / 3600) % 3 != 0 {
+            continue;
+        }
+        let get = |v: &[f64]| {
+            v.get(idx)
+                .copied()
+                .ok_or_else(|| format_err!("Truncated open-meteo response"))
+        };
+        // sum the hour starting at dt and the two following hours
+        let sum = |v: &[f64]| v.iter().skip(idx).take(3).sum::<f64>();
+        let (is_snow, amount) = precipitation(
+            sum(&hourly.rain),
+            sum(&hourly.showers),
+            sum(&hourly.snowfall),
+        );
+        let (rain, snow) = precipitation_json(is_snow, "3h", amount);
+        let temp = get(&hourly.temperature_2m)? + KELVIN_OFFSET;
+        let pressure = get(&hourly.pressure_msl)?;
+        list.push(json!({
+            "dt": dt,
+            "main": {
+                "temp": temp,
+                "feels_like": get(&hourly.apparent_temperature)? + KELVIN_OFFSET,
+                "temp_min": temp,
+                "temp_max": temp,
+                "pressure": pressure,
+                "sea_level": pressure,
+                "grnd_level": pressure,
+                "humidity": get(&hourly.relative_humidity_2m)?.round() as i64,
+            },
+            "weather": condition_json(
+                hourly.weather_code.get(idx).copied().unwrap_or_default(),
+                hourly.is_day.get(idx).copied().unwrap_or(1),
+            ),
+            "wind": {
+                "speed": get(&hourly.wind_speed_10m)?,
+                "deg": get(&hourly.wind_direction_10m)?,
+            },
+            "rain": rain,
+            "snow": snow,
+        }));
+        if list.len() >= FORECAST_ENTRIES {
+            break;
+        }
+    }
+    let value = json!({
+        "list": list,
+        "city": {
+            "timezone": response.utc_offset_seconds,
+            "sunrise": response.daily.sunrise.first().copied().unwrap_or_default(),
+            "sunset": response.daily.sunset.first().copied().unwrap_or_default(),
+        },
+    });
+    serde_json::from_value(value).map_err(Into::into)
+}
+
+impl WeatherProvider for OpenMeteoApi {
+    fn get_name(&self) -> &'static str {
+        "open-meteo"
+    }
+
+    async fn get_weather_data(&self, loc: &WeatherLocation) -> Result<WeatherData, Error> {
+        let (latitude, longitude) = get_lat_lon(loc)?;
+        let response = self.get_forecast(latitude, longitude).await?;
+        weather_data_from_open_meteo(&response, latitude, longitude)
+    }
+
+    async fn get_weather_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error> {
+        let (latitude, longitude) = get_lat_lon(loc)?;
+        let response = self.get_forecast(latitude, longitude).await?;
+        weather_forecast_from_open_meteo(&response, OffsetDateTime::now_utc())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::datetime;
+
+    use crate::providers::open_meteo::{
+        weather_data_from_open_meteo, weather_forecast_from_open_meteo, wmo_to_condition,
+        OpenMeteoResponse,
+    };
+
+    #[test]
+    fn test_wmo_to_condition() {
+        assert_eq!(wmo_to_condition(0).0, 800);
+        assert_eq!(wmo_to_condition(63).1, "Rain");
+        assert_eq!(wmo_to_condition(75).0, 602);
+        assert_eq!(wmo_to_condition(99).1, "Thunderstorm");
+    }
+
+    #[test]
+    fn test_open_meteo_mapping() -> Result<(), Error> {
+        // 2024-06-01 09:00 UTC through 14:00 UTC
+        let data = r#"{
+            "utc_offset_seconds": -18000,
+            "current": {"time": 1717233300, "temperature_2m": 20.0,
+                "relative_humidity_2m": 55.0, "apparent_temperature": 19.0, "is_day": 1,
+                "weather_code": 61, "pressure_msl": 1015.0, "wind_speed_10m": 3.5,
+                "wind_direction_10m": 270.0, "rain": 0.3, "showers": 0.0, "snowfall": 0.0},
+            "hourly": {
+                "time": [1717232400, 1717236000, 1717239600, 1717243200, 1717246800, 1717250400],
+                "temperature_2m": [20.0, 21.0, 22.0, 23.0, 24.0, 25.0],
+                "relative_humidity_2m": [55.0, 54.0, 53.0, 52.0, 51.0, 50.0],
+                "apparent_temperature": [19.0, 20.0, 21.0, 22.0, 23.0, 24.0],
+                "is_day": [1, 1, 1, 1, 1, 1],
+                "weather_code": [61, 61, 3, 2, 1, 0],
+                "pressure_msl": [1015.0, 1015.0, 1014.0, 1014.0, 1013.0, 1013.0],
+                "wind_speed_10m": [3.5, 3.0, 2.5, 2.0, 1.5, 1.0],
+                "wind_direction_10m": [270.0, 270.0, 260.0, 250.0, 240.0, 230.0],
+                "rain": [0.3, 0.2, 0.1, 0.0, 0.0, 0.0],
+                "showers": [0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                "snowfall": [0.0, 0.0, 0.0, 0.0, 0.0, 0.0]},
+            "daily": {"sunrise": [1717236900], "sunset": [1717292000]}
+        }"#;
+        let response: OpenMeteoResponse = serde_json::from_str(data)?;
+
+        let weather = weather_data_from_open_meteo(&response, 44.98, -93.27)?;
+        assert!((weather.main.temp.celcius() - 20.0).abs() < 1e-6);
+        assert_eq!(weather.weather[0].main, "Rain");
+
+        let forecast =
+            weather_forecast_from_open_meteo(&response, datetime!(2024-06-01 09:00 UTC))?;
+        assert_eq!(forecast.list.len(), 2);
+        let rain = forecast.list[0]
+            .rain
+            .as_ref()
+            .and_then(|r| r.three_hour)
+            .unwrap();
+        assert!((rain.millimeters() - 0.6).abs() < 1e-6);
+        assert!(forecast.list[1].rain.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+use anyhow::{format_err, Error};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Write},
+    path::PathBuf,
+};
+use time::{Date, Duration, OffsetDateTime, UtcOffset};
+use tokio::fs;
+
+use weather_api_types::units::Units;
+
+#[cfg(feature = "s3-sync")]
+use crate::s3_sync::S3Sync;
+use crate::{config::Config, feed::xml_escape, model::WeatherDataDB, pgpool::PgPool};
+
+const JSON_CONTENT_TYPE: &str = "application/json";
+const HTML_CONTENT_TYPE: &str = "text/html";
+
+/// Recorded weather of one local day
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DailySummary {
+    pub location_name: StackString,
+    pub date: Date,
+    /// kelvin
+    pub temperature_low: f64,
+    /// kelvin
+    pub temperature_high: f64,
+    /// kelvin
+    pub temperature_mean: f64,
+    /// %
+    pub humidity_mean: f64,
+    /// m/s
+    pub wind_speed_mean: f64,
+    /// mm
+    pub rain: f64,
+    /// mm
+    pub snow: f64,
+    /// most frequent condition of the day
+    pub conditions: StackString,
+    pub observations: usize,
+}
+
+/// Daily summaries in the local time of each row, readings report the
+/// previous hour so one precipitation value is kept per hour
+#[must_use]
+pub fn get_daily_summaries(location_name: &str, history: &[WeatherDataDB]) -> Vec<DailySummary> {
+    #[derive(Default)]
+    struct Accumulator<'a> {
+        low: Option<f64>,
+        high: Option<f64>,
+        temperature: f64,
+        humidity: f64,
+        wind_speed: f64,
+        observations: usize,
+        precipitation: BTreeMap<i64, (f64, f64)>,
+        conditions: HashMap<&'a str, usize>,
+    }
+
+    let mut days: BTreeMap<Date, Accumulator> = BTreeMap::new();
+    for row in history {
+        let Ok(dt) = OffsetDateTime::from_unix_timestamp(i64::from(row.dt)) else {
+            continue;
+        };
+        let offset = UtcOffset::from_whole_seconds(row.timezone).unwrap_or(UtcOffset::UTC);
+        let day = days.entry(dt.to_offset(offset).date()).or_default();
+        day.low = Some(day.low.map_or(row.temperature, |t| t.min(row.temperature)));
+        day.high = Some(day.high.map_or(row.temperature, |t| t.max(row.temperature)));
+        day.temperature += row.temperature;
+        day.humidity += f64::from(row.humidity);
+        day.wind_speed += row.wind_speed;
+        day.observations += 1;
+        let hour = day
+            .precipitation
+            .entry(dt.unix_timestamp() / 3600)
+            .or_default();
+        hour.0 = hour.0.max(row.rain.unwrap_or(0.0));
+        hour.1 = hour.1.max(row.snow.unwrap_or(0.0));
+        let condition = row.condition.trim();
+        if !condition.is_empty() {
+            *day.conditions.entry(condition).or_default() += 1;
+        }
+    }
+    days.into_iter()
+        .map(|(date, day)| {
+            let n = day.observations as f64;
+            let conditions = day
+                .conditions
+                .into_iter()
+                .max_by(|(a, x), (b, y)| x.cmp(y).then_with(|| b.cmp(a)))
+                .map_or_else(StackString::new, |(c, _)| c.into());
+            DailySummary {
+                location_name: location_name.into(),
+                date,
+                temperature_low: day.low.unwrap_or_default(),
+                temperature_high: day.high.unwrap_or_default(),
+                temperature_mean: day.temperature / n,
+                humidity_mean: day.humidity / n,
+                wind_speed_mean: day.wind_speed / n,
+                rain: day.precipitation.values().map(|(r, _)| r).sum(),
+                snow: day.precipitation.values().map(|(_, s)| s).sum(),
+                conditions,
+                observations: day.observations,
+            }
+        })
+        .collect()
+}
+
+/// Path safe name of a location, e.g. `Saint Louis Park, MN` ->
+/// `saint-louis-park-mn`
+#[must_use]
+pub fn location_slug(location_name: &str) -> StackString {
+    let mut slug = String::new();
+    for c in location_name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug.into()
+}
+
+fn date_path(date: Date) -> StackString {
+    format_sstr!(
+        "{:04}/{:02}/{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    )
+}
+
+fn render_html(title: &str, body: &str) -> String {
+    let title = xml_escape(title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         </head>\n<body>\n<h1>{title}</h1>\n{body}</body>\n</html>\n"
+    )
+}
+
+/// Html table of daily summaries, dates link to the per day pages when
+/// `link_prefix` is set
+#[must_use]
+pub fn render_summary_html(
+    title: &str,
+    summaries: &[DailySummary],
+    units: Units,
+    link_prefix: Option<&str>,
+) -> String {
+    let temperature_unit = units.temperature_unit();
+    let precipitation_unit = units.precipitation_unit();
+    let mut body = String::new();
+    body.push_str("<table>\n");
+    let _ = writeln!(
+        body,
+        "<tr><th>Date</th><th>Low ({temperature_unit})</th><th>High \
+         ({temperature_unit})</th><th>Mean ({temperature_unit})</th><th>Humidity \
+         (%)</th><th>Rain ({precipitation_unit})</th><th>Snow \
+         ({precipitation_unit})</th><th>Conditions</th></tr>"
+    );
+    for summary in summaries.iter().rev() {
+        let date = match link_prefix {
+            Some(prefix) => format!(
+                r#"<a href="{prefix}{}.html">{}</a>"#,
+                date_path(summary.date),
+                summary.date
+            ),
+            None => summary.date.to_string(),
+        };
+        let _ = writeln!(
+            body,
+            "<tr><td>{date}</td><td>{:0.1}</td><td>{:0.1}</td><td>{:0.1}</td><td>{:0.0}</td><td>{:\
+             0.2}</td><td>{:0.2}</td><td>{}</td></tr>",
+            units.temperature(summary.temperature_low),
+            units.temperature(summary.temperature_high),
+            units.temperature(summary.temperature_mean),
+            summary.humidity_mean,
+            units.precipitation(summary.rain),
+            units.precipitation(summary.snow),
+            xml_escape(&summary.conditions),
+        );
+    }
+    body.push_str("</table>\n");
+    render_html(title, &body)
+}
+
+/// Directory or `s3://bucket/prefix` receiving the published files
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublishTarget {
+    Directory(PathBuf),
+    #[cfg(feature = "s3-sync")]
+    S3 {
+        bucket: StackString,
+        prefix: StackString,
+    },
+}
+
+impl PublishTarget {
+    /// # Errors
+    /// Return error if `destination` is an s3 url without the s3-sync feature
+    pub fn parse(destination: &str) -> Result<Self, Error> {
+        if !destination.starts_with("s3://") {
+            return Ok(Self::Directory(destination.into()));
+        }
+        #[cfg(feature = "s3-sync")]
+        {
+            let path = destination.trim_start_matches("s3://");
+            let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+            if bucket.is_empty() {
+                return Err(format_err!("invalid s3 url {destination}"));
+            }
+            Ok(Self::S3 {
+                bucket: bucket.into(),
+                prefix: prefix.trim_matches('/').into(),
+            })
+        }
+        #[cfg(not(feature = "s3-sync"))]
+        {
+            Err(format_err!("publishing to s3 requires the s3-sync feature"))
+        }
+    }
+
+    #[cfg_attr(not(feature = "s3-sync"), allow(unused_variables))]
+    async fn write(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), Error> {
+        match self {
+            Self::Directory(directory) => {
+                let path = directory.join(key);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::write(&path, data).await.map_err(Into::into)
+            }
+            #[cfg(feature = "s3-sync")]
+            Self::S3 { bucket, prefix } => {
+                let key = if prefix.is_empty() {
+                    key.into()
+                } else {
+                    format_sstr!("{prefix}/{key}")
+                };
+                let aws_config = aws_config::load_from_env().await;
+                S3Sync::new(&aws_config)
+                    .upload_bytes(bucket, &key, data, content_type)
+                    .await
+                    .map(|_| ())
+            }
+        }
+    }
+}
+
+impl fmt::Display for PublishTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Directory(directory) => write!(f, "{}", directory.display()),
+            #[cfg(feature = "s3-sync")]
+            Self::S3 { bucket, prefix } => write!(f, "s3://{bucket}/{prefix}"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct PublishSummary {
+    pub locations: usize,
+    pub files: usize,
+    pub destination: StackString,
+}
+
+impl fmt::Display for PublishSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "published {} files for {} locations to {}",
+            self.files, self.locations, self.destination
+        )
+    }
+}
+
+/// Render the daily summaries of the last `publish_days` of every
+/// `publish_locations` entry: `{location}/{yyyy}/{mm}/{dd}.json` and `.html`
+/// per day, `{location}/index.json` and `index.html` of the whole period and
+/// a top level `index.html` linking the locations
+///
+/// # Errors
+/// Return error if db query or writing a file fails
+pub async fn publish_snapshots(
+    pool: &PgPool,
+    config: &Config,
+    target: &PublishTarget,
+) -> Result<PublishSummary, Error> {
+    let units = config.publish_units;
+    let start_date = OffsetDateTime::now_utc().date() - Duration::days(config.publish_days.into());
+    let mut files = 0;
+    let mut locations = Vec::new();
+    for loc in &config.publish_locations {
+        let location_name = format_sstr!("{loc}");
+        let history: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates(
+            pool,
+            Some(&location_name),
+            None,
+            Some(start_date),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+        let summaries = get_daily_summaries(&location_name, &history);
+        let slug = location_slug(&location_name);
+        for summary in &summaries {
+            let key = format_sstr!("{slug}/{}", date_path(summary.date));
+            let title = format_sstr!("{location_name} {}", summary.date);
+            let html = render_summary_html(&title, std::slice::from_ref(summary), units, None);
+            target
+                .write(
+                    &format_sstr!("{key}.json"),
+                    serde_json::to_vec(summary)?,
+                    JSON_CONTENT_TYPE,
+                )
+                .await?;
+            target
+                .write(&format_sstr!("{key}.html"), html.into(), HTML_CONTENT_TYPE)
+                .await?;
+            files += 2;
+        }
+        let html = render_summary_html(&location_name, &summaries, units, Some(""));
+        target
+            .write(
+                &format_sstr!("{slug}/index.json"),
+                serde_json::to_vec(&summaries)?,
+                JSON_CONTENT_TYPE,
+            )
+            .await?;
+        target
+            .write(
+                &format_sstr!("{slug}/index.html"),
+                html.into(),
+                HTML_CONTENT_TYPE,
+            )
+            .await?;
+        files += 2;
+        locations.push((location_name, slug));
+    }
+    let mut body = String::from("<ul>\n");
+    for (location_name, slug) in &locations {
+        let _ = writeln!(
+            body,
+            r#"<li><a href="{slug}/index.html">{}</a></li>"#,
+            xml_escape(location_name)
+        );
+    }
+    body.push_str("</ul>\n");
+    let index = render_html("Weather", &body);
+    target
+        .write("index.html", index.into(), HTML_CONTENT_TYPE)
+        .await?;
+    files += 1;
+
+    Ok(PublishSummary {
+        locations: locations.len(),
+        files,
+        destination: format_sstr!("{target}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::{date, datetime};
+
+    use weather_api_types::units::Units;
+
+    use crate::{
+        condition::condition_code,
+        model::WeatherDataDB,
+        publish::{get_daily_summaries, location_slug, render_summary_html, PublishTarget},
+    };
+
+    fn row(dt: i64, temperature: f64, rain: Option<f64>, condition: &str) -> WeatherDataDB {
+        WeatherDataDB {
+            dt: dt as i32,
+            location_name: "test".into(),
+            latitude: 45.0,
+            longitude: -93.0,
+            condition: condition.into(),
+            condition_code: condition_code(condition),
+            temperature,
+            temperature_minimum: temperature,
+            temperature_maximum: temperature,
+            humidity: 50,
+            rain,
+            timezone: -5 * 3600,
+            ..WeatherDataDB::test_row(datetime!(2024-06-01 00:00 UTC))
+        }
+    }
+
+    #[test]
+    fn test_daily_summaries() -> Result<(), Error> {
+        // 01:00 local time (utc-5)
+        let start = datetime!(2024-06-03 06:00 UTC).unix_timestamp();
+        let history = [
+            row(start, 290.0, Some(1.0), "Rain light rain "),
+            row(start + 600, 292.0, Some(1.0), "Rain light rain "),
+            row(start + 3600, 300.0, Some(0.5), "Clear clear sky "),
+            row(start + 7200, 298.0, None, "Rain light rain "),
+            row(start + 86400, 280.0, None, "Clear clear sky "),
+        ];
+        let summaries = get_daily_summaries("Saint Louis Park, MN", &history);
+        assert_eq!(summaries.len(), 2);
+        let day = &summaries[0];
+        assert_eq!(day.date, date!(2024 - 06 - 03));
+        assert_eq!(day.temperature_low, 290.0);
+        assert_eq!(day.temperature_high, 300.0);
+        assert_eq!(day.temperature_mean, 295.0);
+        assert_eq!(day.wind_speed_mean, 2.0);
+        assert!((day.rain - 1.5).abs() < 1e-9);
+        assert_eq!(day.conditions, "Rain light rain");
+        assert_eq!(day.observations, 4);
+        assert_eq!(summaries[1].date, date!(2024 - 06 - 04));
+
+        assert_eq!(
+            location_slug("Saint Louis Park, MN").as_str(),
+            "saint-louis-park-mn"
+        );
+        assert_eq!(location_slug(" 55416 ").as_str(), "55416");
+
+        let html = render_summary_html("Saint Louis Park, MN", &summaries, Units::Metric, Some(""));
+        assert!(html.contains(r#"<a href="2024/06/03.html">2024-06-03</a>"#));
+        assert!(html.contains("<td>16.9</td><td>26.9</td><td>21.9</td>"));
+        assert!(html.find("2024-06-04") < html.find("2024-06-03"));
+
+        assert_eq!(
+            PublishTarget::parse("/var/www/weather")?,
+            PublishTarget::Directory("/var/www/weather".into())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+use anyhow::Error;
+use futures::TryStreamExt;
+use rweb::Schema;
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use rweb_helper::{DateTimeType, DateType, UuidWrapper};
+
+use crate::{coverage::completeness, model::WeatherDataDB, pgpool::PgPool};
+
+/// Intervals longer than this many expected intervals are reported as
+/// missing
+pub const MISSING_INTERVAL_FACTOR: i64 = 2;
+
+/// Physically possible range of each checked field, in the units stored in
+/// `weather_data` (K, kPa, %, m/s, degrees, mm, m), bounded by the world
+/// records
+const VALID_RANGES: [(&str, f64, f64); 10] = [
+    ("temperature", 178.0, 334.0),
+    ("temperature_minimum", 178.0, 334.0),
+    ("temperature_maximum", 178.0, 334.0),
+    ("pressure", 85.0, 110.0),
+    ("humidity", 0.0, 100.0),
+    ("wind_speed", 0.0, 115.0),
+    ("wind_direction", 0.0, 360.0),
+    ("rain", 0.0, 500.0),
+    ("snow", 0.0, 500.0),
+    ("visibility", 0.0, 100_000.0),
+];
+
+fn get_field(row: &WeatherDataDB, field: &str) -> Option<f64> {
+    match field {
+        "temperature" => Some(row.temperature),
+        "temperature_minimum" => Some(row.temperature_minimum),
+        "temperature_maximum" => Some(row.temperature_maximum),
+        "pressure" => Some(row.pressure),
+        "humidity" => Some(row.humidity.into()),
+        "wind_speed" => Some(row.wind_speed),
+        "wind_direction" => row.wind_direction,
+        "rain" => row.rain,
+        "snow" => row.snow,
+        "visibility" => row.visibility,
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Schema)]
+#[schema(component = "MissingInterval")]
+pub struct MissingInterval {
+    #[schema(description = "Last Observation Before the Interval (or range start)")]
+    pub start: DateTimeType,
+    #[schema(description = "First Observation After the Interval (or range end)")]
+    pub end: DateTimeType,
+    #[schema(description = "Length (seconds)")]
+    pub seconds: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "DuplicateTimestamp")]
+pub struct DuplicateTimestamp {
+    #[schema(description = "Server")]
+    pub server: StackString,
+    #[schema(description = "Created At")]
+    pub created_at: DateTimeType,
+    #[schema(description = "Rows Sharing the Timestamp")]
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "ImpossibleValue")]
+pub struct ImpossibleValue {
+    #[schema(description = "History Record ID")]
+    pub id: UuidWrapper,
+    #[schema(description = "Created At")]
+    pub created_at: DateTimeType,
+    #[schema(description = "Field")]
+    pub field: StackString,
+    #[schema(description = "Stored Value")]
+    pub value: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "MonthlyCoverage")]
+pub struct MonthlyCoverage {
+    #[schema(description = "Month (YYYY-MM, UTC)")]
+    pub month: StackString,
+    #[schema(description = "Distinct Observation Times")]
+    pub observations: usize,
+    #[schema(description = "Expected Observations")]
+    pub expected: usize,
+    #[schema(description = "Coverage (%)")]
+    pub coverage: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Schema)]
+#[schema(component = "HistoryQuality")]
+pub struct HistoryQuality {
+    #[schema(description = "Location Name")]
+    pub name: StackString,
+    #[schema(description = "First Date (UTC)")]
+    pub start_date: DateType,
+    #[schema(description = "Last Date (UTC)")]
+    pub end_date: DateType,
+    #[schema(description = "Expected Interval Between Observations (seconds)")]
+    pub expected_interval: u64,
+    #[schema(description = "Rows")]
+    pub observations: usize,
+    #[schema(description = "Intervals Without Observations")]
+    pub missing_intervals: Vec<MissingInterval>,
+    #[schema(description = "Timestamps Recorded More Than Once by a Server")]
+    pub duplicate_timestamps: Vec<DuplicateTimestamp>,
+    #[schema(description = "Values Outside the Physically Possible Range")]
+    pub impossible_values: Vec<ImpossibleValue>,
+    #[schema(description = "Coverage per Month")]
+    pub months: Vec<MonthlyCoverage>,
+}
+
+impl HistoryQuality {
+    /// Check `history` between `start_date` and `end_date` (inclusive, UTC)
+    /// against one observation every `expected_interval` seconds, the range
+    /// ends at `now` when it reaches into the future
+    #[must_use]
+    pub fn new(
+        name: &str,
+        history: &[WeatherDataDB],
+        start_date: Date,
+        end_date: Date,
+        expected_interval: u64,
+        now: OffsetDateTime,
+    ) -> Self {
+        let expected_interval = expected_interval.max(1);
+        let range_start = start_date.with_time(Time::MIDNIGHT).assume_utc();
+        let range_end = (end_date + Duration::days(1))
+            .with_time(Time::MIDNIGHT)
+            .assume_utc()
+            .min(now)
+            .max(range_start);
+        let history: Vec<_> = history
+            .iter()
+            .filter(|row| *row.created_at >= range_start && *row.created_at < range_end)
+            .collect();
+
+        let times: BTreeSet<OffsetDateTime> = history.iter().map(|row| *row.created_at).collect();
+        let threshold = expected_interval as i64 * MISSING_INTERVAL_FACTOR;
+        let bounds: Vec<_> = [range_start]
+            .into_iter()
+            .chain(times.iter().copied())
+            .chain([range_end])
+            .collect();
+        let missing_intervals = bounds
+            .windows(2)
+            .filter_map(|w| {
+                let seconds = (w[1] - w[0]).whole_seconds();
+                (seconds > threshold).then(|| MissingInterval {
+                    start: w[0].into(),
+                    end: w[1].into(),
+                    seconds,
+                })
+            })
+            .collect();
+
+        let mut counts: BTreeMap<(&str, OffsetDateTime), usize> = BTreeMap::new();
+        for row in &history {
+            *counts
+                .entry((row.server.as_str(), *row.created_at))
+                .or_default() += 1;
+        }
+        let duplicate_timestamps = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|((server, created_at), count)| DuplicateTimestamp {
+                server: server.into(),
+                created_at: created_at.into(),
+                count,
+            })
+            .collect();
+
+        let impossible_values = history
+            .iter()
+            .flat_map(|row| {
+                VALID_RANGES.iter().filter_map(|(field, min, max)| {
+                    let value = get_field(row, field)?;
+                    (value.is_nan() || value < *min || value > *max).then(|| ImpossibleValue {
+                        id: row.id.into(),
+                        created_at: (*row.created_at).into(),
+                        field: (*field).into(),
+                        value,
+                    })
+                })
+            })
+            .collect();
+
+        let mut months = Vec::new();
+        let mut month_start = range_start.replace_day(1).unwrap_or(range_start);
+        while month_start < range_end {
+            let next_month = if month_start.month() == time::Month::December {
+                month_start
+                    .replace_year(month_start.year() + 1)
+                    .and_then(|d| d.replace_month(time::Month::January))
+            } else {
+                month_start.replace_month(month_start.month().next())
+            }
+            .unwrap_or(range_end);
+            let start = month_start.max(range_start);
+            let end = next_month.min(range_end);
+            let observations = times.range(start..end).count();
+            let expected = ((end - start).whole_seconds() as u64 / expected_interval) as usize;
+            months.push(MonthlyCoverage {
+                month: format_sstr!("{:04}-{:02}", month_start.year(), month_start.month() as u8),
+                observations,
+                expected,
+                coverage: completeness(observations, expected as f64),
+            });
+            month_start = next_month;
+        }
+
+        Self {
+            name: name.into(),
+            start_date: start_date.into(),
+            end_date: end_date.into(),
+            expected_interval,
+            observations: history.len(),
+            missing_intervals,
+            duplicate_timestamps,
+            impossible_values,
+            months,
+        }
+    }
+}
+
+impl fmt::Display for HistoryQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} - {}: {} rows, {} missing intervals, {} duplicate timestamps, {} impossible \
+             values",
+            self.name,
+            Date::from(self.start_date),
+            Date::from(self.end_date),
+            self.observations,
+            self.missing_intervals.len(),
+            self.duplicate_timestamps.len(),
+            self.impossible_values.len(),
+        )?;
+        for month in &self.months {
+            write!(
+                f,
+                "\n{} {:0.1}% ({} of {})",
+                month.month, month.coverage, month.observations, month.expected
+            )?;
+        }
+        for missing in &self.missing_intervals {
+            write!(
+                f,
+                "\nmissing {} - {} ({}s)",
+                OffsetDateTime::from(missing.start),
+                OffsetDateTime::from(missing.end),
+                missing.seconds
+            )?;
+        }
+        for duplicate in &self.duplicate_timestamps {
+            write!(
+                f,
+                "\nduplicate {} {} ({} rows)",
+                duplicate.server,
+                OffsetDateTime::from(duplicate.created_at),
+                duplicate.count
+            )?;
+        }
+        for impossible in &self.impossible_values {
+            write!(
+                f,
+                "\nimpossible {} {} = {}",
+                OffsetDateTime::from(impossible.created_at),
+                impossible.field,
+                impossible.value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Quality of the recorded history of `name` (optionally of one `server`) as
+/// it was at `asof`, rows inserted later are left out
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn get_history_quality(
+    pool: &PgPool,
+    name: &str,
+    server: Option<&str>,
+    start_date: Date,
+    end_date: Date,
+    expected_interval: u64,
+    asof: Option<OffsetDateTime>,
+) -> Result<HistoryQuality, Error> {
+    // end_date is compared with midnight, include the whole last day
+    let history: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates(
+        pool,
+        Some(name),
+        server,
+        Some(start_date),
+        Some(end_date + Duration::days(1)),
+        None,
+        None,
+        asof,
+        None,
+        None,
+    )
+    .await?
+    .try_collect()
+    .await?;
+    Ok(HistoryQuality::new(
+        name,
+        &history,
+        start_date,
+        end_date,
+        expected_interval,
+        asof.unwrap_or_else(OffsetDateTime::now_utc),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{
+        macros::{date, datetime},
+        Duration, OffsetDateTime,
+    };
+
+    use crate::{model::WeatherDataDB, quality::HistoryQuality};
+
+    #[test]
+    fn test_history_quality() {
+        let start = datetime!(2024-05-31 00:00 UTC);
+        // hourly on the 31st of may, nothing on the 1st of june until noon,
+        // then hourly
+        let mut history: Vec<WeatherDataDB> = (0..24)
+            .map(|i| start + Duration::hours(i))
+            .chain((12..24).map(|i| start + Duration::days(1) + Duration::hours(i)))
+            .map(WeatherDataDB::test_row)
+            .collect();
+        history.push(WeatherDataDB::test_row(start + Duration::hours(3)));
+        let mut humid = WeatherDataDB::test_row(start + Duration::hours(5) + Duration::minutes(30));
+        humid.humidity = 120;
+        humid.pressure = 10.13;
+        history.push(humid);
+
+        let now = datetime!(2024-06-01 23:30 UTC);
+        let quality = HistoryQuality::new(
+            "11106",
+            &history,
+            date!(2024 - 05 - 31),
+            date!(2024 - 06 - 02),
+            3600,
+            now,
+        );
+        assert_eq!(quality.observations, 38);
+        assert_eq!(quality.missing_intervals.len(), 1);
+        let missing = quality.missing_intervals[0];
+        assert_eq!(
+            OffsetDateTime::from(missing.start),
+            datetime!(2024-05-31 23:00 UTC)
+        );
+        assert_eq!(
+            OffsetDateTime::from(missing.end),
+            datetime!(2024-06-01 12:00 UTC)
+        );
+        assert_eq!(missing.seconds, 13 * 3600);
+
+        assert_eq!(quality.duplicate_timestamps.len(), 1);
+        assert_eq!(quality.duplicate_timestamps[0].count, 2);
+
+        let fields: Vec<_> = quality
+            .impossible_values
+            .iter()
+            .map(|v| v.field.as_str())
+            .collect();
+        assert_eq!(fields, ["pressure", "humidity"]);
+
+        assert_eq!(quality.months.len(), 2);
+        assert_eq!(quality.months[0].month, "2024-05");
+        assert_eq!(quality.months[0].expected, 24);
+        assert!((quality.months[0].coverage - 100.0).abs() < 1e-9);
+        assert_eq!(quality.months[1].month, "2024-06");
+        assert_eq!(quality.months[1].observations, 12);
+        assert_eq!(quality.months[1].expected, 23);
+
+        let text = quality.to_string();
+        assert!(text.contains("1 missing intervals, 1 duplicate timestamps, 2 impossible"));
+    }
+}
@@ -0,0 +1,350 @@
+use parking_lot::Mutex;
+use rweb::{
+    filters::{
+        addr::remote,
+        any::any,
+        header::optional,
+        path::{full, FullPath},
+    },
+    Filter, Rejection,
+};
+use stack_string::{format_sstr, StackString};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{config::Config, errors::ServiceError as Error, logged_user::LoggedUser};
+
+/// Number of tracked clients above which idle clients are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Address of the client: the peer address, or when the peer is one of
+/// `trusted_proxies` the rightmost `X-Forwarded-For` hop that isn't a trusted
+/// proxy (entries left of it are supplied by the client and can be forged)
+#[must_use]
+pub fn client_ip(
+    forwarded_for: Option<&str>,
+    remote: Option<SocketAddr>,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let peer = remote?.ip();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let mut client = peer;
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = hop;
+        if !trusted_proxies.contains(&hop) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// Whether the request `path` is an instance of the route `template`, path
+/// parameters such as `{id}` match any single segment
+fn matches_route(template: &str, path: &str) -> bool {
+    let mut template = template.trim_end_matches('/').split('/');
+    let mut path = path.trim_end_matches('/').split('/');
+    loop {
+        match (template.next(), path.next()) {
+            (None, None) => return true,
+            (Some(t), Some(p)) if t == p || (t.starts_with('{') && t.ends_with('}')) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Identity requests are counted against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RateLimitClient {
+    Anonymous(IpAddr),
+    User(StackString),
+}
+
+impl RateLimitClient {
+    /// Logged in users are identified by email, everyone else by
+    /// `client_ip`
+    #[must_use]
+    pub fn new(
+        user: Option<&LoggedUser>,
+        forwarded_for: Option<&str>,
+        remote: Option<SocketAddr>,
+        trusted_proxies: &[IpAddr],
+    ) -> Option<Self> {
+        if let Some(user) = user {
+            return Some(Self::User(user.email.clone()));
+        }
+        client_ip(forwarded_for, remote, trusted_proxies).map(Self::Anonymous)
+    }
+
+    fn key(&self) -> StackString {
+        match self {
+            Self::Anonymous(ip) => format_sstr!("ip:{ip}"),
+            Self::User(email) => format_sstr!("user:{email}"),
+        }
+    }
+}
+
+/// Per client GCRA limiter: a client may burst its whole per minute quota,
+/// after that requests are admitted at the sustained rate
+#[derive(Clone)]
+pub struct RateLimiter {
+    anonymous: u32,
+    authenticated: u32,
+    period: Duration,
+    trusted_proxies: Arc<[IpAddr]>,
+    routes: Arc<[StackString]>,
+    clients: Arc<Mutex<HashMap<StackString, Instant>>>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self::with_limits(
+            config.rate_limit_anonymous,
+            config.rate_limit_authenticated,
+            Duration::from_secs(60),
+        )
+        .with_trusted_proxies(&config.trusted_proxies)
+    }
+
+    #[must_use]
+    pub fn with_limits(anonymous: u32, authenticated: u32, period: Duration) -> Self {
+        Self {
+            anonymous,
+            authenticated,
+            period,
+            trusted_proxies: Arc::new([]),
+            routes: Arc::new([]),
+            clients: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    #[must_use]
+    pub fn with_trusted_proxies(mut self, trusted_proxies: &[IpAddr]) -> Self {
+        self.trusted_proxies = trusted_proxies.into();
+        self
+    }
+
+    /// Only count requests of the api operations `routes`, requests that
+    /// match no route or fetch a static script are never limited
+    #[must_use]
+    pub fn with_routes<'a>(mut self, routes: impl IntoIterator<Item = &'a str>) -> Self {
+        self.routes = routes
+            .into_iter()
+            .filter(|route| !route.ends_with(".js"))
+            .map(Into::into)
+            .collect();
+        self
+    }
+
+    fn is_limited(&self, path: &str) -> bool {
+        self.routes.iter().any(|route| matches_route(route, path))
+    }
+
+    fn limit(&self, client: &RateLimitClient) -> u32 {
+        match client {
+            RateLimitClient::Anonymous(_) => self.anonymous,
+            RateLimitClient::User(_) => self.authenticated,
+        }
+    }
+
+    /// Record a request of `client` at `now`
+    ///
+    /// # Errors
+    /// Returns the time until the client may retry when over its limit
+    pub fn check(&self, client: &RateLimitClient, now: Instant) -> Result<(), Duration> {
+        let limit = self.limit(client);
+        if limit == 0 {
+            return Ok(());
+        }
+        let interval = self.period / limit;
+        let tolerance = self.period - interval;
+        let mut clients = self.clients.lock();
+        if clients.len() > MAX_TRACKED_CLIENTS {
+            clients.retain(|_, tat| *tat > now);
+        }
+        let tat = clients.entry(client.key()).or_insert(now);
+        let start = (*tat).max(now);
+        let ahead = start - now;
+        if ahead > tolerance {
+            return Err(ahead - tolerance);
+        }
+        *tat = start + interval;
+        Ok(())
+    }
+
+    /// Reject requests over the limit with `ServiceError::TooManyRequests`
+    #[must_use]
+    pub fn filter(self) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+        let user = LoggedUser::filter()
+            .map(Some)
+            .or(any().map(|| None))
+            .unify();
+        user.and(optional::<StackString>("x-forwarded-for"))
+            .and(remote())
+            .and(full())
+            .and_then(
+                move |user: Option<LoggedUser>,
+                      forwarded_for: Option<StackString>,
+                      remote: Option<SocketAddr>,
+                      path: FullPath| {
+                    let limiter = self.clone();
+                    async move {
+                        if !limiter.is_limited(path.as_str()) {
+                            return Ok(());
+                        }
+                        let Some(client) = RateLimitClient::new(
+                            user.as_ref(),
+                            forwarded_for.as_deref(),
+                            remote,
+                            &limiter.trusted_proxies,
+                        ) else {
+                            return Ok(());
+                        };
+                        limiter
+                            .check(&client, Instant::now())
+                            .map_err(|retry_after| {
+                                let retry_after = retry_after.as_secs_f64().ceil() as u64;
+                                rweb::reject::custom(Error::TooManyRequests(retry_after))
+                            })
+                    }
+                },
+            )
+            .untuple_one()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr},
+        time::{Duration, Instant},
+    };
+
+    use crate::rate_limit::{matches_route, RateLimitClient, RateLimiter};
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::with_limits(3, 6, Duration::from_secs(60));
+        let anonymous = RateLimitClient::Anonymous(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let user = RateLimitClient::User("user@test".into());
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check(&anonymous, now).is_ok());
+        }
+        let retry_after = limiter.check(&anonymous, now).unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(20));
+        assert!(limiter
+            .check(&anonymous, now + Duration::from_secs(19))
+            .is_err());
+        assert!(limiter
+            .check(&anonymous, now + Duration::from_secs(20))
+            .is_ok());
+
+        for _ in 0..6 {
+            assert!(limiter.check(&user, now).is_ok());
+        }
+        assert!(limiter.check(&user, now).is_err());
+
+        let disabled = RateLimiter::with_limits(0, 0, Duration::from_secs(60));
+        for _ in 0..100 {
+            assert!(disabled.check(&anonymous, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_client() {
+        let remote: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        // without trusted proxies the header is ignored
+        assert_eq!(
+            RateLimitClient::new(None, Some("192.168.1.5"), Some(remote), &[]),
+            Some(RateLimitClient::Anonymous(remote.ip()))
+        );
+        assert_eq!(
+            RateLimitClient::new(None, Some("6.6.6.6, 192.168.1.5"), Some(remote), &[proxy]),
+            Some(RateLimitClient::Anonymous("192.168.1.5".parse().unwrap()))
+        );
+        let inner_proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        assert_eq!(
+            RateLimitClient::new(
+                None,
+                Some("192.168.1.5, 10.0.0.2"),
+                Some(remote),
+                &[proxy, inner_proxy]
+            ),
+            Some(RateLimitClient::Anonymous("192.168.1.5".parse().unwrap()))
+        );
+        assert_eq!(
+            RateLimitClient::new(None, Some("garbage"), Some(remote), &[proxy]),
+            Some(RateLimitClient::Anonymous(remote.ip()))
+        );
+        assert_eq!(
+            RateLimitClient::new(None, None, Some(remote), &[proxy]),
+            Some(RateLimitClient::Anonymous(remote.ip()))
+        );
+        assert_eq!(RateLimitClient::new(None, None, None, &[]), None);
+    }
+
+    #[test]
+    fn test_forged_forwarded_for() {
+        let limiter = RateLimiter::with_limits(3, 6, Duration::from_secs(60));
+        let remote: SocketAddr = "203.0.113.7:4000".parse().unwrap();
+        let now = Instant::now();
+        for i in 0..4 {
+            let forged = format!("198.51.100.{i}");
+            let client = RateLimitClient::new(None, Some(&forged), Some(remote), &[]).unwrap();
+            let result = limiter.check(&client, now);
+            assert_eq!(result.is_ok(), i < 3);
+        }
+
+        // behind a trusted proxy a forged leftmost entry is ignored as well
+        let proxy: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let limiter = limiter.with_trusted_proxies(&[proxy.ip()]);
+        for i in 0..4 {
+            let forged = format!("198.51.100.{i}, 192.0.2.1");
+            let client =
+                RateLimitClient::new(None, Some(&forged), Some(proxy), &[proxy.ip()]).unwrap();
+            assert_eq!(
+                client,
+                RateLimitClient::Anonymous("192.0.2.1".parse().unwrap())
+            );
+            let result = limiter.check(&client, now);
+            assert_eq!(result.is_ok(), i < 3);
+        }
+    }
+
+    #[test]
+    fn test_limited_routes() {
+        assert!(matches_route(
+            "/weather/history/{id}",
+            "/weather/history/42"
+        ));
+        assert!(matches_route("/weather/forecast", "/weather/forecast/"));
+        assert!(!matches_route(
+            "/weather/forecast",
+            "/weather/forecast/blend"
+        ));
+        assert!(!matches_route("/weather/history/{id}", "/weather/history"));
+
+        let limiter = RateLimiter::with_limits(3, 6, Duration::from_secs(60)).with_routes([
+            "/weather/forecast",
+            "/weather/history/{id}",
+            "/weather/timeseries.js",
+        ]);
+        assert!(limiter.is_limited("/weather/forecast"));
+        assert!(limiter.is_limited("/weather/history/42"));
+        assert!(!limiter.is_limited("/weather/timeseries.js"));
+        assert!(!limiter.is_limited("/weather/not-a-route"));
+        assert!(!limiter.is_limited("/favicon.ico"));
+    }
+}
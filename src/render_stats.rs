@@ -0,0 +1,156 @@
+use anyhow::Error;
+use futures::TryStreamExt;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use stack_string::StackString;
+use std::collections::{BTreeMap, VecDeque};
+use time::OffsetDateTime;
+
+use crate::{
+    config::Config, date_time_wrapper::DateTimeWrapper, model::RenderStatistics, pgpool::PgPool,
+};
+
+/// Number of recent renders the percentiles are computed over
+const RENDER_WINDOW: usize = 1000;
+
+/// Rendered payload sizes of one route
+#[derive(Default, Debug)]
+struct RouteRenderStats {
+    count: u64,
+    max_length: usize,
+    /// most recent lengths, at most `RENDER_WINDOW`
+    lengths: VecDeque<usize>,
+    /// persisted (p50, p95), reported until this process renders the route
+    persisted: Option<(usize, usize)>,
+    last_render_at: Option<OffsetDateTime>,
+}
+
+impl RouteRenderStats {
+    fn observe(&mut self, length: usize, now: OffsetDateTime) {
+        self.count += 1;
+        self.max_length = self.max_length.max(length);
+        if self.lengths.len() == RENDER_WINDOW {
+            self.lengths.pop_front();
+        }
+        self.lengths.push_back(length);
+        self.last_render_at = Some(now);
+    }
+
+    /// (p50, p95) of the recent lengths
+    fn percentiles(&self) -> (usize, usize) {
+        if self.lengths.is_empty() {
+            return self.persisted.unwrap_or_default();
+        }
+        let mut lengths: Vec<_> = self.lengths.iter().copied().collect();
+        lengths.sort_unstable();
+        (percentile(&lengths, 0.5), percentile(&lengths, 0.95))
+    }
+}
+
+/// Nearest rank percentile of sorted values
+fn percentile(sorted: &[usize], p: f64) -> usize {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Render stats keyed by route
+static RENDER_STATS: Lazy<Mutex<BTreeMap<StackString, RouteRenderStats>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Record the size of a server rendered page
+pub fn record_render(route: &str, length: usize) {
+    RENDER_STATS
+        .lock()
+        .entry(route.into())
+        .or_default()
+        .observe(length, OffsetDateTime::now_utc());
+}
+
+/// Current stats of every rendered route for this version and server
+#[must_use]
+pub fn get_render_statistics(config: &Config) -> Vec<RenderStatistics> {
+    let now = DateTimeWrapper::now();
+    RENDER_STATS
+        .lock()
+        .iter()
+        .map(|(route, stats)| {
+            let (p50, p95) = stats.percentiles();
+            RenderStatistics {
+                route: route.clone(),
+                version: env!("CARGO_PKG_VERSION").into(),
+                server: config.server.clone(),
+                count: stats.count as i64,
+                p50_length: p50 as i64,
+                p95_length: p95 as i64,
+                max_length: stats.max_length as i64,
+                last_render_at: stats.last_render_at.map(Into::into),
+                updated_at: now,
+            }
+        })
+        .collect()
+}
+
+/// Seed the stats with the rows persisted by an earlier run of this version
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn load_render_statistics(pool: &PgPool, config: &Config) -> Result<(), Error> {
+    let rows: Vec<_> =
+        RenderStatistics::get_by_version(pool, env!("CARGO_PKG_VERSION"), &config.server)
+            .await?
+            .try_collect()
+            .await?;
+    let mut render_stats = RENDER_STATS.lock();
+    for row in rows {
+        let stats = render_stats.entry(row.route).or_default();
+        stats.count += row.count as u64;
+        stats.max_length = stats.max_length.max(row.max_length as usize);
+        stats.persisted = Some((row.p50_length as usize, row.p95_length as usize));
+        if stats.last_render_at.is_none() {
+            stats.last_render_at = row.last_render_at.map(|t| *t);
+        }
+    }
+    Ok(())
+}
+
+/// # Errors
+/// Return error if db query fails
+pub async fn persist_render_statistics(pool: &PgPool, config: &Config) -> Result<(), Error> {
+    for row in get_render_statistics(config) {
+        row.upsert(pool).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::render_stats::{percentile, RouteRenderStats, RENDER_WINDOW};
+
+    #[test]
+    fn test_route_render_stats() {
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let mut stats = RouteRenderStats {
+            persisted: Some((100, 200)),
+            ..RouteRenderStats::default()
+        };
+        assert_eq!(stats.percentiles(), (100, 200));
+        for length in 1..=100 {
+            stats.observe(length * 10, now);
+        }
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.max_length, 1000);
+        assert_eq!(stats.percentiles(), (500, 950));
+        assert_eq!(stats.last_render_at, Some(now));
+
+        for _ in 0..RENDER_WINDOW {
+            stats.observe(42, now);
+        }
+        assert_eq!(stats.lengths.len(), RENDER_WINDOW);
+        assert_eq!(stats.percentiles(), (42, 42));
+        assert_eq!(stats.max_length, 1000);
+
+        assert_eq!(percentile(&[7], 0.95), 7);
+    }
+}
@@ -0,0 +1,642 @@
+use anyhow::format_err;
+use dioxus::prelude::{component, dioxus_elements, rsx, Element, IntoDynNode, Props, VirtualDom};
+use stack_string::{format_sstr, StackString};
+use std::{fmt::Write, str::FromStr};
+use time::{format_description::well_known::Rfc3339, Date, Duration, OffsetDateTime};
+
+use weather_api_types::units::Units;
+
+#[cfg(feature = "analysis")]
+use crate::polars_analysis::{HistoryAnomaly, ANOMALY_THRESHOLD, ANOMALY_WINDOW_DAYS};
+use crate::{
+    errors::ServiceError as Error,
+    notify::Attachment,
+    publish::{location_slug, DailySummary},
+    services::HistoryService,
+};
+
+const PLOT_WIDTH: f64 = 600.0;
+const PLOT_HEIGHT: f64 = 200.0;
+const HTML_CONTENT_TYPE: &str = "text/html";
+
+/// Span of a climate report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportPeriod {
+    /// The 7 days ending on the last day
+    Weekly,
+    /// The calendar month of the last day, up to the last day
+    Monthly,
+}
+
+impl ReportPeriod {
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Weekly => "weekly",
+            Self::Monthly => "monthly",
+        }
+    }
+
+    #[must_use]
+    pub fn start_date(self, end_date: Date) -> Date {
+        match self {
+            Self::Weekly => end_date - Duration::days(6),
+            Self::Monthly => end_date.replace_day(1).unwrap_or(end_date),
+        }
+    }
+}
+
+impl FromStr for ReportPeriod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weekly" | "week" => Ok(Self::Weekly),
+            "monthly" | "month" => Ok(Self::Monthly),
+            _ => Err(format_err!(
+                "Invalid report period {s}, expected weekly or monthly"
+            )),
+        }
+    }
+}
+
+/// Last complete day, the default end of a report
+#[must_use]
+pub fn default_report_end_date() -> Date {
+    OffsetDateTime::now_utc().date() - Duration::days(1)
+}
+
+/// Reading flagged by the anomaly detector
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportAnomaly {
+    pub created_at: OffsetDateTime,
+    /// temperature, pressure or wind_speed
+    pub field: StackString,
+    pub value: f64,
+    pub z_score: f64,
+}
+
+#[cfg(feature = "analysis")]
+impl From<HistoryAnomaly> for ReportAnomaly {
+    fn from(value: HistoryAnomaly) -> Self {
+        Self {
+            created_at: value.created_at.into(),
+            field: value.field,
+            value: value.value,
+            z_score: value.z_score,
+        }
+    }
+}
+
+impl ReportAnomaly {
+    fn format_value(&self, units: Units) -> String {
+        match self.field.as_str() {
+            "temperature" => format!(
+                "{:0.1} {}",
+                units.temperature(self.value),
+                units.temperature_unit()
+            ),
+            "wind_speed" => format!("{:0.1} {}", units.speed(self.value), units.speed_unit()),
+            "pressure" => format!("{:0.2} kPa", self.value),
+            _ => format!("{:0.2}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    Temperature,
+    Precipitation,
+    WindSpeed,
+}
+
+/// Extreme day of the report
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportRecord {
+    pub label: &'static str,
+    pub date: Date,
+    /// kelvin, mm or m/s
+    pub value: f64,
+    pub kind: RecordKind,
+}
+
+impl ReportRecord {
+    fn format_value(&self, units: Units) -> String {
+        match self.kind {
+            RecordKind::Temperature => format!(
+                "{:0.1} {}",
+                units.temperature(self.value),
+                units.temperature_unit()
+            ),
+            RecordKind::Precipitation => format!(
+                "{:0.2} {}",
+                units.precipitation(self.value),
+                units.precipitation_unit()
+            ),
+            RecordKind::WindSpeed => {
+                format!("{:0.1} {}", units.speed(self.value), units.speed_unit())
+            }
+        }
+    }
+}
+
+fn extreme(
+    days: &[DailySummary],
+    label: &'static str,
+    kind: RecordKind,
+    lowest: bool,
+    value: impl Fn(&DailySummary) -> f64,
+) -> Option<ReportRecord> {
+    let compare = |a: &&DailySummary, b: &&DailySummary| value(a).total_cmp(&value(b));
+    let day = if lowest {
+        days.iter().min_by(compare)
+    } else {
+        days.iter().max_by(compare)
+    }?;
+    Some(ReportRecord {
+        label,
+        date: day.date,
+        value: value(day),
+        kind,
+    })
+}
+
+/// Totals over every day of the report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReportTotals {
+    /// kelvin
+    pub temperature_low: f64,
+    /// kelvin
+    pub temperature_high: f64,
+    /// kelvin, weighted by the observations of each day
+    pub temperature_mean: f64,
+    /// mm
+    pub rain: f64,
+    /// mm
+    pub snow: f64,
+    pub observations: usize,
+}
+
+/// Weekly or monthly summary of one location
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClimateReport {
+    pub location_name: StackString,
+    pub period: ReportPeriod,
+    pub start_date: Date,
+    pub end_date: Date,
+    pub days: Vec<DailySummary>,
+    pub anomalies: Vec<ReportAnomaly>,
+}
+
+impl ClimateReport {
+    #[must_use]
+    pub fn title(&self) -> String {
+        let period = match self.period {
+            ReportPeriod::Weekly => "Weekly",
+            ReportPeriod::Monthly => "Monthly",
+        };
+        format!(
+            "{period} Climate Report {} {} - {}",
+            self.location_name, self.start_date, self.end_date
+        )
+    }
+
+    /// e.g. `astoria-ny-weekly-2024-06-07.html`
+    #[must_use]
+    pub fn filename(&self) -> StackString {
+        format_sstr!(
+            "{}-{}-{}.html",
+            location_slug(&self.location_name),
+            self.period.to_str(),
+            self.end_date
+        )
+    }
+
+    #[must_use]
+    pub fn totals(&self) -> Option<ReportTotals> {
+        if self.days.is_empty() {
+            return None;
+        }
+        let observations: usize = self.days.iter().map(|d| d.observations).sum();
+        let weighted: f64 = self
+            .days
+            .iter()
+            .map(|d| d.temperature_mean * d.observations as f64)
+            .sum();
+        Some(ReportTotals {
+            temperature_low: self
+                .days
+                .iter()
+                .map(|d| d.temperature_low)
+                .fold(f64::INFINITY, f64::min),
+            temperature_high: self
+                .days
+                .iter()
+                .map(|d| d.temperature_high)
+                .fold(f64::NEG_INFINITY, f64::max),
+            temperature_mean: weighted / observations.max(1) as f64,
+            rain: self.days.iter().map(|d| d.rain).sum(),
+            snow: self.days.iter().map(|d| d.snow).sum(),
+            observations,
+        })
+    }
+
+    /// Warmest, coldest, windiest and (when there was any precipitation)
+    /// wettest and snowiest day
+    #[must_use]
+    pub fn records(&self) -> Vec<ReportRecord> {
+        let days = &self.days;
+        [
+            extreme(days, "Warmest", RecordKind::Temperature, false, |d| {
+                d.temperature_high
+            }),
+            extreme(days, "Coldest", RecordKind::Temperature, true, |d| {
+                d.temperature_low
+            }),
+            extreme(days, "Wettest", RecordKind::Precipitation, false, |d| {
+                d.rain
+            })
+            .filter(|r| r.value > 0.0),
+            extreme(days, "Snowiest", RecordKind::Precipitation, false, |d| {
+                d.snow
+            })
+            .filter(|r| r.value > 0.0),
+            extreme(days, "Windiest", RecordKind::WindSpeed, false, |d| {
+                d.wind_speed_mean
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Standalone html page of the report
+    ///
+    /// # Errors
+    /// Return error if rendering fails
+    pub fn render_html(&self, units: Units) -> Result<String, Error> {
+        let mut app = VirtualDom::new_with_props(
+            ReportComponent,
+            ReportComponentProps {
+                report: self.clone(),
+                units,
+            },
+        );
+        app.rebuild_in_place();
+        let mut renderer = dioxus_ssr::Renderer::default();
+        let mut buffer = String::from("<!DOCTYPE html>\n");
+        renderer.render_to(&mut buffer, &app)?;
+        Ok(buffer)
+    }
+
+    /// The rendered report as an email attachment
+    ///
+    /// # Errors
+    /// Return error if rendering fails
+    pub fn attachment(&self, units: Units) -> Result<Attachment, Error> {
+        Ok(Attachment {
+            filename: self.filename(),
+            content_type: HTML_CONTENT_TYPE.into(),
+            body: self.render_html(units)?.into_bytes(),
+        })
+    }
+}
+
+/// Report of `name` for the `period` ending on `end_date`, leaving out rows
+/// inserted after `asof`
+///
+/// # Errors
+/// Return error if the history lookups fail
+pub async fn get_climate_report(
+    history: &dyn HistoryService,
+    name: &str,
+    server: Option<&str>,
+    period: ReportPeriod,
+    end_date: Date,
+    asof: Option<OffsetDateTime>,
+) -> Result<ClimateReport, Error> {
+    let start_date = period.start_date(end_date);
+    // end_date is compared with midnight, include the whole last day
+    let days = history
+        .get_daily_summaries(
+            name,
+            server,
+            Some(start_date),
+            Some(end_date + Duration::days(1)),
+            asof,
+        )
+        .await?
+        .into_iter()
+        .filter(|d| d.date >= start_date && d.date <= end_date)
+        .collect();
+    #[cfg(feature = "analysis")]
+    let anomalies = history
+        .get_anomalies(
+            name,
+            server,
+            start_date,
+            end_date,
+            ANOMALY_WINDOW_DAYS,
+            ANOMALY_THRESHOLD,
+            asof,
+        )
+        .await?
+        .anomalies
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    #[cfg(not(feature = "analysis"))]
+    let anomalies = Vec::new();
+    Ok(ClimateReport {
+        location_name: name.into(),
+        period,
+        start_date,
+        end_date,
+        days,
+        anomalies,
+    })
+}
+
+/// Svg of the daily highs and lows over bars of the daily precipitation
+#[must_use]
+pub fn report_plot_svg(days: &[DailySummary], units: Units) -> String {
+    let mut svg = String::new();
+    if days.is_empty() {
+        return svg;
+    }
+    let min = days
+        .iter()
+        .map(|d| units.temperature(d.temperature_low))
+        .fold(f64::INFINITY, f64::min);
+    let max = days
+        .iter()
+        .map(|d| units.temperature(d.temperature_high))
+        .fold(f64::NEG_INFINITY, f64::max);
+    let span = (max - min).max(1.0);
+    let max_precipitation = days.iter().map(|d| d.rain + d.snow).fold(0.0, f64::max);
+    let step = PLOT_WIDTH / days.len() as f64;
+    let x = |i: usize| step * (i as f64 + 0.5);
+    let y = |t: f64| PLOT_HEIGHT - 10.0 - (t - min) / span * (PLOT_HEIGHT - 20.0);
+    let unit = units.temperature_unit();
+
+    write!(
+        &mut svg,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{PLOT_WIDTH}" height="{PLOT_HEIGHT}" viewBox="0 0 {PLOT_WIDTH} {PLOT_HEIGHT}"><rect width="{PLOT_WIDTH}" height="{PLOT_HEIGHT}" fill="#f8f8f8"/>"##
+    )
+    .unwrap_or(());
+    if max_precipitation > 0.0 {
+        for (i, day) in days.iter().enumerate() {
+            let precipitation = day.rain + day.snow;
+            if precipitation <= 0.0 {
+                continue;
+            }
+            let height = precipitation / max_precipitation * PLOT_HEIGHT / 3.0;
+            write!(
+                &mut svg,
+                r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{height:.1}" fill="#7fb3d5"><title>{} {:0.2} {}</title></rect>"##,
+                x(i) - step * 0.3,
+                PLOT_HEIGHT - height,
+                step * 0.6,
+                day.date,
+                units.precipitation(precipitation),
+                units.precipitation_unit(),
+            )
+            .unwrap_or(());
+        }
+    }
+    for (color, high) in [("#c0392b", true), ("#1f4e9c", false)] {
+        let points: Vec<_> = days
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                let t = if high {
+                    d.temperature_high
+                } else {
+                    d.temperature_low
+                };
+                format!("{:.1},{:.1}", x(i), y(units.temperature(t)))
+            })
+            .collect();
+        write!(
+            &mut svg,
+            r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="2"/>"#,
+            points.join(" ")
+        )
+        .unwrap_or(());
+    }
+    write!(
+        &mut svg,
+        r#"<text x="2" y="12" font-size="10">{max:0.0} {unit}</text><text x="2" y="{}" font-size="10">{min:0.0} {unit}</text></svg>"#,
+        PLOT_HEIGHT - 2.0,
+    )
+    .unwrap_or(());
+    svg
+}
+
+#[component]
+pub fn ReportComponent(report: ClimateReport, units: Units) -> Element {
+    let title = report.title();
+    let svg = report_plot_svg(&report.days, units);
+    let temperature_unit = units.temperature_unit();
+    let precipitation_unit = units.precipitation_unit();
+    let rows = report.days.iter().map(|day| {
+        let date = day.date;
+        let low = format!("{:0.1}", units.temperature(day.temperature_low));
+        let high = format!("{:0.1}", units.temperature(day.temperature_high));
+        let mean = format!("{:0.1}", units.temperature(day.temperature_mean));
+        let humidity = format!("{:0.0}", day.humidity_mean);
+        let rain = format!("{:0.2}", units.precipitation(day.rain));
+        let snow = format!("{:0.2}", units.precipitation(day.snow));
+        let conditions = &day.conditions;
+        rsx! {
+            tr {
+                key: "report-day-{date}",
+                td {"{date}"},
+                td {"{low}"},
+                td {"{high}"},
+                td {"{mean}"},
+                td {"{humidity}"},
+                td {"{rain}"},
+                td {"{snow}"},
+                td {"{conditions}"},
+            }
+        }
+    });
+    let totals = report.totals().map(|totals| {
+        let low = format!("{:0.1}", units.temperature(totals.temperature_low));
+        let high = format!("{:0.1}", units.temperature(totals.temperature_high));
+        let mean = format!("{:0.1}", units.temperature(totals.temperature_mean));
+        let rain = format!("{:0.2}", units.precipitation(totals.rain));
+        let snow = format!("{:0.2}", units.precipitation(totals.snow));
+        let observations = totals.observations;
+        rsx! {
+            tr {
+                th {"Total"},
+                th {"{low}"},
+                th {"{high}"},
+                th {"{mean}"},
+                th {""},
+                th {"{rain}"},
+                th {"{snow}"},
+                th {"{observations} observations"},
+            }
+        }
+    });
+    let records = report.records().into_iter().map(|record| {
+        let label = record.label;
+        let date = record.date;
+        let value = record.format_value(units);
+        rsx! {
+            li {
+                key: "report-record-{label}",
+                "{label}: {value} on {date}"
+            }
+        }
+    });
+    let anomalies = report.anomalies.iter().enumerate().map(|(i, anomaly)| {
+        let created_at = anomaly.created_at.format(&Rfc3339).unwrap_or_default();
+        let field = &anomaly.field;
+        let value = anomaly.format_value(units);
+        let z_score = format!("{:0.1}", anomaly.z_score);
+        rsx! {
+            li {
+                key: "report-anomaly-{i}",
+                "{created_at} {field} {value} ({z_score} standard deviations)"
+            }
+        }
+    });
+    let no_anomalies = report.anomalies.is_empty();
+    rsx! {
+        head {
+            title: "{title}",
+            style {
+                {include_str!("../templates/style.css")}
+            }
+        },
+        body {
+            h3 {"{title}"},
+            div {
+                dangerous_inner_html: "{svg}",
+            },
+            table {
+                thead {
+                    tr {
+                        th {"Date"},
+                        th {"Low ({temperature_unit})"},
+                        th {"High ({temperature_unit})"},
+                        th {"Mean ({temperature_unit})"},
+                        th {"Humidity (%)"},
+                        th {"Rain ({precipitation_unit})"},
+                        th {"Snow ({precipitation_unit})"},
+                        th {"Conditions"},
+                    }
+                },
+                tbody {
+                    {rows}
+                },
+                tfoot {
+                    {totals}
+                }
+            },
+            h4 {"Records"},
+            ul {
+                {records}
+            },
+            h4 {"Anomalies"},
+            if no_anomalies {
+                p {"No anomalous readings"}
+            } else {
+                ul {
+                    {anomalies}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::{date, datetime};
+
+    use weather_api_types::units::Units;
+
+    use crate::{
+        publish::DailySummary,
+        report::{ClimateReport, RecordKind, ReportAnomaly, ReportPeriod},
+    };
+
+    fn day(date: time::Date, low: f64, high: f64, rain: f64) -> DailySummary {
+        DailySummary {
+            location_name: "Astoria, NY".into(),
+            date,
+            temperature_low: low,
+            temperature_high: high,
+            temperature_mean: (low + high) / 2.0,
+            humidity_mean: 60.0,
+            wind_speed_mean: high - low,
+            rain,
+            snow: 0.0,
+            conditions: "Clear".into(),
+            observations: 24,
+        }
+    }
+
+    #[test]
+    fn test_climate_report() -> Result<(), Error> {
+        assert_eq!(
+            ReportPeriod::Weekly.start_date(date!(2024 - 06 - 07)),
+            date!(2024 - 06 - 01)
+        );
+        assert_eq!(
+            ReportPeriod::Monthly.start_date(date!(2024 - 06 - 30)),
+            date!(2024 - 06 - 01)
+        );
+        assert_eq!("month".parse::<ReportPeriod>()?, ReportPeriod::Monthly);
+        assert!("daily".parse::<ReportPeriod>().is_err());
+
+        let report = ClimateReport {
+            location_name: "Astoria, NY".into(),
+            period: ReportPeriod::Weekly,
+            start_date: date!(2024 - 06 - 01),
+            end_date: date!(2024 - 06 - 03),
+            days: vec![
+                day(date!(2024 - 06 - 01), 290.0, 300.0, 0.0),
+                day(date!(2024 - 06 - 02), 285.0, 295.0, 12.5),
+                day(date!(2024 - 06 - 03), 288.0, 305.0, 1.0),
+            ],
+            anomalies: vec![ReportAnomaly {
+                created_at: datetime!(2024-06-02 12:00 UTC),
+                field: "temperature".into(),
+                value: 320.0,
+                z_score: 6.5,
+            }],
+        };
+        assert_eq!(report.filename(), "astoria-ny-weekly-2024-06-03.html");
+
+        let totals = report.totals().unwrap();
+        assert!((totals.temperature_low - 285.0).abs() < 1e-9);
+        assert!((totals.temperature_high - 305.0).abs() < 1e-9);
+        assert!((totals.rain - 13.5).abs() < 1e-9);
+        assert_eq!(totals.observations, 72);
+
+        let records = report.records();
+        let labels: Vec<_> = records.iter().map(|r| r.label).collect();
+        assert_eq!(labels, ["Warmest", "Coldest", "Wettest", "Windiest"]);
+        assert_eq!(records[0].date, date!(2024 - 06 - 03));
+        assert_eq!(records[1].date, date!(2024 - 06 - 02));
+        assert_eq!(records[2].kind, RecordKind::Precipitation);
+
+        let html = report.render_html(Units::Metric)?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Weekly Climate Report Astoria, NY"));
+        assert!(html.contains("<polyline"));
+        assert!(html.contains("46.9 C"));
+        assert!(html.contains("Wettest: 12.50 mm on 2024-06-02"));
+
+        let attachment = report.attachment(Units::Imperial)?;
+        assert_eq!(attachment.filename, report.filename());
+        assert_eq!(attachment.content_type, "text/html");
+        Ok(())
+    }
+}
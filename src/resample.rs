@@ -0,0 +1,374 @@
+use anyhow::{format_err, Error};
+use polars::{
+    df as dataframe,
+    prelude::{col, concat, FillNullStrategy, IntoLazy, SortMultipleOptions, UnionArgs},
+};
+use rweb::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    openapi::{
+        ComponentDescriptor, ComponentOrInlineSchema, Entity, MediaType, Response, ResponseEntity,
+        Responses, Schema, Type,
+    },
+    reply, Reply,
+};
+use stack_string::format_sstr;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+use time::{Duration, OffsetDateTime};
+
+use weather_api_types::{plot::PlotPoint, units::Units};
+use weather_util_rust::weather_data::WeatherData;
+
+use crate::{
+    date_time_wrapper::DateTimeWrapper, get_history_humidity_plot, get_history_precip_plot,
+    get_history_pressure_plot, get_history_temperature_plot, get_history_wind_plot,
+    PlotPointWrapper,
+};
+
+/// Grid points returned by one resample request
+pub const MAX_RESAMPLE_POINTS: usize = 100_000;
+
+pub const CSV_CONTENT_TYPE: &str = "text/csv";
+const RESAMPLE_DESCRIPTION: &str = "Observations on a regular time grid";
+
+/// How grid points between two observations are filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleMethod {
+    /// Linear in time between the surrounding observations
+    #[default]
+    Linear,
+    /// Value of the last observation at or before the grid point
+    ForwardFill,
+}
+
+impl FromStr for ResampleMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "ffill" => Ok(Self::ForwardFill),
+            _ => Err(format_err!("Invalid method {s}, expected linear or ffill")),
+        }
+    }
+}
+
+/// Recorded quantity resampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleField {
+    #[default]
+    Temperature,
+    Precipitation,
+    Humidity,
+    Pressure,
+    Wind,
+}
+
+impl ResampleField {
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Temperature => "temperature",
+            Self::Precipitation => "precipitation",
+            Self::Humidity => "humidity",
+            Self::Pressure => "pressure",
+            Self::Wind => "wind",
+        }
+    }
+
+    /// Name of the field in the json precision overrides
+    #[must_use]
+    pub fn metric(self) -> &'static str {
+        match self {
+            Self::Wind => "wind_speed",
+            field => field.to_str(),
+        }
+    }
+
+    /// Series of `history` in `units`, like the history plots
+    #[must_use]
+    pub fn get_series(self, history: &[WeatherData], units: Units) -> Vec<PlotPoint> {
+        match self {
+            Self::Temperature => get_history_temperature_plot(history, units),
+            Self::Precipitation => get_history_precip_plot(history, units),
+            Self::Humidity => get_history_humidity_plot(history),
+            Self::Pressure => get_history_pressure_plot(history),
+            Self::Wind => get_history_wind_plot(history, units),
+        }
+    }
+}
+
+impl fmt::Display for ResampleField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+impl FromStr for ResampleField {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::Temperature,
+            Self::Precipitation,
+            Self::Humidity,
+            Self::Pressure,
+            Self::Wind,
+        ]
+        .into_iter()
+        .find(|field| field.to_str() == s)
+        .ok_or_else(|| format_err!("Invalid field {s}"))
+    }
+}
+
+/// Interval of `<n><unit>` with unit `s`, `m`, `h` or `d`, e.g. `15m`
+///
+/// # Errors
+/// Returns error if the interval isn't a positive number of one of the units
+pub fn parse_interval(s: &str) -> Result<Duration, Error> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format_err!("Interval {s} has no unit"))?;
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().map_err(|_| format_err!("Invalid interval {s}"))?;
+    let interval = match unit {
+        "s" => Duration::seconds(n),
+        "m" => Duration::minutes(n),
+        "h" => Duration::hours(n),
+        "d" => Duration::days(n),
+        _ => return Err(format_err!("Invalid interval unit {unit}")),
+    };
+    if interval.is_positive() {
+        Ok(interval)
+    } else {
+        Err(format_err!("Interval {s} isn't positive"))
+    }
+}
+
+/// `points` on a grid of multiples of `interval` (utc) from the first to the
+/// last observation, grid points that can't be filled (e.g. after the last
+/// observation when interpolating) are left out
+///
+/// The grid is merged into the observations as null rows which polars fills
+/// by interpolating over the timestamps or forward filling, observations at
+/// the same second keep the last one
+///
+/// # Errors
+/// Returns error if the grid has more than `MAX_RESAMPLE_POINTS` points or
+/// polars fails
+pub fn resample_points(
+    points: &[PlotPoint],
+    interval: Duration,
+    method: ResampleMethod,
+) -> Result<Vec<PlotPoint>, Error> {
+    let step = interval.whole_seconds();
+    if step < 1 {
+        return Err(format_err!("Interval must be at least a second"));
+    }
+    let observed: BTreeMap<i64, f64> = points
+        .iter()
+        .map(|p| (p.datetime.unix_timestamp(), p.value))
+        .collect();
+    let (Some(&start), Some(&end)) = (observed.keys().next(), observed.keys().next_back()) else {
+        return Ok(Vec::new());
+    };
+    let offset = points[0].datetime.offset();
+    let first = start.div_euclid(step) * step + if start % step == 0 { 0 } else { step };
+    let size = if first > end {
+        0
+    } else {
+        ((end - first) / step + 1) as usize
+    };
+    if size > MAX_RESAMPLE_POINTS {
+        return Err(format_err!(
+            "{size} grid points, more than {MAX_RESAMPLE_POINTS}"
+        ));
+    }
+    let grid: Vec<i64> = (0..size as i64).map(|i| first + i * step).collect();
+    let on_grid: HashSet<i64> = grid.iter().copied().collect();
+    let missing: Vec<i64> = grid
+        .iter()
+        .copied()
+        .filter(|t| !observed.contains_key(t))
+        .collect();
+
+    let timestamps: Vec<i64> = observed.keys().copied().collect();
+    let values: Vec<Option<f64>> = observed.values().copied().map(Some).collect();
+    let is_grid: Vec<bool> = timestamps.iter().map(|t| on_grid.contains(t)).collect();
+    let observed = dataframe!(
+        "timestamp" => &timestamps,
+        "value" => &values,
+        "grid" => &is_grid,
+    )?;
+    let missing = dataframe!(
+        "timestamp" => &missing,
+        "value" => vec![None::<f64>; missing.len()],
+        "grid" => vec![true; missing.len()],
+    )?;
+    let filled = match method {
+        ResampleMethod::Linear => col("value").interpolate_by(col("timestamp")),
+        ResampleMethod::ForwardFill => {
+            col("value").fill_null_with_strategy(FillNullStrategy::Forward(None))
+        }
+    };
+    let df = concat([observed.lazy(), missing.lazy()], UnionArgs::default())?
+        .sort(["timestamp"], SortMultipleOptions::default())
+        .with_column(filled.alias("value"))
+        .filter(col("grid").and(col("value").is_not_null()))
+        .collect()?;
+
+    let timestamps = df.column("timestamp")?.i64()?;
+    let values = df.column("value")?.f64()?;
+    timestamps
+        .into_iter()
+        .zip(values)
+        .filter_map(|(t, v)| Some((t?, v?)))
+        .map(|(t, value)| -> Result<PlotPoint, Error> {
+            let datetime = OffsetDateTime::from_unix_timestamp(t)?.to_offset(offset);
+            Ok(PlotPoint { datetime, value })
+        })
+        .collect()
+}
+
+/// Resampled series as a json array of plot points or `datetime,value` csv
+pub enum ResampleResponse {
+    Json(Vec<u8>),
+    Csv(Vec<u8>),
+}
+
+impl ResampleResponse {
+    /// # Errors
+    /// Returns error if serialization fails
+    pub fn json<T: serde::Serialize>(plots: &T) -> Result<Self, Error> {
+        Ok(Self::Json(serde_json::to_vec(plots)?))
+    }
+
+    /// # Errors
+    /// Returns error if serialization fails
+    pub fn csv(field: ResampleField, points: &[PlotPoint]) -> Result<Self, Error> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["datetime", field.to_str()])?;
+        for point in points {
+            let datetime = format_sstr!("{}", DateTimeWrapper::from(point.datetime));
+            let value = format_sstr!("{}", point.value);
+            writer.write_record([datetime.as_str(), value.as_str()])?;
+        }
+        Ok(Self::Csv(writer.into_inner()?))
+    }
+}
+
+impl Reply for ResampleResponse {
+    fn into_response(self) -> reply::Response {
+        match self {
+            Self::Json(body) => reply::with_header(body, CONTENT_TYPE, "application/json"),
+            Self::Csv(body) => reply::with_header(body, CONTENT_TYPE, CSV_CONTENT_TYPE),
+        }
+        .into_response()
+    }
+}
+
+impl Entity for ResampleResponse {
+    fn type_name() -> Cow<'static, str> {
+        "resample".into()
+    }
+
+    fn describe(comp_d: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        Vec::<PlotPointWrapper>::describe(comp_d)
+    }
+}
+
+impl ResponseEntity for ResampleResponse {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        let mut response = Response {
+            description: Cow::Borrowed(RESAMPLE_DESCRIPTION),
+            ..Response::default()
+        };
+        response.content.insert(
+            Cow::Borrowed("application/json"),
+            MediaType {
+                schema: Some(Self::describe(comp_d)),
+                ..MediaType::default()
+            },
+        );
+        response.content.insert(
+            Cow::Borrowed(CSV_CONTENT_TYPE),
+            MediaType {
+                schema: Some(ComponentOrInlineSchema::Inline(Schema {
+                    schema_type: Some(Type::String),
+                    description: "datetime,value rows".into(),
+                    ..Schema::default()
+                })),
+                ..MediaType::default()
+            },
+        );
+        let mut map = Responses::new();
+        map.insert(Cow::Owned(StatusCode::OK.as_str().into()), response);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::{macros::datetime, Duration};
+
+    use weather_api_types::plot::PlotPoint;
+
+    use crate::resample::{
+        parse_interval, resample_points, ResampleField, ResampleMethod, ResampleResponse,
+    };
+
+    #[test]
+    fn test_resample_points() -> Result<(), Error> {
+        assert_eq!(parse_interval("15m")?, Duration::minutes(15));
+        assert_eq!(parse_interval("1d")?, Duration::days(1));
+        assert!(parse_interval("15").is_err());
+        assert!(parse_interval("0h").is_err());
+        assert!(parse_interval("5w").is_err());
+
+        let start = datetime!(2024-06-01 12:03 -4);
+        let points: Vec<_> = [(0, 10.0), (20, 20.0), (40, 40.0)]
+            .into_iter()
+            .map(|(minutes, value)| PlotPoint {
+                datetime: start + Duration::minutes(minutes),
+                value,
+            })
+            .collect();
+
+        let linear = resample_points(&points, Duration::minutes(15), ResampleMethod::Linear)?;
+        let times: Vec<_> = linear.iter().map(|p| p.datetime).collect();
+        assert_eq!(
+            times,
+            [
+                datetime!(2024-06-01 12:15 -4),
+                datetime!(2024-06-01 12:30 -4),
+            ]
+        );
+        assert!((linear[0].value - 16.0).abs() < 1e-9);
+        assert!((linear[1].value - 27.0).abs() < 1e-9);
+
+        let ffill = resample_points(&points, Duration::minutes(15), ResampleMethod::ForwardFill)?;
+        let values: Vec<_> = ffill.iter().map(|p| p.value).collect();
+        assert_eq!(values, [10.0, 20.0]);
+
+        let on_grid = resample_points(&points, Duration::minutes(1), ResampleMethod::Linear)?;
+        assert_eq!(on_grid.len(), 41);
+        assert_eq!(on_grid[20], points[1]);
+
+        assert!(resample_points(&[], Duration::minutes(1), ResampleMethod::Linear)?.is_empty());
+        assert!(resample_points(&points, Duration::seconds(1), ResampleMethod::Linear).is_ok());
+
+        let ResampleResponse::Csv(csv) = ResampleResponse::csv(ResampleField::Temperature, &ffill)?
+        else {
+            panic!("expected csv");
+        };
+        let csv = String::from_utf8(csv)?;
+        assert!(csv.starts_with("datetime,temperature\n"));
+        assert_eq!(csv.lines().count(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::{
+    config::Config,
+    model::{KeyItemCache, WeatherLocationCache},
+    pgpool::PgPool,
+};
+
+/// Rows removed by a run of `expire_caches`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    /// `weather_location_cache` entries not looked up within
+    /// `location_cache_retention_days`
+    pub location_cache: u64,
+    /// `key_item_cache` rows of files deleted locally and remotely
+    pub key_item_cache: u64,
+}
+
+impl fmt::Display for RetentionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} location cache entries and {} key item cache entries",
+            self.location_cache, self.key_item_cache
+        )
+    }
+}
+
+/// Expire unused `weather_location_cache` entries and orphaned
+/// `key_item_cache` rows according to the maintenance settings
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn expire_caches(pool: &PgPool, config: &Config) -> Result<RetentionReport, Error> {
+    let mut report = RetentionReport::default();
+    if config.location_cache_retention_days > 0 {
+        report.location_cache =
+            WeatherLocationCache::delete_unused(pool, config.location_cache_retention_days).await?;
+    }
+    if config.purge_orphaned_key_items {
+        report.key_item_cache = KeyItemCache::delete_orphaned(pool).await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::retention::RetentionReport;
+
+    #[test]
+    fn test_retention_report() {
+        let report = RetentionReport {
+            location_cache: 3,
+            key_item_cache: 12,
+        };
+        assert_eq!(
+            report.to_string(),
+            "removed 3 location cache entries and 12 key item cache entries"
+        );
+    }
+}
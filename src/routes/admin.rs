@@ -0,0 +1,491 @@
+use cached::Cached;
+use futures::TryStreamExt;
+use rweb::{
+    delete, filters::BoxedFilter, get, post, reply::Response, Filter, Json, Query, Reply, Schema,
+};
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use std::sync::atomic::Ordering;
+
+use rweb_helper::{json_response::JsonResponse as JsonBase, DateTimeType, RwebResponse};
+use weather_api_types::get_parameters;
+
+#[cfg(feature = "s3-sync")]
+use crate::s3_sync::{ArchiveStatus, S3Sync};
+use crate::{
+    app::{
+        invalidate_weather_caches, AppState, GET_WEATHER_DATA, GET_WEATHER_FORECAST,
+        SKIPPED_RECORDS,
+    },
+    config::{parse_interval, RecordedLocation},
+    errors::ServiceError as Error,
+    logged_user::LoggedUser,
+    logging::{get_log_filter, get_startup_log_filter, set_log_filter},
+    metrics::{render_metrics, CacheMetrics, MetricsResponse, PoolMetrics},
+    model::{RecordedLocationDB, RenderStatistics, WeatherLocationCache},
+    privacy::PrivacyPolicy,
+    providers::{get_provider_health, ProviderHealth},
+    render_stats::get_render_statistics,
+    routes::WarpResult,
+};
+
+/// Server statistics, metrics and maintenance, tagged `admin`
+pub fn get_admin_path(app: &AppState) -> BoxedFilter<(Response,)> {
+    let statistics_path = statistics(app.clone()).boxed();
+    let metrics_path = metrics(app.clone()).boxed();
+    let cache_invalidate_path = cache_invalidate(app.clone()).boxed();
+    let logging_get_path = logging_get().boxed();
+    let logging_set_path = logging_set().boxed();
+    let version_path = version(app.clone()).boxed();
+    let record_locations_get_path = record_locations_get(app.clone()).boxed();
+    let record_locations_post_path = record_locations_post(app.clone()).boxed();
+    let record_locations_delete_path = record_locations_delete(app.clone()).boxed();
+
+    let path = statistics_path
+        .or(metrics_path)
+        .or(cache_invalidate_path)
+        .or(logging_get_path)
+        .or(logging_set_path)
+        .or(version_path)
+        .or(record_locations_get_path)
+        .or(record_locations_post_path)
+        .or(record_locations_delete_path)
+        .map(Reply::into_response)
+        .boxed();
+    #[cfg(feature = "s3-sync")]
+    let path = path
+        .or(archive_verify(app.clone()).map(Reply::into_response))
+        .unify()
+        .boxed();
+    path
+}
+
+#[derive(Serialize, Deserialize, Schema, Clone)]
+#[schema(component = "Statistics")]
+pub struct StatisticsObject {
+    #[schema(description = "Weather Data Cache Hits")]
+    pub data_cache_hits: u64,
+    #[schema(description = "Weather Data Cache Misses")]
+    pub data_cache_misses: u64,
+    #[schema(description = "Forecast Cache Hits")]
+    pub forecast_cache_hits: u64,
+    #[schema(description = "Forecast Cache Misses")]
+    pub forecast_cache_misses: u64,
+    #[schema(description = "Rendered Page Sizes of this Version")]
+    pub render_statistics: Vec<RenderStatisticsObject>,
+    #[schema(description = "Persisted Rendered Page Sizes of every Version")]
+    pub render_statistics_history: Vec<RenderStatisticsObject>,
+    #[schema(description = "Rows Skipped by Delta Recording")]
+    pub skipped_records: u64,
+    #[schema(description = "Weather Provider Health")]
+    pub provider_health: Vec<ProviderHealth>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "RenderStatistics")]
+pub struct RenderStatisticsObject {
+    #[schema(description = "Route")]
+    pub route: StackString,
+    #[schema(description = "Version")]
+    pub version: StackString,
+    #[schema(description = "Server")]
+    pub server: StackString,
+    #[schema(description = "Number of Renders")]
+    pub count: i64,
+    #[schema(description = "Median Size of Recent Renders (bytes)")]
+    pub p50_length: i64,
+    #[schema(description = "95th Percentile Size of Recent Renders (bytes)")]
+    pub p95_length: i64,
+    #[schema(description = "Maximum Size (bytes)")]
+    pub max_length: i64,
+    #[schema(description = "Last Render")]
+    pub last_render_at: Option<DateTimeType>,
+}
+
+impl From<RenderStatistics> for RenderStatisticsObject {
+    fn from(value: RenderStatistics) -> Self {
+        Self {
+            route: value.route,
+            version: value.version,
+            server: value.server,
+            count: value.count,
+            p50_length: value.p50_length,
+            p95_length: value.p95_length,
+            max_length: value.max_length,
+            last_render_at: value.last_render_at.map(|t| t.to_offsetdatetime().into()),
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Get Cache Statistics")]
+struct StatisticsResponse(JsonBase<StatisticsObject, Error>);
+
+#[get("/weather/statistics")]
+#[openapi(tags("admin"))]
+pub async fn statistics(#[data] data: AppState) -> WarpResult<StatisticsResponse> {
+    let render_statistics_history: Vec<RenderStatisticsObject> = if data.pool.is_enabled() {
+        RenderStatistics::get_all(&data.pool)
+            .await
+            .map_err(Into::<Error>::into)?
+            .map_ok(Into::into)
+            .try_collect()
+            .await
+            .map_err(Into::<Error>::into)?
+    } else {
+        Vec::new()
+    };
+    let render_statistics = get_render_statistics(&data.config)
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let data_cache = GET_WEATHER_DATA.lock().await;
+    let forecast_cache = GET_WEATHER_FORECAST.lock().await;
+
+    let stat = StatisticsObject {
+        data_cache_hits: data_cache.cache_hits().unwrap_or(0),
+        data_cache_misses: data_cache.cache_misses().unwrap_or(0),
+        forecast_cache_hits: forecast_cache.cache_hits().unwrap_or(0),
+        forecast_cache_misses: forecast_cache.cache_misses().unwrap_or(0),
+        render_statistics,
+        render_statistics_history,
+        skipped_records: SKIPPED_RECORDS.load(Ordering::Relaxed),
+        provider_health: get_provider_health(),
+    };
+
+    Ok(JsonBase::new(stat).into())
+}
+
+#[get("/weather/metrics")]
+#[openapi(tags("admin"))]
+pub async fn metrics(#[data] data: AppState) -> WarpResult<MetricsResponse> {
+    let caches = {
+        let data_cache = GET_WEATHER_DATA.lock().await;
+        let forecast_cache = GET_WEATHER_FORECAST.lock().await;
+        [
+            CacheMetrics {
+                name: "weather_data",
+                hits: data_cache.cache_hits().unwrap_or(0),
+                misses: data_cache.cache_misses().unwrap_or(0),
+            },
+            CacheMetrics {
+                name: "weather_forecast",
+                hits: forecast_cache.cache_hits().unwrap_or(0),
+                misses: forecast_cache.cache_misses().unwrap_or(0),
+            },
+        ]
+    };
+    let pool = data
+        .pool
+        .status()
+        .map_or_else(PoolMetrics::default, |status| PoolMetrics {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available,
+            waiting: status.waiting,
+        });
+    let body = render_metrics(&caches, &pool, SKIPPED_RECORDS.load(Ordering::Relaxed));
+    Ok(MetricsResponse(body))
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "CacheInvalidateRequest")]
+struct CacheInvalidateRequest {
+    #[schema(description = "Location to evict, every entry when absent")]
+    loc: Option<StackString>,
+    #[schema(description = "Also remove geocoded entries from the location cache (default true)")]
+    location_cache: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "CacheInvalidateResult")]
+struct CacheInvalidateObject {
+    #[schema(description = "Weather Data Cache Entries Removed")]
+    data_cache: usize,
+    #[schema(description = "Forecast Cache Entries Removed")]
+    forecast_cache: usize,
+    #[schema(description = "Location Cache Rows Removed")]
+    location_cache: u64,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Invalidated Cache Entries")]
+struct CacheInvalidateResponse(JsonBase<CacheInvalidateObject, Error>);
+
+#[post("/weather/cache/invalidate")]
+#[openapi(tags("admin"))]
+pub async fn cache_invalidate(
+    #[data] data: AppState,
+    payload: Json<CacheInvalidateRequest>,
+    #[filter = "LoggedUser::session_filter"] _user: LoggedUser,
+) -> WarpResult<CacheInvalidateResponse> {
+    let payload = payload.into_inner();
+    let loc = payload.loc.as_deref().map(get_parameters);
+    let (data_cache, forecast_cache) = invalidate_weather_caches(loc.as_ref()).await;
+    let location_cache = if payload.location_cache.unwrap_or(true) && data.pool.is_enabled() {
+        if let Some(loc) = &loc {
+            WeatherLocationCache::delete_by_weather_location(&data.pool, loc).await
+        } else {
+            WeatherLocationCache::delete_all(&data.pool).await
+        }
+        .map_err(Into::<Error>::into)?
+    } else {
+        0
+    };
+    Ok(JsonBase::new(CacheInvalidateObject {
+        data_cache,
+        forecast_cache,
+        location_cache,
+    })
+    .into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "Logging")]
+struct LoggingObject {
+    #[schema(description = "Current Log Filter")]
+    filter: Option<StackString>,
+    #[schema(description = "Log Filter at Startup (RUST_LOG)")]
+    startup_filter: Option<StackString>,
+}
+
+impl LoggingObject {
+    fn current() -> Self {
+        Self {
+            filter: get_log_filter(),
+            startup_filter: get_startup_log_filter(),
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Log Filter")]
+struct LoggingResponse(JsonBase<LoggingObject, Error>);
+
+#[get("/weather/admin/logging")]
+#[openapi(tags("admin"))]
+pub async fn logging_get(
+    #[filter = "LoggedUser::session_filter"] _user: LoggedUser,
+) -> WarpResult<LoggingResponse> {
+    Ok(JsonBase::new(LoggingObject::current()).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "LoggingRequest")]
+/// The startup filter is restored when `filter` is absent
+struct LoggingRequest {
+    #[schema(description = "Log Filter (e.g. info,weather_api_rust::s3_sync=debug)")]
+    filter: Option<StackString>,
+}
+
+#[post("/weather/admin/logging")]
+#[openapi(tags("admin"))]
+pub async fn logging_set(
+    payload: Json<LoggingRequest>,
+    #[filter = "LoggedUser::session_filter"] _user: LoggedUser,
+) -> WarpResult<LoggingResponse> {
+    let payload = payload.into_inner();
+    set_log_filter(payload.filter.as_deref())
+        .map_err(|e| Error::BadRequest(format_sstr!("{e}")))?;
+    Ok(JsonBase::new(LoggingObject::current()).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "Version")]
+struct VersionObject {
+    #[schema(description = "Service Version")]
+    version: StackString,
+    #[schema(description = "Handling of Client IPs and Locations")]
+    privacy: PrivacyPolicy,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Service Version and Privacy Policy")]
+struct VersionResponse(JsonBase<VersionObject, Error>);
+
+#[get("/weather/version")]
+#[openapi(tags("admin"))]
+pub async fn version(#[data] data: AppState) -> WarpResult<VersionResponse> {
+    let version = VersionObject {
+        version: env!("CARGO_PKG_VERSION").into(),
+        privacy: PrivacyPolicy::new(&data.config),
+    };
+    Ok(JsonBase::new(version).into())
+}
+
+#[cfg(feature = "s3-sync")]
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ArchiveFileStatus")]
+struct ArchiveFileStatus {
+    #[schema(description = "Archive File Name / S3 Key")]
+    key: StackString,
+    #[schema(description = "Local MD5 Checksum")]
+    local_md5: Option<StackString>,
+    #[schema(description = "S3 Etag")]
+    s3_etag: Option<StackString>,
+    #[schema(description = "Number of Rows in Local File")]
+    row_count: Option<usize>,
+    #[schema(description = "Local File Size (bytes)")]
+    local_size: Option<u64>,
+    #[schema(description = "S3 Object Size (bytes)")]
+    s3_size: Option<u64>,
+    #[schema(description = "Local Last Modified")]
+    local_modified: Option<DateTimeType>,
+    #[schema(description = "S3 Last Modified")]
+    s3_modified: Option<DateTimeType>,
+    #[schema(description = "Checksum Mismatch or Missing Copy")]
+    mismatch: bool,
+}
+
+#[cfg(feature = "s3-sync")]
+impl From<ArchiveStatus> for ArchiveFileStatus {
+    fn from(value: ArchiveStatus) -> Self {
+        Self {
+            key: value.key,
+            local_md5: value.local_md5,
+            s3_etag: value.s3_etag,
+            row_count: value.row_count,
+            local_size: value.local_size,
+            s3_size: value.s3_size,
+            local_modified: value.local_modified.map(Into::into),
+            s3_modified: value.s3_modified.map(Into::into),
+            mismatch: value.mismatch,
+        }
+    }
+}
+
+#[cfg(feature = "s3-sync")]
+#[derive(RwebResponse)]
+#[response(description = "Verify Parquet Archive Against S3")]
+struct ArchiveVerifyResponse(JsonBase<Vec<ArchiveFileStatus>, Error>);
+
+#[cfg(feature = "s3-sync")]
+#[get("/weather/admin/archive/verify")]
+#[openapi(tags("admin"))]
+pub async fn archive_verify(
+    #[data] data: AppState,
+    #[filter = "LoggedUser::session_filter"] _user: LoggedUser,
+) -> WarpResult<ArchiveVerifyResponse> {
+    let aws_config = aws_config::load_from_env().await;
+    let sync = S3Sync::new(&aws_config);
+    let status = sync
+        .verify_archive(&data.config.cache_dir, &data.config.s3_bucket)
+        .await
+        .map_err(Into::<Error>::into)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(JsonBase::new(status).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "RecordedLocation")]
+struct RecordedLocationObject {
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Location (zipcode, city name or lat,lon)")]
+    location: StackString,
+    #[schema(description = "Seconds Between Polls (RECORD_INTERVAL when missing)")]
+    interval_seconds: Option<i32>,
+    #[schema(description = "Created At")]
+    created_at: DateTimeType,
+}
+
+impl From<RecordedLocationDB> for RecordedLocationObject {
+    fn from(value: RecordedLocationDB) -> Self {
+        Self {
+            location_name: value.location_name,
+            location: value.location,
+            interval_seconds: value.interval_seconds,
+            created_at: value.created_at.to_offsetdatetime().into(),
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Recorded Locations")]
+struct RecordLocationsResponse(JsonBase<Vec<RecordedLocationObject>, Error>);
+
+#[get("/weather/record-locations")]
+#[openapi(tags("admin"))]
+pub async fn record_locations_get(
+    #[data] data: AppState,
+    #[filter = "LoggedUser::session_filter"] _user: LoggedUser,
+) -> WarpResult<RecordLocationsResponse> {
+    let locations = RecordedLocationDB::get_all(&data.pool)
+        .await
+        .map_err(Into::<Error>::into)?
+        .map_ok(Into::into)
+        .try_collect()
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(locations).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "RecordLocationRequest")]
+struct RecordLocationRequest {
+    #[schema(description = "Location (zipcode, city name or lat,lon)")]
+    location: StackString,
+    #[schema(description = "Polling Interval (e.g. 90, 90s, 5m, 1h)")]
+    interval: Option<StackString>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Recorded Location", status = "CREATED")]
+struct RecordLocationCreatedResponse(JsonBase<RecordedLocationObject, Error>);
+
+#[post("/weather/record-locations")]
+#[openapi(tags("admin"))]
+pub async fn record_locations_post(
+    #[data] data: AppState,
+    payload: Json<RecordLocationRequest>,
+    #[filter = "LoggedUser::session_filter"] _user: LoggedUser,
+) -> WarpResult<RecordLocationCreatedResponse> {
+    let payload = payload.into_inner();
+    let name = payload.location.trim();
+    if name.is_empty() {
+        return Err(Error::BadRequest("location is required".into()).into());
+    }
+    let interval = payload
+        .interval
+        .as_deref()
+        .map(|interval| {
+            parse_interval(interval)
+                .ok_or_else(|| Error::BadRequest(format_sstr!("invalid interval {interval}")))
+        })
+        .transpose()?;
+    let recorded = RecordedLocation {
+        name: name.into(),
+        location: get_parameters(name),
+        interval,
+    };
+    let row = RecordedLocationDB::from(&recorded);
+    row.upsert(&data.pool).await.map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(row.into()).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+struct RecordLocationDeleteRequest {
+    #[schema(description = "Location (zipcode, city name or lat,lon)")]
+    location: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Deleted Recorded Locations")]
+struct RecordLocationDeleteResponse(JsonBase<u64, Error>);
+
+#[delete("/weather/record-locations")]
+#[openapi(tags("admin"))]
+pub async fn record_locations_delete(
+    #[data] data: AppState,
+    query: Query<RecordLocationDeleteRequest>,
+    #[filter = "LoggedUser::session_filter"] _user: LoggedUser,
+) -> WarpResult<RecordLocationDeleteResponse> {
+    let query = query.into_inner();
+    let location_name = format_sstr!("{}", get_parameters(query.location.trim()));
+    let deleted = RecordedLocationDB::delete(&data.pool, &location_name)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(deleted).into())
+}
@@ -0,0 +1,162 @@
+use isocountry::CountryCode;
+use rweb::{filters::BoxedFilter, get, reply::Response, Filter, Query, Reply, Schema};
+use serde::{Deserialize, Serialize};
+use stack_string::StackString;
+
+use rweb_helper::{json_response::JsonResponse as JsonBase, RwebResponse};
+use weather_api_types::dto::{PaginatedLocationCount, Pagination};
+use weather_util_rust::weather_api::WeatherLocation;
+
+use crate::{
+    api_options::ApiOptions, app::AppState, errors::ServiceError as Error,
+    geojson::GeoJsonFeatureCollection, routes::WarpResult, GeoLocationWrapper,
+    PaginatedLocationCountWrapper,
+};
+
+/// Geocoding and recorded locations, tagged `geo`
+pub fn get_geo_path(app: &AppState) -> BoxedFilter<(Response,)> {
+    let locations_path = locations(app.clone()).boxed();
+    let locations_geojson_path = locations_geojson(app.clone()).boxed();
+    let geo_direct_path = geo_direct(app.clone()).boxed();
+    let geo_zip_path = geo_zip(app.clone()).boxed();
+    let geo_reverse_path = geo_reverse(app.clone()).boxed();
+
+    locations_path
+        .or(locations_geojson_path)
+        .or(geo_direct_path)
+        .or(geo_zip_path)
+        .or(geo_reverse_path)
+        .map(Reply::into_response)
+        .boxed()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Direct Geo Location")]
+struct GeoDirectResponse(JsonBase<Vec<GeoLocationWrapper>, Error>);
+
+#[get("/weather/direct")]
+#[openapi(tags("geo"))]
+pub async fn geo_direct(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<GeoDirectResponse> {
+    let query = query.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query
+        .get_weather_location(&data.config)
+        .map_err(Into::<Error>::into)?;
+    let geo_locations: Vec<GeoLocationWrapper> = if let WeatherLocation::CityName(city_name) = loc {
+        api.get_direct_location(&city_name)
+            .await
+            .map_err(Into::<Error>::into)?
+            .into_iter()
+            .map(Into::into)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(GeoDirectResponse(JsonBase::new(geo_locations)))
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+struct ZipOptions {
+    zip: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Zip Geo Location")]
+struct GeoZipResponse(JsonBase<GeoLocationWrapper, Error>);
+
+#[get("/weather/zip")]
+#[openapi(tags("geo"))]
+pub async fn geo_zip(
+    #[data] data: AppState,
+    query: Query<ZipOptions>,
+) -> WarpResult<GeoZipResponse> {
+    let query = query.into_inner();
+    let api = &data.api;
+    let zip_country: Vec<_> = query.zip.split(',').take(2).collect();
+    let zip: u64 = zip_country
+        .first()
+        .expect("zip invalid")
+        .parse()
+        .map_err(Into::<Error>::into)?;
+    let country_code: Option<CountryCode> = zip_country
+        .get(1)
+        .and_then(|s| CountryCode::for_alpha2(s).ok());
+    let loc = api
+        .get_zip_location(zip, country_code)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(GeoZipResponse(JsonBase::new(loc.into())))
+}
+
+#[get("/weather/reverse")]
+#[openapi(tags("geo"))]
+pub async fn geo_reverse(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<GeoDirectResponse> {
+    let query = query.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query
+        .get_weather_location(&data.config)
+        .map_err(Into::<Error>::into)?;
+    let geo_locations = if let WeatherLocation::LatLon {
+        latitude,
+        longitude,
+    } = loc
+    {
+        api.get_geo_location(latitude, longitude)
+            .await
+            .map_err(Into::<Error>::into)?
+            .into_iter()
+            .map(Into::into)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(GeoDirectResponse(JsonBase::new(geo_locations)))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Get Weather History Locations")]
+struct HistoryLocationsResponse(JsonBase<PaginatedLocationCountWrapper, Error>);
+
+#[derive(Deserialize, Schema)]
+struct OffsetLocation {
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+#[get("/weather/locations")]
+#[openapi(tags("geo"))]
+pub async fn locations(
+    #[data] data: AppState,
+    query: Query<OffsetLocation>,
+) -> WarpResult<HistoryLocationsResponse> {
+    let query = query.into_inner();
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(10);
+
+    let (total, data) = data.locations.get_location_counts(offset, limit).await?;
+
+    let pagination = Pagination {
+        limit,
+        offset,
+        total,
+    };
+    let counts: PaginatedLocationCountWrapper = PaginatedLocationCount { pagination, data }.into();
+    Ok(JsonBase::new(counts).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Known Locations with their Latest Observation as GeoJSON")]
+struct LocationsGeoJsonResponse(JsonBase<GeoJsonFeatureCollection, Error>);
+
+#[get("/weather/locations.geojson")]
+#[openapi(tags("geo"))]
+pub async fn locations_geojson(#[data] data: AppState) -> WarpResult<LocationsGeoJsonResponse> {
+    let collection = data.locations.get_locations_geojson().await?;
+    Ok(JsonBase::new(collection).into())
+}
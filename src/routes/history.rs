@@ -0,0 +1,1662 @@
+#[cfg(feature = "s3-sync")]
+use bytes::Bytes;
+use dioxus::prelude::VirtualDom;
+use futures::{future::try_join_all, TryStreamExt};
+use percent_encoding::percent_decode_str;
+use rweb::{
+    filters::BoxedFilter, get, patch, post, reply::Response, Filter, Json, Query, Reply, Schema,
+};
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use time::{Date, Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use rweb_helper::{
+    html_response::HtmlResponse as HtmlBase, json_response::JsonResponse as JsonBase, DateTimeType,
+    DateType, RwebResponse, UuidWrapper,
+};
+use weather_api_common::comparison::{ComparisonComponent, ComparisonComponentProps};
+use weather_api_types::{
+    dto::{ComparisonReading, Pagination, WeatherComparison},
+    plot::PlotData,
+    units::Units,
+};
+
+#[cfg(feature = "analysis")]
+use weather_util_rust::weather_data::WeatherData;
+
+#[cfg(feature = "export-package")]
+use crate::export_package::{
+    build_export_package, package_filename, ExportPackageResponse, PackageInfo,
+};
+use crate::{
+    app::AppState,
+    area::AreaFilter,
+    condition::condition_code,
+    coverage::{expected_interval, get_history_coverage, HistoryCoverage},
+    date_time_wrapper::DateTimeWrapper,
+    errors::ServiceError as Error,
+    export::NdjsonResponse,
+    logged_user::LoggedUser,
+    model::{
+        HistoryCursor, HistoryFields, HistorySort, SortOrder, WeatherDataChange, WeatherDataDB,
+        WeatherEvent, WeatherForecastDB, WeatherSnapshot,
+    },
+    pgpool::PgPool,
+    precision::{JsonPrecision, Rounded},
+    publish::DailySummary,
+    quality::{get_history_quality, HistoryQuality},
+    report::{default_report_end_date, get_climate_report, ReportPeriod},
+    routes::{
+        get_history_rows, parse_condition, parse_param, AnalysisRequest, HistoryPlotRequest,
+        HttpResult, PlotDataResponse, PlotFormatOptions, WarpResult,
+    },
+    signed_url::{check_signed_query, sign_query, DEFAULT_SIGNED_URL_TTL, MAX_SIGNED_URL_TTL},
+    units_wrapper::UnitsWrapper,
+    DailySummaryWrapper, PaginationWrapper, PlotPointWrapper, PrecipitationSummaryWrapper,
+    WeatherComparisonWrapper, WeatherDataChangeWrapper, WeatherDataDBWrapper,
+    WeatherForecastDBWrapper, WithFields,
+};
+#[cfg(feature = "analysis")]
+use crate::{
+    polars_analysis::{AnomalyReport, Climatology, ANOMALY_THRESHOLD, ANOMALY_WINDOW_DAYS},
+    resample::{
+        parse_interval, resample_points, ResampleField, ResampleMethod, ResampleResponse,
+        MAX_RESAMPLE_POINTS,
+    },
+};
+#[cfg(feature = "s3-sync")]
+use crate::{
+    s3_sync::S3Sync,
+    snapshots::{get_snapshot_key, SnapshotImageResponse, MAX_SNAPSHOT_SIZE},
+};
+
+/// Recorded weather history, tagged `history`
+pub fn get_history_path(app: &AppState) -> BoxedFilter<(Response,)> {
+    let history_path = history(app.clone()).boxed();
+    let history_export_path = history_export(app.clone()).boxed();
+    let history_export_sign_path = history_export_sign(app.clone()).boxed();
+    let history_export_signed_path = history_export_signed(app.clone()).boxed();
+    let history_update_path = history_update(app.clone()).boxed();
+    let history_patch_path = history_patch(app.clone()).boxed();
+    let today_summary_path = today_summary(app.clone()).boxed();
+    let history_daily_path = history_daily(app.clone()).boxed();
+    let history_coverage_path = history_coverage(app.clone()).boxed();
+    let history_coverage_plot_path = history_coverage_plot(app.clone()).boxed();
+    let history_quality_path = history_quality(app.clone()).boxed();
+    let climate_report_path = climate_report(app.clone()).boxed();
+    let history_forecasts_path = history_forecasts(app.clone()).boxed();
+    let history_changes_path = history_changes(app.clone()).boxed();
+    let history_get_path = history_get(app.clone()).boxed();
+    let compare_yesterday_path = compare_yesterday(app.clone()).boxed();
+    let compare_yesterday_html_path = compare_yesterday_html(app.clone()).boxed();
+    let events_path = events(app.clone()).boxed();
+    let snapshots_path = snapshots(app.clone()).boxed();
+    let snapshot_link_path = snapshot_link(app.clone()).boxed();
+
+    let path = history_path
+        .or(history_export_path)
+        .or(history_export_sign_path)
+        .or(history_export_signed_path)
+        .or(history_update_path)
+        .or(history_patch_path)
+        .or(today_summary_path)
+        .or(history_daily_path)
+        .or(history_coverage_path)
+        .or(history_coverage_plot_path)
+        .or(history_quality_path)
+        .or(climate_report_path)
+        .or(history_forecasts_path)
+        .or(history_changes_path)
+        .map(Reply::into_response)
+        .boxed();
+    #[cfg(feature = "analysis")]
+    let path = path
+        .or(history_climatology(app.clone()).map(Reply::into_response))
+        .unify()
+        .or(history_anomalies(app.clone()).map(Reply::into_response))
+        .unify()
+        .or(history_resample(app.clone()).map(Reply::into_response))
+        .unify()
+        .boxed();
+    #[cfg(feature = "export-package")]
+    let path = path
+        .or(history_export_package(app.clone()).map(Reply::into_response))
+        .unify()
+        .boxed();
+    // `/weather/history/{id}` would also match the static paths above
+    let path = path
+        .or(history_get_path)
+        .or(compare_yesterday_path)
+        .or(compare_yesterday_html_path)
+        .or(events_path)
+        .or(snapshots_path)
+        .or(snapshot_link_path)
+        .map(Reply::into_response)
+        .boxed();
+    #[cfg(feature = "s3-sync")]
+    let path = path
+        .or(snapshot_upload(app.clone()).map(Reply::into_response))
+        .unify()
+        .or(snapshot_image(app.clone()).map(Reply::into_response))
+        .unify()
+        .boxed();
+    path
+}
+
+#[derive(Deserialize, Schema)]
+struct HistoryRequest {
+    name: Option<StackString>,
+    server: Option<StackString>,
+    start_time: Option<DateType>,
+    end_time: Option<DateType>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    cursor: Option<StackString>,
+    #[schema(description = "Condition Group (e.g. rain, clouds) or Condition Code")]
+    condition: Option<StackString>,
+    #[schema(description = "Sort Column: created_at (default), temperature or wind_speed")]
+    sort: Option<StackString>,
+    #[schema(description = "Sort Order: asc (default) or desc")]
+    order: Option<StackString>,
+    #[schema(description = "Comma Separated Fields of Each Row (default all)")]
+    fields: Option<StackString>,
+    #[schema(description = "Bounding Box Minimum Latitude")]
+    min_lat: Option<f64>,
+    #[schema(description = "Bounding Box Maximum Latitude")]
+    max_lat: Option<f64>,
+    #[schema(description = "Bounding Box Minimum Longitude")]
+    min_lon: Option<f64>,
+    #[schema(description = "Bounding Box Maximum Longitude")]
+    max_lon: Option<f64>,
+    #[schema(description = "Latitude of the Radius Center")]
+    lat: Option<f64>,
+    #[schema(description = "Longitude of the Radius Center")]
+    lon: Option<f64>,
+    #[schema(description = "Radius (km) Around lat / lon")]
+    radius_km: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+struct HistoryExportRequest {
+    name: Option<StackString>,
+    server: Option<StackString>,
+    start_time: Option<DateType>,
+    end_time: Option<DateType>,
+    #[schema(description = "Condition Group (e.g. rain, clouds) or Condition Code")]
+    condition: Option<StackString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+/// Every matching row as newline delimited json, streamed from the db rather
+/// than collected so that large exports don't have to fit in memory
+#[get("/weather/history/export")]
+#[openapi(tags("history"))]
+pub async fn history_export(
+    #[data] data: AppState,
+    query: Query<HistoryExportRequest>,
+    _: LoggedUser,
+) -> WarpResult<NdjsonResponse> {
+    let response = history_export_body(&data, query.into_inner()).await?;
+    Ok(response)
+}
+
+async fn history_export_body(
+    data: &AppState,
+    query: HistoryExportRequest,
+) -> HttpResult<NdjsonResponse> {
+    let condition = parse_condition(query.condition.as_deref())?;
+    let rows = WeatherDataDB::get_by_name_dates(
+        &data.pool,
+        query.name.as_ref().map(StackString::as_str),
+        query.server.as_ref().map(StackString::as_str),
+        query.start_time.map(Into::into),
+        query.end_time.map(Into::into),
+        condition,
+        None,
+        query.asof.map(Into::into),
+        None,
+        None,
+    )
+    .await?;
+    Ok(NdjsonResponse::new(rows))
+}
+
+fn get_signing_key(data: &AppState) -> HttpResult<&[u8]> {
+    data.config
+        .url_signing_key
+        .as_ref()
+        .map(StackString::as_bytes)
+        .ok_or_else(|| Error::BadRequest("url signing is not configured".into()))
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "HistoryExportSignRequest")]
+struct HistoryExportSignRequest {
+    name: Option<StackString>,
+    server: Option<StackString>,
+    start_time: Option<DateType>,
+    end_time: Option<DateType>,
+    #[schema(description = "Condition Group (e.g. rain, clouds) or Condition Code")]
+    condition: Option<StackString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+    #[schema(description = "Lifetime of the URL in seconds (default 1 day, at most 7 days)")]
+    expires_in: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "SignedExportUrl")]
+struct SignedExportUrl {
+    #[schema(description = "Export URL Usable Without Login")]
+    url: StackString,
+    #[schema(description = "Expiration Datetime")]
+    expires_at: DateTimeType,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Signed History Export URL")]
+struct SignedExportUrlResponse(JsonBase<SignedExportUrl, Error>);
+
+/// Time limited link to one export query for someone without an account
+#[get("/weather/history/export/sign")]
+#[openapi(tags("history"))]
+pub async fn history_export_sign(
+    #[data] data: AppState,
+    query: Query<HistoryExportSignRequest>,
+    _: LoggedUser,
+) -> WarpResult<SignedExportUrlResponse> {
+    let signed = history_export_sign_body(&data, query.into_inner())?;
+    Ok(JsonBase::new(signed).into())
+}
+
+fn history_export_sign_body(
+    data: &AppState,
+    query: HistoryExportSignRequest,
+) -> HttpResult<SignedExportUrl> {
+    let key = get_signing_key(data)?;
+    let ttl = query
+        .expires_in
+        .map_or(DEFAULT_SIGNED_URL_TTL, |s| {
+            Duration::seconds(s.try_into().unwrap_or(i64::MAX))
+        })
+        .min(MAX_SIGNED_URL_TTL);
+    let expires_at = OffsetDateTime::now_utc() + ttl;
+    let expires = expires_at.unix_timestamp();
+    let export = HistoryExportRequest {
+        name: query.name,
+        server: query.server,
+        start_time: query.start_time,
+        end_time: query.end_time,
+        condition: query.condition,
+        asof: query.asof,
+    };
+    let query_string = serde_urlencoded::to_string(&export)?;
+    let signature = sign_query(key, &query_string, expires);
+    let separator = if query_string.is_empty() { "" } else { "&" };
+    Ok(SignedExportUrl {
+        url: format_sstr!(
+            "/weather/history/export/signed?{query_string}{separator}expires={expires}&signature={signature}"
+        ),
+        expires_at: expires_at.into(),
+    })
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "SignedExportRequest")]
+struct SignedExportRequest {
+    name: Option<StackString>,
+    server: Option<StackString>,
+    start_time: Option<DateType>,
+    end_time: Option<DateType>,
+    condition: Option<StackString>,
+    asof: Option<DateTimeType>,
+    #[schema(description = "Expiration Unix Timestamp")]
+    expires: i64,
+    #[schema(description = "HMAC-SHA256 Signature (hex)")]
+    signature: StackString,
+}
+
+/// Export of a signed url, the signature stands in for the login
+#[get("/weather/history/export/signed")]
+#[openapi(tags("history"))]
+pub async fn history_export_signed(
+    #[data] data: AppState,
+    query: Query<SignedExportRequest>,
+) -> WarpResult<NdjsonResponse> {
+    let query = query.into_inner();
+    let key = get_signing_key(&data)?;
+    let export = HistoryExportRequest {
+        name: query.name,
+        server: query.server,
+        start_time: query.start_time,
+        end_time: query.end_time,
+        condition: query.condition,
+        asof: query.asof,
+    };
+    let query_string = serde_urlencoded::to_string(&export).map_err(Into::<Error>::into)?;
+    check_signed_query(
+        key,
+        &query_string,
+        query.expires,
+        &query.signature,
+        OffsetDateTime::now_utc(),
+    )?;
+    let response = history_export_body(&data, export).await?;
+    Ok(response)
+}
+
+/// Csv, json schema, plots and README of a location's history in one zip for
+/// sharing with collaborators, the range is read like the history plots
+#[cfg(feature = "export-package")]
+#[get("/weather/history/export-package")]
+#[openapi(tags("history"))]
+pub async fn history_export_package(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    _: LoggedUser,
+) -> WarpResult<ExportPackageResponse> {
+    let query = query.into_inner();
+    let rows = get_history_rows(&query, data.history.as_ref()).await?;
+    let info = PackageInfo {
+        name: &query.name,
+        start_date: query.start_time.map(Into::into),
+        end_date: query.end_time.map(Into::into),
+        units: query.get_units(),
+        exported_at: OffsetDateTime::now_utc(),
+    };
+    let filename = package_filename(&info);
+    let body = build_export_package(&data.config, &info, &rows).map_err(Into::<Error>::into)?;
+    Ok(ExportPackageResponse { filename, body })
+}
+
+#[derive(Debug, Serialize, Schema)]
+#[schema(component = "PaginatedWeatherDataDB")]
+struct PaginatedWeatherDataDB {
+    pagination: PaginationWrapper,
+    data: WithFields<Vec<WeatherDataDBWrapper>>,
+    #[schema(description = "Snapshots Within the Requested Range")]
+    snapshots: Vec<WeatherSnapshotObject>,
+    #[schema(description = "Cursor for the Next Page (keyset pagination)")]
+    next_cursor: Option<StackString>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Get Weather History")]
+struct HistoryResponse(JsonBase<Rounded<PaginatedWeatherDataDB>, Error>);
+
+#[get("/weather/history")]
+#[openapi(tags("history"))]
+pub async fn history(
+    #[data] data: AppState,
+    query: Query<HistoryRequest>,
+    _: LoggedUser,
+) -> WarpResult<HistoryResponse> {
+    let query = query.into_inner();
+    let precision = JsonPrecision::from_config(&data.config);
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(10);
+
+    let server = query.server.as_ref().map(StackString::as_str);
+    let name = query.name.as_ref().map(StackString::as_str);
+    let start_time: Option<Date> = query.start_time.map(Into::into);
+    let end_time = query.end_time.map(Into::into);
+    let condition = parse_condition(query.condition.as_deref())?;
+    let asof = query.asof.map(Into::into);
+    let sort: HistorySort = parse_param(query.sort.as_deref())?.unwrap_or_default();
+    let order: SortOrder = parse_param(query.order.as_deref())?.unwrap_or_default();
+    let fields: Option<HistoryFields> = parse_param(query.fields.as_deref())?;
+    let area = AreaFilter::from_params(
+        [query.min_lat, query.max_lat, query.min_lon, query.max_lon],
+        [query.lat, query.lon, query.radius_km],
+    )
+    .map_err(|e| Error::BadRequest(format_sstr!("{e}")))?;
+    let total = WeatherDataDB::get_total_by_name_dates(
+        &data.pool, name, server, start_time, end_time, condition, area, asof,
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    let snapshots = get_snapshots(&data.pool, name, start_time, end_time).await?;
+
+    let rows: Vec<WeatherDataDB> = if let Some(cursor) = &query.cursor {
+        if sort != HistorySort::CreatedAt {
+            return Err(Error::BadRequest("cursor requires sort=created_at".into()).into());
+        }
+        let cursor: HistoryCursor = cursor
+            .parse()
+            .map_err(|e| Error::BadRequest(format_sstr!("{e}")))?;
+        WeatherDataDB::get_by_name_dates_after(
+            &data.pool,
+            name,
+            server,
+            start_time,
+            end_time,
+            condition,
+            area,
+            asof,
+            Some(cursor),
+            order,
+            limit,
+        )
+        .await
+        .map_err(Into::<Error>::into)?
+        .try_collect()
+        .await
+        .map_err(Into::<Error>::into)?
+    } else {
+        WeatherDataDB::get_by_name_dates_sorted(
+            &data.pool,
+            name,
+            server,
+            start_time,
+            end_time,
+            condition,
+            area,
+            asof,
+            sort,
+            order,
+            Some(offset),
+            Some(limit),
+        )
+        .await
+        .map_err(Into::<Error>::into)?
+        .try_collect()
+        .await
+        .map_err(Into::<Error>::into)?
+    };
+    let next_cursor = if rows.len() == limit && sort == HistorySort::CreatedAt {
+        rows.last()
+            .map(|row| format_sstr!("{}", HistoryCursor::from(row)))
+    } else {
+        None
+    };
+    let data: Vec<_> = rows
+        .into_iter()
+        .map(Into::<WeatherDataDBWrapper>::into)
+        .collect();
+
+    let pagination = Pagination {
+        limit,
+        offset,
+        total,
+    };
+    let history = PaginatedWeatherDataDB {
+        pagination: pagination.into(),
+        data: WithFields::new(data, fields),
+        snapshots,
+        next_cursor,
+    };
+    Ok(JsonBase::new(Rounded::new(history, precision)).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Recorded Precipitation Today and This Week")]
+struct TodaySummaryResponse(JsonBase<PrecipitationSummaryWrapper, Error>);
+
+#[get("/weather/history/today-summary")]
+#[openapi(tags("history"))]
+pub async fn today_summary(
+    #[data] data: AppState,
+    query: Query<AnalysisRequest>,
+    _: LoggedUser,
+) -> WarpResult<TodaySummaryResponse> {
+    let summary = data
+        .history
+        .get_precipitation_summary(&query.into_inner().name)
+        .await?;
+    Ok(JsonBase::new(summary.into()).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "HistoryUpdateRequest")]
+struct HistoryUpdateRequest {
+    updates: Vec<WeatherDataDBWrapper>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Update Weather History", status = "CREATED")]
+struct HistoryUpdateResponse(JsonBase<u64, Error>);
+
+#[post("/weather/history")]
+#[openapi(tags("history"))]
+pub async fn history_update(
+    #[data] data: AppState,
+    payload: Json<HistoryUpdateRequest>,
+    _: LoggedUser,
+) -> WarpResult<HistoryUpdateResponse> {
+    let payload = payload.into_inner();
+    let inserts = {
+        let pool = &data.pool;
+        let futures = payload.updates.into_iter().map(|update| async move {
+            let entry: WeatherDataDB = update.into();
+            entry.insert(pool).await.map_err(Into::<Error>::into)
+        });
+        let results: Result<Vec<u64>, Error> = try_join_all(futures).await;
+        results?.into_iter().sum()
+    };
+    Ok(JsonBase::new(inserts).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "HistoryPatchRequest")]
+/// Fields to correct, absent fields are left unchanged
+struct HistoryPatchRequest {
+    #[schema(description = "Location Name")]
+    location_name: Option<StackString>,
+    #[schema(description = "Latitude")]
+    latitude: Option<f64>,
+    #[schema(description = "Longitude")]
+    longitude: Option<f64>,
+    #[schema(description = "Condition")]
+    condition: Option<StackString>,
+    #[schema(description = "Temperature (K)")]
+    temperature: Option<f64>,
+    #[schema(description = "Minimum Temperature (K)")]
+    temperature_minimum: Option<f64>,
+    #[schema(description = "Maximum Temperature (K)")]
+    temperature_maximum: Option<f64>,
+    #[schema(description = "Pressure (kPa)")]
+    pressure: Option<f64>,
+    #[schema(description = "Humidity (percent)")]
+    humidity: Option<i32>,
+    #[schema(description = "Visibility (meters)")]
+    visibility: Option<f64>,
+    #[schema(description = "Rain (mm per hour)")]
+    rain: Option<f64>,
+    #[schema(description = "Snow (mm per hour)")]
+    snow: Option<f64>,
+    #[schema(description = "Wind Speed (m/s)")]
+    wind_speed: Option<f64>,
+    #[schema(description = "Wind Direction (degrees)")]
+    wind_direction: Option<f64>,
+    #[schema(description = "Country Code (ISO 3166-1 alpha-2)")]
+    country: Option<StackString>,
+}
+
+impl HistoryPatchRequest {
+    fn apply(self, row: &mut WeatherDataDB) -> HttpResult<()> {
+        if self
+            .latitude
+            .is_some_and(|lat| !(-90.0..=90.0).contains(&lat))
+        {
+            return Err(Error::BadRequest("latitude out of range".into()));
+        }
+        if self
+            .longitude
+            .is_some_and(|lon| !(-180.0..=180.0).contains(&lon))
+        {
+            return Err(Error::BadRequest("longitude out of range".into()));
+        }
+        if self.humidity.is_some_and(|h| !(0..=100).contains(&h)) {
+            return Err(Error::BadRequest("humidity out of range".into()));
+        }
+        if self
+            .location_name
+            .as_ref()
+            .is_some_and(|n| n.trim().is_empty())
+        {
+            return Err(Error::BadRequest("empty location_name".into()));
+        }
+        let temperatures = [
+            self.temperature,
+            self.temperature_minimum,
+            self.temperature_maximum,
+        ];
+        if temperatures.into_iter().flatten().any(|t| t <= 0.0) {
+            return Err(Error::BadRequest("temperatures are in kelvin".into()));
+        }
+        let negative = [self.pressure, self.rain, self.snow, self.wind_speed];
+        if negative.into_iter().flatten().any(|v| v < 0.0) {
+            return Err(Error::BadRequest("negative value".into()));
+        }
+        if let Some(location_name) = self.location_name {
+            row.location_name = location_name;
+        }
+        if let Some(condition) = self.condition {
+            row.condition_code = condition_code(&condition);
+            row.condition = condition;
+        }
+        if let Some(country) = self.country {
+            row.country = country;
+        }
+        row.latitude = self.latitude.unwrap_or(row.latitude);
+        row.longitude = self.longitude.unwrap_or(row.longitude);
+        row.temperature = self.temperature.unwrap_or(row.temperature);
+        row.temperature_minimum = self.temperature_minimum.unwrap_or(row.temperature_minimum);
+        row.temperature_maximum = self.temperature_maximum.unwrap_or(row.temperature_maximum);
+        row.pressure = self.pressure.unwrap_or(row.pressure);
+        row.humidity = self.humidity.unwrap_or(row.humidity);
+        row.wind_speed = self.wind_speed.unwrap_or(row.wind_speed);
+        row.visibility = self.visibility.or(row.visibility);
+        row.rain = self.rain.or(row.rain);
+        row.snow = self.snow.or(row.snow);
+        row.wind_direction = self.wind_direction.or(row.wind_direction);
+        row.set_derived_metrics();
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "HistoryDailyRequest")]
+struct HistoryDailyRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Server")]
+    server: Option<StackString>,
+    #[schema(description = "Start Date")]
+    start_time: Option<DateType>,
+    #[schema(description = "End Date")]
+    end_time: Option<DateType>,
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Daily Weather Summaries")]
+struct HistoryDailyResponse(JsonBase<Vec<DailySummaryWrapper>, Error>);
+
+#[get("/weather/history/daily")]
+#[openapi(tags("history"))]
+pub async fn history_daily(
+    #[data] data: AppState,
+    query: Query<HistoryDailyRequest>,
+    _: LoggedUser,
+) -> WarpResult<HistoryDailyResponse> {
+    let summaries = history_daily_body(&data, query.into_inner())
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(JsonBase::new(summaries).into())
+}
+
+async fn history_daily_body(
+    data: &AppState,
+    query: HistoryDailyRequest,
+) -> HttpResult<Vec<DailySummary>> {
+    data.history
+        .get_daily_summaries(
+            &query.name,
+            query.server.as_ref().map(StackString::as_str),
+            query.start_time.map(Into::into),
+            query.end_time.map(Into::into),
+            query.asof.map(Into::into),
+        )
+        .await
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "HistoryForecastsRequest")]
+struct HistoryForecastsRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Server")]
+    server: Option<StackString>,
+    #[schema(description = "First Fetch Date")]
+    start_time: Option<DateType>,
+    #[schema(description = "Last Fetch Date")]
+    end_time: Option<DateType>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Recorded Forecast Entries")]
+struct HistoryForecastsResponse(JsonBase<Vec<WeatherForecastDBWrapper>, Error>);
+
+#[get("/weather/history/forecasts")]
+#[openapi(tags("history"))]
+pub async fn history_forecasts(
+    #[data] data: AppState,
+    query: Query<HistoryForecastsRequest>,
+    _: LoggedUser,
+) -> WarpResult<HistoryForecastsResponse> {
+    let forecasts = history_forecasts_body(&data, query.into_inner())
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(JsonBase::new(forecasts).into())
+}
+
+async fn history_forecasts_body(
+    data: &AppState,
+    query: HistoryForecastsRequest,
+) -> HttpResult<Vec<WeatherForecastDB>> {
+    let forecasts = WeatherForecastDB::get_by_name_fetched(
+        &data.pool,
+        &query.name,
+        query.server.as_ref().map(StackString::as_str),
+        query.start_time.map(Into::into),
+        query.end_time.map(Into::into),
+        query.offset,
+        query.limit,
+    )
+    .await?
+    .try_collect()
+    .await?;
+    Ok(forecasts)
+}
+
+/// Rows returned by one change feed request unless a smaller limit is given
+const CHANGES_BATCH_SIZE: usize = 1000;
+const MAX_CHANGES_BATCH_SIZE: usize = 10_000;
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "WeatherChangesRequest")]
+struct WeatherChangesRequest {
+    #[schema(description = "Last Sequence Number Already Seen (default 0)")]
+    since_seq: Option<i64>,
+    #[schema(description = "Maximum Number of Rows (default 1000, at most 10000)")]
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "WeatherChanges")]
+struct WeatherChanges {
+    #[schema(description = "Inserted or Updated Rows Ordered by Sequence Number")]
+    changes: Vec<WeatherDataChangeWrapper>,
+    #[schema(description = "Sequence Number to Pass as since_seq in the Next Request")]
+    next_seq: i64,
+    #[schema(description = "More Rows Are Available After next_seq")]
+    has_more: bool,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Weather Data Changes")]
+struct WeatherChangesResponse(JsonBase<WeatherChanges, Error>);
+
+#[get("/weather/changes")]
+#[openapi(tags("history"))]
+pub async fn history_changes(
+    #[data] data: AppState,
+    query: Query<WeatherChangesRequest>,
+    _: LoggedUser,
+) -> WarpResult<WeatherChangesResponse> {
+    let changes = history_changes_body(&data, query.into_inner()).await?;
+    Ok(JsonBase::new(changes).into())
+}
+
+async fn history_changes_body(
+    data: &AppState,
+    query: WeatherChangesRequest,
+) -> HttpResult<WeatherChanges> {
+    let since_seq = query.since_seq.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(CHANGES_BATCH_SIZE)
+        .clamp(1, MAX_CHANGES_BATCH_SIZE);
+    let changes = WeatherDataChange::get_since(&data.pool, since_seq, limit as i64).await?;
+    let (next_seq, has_more) = WeatherDataChange::get_next_seq(&changes, since_seq, limit);
+    Ok(WeatherChanges {
+        has_more,
+        next_seq,
+        changes: changes.into_iter().map(Into::into).collect(),
+    })
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "HistoryCoverageRequest")]
+struct HistoryCoverageRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Start Date (default 30 days ago)")]
+    start_date: Option<DateType>,
+    #[schema(description = "End Date (default today)")]
+    end_date: Option<DateType>,
+    #[schema(description = "Expected Interval Between Observations (seconds)")]
+    interval: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+impl HistoryCoverageRequest {
+    fn get_dates(&self) -> HttpResult<(Date, Date)> {
+        let end_date = self
+            .end_date
+            .map_or_else(|| OffsetDateTime::now_utc().date(), Into::into);
+        let start_date = self
+            .start_date
+            .map_or_else(|| end_date - Duration::days(30), Into::into);
+        if start_date > end_date {
+            return Err(Error::BadRequest("start_date is after end_date".into()));
+        }
+        Ok((start_date, end_date))
+    }
+}
+
+async fn get_coverage(
+    data: &AppState,
+    query: &HistoryCoverageRequest,
+) -> HttpResult<HistoryCoverage> {
+    let (start_date, end_date) = query.get_dates()?;
+    // end_date is compared with midnight, include the whole last day
+    let history: Vec<WeatherDataDB> = WeatherDataDB::get_by_name_dates(
+        &data.pool,
+        Some(&query.name),
+        None,
+        Some(start_date),
+        Some(end_date + Duration::days(1)),
+        None,
+        None,
+        query.asof.map(Into::into),
+        None,
+        None,
+    )
+    .await
+    .map_err(Into::<Error>::into)?
+    .try_collect()
+    .await
+    .map_err(Into::<Error>::into)?;
+    let interval = query
+        .interval
+        .filter(|i| *i > 0)
+        .unwrap_or_else(|| expected_interval(&data.config));
+    Ok(get_history_coverage(
+        &query.name,
+        &history,
+        start_date,
+        end_date,
+        interval,
+    ))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Observation Coverage of a Location")]
+struct HistoryCoverageResponse(JsonBase<HistoryCoverage, Error>);
+
+#[get("/weather/history/coverage")]
+#[openapi(tags("history"))]
+pub async fn history_coverage(
+    #[data] data: AppState,
+    query: Query<HistoryCoverageRequest>,
+    _: LoggedUser,
+) -> WarpResult<HistoryCoverageResponse> {
+    let query = query.into_inner();
+    let mut coverage = get_coverage(&data, &query).await?;
+    let query_string = serde_urlencoded::to_string(&query).map_err(Into::<Error>::into)?;
+    coverage.plot.replace(
+        PlotData {
+            plot_url: format!("/weather/history/coverage/plot?{query_string}"),
+            title: format!("Observation Coverage {}", query.name),
+            xaxis: String::new(),
+            yaxis: "%".into(),
+            markers_url: None,
+        }
+        .into(),
+    );
+    Ok(JsonBase::new(coverage).into())
+}
+
+#[get("/weather/history/coverage/plot")]
+#[openapi(tags("history"))]
+pub async fn history_coverage_plot(
+    #[data] data: AppState,
+    query: Query<HistoryCoverageRequest>,
+    format: Query<PlotFormatOptions>,
+    _: LoggedUser,
+) -> WarpResult<PlotDataResponse> {
+    let query = query.into_inner();
+    let coverage = get_coverage(&data, &query).await?;
+    let plots: Vec<PlotPointWrapper> = coverage
+        .completeness_plot()
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let plots = format.into_inner().format(&data.config, "coverage", plots);
+    Ok(JsonBase::new(plots).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "HistoryQualityRequest")]
+struct HistoryQualityRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Server (default all servers)")]
+    server: Option<StackString>,
+    #[schema(description = "Start Date (default a year before end_date)")]
+    start_date: Option<DateType>,
+    #[schema(description = "End Date (default today)")]
+    end_date: Option<DateType>,
+    #[schema(description = "Expected Interval Between Observations (seconds)")]
+    interval: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Data Quality of the Recorded History of a Location")]
+struct HistoryQualityResponse(JsonBase<HistoryQuality, Error>);
+
+#[get("/weather/history/quality")]
+#[openapi(tags("history"))]
+pub async fn history_quality(
+    #[data] data: AppState,
+    query: Query<HistoryQualityRequest>,
+    _: LoggedUser,
+) -> WarpResult<HistoryQualityResponse> {
+    let query = query.into_inner();
+    let end_date = query
+        .end_date
+        .map_or_else(|| OffsetDateTime::now_utc().date(), Into::into);
+    let start_date = query
+        .start_date
+        .map_or_else(|| end_date - Duration::days(365), Into::into);
+    if start_date > end_date {
+        return Err(Error::BadRequest("start_date is after end_date".into()).into());
+    }
+    let interval = query
+        .interval
+        .filter(|i| *i > 0)
+        .unwrap_or_else(|| expected_interval(&data.config));
+    let quality = get_history_quality(
+        &data.pool,
+        &query.name,
+        query.server.as_deref(),
+        start_date,
+        end_date,
+        interval,
+        query.asof.map(Into::into),
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(quality).into())
+}
+
+#[cfg(feature = "analysis")]
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ClimatologyRequest")]
+struct ClimatologyRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Server")]
+    server: Option<StackString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+#[cfg(feature = "analysis")]
+#[derive(RwebResponse)]
+#[response(description = "Monthly Normals")]
+struct ClimatologyResponse(JsonBase<Climatology, Error>);
+
+/// Normals of each calendar month over every year of the parquet archive, of
+/// the db when `asof` is given
+#[cfg(feature = "analysis")]
+#[get("/weather/history/climatology")]
+#[openapi(tags("history"))]
+pub async fn history_climatology(
+    #[data] data: AppState,
+    query: Query<ClimatologyRequest>,
+    _: LoggedUser,
+) -> WarpResult<ClimatologyResponse> {
+    let query = query.into_inner();
+    let server = query.server.as_ref().map(StackString::as_str);
+    let climatology = data
+        .history
+        .get_climatology(&query.name, server, query.asof.map(Into::into))
+        .await?;
+    Ok(JsonBase::new(climatology).into())
+}
+
+#[cfg(feature = "analysis")]
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "AnomaliesRequest")]
+struct AnomaliesRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Server")]
+    server: Option<StackString>,
+    #[schema(description = "Start Date (default 30 days ago)")]
+    start_date: Option<DateType>,
+    #[schema(description = "End Date (default today)")]
+    end_date: Option<DateType>,
+    #[schema(description = "Threshold in Standard Deviations (default 4)")]
+    threshold: Option<f64>,
+    #[schema(description = "Trailing Window (days, default 30)")]
+    window_days: Option<i64>,
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+#[cfg(feature = "analysis")]
+#[derive(RwebResponse)]
+#[response(description = "Observations Deviating from the Trailing Window")]
+struct AnomaliesResponse(JsonBase<AnomalyReport, Error>);
+
+/// Flag temperature, pressure and wind speed readings more than `threshold`
+/// standard deviations from the rolling window, e.g. sensor or api glitches
+#[cfg(feature = "analysis")]
+#[get("/weather/history/anomalies")]
+#[openapi(tags("history"))]
+pub async fn history_anomalies(
+    #[data] data: AppState,
+    query: Query<AnomaliesRequest>,
+    _: LoggedUser,
+) -> WarpResult<AnomaliesResponse> {
+    let report = history_anomalies_body(&data, query.into_inner()).await?;
+    Ok(JsonBase::new(report).into())
+}
+
+#[cfg(feature = "analysis")]
+async fn history_anomalies_body(
+    data: &AppState,
+    query: AnomaliesRequest,
+) -> HttpResult<AnomalyReport> {
+    let end_date = query
+        .end_date
+        .map_or_else(|| OffsetDateTime::now_utc().date(), Into::into);
+    let start_date = query
+        .start_date
+        .map_or_else(|| end_date - Duration::days(30), Into::into);
+    if start_date > end_date {
+        return Err(Error::BadRequest("start_date is after end_date".into()));
+    }
+    let threshold = query.threshold.unwrap_or(ANOMALY_THRESHOLD);
+    if threshold.is_nan() || threshold <= 0.0 {
+        return Err(Error::BadRequest(format_sstr!(
+            "Invalid threshold {threshold}"
+        )));
+    }
+    let window_days = query.window_days.unwrap_or(ANOMALY_WINDOW_DAYS);
+    if window_days < 1 {
+        return Err(Error::BadRequest(format_sstr!(
+            "Invalid window_days {window_days}"
+        )));
+    }
+    data.history
+        .get_anomalies(
+            &query.name,
+            query.server.as_ref().map(StackString::as_str),
+            start_date,
+            end_date,
+            window_days,
+            threshold,
+            query.asof.map(Into::into),
+        )
+        .await
+}
+
+#[cfg(feature = "analysis")]
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ResampleRequest")]
+struct ResampleRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Server")]
+    server: Option<StackString>,
+    start_time: Option<DateType>,
+    end_time: Option<DateType>,
+    #[schema(description = "Grid Interval, e.g. 30s, 15m, 1h or 1d")]
+    interval: StackString,
+    #[schema(description = "Fill Method: linear (default) or ffill")]
+    method: Option<StackString>,
+    #[schema(
+        description = "Field: temperature (default), precipitation, humidity, pressure or wind"
+    )]
+    field: Option<StackString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    units: Option<UnitsWrapper>,
+    #[schema(description = "Output Format: json (default) or csv")]
+    format: Option<StackString>,
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+/// Recorded observations of one field on a regular time grid, for analysis
+/// tools that expect evenly spaced series
+#[cfg(feature = "analysis")]
+#[get("/weather/history/resample")]
+#[openapi(tags("history"))]
+pub async fn history_resample(
+    #[data] data: AppState,
+    query: Query<ResampleRequest>,
+    _: LoggedUser,
+) -> WarpResult<ResampleResponse> {
+    let response = history_resample_body(&data, query.into_inner()).await?;
+    Ok(response)
+}
+
+#[cfg(feature = "analysis")]
+async fn history_resample_body(
+    data: &AppState,
+    query: ResampleRequest,
+) -> HttpResult<ResampleResponse> {
+    let interval =
+        parse_interval(&query.interval).map_err(|e| Error::BadRequest(format_sstr!("{e}")))?;
+    let method: ResampleMethod = parse_param(query.method.as_deref())?.unwrap_or_default();
+    let field: ResampleField = parse_param(query.field.as_deref())?.unwrap_or_default();
+    let csv = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(format) => {
+            return Err(Error::BadRequest(format_sstr!("Invalid format {format}")));
+        }
+    };
+    let units = query.units.map_or(Units::Imperial, Into::into);
+    let rows = data
+        .history
+        .get_history(
+            &query.name,
+            query.server.as_ref().map(StackString::as_str),
+            query.start_time.map(Into::into),
+            query.end_time.map(Into::into),
+            None,
+            query.asof.map(Into::into),
+        )
+        .await?;
+    if let (Some(first), Some(last)) = (rows.first(), rows.last()) {
+        let span = last.created_at.unix_timestamp() - first.created_at.unix_timestamp();
+        if span / interval.whole_seconds() >= MAX_RESAMPLE_POINTS as i64 {
+            return Err(Error::BadRequest(format_sstr!(
+                "More than {MAX_RESAMPLE_POINTS} points, use a longer interval"
+            )));
+        }
+    }
+    let history: Vec<WeatherData> = rows.into_iter().map(Into::into).collect();
+    let points = resample_points(&field.get_series(&history, units), interval, method)?;
+    let response = if csv {
+        ResampleResponse::csv(field, &points)?
+    } else {
+        let plots: Vec<PlotPointWrapper> = points.into_iter().map(Into::into).collect();
+        let plots = Rounded::new(plots, JsonPrecision::from_config(&data.config))
+            .with_metric(field.metric());
+        ResampleResponse::json(&plots)?
+    };
+    Ok(response)
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ReportRequest")]
+struct ReportRequest {
+    #[schema(description = "Server")]
+    server: Option<StackString>,
+    #[schema(description = "Last Day of the Report (default yesterday)")]
+    end_date: Option<DateType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    units: Option<UnitsWrapper>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(description = "Leave Out Rows Inserted After This Time (reproducible results)")]
+    asof: Option<DateTimeType>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Weekly or Monthly Climate Report", content = "html")]
+struct ReportResponse(HtmlBase<String, Error>);
+
+/// Standalone page of the daily summaries, records and anomalies of the 7
+/// days (`weekly`) or the month to date (`monthly`) ending on `end_date`
+#[get("/weather/reports/{period}/{location}")]
+#[openapi(tags("history"))]
+pub async fn climate_report(
+    #[data] data: AppState,
+    period: StackString,
+    location: StackString,
+    query: Query<ReportRequest>,
+    _: LoggedUser,
+) -> WarpResult<ReportResponse> {
+    let body = climate_report_body(&data, &period, &location, query.into_inner()).await?;
+    Ok(HtmlBase::new(body).into())
+}
+
+async fn climate_report_body(
+    data: &AppState,
+    period: &str,
+    location: &str,
+    query: ReportRequest,
+) -> HttpResult<String> {
+    let period: ReportPeriod = period
+        .parse()
+        .map_err(|e: anyhow::Error| Error::BadRequest(format_sstr!("{e}")))?;
+    let location = percent_decode_str(location)
+        .decode_utf8()
+        .map_err(|e| Error::BadRequest(format_sstr!("Invalid location {e}")))?;
+    let end_date = query
+        .end_date
+        .map_or_else(default_report_end_date, Into::into);
+    let report = get_climate_report(
+        data.history.as_ref(),
+        &location,
+        query.server.as_ref().map(StackString::as_str),
+        period,
+        end_date,
+        query.asof.map(Into::into),
+    )
+    .await?;
+    report.render_html(query.units.map_or(Units::Imperial, Into::into))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Weather History Record")]
+struct HistoryRecordResponse(JsonBase<WeatherDataDBWrapper, Error>);
+
+#[get("/weather/history/{id}")]
+#[openapi(tags("history"))]
+pub async fn history_get(
+    #[data] data: AppState,
+    id: StackString,
+    _: LoggedUser,
+) -> WarpResult<HistoryRecordResponse> {
+    let row = history_get_body(&data, &id).await?;
+    Ok(JsonBase::new(row.into()).into())
+}
+
+async fn history_get_body(data: &AppState, id: &str) -> HttpResult<WeatherDataDB> {
+    let id: Uuid = id
+        .parse()
+        .map_err(|_| Error::BadRequest(format_sstr!("invalid id {id}")))?;
+    data.history
+        .get_by_id(id)
+        .await?
+        .ok_or_else(|| Error::NotFound("history record not found".into()))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Corrected Weather History Record")]
+struct HistoryPatchResponse(JsonBase<WeatherDataDBWrapper, Error>);
+
+#[patch("/weather/history/{id}")]
+#[openapi(tags("history"))]
+pub async fn history_patch(
+    #[data] data: AppState,
+    id: StackString,
+    payload: Json<HistoryPatchRequest>,
+    _: LoggedUser,
+) -> WarpResult<HistoryPatchResponse> {
+    let row = history_patch_body(&data, &id, payload.into_inner()).await?;
+    Ok(JsonBase::new(row.into()).into())
+}
+
+async fn history_patch_body(
+    data: &AppState,
+    id: &str,
+    payload: HistoryPatchRequest,
+) -> HttpResult<WeatherDataDB> {
+    let id: Uuid = id
+        .parse()
+        .map_err(|_| Error::BadRequest(format_sstr!("invalid id {id}")))?;
+    let mut row = WeatherDataDB::get_by_id(&data.pool, id)
+        .await?
+        .ok_or_else(|| Error::NotFound("history record not found".into()))?;
+    payload.apply(&mut row)?;
+    row.update(&data.pool).await?;
+    Ok(row)
+}
+
+/// Most recent entry must be observed within this many seconds of now
+const COMPARISON_MAX_AGE: i32 = 3 * 3600;
+/// Yesterday's entry must be observed within this many seconds of 24 hours
+/// before the most recent entry
+const COMPARISON_MAX_OFFSET: i32 = 3600;
+const SECONDS_PER_DAY: i32 = 24 * 3600;
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "CompareRequest")]
+struct CompareRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    units: Option<UnitsWrapper>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Current Weather Compared to Yesterday")]
+struct CompareResponse(JsonBase<WeatherComparisonWrapper, Error>);
+
+#[get("/weather/compare/yesterday")]
+#[openapi(tags("history"))]
+pub async fn compare_yesterday(
+    #[data] data: AppState,
+    query: Query<AnalysisRequest>,
+) -> WarpResult<CompareResponse> {
+    let comparison = compare_yesterday_body(&data.pool, &query.into_inner().name).await?;
+    Ok(JsonBase::new(comparison.into()).into())
+}
+
+#[derive(RwebResponse)]
+#[response(
+    description = "Current Weather Compared to Yesterday",
+    content = "html"
+)]
+struct CompareHtmlResponse(HtmlBase<String, Error>);
+
+#[get("/weather/compare/yesterday.html")]
+#[openapi(tags("history"))]
+pub async fn compare_yesterday_html(
+    #[data] data: AppState,
+    query: Query<CompareRequest>,
+) -> WarpResult<CompareHtmlResponse> {
+    let query = query.into_inner();
+    let comparison = compare_yesterday_body(&data.pool, &query.name).await?;
+    let body = {
+        let mut app = VirtualDom::new_with_props(
+            ComparisonComponent,
+            ComparisonComponentProps {
+                comparison,
+                units: query.units.map(Into::into),
+            },
+        );
+        app.rebuild_in_place();
+        let mut renderer = dioxus_ssr::Renderer::default();
+        let mut buffer = String::new();
+        renderer
+            .render_to(&mut buffer, &app)
+            .map_err(Into::<Error>::into)?;
+        buffer
+    };
+    Ok(HtmlBase::new(body).into())
+}
+
+fn comparison_reading(row: &WeatherDataDB) -> ComparisonReading {
+    ComparisonReading {
+        created_at: row.created_at.to_string(),
+        temperature: row.temperature,
+        humidity: row.humidity,
+        pressure: row.pressure,
+    }
+}
+
+async fn compare_yesterday_body(pool: &PgPool, name: &str) -> HttpResult<WeatherComparison> {
+    let now = OffsetDateTime::now_utc().unix_timestamp() as i32;
+    let current = WeatherDataDB::get_closest_by_name(pool, name, now, COMPARISON_MAX_AGE)
+        .await
+        .map_err(Into::<Error>::into)?
+        .ok_or_else(|| Error::BadRequest(format_sstr!("No recent weather recorded for {name}")))?;
+    let yesterday = WeatherDataDB::get_closest_by_name(
+        pool,
+        name,
+        current.dt - SECONDS_PER_DAY,
+        COMPARISON_MAX_OFFSET,
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(WeatherComparison {
+        location_name: name.into(),
+        current: comparison_reading(&current),
+        yesterday: yesterday.as_ref().map(comparison_reading),
+    })
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "WeatherEvent")]
+struct WeatherEventObject {
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Server")]
+    server: StackString,
+    #[schema(description = "Event Type")]
+    event_type: StackString,
+    #[schema(description = "Event Start")]
+    start_time: DateTimeType,
+    #[schema(description = "Event End")]
+    end_time: DateTimeType,
+    #[schema(description = "Peak Wind Speed (m/s)")]
+    peak_wind_speed: f64,
+    #[schema(description = "Total Precipitation (mm)")]
+    total_precipitation: f64,
+    #[schema(description = "Minimum Pressure (kPa)")]
+    min_pressure: f64,
+    #[schema(description = "Maximum Pressure Drop Rate (kPa/h)")]
+    max_pressure_drop_rate: f64,
+}
+
+impl From<WeatherEvent> for WeatherEventObject {
+    fn from(value: WeatherEvent) -> Self {
+        Self {
+            location_name: value.location_name,
+            server: value.server,
+            event_type: value.event_type,
+            start_time: value.start_time.to_offsetdatetime().into(),
+            end_time: value.end_time.to_offsetdatetime().into(),
+            peak_wind_speed: value.peak_wind_speed,
+            total_precipitation: value.total_precipitation,
+            min_pressure: value.min_pressure,
+            max_pressure_drop_rate: value.max_pressure_drop_rate,
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Detected Weather Events")]
+struct WeatherEventsResponse(JsonBase<Vec<WeatherEventObject>, Error>);
+
+#[get("/weather/events")]
+#[openapi(tags("history"))]
+pub async fn events(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+) -> WarpResult<WeatherEventsResponse> {
+    let query = query.into_inner();
+    let events: Vec<WeatherEventObject> = WeatherEvent::get_events(
+        &data.pool,
+        Some(&query.name),
+        query.server.as_ref().map(StackString::as_str),
+        query.start_time.map(Into::into),
+        query.end_time.map(Into::into),
+    )
+    .await
+    .map_err(Into::<Error>::into)?
+    .map_ok(Into::into)
+    .try_collect()
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(events).into())
+}
+
+#[derive(Debug, Serialize, Deserialize, Schema)]
+#[schema(component = "WeatherSnapshot")]
+struct WeatherSnapshotObject {
+    #[schema(description = "ID")]
+    id: UuidWrapper,
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Taken At")]
+    taken_at: DateTimeType,
+    #[schema(description = "Image URL")]
+    url: StackString,
+    #[schema(description = "Content Type")]
+    content_type: Option<StackString>,
+}
+
+impl From<WeatherSnapshot> for WeatherSnapshotObject {
+    fn from(value: WeatherSnapshot) -> Self {
+        Self {
+            id: value.id.into(),
+            url: value.get_url(),
+            location_name: value.location_name,
+            taken_at: value.taken_at.to_offsetdatetime().into(),
+            content_type: value.content_type,
+        }
+    }
+}
+
+async fn get_snapshots(
+    pool: &PgPool,
+    name: Option<&str>,
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+) -> HttpResult<Vec<WeatherSnapshotObject>> {
+    WeatherSnapshot::get_snapshots(pool, name, start_date, end_date)
+        .await
+        .map_err(Into::<Error>::into)?
+        .map_ok(Into::into)
+        .try_collect()
+        .await
+        .map_err(Into::into)
+}
+
+#[derive(Deserialize, Schema)]
+struct SnapshotRequest {
+    name: Option<StackString>,
+    start_time: Option<DateType>,
+    end_time: Option<DateType>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Weather Snapshots")]
+struct SnapshotsResponse(JsonBase<Vec<WeatherSnapshotObject>, Error>);
+
+#[get("/weather/snapshots")]
+#[openapi(tags("history"))]
+pub async fn snapshots(
+    #[data] data: AppState,
+    query: Query<SnapshotRequest>,
+    _: LoggedUser,
+) -> WarpResult<SnapshotsResponse> {
+    let query = query.into_inner();
+    let snapshots = get_snapshots(
+        &data.pool,
+        query.name.as_ref().map(StackString::as_str),
+        query.start_time.map(Into::into),
+        query.end_time.map(Into::into),
+    )
+    .await?;
+    Ok(JsonBase::new(snapshots).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "SnapshotLinkRequest")]
+struct SnapshotLinkRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Image URL")]
+    image_url: StackString,
+    #[schema(description = "Taken At (defaults to now)")]
+    taken_at: Option<DateTimeType>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Created Snapshot", status = "CREATED")]
+struct SnapshotCreatedResponse(JsonBase<WeatherSnapshotObject, Error>);
+
+#[post("/weather/snapshots")]
+#[openapi(tags("history"))]
+pub async fn snapshot_link(
+    #[data] data: AppState,
+    payload: Json<SnapshotLinkRequest>,
+    _: LoggedUser,
+) -> WarpResult<SnapshotCreatedResponse> {
+    let payload = payload.into_inner();
+    let taken_at: OffsetDateTime = payload
+        .taken_at
+        .map_or_else(OffsetDateTime::now_utc, Into::into);
+    let snapshot = WeatherSnapshot {
+        id: Uuid::new_v4(),
+        location_name: payload.name,
+        taken_at: taken_at.into(),
+        image_url: Some(payload.image_url),
+        s3_key: None,
+        content_type: None,
+        created_at: DateTimeWrapper::now(),
+    };
+    snapshot
+        .insert(&data.pool)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(snapshot.into()).into())
+}
+
+#[cfg(feature = "s3-sync")]
+#[derive(Serialize, Deserialize, Schema)]
+struct SnapshotUploadRequest {
+    name: StackString,
+    taken_at: Option<DateTimeType>,
+}
+
+#[cfg(feature = "s3-sync")]
+#[post("/weather/snapshots/upload")]
+#[openapi(tags("history"))]
+pub async fn snapshot_upload(
+    #[data] data: AppState,
+    query: Query<SnapshotUploadRequest>,
+    #[header = "content-type"] content_type: String,
+    #[body] body: Bytes,
+    _: LoggedUser,
+) -> WarpResult<SnapshotCreatedResponse> {
+    let query = query.into_inner();
+    let snapshot = snapshot_upload_body(&data, query, content_type.into(), body).await?;
+    Ok(JsonBase::new(snapshot.into()).into())
+}
+
+#[cfg(feature = "s3-sync")]
+async fn snapshot_upload_body(
+    data: &AppState,
+    query: SnapshotUploadRequest,
+    content_type: StackString,
+    body: Bytes,
+) -> HttpResult<WeatherSnapshot> {
+    if !content_type.starts_with("image/") {
+        return Err(Error::BadRequest("snapshot must be an image".into()));
+    }
+    if body.len() as u64 > MAX_SNAPSHOT_SIZE {
+        return Err(Error::BadRequest(format_sstr!(
+            "snapshot exceeds {MAX_SNAPSHOT_SIZE} bytes"
+        )));
+    }
+    let id = Uuid::new_v4();
+    let key = get_snapshot_key(&query.name, id, &content_type);
+    let aws_config = aws_config::load_from_env().await;
+    let sync = S3Sync::new(&aws_config);
+    sync.upload_bytes(&data.config.s3_bucket, &key, body.to_vec(), &content_type)
+        .await?;
+    let taken_at: OffsetDateTime = query
+        .taken_at
+        .map_or_else(OffsetDateTime::now_utc, Into::into);
+    let snapshot = WeatherSnapshot {
+        id,
+        location_name: query.name,
+        taken_at: taken_at.into(),
+        image_url: None,
+        s3_key: Some(key),
+        content_type: Some(content_type),
+        created_at: DateTimeWrapper::now(),
+    };
+    snapshot.insert(&data.pool).await?;
+    Ok(snapshot)
+}
+
+#[cfg(feature = "s3-sync")]
+#[derive(Serialize, Deserialize, Schema)]
+struct SnapshotImageRequest {
+    id: UuidWrapper,
+}
+
+#[cfg(feature = "s3-sync")]
+#[get("/weather/snapshots/image")]
+#[openapi(tags("history"))]
+pub async fn snapshot_image(
+    #[data] data: AppState,
+    query: Query<SnapshotImageRequest>,
+) -> WarpResult<SnapshotImageResponse> {
+    let id: Uuid = query.into_inner().id.into();
+    let response = snapshot_image_body(&data, id).await?;
+    Ok(response)
+}
+
+#[cfg(feature = "s3-sync")]
+async fn snapshot_image_body(data: &AppState, id: Uuid) -> HttpResult<SnapshotImageResponse> {
+    let snapshot = WeatherSnapshot::get_by_id(&data.pool, id)
+        .await?
+        .ok_or_else(|| Error::BadRequest("snapshot not found".into()))?;
+    let key = snapshot
+        .s3_key
+        .ok_or_else(|| Error::BadRequest("snapshot is not stored on s3".into()))?;
+    let aws_config = aws_config::load_from_env().await;
+    let sync = S3Sync::new(&aws_config);
+    let image = sync.download_bytes(&data.config.s3_bucket, &key).await?;
+    Ok(SnapshotImageResponse {
+        content_type: snapshot
+            .content_type
+            .unwrap_or_else(|| "application/octet-stream".into()),
+        data: image,
+    })
+}
@@ -0,0 +1,653 @@
+use dioxus::prelude::VirtualDom;
+use rweb::{filters::BoxedFilter, get, reply::Response, Filter, Query, Reply};
+use stack_string::StackString;
+use std::convert::Infallible;
+use time::{OffsetDateTime, UtcOffset};
+
+use rweb_helper::{
+    html_response::HtmlResponse as HtmlBase, json_response::JsonResponse as JsonBase, RwebResponse,
+};
+use weather_api_common::weather_element::{ForecastComponent, ForecastComponentProps};
+use weather_api_types::{
+    activity::{get_activity_scores, get_forecast_conditions, ActivityConditions},
+    get_parameters,
+    plot::PlotPoint,
+    units::Units,
+};
+use weather_util_rust::{
+    weather_api::{WeatherApi, WeatherLocation},
+    weather_data::WeatherData,
+    weather_forecast::WeatherForecast,
+};
+
+use crate::{
+    api_options::ApiOptions,
+    app::AppState,
+    degraded::{Degraded, DegradedResponse},
+    downsample::lttb,
+    errors::ServiceError as Error,
+    get_forecast_humidity_plot, get_forecast_plots, get_forecast_precip_plot,
+    get_forecast_pressure_plot, get_forecast_temp_plot, get_forecast_wind_plot,
+    get_history_dew_point_plot, get_history_heat_index_plot, get_history_humidity_plot,
+    get_history_plots, get_history_precip_plot, get_history_pressure_plot,
+    get_history_temperature_plot, get_history_uv_index_plot, get_history_wind_chill_plot,
+    get_history_wind_plot,
+    model::UvIndexData,
+    offline_forecast::OFFLINE_PROVIDER,
+    onecall::{fetch_onecall, OneCallPart},
+    providers::get_lat_lon,
+    render_stats::record_render,
+    routes::{
+        get_history_data, AnalysisRequest, HistoryPlotRequest, HttpResult, PlotDataResponse,
+        PlotFormatOptions, RefreshOptions, WarpResult,
+    },
+    PlotDataWrapper, PlotPointWrapper,
+};
+
+/// Plot pages and plot data series, tagged `plots`
+pub fn get_plots_path(app: &AppState) -> BoxedFilter<(Response,)> {
+    let forecast_plot_path = forecast_plot(app.clone()).boxed();
+    let timeseries_js_path = timeseries_js().boxed();
+    let history_plot_path = history_plot(app.clone()).boxed();
+    let forecast_plots_path = forecast_plots(app.clone()).boxed();
+    let history_plots_path = history_plots(app.clone()).boxed();
+    let forecast_temp_plot_path = forecast_temp_plot(app.clone()).boxed();
+    let forecast_precip_plot_path = forecast_precip_plot(app.clone()).boxed();
+    let forecast_humidity_plot_path = forecast_humidity_plot(app.clone()).boxed();
+    let forecast_pressure_plot_path = forecast_pressure_plot(app.clone()).boxed();
+    let forecast_wind_plot_path = forecast_wind_plot(app.clone()).boxed();
+    let history_temp_plot_path = history_temp_plot(app.clone()).boxed();
+    let history_precip_plot_path = history_precip_plot(app.clone()).boxed();
+    let history_humidity_plot_path = history_humidity_plot(app.clone()).boxed();
+    let history_pressure_plot_path = history_pressure_plot(app.clone()).boxed();
+    let history_wind_plot_path = history_wind_plot(app.clone()).boxed();
+    let history_dew_point_plot_path = history_dew_point_plot(app.clone()).boxed();
+    let history_heat_index_plot_path = history_heat_index_plot(app.clone()).boxed();
+    let history_wind_chill_plot_path = history_wind_chill_plot(app.clone()).boxed();
+    let history_uv_index_plot_path = history_uv_index_plot(app.clone()).boxed();
+    let activity_score_path = activity_score(app.clone()).boxed();
+
+    forecast_plot_path
+        .or(timeseries_js_path)
+        .or(history_plot_path)
+        .or(forecast_plots_path)
+        .or(history_plots_path)
+        .or(forecast_temp_plot_path)
+        .or(forecast_precip_plot_path)
+        .or(forecast_humidity_plot_path)
+        .or(forecast_pressure_plot_path)
+        .or(forecast_wind_plot_path)
+        .or(history_temp_plot_path)
+        .or(history_precip_plot_path)
+        .or(history_humidity_plot_path)
+        .or(history_pressure_plot_path)
+        .or(history_wind_plot_path)
+        .or(history_dew_point_plot_path)
+        .or(history_heat_index_plot_path)
+        .or(history_wind_chill_plot_path)
+        .or(history_uv_index_plot_path)
+        .or(activity_score_path)
+        .map(Reply::into_response)
+        .boxed()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "TimeseriesScript", content = "js")]
+struct TimeseriesJsResponse(HtmlBase<&'static str, Infallible>);
+
+#[get("/weather/timeseries.js")]
+#[openapi(tags("plots"))]
+pub async fn timeseries_js() -> WarpResult<TimeseriesJsResponse> {
+    Ok(HtmlBase::new(include_str!("../../templates/timeseries.js")).into())
+}
+
+#[derive(RwebResponse)]
+#[response(
+    description = "Show Plot of Current Weather and Forecast",
+    content = "html"
+)]
+struct WeatherPlotResponse(HtmlBase<String, Error>);
+
+#[get("/weather/plot.html")]
+#[openapi(tags("plots"))]
+pub async fn forecast_plot(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    refresh: Query<RefreshOptions>,
+) -> WarpResult<DegradedResponse<WeatherPlotResponse>> {
+    let query = query.into_inner();
+    let refresh = refresh.into_inner().get_refresh();
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let mut degraded = Degraded::default();
+    let (weather, offline) = match data.weather.get_weather(&api, &loc).await {
+        Ok(weather) => (weather, false),
+        Err(e) => match data.weather.get_recorded_weather(&loc).await? {
+            Some(weather) => {
+                degraded.add(
+                    "weather",
+                    "Current weather is unavailable, showing the last recorded observation",
+                    &e,
+                );
+                (weather, true)
+            }
+            None => return Err(e.into()),
+        },
+    };
+
+    let mut plots = get_forecast_plots(&query, &weather).map_err(Into::<Error>::into)?;
+    if offline {
+        for plot in &mut plots {
+            plot.title = format!("{} ({OFFLINE_PROVIDER})", plot.title);
+        }
+    }
+
+    let body = {
+        let mut app = VirtualDom::new_with_props(
+            ForecastComponent,
+            ForecastComponentProps {
+                weather,
+                plots,
+                refresh,
+                warnings: degraded.warnings(),
+            },
+        );
+        app.rebuild_in_place();
+        let mut renderer = dioxus_ssr::Renderer::default();
+        let mut buffer = String::new();
+        renderer
+            .render_to(&mut buffer, &app)
+            .map_err(Into::<Error>::into)?;
+        buffer
+    };
+
+    record_render("/weather/plot.html", body.len());
+    Ok(DegradedResponse::new(HtmlBase::new(body).into(), &degraded))
+}
+
+/// Forecast from the provider, or the offline forecast of the recorded
+/// location when the provider can't be reached
+async fn get_plot_forecast(
+    data: &AppState,
+    api: &WeatherApi,
+    loc: &WeatherLocation,
+    degraded: &mut Degraded,
+) -> HttpResult<WeatherForecast> {
+    match data.weather.get_forecast(api, loc).await {
+        Ok(forecast) => Ok(forecast),
+        Err(e) => match data.weather.get_offline_forecast(loc).await {
+            Ok(forecast) => {
+                degraded.add(
+                    "forecast",
+                    "Forecast is unavailable, using the offline forecast",
+                    &e,
+                );
+                Ok(forecast)
+            }
+            Err(_) => Err(e),
+        },
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Show Plot of Historical Weather", content = "html")]
+struct HistoryPlotResponse(HtmlBase<String, Error>);
+
+#[get("/weather/history_plot.html")]
+#[openapi(tags("plots"))]
+pub async fn history_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+) -> WarpResult<HistoryPlotResponse> {
+    let query = query.into_inner();
+    let history = get_history_data(&query, data.history.as_ref()).await?;
+
+    if history.is_empty() {
+        return Ok(HtmlBase::new(String::new()).into());
+    }
+    let weather = history.first().unwrap().clone();
+    let query_string = serde_urlencoded::to_string(&query).map_err(Into::<Error>::into)?;
+    let plots = get_history_plots(&query_string, &weather, query.get_units());
+
+    let body = {
+        let mut app = VirtualDom::new_with_props(
+            ForecastComponent,
+            ForecastComponentProps {
+                weather,
+                plots,
+                refresh: None,
+                warnings: Vec::new(),
+            },
+        );
+        app.rebuild_in_place();
+        let mut renderer = dioxus_ssr::Renderer::default();
+        let mut buffer = String::new();
+        renderer
+            .render_to(&mut buffer, &app)
+            .map_err(Into::<Error>::into)?;
+        buffer
+    };
+
+    record_render("/weather/history_plot.html", body.len());
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Forecast Plot Data")]
+struct ForecastPlotsResponse(JsonBase<Vec<PlotDataWrapper>, Error>);
+
+#[get("/weather/forecast-plots")]
+#[openapi(tags("plots"))]
+pub async fn forecast_plots(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<ForecastPlotsResponse> {
+    let query = query.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+
+    let weather = data.weather.get_weather(&api, &loc).await?;
+
+    let plots = get_forecast_plots(&query, &weather)
+        .map_err(Into::<Error>::into)?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(JsonBase::new(plots).into())
+}
+
+/// Forecast plot series in the units of `query`
+async fn forecast_series_plot(
+    data: &AppState,
+    query: ApiOptions,
+    format: PlotFormatOptions,
+    metric: &'static str,
+    series: impl FnOnce(&WeatherForecast, Units) -> Vec<PlotPoint>,
+) -> HttpResult<DegradedResponse<PlotDataResponse>> {
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+
+    let mut degraded = Degraded::default();
+    let forecast = get_plot_forecast(data, &api, &loc, &mut degraded).await?;
+    let plots: Vec<PlotPointWrapper> = series(&forecast, query.get_units(Units::Imperial))
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let plots = format.format(&data.config, metric, plots);
+    Ok(DegradedResponse::new(
+        JsonBase::new(plots).into(),
+        &degraded,
+    ))
+}
+
+#[get("/weather/forecast-plots/temperature")]
+#[openapi(tags("plots"))]
+pub async fn forecast_temp_plot(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<DegradedResponse<PlotDataResponse>> {
+    let plots = forecast_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "temperature",
+        get_forecast_temp_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/forecast-plots/precipitation")]
+#[openapi(tags("plots"))]
+pub async fn forecast_precip_plot(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<DegradedResponse<PlotDataResponse>> {
+    let plots = forecast_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "precipitation",
+        get_forecast_precip_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/forecast-plots/humidity")]
+#[openapi(tags("plots"))]
+pub async fn forecast_humidity_plot(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<DegradedResponse<PlotDataResponse>> {
+    let plots = forecast_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "humidity",
+        |forecast, _| get_forecast_humidity_plot(forecast),
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/forecast-plots/pressure")]
+#[openapi(tags("plots"))]
+pub async fn forecast_pressure_plot(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<DegradedResponse<PlotDataResponse>> {
+    let plots = forecast_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "pressure",
+        |forecast, _| get_forecast_pressure_plot(forecast),
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/forecast-plots/wind")]
+#[openapi(tags("plots"))]
+pub async fn forecast_wind_plot(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<DegradedResponse<PlotDataResponse>> {
+    let plots = forecast_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "wind_speed",
+        get_forecast_wind_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Historical Plot Data")]
+struct HistoryPlotsResponse(JsonBase<Vec<PlotDataWrapper>, Error>);
+
+#[get("/weather/history-plots")]
+#[openapi(tags("plots"))]
+pub async fn history_plots(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+) -> WarpResult<HistoryPlotsResponse> {
+    let query = query.into_inner();
+    let query_string = serde_urlencoded::to_string(&query).map_err(Into::<Error>::into)?;
+    let history = get_history_data(&query, data.history.as_ref()).await?;
+
+    let plots = if let Some(weather) = history.first() {
+        get_history_plots(&query_string, weather, query.get_units())
+            .into_iter()
+            .map(Into::into)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(JsonBase::new(plots).into())
+}
+
+/// History plot series in the units of `query`, downsampled to its maximum
+/// number of points
+async fn history_series_plot(
+    data: &AppState,
+    query: HistoryPlotRequest,
+    format: PlotFormatOptions,
+    metric: &'static str,
+    series: impl FnOnce(&[WeatherData], Units) -> Vec<PlotPoint>,
+) -> HttpResult<PlotDataResponse> {
+    let history = get_history_data(&query, data.history.as_ref()).await?;
+    let plots = series(&history, query.get_units());
+    let plots: Vec<PlotPointWrapper> = lttb(&plots, query.get_max_points(&data.config))
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let plots = format.format(&data.config, metric, plots);
+    Ok(JsonBase::new(plots).into())
+}
+
+#[get("/weather/history-plots/temperature")]
+#[openapi(tags("plots"))]
+pub async fn history_temp_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "temperature",
+        get_history_temperature_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/history-plots/precipitation")]
+#[openapi(tags("plots"))]
+pub async fn history_precip_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "precipitation",
+        get_history_precip_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/history-plots/humidity")]
+#[openapi(tags("plots"))]
+pub async fn history_humidity_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "humidity",
+        |history, _| get_history_humidity_plot(history),
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/history-plots/pressure")]
+#[openapi(tags("plots"))]
+pub async fn history_pressure_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "pressure",
+        |history, _| get_history_pressure_plot(history),
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/history-plots/wind")]
+#[openapi(tags("plots"))]
+pub async fn history_wind_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "wind_speed",
+        get_history_wind_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/history-plots/dew-point")]
+#[openapi(tags("plots"))]
+pub async fn history_dew_point_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "dew_point",
+        get_history_dew_point_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/history-plots/heat-index")]
+#[openapi(tags("plots"))]
+pub async fn history_heat_index_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "heat_index",
+        get_history_heat_index_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+#[get("/weather/history-plots/wind-chill")]
+#[openapi(tags("plots"))]
+pub async fn history_wind_chill_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_series_plot(
+        &data,
+        query.into_inner(),
+        format.into_inner(),
+        "wind_chill",
+        get_history_wind_chill_plot,
+    )
+    .await?;
+    Ok(plots)
+}
+
+/// Recorded uv index readings, see `/weather/uv`
+#[get("/weather/history-plots/uv-index")]
+#[openapi(tags("plots"))]
+pub async fn history_uv_index_plot(
+    #[data] data: AppState,
+    query: Query<HistoryPlotRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let plots = history_uv_index_plot_body(&data, query.into_inner(), format.into_inner()).await?;
+    Ok(plots)
+}
+
+async fn history_uv_index_plot_body(
+    data: &AppState,
+    query: HistoryPlotRequest,
+    format: PlotFormatOptions,
+) -> HttpResult<PlotDataResponse> {
+    let readings = UvIndexData::get_by_name_dates(
+        &data.pool,
+        &query.name,
+        query.server.as_ref().map(StackString::as_str),
+        query.start_time.map(Into::into),
+        query.end_time.map(Into::into),
+    )
+    .await?;
+    let plots = get_history_uv_index_plot(&readings);
+    let plots: Vec<PlotPointWrapper> = lttb(&plots, query.get_max_points(&data.config))
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let plots = format.format(&data.config, "uvi", plots);
+    Ok(JsonBase::new(plots).into())
+}
+
+/// Utc offset and hourly conditions of the One Call forecast of `loc`
+async fn get_hourly_conditions(
+    data: &AppState,
+    loc: &WeatherLocation,
+) -> Option<(UtcOffset, Vec<(OffsetDateTime, ActivityConditions)>)> {
+    let exclude = [
+        OneCallPart::Current,
+        OneCallPart::Minutely,
+        OneCallPart::Daily,
+        OneCallPart::Alerts,
+    ];
+    let loc = data.locations.resolve(&data.api, loc).await.ok()?;
+    let (latitude, longitude) = get_lat_lon(&loc).ok()?;
+    let onecall = fetch_onecall(
+        &data.client,
+        &data.config,
+        &data.config.api_key,
+        latitude,
+        longitude,
+        &exclude,
+    )
+    .await
+    .ok()?;
+    let offset = UtcOffset::from_whole_seconds(onecall.timezone_offset).ok()?;
+    let conditions: Vec<_> = onecall
+        .hourly?
+        .iter()
+        .map(|hour| (hour.dt, hour.activity_conditions()))
+        .collect();
+    (!conditions.is_empty()).then_some((offset, conditions))
+}
+
+#[get("/weather/analysis/activity-score")]
+#[openapi(tags("plots"))]
+pub async fn activity_score(
+    #[data] data: AppState,
+    query: Query<AnalysisRequest>,
+    format: Query<PlotFormatOptions>,
+) -> WarpResult<PlotDataResponse> {
+    let query = query.into_inner();
+    let loc = get_parameters(&query.name);
+    let now = OffsetDateTime::now_utc();
+    // One Call needs its own subscription, without it the three hour
+    // forecast is scored without precipitation probability and UV index
+    let scores = match get_hourly_conditions(&data, &loc).await {
+        Some((offset, conditions)) => get_activity_scores(&conditions, offset, now, 48),
+        None => {
+            let forecast = data.weather.get_forecast(&data.api, &loc).await?;
+            let conditions = get_forecast_conditions(&forecast);
+            get_activity_scores(&conditions, forecast.city.timezone.into(), now, 48)
+        }
+    };
+    let plots: Vec<PlotPointWrapper> = scores.into_iter().map(Into::into).collect();
+    let plots = format
+        .into_inner()
+        .format(&data.config, "activity_score", plots);
+    Ok(JsonBase::new(plots).into())
+}
@@ -0,0 +1,355 @@
+use futures::TryStreamExt;
+use rweb::{
+    delete, filters::BoxedFilter, get, post, reply::Response, Filter, Json, Query, Reply, Schema,
+};
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use uuid::Uuid;
+
+use rweb_helper::{
+    json_response::JsonResponse as JsonBase, DateTimeType, RwebResponse, UuidWrapper,
+};
+use weather_api_types::get_parameters;
+
+use crate::{
+    alerts::{AlertComparison, AlertMetric},
+    app::AppState,
+    date_time_wrapper::DateTimeWrapper,
+    errors::ServiceError as Error,
+    logged_user::{fill_api_keys_from_db, LoggedUser},
+    model::{AlertRule, ApiKey},
+    notify::{notify_all, Notification},
+    pgpool::PgPool,
+    routes::{HttpResult, WarpResult},
+};
+
+/// Current user, api keys, alert rules and notifications, tagged `user`
+pub fn get_user_path(app: &AppState) -> BoxedFilter<(Response,)> {
+    let alert_rules_path = alert_rules(app.clone()).boxed();
+    let alert_rule_create_path = alert_rule_create(app.clone()).boxed();
+    let alert_rule_delete_path = alert_rule_delete(app.clone()).boxed();
+    let api_keys_path = api_keys(app.clone()).boxed();
+    let api_key_create_path = api_key_create(app.clone()).boxed();
+    let api_key_revoke_path = api_key_revoke(app.clone()).boxed();
+    let notify_test_path = notify_test(app.clone()).boxed();
+    let user_path = user().boxed();
+
+    alert_rules_path
+        .or(alert_rule_create_path)
+        .or(alert_rule_delete_path)
+        .or(api_keys_path)
+        .or(api_key_create_path)
+        .or(api_key_revoke_path)
+        .or(notify_test_path)
+        .or(user_path)
+        .map(Reply::into_response)
+        .boxed()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Logged in User")]
+struct UserResponse(JsonBase<LoggedUser, Error>);
+
+#[get("/weather/user")]
+#[openapi(tags("user"))]
+pub async fn user(user: LoggedUser) -> WarpResult<UserResponse> {
+    Ok(JsonBase::new(user).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "AlertRule")]
+struct AlertRuleObject {
+    #[schema(description = "ID")]
+    id: UuidWrapper,
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(
+        description = "Metric (temperature (C), wind_speed (m/s), humidity (%), pressure (kPa) \
+                       or precipitation (mm))"
+    )]
+    metric: StackString,
+    #[schema(description = "Comparison (above or below)")]
+    comparison: StackString,
+    #[schema(description = "Threshold")]
+    threshold: f64,
+    #[schema(description = "Evaluate the Forecast for the Next N Hours")]
+    forecast_hours: Option<i32>,
+    #[schema(description = "Webhook URL")]
+    webhook_url: StackString,
+    #[schema(description = "Last Fired At")]
+    last_fired_at: Option<DateTimeType>,
+}
+
+impl From<AlertRule> for AlertRuleObject {
+    fn from(value: AlertRule) -> Self {
+        Self {
+            id: value.id.into(),
+            location_name: value.location_name,
+            metric: value.metric,
+            comparison: value.comparison,
+            threshold: value.threshold,
+            forecast_hours: value.forecast_hours,
+            webhook_url: value.webhook_url,
+            last_fired_at: value.last_fired_at.map(|t| t.to_offsetdatetime().into()),
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Alert Rules")]
+struct AlertRulesResponse(JsonBase<Vec<AlertRuleObject>, Error>);
+
+#[get("/weather/alert-rules")]
+#[openapi(tags("user"))]
+pub async fn alert_rules(
+    #[data] data: AppState,
+    user: LoggedUser,
+) -> WarpResult<AlertRulesResponse> {
+    let rules: Vec<AlertRuleObject> = AlertRule::get_by_email(&data.pool, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?
+        .map_ok(Into::into)
+        .try_collect()
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(rules).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "AlertRuleRequest")]
+struct AlertRuleRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Metric")]
+    metric: StackString,
+    #[schema(description = "Comparison (above or below)")]
+    comparison: StackString,
+    #[schema(description = "Threshold")]
+    threshold: f64,
+    #[schema(description = "Evaluate the Forecast for the Next N Hours")]
+    forecast_hours: Option<i32>,
+    #[schema(description = "Webhook URL")]
+    webhook_url: StackString,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Created Alert Rule", status = "CREATED")]
+struct AlertRuleCreatedResponse(JsonBase<AlertRuleObject, Error>);
+
+#[post("/weather/alert-rules")]
+#[openapi(tags("user"))]
+pub async fn alert_rule_create(
+    #[data] data: AppState,
+    payload: Json<AlertRuleRequest>,
+    user: LoggedUser,
+) -> WarpResult<AlertRuleCreatedResponse> {
+    let rule = alert_rule_create_body(&data.pool, payload.into_inner(), user.email).await?;
+    Ok(JsonBase::new(rule.into()).into())
+}
+
+async fn alert_rule_create_body(
+    pool: &PgPool,
+    payload: AlertRuleRequest,
+    email: StackString,
+) -> HttpResult<AlertRule> {
+    let metric: AlertMetric = payload
+        .metric
+        .parse()
+        .map_err(|e| Error::BadRequest(format_sstr!("{e}")))?;
+    let comparison: AlertComparison = payload
+        .comparison
+        .parse()
+        .map_err(|e| Error::BadRequest(format_sstr!("{e}")))?;
+    if payload
+        .forecast_hours
+        .is_some_and(|h| !(1..=120).contains(&h))
+    {
+        return Err(Error::BadRequest(
+            "forecast_hours must be between 1 and 120".into(),
+        ));
+    }
+    if !payload.webhook_url.starts_with("https://") && !payload.webhook_url.starts_with("http://") {
+        return Err(Error::BadRequest(
+            "webhook_url must be an http(s) url".into(),
+        ));
+    }
+    let rule = AlertRule {
+        id: Uuid::new_v4(),
+        email,
+        location_name: format_sstr!("{}", get_parameters(&payload.name)),
+        metric: metric.to_str().into(),
+        comparison: comparison.to_str().into(),
+        threshold: payload.threshold,
+        forecast_hours: payload.forecast_hours,
+        webhook_url: payload.webhook_url,
+        last_fired_at: None,
+        created_at: DateTimeWrapper::now(),
+    };
+    rule.insert(pool).await?;
+    Ok(rule)
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+struct AlertRuleDeleteRequest {
+    id: UuidWrapper,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Deleted Alert Rules")]
+struct AlertRuleDeleteResponse(JsonBase<u64, Error>);
+
+#[delete("/weather/alert-rules")]
+#[openapi(tags("user"))]
+pub async fn alert_rule_delete(
+    #[data] data: AppState,
+    query: Query<AlertRuleDeleteRequest>,
+    user: LoggedUser,
+) -> WarpResult<AlertRuleDeleteResponse> {
+    let id: Uuid = query.into_inner().id.into();
+    let deleted = AlertRule::delete(&data.pool, id, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(deleted).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ApiKey")]
+struct ApiKeyObject {
+    #[schema(description = "ID")]
+    id: UuidWrapper,
+    #[schema(description = "Description")]
+    description: Option<StackString>,
+    #[schema(description = "Created At")]
+    created_at: DateTimeType,
+    #[schema(description = "Revoked At")]
+    revoked_at: Option<DateTimeType>,
+}
+
+impl From<ApiKey> for ApiKeyObject {
+    fn from(value: ApiKey) -> Self {
+        Self {
+            id: value.id.into(),
+            description: value.description,
+            created_at: value.created_at.to_offsetdatetime().into(),
+            revoked_at: value.revoked_at.map(|t| t.to_offsetdatetime().into()),
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Api Keys")]
+struct ApiKeysResponse(JsonBase<Vec<ApiKeyObject>, Error>);
+
+#[get("/weather/api-keys")]
+#[openapi(tags("user"))]
+pub async fn api_keys(
+    #[data] data: AppState,
+    #[filter = "LoggedUser::session_filter"] user: LoggedUser,
+) -> WarpResult<ApiKeysResponse> {
+    let keys: Vec<ApiKeyObject> = ApiKey::get_by_email(&data.pool, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?
+        .map_ok(Into::into)
+        .try_collect()
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(keys).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ApiKeyRequest")]
+struct ApiKeyRequest {
+    #[schema(description = "Description")]
+    description: Option<StackString>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ApiKeyCreated")]
+struct ApiKeyCreatedObject {
+    #[schema(description = "Api Key, send as the X-Api-Key header, it is only shown once")]
+    key: StackString,
+    #[schema(description = "Api Key")]
+    api_key: ApiKeyObject,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Created Api Key", status = "CREATED")]
+struct ApiKeyCreatedResponse(JsonBase<ApiKeyCreatedObject, Error>);
+
+#[post("/weather/api-keys")]
+#[openapi(tags("user"))]
+pub async fn api_key_create(
+    #[data] data: AppState,
+    payload: Json<ApiKeyRequest>,
+    #[filter = "LoggedUser::session_filter"] user: LoggedUser,
+) -> WarpResult<ApiKeyCreatedResponse> {
+    let (api_key, key) = ApiKey::generate(&user.email, payload.into_inner().description);
+    api_key
+        .insert(&data.pool)
+        .await
+        .map_err(Into::<Error>::into)?;
+    fill_api_keys_from_db(&data.pool).await?;
+    Ok(JsonBase::new(ApiKeyCreatedObject {
+        key,
+        api_key: api_key.into(),
+    })
+    .into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+struct ApiKeyRevokeRequest {
+    id: UuidWrapper,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Revoked Api Keys")]
+struct ApiKeyRevokeResponse(JsonBase<u64, Error>);
+
+#[delete("/weather/api-keys")]
+#[openapi(tags("user"))]
+pub async fn api_key_revoke(
+    #[data] data: AppState,
+    query: Query<ApiKeyRevokeRequest>,
+    #[filter = "LoggedUser::session_filter"] user: LoggedUser,
+) -> WarpResult<ApiKeyRevokeResponse> {
+    let id: Uuid = query.into_inner().id.into();
+    let revoked = ApiKey::revoke(&data.pool, id, &user.email)
+        .await
+        .map_err(Into::<Error>::into)?;
+    fill_api_keys_from_db(&data.pool).await?;
+    Ok(JsonBase::new(revoked).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "NotifyResult")]
+struct NotifyResult {
+    #[schema(description = "Sink (webhook, ntfy or email)")]
+    sink: StackString,
+    #[schema(description = "Error, null when the notification was sent")]
+    error: Option<StackString>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Test Notification Results")]
+struct NotifyTestResponse(JsonBase<Vec<NotifyResult>, Error>);
+
+#[post("/weather/notify/test")]
+#[openapi(tags("user"))]
+pub async fn notify_test(
+    #[data] data: AppState,
+    user: LoggedUser,
+) -> WarpResult<NotifyTestResponse> {
+    let notification = Notification::new(
+        "Weather test notification",
+        format_sstr!("Test notification requested by {}", user.email),
+    );
+    let results = notify_all(&data.client, &data.config, &notification)
+        .await
+        .into_iter()
+        .map(|(sink, result)| NotifyResult {
+            sink: sink.into(),
+            error: result.err().map(|e| format_sstr!("{e}")),
+        })
+        .collect();
+    Ok(JsonBase::new(results).into())
+}
@@ -0,0 +1,1248 @@
+use dioxus::prelude::VirtualDom;
+use futures::future::join_all;
+use rweb::{filters::BoxedFilter, get, reply::Response, Filter, Query, Reply, Schema};
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use time::{Duration, OffsetDateTime, UtcOffset};
+use time_tz::{timezones, Offset, TimeZone};
+
+use rweb_helper::{
+    html_response::HtmlResponse as HtmlBase, json_response::JsonResponse as JsonBase, DateTimeType,
+    DateType, RwebResponse,
+};
+use weather_api_common::weather_element::{WeatherComponent, WeatherComponentProps};
+use weather_api_types::{get_parameters, units::Units};
+use weather_util_rust::{weather_data::WeatherData, weather_forecast::WeatherForecast};
+
+use crate::{
+    accuracy::{forecast_accuracy as get_forecast_accuracy, LeadTimeAccuracy},
+    air_quality::{fetch_air_quality, get_aqi_description},
+    analysis::{get_clothing_advice, get_watering_advice, WateringAdvice},
+    api_options::ApiOptions,
+    app::{get_location_api, get_provider, AppState},
+    astronomy::{get_daylight_summary, get_moon_summary},
+    attribution::Attribution,
+    barometer::get_pressure_tendency,
+    compact::{encode_compact, CompactBinResponse},
+    config::Config,
+    degraded::{Degraded, DegradedResponse},
+    errors::ServiceError as Error,
+    feed::{render_forecast_feed, AtomResponse},
+    geoip::{lookup_location, visitor_ip, VisitorAddress},
+    landing::{get_conditions_summary, get_landing_links, LandingComponent, LandingComponentProps},
+    lightning::{get_recent_activity, LightningAlertCondition},
+    model::{AirQualityData, LightningActivity, UvIndexData, WeatherSnapshot},
+    offline_forecast::OFFLINE_PROVIDER,
+    onecall::{fetch_onecall, OneCall, OneCallPart},
+    providers::{
+        approximate_timezone,
+        blend::{blend_forecasts, BlendedForecastEntry},
+        get_lat_lon,
+        nws::{NwsAlert, NwsApi},
+        WeatherProvider,
+    },
+    render_stats::record_render,
+    routes::{AnalysisRequest, HttpResult, RefreshOptions, WarpResult},
+    tropical::{
+        get_active_storms, get_nearby_storms, NearbyStorm, TropicalComponent,
+        TropicalComponentProps,
+    },
+    units_wrapper::UnitsWrapper,
+    uv_index::{get_uv_index, get_uv_risk},
+    OneCallDailyWrapper, OneCallHourlyWrapper, OneCallWrapper, WeatherDataAdviceWrapper,
+    WeatherForecastMetaWrapper, WithUnits,
+};
+
+/// Current weather, forecasts, alerts and analysis, tagged `weather`
+pub fn get_weather_path(app: &AppState) -> BoxedFilter<(Response,)> {
+    let landing_path = landing(app.clone()).boxed();
+    let frontpage_path = frontpage(app.clone()).boxed();
+    let weather_path = weather(app.clone()).boxed();
+    let forecast_path = forecast(app.clone()).boxed();
+    let alerts_path = alerts(app.clone()).boxed();
+    let air_quality_path = air_quality(app.clone()).boxed();
+    let uv_index_path = uv_index(app.clone()).boxed();
+    let astronomy_path = astronomy(app.clone()).boxed();
+    let forecast_blend_path = forecast_blend(app.clone()).boxed();
+    let onecall_path = onecall(app.clone()).boxed();
+    let forecast_hourly_path = forecast_hourly(app.clone()).boxed();
+    let forecast_daily_path = forecast_daily(app.clone()).boxed();
+    let forecast_offline_path = forecast_offline(app.clone()).boxed();
+    let forecast_accuracy_path = forecast_accuracy(app.clone()).boxed();
+    let forecast_feed_path = forecast_feed(app.clone()).boxed();
+    let watering_path = watering(app.clone()).boxed();
+    let simple_weather_path = simple_weather(app.clone()).boxed();
+    let compact_bin_path = compact_bin(app.clone()).boxed();
+    let lightning_path = lightning(app.clone()).boxed();
+    let tropical_path = tropical(app.clone()).boxed();
+    let tropical_html_path = tropical_html(app.clone()).boxed();
+
+    landing_path
+        .or(frontpage_path)
+        .or(weather_path)
+        .or(forecast_path)
+        .or(alerts_path)
+        .or(air_quality_path)
+        .or(uv_index_path)
+        .or(astronomy_path)
+        .or(forecast_blend_path)
+        .or(onecall_path)
+        .or(forecast_hourly_path)
+        .or(forecast_daily_path)
+        .or(forecast_offline_path)
+        .or(forecast_accuracy_path)
+        .or(forecast_feed_path)
+        .or(watering_path)
+        .or(simple_weather_path)
+        .or(compact_bin_path)
+        .or(lightning_path)
+        .or(tropical_path)
+        .or(tropical_html_path)
+        .map(Reply::into_response)
+        .boxed()
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Display Current Weather and Forecast", content = "html")]
+struct IndexResponse(HtmlBase<StackString, Error>);
+
+#[get("/weather/index.html")]
+#[openapi(tags("weather"))]
+pub async fn frontpage(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    refresh: Query<RefreshOptions>,
+    #[filter = "visitor_ip"] visitor_ip: Option<VisitorAddress>,
+) -> WarpResult<DegradedResponse<IndexResponse>> {
+    let query = query.into_inner();
+    let refresh = refresh.into_inner().get_refresh();
+    let api = query.get_weather_api(&data.api);
+    let visitor_location = visitor_ip
+        .filter(|_| !query.has_location())
+        .and_then(|visitor| visitor.ip(&data.config.trusted_proxies))
+        .and_then(|ip| lookup_location(&data.config, ip));
+    // without `geoip_persist` visitor locations are served statelessly
+    let (loc, persist) = match visitor_location {
+        Some(loc) => (loc, data.config.geoip_persist),
+        None => (query.get_weather_location(&data.config)?, true),
+    };
+    let service = if persist {
+        data.weather.clone()
+    } else {
+        data.weather.stateless()
+    };
+
+    // only the current weather is required, anything else missing is shown
+    // in a banner
+    let mut degraded = Degraded::default();
+    let weather = service.get_weather(&api, &loc).await?;
+    let forecast = degraded.check(
+        "forecast",
+        "Forecast is currently unavailable",
+        service.get_forecast(&api, &loc).await,
+    );
+    let location_name = format_sstr!("{loc}");
+    let (snapshot_url, precipitation) = if persist && data.pool.is_enabled() {
+        let snapshot_url = degraded
+            .check(
+                "snapshot",
+                "Latest snapshot is currently unavailable",
+                WeatherSnapshot::get_latest(&data.pool, &location_name).await,
+            )
+            .flatten()
+            .map(|s| s.get_url().into());
+        let precipitation = degraded
+            .check(
+                "precipitation",
+                "Recorded precipitation is currently unavailable",
+                data.history.get_precipitation_summary(&location_name).await,
+            )
+            .filter(|summary| summary.hours > 0);
+        (snapshot_url, precipitation)
+    } else {
+        (None, None)
+    };
+    let offset: UtcOffset = weather.timezone.into();
+    let now = OffsetDateTime::now_utc();
+    let moon = get_moon_summary(
+        now,
+        offset,
+        weather.coord.lat.into(),
+        weather.coord.lon.into(),
+    );
+    let daylight = get_daylight_summary(
+        now.to_offset(offset).date(),
+        offset,
+        weather.coord.lat.into(),
+        weather.coord.lon.into(),
+    );
+
+    let body = {
+        let mut app = VirtualDom::new_with_props(
+            WeatherComponent,
+            WeatherComponentProps {
+                weather,
+                forecast,
+                snapshot_url,
+                units: query.units.map(Into::into),
+                refresh,
+                precipitation,
+                moon: Some(moon),
+                daylight: Some(daylight),
+                warnings: degraded.warnings(),
+            },
+        );
+        app.rebuild_in_place();
+        let mut renderer = dioxus_ssr::Renderer::default();
+        let mut buffer = String::new();
+        renderer
+            .render_to(&mut buffer, &app)
+            .map_err(Into::<Error>::into)?;
+        buffer
+    };
+    record_render("/weather/index.html", body.len());
+    Ok(DegradedResponse::new(
+        HtmlBase::new(body.into()).into(),
+        &degraded,
+    ))
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "AdviceOptions")]
+struct AdviceOptions {
+    #[schema(description = "Append Clothing Advice")]
+    advice: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "MetaOptions")]
+struct MetaOptions {
+    #[schema(description = "Include Attribution Block")]
+    meta: Option<bool>,
+}
+
+impl MetaOptions {
+    fn get_attribution(&self, config: &Config) -> Option<Attribution> {
+        if self.meta.unwrap_or(false) {
+            Some(Attribution::new(config, OffsetDateTime::now_utc()))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Get WeatherData Api Json")]
+struct WeatherResponse(JsonBase<WithUnits<WeatherDataAdviceWrapper>, Error>);
+
+#[get("/weather/weather")]
+#[openapi(tags("weather"))]
+pub async fn weather(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    advice: Query<AdviceOptions>,
+    meta: Query<MetaOptions>,
+) -> WarpResult<WeatherResponse> {
+    let advice = advice.into_inner().advice.unwrap_or(false);
+    let meta = meta.into_inner().get_attribution(&data.config);
+    let query = query.into_inner();
+    let units = query.get_units(Units::Standard);
+    let weather_data = weather_json(data, query, advice, meta).await?;
+    Ok(JsonBase::new(WithUnits::new(weather_data, units)).into())
+}
+
+async fn weather_json(
+    data: AppState,
+    query: ApiOptions,
+    advice: bool,
+    meta: Option<Attribution>,
+) -> HttpResult<WeatherDataAdviceWrapper> {
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let weather = data.weather.get_weather(&api, &loc).await?;
+    let tendency = if data.pool.is_enabled() {
+        get_pressure_tendency(&data.pool, &format_sstr!("{loc}"), &weather).await?
+    } else {
+        None
+    };
+    let advice = if advice {
+        let forecast = data.weather.get_forecast(&api, &loc).await?;
+        Some(get_clothing_advice(&weather, &forecast))
+    } else {
+        None
+    };
+    Ok(WeatherDataAdviceWrapper {
+        weather,
+        advice,
+        meta,
+        tendency,
+    })
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "SimpleWeather")]
+struct SimpleWeather {
+    #[schema(description = "Temperature (F)")]
+    temp_f: f64,
+    #[schema(description = "Temperature (C)")]
+    temp_c: f64,
+    #[schema(description = "Current Conditions")]
+    condition: StackString,
+    #[schema(description = "Weather Icon")]
+    icon: StackString,
+    #[schema(description = "Forecast Precipitation over the Next Hour (mm)")]
+    precip_next_hour: f64,
+    #[schema(description = "Sunrise")]
+    sunrise: DateTimeType,
+    #[schema(description = "Sunset")]
+    sunset: DateTimeType,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Get Minimal Flat Weather Json")]
+struct SimpleWeatherResponse(JsonBase<SimpleWeather, Error>);
+
+#[get("/weather/simple")]
+#[openapi(tags("weather"))]
+pub async fn simple_weather(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<SimpleWeatherResponse> {
+    let simple = simple_weather_body(data, query.into_inner()).await?;
+    Ok(JsonBase::new(simple).into())
+}
+
+async fn simple_weather_body(data: AppState, query: ApiOptions) -> HttpResult<SimpleWeather> {
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let weather = data.weather.get_weather(&api, &loc).await?;
+    let forecast = data.weather.get_forecast(&api, &loc).await?;
+
+    let fo: UtcOffset = weather.timezone.into();
+    let (condition, icon) = weather.weather.first().map_or_else(Default::default, |w| {
+        (
+            format_sstr!("{}", w.description),
+            format_sstr!("{}", w.icon),
+        )
+    });
+    // forecast entries cover three hours, take the first one still ahead
+    let precip_next_hour = forecast
+        .list
+        .iter()
+        .find(|entry| entry.dt > weather.dt)
+        .map_or(0.0, |entry| {
+            let rain = entry
+                .rain
+                .as_ref()
+                .and_then(|r| r.three_hour)
+                .unwrap_or_default();
+            let snow = entry
+                .snow
+                .as_ref()
+                .and_then(|s| s.three_hour)
+                .unwrap_or_default();
+            (rain + snow).millimeters() / 3.0
+        });
+    Ok(SimpleWeather {
+        temp_f: weather.main.temp.fahrenheit(),
+        temp_c: weather.main.temp.celcius(),
+        condition,
+        icon,
+        precip_next_hour,
+        sunrise: weather.sys.sunrise.to_offset(fo).into(),
+        sunset: weather.sys.sunset.to_offset(fo).into(),
+    })
+}
+
+#[get("/weather/compact.bin")]
+#[openapi(tags("weather"))]
+pub async fn compact_bin(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<CompactBinResponse> {
+    let body = compact_bin_body(data, query.into_inner()).await?;
+    Ok(CompactBinResponse(body))
+}
+
+async fn compact_bin_body(data: AppState, query: ApiOptions) -> HttpResult<Vec<u8>> {
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let weather = data.weather.get_weather(&api, &loc).await?;
+    let forecast = data.weather.get_forecast(&api, &loc).await?;
+    Ok(encode_compact(&weather, &forecast))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Get WeatherForecast Api Json")]
+struct ForecastResponse(JsonBase<WithUnits<WeatherForecastMetaWrapper>, Error>);
+
+#[get("/weather/forecast")]
+#[openapi(tags("weather"))]
+pub async fn forecast(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    meta: Query<MetaOptions>,
+) -> WarpResult<ForecastResponse> {
+    let meta = meta.into_inner().get_attribution(&data.config);
+    let query = query.into_inner();
+    let units = query.get_units(Units::Standard);
+    let forecast = forecast_body(data, query).await?;
+    Ok(JsonBase::new(WithUnits::new(
+        WeatherForecastMetaWrapper { forecast, meta },
+        units,
+    ))
+    .into())
+}
+
+#[get("/weather/forecast/offline")]
+#[openapi(tags("weather"))]
+pub async fn forecast_offline(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<ForecastResponse> {
+    let query = query.into_inner();
+    let units = query.get_units(Units::Standard);
+    let loc = query.get_weather_location(&data.config)?;
+    let now = OffsetDateTime::now_utc();
+    let forecast = data.weather.get_offline_forecast(&loc).await?;
+    let meta = Some(Attribution {
+        provider: OFFLINE_PROVIDER.into(),
+        ..Attribution::new(&data.config, now)
+    });
+    Ok(JsonBase::new(WithUnits::new(
+        WeatherForecastMetaWrapper { forecast, meta },
+        units,
+    ))
+    .into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ForecastAccuracyRequest")]
+struct ForecastAccuracyRequest {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Server")]
+    server: Option<StackString>,
+    #[schema(description = "Start Date (default a week ago)")]
+    start_time: Option<DateType>,
+    #[schema(description = "End Date (default today)")]
+    end_time: Option<DateType>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "ForecastAccuracy")]
+struct ForecastAccuracyReport {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Start Date")]
+    start_date: DateType,
+    #[schema(description = "End Date")]
+    end_date: DateType,
+    #[schema(description = "Error Statistics by Lead Time")]
+    lead_times: Vec<LeadTimeAccuracy>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Forecast Accuracy")]
+struct ForecastAccuracyResponse(JsonBase<ForecastAccuracyReport, Error>);
+
+#[get("/weather/forecast/accuracy")]
+#[openapi(tags("weather"))]
+pub async fn forecast_accuracy(
+    #[data] data: AppState,
+    query: Query<ForecastAccuracyRequest>,
+) -> WarpResult<ForecastAccuracyResponse> {
+    let report = forecast_accuracy_body(data, query.into_inner()).await?;
+    Ok(JsonBase::new(report).into())
+}
+
+async fn forecast_accuracy_body(
+    data: AppState,
+    query: ForecastAccuracyRequest,
+) -> HttpResult<ForecastAccuracyReport> {
+    let end_date = query
+        .end_time
+        .map_or_else(|| OffsetDateTime::now_utc().date(), Into::into);
+    let start_date = query
+        .start_time
+        .map_or_else(|| end_date - Duration::days(7), Into::into);
+    if start_date > end_date {
+        return Err(Error::BadRequest("start_time after end_time".into()));
+    }
+    let lead_times = if data.pool.is_enabled() {
+        get_forecast_accuracy(
+            &data.pool,
+            &query.name,
+            query.server.as_deref(),
+            start_date,
+            end_date,
+        )
+        .await?
+    } else {
+        Vec::new()
+    };
+    Ok(ForecastAccuracyReport {
+        name: query.name,
+        start_date: start_date.into(),
+        end_date: end_date.into(),
+        lead_times,
+    })
+}
+
+async fn forecast_body(data: AppState, query: ApiOptions) -> HttpResult<WeatherForecast> {
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let weather_forecast = data.weather.get_forecast(&api, &loc).await?;
+    Ok(weather_forecast)
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "FeedRequest")]
+struct FeedRequest {
+    #[schema(description = "Location (zipcode, city name or zipcode,country code)")]
+    loc: StackString,
+    #[schema(description = "Units (default imperial)")]
+    units: Option<UnitsWrapper>,
+}
+
+#[get("/weather/feed.xml")]
+#[openapi(tags("weather"))]
+pub async fn forecast_feed(
+    #[data] data: AppState,
+    query: Query<FeedRequest>,
+) -> WarpResult<AtomResponse> {
+    let query = query.into_inner();
+    let loc = get_parameters(&query.loc);
+    let forecast = data.weather.get_forecast(&data.api, &loc).await?;
+    let units = query.units.map_or(Units::Imperial, Into::into);
+    let body = render_forecast_feed(&query.loc, &forecast, units, OffsetDateTime::now_utc());
+    Ok(AtomResponse(body))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Watering Advice")]
+struct WateringResponse(JsonBase<WateringAdvice, Error>);
+
+#[get("/weather/analysis/watering")]
+#[openapi(tags("weather"))]
+pub async fn watering(
+    #[data] data: AppState,
+    query: Query<AnalysisRequest>,
+) -> WarpResult<WateringResponse> {
+    let advice = watering_body(data, query.into_inner()).await?;
+    Ok(JsonBase::new(advice).into())
+}
+
+async fn watering_body(data: AppState, query: AnalysisRequest) -> HttpResult<WateringAdvice> {
+    let start_date = OffsetDateTime::now_utc().date() - Duration::days(7);
+    let history = data.history.get_recent(&query.name, start_date).await?;
+    let loc = get_parameters(&query.name);
+    let forecast = data.weather.get_forecast(&data.api, &loc).await?;
+    let threshold = data
+        .config
+        .watering_thresholds
+        .get(&query.name)
+        .copied()
+        .unwrap_or(data.config.watering_threshold);
+    Ok(get_watering_advice(
+        &query.name,
+        &history,
+        &forecast,
+        f64::from(threshold),
+    ))
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "LightningActivity")]
+struct LightningActivityObject {
+    #[schema(description = "Recorded At")]
+    recorded_at: DateTimeType,
+    #[schema(description = "Radius (km)")]
+    radius_km: f64,
+    #[schema(description = "Strike Count")]
+    strike_count: i32,
+    #[schema(description = "Nearest Strike Distance (km)")]
+    nearest_distance_km: Option<f64>,
+}
+
+impl From<LightningActivity> for LightningActivityObject {
+    fn from(value: LightningActivity) -> Self {
+        Self {
+            recorded_at: value.recorded_at.to_offsetdatetime().into(),
+            radius_km: value.radius_km,
+            strike_count: value.strike_count,
+            nearest_distance_km: value.nearest_distance_km,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "LightningReport")]
+struct LightningReport {
+    #[schema(description = "Location Name")]
+    name: StackString,
+    #[schema(description = "Alert Distance (km)")]
+    alert_distance_km: f64,
+    #[schema(description = "Strikes Within Alert Distance")]
+    alert: bool,
+    #[schema(description = "Total Strikes in the Last Hour")]
+    strike_count: i64,
+    #[schema(description = "Nearest Strike in the Last Hour (km)")]
+    nearest_distance_km: Option<f64>,
+    #[schema(description = "Recent Activity")]
+    activity: Vec<LightningActivityObject>,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Recent Lightning Activity")]
+struct LightningResponse(JsonBase<LightningReport, Error>);
+
+#[get("/weather/lightning")]
+#[openapi(tags("weather"))]
+pub async fn lightning(
+    #[data] data: AppState,
+    query: Query<AnalysisRequest>,
+) -> WarpResult<LightningResponse> {
+    let query = query.into_inner();
+    let now = OffsetDateTime::now_utc();
+    let activity = get_recent_activity(
+        &data.pool,
+        &query.name,
+        Some(&data.config.server),
+        now - Duration::hours(1),
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    let condition = LightningAlertCondition::default();
+    let report = LightningReport {
+        name: query.name,
+        alert_distance_km: condition.distance_km,
+        alert: condition.is_triggered(&activity, now),
+        strike_count: activity.iter().map(|a| i64::from(a.strike_count)).sum(),
+        nearest_distance_km: activity
+            .iter()
+            .filter_map(|a| a.nearest_distance_km)
+            .reduce(f64::min),
+        activity: activity.into_iter().map(Into::into).collect(),
+    };
+    Ok(JsonBase::new(report).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "TropicalStorm")]
+struct TropicalStormObject {
+    #[schema(description = "Storm ID")]
+    id: StackString,
+    #[schema(description = "Storm Name")]
+    name: StackString,
+    #[schema(description = "Classification (TD, TS, HU, ...)")]
+    classification: StackString,
+    #[schema(description = "Maximum Sustained Wind (kt)")]
+    intensity: StackString,
+    #[schema(description = "Minimum Central Pressure (mb)")]
+    pressure: StackString,
+    #[schema(description = "Latitude")]
+    latitude: f64,
+    #[schema(description = "Longitude")]
+    longitude: f64,
+    #[schema(description = "Distance From Location (km)")]
+    distance_km: f64,
+    #[schema(description = "Direction of Motion (degrees)")]
+    movement_dir: Option<f64>,
+    #[schema(description = "Speed of Motion (mph)")]
+    movement_speed: Option<f64>,
+    #[schema(description = "Last Update")]
+    last_update: DateTimeType,
+    #[schema(description = "Public Advisory URL")]
+    advisory_url: Option<StackString>,
+    #[schema(description = "Forecast Track KMZ URL")]
+    track_kmz: Option<StackString>,
+    #[schema(description = "Forecast Cone KMZ URL")]
+    cone_kmz: Option<StackString>,
+}
+
+impl From<NearbyStorm> for TropicalStormObject {
+    fn from(value: NearbyStorm) -> Self {
+        let storm = value.storm;
+        Self {
+            id: storm.id,
+            name: storm.name,
+            classification: storm.classification,
+            intensity: storm.intensity,
+            pressure: storm.pressure,
+            latitude: storm.latitude_numeric,
+            longitude: storm.longitude_numeric,
+            distance_km: value.distance_km,
+            movement_dir: storm.movement_dir,
+            movement_speed: storm.movement_speed,
+            last_update: storm.last_update.into(),
+            advisory_url: storm.public_advisory.and_then(|p| p.url),
+            track_kmz: storm.forecast_track.and_then(|p| p.kmz_file),
+            cone_kmz: storm.track_cone.and_then(|p| p.kmz_file),
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Active Tropical Storms Near Location")]
+struct TropicalResponse(JsonBase<Vec<TropicalStormObject>, Error>);
+
+#[get("/weather/tropical")]
+#[openapi(tags("weather"))]
+pub async fn tropical(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<TropicalResponse> {
+    let (_, storms) = tropical_body(&data, &query.into_inner()).await?;
+    let storms = storms.into_iter().map(Into::into).collect();
+    Ok(JsonBase::new(storms).into())
+}
+
+async fn tropical_body(
+    data: &AppState,
+    query: &ApiOptions,
+) -> HttpResult<(WeatherData, Vec<NearbyStorm>)> {
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let weather = data.weather.get_weather(&api, &loc).await?;
+    let storms = get_active_storms(&data.client, &data.config.tropical_url).await?;
+    let storms = get_nearby_storms(
+        &storms,
+        weather.coord.lat.into(),
+        weather.coord.lon.into(),
+        data.config.tropical_distance_km,
+    );
+    Ok((weather, storms))
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Tropical Storm Map", content = "html")]
+struct TropicalHtmlResponse(HtmlBase<String, Error>);
+
+#[get("/weather/tropical.html")]
+#[openapi(tags("weather"))]
+pub async fn tropical_html(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<TropicalHtmlResponse> {
+    let (weather, storms) = tropical_body(&data, &query.into_inner()).await?;
+    let title = format!(
+        "{} active storms within {:0.0} km of {}",
+        storms.len(),
+        data.config.tropical_distance_km,
+        weather.name
+    );
+    let body = {
+        let mut app = VirtualDom::new_with_props(
+            TropicalComponent,
+            TropicalComponentProps {
+                title,
+                latitude: weather.coord.lat.into(),
+                longitude: weather.coord.lon.into(),
+                storms,
+                max_distance_km: data.config.tropical_distance_km,
+            },
+        );
+        app.rebuild_in_place();
+        let mut renderer = dioxus_ssr::Renderer::default();
+        let mut buffer = String::new();
+        renderer
+            .render_to(&mut buffer, &app)
+            .map_err(Into::<Error>::into)?;
+        buffer
+    };
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Landing Page", content = "html")]
+struct LandingResponse(HtmlBase<String, Error>);
+
+#[get("/weather")]
+#[openapi(tags("weather"))]
+pub async fn landing(#[data] data: AppState) -> WarpResult<LandingResponse> {
+    if !data.config.landing_page {
+        return Err(Error::NotFound("landing page is disabled".into()).into());
+    }
+    // the landing page is still useful without upstream access, failures
+    // only hide the current conditions
+    let conditions = match ApiOptions::default().get_weather_location(&data.config) {
+        Ok(loc) => data
+            .weather
+            .get_weather(&data.api, &loc)
+            .await
+            .ok()
+            .map(|weather| get_conditions_summary(&weather)),
+        Err(_) => None,
+    };
+    let body = {
+        let mut app = VirtualDom::new_with_props(
+            LandingComponent,
+            LandingComponentProps {
+                title: "Weather App".into(),
+                conditions,
+                links: get_landing_links(data.config.wasm_app_url.as_deref()),
+            },
+        );
+        app.rebuild_in_place();
+        let mut renderer = dioxus_ssr::Renderer::default();
+        let mut buffer = String::new();
+        renderer
+            .render_to(&mut buffer, &app)
+            .map_err(Into::<Error>::into)?;
+        buffer
+    };
+    record_render("/weather", body.len());
+    Ok(HtmlBase::new(body).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "AirQuality")]
+struct AirQualityWrapper {
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Measurement DateTime")]
+    recorded_at: DateTimeType,
+    #[schema(description = "Air Quality Index (1 = Good, 5 = Very Poor)")]
+    aqi: i32,
+    #[schema(description = "Air Quality Description")]
+    description: StackString,
+    #[schema(description = "PM2.5 (μg/m³)")]
+    pm2_5: f64,
+    #[schema(description = "PM10 (μg/m³)")]
+    pm10: f64,
+    #[schema(description = "Ozone (μg/m³)")]
+    o3: f64,
+    #[schema(description = "Nitrogen Dioxide (μg/m³)")]
+    no2: f64,
+}
+
+impl From<AirQualityData> for AirQualityWrapper {
+    fn from(value: AirQualityData) -> Self {
+        Self {
+            location_name: value.location_name,
+            recorded_at: value.recorded_at.to_offsetdatetime().into(),
+            aqi: value.aqi,
+            description: get_aqi_description(value.aqi).into(),
+            pm2_5: value.pm2_5,
+            pm10: value.pm10,
+            o3: value.o3,
+            no2: value.no2,
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Current Air Quality")]
+struct AirQualityResponse(JsonBase<AirQualityWrapper, Error>);
+
+#[get("/weather/air-quality")]
+#[openapi(tags("weather"))]
+pub async fn air_quality(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<AirQualityResponse> {
+    let query = query.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let location_name = format_sstr!("{loc}");
+    // openweathermap updates air pollution hourly, reuse recent readings
+    if let Some(latest) =
+        AirQualityData::get_latest(&data.pool, &location_name, &data.config.server)
+            .await
+            .map_err(Into::<Error>::into)?
+    {
+        if *latest.recorded_at > OffsetDateTime::now_utc() - Duration::hours(1) {
+            return Ok(JsonBase::new(latest.into()).into());
+        }
+    }
+    let loc = data.locations.resolve(&api, &loc).await?;
+    let (latitude, longitude) = get_lat_lon(&loc).map_err(Into::<Error>::into)?;
+    let api_key = query
+        .appid
+        .as_ref()
+        .map_or(data.config.api_key.as_str(), |appid| appid.as_str());
+    let air_quality = fetch_air_quality(
+        &data.client,
+        &data.config,
+        api_key,
+        &location_name,
+        latitude,
+        longitude,
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    air_quality
+        .insert(&data.pool)
+        .await
+        .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(air_quality.into()).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "UvIndex")]
+struct UvIndexWrapper {
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Measurement DateTime")]
+    recorded_at: DateTimeType,
+    #[schema(description = "UV Index")]
+    uvi: f64,
+    #[schema(description = "Exposure Risk (Low, Moderate, High, Very High, Extreme)")]
+    risk: StackString,
+}
+
+impl From<UvIndexData> for UvIndexWrapper {
+    fn from(value: UvIndexData) -> Self {
+        Self {
+            location_name: value.location_name,
+            recorded_at: value.recorded_at.to_offsetdatetime().into(),
+            uvi: value.uvi,
+            risk: get_uv_risk(value.uvi).into(),
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Current UV Index")]
+struct UvIndexResponse(JsonBase<UvIndexWrapper, Error>);
+
+#[get("/weather/uv")]
+#[openapi(tags("weather"))]
+pub async fn uv_index(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<UvIndexResponse> {
+    let query = query.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let location_name = format_sstr!("{loc}");
+    let loc = data.locations.resolve(&api, &loc).await?;
+    let (latitude, longitude) = get_lat_lon(&loc).map_err(Into::<Error>::into)?;
+    let api_key = query
+        .appid
+        .as_ref()
+        .map_or(data.config.api_key.as_str(), |appid| appid.as_str());
+    let uv_index = get_uv_index(
+        &data.pool,
+        &data.client,
+        &data.config,
+        api_key,
+        &location_name,
+        latitude,
+        longitude,
+    )
+    .await
+    .map_err(Into::<Error>::into)?;
+    Ok(JsonBase::new(uv_index.into()).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "AstronomyOptions")]
+struct AstronomyOptions {
+    #[schema(description = "Local Date (defaults to today)")]
+    date: Option<DateType>,
+    #[schema(
+        description = "IANA Timezone (defaults to an offset approximated from the longitude)"
+    )]
+    tz: Option<StackString>,
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "Astronomy")]
+struct AstronomyWrapper {
+    #[schema(description = "Location Name")]
+    location_name: StackString,
+    #[schema(description = "Local Date")]
+    date: DateType,
+    #[schema(description = "UTC Offset (seconds)")]
+    utc_offset: i32,
+    #[schema(description = "Moon Phase (0 and 1 are new moon, 0.5 is full moon)")]
+    moon_phase: f64,
+    #[schema(description = "Moon Phase Name")]
+    moon_phase_name: StackString,
+    #[schema(description = "Moon Illumination (%)")]
+    moon_illumination: f64,
+    #[schema(description = "Local Moonrise (HH:MM)")]
+    moonrise: Option<StackString>,
+    #[schema(description = "Local Moonset (HH:MM)")]
+    moonset: Option<StackString>,
+    #[schema(description = "Local Sunrise (HH:MM)")]
+    sunrise: Option<StackString>,
+    #[schema(description = "Local Sunset (HH:MM)")]
+    sunset: Option<StackString>,
+    #[schema(description = "Local Civil Dawn (HH:MM)")]
+    civil_dawn: Option<StackString>,
+    #[schema(description = "Local Civil Dusk (HH:MM)")]
+    civil_dusk: Option<StackString>,
+    #[schema(description = "Daylight Duration (seconds)")]
+    daylight: i64,
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Moon Phase, Moonrise / Moonset, Daylight and Civil Twilight")]
+struct AstronomyResponse(JsonBase<AstronomyWrapper, Error>);
+
+#[get("/weather/astronomy")]
+#[openapi(tags("weather"))]
+pub async fn astronomy(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+    options: Query<AstronomyOptions>,
+) -> WarpResult<AstronomyResponse> {
+    let query = query.into_inner();
+    let options = options.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let location_name = format_sstr!("{loc}");
+    let loc = data.locations.resolve(&api, &loc).await?;
+    let (latitude, longitude) = get_lat_lon(&loc).map_err(Into::<Error>::into)?;
+
+    let now = OffsetDateTime::now_utc();
+    let offset = match &options.tz {
+        Some(tz) => timezones::get_by_name(tz)
+            .ok_or_else(|| Error::BadRequest(format_sstr!("unknown timezone {tz}")))?
+            .get_offset_utc(&now)
+            .to_utc(),
+        None => UtcOffset::from_whole_seconds(approximate_timezone(longitude))
+            .map_err(|e| Error::BadRequest(format_sstr!("{e}")))?,
+    };
+    let date = options
+        .date
+        .map_or_else(|| now.to_offset(offset).date(), Into::into);
+    // the moon phase at local noon of the requested day
+    let noon = date.midday().assume_offset(offset);
+    let moon = get_moon_summary(noon, offset, latitude, longitude);
+    let daylight = get_daylight_summary(date, offset, latitude, longitude);
+    Ok(JsonBase::new(AstronomyWrapper {
+        location_name,
+        date: date.into(),
+        utc_offset: offset.whole_seconds(),
+        moon_phase: moon.phase,
+        moon_phase_name: moon.phase_name.into(),
+        moon_illumination: moon.illumination,
+        moonrise: moon.moonrise.map(Into::into),
+        moonset: moon.moonset.map(Into::into),
+        sunrise: daylight.sunrise.map(Into::into),
+        sunset: daylight.sunset.map(Into::into),
+        civil_dawn: daylight.civil_dawn.map(Into::into),
+        civil_dusk: daylight.civil_dusk.map(Into::into),
+        daylight: daylight.daylight,
+    })
+    .into())
+}
+
+async fn get_onecall(
+    data: &AppState,
+    query: &ApiOptions,
+    exclude: &[OneCallPart],
+) -> Result<OneCall, Error> {
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let loc = data.locations.resolve(&api, &loc).await?;
+    let (latitude, longitude) = get_lat_lon(&loc)?;
+    let api_key = query
+        .appid
+        .as_ref()
+        .map_or(data.config.api_key.as_str(), |appid| appid.as_str());
+    fetch_onecall(
+        &data.client,
+        &data.config,
+        api_key,
+        latitude,
+        longitude,
+        exclude,
+    )
+    .await
+    .map_err(Into::into)
+}
+
+#[derive(RwebResponse)]
+#[response(description = "One Call Current Conditions and Forecasts")]
+struct OneCallResponse(JsonBase<WithUnits<OneCallWrapper>, Error>);
+
+#[get("/weather/onecall")]
+#[openapi(tags("weather"))]
+pub async fn onecall(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<OneCallResponse> {
+    let query = query.into_inner();
+    let onecall = get_onecall(&data, &query, &[OneCallPart::Alerts]).await?;
+    let units = query.get_units(Units::Standard);
+    Ok(JsonBase::new(WithUnits::new(onecall.into(), units)).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Hourly Forecast (48 hours)")]
+struct HourlyForecastResponse(JsonBase<WithUnits<Vec<OneCallHourlyWrapper>>, Error>);
+
+#[get("/weather/forecast/hourly")]
+#[openapi(tags("weather"))]
+pub async fn forecast_hourly(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<HourlyForecastResponse> {
+    let query = query.into_inner();
+    let exclude = [
+        OneCallPart::Current,
+        OneCallPart::Minutely,
+        OneCallPart::Daily,
+        OneCallPart::Alerts,
+    ];
+    let onecall = get_onecall(&data, &query, &exclude).await?;
+    let hourly = onecall
+        .hourly
+        .unwrap_or_default()
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let units = query.get_units(Units::Standard);
+    Ok(JsonBase::new(WithUnits::new(hourly, units)).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Daily Forecast (8 days)")]
+struct DailyForecastResponse(JsonBase<WithUnits<Vec<OneCallDailyWrapper>>, Error>);
+
+#[get("/weather/forecast/daily")]
+#[openapi(tags("weather"))]
+pub async fn forecast_daily(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<DailyForecastResponse> {
+    let query = query.into_inner();
+    let exclude = [
+        OneCallPart::Current,
+        OneCallPart::Minutely,
+        OneCallPart::Hourly,
+        OneCallPart::Alerts,
+    ];
+    let onecall = get_onecall(&data, &query, &exclude).await?;
+    let daily = onecall
+        .daily
+        .unwrap_or_default()
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let units = query.get_units(Units::Standard);
+    Ok(JsonBase::new(WithUnits::new(daily, units)).into())
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Forecast Blended Across Providers")]
+struct BlendedForecastResponse(JsonBase<Vec<BlendedForecastEntry>, Error>);
+
+#[get("/weather/forecast/blend")]
+#[openapi(tags("weather"))]
+pub async fn forecast_blend(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<BlendedForecastResponse> {
+    let query = query.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let location_name = format_sstr!("{loc}");
+    let api = get_location_api(&data.pool, &data.config, &api, &location_name)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let loc = data.locations.resolve(&api, &loc).await?;
+    let chain = get_provider(&data.config, &api, &location_name);
+    let results = join_all(chain.get_providers().iter().map(|provider| {
+        let loc = &loc;
+        async move {
+            (
+                provider.get_name(),
+                provider.get_weather_forecast(loc).await,
+            )
+        }
+    }))
+    .await;
+    let mut forecasts = Vec::new();
+    let mut last_error = None;
+    for (name, result) in results {
+        match result {
+            Ok(forecast) => forecasts.push((name, forecast)),
+            Err(e) => {
+                last_error.replace(e);
+            }
+        }
+    }
+    if forecasts.is_empty() {
+        if let Some(e) = last_error {
+            return Err(Into::<Error>::into(e).into());
+        }
+    }
+    Ok(JsonBase::new(blend_forecasts(&forecasts)).into())
+}
+
+#[derive(Serialize, Deserialize, Schema)]
+#[schema(component = "WeatherAlert")]
+struct WeatherAlertObject {
+    #[schema(description = "Alert ID")]
+    id: StackString,
+    #[schema(description = "Event (e.g. Tornado Warning)")]
+    event: StackString,
+    #[schema(description = "Headline")]
+    headline: Option<StackString>,
+    #[schema(description = "Description")]
+    description: Option<StackString>,
+    #[schema(description = "Instruction")]
+    instruction: Option<StackString>,
+    #[schema(description = "Severity")]
+    severity: StackString,
+    #[schema(description = "Certainty")]
+    certainty: StackString,
+    #[schema(description = "Urgency")]
+    urgency: StackString,
+    #[schema(description = "Effective")]
+    effective: DateTimeType,
+    #[schema(description = "Expires")]
+    expires: Option<DateTimeType>,
+    #[schema(description = "Affected Area")]
+    area_desc: StackString,
+    #[schema(description = "Issuing Office")]
+    sender_name: StackString,
+}
+
+impl From<NwsAlert> for WeatherAlertObject {
+    fn from(value: NwsAlert) -> Self {
+        Self {
+            id: value.id,
+            event: value.event,
+            headline: value.headline,
+            description: value.description,
+            instruction: value.instruction,
+            severity: value.severity,
+            certainty: value.certainty,
+            urgency: value.urgency,
+            effective: value.effective.into(),
+            expires: value.expires.map(Into::into),
+            area_desc: value.area_desc,
+            sender_name: value.sender_name,
+        }
+    }
+}
+
+#[derive(RwebResponse)]
+#[response(description = "Active NWS Watches and Warnings")]
+struct AlertsResponse(JsonBase<Vec<WeatherAlertObject>, Error>);
+
+#[get("/weather/alerts")]
+#[openapi(tags("weather"))]
+pub async fn alerts(
+    #[data] data: AppState,
+    query: Query<ApiOptions>,
+) -> WarpResult<AlertsResponse> {
+    let query = query.into_inner();
+    let api = query.get_weather_api(&data.api);
+    let loc = query.get_weather_location(&data.config)?;
+    let loc = data.locations.resolve(&api, &loc).await?;
+    let (latitude, longitude) = get_lat_lon(&loc).map_err(Into::<Error>::into)?;
+    let alerts = NwsApi::new(&data.config)
+        .get_active_alerts(latitude, longitude)
+        .await
+        .map_err(Into::<Error>::into)?;
+    let alerts = alerts.into_iter().map(Into::into).collect();
+    Ok(JsonBase::new(alerts).into())
+}
@@ -1,26 +1,35 @@
-use crate::{exponential_retry, get_md5sum, polars_analysis::merge_parquet_files};
+use crate::{
+    exponential_retry, get_md5sum,
+    polars_analysis::{get_parquet_bytes_row_count, get_parquet_row_count, merge_parquet_files},
+};
 use anyhow::{format_err, Error};
 use aws_config::SdkConfig;
 use aws_sdk_s3::{
-    operation::list_objects::ListObjectsOutput, primitives::ByteStream, types::Object as S3Object,
+    operation::list_objects::ListObjectsOutput,
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart, Object as S3Object},
     Client as S3Client,
 };
-use futures::TryStreamExt;
+use futures::{Stream, TryStreamExt};
 use log::debug;
 use rand::{
     distributions::{Alphanumeric, DistString},
     thread_rng,
 };
+use serde::Serialize;
 use stack_string::{format_sstr, StackString};
 use std::{
     borrow::Borrow,
     cmp::Ordering,
+    collections::{HashMap, HashSet},
     convert::{TryFrom, TryInto},
-    fs,
+    fmt, fs,
     hash::{Hash, Hasher},
+    mem,
     path::Path,
     time::SystemTime,
 };
+use time::OffsetDateTime;
 use tokio::{
     fs::File,
     task::{spawn, spawn_blocking, JoinHandle},
@@ -28,6 +37,8 @@ use tokio::{
 
 use crate::{model::KeyItemCache, pgpool::PgPool};
 
+const MULTIPART_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
 #[derive(Clone)]
 pub struct S3Sync {
     s3_client: S3Client,
@@ -103,6 +114,100 @@ impl Borrow<str> for &KeyItem {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveStatus {
+    pub key: StackString,
+    pub local_md5: Option<StackString>,
+    pub s3_etag: Option<StackString>,
+    pub row_count: Option<usize>,
+    pub local_size: Option<u64>,
+    pub s3_size: Option<u64>,
+    pub local_modified: Option<OffsetDateTime>,
+    pub s3_modified: Option<OffsetDateTime>,
+    pub mismatch: bool,
+}
+
+impl ArchiveStatus {
+    fn from_remote(item: KeyItem) -> Self {
+        Self {
+            key: item.key,
+            local_md5: None,
+            s3_etag: Some(item.etag),
+            row_count: None,
+            local_size: None,
+            s3_size: Some(item.size),
+            local_modified: None,
+            s3_modified: OffsetDateTime::from_unix_timestamp(item.timestamp).ok(),
+            mismatch: true,
+        }
+    }
+}
+
+/// Rows of a local parquet file and of its s3 copy
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RowCountCheck {
+    pub key: StackString,
+    pub local_rows: usize,
+    pub s3_rows: Option<usize>,
+    pub matches: bool,
+}
+
+impl fmt::Display for RowCountCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} local {}", self.key, self.local_rows)?;
+        match self.s3_rows {
+            Some(rows) => write!(f, " s3 {rows}")?,
+            None => write!(f, " missing on s3")?,
+        }
+        if !self.matches {
+            write!(f, " MISMATCH")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SyncSummary {
+    pub title: StackString,
+    pub s3_bucket: StackString,
+    pub n_keys: usize,
+    pub local_updates: usize,
+    pub uploaded: usize,
+    pub downloaded: usize,
+}
+
+impl fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} s3_bucket nkeys {} updated files {} uploaded {} downloaded {}",
+            self.title,
+            self.s3_bucket,
+            self.n_keys,
+            self.local_updates,
+            self.uploaded,
+            self.downloaded,
+        )
+    }
+}
+
+/// Monthly parquet archives written by `write_parquet_month`
+fn is_archive_key(key: &str) -> bool {
+    key.starts_with("weather_data_") && key.ends_with(".parquet")
+}
+
+impl KeyItem {
+    /// Etag of a multipart upload is `<md5 of part md5s>-<number of parts>`
+    /// rather than the md5 of the object, for those only the size is compared
+    fn matches_local(&self, local_md5: &str, local_size: u64) -> bool {
+        if self.etag.contains('-') {
+            self.size == local_size
+        } else {
+            self.etag == local_md5
+        }
+    }
+}
+
 impl Default for S3Sync {
     fn default() -> Self {
         let config = SdkConfig::builder().build();
@@ -176,6 +281,26 @@ impl S3Sync {
         Ok(nkeys)
     }
 
+    async fn list_keys(&self, bucket: &str) -> Result<Vec<KeyItem>, Error> {
+        let mut marker: Option<String> = None;
+        let mut keys = Vec::new();
+        loop {
+            let mut output = self.list_objects(bucket, marker.as_ref()).await?;
+            if let Some(contents) = output.contents.take() {
+                if let Some(last) = contents.last() {
+                    if let Some(key) = last.key() {
+                        marker.replace(key.into());
+                    }
+                }
+                keys.extend(contents.into_iter().filter_map(KeyItem::from_s3_object));
+            }
+            if output.is_truncated == Some(false) || output.is_truncated.is_none() {
+                break;
+            }
+        }
+        Ok(keys)
+    }
+
     async fn get_and_process_keys(&self, bucket: &str, pool: &PgPool) -> Result<usize, Error> {
         let result: Result<usize, _> =
             exponential_retry(|| async move { self.get_and_process_keys_impl(bucket, pool).await })
@@ -246,7 +371,7 @@ impl S3Sync {
         local_dir: &Path,
         s3_bucket: &str,
         pool: &PgPool,
-    ) -> Result<StackString, Error> {
+    ) -> Result<SyncSummary, Error> {
         let local_updates = self.process_files(local_dir, pool).await?;
         let n_keys = self.get_and_process_keys(s3_bucket, pool).await?;
 
@@ -282,11 +407,127 @@ impl S3Sync {
             key_item.insert(pool).await?;
         }
 
-        let msg = format_sstr!(
-            "{title} {s3_bucket} s3_bucket nkeys {n_keys} updated files {local_updates} uploaded \
-             {number_uploaded} downloaded {number_downloaded}",
-        );
-        Ok(msg)
+        Ok(SyncSummary {
+            title: title.into(),
+            s3_bucket: s3_bucket.into(),
+            n_keys,
+            local_updates,
+            uploaded: number_uploaded,
+            downloaded: number_downloaded,
+        })
+    }
+
+    /// Compare each local parquet file against its s3 counterpart, flagging
+    /// any file where the md5sum and etag differ or where one side is missing.
+    /// # Errors
+    /// Return error if listing s3 or reading local files fails
+    pub async fn verify_archive(
+        &self,
+        local_dir: &Path,
+        s3_bucket: &str,
+    ) -> Result<Vec<ArchiveStatus>, Error> {
+        let mut remote: HashMap<StackString, KeyItem> =
+            exponential_retry(|| async move { self.list_keys(s3_bucket).await })
+                .await?
+                .into_iter()
+                .filter(|item| is_archive_key(&item.key))
+                .map(|item| (item.key.clone(), item))
+                .collect();
+
+        let mut local_files = Vec::new();
+        for dir_line in local_dir.read_dir()? {
+            let f = dir_line?.path();
+            let file_name = f.file_name().and_then(|f| f.to_str());
+            if file_name.is_some_and(is_archive_key) {
+                local_files.push(f);
+            }
+        }
+        local_files.sort();
+
+        let mut output = Vec::with_capacity(local_files.len());
+        for f in local_files {
+            let key: StackString = match f.file_name() {
+                Some(file_name) => file_name.to_string_lossy().as_ref().into(),
+                None => continue,
+            };
+            let metadata = fs::metadata(&f)?;
+            let modified: i64 = metadata
+                .modified()?
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs()
+                .try_into()?;
+            let local_md5 = get_md5sum(&f).await?;
+            let row_count = {
+                let f = f.clone();
+                spawn_blocking(move || get_parquet_row_count(&f)).await??
+            };
+            let remote_item = remote.remove(&key);
+            let mismatch = !remote_item
+                .as_ref()
+                .is_some_and(|item| item.matches_local(&local_md5, metadata.len()));
+            output.push(ArchiveStatus {
+                key,
+                local_md5: Some(local_md5),
+                s3_etag: remote_item.as_ref().map(|item| item.etag.clone()),
+                row_count: Some(row_count),
+                local_size: Some(metadata.len()),
+                s3_size: remote_item.as_ref().map(|item| item.size),
+                local_modified: OffsetDateTime::from_unix_timestamp(modified).ok(),
+                s3_modified: remote_item
+                    .as_ref()
+                    .and_then(|item| OffsetDateTime::from_unix_timestamp(item.timestamp).ok()),
+                mismatch,
+            });
+        }
+        let mut remote_only: Vec<_> = remote
+            .into_values()
+            .map(ArchiveStatus::from_remote)
+            .collect();
+        remote_only.sort_by(|a, b| a.key.cmp(&b.key));
+        output.extend(remote_only);
+        Ok(output)
+    }
+
+    /// Compare the row counts of the local parquet files `keys` with their
+    /// s3 copies, unlike `verify_archive` this holds for files rewritten
+    /// locally, e.g. by a rebuild from the db
+    /// # Errors
+    /// Return error if listing or downloading from s3 or reading a local file
+    /// fails
+    pub async fn verify_row_counts(
+        &self,
+        local_dir: &Path,
+        s3_bucket: &str,
+        keys: &[StackString],
+    ) -> Result<Vec<RowCountCheck>, Error> {
+        let remote: HashSet<StackString> =
+            exponential_retry(|| async move { self.list_keys(s3_bucket).await })
+                .await?
+                .into_iter()
+                .map(|item| item.key)
+                .collect();
+        let mut output = Vec::with_capacity(keys.len());
+        for key in keys {
+            let local_rows = {
+                let f = local_dir.join(key.as_str());
+                spawn_blocking(move || get_parquet_row_count(&f)).await??
+            };
+            let s3_rows = if remote.contains(key) {
+                let data =
+                    exponential_retry(|| async move { self.download_bytes(s3_bucket, key).await })
+                        .await?;
+                Some(spawn_blocking(move || get_parquet_bytes_row_count(data)).await??)
+            } else {
+                None
+            };
+            output.push(RowCountCheck {
+                key: key.clone(),
+                local_rows,
+                s3_rows,
+                matches: s3_rows == Some(local_rows),
+            });
+        }
+        Ok(output)
     }
 
     async fn download_to_file(
@@ -383,6 +624,181 @@ impl S3Sync {
         exponential_retry(|| async move { self.upload_file_impl(s3_bucket, s3_key, local_file).await })
             .await
     }
+
+    async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        data: Vec<u8>,
+    ) -> Result<CompletedPart, Error> {
+        let e_tag = self
+            .s3_client
+            .upload_part()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(data))
+            .send()
+            .await?
+            .e_tag
+            .ok_or_else(|| format_err!("Missing etag"))?;
+        Ok(CompletedPart::builder()
+            .e_tag(e_tag)
+            .part_number(part_number)
+            .build())
+    }
+
+    async fn upload_multipart<S>(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        mut buffer: Vec<u8>,
+        stream: &mut S,
+    ) -> Result<StackString, Error>
+    where
+        S: Stream<Item = Result<Vec<u8>, Error>> + Unpin,
+    {
+        let mut parts = Vec::new();
+        loop {
+            let chunk = stream.try_next().await?;
+            let finished = chunk.is_none();
+            if let Some(chunk) = chunk {
+                buffer.extend_from_slice(&chunk);
+            }
+            if buffer.len() >= MULTIPART_CHUNK_SIZE || (finished && !buffer.is_empty()) {
+                let part_number = (parts.len() + 1).try_into()?;
+                let data = mem::replace(&mut buffer, Vec::with_capacity(MULTIPART_CHUNK_SIZE));
+                parts.push(
+                    self.upload_part(bucket, key, upload_id, part_number, data)
+                        .await?,
+                );
+            }
+            if finished {
+                break;
+            }
+        }
+        let etag = self
+            .s3_client
+            .complete_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await?
+            .e_tag
+            .ok_or_else(|| format_err!("Missing etag"))?
+            .trim_matches('"')
+            .into();
+        Ok(etag)
+    }
+
+    /// # Errors
+    /// Return error if s3 api call fails
+    pub async fn upload_bytes(
+        &self,
+        bucket: &str,
+        key: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<StackString, Error> {
+        let etag = self
+            .s3_client
+            .put_object()
+            .bucket(bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(data))
+            .send()
+            .await?
+            .e_tag
+            .ok_or_else(|| format_err!("Missing etag"))?
+            .trim_matches('"')
+            .into();
+        Ok(etag)
+    }
+
+    /// # Errors
+    /// Return error if s3 api call fails
+    pub async fn download_bytes(&self, bucket: &str, key: &str) -> Result<Vec<u8>, Error> {
+        let object = self
+            .s3_client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?;
+        let data = object.body.collect().await?;
+        Ok(data.into_bytes().to_vec())
+    }
+
+    /// Upload a stream of byte chunks directly to s3 without writing a local
+    /// file, switching to a multipart upload once more than
+    /// `MULTIPART_CHUNK_SIZE` bytes are buffered.
+    /// # Errors
+    /// Return error if the input stream or any s3 api call fails
+    pub async fn upload_stream<S>(
+        &self,
+        bucket: &str,
+        key: &str,
+        mut stream: S,
+    ) -> Result<StackString, Error>
+    where
+        S: Stream<Item = Result<Vec<u8>, Error>> + Unpin,
+    {
+        let mut buffer = Vec::with_capacity(MULTIPART_CHUNK_SIZE);
+        while buffer.len() < MULTIPART_CHUNK_SIZE {
+            let Some(chunk) = stream.try_next().await? else {
+                let etag = self
+                    .s3_client
+                    .put_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .body(ByteStream::from(buffer))
+                    .send()
+                    .await?
+                    .e_tag
+                    .ok_or_else(|| format_err!("Missing etag"))?
+                    .trim_matches('"')
+                    .into();
+                return Ok(etag);
+            };
+            buffer.extend_from_slice(&chunk);
+        }
+        let upload_id = self
+            .s3_client
+            .create_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await?
+            .upload_id
+            .ok_or_else(|| format_err!("Missing upload id"))?;
+        match self
+            .upload_multipart(bucket, key, &upload_id, buffer, &mut stream)
+            .await
+        {
+            Ok(etag) => Ok(etag),
+            Err(e) => {
+                self.s3_client
+                    .abort_multipart_upload()
+                    .bucket(bucket)
+                    .key(key)
+                    .upload_id(&upload_id)
+                    .send()
+                    .await?;
+                Err(e)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -390,7 +806,41 @@ mod tests {
     use anyhow::Error;
     use futures::TryStreamExt;
 
-    use crate::{config::Config, model::KeyItemCache, pgpool::PgPool, s3_sync::S3Sync};
+    use crate::{
+        config::Config,
+        model::KeyItemCache,
+        pgpool::PgPool,
+        s3_sync::{is_archive_key, KeyItem, S3Sync},
+    };
+
+    #[test]
+    fn test_is_archive_key() {
+        assert!(is_archive_key("weather_data_2024_03.parquet"));
+        assert!(!is_archive_key("weather_data_2024_03.parquet.tmp"));
+        assert!(!is_archive_key("history_export.parquet"));
+        assert!(!is_archive_key("weather_data_2024_03.csv"));
+    }
+
+    #[test]
+    fn test_key_item_matches_local() {
+        let md5 = "0123456789abcdef0123456789abcdef";
+        let item = KeyItem {
+            key: "weather_data_2024_03.parquet".into(),
+            etag: md5.into(),
+            timestamp: 0,
+            size: 1024,
+        };
+        assert!(item.matches_local(md5, 1024));
+        assert!(!item.matches_local("fedcba9876543210fedcba9876543210", 1024));
+
+        let multipart = KeyItem {
+            etag: "fedcba9876543210fedcba9876543210-3".into(),
+            size: 20 * 1024 * 1024,
+            ..item
+        };
+        assert!(multipart.matches_local(md5, 20 * 1024 * 1024));
+        assert!(!multipart.matches_local(md5, 1024));
+    }
 
     #[tokio::test]
     #[ignore]
@@ -398,7 +848,7 @@ mod tests {
         let aws_config = aws_config::load_from_env().await;
         let s3_sync = S3Sync::new(&aws_config);
         let config = Config::init_config(None)?;
-        let pool = PgPool::new(&config.database_url)?;
+        let pool = PgPool::from_config(&config)?;
 
         s3_sync.process_files(&config.cache_dir, &pool).await?;
         s3_sync
@@ -0,0 +1,560 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use stack_string::format_sstr;
+use std::sync::Arc;
+#[cfg(feature = "analysis")]
+use time::{
+    macros::{date, time},
+    PrimitiveDateTime,
+};
+use time::{Date, Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use weather_api_types::dto::{LocationCount, PrecipitationSummary};
+use weather_util_rust::{
+    weather_api::{WeatherApi, WeatherLocation},
+    weather_data::WeatherData,
+    weather_forecast::WeatherForecast,
+};
+
+use crate::{
+    analysis::get_precipitation_summary,
+    app::{get_weather_data, get_weather_forecast, resolve_location},
+    condition::ConditionFilter,
+    config::Config,
+    errors::ServiceError as Error,
+    fusion::Fusion,
+    geojson::GeoJsonFeatureCollection,
+    model::{WeatherDataDB, WeatherLocationCache},
+    offline_forecast::{get_recorded_offline_forecast, get_recorded_weather},
+    pgpool::PgPool,
+    publish::DailySummary,
+};
+#[cfg(feature = "analysis")]
+use crate::{
+    polars_analysis::{
+        find_anomalies, get_climatology, get_monthly_normals, stream_by_name_dates, AnomalyReport,
+        Climatology,
+    },
+    publish::get_daily_summaries,
+};
+
+/// Current conditions and forecasts, `api` carries the api key of the request
+#[async_trait]
+pub trait WeatherService: Send + Sync {
+    /// Current weather of `loc`
+    async fn get_weather(
+        &self,
+        api: &WeatherApi,
+        loc: &WeatherLocation,
+    ) -> Result<WeatherData, Error>;
+
+    /// Forecast of `loc`
+    async fn get_forecast(
+        &self,
+        api: &WeatherApi,
+        loc: &WeatherLocation,
+    ) -> Result<WeatherForecast, Error>;
+
+    /// Most recent recorded observation of `loc`, `None` when nothing is
+    /// recorded
+    async fn get_recorded_weather(
+        &self,
+        loc: &WeatherLocation,
+    ) -> Result<Option<WeatherData>, Error>;
+
+    /// Forecast built from the recorded history of `loc`
+    async fn get_offline_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error>;
+
+    /// The same service without recording observations or caching locations
+    fn stateless(&self) -> Arc<dyn WeatherService>;
+}
+
+/// Recorded observations
+#[async_trait]
+pub trait HistoryService: Send + Sync {
+    /// Observations of `name` between `start_date` and `end_date`, leaving
+    /// out rows inserted after `asof`
+    async fn get_history(
+        &self,
+        name: &str,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        condition: Option<ConditionFilter>,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<Vec<WeatherDataDB>, Error>;
+
+    /// Observations of `name` since `start_date`, for views of the last few
+    /// days that shouldn't wait on the archive
+    async fn get_recent(&self, name: &str, start_date: Date) -> Result<Vec<WeatherDataDB>, Error>;
+
+    async fn get_by_id(&self, id: Uuid) -> Result<Option<WeatherDataDB>, Error>;
+
+    /// Summaries per local day of `name`
+    async fn get_daily_summaries(
+        &self,
+        name: &str,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<Vec<DailySummary>, Error>;
+
+    /// Monthly normals of `name`, leaving out rows inserted after `asof`
+    #[cfg(feature = "analysis")]
+    async fn get_climatology(
+        &self,
+        name: &str,
+        server: Option<&str>,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<Climatology, Error>;
+
+    /// Observations of `name` between `start_date` and `end_date` (inclusive)
+    /// deviating from the trailing `window_days` window
+    #[cfg(feature = "analysis")]
+    #[allow(clippy::too_many_arguments)]
+    async fn get_anomalies(
+        &self,
+        name: &str,
+        server: Option<&str>,
+        start_date: Date,
+        end_date: Date,
+        window_days: i64,
+        threshold: f64,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<AnomalyReport, Error> {
+        // end_date is compared with midnight, include the whole last day
+        let history = self
+            .get_history(
+                name,
+                server,
+                Some(start_date - Duration::days(window_days)),
+                Some(end_date + Duration::days(1)),
+                None,
+                asof,
+            )
+            .await?;
+        Ok(AnomalyReport {
+            location_name: name.into(),
+            threshold,
+            window_days,
+            anomalies: find_anomalies(&history, start_date, window_days, threshold),
+        })
+    }
+
+    /// Precipitation of the past week
+    async fn get_precipitation_summary(&self, name: &str) -> Result<PrecipitationSummary, Error> {
+        // a week plus a day to cover the utc offset of the location
+        let now = OffsetDateTime::now_utc();
+        let history = self
+            .get_recent(name, now.date() - Duration::days(8))
+            .await?;
+        Ok(get_precipitation_summary(name, &history, now))
+    }
+}
+
+/// Geocoding and the recorded locations
+#[async_trait]
+pub trait LocationService: Send + Sync {
+    /// Latitude / longitude of `loc`
+    async fn resolve(
+        &self,
+        api: &WeatherApi,
+        loc: &WeatherLocation,
+    ) -> Result<WeatherLocation, Error>;
+
+    /// Total number of recorded locations and a page of their observation
+    /// counts
+    async fn get_location_counts(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(usize, Vec<LocationCount>), Error>;
+
+    /// Cached locations with their latest observation
+    async fn get_locations_geojson(&self) -> Result<GeoJsonFeatureCollection, Error>;
+}
+
+/// Provider chain behind the weather data / forecast caches, observations are
+/// recorded when the database is enabled
+#[derive(Clone)]
+pub struct CachedWeatherService {
+    pool: PgPool,
+    config: Config,
+}
+
+impl CachedWeatherService {
+    #[must_use]
+    pub fn new(pool: &PgPool, config: &Config) -> Self {
+        Self {
+            pool: pool.clone(),
+            config: config.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl WeatherService for CachedWeatherService {
+    async fn get_weather(
+        &self,
+        api: &WeatherApi,
+        loc: &WeatherLocation,
+    ) -> Result<WeatherData, Error> {
+        get_weather_data(&self.pool, &self.config, api, loc).await
+    }
+
+    async fn get_forecast(
+        &self,
+        api: &WeatherApi,
+        loc: &WeatherLocation,
+    ) -> Result<WeatherForecast, Error> {
+        get_weather_forecast(&self.pool, &self.config, api, loc).await
+    }
+
+    async fn get_recorded_weather(
+        &self,
+        loc: &WeatherLocation,
+    ) -> Result<Option<WeatherData>, Error> {
+        if !self.pool.is_enabled() {
+            return Ok(None);
+        }
+        let location_name = format_sstr!("{loc}");
+        let recorded =
+            get_recorded_weather(&self.pool, &location_name, OffsetDateTime::now_utc()).await?;
+        Ok(recorded.map(Into::into))
+    }
+
+    async fn get_offline_forecast(&self, loc: &WeatherLocation) -> Result<WeatherForecast, Error> {
+        let location_name = format_sstr!("{loc}");
+        get_recorded_offline_forecast(&self.pool, &location_name, OffsetDateTime::now_utc())
+            .await
+            .map_err(Into::into)
+    }
+
+    fn stateless(&self) -> Arc<dyn WeatherService> {
+        Arc::new(Self::new(&PgPool::disabled(), &self.config))
+    }
+}
+
+/// Ranges starting before this month are read from the parquet archive
+#[cfg(feature = "analysis")]
+fn read_from_archive(start_date: Option<Date>) -> bool {
+    let now = OffsetDateTime::now_utc();
+    let first_of_month = PrimitiveDateTime::new(
+        Date::from_calendar_date(now.year(), now.month(), 1)
+            .unwrap_or_else(|_| date!(2023 - 01 - 01)),
+        time!(00:00),
+    )
+    .assume_utc()
+    .date();
+    start_date.is_none() || start_date < Some(first_of_month)
+}
+
+/// History before this month is read from the parquet archive in
+/// `cache_dir` when the `analysis` feature is enabled, everything else from
+/// the database
+#[derive(Clone)]
+pub struct StoredHistoryService {
+    pool: PgPool,
+    #[cfg_attr(not(feature = "analysis"), allow(dead_code))]
+    config: Config,
+    fusion: Option<Fusion>,
+}
+
+impl StoredHistoryService {
+    #[must_use]
+    pub fn new(pool: &PgPool, config: &Config) -> Self {
+        Self {
+            pool: pool.clone(),
+            config: config.clone(),
+            fusion: Fusion::from_config(config),
+        }
+    }
+
+    /// Co-located observations of several sources are combined when fusion
+    /// is enabled and no specific server was requested
+    fn fuse(&self, server: Option<&str>, history: Vec<WeatherDataDB>) -> Vec<WeatherDataDB> {
+        match (&self.fusion, server) {
+            (Some(fusion), None) => fusion.fuse(history),
+            _ => history,
+        }
+    }
+}
+
+#[async_trait]
+impl HistoryService for StoredHistoryService {
+    async fn get_history(
+        &self,
+        name: &str,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        condition: Option<ConditionFilter>,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<Vec<WeatherDataDB>, Error> {
+        // the parquet archive has no ingestion times, `asof` queries are
+        // answered from the db
+        #[cfg(feature = "analysis")]
+        {
+            if asof.is_none() && read_from_archive(start_date) {
+                let mut history: Vec<WeatherDataDB> = stream_by_name_dates(
+                    &self.config.cache_dir,
+                    Some(name),
+                    server,
+                    start_date,
+                    end_date,
+                    None,
+                    None,
+                )?
+                .try_collect()
+                .await?;
+                // the parquet archive has no condition_code column, it is
+                // derived from the condition text when read
+                if let Some(condition) = condition {
+                    history.retain(|row| condition.matches(row.condition_code));
+                }
+                return Ok(self.fuse(server, history));
+            }
+        }
+        let history = WeatherDataDB::get_by_name_dates(
+            &self.pool,
+            Some(name),
+            server,
+            start_date,
+            end_date,
+            condition,
+            None,
+            asof,
+            None,
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+        Ok(self.fuse(server, history))
+    }
+
+    async fn get_recent(&self, name: &str, start_date: Date) -> Result<Vec<WeatherDataDB>, Error> {
+        let history = WeatherDataDB::get_by_name_dates(
+            &self.pool,
+            Some(name),
+            None,
+            Some(start_date),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+        Ok(self.fuse(None, history))
+    }
+
+    async fn get_by_id(&self, id: Uuid) -> Result<Option<WeatherDataDB>, Error> {
+        WeatherDataDB::get_by_id(&self.pool, id)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn get_daily_summaries(
+        &self,
+        name: &str,
+        server: Option<&str>,
+        start_date: Option<Date>,
+        end_date: Option<Date>,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<Vec<DailySummary>, Error> {
+        #[cfg(feature = "analysis")]
+        {
+            if asof.is_none() && read_from_archive(start_date) {
+                let history = self
+                    .get_history(name, server, start_date, end_date, None, None)
+                    .await?;
+                return Ok(get_daily_summaries(name, &history));
+            }
+        }
+        WeatherDataDB::get_daily_summaries(&self.pool, name, server, start_date, end_date, asof)
+            .await
+            .map_err(Into::into)
+    }
+
+    #[cfg(feature = "analysis")]
+    async fn get_climatology(
+        &self,
+        name: &str,
+        server: Option<&str>,
+        asof: Option<OffsetDateTime>,
+    ) -> Result<Climatology, Error> {
+        // the parquet archive has no ingestion times, `asof` normals are
+        // computed from the daily summaries of the db
+        if asof.is_some() {
+            let days = self
+                .get_daily_summaries(name, server, None, None, asof)
+                .await?;
+            return Ok(Climatology {
+                location_name: name.into(),
+                months: get_monthly_normals(&days),
+            });
+        }
+        get_climatology(&self.config.cache_dir, name, server)
+            .await
+            .map_err(Into::into)
+    }
+}
+
+/// Geo api lookups behind `weather_location_cache`
+#[derive(Clone)]
+pub struct CachedLocationService {
+    pool: PgPool,
+}
+
+impl CachedLocationService {
+    #[must_use]
+    pub fn new(pool: &PgPool) -> Self {
+        Self { pool: pool.clone() }
+    }
+}
+
+#[async_trait]
+impl LocationService for CachedLocationService {
+    async fn resolve(
+        &self,
+        api: &WeatherApi,
+        loc: &WeatherLocation,
+    ) -> Result<WeatherLocation, Error> {
+        resolve_location(&self.pool, api, loc).await
+    }
+
+    async fn get_location_counts(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(usize, Vec<LocationCount>), Error> {
+        let total = WeatherDataDB::get_total_locations(&self.pool).await?;
+        let counts = WeatherDataDB::get_locations(&self.pool, Some(offset), Some(limit))
+            .await?
+            .map_ok(|(location, count)| LocationCount {
+                location: location.into(),
+                count,
+            })
+            .try_collect()
+            .await?;
+        Ok((total, counts))
+    }
+
+    async fn get_locations_geojson(&self) -> Result<GeoJsonFeatureCollection, Error> {
+        let locations: Vec<_> = WeatherLocationCache::get_all(&self.pool)
+            .await?
+            .try_collect()
+            .await?;
+        let observations: Vec<_> = WeatherDataDB::get_latest_by_location(&self.pool)
+            .await?
+            .try_collect()
+            .await?;
+        Ok(GeoJsonFeatureCollection::new(locations, observations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use time::{Date, Duration, OffsetDateTime};
+    use uuid::Uuid;
+
+    use crate::{
+        condition::ConditionFilter, errors::ServiceError as Error, model::WeatherDataDB,
+        publish::DailySummary, services::HistoryService,
+    };
+
+    /// In memory history
+    struct StaticHistory(Vec<WeatherDataDB>);
+
+    #[async_trait]
+    impl HistoryService for StaticHistory {
+        async fn get_history(
+            &self,
+            name: &str,
+            _: Option<&str>,
+            _: Option<Date>,
+            _: Option<Date>,
+            _: Option<ConditionFilter>,
+            _: Option<OffsetDateTime>,
+        ) -> Result<Vec<WeatherDataDB>, Error> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|row| row.location_name == name)
+                .cloned()
+                .collect())
+        }
+
+        async fn get_recent(
+            &self,
+            name: &str,
+            start_date: Date,
+        ) -> Result<Vec<WeatherDataDB>, Error> {
+            let history = self.get_history(name, None, None, None, None, None).await?;
+            Ok(history
+                .into_iter()
+                .filter(|row| row.created_at.date() >= start_date)
+                .collect())
+        }
+
+        async fn get_by_id(&self, id: Uuid) -> Result<Option<WeatherDataDB>, Error> {
+            Ok(self.0.iter().find(|row| row.id == id).cloned())
+        }
+
+        async fn get_daily_summaries(
+            &self,
+            _: &str,
+            _: Option<&str>,
+            _: Option<Date>,
+            _: Option<Date>,
+            _: Option<OffsetDateTime>,
+        ) -> Result<Vec<DailySummary>, Error> {
+            Ok(Vec::new())
+        }
+
+        #[cfg(feature = "analysis")]
+        async fn get_climatology(
+            &self,
+            name: &str,
+            _: Option<&str>,
+            _: Option<OffsetDateTime>,
+        ) -> Result<crate::polars_analysis::Climatology, Error> {
+            Ok(crate::polars_analysis::Climatology {
+                location_name: name.into(),
+                months: Vec::new(),
+            })
+        }
+    }
+
+    fn row(created_at: OffsetDateTime, rain: Option<f64>) -> WeatherDataDB {
+        WeatherDataDB {
+            rain,
+            ..WeatherDataDB::test_row(created_at)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_history_service() -> Result<(), Error> {
+        let now = OffsetDateTime::now_utc();
+        let old = row(now - Duration::days(30), Some(5.0));
+        let recent = row(now, Some(1.5));
+        let id = recent.id;
+        let service = StaticHistory(vec![old, recent]);
+        let service: &dyn HistoryService = &service;
+
+        assert_eq!(service.get_by_id(id).await?.map(|row| row.id), Some(id));
+        assert_eq!(service.get_recent("11106", now.date()).await?.len(), 1);
+        let summary = service.get_precipitation_summary("11106").await?;
+        assert_eq!(summary.hours, 1);
+        assert!((summary.today_rain - 1.5).abs() < 1e-9);
+        assert!(service.get_recent("other", now.date()).await?.is_empty());
+        Ok(())
+    }
+}
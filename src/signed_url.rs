@@ -0,0 +1,145 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use stack_string::{format_sstr, StackString};
+use time::{Duration, OffsetDateTime};
+
+use crate::errors::ServiceError as Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Lifetime of a signed url when none is requested
+pub const DEFAULT_SIGNED_URL_TTL: Duration = Duration::hours(24);
+/// Longest lifetime a signed url can be issued for
+pub const MAX_SIGNED_URL_TTL: Duration = Duration::days(7);
+
+fn get_mac(key: &[u8], query: &str, expires: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(query.as_bytes());
+    mac.update(format_sstr!("&expires={expires}").as_bytes());
+    mac
+}
+
+fn decode_hex(input: &str) -> Option<Vec<u8>> {
+    if input.len() % 2 != 0 {
+        return None;
+    }
+    (0..input.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(input.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Hex HMAC-SHA256 of the canonical `query` string and the unix timestamp
+/// the url expires at
+#[must_use]
+pub fn sign_query(key: &[u8], query: &str, expires: i64) -> StackString {
+    let mut output = StackString::new();
+    for b in get_mac(key, query, expires).finalize().into_bytes() {
+        output.push_str(&format_sstr!("{b:02x}"));
+    }
+    output
+}
+
+/// Whether `signature` was issued for `query` and `expires` with `key` and
+/// the url has not expired at `now`, the comparison is constant time
+#[must_use]
+pub fn verify_query(
+    key: &[u8],
+    query: &str,
+    expires: i64,
+    signature: &str,
+    now: OffsetDateTime,
+) -> bool {
+    if now.unix_timestamp() > expires {
+        return false;
+    }
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+    get_mac(key, query, expires)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+/// Like `verify_query`, but says why a signed url is refused
+///
+/// # Errors
+/// Returns `Forbidden` if the url has expired or the signature doesn't match
+pub fn check_signed_query(
+    key: &[u8],
+    query: &str,
+    expires: i64,
+    signature: &str,
+    now: OffsetDateTime,
+) -> Result<(), Error> {
+    if now.unix_timestamp() > expires {
+        Err(Error::Forbidden("signed url expired".into()))
+    } else if verify_query(key, query, expires, signature, now) {
+        Ok(())
+    } else {
+        Err(Error::Forbidden("invalid signature".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration};
+
+    use crate::{
+        errors::ServiceError,
+        signed_url::{check_signed_query, sign_query, verify_query},
+    };
+
+    #[test]
+    fn test_signed_url() {
+        let key = b"test signing key";
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let expires = (now + Duration::hours(1)).unix_timestamp();
+        let query = "name=Minneapolis&start_time=2024-05-01";
+        let signature = sign_query(key, query, expires);
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, sign_query(key, query, expires));
+
+        assert!(verify_query(key, query, expires, &signature, now));
+        assert!(!verify_query(
+            key,
+            query,
+            expires,
+            &signature,
+            now + Duration::hours(2)
+        ));
+        assert!(!verify_query(
+            key,
+            "name=Minneapolis&start_time=2024-01-01",
+            expires,
+            &signature,
+            now
+        ));
+        assert!(!verify_query(key, query, expires + 3600, &signature, now));
+        assert!(!verify_query(b"other key", query, expires, &signature, now));
+        assert!(!verify_query(key, query, expires, "not hex", now));
+        assert!(!verify_query(key, query, expires, &signature[..62], now));
+    }
+
+    #[test]
+    fn test_check_signed_query() {
+        let key = b"test signing key";
+        let now = datetime!(2024-06-01 12:00 UTC);
+        let expires = (now + Duration::hours(1)).unix_timestamp();
+        let query = "name=Minneapolis&start_time=2024-05-01";
+        let signature = sign_query(key, query, expires);
+        assert!(check_signed_query(key, query, expires, &signature, now).is_ok());
+
+        let mut tampered: String = signature.as_str().into();
+        let last = if tampered.ends_with('0') { "1" } else { "0" };
+        tampered.replace_range(63.., last);
+        match check_signed_query(key, query, expires, &tampered, now) {
+            Err(ServiceError::Forbidden(message)) => assert_eq!(message, "invalid signature"),
+            result => panic!("unexpected {result:?}"),
+        }
+        match check_signed_query(key, query, expires, &signature, now + Duration::hours(2)) {
+            Err(ServiceError::Forbidden(message)) => assert_eq!(message, "signed url expired"),
+            result => panic!("unexpected {result:?}"),
+        }
+    }
+}
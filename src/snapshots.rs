@@ -0,0 +1,96 @@
+use rweb::{
+    http::{header::CONTENT_TYPE, StatusCode},
+    openapi::{
+        ComponentDescriptor, ComponentOrInlineSchema, Entity, MediaType, Response, ResponseEntity,
+        Responses, Schema, Type,
+    },
+    reply, Reply,
+};
+use stack_string::{format_sstr, StackString};
+use std::borrow::Cow;
+use uuid::Uuid;
+
+/// Largest snapshot accepted by `/weather/snapshots/upload`
+pub const MAX_SNAPSHOT_SIZE: u64 = 10 * 1024 * 1024;
+
+fn get_extension(content_type: &str) -> &'static str {
+    match content_type {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => "bin",
+    }
+}
+
+/// S3 key for an uploaded snapshot, `snapshots/<location>/<id>.<ext>`
+#[must_use]
+pub fn get_snapshot_key(location_name: &str, id: Uuid, content_type: &str) -> StackString {
+    let location: String = location_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let ext = get_extension(content_type);
+    format_sstr!("snapshots/{location}/{id}.{ext}")
+}
+
+pub struct SnapshotImageResponse {
+    pub content_type: StackString,
+    pub data: Vec<u8>,
+}
+
+impl Reply for SnapshotImageResponse {
+    fn into_response(self) -> reply::Response {
+        reply::with_header(self.data, CONTENT_TYPE, self.content_type.as_str()).into_response()
+    }
+}
+
+impl Entity for SnapshotImageResponse {
+    fn type_name() -> Cow<'static, str> {
+        "snapshot_image".into()
+    }
+
+    fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        ComponentOrInlineSchema::Inline(Schema {
+            schema_type: Some(Type::String),
+            format: "binary".into(),
+            ..Schema::default()
+        })
+    }
+}
+
+impl ResponseEntity for SnapshotImageResponse {
+    fn describe_responses(comp_d: &mut ComponentDescriptor) -> Responses {
+        let mut response = Response {
+            description: Cow::Borrowed("Snapshot Image"),
+            ..Response::default()
+        };
+        response.content.insert(
+            Cow::Borrowed("image/*"),
+            MediaType {
+                schema: Some(Self::describe(comp_d)),
+                ..MediaType::default()
+            },
+        );
+        let mut map = Responses::new();
+        map.insert(Cow::Owned(StatusCode::OK.as_str().into()), response);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::snapshots::get_snapshot_key;
+
+    #[test]
+    fn test_get_snapshot_key() {
+        let key = get_snapshot_key("Saint Louis Park, MN", Uuid::nil(), "image/jpeg");
+        assert_eq!(
+            key.as_str(),
+            "snapshots/Saint_Louis_Park__MN/00000000-0000-0000-0000-000000000000.jpg"
+        );
+        assert!(get_snapshot_key("x", Uuid::nil(), "text/plain").ends_with(".bin"));
+    }
+}
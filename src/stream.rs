@@ -0,0 +1,244 @@
+use futures::{stream, SinkExt, Stream, StreamExt};
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use rweb::{
+    filters::{
+        query::query,
+        sse,
+        ws::{ws, Message, WebSocket, Ws},
+        BoxedFilter,
+    },
+    Filter, Reply,
+};
+use serde::Deserialize;
+use stack_string::{format_sstr, StackString};
+use std::{collections::HashSet, convert::Infallible, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+
+use weather_api_types::get_parameters;
+
+use crate::model::WeatherDataDB;
+
+/// Rows buffered per subscriber before a slow stream starts skipping rows
+const UPDATE_CAPACITY: usize = 64;
+
+/// Every `WeatherDataDB` row written to the db
+static WEATHER_UPDATES: Lazy<Sender<WeatherDataDB>> =
+    Lazy::new(|| broadcast::channel(UPDATE_CAPACITY).0);
+
+/// Notify the open `/weather/stream` and `/weather/ws` connections of a newly
+/// written row
+pub fn publish_weather_update(row: &WeatherDataDB) {
+    // no receivers is not an error, nobody is listening
+    WEATHER_UPDATES.send(row.clone()).ok();
+}
+
+#[derive(Deserialize)]
+struct StreamRequest {
+    loc: StackString,
+}
+
+/// `location_name` of the rows recorded for `loc`
+fn location_name(loc: &str) -> StackString {
+    format_sstr!("{}", get_parameters(loc))
+}
+
+/// Rows for `location_name`, ends when the sender is dropped
+fn location_updates(
+    receiver: Receiver<WeatherDataDB>,
+    location_name: StackString,
+) -> impl Stream<Item = WeatherDataDB> {
+    stream::unfold(receiver, move |mut receiver| {
+        let location_name = location_name.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(row) if row.location_name == location_name => {
+                        return Some((row, receiver));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("stream for {location_name} skipped {skipped} rows");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    })
+}
+
+/// `/weather/stream?loc=...` server-sent events, a `weather` event with the
+/// json row each time a row is recorded for `loc` (same format as
+/// `LOCATIONS_TO_RECORD`)
+pub fn weather_stream() -> BoxedFilter<(impl Reply,)> {
+    rweb::path!("weather" / "stream")
+        .and(rweb::path::end())
+        .and(query::<StreamRequest>())
+        .map(|request: StreamRequest| {
+            let location_name = location_name(&request.loc);
+            let events = location_updates(WEATHER_UPDATES.subscribe(), location_name).map(|row| {
+                let event = sse::Event::default()
+                    .event("weather")
+                    .id(row.id.to_string())
+                    .json_data(&row)
+                    .unwrap_or_else(|_| sse::Event::default().comment("invalid row"));
+                Ok::<_, Infallible>(event)
+            });
+            sse::reply(
+                sse::keep_alive()
+                    .interval(Duration::from_secs(30))
+                    .stream(events),
+            )
+        })
+        .boxed()
+}
+
+#[derive(Deserialize)]
+struct WsRequest {
+    loc: Option<StackString>,
+}
+
+/// Text message from a `/weather/ws` client, e.g.
+/// `{"action": "subscribe", "loc": "11106"}`
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum WsCommand {
+    Subscribe { loc: StackString },
+    Unsubscribe { loc: StackString },
+}
+
+impl WsCommand {
+    fn apply(&self, locations: &mut HashSet<StackString>) {
+        match self {
+            Self::Subscribe { loc } => {
+                locations.insert(location_name(loc));
+            }
+            Self::Unsubscribe { loc } => {
+                locations.remove(&location_name(loc));
+            }
+        }
+    }
+}
+
+/// Forward the rows of the subscribed locations as json text messages until
+/// either side closes the connection
+async fn handle_ws(socket: WebSocket, mut locations: HashSet<StackString>) {
+    let (mut sink, mut source) = socket.split();
+    let mut receiver = WEATHER_UPDATES.subscribe();
+    loop {
+        tokio::select! {
+            message = source.next() => match message {
+                Some(Ok(message)) => {
+                    if message.is_close() {
+                        break;
+                    }
+                    if let Ok(text) = message.to_str() {
+                        match serde_json::from_str::<WsCommand>(text) {
+                            Ok(command) => command.apply(&mut locations),
+                            Err(e) => debug!("invalid ws message {text} {e}"),
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    debug!("ws error {e}");
+                    break;
+                }
+                None => break,
+            },
+            row = receiver.recv() => match row {
+                Ok(row) => {
+                    if !locations.contains(&row.location_name) {
+                        continue;
+                    }
+                    let text = match serde_json::to_string(&row) {
+                        Ok(text) => text,
+                        Err(e) => {
+                            warn!("failed to serialize row {e}");
+                            continue;
+                        }
+                    };
+                    if sink.send(Message::text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("websocket skipped {skipped} rows");
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+/// `/weather/ws` websocket pushing each recorded row as json, only rows of
+/// the locations subscribed to (initially `?loc=...` if given) are sent
+pub fn weather_ws() -> BoxedFilter<(impl Reply,)> {
+    rweb::path!("weather" / "ws")
+        .and(rweb::path::end())
+        .and(ws())
+        .and(query::<WsRequest>())
+        .map(|ws: Ws, request: WsRequest| {
+            let locations = request.loc.iter().map(|l| location_name(l)).collect();
+            ws.on_upgrade(move |socket| handle_ws(socket, locations))
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use time::macros::datetime;
+    use tokio::sync::broadcast;
+
+    use std::collections::HashSet;
+
+    use crate::{
+        model::WeatherDataDB,
+        stream::{location_updates, WsCommand},
+    };
+
+    fn row(location_name: &str) -> WeatherDataDB {
+        WeatherDataDB {
+            location_name: location_name.into(),
+            latitude: 0.0,
+            longitude: 0.0,
+            humidity: 50,
+            ..WeatherDataDB::test_row(datetime!(2024-06-01 00:00 UTC))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_location_updates() {
+        let (sender, receiver) = broadcast::channel(8);
+        let updates = location_updates(receiver, "11106".into());
+        let first = row("11106");
+        let second = row("11106");
+        sender.send(row("10001")).unwrap();
+        sender.send(first.clone()).unwrap();
+        sender.send(row("10001")).unwrap();
+        sender.send(second.clone()).unwrap();
+        drop(sender);
+        let ids: Vec<_> = updates.map(|r| r.id).collect().await;
+        assert_eq!(ids, vec![first.id, second.id]);
+    }
+
+    #[test]
+    fn test_ws_command() {
+        let mut locations = HashSet::new();
+        let command: WsCommand =
+            serde_json::from_str(r#"{"action": "subscribe", "loc": "11106"}"#).unwrap();
+        assert_eq!(
+            command,
+            WsCommand::Subscribe {
+                loc: "11106".into()
+            }
+        );
+        command.apply(&mut locations);
+        assert_eq!(locations.len(), 1);
+        let command: WsCommand =
+            serde_json::from_str(r#"{"action": "unsubscribe", "loc": "11106"}"#).unwrap();
+        command.apply(&mut locations);
+        assert!(locations.is_empty());
+        assert!(serde_json::from_str::<WsCommand>(r#"{"action": "clear"}"#).is_err());
+    }
+}
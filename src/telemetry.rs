@@ -0,0 +1,80 @@
+use anyhow::Error;
+use log::error;
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+use crate::config::{Config, ConfigInner};
+
+/// Flushes the batched spans to the collector when dropped
+pub struct TelemetryGuard(TracerProvider);
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.shutdown() {
+            error!("Failed to flush spans {e}");
+        }
+    }
+}
+
+/// Collector spans are exported to, `None` (telemetry disabled) unless
+/// `otlp_endpoint` is set to a non empty value
+fn get_otlp_endpoint(config: &ConfigInner) -> Option<&str> {
+    config
+        .otlp_endpoint
+        .as_deref()
+        .map(str::trim)
+        .filter(|endpoint| !endpoint.is_empty())
+}
+
+/// Export `tracing` spans to the OTLP collector at `otlp_endpoint`, nothing
+/// is exported when it isn't set. Spans are OTLP only: no subscriber is
+/// installed without an endpoint, so `#[instrument]` spans are dropped, and
+/// the subscriber has no fmt layer, so spans never reach the logs. Logging
+/// stays with `env_logger` and `log` records are not bridged into spans.
+///
+/// # Errors
+/// Return error if the exporter can't be built or a global subscriber is
+/// already set
+pub fn init_telemetry(config: &Config) -> Result<Option<TelemetryGuard>, Error> {
+    let Some(endpoint) = get_otlp_endpoint(config) else {
+        return Ok(None);
+    };
+    let exporter = SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.otlp_service_name.to_string(),
+        )]))
+        .build();
+    let tracer = provider.tracer("weather_api_rust");
+    let subscriber = Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(Some(TelemetryGuard(provider)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::ConfigInner, telemetry::get_otlp_endpoint};
+
+    #[test]
+    fn test_get_otlp_endpoint() {
+        let config = ConfigInner::default();
+        assert_eq!(get_otlp_endpoint(&config), None);
+        let config = ConfigInner {
+            otlp_endpoint: Some(" ".into()),
+            ..ConfigInner::default()
+        };
+        assert_eq!(get_otlp_endpoint(&config), None);
+        let config = ConfigInner {
+            otlp_endpoint: Some("http://localhost:4317".into()),
+            ..ConfigInner::default()
+        };
+        assert_eq!(get_otlp_endpoint(&config), Some("http://localhost:4317"));
+    }
+}
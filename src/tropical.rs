@@ -0,0 +1,306 @@
+use cached::{proc_macro::cached, TimedSizedCache};
+use dioxus::prelude::{component, dioxus_elements, rsx, Element, IntoDynNode, Props};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use stack_string::{format_sstr, StackString};
+use std::fmt::Write;
+use time::OffsetDateTime;
+
+use crate::{errors::ServiceError, lightning::haversine_distance};
+
+const KM_PER_DEGREE: f64 = 111.19;
+const KM_PER_MILE: f64 = 1.609_344;
+const MAP_SIZE: f64 = 600.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NhcProduct {
+    #[serde(default)]
+    pub adv_num: Option<StackString>,
+    #[serde(default)]
+    pub url: Option<StackString>,
+    #[serde(default)]
+    pub kmz_file: Option<StackString>,
+    #[serde(default)]
+    pub zip_file: Option<StackString>,
+}
+
+/// Active storm entry from the NHC `CurrentStorms.json` feed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NhcStorm {
+    pub id: StackString,
+    pub name: StackString,
+    /// TD, TS, HU, STD, STS, PTC, TY
+    pub classification: StackString,
+    /// maximum sustained wind (kt)
+    pub intensity: StackString,
+    /// minimum central pressure (mb)
+    pub pressure: StackString,
+    pub latitude_numeric: f64,
+    pub longitude_numeric: f64,
+    /// direction of motion (degrees)
+    #[serde(default)]
+    pub movement_dir: Option<f64>,
+    /// speed of motion (mph)
+    #[serde(default)]
+    pub movement_speed: Option<f64>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_update: OffsetDateTime,
+    #[serde(default)]
+    pub public_advisory: Option<NhcProduct>,
+    #[serde(default)]
+    pub forecast_track: Option<NhcProduct>,
+    #[serde(default)]
+    pub track_cone: Option<NhcProduct>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NhcActiveStorms {
+    #[serde(default)]
+    active_storms: Vec<NhcStorm>,
+}
+
+/// Storm along with its distance from the requested location
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearbyStorm {
+    pub storm: NhcStorm,
+    pub distance_km: f64,
+}
+
+/// Active storms, along with their track and cone file links, are cached for
+/// 30 minutes (NHC issues advisories every 3-6 hours).
+///
+/// # Errors
+/// Return error if the feed request fails
+#[cached(
+    ty = "TimedSizedCache<StackString, Vec<NhcStorm>>",
+    create = "{ TimedSizedCache::with_size_and_lifespan(10, 1800) }",
+    convert = r#"{ url.into() }"#,
+    result = true
+)]
+pub async fn get_active_storms(client: &Client, url: &str) -> Result<Vec<NhcStorm>, ServiceError> {
+    let storms: NhcActiveStorms = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(storms.active_storms)
+}
+
+/// Storms within `max_distance_km` of a location, nearest first
+#[must_use]
+pub fn get_nearby_storms(
+    storms: &[NhcStorm],
+    latitude: f64,
+    longitude: f64,
+    max_distance_km: f64,
+) -> Vec<NearbyStorm> {
+    let mut nearby: Vec<_> = storms
+        .iter()
+        .map(|storm| NearbyStorm {
+            storm: storm.clone(),
+            distance_km: haversine_distance(
+                latitude,
+                longitude,
+                storm.latitude_numeric,
+                storm.longitude_numeric,
+            ),
+        })
+        .filter(|s| s.distance_km <= max_distance_km)
+        .collect();
+    nearby.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
+    nearby
+}
+
+fn escape_xml(s: &str) -> StackString {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .into()
+}
+
+/// Project a point onto the map, centered on the location and spanning
+/// `max_distance_km` in every direction.
+fn project(latitude: f64, longitude: f64, center: (f64, f64), max_distance_km: f64) -> (f64, f64) {
+    let (lat0, lon0) = center;
+    let scale = MAP_SIZE / 2.0 / max_distance_km;
+    let x = (longitude - lon0) * lat0.to_radians().cos() * KM_PER_DEGREE;
+    let y = (latitude - lat0) * KM_PER_DEGREE;
+    (MAP_SIZE / 2.0 + x * scale, MAP_SIZE / 2.0 - y * scale)
+}
+
+/// Render an svg overlay of the location, the monitored radius, current
+/// storm positions and their projected 12 hour motion.
+#[must_use]
+pub fn tropical_map_svg(
+    latitude: f64,
+    longitude: f64,
+    storms: &[NearbyStorm],
+    max_distance_km: f64,
+) -> String {
+    let center = (latitude, longitude);
+    let half = MAP_SIZE / 2.0;
+    let mut svg = String::new();
+    write!(
+        &mut svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{MAP_SIZE}" height="{MAP_SIZE}" viewBox="0 0 {MAP_SIZE} {MAP_SIZE}">"#
+    )
+    .unwrap_or(());
+    write!(
+        &mut svg,
+        r##"<rect width="{MAP_SIZE}" height="{MAP_SIZE}" fill="#dbe9f4"/><circle cx="{half}" cy="{half}" r="{half}" fill="none" stroke="#888" stroke-dasharray="6,4"/><circle cx="{half}" cy="{half}" r="5" fill="#1f4e9c"/>"##
+    )
+    .unwrap_or(());
+    for nearby in storms {
+        let storm = &nearby.storm;
+        let (x, y) = project(
+            storm.latitude_numeric,
+            storm.longitude_numeric,
+            center,
+            max_distance_km,
+        );
+        if let (Some(dir), Some(speed)) = (storm.movement_dir, storm.movement_speed) {
+            let distance_km = speed * KM_PER_MILE * 12.0;
+            let scale = half / max_distance_km;
+            let x1 = x + distance_km * dir.to_radians().sin() * scale;
+            let y1 = y - distance_km * dir.to_radians().cos() * scale;
+            write!(
+                &mut svg,
+                r##"<line x1="{x:.1}" y1="{y:.1}" x2="{x1:.1}" y2="{y1:.1}" stroke="#c0392b" stroke-width="2" stroke-dasharray="4,3"/>"##
+            )
+            .unwrap_or(());
+        }
+        let label = escape_xml(&format_sstr!(
+            "{} {} ({} kt)",
+            storm.classification,
+            storm.name,
+            storm.intensity
+        ));
+        write!(
+            &mut svg,
+            r##"<circle cx="{x:.1}" cy="{y:.1}" r="8" fill="#c0392b"/><text x="{:.1}" y="{:.1}" font-size="12">{label}</text>"##,
+            x + 10.0,
+            y + 4.0,
+        )
+        .unwrap_or(());
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+#[component]
+pub fn TropicalComponent(
+    title: String,
+    latitude: f64,
+    longitude: f64,
+    storms: Vec<NearbyStorm>,
+    max_distance_km: f64,
+) -> Element {
+    let svg = tropical_map_svg(latitude, longitude, &storms, max_distance_km);
+    let rows = storms.iter().map(|nearby| {
+        let storm = &nearby.storm;
+        let id = &storm.id;
+        let name = format!("{} {}", storm.classification, storm.name);
+        let distance = format!("{:0.0} km", nearby.distance_km);
+        let intensity = format!("{} kt / {} mb", storm.intensity, storm.pressure);
+        let advisory = storm
+            .public_advisory
+            .as_ref()
+            .and_then(|p| p.url.clone())
+            .unwrap_or_default();
+        let track = storm
+            .forecast_track
+            .as_ref()
+            .and_then(|p| p.kmz_file.clone())
+            .unwrap_or_default();
+        let cone = storm
+            .track_cone
+            .as_ref()
+            .and_then(|p| p.kmz_file.clone())
+            .unwrap_or_default();
+        rsx! {
+            tr {
+                key: "storm-{id}",
+                td {"{name}"},
+                td {"{distance}"},
+                td {"{intensity}"},
+                td { a { href: "{advisory}", target: "_blank", "advisory" } },
+                td { a { href: "{track}", "track (kmz)" } },
+                td { a { href: "{cone}", "cone (kmz)" } },
+            }
+        }
+    });
+    rsx! {
+        head {
+            title: "Tropical Storms",
+            style {
+                {include_str!("../templates/style.css")}
+            }
+        },
+        body {
+            h3 {"{title}"},
+            div {
+                dangerous_inner_html: "{svg}",
+            },
+            table {
+                tbody {
+                    {rows}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::tropical::{get_nearby_storms, project, NhcStorm};
+
+    fn storm(name: &str, latitude: f64, longitude: f64) -> NhcStorm {
+        NhcStorm {
+            id: name.into(),
+            name: name.into(),
+            classification: "HU".into(),
+            intensity: "75".into(),
+            pressure: "985".into(),
+            latitude_numeric: latitude,
+            longitude_numeric: longitude,
+            movement_dir: Some(315.0),
+            movement_speed: Some(12.0),
+            last_update: datetime!(2024-08-15 21:00 UTC),
+            public_advisory: None,
+            forecast_track: None,
+            track_cone: None,
+        }
+    }
+
+    #[test]
+    fn test_get_nearby_storms() {
+        let storms = [
+            storm("far", 15.0, -40.0),
+            storm("near", 26.0, -78.0),
+            storm("nearer", 25.5, -79.5),
+        ];
+        // Miami
+        let nearby = get_nearby_storms(&storms, 25.76, -80.19, 1500.0);
+        assert_eq!(nearby.len(), 2);
+        assert_eq!(nearby[0].storm.name.as_str(), "nearer");
+        assert!(nearby[0].distance_km < nearby[1].distance_km);
+    }
+
+    #[test]
+    fn test_project() {
+        let (x, y) = project(25.0, -80.0, (25.0, -80.0), 1000.0);
+        assert!((x - 300.0).abs() < 1e-6);
+        assert!((y - 300.0).abs() < 1e-6);
+        // north is up
+        let (_, y) = project(26.0, -80.0, (25.0, -80.0), 1000.0);
+        assert!(y < 300.0);
+    }
+}
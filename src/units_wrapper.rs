@@ -0,0 +1,38 @@
+use derive_more::{Deref, Display, From, FromStr, Into};
+use rweb::openapi::{ComponentDescriptor, ComponentOrInlineSchema, Entity, Schema, Type};
+use serde::{Deserialize, Serialize};
+
+use weather_api_types::units::Units;
+
+#[derive(
+    Serialize,
+    Debug,
+    FromStr,
+    PartialEq,
+    Clone,
+    Copy,
+    Deref,
+    Into,
+    From,
+    Deserialize,
+    Hash,
+    Display,
+    Eq,
+    Default,
+)]
+pub struct UnitsWrapper(Units);
+
+impl Entity for UnitsWrapper {
+    fn type_name() -> std::borrow::Cow<'static, str> {
+        "units".into()
+    }
+
+    #[inline]
+    fn describe(_: &mut ComponentDescriptor) -> ComponentOrInlineSchema {
+        ComponentOrInlineSchema::Inline(Schema {
+            schema_type: Some(Type::String),
+            format: "standard|metric|imperial".into(),
+            ..Schema::default()
+        })
+    }
+}
@@ -0,0 +1,177 @@
+use once_cell::sync::OnceCell;
+use parking_lot::Mutex;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{sleep, timeout},
+};
+
+use crate::config::Config;
+
+static UPSTREAM_QUEUE: OnceCell<UpstreamQueue> = OnceCell::new();
+
+/// Returned when an upstream request couldn't be scheduled within
+/// `upstream_queue_timeout`
+#[derive(Error, Debug)]
+#[error("Upstream queue is full")]
+pub struct UpstreamBusy;
+
+/// Per minute budget of upstream requests (GCRA, same as the client rate
+/// limiter), the whole budget may be used in a burst
+struct Budget {
+    interval: Duration,
+    tolerance: Duration,
+    tat: Mutex<Instant>,
+}
+
+impl Budget {
+    fn new(calls_per_minute: u32, period: Duration) -> Self {
+        let interval = period / calls_per_minute;
+        Self {
+            interval,
+            tolerance: period - interval,
+            tat: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Reserve the next slot, returns the wait until the slot or `None` when
+    /// the wait would exceed `max_wait`
+    fn reserve(&self, now: Instant, max_wait: Duration) -> Option<Duration> {
+        let mut tat = self.tat.lock();
+        let start = (*tat).max(now);
+        let wait = (start - now).saturating_sub(self.tolerance);
+        if wait > max_wait {
+            return None;
+        }
+        *tat = start + self.interval;
+        Some(wait)
+    }
+}
+
+/// Held for the duration of an upstream request
+pub struct UpstreamPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Queue in front of provider and geocoding requests: at most
+/// `upstream_concurrency` requests run at once and at most
+/// `upstream_calls_per_minute` are started per minute, requests wait up to
+/// `upstream_queue_timeout` seconds for their turn
+pub struct UpstreamQueue {
+    semaphore: Option<Arc<Semaphore>>,
+    budget: Option<Budget>,
+    timeout: Duration,
+}
+
+impl UpstreamQueue {
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        Self::with_limits(
+            config.upstream_concurrency,
+            config.upstream_calls_per_minute,
+            Duration::from_secs(60),
+            Duration::from_secs(config.upstream_queue_timeout),
+        )
+    }
+
+    #[must_use]
+    pub fn with_limits(
+        concurrency: usize,
+        calls_per_period: u32,
+        period: Duration,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            semaphore: (concurrency > 0).then(|| Arc::new(Semaphore::new(concurrency))),
+            budget: (calls_per_period > 0).then(|| Budget::new(calls_per_period, period)),
+            timeout,
+        }
+    }
+
+    /// Wait for a free slot
+    ///
+    /// # Errors
+    /// Returns `UpstreamBusy` if no slot is available within the timeout
+    pub async fn acquire(&self) -> Result<UpstreamPermit, UpstreamBusy> {
+        let start = Instant::now();
+        let permit = match &self.semaphore {
+            Some(semaphore) => Some(
+                timeout(self.timeout, semaphore.clone().acquire_owned())
+                    .await
+                    .map_err(|_| UpstreamBusy)?
+                    .map_err(|_| UpstreamBusy)?,
+            ),
+            None => None,
+        };
+        if let Some(budget) = &self.budget {
+            let now = Instant::now();
+            let remaining = self.timeout.saturating_sub(now - start);
+            let wait = budget.reserve(now, remaining).ok_or(UpstreamBusy)?;
+            if !wait.is_zero() {
+                sleep(wait).await;
+            }
+        }
+        Ok(UpstreamPermit { _permit: permit })
+    }
+}
+
+/// Apply the upstream queue settings, called once at startup
+pub fn init_upstream_queue(config: &Config) {
+    let _ = UPSTREAM_QUEUE.set(UpstreamQueue::new(config));
+}
+
+/// Wait for a slot of the upstream queue, requests aren't limited before
+/// `init_upstream_queue` is called
+///
+/// # Errors
+/// Returns `UpstreamBusy` if no slot is available within the timeout
+pub async fn upstream_permit() -> Result<UpstreamPermit, UpstreamBusy> {
+    match UPSTREAM_QUEUE.get() {
+        Some(queue) => queue.acquire().await,
+        None => Ok(UpstreamPermit { _permit: None }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::upstream_queue::{Budget, UpstreamQueue};
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(budget.reserve(now, Duration::ZERO), Some(Duration::ZERO));
+        }
+        assert_eq!(budget.reserve(now, Duration::from_secs(10)), None);
+        assert_eq!(
+            budget.reserve(now, Duration::from_secs(30)),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(
+            budget.reserve(now, Duration::from_secs(60)),
+            Some(Duration::from_secs(40))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upstream_queue() {
+        let queue =
+            UpstreamQueue::with_limits(1, 0, Duration::from_secs(60), Duration::from_millis(10));
+        let permit = queue.acquire().await.unwrap();
+        assert!(queue.acquire().await.is_err());
+        drop(permit);
+        assert!(queue.acquire().await.is_ok());
+
+        let unlimited = UpstreamQueue::with_limits(0, 0, Duration::from_secs(60), Duration::ZERO);
+        let _permits: Vec<_> = futures::future::try_join_all((0..10).map(|_| unlimited.acquire()))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{format_err, Error};
+use log::{error, info};
+use reqwest::Client;
+use stack_string::format_sstr;
+use time::{Duration, OffsetDateTime};
+use tracing::instrument;
+use uuid::Uuid;
+
+use weather_util_rust::weather_api::WeatherLocation;
+
+use crate::{
+    config::Config,
+    model::{UvIndexData, WeatherLocationCache},
+    onecall::{fetch_onecall, OneCall, OneCallPart},
+    pgpool::PgPool,
+};
+
+/// openweathermap updates the current uv index about hourly, stored
+/// readings younger than this are reused
+pub const UV_INDEX_MAX_AGE: Duration = Duration::hours(1);
+
+/// WHO exposure category of a uv index
+#[must_use]
+pub fn get_uv_risk(uvi: f64) -> &'static str {
+    if uvi < 3.0 {
+        "Low"
+    } else if uvi < 6.0 {
+        "Moderate"
+    } else if uvi < 8.0 {
+        "High"
+    } else if uvi < 11.0 {
+        "Very High"
+    } else {
+        "Extreme"
+    }
+}
+
+fn onecall_uv_index(
+    onecall: &OneCall,
+    location_name: &str,
+    server: &str,
+) -> Result<UvIndexData, Error> {
+    let current = onecall
+        .current
+        .as_ref()
+        .ok_or_else(|| format_err!("One Call response without current conditions"))?;
+    Ok(UvIndexData {
+        id: Uuid::new_v4(),
+        location_name: location_name.into(),
+        server: server.into(),
+        recorded_at: current.dt.into(),
+        uvi: current.uvi,
+        timezone: onecall.timezone_offset,
+    })
+}
+
+/// Current uv index from the openweathermap One Call api
+///
+/// # Errors
+/// Return error if the api request fails or has no current conditions
+#[instrument(skip(client, config, api_key))]
+pub async fn fetch_uv_index(
+    client: &Client,
+    config: &Config,
+    api_key: &str,
+    location_name: &str,
+    latitude: f64,
+    longitude: f64,
+) -> Result<UvIndexData, Error> {
+    let exclude = [
+        OneCallPart::Minutely,
+        OneCallPart::Hourly,
+        OneCallPart::Daily,
+        OneCallPart::Alerts,
+    ];
+    let onecall = fetch_onecall(client, config, api_key, latitude, longitude, &exclude).await?;
+    onecall_uv_index(&onecall, location_name, &config.server)
+}
+
+/// Latest uv index of `location_name`, a stored reading younger than
+/// `UV_INDEX_MAX_AGE` or a fresh one, which is stored when the db is enabled
+///
+/// # Errors
+/// Return error if db query or api request fails
+pub async fn get_uv_index(
+    pool: &PgPool,
+    client: &Client,
+    config: &Config,
+    api_key: &str,
+    location_name: &str,
+    latitude: f64,
+    longitude: f64,
+) -> Result<UvIndexData, Error> {
+    if !pool.is_enabled() {
+        return fetch_uv_index(client, config, api_key, location_name, latitude, longitude).await;
+    }
+    if let Some(latest) = UvIndexData::get_latest(pool, location_name, &config.server).await? {
+        if *latest.recorded_at > OffsetDateTime::now_utc() - UV_INDEX_MAX_AGE {
+            return Ok(latest);
+        }
+    }
+    let uv_index =
+        fetch_uv_index(client, config, api_key, location_name, latitude, longitude).await?;
+    uv_index.insert(pool).await?;
+    Ok(uv_index)
+}
+
+/// Record the uv index of each location with a cached latitude / longitude,
+/// readings younger than `UV_INDEX_MAX_AGE` are not fetched again
+///
+/// # Errors
+/// Return error if db query fails
+pub async fn record_uv_index(
+    pool: &PgPool,
+    config: &Config,
+    client: &Client,
+    locations: &[WeatherLocation],
+) -> Result<(), Error> {
+    for loc in locations {
+        let Some(cache) = WeatherLocationCache::from_weather_location_cache(pool, loc).await?
+        else {
+            info!("no cached coordinates for {loc}, skipping uv index");
+            continue;
+        };
+        let location_name = format_sstr!("{loc}");
+        if let Err(e) = get_uv_index(
+            pool,
+            client,
+            config,
+            &config.api_key,
+            &location_name,
+            cache.latitude,
+            cache.longitude,
+        )
+        .await
+        {
+            error!("failed to record uv index for {loc} {e}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error;
+    use time::macros::datetime;
+
+    use crate::{
+        onecall::OneCall,
+        uv_index::{get_uv_risk, onecall_uv_index},
+    };
+
+    #[test]
+    fn test_onecall_uv_index() -> Result<(), Error> {
+        let data = r#"{
+            "lat": 44.98, "lon": -93.27, "timezone": "America/Chicago",
+            "timezone_offset": -18000,
+            "current": {"dt": 1717243200, "sunrise": 1717223045, "sunset": 1717278640,
+                "temp": 297.55, "feels_like": 297.87, "pressure": 1014, "humidity": 45,
+                "dew_point": 285.69, "uvi": 7.4, "clouds": 0, "visibility": 10000,
+                "wind_speed": 3.13, "wind_deg": 93,
+                "weather": [{"id": 800, "main": "Clear", "description": "clear sky",
+                    "icon": "01d"}]}
+        }"#;
+        let onecall: OneCall = serde_json::from_str(data)?;
+        let uv_index = onecall_uv_index(&onecall, "Minneapolis", "test")?;
+        assert_eq!(*uv_index.recorded_at, datetime!(2024-06-01 12:00 UTC));
+        assert!((uv_index.uvi - 7.4).abs() < 1e-9);
+        assert_eq!(uv_index.timezone, -18000);
+        assert_eq!(get_uv_risk(uv_index.uvi), "High");
+        assert_eq!(get_uv_risk(0.0), "Low");
+        assert_eq!(get_uv_risk(3.0), "Moderate");
+        assert_eq!(get_uv_risk(11.2), "Extreme");
+
+        let empty: OneCall = serde_json::from_str(
+            r#"{"lat": 44.98, "lon": -93.27, "timezone": "UTC", "timezone_offset": 0}"#,
+        )?;
+        assert!(onecall_uv_index(&empty, "Minneapolis", "test").is_err());
+        Ok(())
+    }
+}
@@ -39,9 +39,26 @@ fn main() -> Result<(), Error> {
             .block_on(async move {
                 while let Some(loc) = recv_loc.next().await {
                     debug!("get loc {loc:?}");
-                    let weather = api.get_weather_data(&loc).await.ok();
-                    let forecast = api.get_weather_forecast(&loc).await.ok();
-                    let entry = WeatherEntry { weather, forecast };
+                    let mut errors = Vec::new();
+                    let weather = match api.get_weather_data(&loc).await {
+                        Ok(weather) => Some(weather),
+                        Err(e) => {
+                            errors.push(format!("Failed to fetch weather for {loc}: {e}"));
+                            None
+                        }
+                    };
+                    let forecast = match api.get_weather_forecast(&loc).await {
+                        Ok(forecast) => Some(forecast),
+                        Err(e) => {
+                            errors.push(format!("Failed to fetch forecast for {loc}: {e}"));
+                            None
+                        }
+                    };
+                    let entry = WeatherEntry {
+                        weather,
+                        forecast,
+                        errors,
+                    };
                     send_result.send((loc, entry)).await.unwrap();
                 }
             });
@@ -1,4 +1,4 @@
-async function create_plot(url, title, xaxis, yaxis) {
+async function create_plot(url, title, xaxis, yaxis, markers_url) {
     let response = await fetch(url);
     let data = await response.json();
 
@@ -72,6 +72,29 @@ async function create_plot(url, title, xaxis, yaxis) {
     x.domain(d3.extent(data, function(d) {return d.datetime; }));
     y.domain([ymin, ymax]);
 
+    if (markers_url) {
+        let markers_response = await fetch(markers_url);
+        if (markers_response.ok) {
+            let markers = await markers_response.json();
+            markers.forEach(function(m) {
+                let start = Math.max(x(new Date(m.start_time)), 0);
+                let end = Math.min(x(new Date(m.end_time)), width);
+                if (end < start) {
+                    return;
+                }
+                svg.append("rect")
+                    .attr("x", start)
+                    .attr("y", 0)
+                    .attr("width", Math.max(end - start, 2))
+                    .attr("height", height)
+                    .attr("fill", "orange")
+                    .attr("opacity", 0.3)
+                    .append("title")
+                    .text(m.event_type + " " + m.start_time + " - " + m.end_time);
+            });
+        }
+    }
+
     svg.append("path").attr("class", "line").attr("d", valueline(data));
 
     svg.append("g")
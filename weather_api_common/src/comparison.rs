@@ -0,0 +1,121 @@
+use dioxus::prelude::{component, dioxus_elements, rsx, Element, GlobalSignal, IntoDynNode, Props};
+
+use crate::{
+    dto::{ComparisonReading, WeatherComparison},
+    units::Units,
+};
+
+/// Changes smaller than these are shown as steady (K, %, kPa)
+const TEMPERATURE_TOLERANCE: f64 = 0.5;
+const HUMIDITY_TOLERANCE: f64 = 2.0;
+const PRESSURE_TOLERANCE: f64 = 0.1;
+
+/// Arrow showing whether `current` went up, down or held steady compared to
+/// `previous`
+#[must_use]
+pub fn trend_arrow(current: f64, previous: f64, tolerance: f64) -> &'static str {
+    if current - previous > tolerance {
+        "↑"
+    } else if previous - current > tolerance {
+        "↓"
+    } else {
+        "→"
+    }
+}
+
+type Metric = (
+    String,
+    fn(&ComparisonReading) -> f64,
+    fn(Units, f64) -> f64,
+    f64,
+);
+
+/// (label, now, yesterday, arrow) rows of the comparison card, the arrows
+/// compare the recorded values so the tolerances don't depend on `units`
+fn comparison_rows(
+    comparison: &WeatherComparison,
+    units: Units,
+) -> Vec<(String, String, String, &'static str)> {
+    let temperature_unit = units.temperature_unit();
+    let metrics: [Metric; 3] = [
+        (
+            format!("Temperature ({temperature_unit})"),
+            |r| r.temperature,
+            Units::temperature,
+            TEMPERATURE_TOLERANCE,
+        ),
+        (
+            "Humidity (%)".into(),
+            |r| r.humidity.into(),
+            |_, x| x,
+            HUMIDITY_TOLERANCE,
+        ),
+        (
+            "Pressure (kPa)".into(),
+            |r| r.pressure,
+            |_, x| x,
+            PRESSURE_TOLERANCE,
+        ),
+    ];
+    metrics
+        .into_iter()
+        .map(|(label, value, convert, tolerance)| {
+            let display = |x: f64| format!("{:0.1}", convert(units, x));
+            let current = value(&comparison.current);
+            let (yesterday, arrow) = comparison.yesterday.as_ref().map_or_else(
+                || ("n/a".into(), ""),
+                |y| {
+                    let previous = value(y);
+                    (display(previous), trend_arrow(current, previous, tolerance))
+                },
+            );
+            (label, display(current), yesterday, arrow)
+        })
+        .collect()
+}
+
+/// Current temperature, humidity and pressure next to the same hour
+/// yesterday
+#[component]
+pub fn ComparisonComponent(comparison: WeatherComparison, units: Option<Units>) -> Element {
+    comparison_element(&comparison, units.unwrap_or(Units::Imperial))
+}
+
+pub fn comparison_element(comparison: &WeatherComparison, units: Units) -> Element {
+    let location_name = &comparison.location_name;
+    let recorded_at = &comparison.current.created_at;
+    let rows =
+        comparison_rows(comparison, units)
+            .into_iter()
+            .map(|(label, current, yesterday, arrow)| {
+                rsx! {
+                    tr {
+                        key: "comparison-{label}",
+                        td {"{label}"},
+                        td {"{current}"},
+                        td {"{yesterday}"},
+                        td {"{arrow}"},
+                    }
+                }
+            });
+    rsx! {
+        div {
+            class: "border border-gray-300 rounded-lg p-2 m-2 inline-block",
+            h3 {"{location_name}: today vs yesterday"},
+            table {
+                thead {
+                    tr {
+                        th {""},
+                        th {"Now"},
+                        th {"Yesterday"},
+                        th {""},
+                    }
+                },
+                tbody {
+                    {rows}
+                }
+            },
+            div { class: "text-sm", "recorded {recorded_at}" }
+        }
+    }
+}
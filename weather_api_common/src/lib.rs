@@ -1,8 +1,12 @@
 #![allow(clippy::pedantic)]
 #![allow(clippy::too_many_arguments)]
 
+pub mod comparison;
+pub mod notice;
 pub mod weather_element;
 
+pub use weather_api_types::{activity, dto, units};
+
 #[cfg(target_arch = "wasm32")]
 pub mod wasm_utils;
 
@@ -12,60 +16,21 @@ pub mod wasm_components;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod non_wasm_utils;
 
-use serde::{Deserialize, Serialize};
 use std::fmt;
 
-use weather_util_rust::{
-    weather_api::WeatherLocation, weather_data::WeatherData, weather_forecast::WeatherForecast,
+pub use weather_api_types::{
+    get_parameters, LocationCount, PaginatedLocationCount, Pagination, DEFAULT_HOST,
+    DEFAULT_LOCATION, DEFAULT_STR,
 };
 
+use weather_util_rust::{weather_data::WeatherData, weather_forecast::WeatherForecast};
+
 #[derive(Clone, Debug)]
 pub struct WeatherEntry {
     pub weather: Option<WeatherData>,
     pub forecast: Option<WeatherForecast>,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct LocationCount {
-    pub location: String,
-    pub count: i64,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
-pub struct Pagination {
-    pub limit: usize,
-    pub offset: usize,
-    pub total: usize,
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct PaginatedLocationCount {
-    pagination: Pagination,
-    data: Vec<LocationCount>,
-}
-
-pub static DEFAULT_STR: &str = "11106";
-pub static DEFAULT_HOST: &str = "cloud.ddboline.net";
-
-pub static DEFAULT_LOCATION: &str = "10001";
-
-pub fn get_parameters(search_str: &str) -> WeatherLocation {
-    let mut opts = WeatherLocation::from_city_name(search_str);
-    if let Ok(zip) = search_str.parse::<u64>() {
-        opts = WeatherLocation::from_zipcode(zip);
-    } else if search_str.contains(',') {
-        let mut iter = search_str.split(',');
-        if let Some(lat) = iter.next() {
-            if let Ok(lat) = lat.parse() {
-                if let Some(lon) = iter.next() {
-                    if let Ok(lon) = lon.parse() {
-                        opts = WeatherLocation::from_lat_lon(lat, lon);
-                    }
-                }
-            }
-        }
-    }
-    opts
+    /// Failures fetching `weather` or `forecast`, shown to the user
+    pub errors: Vec<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -73,6 +38,7 @@ pub enum WeatherPage {
     Index,
     Plot,
     HistoryPlot,
+    HistoryTable,
     Wasm,
 }
 
@@ -82,6 +48,7 @@ impl WeatherPage {
             Self::Index => "weather/index.html",
             Self::Plot => "weather/plot.html",
             Self::HistoryPlot => "weather/history_plot.html",
+            Self::HistoryTable => "weather/history",
             Self::Wasm => "wasm_weather/index.html",
         }
     }
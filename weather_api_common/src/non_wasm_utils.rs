@@ -1,16 +1,24 @@
+#[cfg(feature = "ip-lookup")]
 use anyhow::Error;
+#[cfg(feature = "ip-lookup")]
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "ip-lookup")]
 use std::net::Ipv4Addr;
+use std::time::Duration;
+#[cfg(feature = "ip-lookup")]
 use url::Url;
 
+#[cfg(feature = "ip-lookup")]
 use weather_util_rust::{latitude::Latitude, longitude::Longitude, weather_api::WeatherLocation};
 
+#[cfg(feature = "ip-lookup")]
 pub async fn get_ip_address() -> Result<Ipv4Addr, Error> {
     let url: Url = "https://ipinfo.io/ip".parse()?;
     let text = reqwest::get(url).await?.text().await?;
     text.trim().parse().map_err(Into::into)
 }
 
+#[cfg(feature = "ip-lookup")]
 pub async fn get_location_from_ip(ip: Ipv4Addr) -> Result<WeatherLocation, Error> {
     #[derive(Default, Serialize, Deserialize)]
     struct Location {
@@ -26,3 +34,7 @@ pub async fn get_location_from_ip(ip: Ipv4Addr) -> Result<WeatherLocation, Error
         location.longitude,
     ))
 }
+
+pub async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
@@ -0,0 +1,88 @@
+use dioxus::prelude::{
+    component, dioxus_elements, rsx, Element, EventHandler, GlobalSignal, IntoDynNode, Readable,
+    Signal, Writable,
+};
+
+use weather_util_rust::weather_api::WeatherLocation;
+
+/// What to refetch when the retry button of a notice is pressed
+#[derive(Debug, Clone, PartialEq)]
+pub enum NoticeRetry {
+    /// Current weather and forecast for a location
+    Weather(WeatherLocation),
+    /// Locations with recorded history
+    Locations,
+}
+
+/// Error or notice shown as a toast until it is dismissed, failures that are
+/// likely transient carry a retry action
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notice {
+    pub id: usize,
+    pub message: String,
+    pub retry: Option<NoticeRetry>,
+}
+
+/// Append a notice, identical messages are only shown once, the notices are
+/// peeked so that resources pushing failures don't subscribe to them
+pub fn push_notice(
+    mut notices: Signal<Vec<Notice>>,
+    message: impl Into<String>,
+    retry: Option<NoticeRetry>,
+) {
+    let message = message.into();
+    if notices.peek().iter().any(|n| n.message == message) {
+        return;
+    }
+    let id = notices.peek().iter().map(|n| n.id + 1).max().unwrap_or(0);
+    notices.write().push(Notice { id, message, retry });
+}
+
+/// Remove a notice, returns it when it was still shown
+pub fn dismiss_notice(mut notices: Signal<Vec<Notice>>, id: usize) -> Option<Notice> {
+    let index = notices.peek().iter().position(|n| n.id == id)?;
+    Some(notices.write().remove(index))
+}
+
+#[component]
+pub fn ToastComponent(
+    notices: Signal<Vec<Notice>>,
+    on_retry: EventHandler<NoticeRetry>,
+) -> Element {
+    rsx! {
+        div { class: "fixed bottom-0 right-0 m-4 z-50",
+            {notices.read().iter().map(|notice| {
+                let id = notice.id;
+                let message = notice.message.clone();
+                let retry = notice.retry.is_some();
+                rsx! {
+                    div {
+                        key: "notice-key-{id}",
+                        class: "bg-red-600 text-white rounded-lg shadow-sm px-4 py-2 mb-2",
+                        span { "{message} " },
+                        if retry {
+                            input {
+                                "type": "button",
+                                name: "retry",
+                                value: "Retry",
+                                onclick: move |_| {
+                                    if let Some(retry) = dismiss_notice(notices, id).and_then(|n| n.retry) {
+                                        on_retry.call(retry);
+                                    }
+                                },
+                            },
+                        }
+                        input {
+                            "type": "button",
+                            name: "dismiss",
+                            value: "Dismiss",
+                            onclick: move |_| {
+                                dismiss_notice(notices, id);
+                            },
+                        },
+                    }
+                }
+            })}
+        }
+    }
+}
@@ -1,7 +1,13 @@
-use dioxus::prelude::{component, use_resource, use_signal, Element, Readable, Writable};
-use js_sys::Date as JsDate;
-use log::debug;
-use std::collections::{HashMap, HashSet};
+use dioxus::prelude::{
+    component, dioxus_core, dioxus_elements, fc_to_builder, rsx, spawn, use_effect, use_resource,
+    use_signal, Element, GlobalSignal, IntoDynNode, Readable, Signal, Writable,
+};
+use js_sys::{encode_uri_component, Date as JsDate};
+use log::{debug, error};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
 use time::{Date, Duration, Month, PrimitiveDateTime, Time};
 use web_sys::window;
 
@@ -10,13 +16,19 @@ use weather_util_rust::weather_api::WeatherLocation;
 use crate::{get_parameters, WeatherEntry, WeatherPage, DEFAULT_HOST, DEFAULT_LOCATION};
 
 use crate::{
+    comparison::ComparisonComponent,
+    dto::{HistoryEntry, LoggedUser, PaginatedHistory},
+    notice::{push_notice, NoticeRetry, ToastComponent},
     wasm_utils::{
-        get_history, get_ip_address, get_location_from_ip, get_locations, get_weather_data_forecast,
+        get_comparison, get_history, get_history_page, get_ip_address, get_location_from_ip,
+        get_locations, get_precipitation_summary, get_refresh_parameter, get_user,
+        get_weather_data_forecast, login, logout,
     },
-    weather_element::index_element,
+    weather_element::{index_element, refresh_select_element, use_auto_refresh},
 };
 
 const DEFAULT_HISTORY_DAYS: i64 = 7;
+const HISTORY_PAGE_SIZE: usize = 50;
 
 #[component]
 pub fn IndexComponent() -> Element {
@@ -62,6 +74,10 @@ pub fn IndexComponent() -> Element {
         Some(date)
     });
     let mut cache = use_signal(|| default_cache);
+    let logged_user = use_signal(|| None);
+    let notices = use_signal(Vec::new);
+    let refresh = use_signal(get_refresh_parameter);
+    let refreshing = use_signal(|| None);
     let mut weather = use_signal(|| None);
     let mut forecast = use_signal(|| None);
 
@@ -97,25 +113,59 @@ pub fn IndexComponent() -> Element {
         None
     });
 
-    let _history_location_future = use_resource(move || async move {
+    let mut history_location_future = use_resource(move || async move {
         debug!("run history_location_future");
-        if let Ok(locations) = get_locations().await {
-            if history_location_cache.read().is_empty() {
-                let cache: HashSet<String> = locations
-                    .iter()
-                    .filter_map(|lc| {
-                        if lc.count > 100 {
-                            Some(lc.location.clone())
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                history_location_cache.set(cache);
+        match get_locations().await {
+            Ok(locations) => {
+                if history_location_cache.read().is_empty() {
+                    let cache: HashSet<String> = locations
+                        .iter()
+                        .filter_map(|lc| {
+                            if lc.count > 100 {
+                                Some(lc.location.clone())
+                            } else {
+                                None
+                            }
+                        })
+                        .collect();
+                    history_location_cache.set(cache);
+                }
+                Some(locations)
+            }
+            Err(e) => {
+                error!("locations failed {e:?}");
+                push_notice(
+                    notices,
+                    "Failed to fetch history locations",
+                    Some(NoticeRetry::Locations),
+                );
+                None
             }
-            return Some(locations);
         }
-        None
+    });
+
+    use_auto_refresh(refresh, location, cache, refreshing);
+
+    // not every history location has a recent entry, so failures aren't shown
+    let comparison_future = use_resource(move || async move {
+        let name = history_location();
+        debug!("run comparison_future {name}");
+        get_comparison(&name)
+            .await
+            .map_err(|e| debug!("comparison failed {e:?}"))
+            .ok()
+    });
+
+    let mut precipitation = use_signal(|| None);
+    let _precipitation_future = use_resource(move || async move {
+        let name = history_location();
+        debug!("run precipitation_future {name}");
+        let summary = get_precipitation_summary(&name)
+            .await
+            .map_err(|e| debug!("precipitation summary failed {e:?}"))
+            .ok()
+            .filter(|summary| summary.hours > 0);
+        precipitation.set(summary);
     });
 
     let _run_weather_future = use_resource(move || {
@@ -126,26 +176,36 @@ pub fn IndexComponent() -> Element {
             let entry = if let Some(entry) = entry_opt {
                 entry
             } else {
-                let entry = get_weather_data_forecast(&l).await;
+                let entry = get_weather_data_forecast(&l).await?;
+                for message in &entry.errors {
+                    push_notice(
+                        notices,
+                        message.as_str(),
+                        Some(NoticeRetry::Weather(l.clone())),
+                    );
+                }
                 let mut new_cache = (*cache.read()).clone();
                 cache.set({
-                    let l = (*location.read()).clone();
                     new_cache.insert(l.clone(), entry.clone());
                     new_cache
                 });
                 entry
             };
+            if l != *location.peek() {
+                debug!("discard stale result for {l}");
+                return None;
+            }
             if let Some(w) = &entry.weather {
                 weather.set(Some(w.clone()));
             }
             if let Some(f) = &entry.forecast {
                 forecast.set(Some(f.clone()));
             }
-            (l, entry)
+            Some((l, entry))
         }
     });
 
-    index_element(
+    let index = index_element(
         height,
         width,
         host,
@@ -159,7 +219,330 @@ pub fn IndexComponent() -> Element {
         location_future,
         weather,
         forecast,
+        precipitation,
         start_date,
         end_date,
-    )
+    );
+
+    rsx! {
+        LoginComponent { user: logged_user },
+        {refresh_select_element(refresh)},
+        ToastComponent {
+            notices,
+            on_retry: move |retry| match retry {
+                NoticeRetry::Weather(l) => {
+                    cache.write().remove(&l);
+                }
+                NoticeRetry::Locations => history_location_future.restart(),
+            },
+        },
+        {index},
+        if let Some(Some(comparison)) = comparison_future.read().clone() {
+            ComparisonComponent { comparison, units: None }
+        }
+        if page_type() == WeatherPage::HistoryTable {
+            if logged_user.read().is_some() {
+                HistoryTableComponent {
+                    name: history_location,
+                    start_date,
+                    end_date,
+                }
+            } else {
+                div { "Login required to browse the weather history" }
+            }
+        }
+    }
+}
+
+/// Logged in email with a logout button, or a login button redirecting to
+/// the auth service, `user` is `None` whenever there is no valid session.
+#[component]
+pub fn LoginComponent(mut user: Signal<Option<LoggedUser>>) -> Element {
+    let _user_future = use_resource(move || async move {
+        debug!("run user_future");
+        match get_user().await {
+            Ok(u) => user.set(Some(u)),
+            Err(e) => {
+                debug!("no session {e}");
+                user.set(None);
+            }
+        }
+    });
+
+    if let Some(u) = user() {
+        rsx! {
+            div {
+                span { "{u.email} " },
+                input {
+                    "type": "button",
+                    name: "logout",
+                    value: "Logout",
+                    onclick: move |_| {
+                        spawn(async move {
+                            if let Err(e) = logout().await {
+                                error!("logout failed {e:?}");
+                            }
+                            user.set(None);
+                        });
+                    },
+                },
+            }
+        }
+    } else {
+        rsx! {
+            div {
+                input {
+                    "type": "button",
+                    name: "login",
+                    value: "Login",
+                    onclick: move |_| {
+                        if let Err(e) = login() {
+                            error!("login failed {e:?}");
+                        }
+                    },
+                },
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HistoryColumn {
+    CreatedAt,
+    Condition,
+    Temperature,
+    Pressure,
+    Humidity,
+    Precipitation,
+    WindSpeed,
+}
+
+impl HistoryColumn {
+    const ALL: [Self; 7] = [
+        Self::CreatedAt,
+        Self::Condition,
+        Self::Temperature,
+        Self::Pressure,
+        Self::Humidity,
+        Self::Precipitation,
+        Self::WindSpeed,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::CreatedAt => "Time",
+            Self::Condition => "Condition",
+            Self::Temperature => "Temperature (F°)",
+            Self::Pressure => "Pressure (kPa)",
+            Self::Humidity => "Humidity (%)",
+            Self::Precipitation => "Precipitation (mm)",
+            Self::WindSpeed => "Wind (m/s)",
+        }
+    }
+
+    fn compare(self, a: &HistoryEntry, b: &HistoryEntry) -> Ordering {
+        let cmp_f64 = |x: f64, y: f64| x.partial_cmp(&y).unwrap_or(Ordering::Equal);
+        match self {
+            Self::CreatedAt => a.created_at.cmp(&b.created_at),
+            Self::Condition => a.condition.cmp(&b.condition),
+            Self::Temperature => cmp_f64(a.temperature, b.temperature),
+            Self::Pressure => cmp_f64(a.pressure, b.pressure),
+            Self::Humidity => a.humidity.cmp(&b.humidity),
+            Self::Precipitation => cmp_f64(precipitation(a), precipitation(b)),
+            Self::WindSpeed => cmp_f64(a.wind_speed, b.wind_speed),
+        }
+    }
+}
+
+fn precipitation(entry: &HistoryEntry) -> f64 {
+    entry.rain.unwrap_or(0.0) + entry.snow.unwrap_or(0.0)
+}
+
+fn kelvin_to_fahrenheit(kelvin: f64) -> f64 {
+    (kelvin - 273.15) * 9.0 / 5.0 + 32.0
+}
+
+fn history_csv(entries: &[HistoryEntry]) -> String {
+    let mut csv = String::from(
+        "id,created_at,location_name,server,condition,temperature,temperature_minimum,\
+         temperature_maximum,pressure,humidity,rain,snow,wind_speed,wind_direction\n",
+    );
+    for e in entries {
+        let optional = |x: Option<f64>| x.map_or(String::new(), |x| format!("{x}"));
+        csv.push_str(&format!(
+            "{},{},\"{}\",{},\"{}\",{},{},{},{},{},{},{},{},{}\n",
+            e.id,
+            e.created_at,
+            e.location_name.replace('"', "\"\""),
+            e.server,
+            e.condition.trim().replace('"', "\"\""),
+            e.temperature,
+            e.temperature_minimum,
+            e.temperature_maximum,
+            e.pressure,
+            e.humidity,
+            optional(e.rain),
+            optional(e.snow),
+            e.wind_speed,
+            optional(e.wind_direction),
+        ));
+    }
+    csv
+}
+
+/// Raw weather history rows for a location, fetched a page at a time from
+/// `/weather/history` using its keyset cursor.
+#[component]
+pub fn HistoryTableComponent(
+    name: Signal<String>,
+    start_date: Signal<Option<Date>>,
+    end_date: Signal<Option<Date>>,
+) -> Element {
+    let mut page_cursor = use_signal(|| None::<String>);
+    let mut previous_cursors = use_signal(Vec::<Option<String>>::new);
+    let mut sort_column = use_signal(|| HistoryColumn::CreatedAt);
+    let mut sort_ascending = use_signal(|| true);
+
+    use_effect(move || {
+        let _ = (name(), start_date(), end_date());
+        page_cursor.set(None);
+        previous_cursors.set(Vec::new());
+    });
+
+    let history_page = use_resource(move || {
+        let name = name();
+        let start_date = start_date();
+        let end_date = end_date();
+        let cursor = page_cursor();
+        debug!("run history_page {name} {cursor:?}");
+        async move {
+            get_history_page(
+                &name,
+                start_date,
+                end_date,
+                cursor.as_deref(),
+                HISTORY_PAGE_SIZE,
+            )
+            .await
+        }
+    });
+
+    let page: Option<Result<PaginatedHistory, String>> = history_page
+        .read()
+        .as_ref()
+        .map(|r| r.as_ref().map(Clone::clone).map_err(|e| format!("{e}")));
+
+    let history = match page {
+        None => return rsx! { div { "Loading..." } },
+        Some(Err(e)) => {
+            debug!("history page failed {e}");
+            return rsx! { div { "Login required to browse the weather history" } };
+        }
+        Some(Ok(history)) => history,
+    };
+
+    let column = sort_column();
+    let ascending = sort_ascending();
+    let mut entries = history.data;
+    entries.sort_by(|a, b| {
+        let ordering = column.compare(a, b);
+        if ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+    let csv_href = format!(
+        "data:text/csv;charset=utf-8,{}",
+        encode_uri_component(&history_csv(&entries))
+    );
+    let csv_name = format!("weather_history_{}.csv", name());
+    let next_cursor = history.next_cursor;
+    let has_next = next_cursor.is_some();
+    let has_previous = !previous_cursors.read().is_empty();
+    let total = history.pagination.total;
+
+    rsx! {
+        div {
+            input {
+                "type": "button",
+                name: "previous",
+                value: "Previous",
+                disabled: !has_previous,
+                onclick: move |_| {
+                    let cursor = previous_cursors.write().pop();
+                    if let Some(cursor) = cursor {
+                        page_cursor.set(cursor);
+                    }
+                },
+            },
+            input {
+                "type": "button",
+                name: "next",
+                value: "Next",
+                disabled: !has_next,
+                onclick: move |_| {
+                    if let Some(cursor) = next_cursor.clone() {
+                        let current = page_cursor();
+                        previous_cursors.write().push(current);
+                        page_cursor.set(Some(cursor));
+                    }
+                },
+            },
+            a {
+                href: "{csv_href}",
+                download: "{csv_name}",
+                "Download CSV",
+            },
+            span { " {total} entries" },
+        },
+        table {
+            thead {
+                tr {
+                    {HistoryColumn::ALL.iter().map(|c| {
+                        let c = *c;
+                        let arrow = if c != column {
+                            ""
+                        } else if ascending {
+                            " ▲"
+                        } else {
+                            " ▼"
+                        };
+                        rsx! {
+                            th {
+                                key: "history-column-{c:?}",
+                                onclick: move |_| {
+                                    if sort_column() == c {
+                                        sort_ascending.set(!sort_ascending());
+                                    } else {
+                                        sort_column.set(c);
+                                        sort_ascending.set(true);
+                                    }
+                                },
+                                "{c.label()}{arrow}"
+                            }
+                        }
+                    })}
+                }
+            },
+            tbody {
+                {entries.iter().map(|e| {
+                    let temperature = kelvin_to_fahrenheit(e.temperature);
+                    let precip = precipitation(e);
+                    rsx! {
+                        tr {
+                            key: "history-row-{e.id}",
+                            td { "{e.created_at}" },
+                            td { "{e.condition}" },
+                            td { "{temperature:0.1}" },
+                            td { "{e.pressure:0.1}" },
+                            td { "{e.humidity}" },
+                            td { "{precip:0.1}" },
+                            td { "{e.wind_speed:0.1}" },
+                        }
+                    }
+                })}
+            }
+        }
+    }
 }
@@ -1,13 +1,13 @@
 use anyhow::{format_err, Error};
 use http::Method;
-use log::error;
+use log::{debug, error};
 use serde::{Deserialize, Serialize};
-use std::net::Ipv4Addr;
+use std::{cell::RefCell, net::Ipv4Addr, time::Duration};
 use time::Date;
 use url::Url;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::JsFuture;
-use web_sys::{window, RequestInit, Response};
+use web_sys::{window, AbortController, AbortSignal, RequestCredentials, RequestInit, Response};
 
 use weather_util_rust::{
     format_string, latitude::Latitude, longitude::Longitude, weather_api::WeatherLocation,
@@ -15,17 +15,39 @@ use weather_util_rust::{
 };
 
 use crate::{
-    weather_element::PlotData, LocationCount, PaginatedLocationCount, WeatherEntry, DEFAULT_HOST,
+    dto::{
+        LocationCount, LoggedUser, PaginatedHistory, PaginatedLocationCount, PrecipitationSummary,
+        WeatherComparison,
+    },
+    weather_element::PlotData,
+    WeatherEntry, DEFAULT_HOST,
 };
 
+thread_local! {
+    /// Controller of the in flight weather / forecast fetch, aborted when a
+    /// newer location is requested
+    static WEATHER_ABORT: RefCell<Option<AbortController>> = const { RefCell::new(None) };
+}
+
 enum FetchOutput {
     Text,
     Json,
 }
 
 async fn fetch(url: &Url, method: Method, return_type: FetchOutput) -> Result<JsValue, JsValue> {
+    fetch_abortable(url, method, return_type, None).await
+}
+
+async fn fetch_abortable(
+    url: &Url,
+    method: Method,
+    return_type: FetchOutput,
+    signal: Option<&AbortSignal>,
+) -> Result<JsValue, JsValue> {
     let opts = RequestInit::new();
     opts.set_method(method.as_str());
+    opts.set_credentials(RequestCredentials::Include);
+    opts.set_signal(signal);
 
     let window = window().ok_or_else(|| JsValue::from_str("No window"))?;
     let resp = JsFuture::from(window.fetch_with_str_and_init(url.as_str(), &opts)).await?;
@@ -39,6 +61,14 @@ async fn fetch(url: &Url, method: Method, return_type: FetchOutput) -> Result<Js
 async fn run_api<T: serde::de::DeserializeOwned>(
     command: &str,
     options: &[(&'static str, ApiStringType)],
+) -> Result<T, Error> {
+    run_api_abortable(command, options, None).await
+}
+
+async fn run_api_abortable<T: serde::de::DeserializeOwned>(
+    command: &str,
+    options: &[(&'static str, ApiStringType)],
+    signal: Option<&AbortSignal>,
 ) -> Result<T, Error> {
     let window = window().expect("window now found");
     let location = window.location();
@@ -51,7 +81,7 @@ async fn run_api<T: serde::de::DeserializeOwned>(
         format!("https://{host}/weather/{command}")
     };
     let url = Url::parse_with_params(&base_url, options)?;
-    let json = fetch(&url, Method::GET, FetchOutput::Json)
+    let json = fetch_abortable(&url, Method::GET, FetchOutput::Json, signal)
         .await
         .map_err(|e| format_err!("{:?}", e))?;
     serde_wasm_bindgen::from_value(json).map_err(|e| format_err!("{:?}", e))
@@ -120,10 +150,43 @@ pub async fn get_location_from_ip(ip: Ipv4Addr) -> Result<WeatherLocation, JsVal
     ))
 }
 
-pub async fn get_weather_data_forecast(location: &WeatherLocation) -> WeatherEntry {
-    let weather = get_weather_data(location).await.ok();
-    let forecast = get_weather_forecast(location).await.ok();
-    WeatherEntry { weather, forecast }
+/// Weather and forecast for `location`, aborting the fetch of any previously
+/// requested location, returns `None` when superseded by a newer request
+pub async fn get_weather_data_forecast(location: &WeatherLocation) -> Option<WeatherEntry> {
+    let controller = AbortController::new().ok();
+    let signal = controller.as_ref().map(AbortController::signal);
+    WEATHER_ABORT.with(|current| {
+        if let Some(previous) = current.replace(controller) {
+            previous.abort();
+        }
+    });
+    let options = location.get_options();
+    let mut errors = Vec::new();
+    let weather = match run_api_abortable("weather", &options, signal.as_ref()).await {
+        Ok(weather) => Some(weather),
+        Err(e) => {
+            error!("weather failed {e}");
+            errors.push(format!("Failed to fetch weather for {location}"));
+            None
+        }
+    };
+    let forecast = match run_api_abortable("forecast", &options, signal.as_ref()).await {
+        Ok(forecast) => Some(forecast),
+        Err(e) => {
+            error!("forecast failed {e}");
+            errors.push(format!("Failed to fetch forecast for {location}"));
+            None
+        }
+    };
+    if signal.as_ref().is_some_and(AbortSignal::aborted) {
+        debug!("superseded fetch for {location}");
+        return None;
+    }
+    Some(WeatherEntry {
+        weather,
+        forecast,
+        errors,
+    })
 }
 
 pub async fn get_weather_data(loc: &WeatherLocation) -> Result<WeatherData, Error> {
@@ -160,6 +223,82 @@ pub async fn get_history_plots(
     run_api("history-plots", &options).await
 }
 
+pub async fn get_history_page(
+    name: &str,
+    start_time: Option<Date>,
+    end_time: Option<Date>,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<PaginatedHistory, Error> {
+    let mut options = vec![("name", name.into()), ("limit", format_string!("{limit}"))];
+    if let Some(start_time) = start_time {
+        options.push(("start_time", format_string!("{start_time}")))
+    };
+    if let Some(end_time) = end_time {
+        options.push(("end_time", format_string!("{end_time}")))
+    };
+    if let Some(cursor) = cursor {
+        options.push(("cursor", cursor.into()))
+    };
+    run_api("history", &options).await
+}
+
+/// Latest recorded conditions for `name` next to the same hour yesterday
+pub async fn get_comparison(name: &str) -> Result<WeatherComparison, Error> {
+    run_api("compare/yesterday", &[("name", name.into())]).await
+}
+
+/// Recorded precipitation today and this week for `name`
+pub async fn get_precipitation_summary(name: &str) -> Result<PrecipitationSummary, Error> {
+    run_api("history/today-summary", &[("name", name.into())]).await
+}
+
+fn get_base_url() -> Result<String, JsValue> {
+    let window = window().ok_or_else(|| JsValue::from_str("No window"))?;
+    let location = window.location();
+    let host = location.host()?;
+    let protocol = location.protocol()?;
+    if protocol != "https:" {
+        Ok(format!("https://{DEFAULT_HOST}"))
+    } else {
+        Ok(format!("https://{host}"))
+    }
+}
+
+/// The logged in user, the server answers with the login page rather than
+/// json when there is no valid session so any failure means logged out.
+pub async fn get_user() -> Result<LoggedUser, Error> {
+    run_api("user", &[]).await
+}
+
+/// Send the browser to the auth service login page, returning here afterwards
+pub fn login() -> Result<(), JsValue> {
+    let window = window().ok_or_else(|| JsValue::from_str("No window"))?;
+    let final_url = window.location().href()?;
+    let base_url = get_base_url()?;
+    let url = Url::parse_with_params(
+        &format!("{base_url}/auth/login.html"),
+        &[("final_url", final_url)],
+    )
+    .map_err(|e| {
+        let e: JsValue = format!("{e}").into();
+        e
+    })?;
+    window.location().set_href(url.as_str())
+}
+
+/// End the session with the auth service, clearing the session cookies
+pub async fn logout() -> Result<(), JsValue> {
+    let base_url = get_base_url()?;
+    let url: Url = format!("{base_url}/api/auth").parse().map_err(|e| {
+        error!("error {e}");
+        let e: JsValue = format!("{e}").into();
+        e
+    })?;
+    fetch(&url, Method::DELETE, FetchOutput::Text).await?;
+    Ok(())
+}
+
 pub fn set_history(history: &[String]) -> Result<(), JsValue> {
     let window = window().ok_or_else(|| JsValue::from_str("No window"))?;
     let local_storage = window
@@ -215,3 +354,30 @@ pub async fn get_locations() -> Result<Vec<LocationCount>, JsValue> {
         counts.append(&mut response.data);
     }
 }
+
+/// Resolve after `duration`, using `setTimeout`
+pub async fn sleep(duration: Duration) {
+    let millis = duration.as_millis().try_into().unwrap_or(i32::MAX);
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        if let Some(window) = window() {
+            if let Err(e) =
+                window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+            {
+                error!("setTimeout failed {e:?}");
+            }
+        }
+    });
+    if let Err(e) = JsFuture::from(promise).await {
+        error!("sleep failed {e:?}");
+    }
+}
+
+/// Auto-refresh interval (seconds) from the `refresh` query parameter of the
+/// page url
+pub fn get_refresh_parameter() -> Option<u64> {
+    let href = window()?.location().href().ok()?;
+    let url = Url::parse(&href).ok()?;
+    url.query_pairs()
+        .find(|(key, _)| key == "refresh")
+        .and_then(|(_, value)| value.parse().ok())
+}
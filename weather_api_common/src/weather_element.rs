@@ -2,10 +2,10 @@ use dioxus::prelude::{
     component, dioxus_elements, rsx, use_resource, use_signal, Element, GlobalSignal, IntoDynNode,
     Key, Props, Readable, Resource, Signal, Writable,
 };
-use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fmt::Write,
+    time::Duration,
 };
 use time::{
     format_description::FormatItem, macros::format_description, Date, OffsetDateTime, UtcOffset,
@@ -26,15 +26,26 @@ use std::sync::Arc;
 
 #[cfg(target_arch = "wasm32")]
 use crate::wasm_utils::{
-    get_ip_address, get_location_from_ip, get_weather_data_forecast, set_history,
+    get_ip_address, get_location_from_ip, get_refresh_parameter, get_weather_data_forecast,
+    set_history, sleep,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::non_wasm_utils::sleep;
+
 use weather_util_rust::{
     format_string, weather_api::WeatherLocation, weather_data::WeatherData,
     weather_forecast::WeatherForecast,
 };
 
-use crate::{get_parameters, WeatherEntry, WeatherPage, DEFAULT_LOCATION, DEFAULT_STR};
+use crate::{
+    activity::{get_activity_scores, get_forecast_conditions},
+    dto::{DaylightSummary, MoonSummary, PrecipitationSummary},
+    get_parameters,
+    notice::{push_notice, NoticeRetry, ToastComponent},
+    units::Units,
+    WeatherEntry, WeatherPage, DEFAULT_LOCATION, DEFAULT_STR,
+};
 
 #[cfg(debug_assertions)]
 use crate::DEFAULT_HOST;
@@ -47,18 +58,10 @@ static BASE_HOST: Option<&str> = None;
 
 static DATE_FORMAT: &[FormatItem<'static>] = format_description!("[year]-[month]-[day]");
 
-#[derive(PartialEq, Deserialize, Serialize, Debug, Clone, Copy)]
-pub struct PlotPoint {
-    pub datetime: OffsetDateTime,
-    pub value: f64,
-}
-#[derive(PartialEq, Deserialize, Serialize, Debug, Clone)]
-pub struct PlotData {
-    pub plot_url: String,
-    pub title: String,
-    pub xaxis: String,
-    pub yaxis: String,
-}
+/// Auto-refresh intervals offered by the apps (seconds)
+const REFRESH_INTERVALS: [u64; 5] = [60, 300, 900, 1800, 3600];
+
+pub use weather_api_types::plot::{PlotData, PlotPoint};
 
 fn update_search_history(sh: &Vec<String>, s: &str) -> Vec<String> {
     let mut v: Vec<String> = Vec::with_capacity(sh.len());
@@ -77,12 +80,75 @@ fn update_search_history(sh: &Vec<String>, s: &str) -> Vec<String> {
 }
 
 #[component]
-pub fn WeatherComponent(weather: WeatherData, forecast: WeatherForecast) -> Element {
-    weather_element(&weather, &forecast)
+pub fn WeatherComponent(
+    weather: WeatherData,
+    forecast: Option<WeatherForecast>,
+    snapshot_url: Option<String>,
+    units: Option<Units>,
+    refresh: Option<u64>,
+    precipitation: Option<PrecipitationSummary>,
+    moon: Option<MoonSummary>,
+    daylight: Option<DaylightSummary>,
+    warnings: Vec<String>,
+) -> Element {
+    weather_element(
+        &weather,
+        forecast.as_ref(),
+        snapshot_url.as_deref(),
+        units,
+        refresh,
+        precipitation.as_ref(),
+        moon.as_ref(),
+        daylight.as_ref(),
+        &warnings,
+    )
 }
 
-pub fn weather_element(weather: &WeatherData, forecast: &WeatherForecast) -> Element {
-    let weather_data = weather.get_current_conditions();
+/// Banner listing the parts of the page that couldn't be loaded
+fn warning_element(warnings: &[String]) -> Element {
+    rsx! {
+        if !warnings.is_empty() {
+            div {
+                style: "background-color: #fff3cd; border: 1px solid #e0b252; padding: 4px; margin: 4px 0px; font-size: 14px;",
+                {warnings.iter().enumerate().map(|(i, warning)| {
+                    rsx! {
+                        div {
+                            key: "warning-{i}",
+                            "{warning}"
+                        }
+                    }
+                })}
+            }
+        }
+    }
+}
+
+/// Meta refresh reloading the page every `refresh` seconds, for displays left
+/// showing the server rendered pages
+fn refresh_meta_element(refresh: Option<u64>) -> Element {
+    rsx! {
+        if let Some(refresh) = refresh {
+            meta { "http-equiv": "refresh", content: "{refresh}" }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn weather_element(
+    weather: &WeatherData,
+    forecast: Option<&WeatherForecast>,
+    snapshot_url: Option<&str>,
+    units: Option<Units>,
+    refresh: Option<u64>,
+    precipitation: Option<&PrecipitationSummary>,
+    moon: Option<&MoonSummary>,
+    daylight: Option<&DaylightSummary>,
+    warnings: &[String],
+) -> Element {
+    let weather_data = units.map_or_else(
+        || weather.get_current_conditions(),
+        |units| units.current_conditions(weather).into(),
+    );
     let weather_lines: Vec<_> = weather_data.split('\n').map(str::trim_end).collect();
     let weather_cols = weather_lines.iter().map(|x| x.len()).max().unwrap_or(0) + 2;
     let weather_rows = weather_lines.len() + 2;
@@ -106,6 +172,8 @@ pub fn weather_element(weather: &WeatherData, forecast: &WeatherForecast) -> Ele
                 target: "_blank",
                 "{title}",
             }
+            {moon.map(moon_element)},
+            {daylight.map(daylight_element)},
         }
     };
 
@@ -118,8 +186,17 @@ pub fn weather_element(weather: &WeatherData, forecast: &WeatherForecast) -> Ele
         },
     };
 
-    let forecast_element = {
-        let weather_forecast = forecast.get_forecast();
+    let forecast_element = forecast.map(|forecast| {
+        let weather_forecast: Vec<_> = units.map_or_else(
+            || {
+                forecast
+                    .get_forecast()
+                    .into_iter()
+                    .map(Into::into)
+                    .collect()
+            },
+            |units| units.forecast(forecast),
+        );
         let forecast_lines: Vec<_> = weather_forecast.iter().map(|s| s.trim_end()).collect();
         let forecast_cols = forecast_lines.iter().map(|x| x.len()).max().unwrap_or(0) + 10;
         let forecast_rows = forecast_lines.len() + 2;
@@ -133,27 +210,156 @@ pub fn weather_element(weather: &WeatherData, forecast: &WeatherForecast) -> Ele
                 "{forecast_lines}"
             }
         }
-    };
+    });
+
+    let snapshot_element = snapshot_url.map(|snapshot_url| {
+        rsx! {
+            a {
+                href: "{snapshot_url}",
+                target: "_blank",
+                img {
+                    src: "{snapshot_url}",
+                    alt: "Latest Snapshot",
+                    style: "max-height: 240px; vertical-align: top;",
+                }
+            }
+        }
+    });
 
     rsx! {
         head {
             title: "Weather Plots",
+            {refresh_meta_element(refresh)},
             style {
                 {include_str!("../../templates/style.css")}
             }
         },
         body {
             {location_element},
+            {warning_element(warnings)},
             div {
                 {weather_element},
                 {forecast_element},
+                {snapshot_element},
             },
+            {precipitation.map(|p| precipitation_element(p, units.unwrap_or_default()))},
+            {forecast.map(|forecast| activity_element(forecast, weather.dt))},
+        }
+    }
+}
+
+/// Emoji of the phase, 0 new moon, 0.5 full moon
+fn moon_icon(phase: f64) -> &'static str {
+    const ICONS: [&str; 8] = [
+        "\u{1f311}",
+        "\u{1f312}",
+        "\u{1f313}",
+        "\u{1f314}",
+        "\u{1f315}",
+        "\u{1f316}",
+        "\u{1f317}",
+        "\u{1f318}",
+    ];
+    ICONS[((phase * 8.0).round() as usize) % 8]
+}
+
+/// Moon phase icon, the details are shown on hover
+fn moon_element(moon: &MoonSummary) -> Element {
+    let icon = moon_icon(moon.phase);
+    let mut title = format!("{} {:0.0}% illuminated", moon.phase_name, moon.illumination);
+    if let Some(moonrise) = &moon.moonrise {
+        write!(&mut title, ", rises {moonrise}").unwrap();
+    }
+    if let Some(moonset) = &moon.moonset {
+        write!(&mut title, ", sets {moonset}").unwrap();
+    }
+    rsx! {
+        span {
+            title: "{title}",
+            style: "margin-left: 8px;",
+            "{icon}"
+        }
+    }
+}
+
+/// Daylight duration, sunrise / sunset and civil twilight are shown on hover
+fn daylight_element(daylight: &DaylightSummary) -> Element {
+    let hours = daylight.daylight / 3600;
+    let minutes = (daylight.daylight % 3600) / 60;
+    let mut title = format!("{hours}h {minutes}m of daylight");
+    if let (Some(sunrise), Some(sunset)) = (&daylight.sunrise, &daylight.sunset) {
+        write!(&mut title, ", sunrise {sunrise}, sunset {sunset}").unwrap();
+    }
+    if let (Some(dawn), Some(dusk)) = (&daylight.civil_dawn, &daylight.civil_dusk) {
+        write!(&mut title, ", civil twilight {dawn} - {dusk}").unwrap();
+    }
+    rsx! {
+        span {
+            title: "{title}",
+            style: "margin-left: 8px;",
+            "\u{2600}\u{fe0f} {hours}h {minutes}m"
+        }
+    }
+}
+
+/// Rain (and snow when there was any) recorded so far today and this week
+fn precipitation_element(summary: &PrecipitationSummary, units: Units) -> Element {
+    let unit = units.precipitation_unit();
+    let mut text = format!(
+        "Rain so far today {:0.2} {unit}, this week {:0.2} {unit}",
+        units.precipitation(summary.today_rain),
+        units.precipitation(summary.week_rain),
+    );
+    if summary.week_snow > 0.0 {
+        write!(
+            &mut text,
+            "; Snow so far today {:0.2} {unit}, this week {:0.2} {unit}",
+            units.precipitation(summary.today_snow),
+            units.precipitation(summary.week_snow),
+        )
+        .unwrap();
+    }
+    rsx! {
+        div {
+            style: "font-size: 14px; margin-top: 4px;",
+            "{text}"
+        }
+    }
+}
+
+/// Activity scores of the 48 hours from the current observation at `now`
+fn activity_element(forecast: &WeatherForecast, now: OffsetDateTime) -> Element {
+    static TIME_FORMAT: &[FormatItem<'static>] =
+        format_description!("[weekday repr:short] [hour]:[minute]");
+    let conditions = get_forecast_conditions(forecast);
+    let scores = get_activity_scores(&conditions, forecast.city.timezone.into(), now, 48);
+    rsx! {
+        div {
+            style: "display: flex; height: 16px; margin-top: 4px;",
+            title: "Activity Score",
+            {scores.iter().map(|p| {
+                let hue = (p.value * 1.2) as i64;
+                let label = p.datetime.format(TIME_FORMAT).unwrap_or_default();
+                let value = p.value as i64;
+                rsx! {
+                    div {
+                        key: "activity-{label}",
+                        style: "flex: 1; background-color: hsl({hue}, 70%, 50%);",
+                        title: "{label} {value}",
+                    }
+                }
+            })}
         }
     }
 }
 
 #[component]
-pub fn ForecastComponent(weather: WeatherData, plots: Vec<PlotData>) -> Element {
+pub fn ForecastComponent(
+    weather: WeatherData,
+    plots: Vec<PlotData>,
+    refresh: Option<u64>,
+    warnings: Vec<String>,
+) -> Element {
     let name = &weather.name;
     let lat = weather.coord.lat;
     let lon = weather.coord.lon;
@@ -178,12 +384,14 @@ pub fn ForecastComponent(weather: WeatherData, plots: Vec<PlotData>) -> Element
     rsx! {
         head {
             title: "Weather Plots",
+            {refresh_meta_element(refresh)},
             style {
                 {include_str!("../../templates/style.css")}
             }
         },
         body {
             {location_element},
+            {warning_element(&warnings)},
             {plot_element(&plots)},
         }
     }
@@ -202,9 +410,13 @@ fn plot_element(plots: &[PlotData]) -> Element {
         let title = &pd.title;
         let xaxis = &pd.xaxis;
         let yaxis = &pd.yaxis;
+        let markers_url = pd
+            .markers_url
+            .as_ref()
+            .map_or_else(|| "null".into(), |u| format!("'{u}'"));
         writeln!(
             &mut script_body,
-            "\t await create_plot('{plot_url}', '{title}', '{xaxis}', '{yaxis}');"
+            "\t await create_plot('{plot_url}', '{title}', '{xaxis}', '{yaxis}', {markers_url});"
         )
         .unwrap();
     }
@@ -225,6 +437,8 @@ fn plot_element(plots: &[PlotData]) -> Element {
 }
 
 fn weather_app_element(
+    loading: bool,
+    refresh: Signal<Option<u64>>,
     mut draft: Signal<String>,
     mut location_cache: Signal<HashMap<String, WeatherLocation>>,
     cache: Signal<HashMap<WeatherLocation, WeatherEntry>>,
@@ -233,9 +447,20 @@ fn weather_app_element(
     mut forecast: Signal<WeatherForecast>,
     mut search_history: Signal<Vec<String>>,
 ) -> Element {
-    let country_info_element = country_info(&weather.read());
-    let country_data_element = country_data(&weather.read());
-    let week_weather_element = week_weather(&forecast.read());
+    let weather_card = if loading {
+        skeleton_element()
+    } else {
+        let country_info_element = country_info(&weather.read());
+        let country_data_element = country_data(&weather.read());
+        let week_weather_element = week_weather(&forecast.read());
+        rsx! {
+            div { class: "px-6 py-6 relative",
+                {country_info_element},
+                {country_data_element},
+            }
+            {week_weather_element},
+        }
+    };
 
     rsx! {
         link { rel: "stylesheet", href: "https://unpkg.com/tailwindcss@^2.0/dist/tailwind.min.css" },
@@ -367,14 +592,11 @@ fn weather_app_element(
                             })
                         }
                     }
+                    {refresh_select_element(refresh)},
                 }
                 div { class: "flex flex-wrap w-full px-2",
                     div { class: "bg-gray-900 text-white relative min-w-0 break-words rounded-lg overflow-hidden shadow-sm mb-4 w-full bg-white dark:bg-gray-600",
-                        div { class: "px-6 py-6 relative",
-                            {country_info_element},
-                            {country_data_element},
-                        }
-                        {week_weather_element},
+                        {weather_card},
                     }
                 }
             }
@@ -382,6 +604,96 @@ fn weather_app_element(
     }
 }
 
+/// Evict the selected location from `cache` every `refresh` seconds so that
+/// its weather and forecast are fetched again, `refreshing` marks the location
+/// whose stale data stays displayed until the fetch completes
+pub fn use_auto_refresh(
+    refresh: Signal<Option<u64>>,
+    location: Signal<WeatherLocation>,
+    mut cache: Signal<HashMap<WeatherLocation, WeatherEntry>>,
+    mut refreshing: Signal<Option<WeatherLocation>>,
+) {
+    let _refresh_future = use_resource(move || async move {
+        let seconds = match refresh() {
+            Some(seconds) => seconds,
+            None => return,
+        };
+        loop {
+            sleep(Duration::from_secs(seconds)).await;
+            let l = location.peek().clone();
+            refreshing.set(Some(l.clone()));
+            cache.write().remove(&l);
+        }
+    });
+}
+
+pub fn refresh_select_element(mut refresh: Signal<Option<u64>>) -> Element {
+    let current = refresh();
+    rsx! {
+        select { class: "bg-white border border-gray-100 w-full mt-2",
+            id: "refresh-selector",
+            onchange: move |x| {
+                let s = x.map(|data| data.value()).data().to_string();
+                refresh.set(s.parse().ok());
+            },
+            option { value: "off", selected: current.is_none(), "Auto-refresh off" },
+            {REFRESH_INTERVALS.iter().map(|seconds| {
+                let minutes = seconds / 60;
+                let selected = current == Some(*seconds);
+                rsx! {
+                    option {
+                        key: "refresh-key-{seconds}",
+                        value: "{seconds}",
+                        selected: selected,
+                        "Refresh every {minutes} min"
+                    }
+                }
+            })}
+        }
+    }
+}
+
+/// Placeholder with the layout of the weather card, shown while the weather
+/// and forecast of the selected location are being fetched
+fn skeleton_element() -> Element {
+    rsx! {
+        div { class: "px-6 py-6 relative animate-pulse",
+            div { class: "flex mb-4 justify-between items-center",
+                div {
+                    div { class: "h-5 w-32 bg-gray-400 rounded mb-2" },
+                    div { class: "h-3 w-24 bg-gray-500 rounded" },
+                }
+                div { class: "h-12 w-12 bg-gray-400 rounded-full" },
+            }
+            div { class: "flex justify-between items-center",
+                div { class: "h-10 w-20 bg-gray-400 rounded" },
+                div {
+                    div { class: "h-3 w-28 bg-gray-500 rounded mb-2" },
+                    div { class: "h-3 w-28 bg-gray-500 rounded mb-2" },
+                    div { class: "h-3 w-28 bg-gray-500 rounded" },
+                }
+            }
+        }
+        div { class: "divider table mx-2 text-center bg-transparent whitespace-nowrap",
+            span { class: "inline-block px-3", small { "Forecast" } },
+        }
+        div { class: "px-6 py-6 relative animate-pulse",
+            div { class: "text-center justify-between items-center flex",
+                style: "flex-flow: initial;",
+                {(0..5).map(|i| rsx! {
+                    div { class: "text-center mb-0 flex items-center justify-center flex-col",
+                        key: "skeleton-key-{i}",
+                        div { class: "h-3 w-8 bg-gray-500 rounded my-1" },
+                        div { class: "h-8 w-8 bg-gray-400 rounded-full" },
+                        div { class: "h-3 w-10 bg-gray-500 rounded my-1" },
+                        div { class: "h-3 w-10 bg-gray-500 rounded my-1" },
+                    }
+                })}
+            }
+        }
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(Clone)]
 pub struct AppProps {
@@ -406,8 +718,17 @@ pub fn WeatherAppComponent(props: AppProps) -> Element {
     let mut forecast = use_signal(WeatherForecast::default);
     let draft = use_signal(String::new);
     let search_history = use_signal(|| vec![String::from(DEFAULT_STR)]);
+    let notices = use_signal(Vec::new);
+    let refreshing = use_signal(|| None);
+
+    #[cfg(target_arch = "wasm32")]
+    let refresh = use_signal(get_refresh_parameter);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let refresh = use_signal(|| None);
 
     let mut location = use_signal(|| get_parameters(DEFAULT_LOCATION));
+    use_auto_refresh(refresh, location, cache, refreshing);
 
     #[cfg(not(target_arch = "wasm32"))]
     let mut recv_future = use_resource(move || {
@@ -446,10 +767,17 @@ pub fn WeatherAppComponent(props: AppProps) -> Element {
         let entry_opt = cache().get(&l).cloned();
         async move {
             if let Some(entry) = entry_opt {
-                (l, entry)
+                Some((l, entry))
             } else {
-                let entry = get_weather_data_forecast(&l).await;
-                (l, entry)
+                let entry = get_weather_data_forecast(&l).await?;
+                for message in &entry.errors {
+                    push_notice(
+                        notices,
+                        message.as_str(),
+                        Some(NoticeRetry::Weather(l.clone())),
+                    );
+                }
+                Some((l, entry))
             }
         }
     });
@@ -467,6 +795,13 @@ pub fn WeatherAppComponent(props: AppProps) -> Element {
                         new_cache
                     });
                     recv_future.restart();
+                    for message in &entry.errors {
+                        push_notice(
+                            notices,
+                            message.as_str(),
+                            Some(NoticeRetry::Weather(loc.clone())),
+                        );
+                    }
                     if let Some(w) = &entry.weather {
                         weather.set(w.clone());
                     }
@@ -492,27 +827,31 @@ pub fn WeatherAppComponent(props: AppProps) -> Element {
         #[cfg(target_arch = "wasm32")]
         {
             let result = (*weather_future.read()).clone();
-            if let Some((loc, entry)) = result {
+            if let Some(Some((loc, entry))) = result {
                 if !cache.read().contains_key(&loc) || cache.read().is_empty() {
-                    let location = location.read().clone();
                     cache.set({
                         let mut new_cache = cache.read().clone();
-                        new_cache.insert(location.clone(), entry.clone());
-                        if let Some(we) = new_cache.get(&location) {
-                            if let Some(w) = &we.weather {
-                                weather.set(w.clone());
-                            }
-                            if let Some(f) = &we.forecast {
-                                forecast.set(f.clone());
-                            }
-                        }
+                        new_cache.insert(loc.clone(), entry.clone());
                         new_cache
                     });
+                    // results for a location that is no longer selected are only cached
+                    if loc == *location.read() {
+                        if let Some(w) = &entry.weather {
+                            weather.set(w.clone());
+                        }
+                        if let Some(f) = &entry.forecast {
+                            forecast.set(f.clone());
+                        }
+                    }
                 }
             }
         }
 
-        weather_app_element(
+        let loading = !cache.read().contains_key(&*location.read())
+            && refreshing.read().as_ref() != Some(&*location.read());
+        let app = weather_app_element(
+            loading,
+            refresh,
             draft,
             location_cache,
             cache,
@@ -520,7 +859,18 @@ pub fn WeatherAppComponent(props: AppProps) -> Element {
             weather,
             forecast,
             search_history,
-        )
+        );
+        rsx! {
+            ToastComponent {
+                notices,
+                on_retry: move |retry| {
+                    if let NoticeRetry::Weather(l) = retry {
+                        cache.write().remove(&l);
+                    }
+                },
+            },
+            {app},
+        }
     }
 }
 
@@ -670,6 +1020,7 @@ pub fn index_element(
     mut location_future: Resource<Option<WeatherLocation>>,
     weather: Signal<Option<WeatherData>>,
     forecast: Signal<Option<WeatherForecast>>,
+    precipitation: Signal<Option<PrecipitationSummary>>,
     mut start_date: Signal<Option<Date>>,
     mut end_date: Signal<Option<Date>>,
 ) -> Element {
@@ -681,7 +1032,7 @@ pub fn index_element(
         WeatherPage::Index | WeatherPage::Plot => {
             Url::parse_with_params(url.as_str(), location.read().get_options()).unwrap_or(url)
         }
-        WeatherPage::Wasm => url,
+        WeatherPage::Wasm | WeatherPage::HistoryTable => url,
         WeatherPage::HistoryPlot => {
             let hl = (*history_location.read()).clone();
             let mut options = vec![("name", &hl)];
@@ -765,7 +1116,7 @@ pub fn index_element(
                 },
             })
         }
-        WeatherPage::HistoryPlot => {
+        WeatherPage::HistoryPlot | WeatherPage::HistoryTable => {
             let hlc = (*history_location_cache.read()).clone();
             let mut locations: Vec<_> = hlc.iter().map(|l| l.as_str()).collect();
             locations.sort();
@@ -850,11 +1201,22 @@ pub fn index_element(
             let w = weather.read().clone();
             let f = forecast.read().clone();
             if let Some((weather, forecast)) = w.as_ref().and_then(|w| f.as_ref().map(|f| (w, f))) {
-                Some(weather_element(weather, forecast))
+                Some(weather_element(
+                    weather,
+                    Some(forecast),
+                    None,
+                    None,
+                    None,
+                    precipitation.read().as_ref(),
+                    None,
+                    None,
+                    &[],
+                ))
             } else {
                 None
             }
         }
+        WeatherPage::HistoryTable => None,
         _ => Some(rsx! {
             iframe {
                 src: "{url}",
@@ -908,6 +1270,14 @@ pub fn index_element(
                     page_type.set(WeatherPage::HistoryPlot);
                 },
             }
+            input {
+                "type": "button",
+                name: "history_table",
+                value: "History Table",
+                onclick: move |_| {
+                    page_type.set(WeatherPage::HistoryTable);
+                },
+            }
             input {
                 "type": "button",
                 name: "wasm",
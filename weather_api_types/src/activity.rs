@@ -0,0 +1,266 @@
+use time::{Duration, OffsetDateTime, Time, UtcOffset};
+
+use weather_util_rust::{precipitation::Precipitation, weather_forecast::WeatherForecast};
+
+use crate::plot::PlotPoint;
+
+/// Conditions used to score outdoor activity comfort
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ActivityConditions {
+    /// temperature in Celsius
+    pub temperature: f64,
+    /// relative humidity in percent
+    pub humidity: f64,
+    /// wind speed in m/s
+    pub wind_speed: f64,
+    /// precipitation rate in mm/h
+    pub precipitation: f64,
+    /// probability of precipitation from 0 to 1, if known
+    pub pop: Option<f64>,
+    /// UV index, if known
+    pub uv_index: Option<f64>,
+}
+
+impl ActivityConditions {
+    fn interpolate(&self, other: &Self, frac: f64) -> Self {
+        let lerp = |a: f64, b: f64| a + (b - a) * frac;
+        let lerp_option = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => Some(lerp(a, b)),
+            (a, b) => a.or(b),
+        };
+        Self {
+            temperature: lerp(self.temperature, other.temperature),
+            humidity: lerp(self.humidity, other.humidity),
+            wind_speed: lerp(self.wind_speed, other.wind_speed),
+            precipitation: lerp(self.precipitation, other.precipitation),
+            pop: lerp_option(self.pop, other.pop),
+            uv_index: lerp_option(self.uv_index, other.uv_index),
+        }
+    }
+
+    /// Comfort score from 0 (stay inside) to 100 (ideal), penalizing
+    /// departures from a 10-18C temperature band, muggy air, wind, rain, a
+    /// likely chance of rain and high UV.
+    pub fn score(&self) -> f64 {
+        let temperature_penalty = if self.temperature < 10.0 {
+            (10.0 - self.temperature) * 3.0
+        } else if self.temperature > 18.0 {
+            (self.temperature - 18.0) * 4.0
+        } else {
+            0.0
+        };
+        let humidity_penalty = (self.humidity - 60.0).max(0.0) * 0.5;
+        let wind_penalty = (self.wind_speed - 4.0).max(0.0) * 3.0;
+        let precipitation_penalty = (self.precipitation * 20.0).min(60.0);
+        let pop_penalty = self.pop.map_or(0.0, |pop| pop * 20.0);
+        let uv_penalty = self.uv_index.map_or(0.0, |uv| (uv - 5.0).max(0.0) * 5.0);
+        (100.0
+            - temperature_penalty
+            - humidity_penalty
+            - wind_penalty
+            - precipitation_penalty
+            - pop_penalty
+            - uv_penalty)
+            .clamp(0.0, 100.0)
+    }
+}
+
+/// Conditions of each entry of the three hour forecast, which has neither
+/// the probability of precipitation nor the UV index
+pub fn get_forecast_conditions(
+    forecast: &WeatherForecast,
+) -> Vec<(OffsetDateTime, ActivityConditions)> {
+    forecast
+        .list
+        .iter()
+        .map(|entry| {
+            let rain = entry
+                .rain
+                .as_ref()
+                .and_then(|r| r.three_hour)
+                .unwrap_or_default();
+            let snow = entry
+                .snow
+                .as_ref()
+                .and_then(|s| s.three_hour)
+                .unwrap_or_default();
+            let precipitation: Precipitation = rain + snow;
+            let humidity: i64 = entry.main.humidity.into();
+            let c = ActivityConditions {
+                temperature: entry.main.temp.celcius(),
+                humidity: humidity as f64,
+                wind_speed: entry.wind.speed.mps(),
+                precipitation: precipitation.millimeters() / 3.0,
+                pop: None,
+                uv_index: None,
+            };
+            (entry.dt, c)
+        })
+        .collect()
+}
+
+/// Hourly activity scores for the `hours` hours starting at the hour of
+/// `now` (in the `offset` of the location), interpolated between the
+/// time ordered `conditions`. Hours before the first entry take its
+/// conditions, the scores end with the last entry.
+pub fn get_activity_scores(
+    conditions: &[(OffsetDateTime, ActivityConditions)],
+    offset: UtcOffset,
+    now: OffsetDateTime,
+    hours: i64,
+) -> Vec<PlotPoint> {
+    let now = now.to_offset(offset);
+    let start = Time::from_hms(now.hour(), 0, 0).map_or(now, |t| now.replace_time(t));
+    (0..hours)
+        .map_while(|h| {
+            let datetime = start + Duration::hours(h);
+            let idx = conditions.partition_point(|(dt, _)| *dt <= datetime);
+            let c = match (
+                idx.checked_sub(1).map(|i| &conditions[i]),
+                conditions.get(idx),
+            ) {
+                (Some((t0, c0)), Some((t1, c1))) => {
+                    c0.interpolate(c1, (datetime - *t0) / (*t1 - *t0))
+                }
+                (None, Some((_, c1))) => *c1,
+                (Some((t0, c0)), None) if datetime == *t0 => *c0,
+                _ => return None,
+            };
+            Some(PlotPoint {
+                datetime,
+                value: c.score(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration, UtcOffset};
+
+    use crate::activity::{get_activity_scores, ActivityConditions};
+
+    fn ideal() -> ActivityConditions {
+        ActivityConditions {
+            temperature: 15.0,
+            humidity: 50.0,
+            wind_speed: 2.0,
+            precipitation: 0.0,
+            pop: Some(0.0),
+            uv_index: Some(3.0),
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(ideal().score(), 100.0);
+        assert_eq!(ActivityConditions::default().score(), 70.0);
+
+        let score = |c: ActivityConditions| c.score();
+        assert_eq!(
+            score(ActivityConditions {
+                temperature: 5.0,
+                ..ideal()
+            }),
+            85.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                temperature: 28.0,
+                ..ideal()
+            }),
+            60.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                humidity: 90.0,
+                ..ideal()
+            }),
+            85.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                wind_speed: 9.0,
+                ..ideal()
+            }),
+            85.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                precipitation: 1.0,
+                ..ideal()
+            }),
+            80.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                precipitation: 10.0,
+                ..ideal()
+            }),
+            40.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                pop: Some(1.0),
+                ..ideal()
+            }),
+            80.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                pop: None,
+                ..ideal()
+            }),
+            100.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                uv_index: Some(9.0),
+                ..ideal()
+            }),
+            80.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                uv_index: None,
+                ..ideal()
+            }),
+            100.0
+        );
+        assert_eq!(
+            score(ActivityConditions {
+                temperature: 40.0,
+                precipitation: 10.0,
+                ..ideal()
+            }),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_get_activity_scores() {
+        let t0 = datetime!(2024-06-01 12:00 UTC);
+        let conditions = [
+            (t0, ideal()),
+            (
+                t0 + Duration::hours(3),
+                ActivityConditions {
+                    precipitation: 3.0,
+                    ..ideal()
+                },
+            ),
+        ];
+        let now = datetime!(2024-06-01 10:40 UTC);
+        let scores = get_activity_scores(&conditions, UtcOffset::UTC, now, 48);
+        let values: Vec<_> = scores.iter().map(|p| p.value.round()).collect();
+        assert_eq!(values, [100.0, 100.0, 100.0, 80.0, 60.0, 40.0]);
+        assert_eq!(scores[0].datetime, datetime!(2024-06-01 10:00 UTC));
+        assert_eq!(scores[5].datetime, t0 + Duration::hours(3));
+
+        let offset = UtcOffset::from_hms(5, 30, 0).unwrap();
+        let scores = get_activity_scores(&conditions, offset, now, 2);
+        assert_eq!(scores[0].datetime, datetime!(2024-06-01 16:00 +5:30));
+
+        assert!(get_activity_scores(&[], UtcOffset::UTC, now, 48).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of history entries recorded for a location
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LocationCount {
+    pub location: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    pub limit: usize,
+    pub offset: usize,
+    pub total: usize,
+}
+
+/// Response body of `/weather/locations`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PaginatedLocationCount {
+    pub pagination: Pagination,
+    pub data: Vec<LocationCount>,
+}
+
+/// Row of `/weather/history`, only the columns shown in the history table
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub created_at: String,
+    pub location_name: String,
+    pub condition: String,
+    pub temperature: f64,
+    pub temperature_minimum: f64,
+    pub temperature_maximum: f64,
+    pub pressure: f64,
+    pub humidity: i32,
+    pub rain: Option<f64>,
+    pub snow: Option<f64>,
+    pub wind_speed: f64,
+    pub wind_direction: Option<f64>,
+    pub server: String,
+}
+
+/// Response body of `/weather/history`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PaginatedHistory {
+    pub pagination: Pagination,
+    pub data: Vec<HistoryEntry>,
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+}
+
+/// Response body of `/weather/user`, the session id and secret are left out
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LoggedUser {
+    pub email: String,
+}
+
+/// Temperature (K), humidity (%) and pressure (kPa) of a recorded history
+/// entry
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ComparisonReading {
+    pub created_at: String,
+    pub temperature: f64,
+    pub humidity: i32,
+    pub pressure: f64,
+}
+
+/// Response body of `/weather/compare/yesterday`, `yesterday` is the entry
+/// recorded closest to 24 hours before `current` when there is one
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WeatherComparison {
+    pub location_name: String,
+    pub current: ComparisonReading,
+    pub yesterday: Option<ComparisonReading>,
+}
+
+/// Response body of `/weather/history/today-summary`, recorded precipitation
+/// (mm) since local midnight and since the start of the week (Monday)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct PrecipitationSummary {
+    pub location_name: String,
+    pub today_rain: f64,
+    pub today_snow: f64,
+    pub week_rain: f64,
+    pub week_snow: f64,
+    /// hours this week with a recorded entry
+    pub hours: usize,
+}
+
+/// Moon phase (fraction of the lunation, 0 new moon, 0.5 full moon),
+/// illumination (%) and local moonrise / moonset (`HH:MM`) of the day
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MoonSummary {
+    pub phase: f64,
+    pub phase_name: String,
+    pub illumination: f64,
+    pub moonrise: Option<String>,
+    pub moonset: Option<String>,
+}
+
+/// Local sunrise / sunset and civil dawn / dusk (`HH:MM`) of the day along
+/// with the daylight duration (seconds), the times are `None` during polar
+/// day or night
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DaylightSummary {
+    pub sunrise: Option<String>,
+    pub sunset: Option<String>,
+    pub civil_dawn: Option<String>,
+    pub civil_dusk: Option<String>,
+    pub daylight: i64,
+}
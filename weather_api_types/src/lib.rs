@@ -0,0 +1,34 @@
+#![allow(clippy::pedantic)]
+
+pub mod activity;
+pub mod dto;
+pub mod plot;
+pub mod units;
+
+pub use dto::{LocationCount, PaginatedLocationCount, Pagination};
+
+use weather_util_rust::weather_api::WeatherLocation;
+
+pub static DEFAULT_STR: &str = "11106";
+pub static DEFAULT_HOST: &str = "cloud.ddboline.net";
+
+pub static DEFAULT_LOCATION: &str = "10001";
+
+pub fn get_parameters(search_str: &str) -> WeatherLocation {
+    let mut opts = WeatherLocation::from_city_name(search_str);
+    if let Ok(zip) = search_str.parse::<u64>() {
+        opts = WeatherLocation::from_zipcode(zip);
+    } else if search_str.contains(',') {
+        let mut iter = search_str.split(',');
+        if let Some(lat) = iter.next() {
+            if let Ok(lat) = lat.parse() {
+                if let Some(lon) = iter.next() {
+                    if let Ok(lon) = lon.parse() {
+                        opts = WeatherLocation::from_lat_lon(lat, lon);
+                    }
+                }
+            }
+        }
+    }
+    opts
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(PartialEq, Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct PlotPoint {
+    pub datetime: OffsetDateTime,
+    pub value: f64,
+}
+#[derive(PartialEq, Deserialize, Serialize, Debug, Clone)]
+pub struct PlotData {
+    pub plot_url: String,
+    pub title: String,
+    pub xaxis: String,
+    pub yaxis: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub markers_url: Option<String>,
+}
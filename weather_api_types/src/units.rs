@@ -0,0 +1,365 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, fmt::Write, str::FromStr};
+use time::{format_description::FormatItem, macros::format_description, Date, UtcOffset};
+
+use weather_util_rust::{weather_data::WeatherData, weather_forecast::WeatherForecast};
+
+static TIME_FORMAT: &[FormatItem<'static>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+
+/// Temperature keys of the openweathermap json, `temp` and `feels_like` may
+/// also be objects of temperatures in the One Call daily forecast,
+/// `heat_index` and `wind_chill` are the derived metrics of recorded history
+const TEMPERATURE_KEYS: [&str; 7] = [
+    "temp",
+    "feels_like",
+    "temp_min",
+    "temp_max",
+    "dew_point",
+    "heat_index",
+    "wind_chill",
+];
+const SPEED_KEYS: [&str; 4] = ["speed", "gust", "wind_speed", "wind_gust"];
+const PRECIPITATION_KEYS: [&str; 3] = ["rain", "snow", "precipitation"];
+
+/// Unit system, mirrors the openweathermap `units` parameter but also applies
+/// to precipitation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// Kelvin, m/s and mm
+    #[default]
+    Standard,
+    /// Celsius, m/s and mm
+    Metric,
+    /// Fahrenheit, mph and inches
+    Imperial,
+}
+
+impl Units {
+    #[must_use]
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Metric => "metric",
+            Self::Imperial => "imperial",
+        }
+    }
+
+    #[must_use]
+    pub fn temperature(self, kelvin: f64) -> f64 {
+        match self {
+            Self::Standard => kelvin,
+            Self::Metric => kelvin - 273.15,
+            Self::Imperial => (kelvin - 273.15) * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    #[must_use]
+    pub fn temperature_unit(self) -> &'static str {
+        match self {
+            Self::Standard => "K",
+            Self::Metric => "C",
+            Self::Imperial => "F",
+        }
+    }
+
+    #[must_use]
+    pub fn speed(self, meters_per_second: f64) -> f64 {
+        match self {
+            Self::Standard | Self::Metric => meters_per_second,
+            Self::Imperial => meters_per_second * 3600.0 / 1609.344,
+        }
+    }
+
+    #[must_use]
+    pub fn speed_unit(self) -> &'static str {
+        match self {
+            Self::Standard | Self::Metric => "m/s",
+            Self::Imperial => "mph",
+        }
+    }
+
+    #[must_use]
+    pub fn precipitation(self, millimeters: f64) -> f64 {
+        match self {
+            Self::Standard | Self::Metric => millimeters,
+            Self::Imperial => millimeters / 25.4,
+        }
+    }
+
+    #[must_use]
+    pub fn precipitation_unit(self) -> &'static str {
+        match self {
+            Self::Standard | Self::Metric => "mm",
+            Self::Imperial => "in",
+        }
+    }
+
+    /// Convert the temperatures, wind speeds and precipitation of an
+    /// openweathermap shaped json value (weather, forecast or One Call) in place
+    pub fn convert_json(self, value: &mut Value) {
+        if self == Self::Standard {
+            return;
+        }
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    self.convert_json(item);
+                }
+            }
+            Value::Object(map) => {
+                for (key, item) in map.iter_mut() {
+                    let key = key.as_str();
+                    if TEMPERATURE_KEYS.contains(&key) {
+                        self.convert_numbers(item, Self::temperature);
+                    } else if SPEED_KEYS.contains(&key) {
+                        self.convert_numbers(item, Self::speed);
+                    } else if PRECIPITATION_KEYS.contains(&key) {
+                        self.convert_numbers(item, Self::precipitation);
+                    } else {
+                        self.convert_json(item);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn convert_numbers(self, value: &mut Value, convert: fn(Self, f64) -> f64) {
+        match value {
+            Value::Number(n) => {
+                if let Some(x) = n.as_f64().map(|x| convert(self, x)) {
+                    if let Some(x) = serde_json::Number::from_f64(x) {
+                        *n = x;
+                    }
+                }
+            }
+            Value::Object(map) => {
+                for item in map.values_mut() {
+                    self.convert_numbers(item, convert);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Current conditions text, the counterpart of
+    /// `WeatherData::get_current_conditions` in these units
+    #[must_use]
+    pub fn current_conditions(self, weather: &WeatherData) -> String {
+        let fo: UtcOffset = weather.timezone.into();
+        let temperature_unit = self.temperature_unit();
+        let mut output = String::new();
+        writeln!(
+            output,
+            "Current conditions {} {:0.5}N {:0.5}E",
+            weather.name, weather.coord.lat, weather.coord.lon
+        )
+        .unwrap();
+        let dt = weather
+            .dt
+            .to_offset(fo)
+            .format(TIME_FORMAT)
+            .unwrap_or_default();
+        writeln!(output, "Last Updated {dt}").unwrap();
+        writeln!(
+            output,
+            "\tTemperature: {:0.2} {temperature_unit}",
+            self.temperature(weather.main.temp.kelvin())
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "\tFeels Like: {:0.2} {temperature_unit}",
+            self.temperature(weather.main.feels_like.kelvin())
+        )
+        .unwrap();
+        let humidity: i64 = weather.main.humidity.into();
+        writeln!(output, "\tRelative Humidity: {humidity}%").unwrap();
+        let direction = weather
+            .wind
+            .deg
+            .map_or_else(String::new, |d| format!("{:0.0} degrees at ", d.deg()));
+        writeln!(
+            output,
+            "\tWind: {direction}{:0.2} {}",
+            self.speed(weather.wind.speed.mps()),
+            self.speed_unit()
+        )
+        .unwrap();
+        let conditions: Vec<_> = weather
+            .weather
+            .iter()
+            .map(|w| w.description.as_str())
+            .collect();
+        writeln!(output, "\tConditions: {}", conditions.join(", ")).unwrap();
+        let sunrise = weather
+            .sys
+            .sunrise
+            .to_offset(fo)
+            .format(TIME_FORMAT)
+            .unwrap_or_default();
+        let sunset = weather
+            .sys
+            .sunset
+            .to_offset(fo)
+            .format(TIME_FORMAT)
+            .unwrap_or_default();
+        writeln!(output, "\tSunrise: {sunrise}").unwrap();
+        writeln!(output, "\tSunset: {sunset}").unwrap();
+        let rain = weather
+            .rain
+            .as_ref()
+            .and_then(|r| r.one_hour.or(r.three_hour));
+        if let Some(rain) = rain {
+            writeln!(
+                output,
+                "\tRain: {:0.2} {}",
+                self.precipitation(rain.millimeters()),
+                self.precipitation_unit()
+            )
+            .unwrap();
+        }
+        let snow = weather
+            .snow
+            .as_ref()
+            .and_then(|s| s.one_hour.or(s.three_hour));
+        if let Some(snow) = snow {
+            writeln!(
+                output,
+                "\tSnow: {:0.2} {}",
+                self.precipitation(snow.millimeters()),
+                self.precipitation_unit()
+            )
+            .unwrap();
+        }
+        output
+    }
+
+    /// Daily high / low and precipitation lines, the counterpart of
+    /// `WeatherForecast::get_forecast` in these units
+    #[must_use]
+    pub fn forecast(self, forecast: &WeatherForecast) -> Vec<String> {
+        let fo: UtcOffset = forecast.city.timezone.into();
+        let mut days: BTreeMap<Date, (f64, f64, f64, f64)> = BTreeMap::new();
+        for entry in &forecast.list {
+            let date = entry.dt.to_offset(fo).date();
+            let rain = entry
+                .rain
+                .as_ref()
+                .and_then(|r| r.three_hour)
+                .map_or(0.0, |r| r.millimeters());
+            let snow = entry
+                .snow
+                .as_ref()
+                .and_then(|s| s.three_hour)
+                .map_or(0.0, |s| s.millimeters());
+            let day = days
+                .entry(date)
+                .or_insert((f64::INFINITY, f64::NEG_INFINITY, 0.0, 0.0));
+            day.0 = day.0.min(entry.main.temp_min.kelvin());
+            day.1 = day.1.max(entry.main.temp_max.kelvin());
+            day.2 += rain;
+            day.3 += snow;
+        }
+        let temperature_unit = self.temperature_unit();
+        let precipitation_unit = self.precipitation_unit();
+        let mut lines = vec![String::from("\nForecast:")];
+        lines.extend(days.into_iter().map(|(date, (low, high, rain, snow))| {
+            let mut line = format!(
+                "\t{date} High: {:0.1} {temperature_unit} / Low: {:0.1} {temperature_unit}",
+                self.temperature(high),
+                self.temperature(low),
+            );
+            if rain > 0.0 {
+                write!(
+                    line,
+                    " Rain: {:0.2} {precipitation_unit}",
+                    self.precipitation(rain)
+                )
+                .unwrap();
+            }
+            if snow > 0.0 {
+                write!(
+                    line,
+                    " Snow: {:0.2} {precipitation_unit}",
+                    self.precipitation(snow)
+                )
+                .unwrap();
+            }
+            line
+        }));
+        lines
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+impl FromStr for Units {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Self::Standard),
+            "metric" => Ok(Self::Metric),
+            "imperial" => Ok(Self::Imperial),
+            _ => Err(format!("Invalid units {s}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::units::Units;
+
+    fn approx(value: &Value, expected: f64) -> bool {
+        value
+            .as_f64()
+            .is_some_and(|value| (value - expected).abs() < 1e-6)
+    }
+
+    #[test]
+    fn test_convert_json() {
+        let weather = json!({
+            "dew_point": 283.15,
+            "heat_index": 310.15,
+            "wind_chill": 263.15,
+            "humidity": 80,
+        });
+        let mut metric = weather.clone();
+        Units::Metric.convert_json(&mut metric);
+        assert!(approx(&metric["dew_point"], 10.0));
+        assert!(approx(&metric["heat_index"], 37.0));
+        assert!(approx(&metric["wind_chill"], -10.0));
+        assert_eq!(metric["humidity"], json!(80));
+
+        let mut imperial = weather.clone();
+        Units::Imperial.convert_json(&mut imperial);
+        assert!(approx(&imperial["heat_index"], 98.6));
+        assert!(approx(&imperial["wind_chill"], 14.0));
+
+        let mut standard = weather.clone();
+        Units::Standard.convert_json(&mut standard);
+        assert_eq!(standard, weather);
+
+        let mut forecast = json!({
+            "daily": [{"temp": {"min": 273.15, "max": 283.15}, "wind_speed": 10.0}],
+            "rain": {"1h": 25.4},
+        });
+        Units::Imperial.convert_json(&mut forecast);
+        assert!(approx(&forecast["daily"][0]["temp"]["min"], 32.0));
+        assert!(approx(&forecast["daily"][0]["temp"]["max"], 50.0));
+        assert!(approx(
+            &forecast["daily"][0]["wind_speed"],
+            22.369_362_920_544_02
+        ));
+        assert!(approx(&forecast["rain"]["1h"], 1.0));
+    }
+}